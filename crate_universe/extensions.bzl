@@ -131,6 +131,8 @@ CARGO_BAZEL_REPIN=1 bazel sync --only=crates
 
 This will result in all dependencies being updated for a project. The `CARGO_BAZEL_REPIN`
 environment variable can also be used to customize how dependencies are updated.
+For example, `CARGO_BAZEL_REPIN=serde` only updates `serde` (and whatever it requires to change),
+preserving all other pins.
 For more details about repin, [please refer to the documentation](https://bazelbuild.github.io/rules_rust/crate_universe.html#crates_vendor).
 
 ### Direct Dependencies
@@ -494,9 +496,11 @@ def _collect_render_config(module, repository):
         # bzlmod doesn't allow passing `None` as a default parameter to indicate a value was
         # not provided. So for backward compatibility, certain empty values are assumed to be
         # not provided and thus are converted explicitly to `None`.
-        for null_defaults in ["vendor_mode", "regen_command", "default_package_name"]:
+        for null_defaults in ["vendor_mode", "regen_command", "default_package_name", "package_aliases"]:
             if config_kwargs[null_defaults] == "":
                 config_kwargs[null_defaults] = None
+        if not config_kwargs["templates_version"]:
+            config_kwargs["templates_version"] = None
 
         config = json.decode(generate_render_config(**config_kwargs))
 
@@ -588,6 +592,10 @@ def _generate_hub_and_spokes(
             generate_binaries = cfg.generate_binaries,
             render_config = render_config,
             repository_ctx = module_ctx,
+            target_specs = cfg.target_specs,
+            feature_unification = cfg.feature_unification,
+            strict_dev_dependencies = cfg.strict_dev_dependencies,
+            cargo_profile = cfg.cargo_profile,
         ),
     )
 
@@ -705,7 +713,11 @@ def _generate_hub_and_spokes(
             version = version.replace("+", "-"),
         )
 
-        build_file_content = module_ctx.read(crates_dir.get_child("BUILD.%s-%s.bazel" % (name, version)))
+        if render_config["build_file_layout"] == "per_crate":
+            build_file = crates_dir.get_child("%s-%s" % (name, version.replace("+", "-"))).get_child("BUILD.bazel")
+        else:
+            build_file = crates_dir.get_child("BUILD.%s-%s.bazel" % (name, version))
+        build_file_content = module_ctx.read(build_file)
         if "Http" in repo:
             # Replicates functionality in repo_http.j2.
             repo = repo["Http"]
@@ -733,6 +745,7 @@ def _generate_hub_and_spokes(
             new_git_repository(
                 name = crate_repo_name,
                 init_submodules = True,
+                recursive_init_submodules = bool(repo.get("submodules", None)),
                 patch_args = repo.get("patch_args", None),
                 patch_tool = repo.get("patch_tool", None),
                 patches = repo.get("patches", None),
@@ -746,6 +759,7 @@ def _generate_hub_and_spokes(
             options = {
                 "config": render_config,
                 "crate_context": crate,
+                "debug_assertions_conditions": contents.get("debug_assertions_conditions", {}),
                 "platform_conditions": contents["conditions"],
                 "supported_platform_triples": cfg.supported_platform_triples,
             }
@@ -1009,6 +1023,8 @@ def _crate_impl(module_ctx):
 _FROM_COMMON_ATTRS = {
     "cargo_config": CRATES_VENDOR_ATTRS["cargo_config"],
     "cargo_lockfile": CRATES_VENDOR_ATTRS["cargo_lockfile"],
+    "cargo_profile": CRATES_VENDOR_ATTRS["cargo_profile"],
+    "feature_unification": CRATES_VENDOR_ATTRS["feature_unification"],
     "generate_binaries": CRATES_VENDOR_ATTRS["generate_binaries"],
     "generate_build_scripts": CRATES_VENDOR_ATTRS["generate_build_scripts"],
     "host_tools_repo": attr.string(
@@ -1031,7 +1047,17 @@ _FROM_COMMON_ATTRS = {
             "If set, this file must exist within the workspace (but can be empty) before this rule will work."
         ),
     ),
+    "strict_dev_dependencies": CRATES_VENDOR_ATTRS["strict_dev_dependencies"],
     "supported_platform_triples": CRATES_VENDOR_ATTRS["supported_platform_triples"],
+    "target_specs": attr.string_dict(
+        doc = (
+            "A mapping of platform triples which are not built into `rustc` (or recognized by crate_universe) to " +
+            "the labels of their [custom target specifications](https://doc.rust-lang.org/rustc/targets/custom.html). " +
+            "These triples must also be listed in `supported_platform_triples`. Note that custom target " +
+            "specifications require a nightly `rustc`. Use the `platform_labels` parameter of `render_config` to " +
+            "choose the platform constraints used for them in generated `select`s."
+        ),
+    ),
 }
 
 _from_cargo = tag_class(
@@ -1070,7 +1096,7 @@ _annotation = tag_class(
             doc = "A list of labels to add to a crate's `cargo_build_script::deps` attribute.",
         ),
         "build_script_env": attr.string_dict(
-            doc = "Additional environment variables to set on a crate's `cargo_build_script::env` attribute.",
+            doc = "Additional environment variables to set on a crate's `cargo_build_script::env` attribute. Values may use location expansion such as `$(execpath @protobuf//:protoc)`, where labels which aren't in `build_script_data` are added to the build script's `tools`, and make variables of `build_script_toolchains`.",
         ),
         "build_script_proc_macro_deps": _relative_label_list(
             doc = "A list of labels to add to a crate's `cargo_build_script::proc_macro_deps` attribute.",
@@ -1087,6 +1113,9 @@ _annotation = tag_class(
         "build_script_tools": _relative_label_list(
             doc = "A list of labels to add to a crate's `cargo_build_script::tools` attribute.",
         ),
+        "cargo_pkg_env": attr.bool(
+            doc = "If True, the `CARGO_PKG_*` environment variables Cargo derives from the crate's manifest (e.g. `CARGO_PKG_DESCRIPTION` or `CARGO_PKG_README`) are set on the crate's `rustc_env` and its build script's `build_script_env`.",
+        ),
         "compile_data": _relative_label_list(
             doc = "A list of labels to add to a crate's `rust_library::compile_data` attribute.",
         ),
@@ -1126,6 +1155,18 @@ _annotation = tag_class(
             values = _OPT_BOOL_VALUES.keys(),
             default = "auto",
         ),
+        "gen_docs": attr.bool(
+            doc = "If True, a `rust_doc` target named `{lib}_doc` is generated for the crate's library. Direct dependencies of the workspace are also available as `{crate}_doc` in the generated repository.",
+        ),
+        "ignore_advisories": attr.string_list(
+            doc = "A list of [RustSec](https://rustsec.org/) advisory ids (e.g. `RUSTSEC-2020-0071`) which should not be reported for the crate when auditing it.",
+        ),
+        "override_crate_features": attr.string_list(
+            doc = "A list of features which replace the ones Cargo resolved for a crate's `rust_library::crate_features` attribute. `crate_features` are still added to them.",
+        ),
+        "override_path": attr.string(
+            doc = "The directory of a local fork to use as the crate's sources instead of the ones from its registry, either a package of the root workspace (e.g. `//third_party/forks/foo`) or a path relative to it. The crate is otherwise rendered from the metadata of the locked version.",
+        ),
         "override_target_bin": attr.label(
             doc = "An optional alternate target to use when something depends on this crate to allow the parent repo to provide its own version of this dependency.",
         ),
@@ -1150,6 +1191,9 @@ _annotation = tag_class(
         "proc_macro_deps": _relative_label_list(
             doc = "A list of labels to add to a crate's `rust_library::proc_macro_deps` attribute.",
         ),
+        "remove_crate_features": attr.string_list(
+            doc = "A list of features to remove from a crate's `rust_library::crate_features` attribute, e.g. ones activated by `default`. Features explicitly enabled by dependents of the crate can't be removed.",
+        ),
         "repositories": attr.string_list(
             doc = "A list of repository names specified from `crate.from_cargo(name=...)` that this annotation is applied to. Defaults to all repositories.",
             default = [],
@@ -1164,7 +1208,7 @@ _annotation = tag_class(
             doc = "A list of strings to set on a crate's `rust_library::rustc_flags` attribute.",
         ),
         "shallow_since": attr.string(
-            doc = "An optional timestamp used for crates originating from a git repository instead of a crate registry. This flag optimizes fetching the source code.",
+            doc = "An optional timestamp used for crates originating from a git repository instead of a crate registry. This flag optimizes fetching the source code. Defaults to the date of the pinned commit.",
         ),
         "version": attr.string(
             doc = "The versions of the crate the annotation is applied to. Defaults to all versions.",
@@ -1234,10 +1278,30 @@ _splicing_config = tag_class(
             default = [],
         ),
     } | {
+        "ca_info": attr.string(
+            doc = "The absolute path to a file of PEM encoded certificate authorities to trust instead of the system's when Cargo connects to registries.",
+        ),
+        "dedup_pins": attr.string_dict(
+            doc = "A mapping of crate names to the version every copy of the crate is updated to when repinning. Pins take precedence over `dedup_strategy`.",
+        ),
+        "dedup_strategy": attr.string(
+            doc = "The strategy used to deduplicate crates resolved at several versions when repinning. `minimal-versions` updates every copy of a crate to its lowest resolved version and `highest` to its highest. Copies whose dependents don't allow the update are left in place.",
+            values = ["", "highest", "minimal-versions"],
+        ),
+        "download_mirrors": attr.string_dict(
+            doc = "A mapping of url prefixes of `.crate` downloads (e.g. `https://static.crates.io/crates/`) to the prefixes replacing them in the generated repository definitions.",
+        ),
+        "index_mirrors": attr.string_dict(
+            doc = "A mapping of registry index urls (e.g. `sparse+https://index.crates.io/`) to the urls of the indexes mirroring them. Cargo is configured to [replace](https://doc.rust-lang.org/cargo/reference/source-replacement.html) each registry with its mirror when resolving dependencies.",
+        ),
         "resolver_version": attr.string(
             doc = "The [resolver version](https://doc.rust-lang.org/cargo/reference/resolver.html#resolver-versions) to use in generated Cargo manifests. This flag is **only** used when splicing a manifest from direct package definitions. See `crates_repository::packages`",
             default = "2",
         ),
+        "report_duplicates": attr.bool(
+            doc = "Whether to warn about each crate resolved at several semver incompatible versions along with the dependency chains pulling in each version.",
+            default = False,
+        ),
     },
 )
 
@@ -1263,6 +1327,11 @@ can be found below where the supported keys for each template can be found in th
             default = [],
         ),
     } | {
+        "build_file_layout": attr.string(
+            doc = "How crate BUILD files are laid out. `flat` names them after `build_file_template`. `per_crate` renders a `BUILD.bazel` file in a `{name}-{version}` directory for each crate.",
+            default = "flat",
+            values = ["flat", "per_crate"],
+        ),
         "build_file_template": attr.string(
             doc = "The base template to use for BUILD file names. The available format keys are [`{name}`, {version}`].",
             default = "//:BUILD.{name}-{version}.bazel",
@@ -1302,6 +1371,11 @@ can be found below where the supported keys for each template can be found in th
             doc = "Whether to generate `target_compatible_with` annotations on the generated BUILD files.  This catches a `target_triple` being targeted that isn't declared in `supported_platform_triples`.",
             default = True,
         ),
+        "package_aliases": attr.string(
+            doc = "Stable aliases to render for each package so labels don't change when it's upgraded. `unversioned` renders e.g. `@crates//:serde` for packages resolved at a single version. `major` additionally renders an alias per semver compatible series, e.g. `@crates//:serde-1` or `@crates//:rand-0.8`.",
+            default = "",
+            values = ["", "major", "unversioned"],
+        ),
         "platforms_template": attr.string(
             doc = "The base template to use for platform names. See [platforms documentation](https://docs.bazel.build/versions/main/platforms.html). The available format keys are [`{triple}`].",
             default = "@rules_rust//rust/platform:{triple}",
@@ -1310,6 +1384,12 @@ can be found below where the supported keys for each template can be found in th
             doc = "An optional command to demonstrate how generated files should be regenerated.",
             default = "",
         ),
+        "templates": attr.string_dict(
+            doc = "A mapping of the names of rendering templates, e.g. `partials/crate/loads.j2` and `partials/crate/footer.j2` which extend each crate's BUILD file, to [Tera](https://keats.github.io/tera/) templates overriding them.",
+        ),
+        "templates_version": attr.int(
+            doc = "The version of the rendering templates `templates` were written against. Rendering fails if this does not match the version of the default templates, e.g. after an upgrade which changed them.",
+        ),
         "vendor_mode": attr.string(
             doc = "An optional configuration for rendering content to be rendered into repositories.",
             default = "",
@@ -1325,9 +1405,14 @@ Environment Variables:
 
 | variable | usage |
 | --- | --- |
+| `CARGO_BAZEL_ADVISORY_DB` | The path to a checkout of the [RustSec advisory database](https://github.com/rustsec/advisory-db). If set, repinning warns about crates affected by advisories which are not ignored by the `ignore_advisories` annotation |
+| `CARGO_BAZEL_CRATE_CACHE` | A directory in which the archives of downloaded crates are cached by their checksum across repins and `crate` extensions. Unchanged crates are restored into `CARGO_HOME` instead of being downloaded again, which is most useful when `CARGO_HOME` is isolated. Crates of registries other than crates.io are only restored once their index was fetched, see `CARGO_BAZEL_REGISTRY_CACHE` |
+| `CARGO_BAZEL_FAIL_ON_YANKED` | If set to `true`, repinning fails instead of warning when locked crates have been yanked from their registries |
 | `CARGO_BAZEL_GENERATOR_SHA256` | The sha256 checksum of the file located at `CARGO_BAZEL_GENERATOR_URL` |
 | `CARGO_BAZEL_GENERATOR_URL` | The URL of a cargo-bazel binary. This variable takes precedence over attributes and can use `file://` for local paths |
 | `CARGO_BAZEL_ISOLATED` | An authoritative flag as to whether or not the `CARGO_HOME` environment variable should be isolated from the host configuration |
+| `CARGO_BAZEL_NETWORK_RETRIES` | The number of times downloads which fail due to network errors are retried during repinning (defaults to `3`). Cargo makes these retries itself unless `net.retry` is configured, in which case failed invocations of cargo are rerun with an exponential backoff instead. Crates downloaded by earlier attempts are reused and the errors of all attempts are reported together |
+| `CARGO_BAZEL_REGISTRY_CACHE` | A directory in which registry indexes are cached across repins and `crate` extensions. Cargo only makes conditional requests for the cached files of sparse (`sparse+https`) indexes, which speeds up repinning when `CARGO_HOME` is isolated. Repins sharing the cache lock it while running cargo. It's only used with an isolated `CARGO_HOME` |
 | `CARGO_BAZEL_REPIN` | An indicator that the dependencies represented by the rule should be regenerated. `REPIN` may also be used. See [Repinning / Updating Dependencies](#repinning--updating-dependencies) for more details. |
 | `CARGO_BAZEL_REPIN_ONLY` | A comma-delimited allowlist for rules to execute repinning. Can be useful if multiple instances of the repository rule are used in a Bazel workspace, but repinning should be limited to one of them. |
 
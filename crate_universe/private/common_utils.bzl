@@ -6,9 +6,11 @@ load("//rust/platform:triple.bzl", _get_host_triple = "get_host_triple")
 
 get_host_triple = _get_host_triple
 
+CARGO_BAZEL_ADVISORY_DB = "CARGO_BAZEL_ADVISORY_DB"
 CARGO_BAZEL_ISOLATED = "CARGO_BAZEL_ISOLATED"
 CARGO_BAZEL_REPIN = "CARGO_BAZEL_REPIN"
 CARGO_BAZEL_DEBUG = "CARGO_BAZEL_DEBUG"
+CARGO_BAZEL_FAIL_ON_YANKED = "CARGO_BAZEL_FAIL_ON_YANKED"
 REPIN = "REPIN"
 
 CARGO_BAZEL_REPIN_ONLY = "CARGO_BAZEL_REPIN_ONLY"
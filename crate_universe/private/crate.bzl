@@ -98,6 +98,7 @@ def _annotation(
         build_script_rustc_env = None,
         build_script_toolchains = None,
         build_script_use_default_shell_env = None,
+        cargo_pkg_env = False,
         compile_data = None,
         compile_data_glob = None,
         crate_features = None,
@@ -108,14 +109,19 @@ def _annotation(
         gen_binaries = None,
         disable_pipelining = False,
         gen_build_script = None,
+        gen_docs = False,
+        ignore_advisories = None,
+        override_crate_features = None,
         patch_args = None,
         patch_tool = None,
         patches = None,
         proc_macro_deps = None,
+        remove_crate_features = None,
         rustc_env = None,
         rustc_env_files = None,
         rustc_flags = None,
         shallow_since = None,
+        override_path = None,
         override_targets = None):
     """A collection of extra attributes and settings for a particular crate
 
@@ -134,7 +140,9 @@ def _annotation(
             attribute.
         build_script_deps (list, optional): A list of labels to add to a crate's `cargo_build_script::deps` attribute.
         build_script_env (dict, optional): Additional environment variables to set on a crate's
-            `cargo_build_script::env` attribute.
+            `cargo_build_script::env` attribute. Values may use location expansion such as
+            `$(execpath @protobuf//:protoc)`, where labels which aren't in `build_script_data` are
+            added to the build script's `tools`, and make variables of `build_script_toolchains`.
         build_script_proc_macro_deps (list, optional): A list of labels to add to a crate's
             `cargo_build_script::proc_macro_deps` attribute.
         build_script_rundir (str, optional): An override for the build script's rundir attribute.
@@ -143,6 +151,9 @@ def _annotation(
         build_script_toolchains (list, optional): A list of labels to set on a crates's `cargo_build_script::toolchains` attribute.
         build_script_use_default_shell_env (int, optional): Whether or not to include the default shell environment for the build
             script action.
+        cargo_pkg_env (bool, optional): If True, the `CARGO_PKG_*` environment variables Cargo derives from the
+            crate's manifest (e.g. `CARGO_PKG_DESCRIPTION` or `CARGO_PKG_README`) are set on the crate's `rustc_env`
+            and its build script's `build_script_env`.
         compile_data (list, optional): A list of labels to add to a crate's `rust_library::compile_data` attribute.
         compile_data_glob (list, optional): A list of glob patterns to add to a crate's `rust_library::compile_data`
             attribute.
@@ -154,10 +165,19 @@ def _annotation(
         extra_aliased_targets (dict, optional): A list of targets to add to the generated aliases in the root
             crate_universe repository.
         gen_binaries (list or bool, optional): As a list, the subset of the crate's bins that should get `rust_binary`
-            targets produced. Or `True` to generate all, `False` to generate none.
+            targets produced. Or `True` to generate all, `False` to generate none. The binaries are available as
+            `{crate}__{bin}` in the generated repository. Like Cargo, bins whose `required-features` are not
+            enabled are skipped, and requesting such a bin by name is an error.
         disable_pipelining (bool, optional): If True, disables pipelining for library targets for this crate.
         gen_build_script (bool, optional): An authorative flag to determine whether or not to produce
             `cargo_build_script` targets for the current crate.
+        gen_docs (bool, optional): If True, a `rust_doc` target named `{lib}_doc` is generated for the crate's
+            library. Direct dependencies of the workspace are also available as `{crate}_doc` in the generated
+            repository.
+        ignore_advisories (list, optional): A list of [RustSec](https://rustsec.org/) advisory ids (e.g.
+            `RUSTSEC-2020-0071`) which should not be reported for the crate when auditing it.
+        override_crate_features (list, optional): A list of features which replace the ones Cargo resolved for
+            a crate's `rust_library::crate_features` attribute. `crate_features` are still added to them.
         patch_args (list, optional): The `patch_args` attribute of a Bazel repository rule. See
             [http_archive.patch_args](https://docs.bazel.build/versions/main/repo/http.html#http_archive-patch_args)
         patch_tool (string, optional): The `patch_tool` attribute of a Bazel repository rule. See
@@ -166,12 +186,19 @@ def _annotation(
             [http_archive.patches](https://docs.bazel.build/versions/main/repo/http.html#http_archive-patches)
         proc_macro_deps (list, optional): A list of labels to add to a crate's `rust_library::proc_macro_deps`
             attribute.
+        remove_crate_features (list, optional): A list of features to remove from a crate's
+            `rust_library::crate_features` attribute, e.g. ones activated by `default`. Features
+            explicitly enabled by dependents of the crate can't be removed.
         rustc_env (dict, optional): Additional variables to set on a crate's `rust_library::rustc_env` attribute.
         rustc_env_files (list, optional): A list of labels to set on a crate's `rust_library::rustc_env_files`
             attribute.
         rustc_flags (list, optional): A list of strings to set on a crate's `rust_library::rustc_flags` attribute.
         shallow_since (str, optional): An optional timestamp used for crates originating from a git repository
-            instead of a crate registry. This flag optimizes fetching the source code.
+            instead of a crate registry. This flag optimizes fetching the source code. Defaults to the date of the
+            pinned commit.
+        override_path (str, optional): The directory of a local fork to use as the crate's sources instead of
+            the ones from its registry, either a package of the root workspace (e.g. `//third_party/forks/foo`)
+            or a path relative to it. The crate is otherwise rendered from the metadata of the locked version.
         override_targets (dict, optional): A dictionary of alternate targets to use when something depends on this crate to allow
             the parent repo to provide its own version of this dependency. Keys can be `proc-marco`, `custom-build`, `lib`, `bin`.
 
@@ -201,6 +228,7 @@ def _annotation(
             build_script_rustc_env = build_script_rustc_env,
             build_script_toolchains = _stringify_list(build_script_toolchains),
             build_script_use_default_shell_env = build_script_use_default_shell_env,
+            cargo_pkg_env = cargo_pkg_env,
             compile_data = _stringify_list(compile_data),
             compile_data_glob = compile_data_glob,
             crate_features = crate_features,
@@ -211,14 +239,19 @@ def _annotation(
             gen_binaries = gen_binaries,
             disable_pipelining = disable_pipelining,
             gen_build_script = gen_build_script,
+            gen_docs = gen_docs,
+            ignore_advisories = ignore_advisories,
+            override_crate_features = override_crate_features,
             patch_args = patch_args,
             patch_tool = patch_tool,
             patches = _stringify_list(patches),
             proc_macro_deps = _stringify_list(proc_macro_deps),
+            remove_crate_features = remove_crate_features,
             rustc_env = rustc_env,
             rustc_env_files = _stringify_list(rustc_env_files),
             rustc_flags = rustc_flags,
             shallow_since = shallow_since,
+            override_path = override_path,
             override_targets = override_targets,
         ),
     ))
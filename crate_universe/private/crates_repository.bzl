@@ -142,9 +142,14 @@ Environment Variables:
 
 | variable | usage |
 | --- | --- |
+| `CARGO_BAZEL_ADVISORY_DB` | The path to a checkout of the [RustSec advisory database](https://github.com/rustsec/advisory-db). If set, repinning warns about crates affected by advisories which are not ignored by the `ignore_advisories` annotation |
+| `CARGO_BAZEL_CRATE_CACHE` | A directory in which the archives of downloaded crates are cached by their checksum across repins and `crates_repository` rules. Unchanged crates are restored into `CARGO_HOME` instead of being downloaded again, which is most useful when `CARGO_HOME` is isolated. Crates of registries other than crates.io are only restored once their index was fetched, see `CARGO_BAZEL_REGISTRY_CACHE` |
+| `CARGO_BAZEL_FAIL_ON_YANKED` | If set to `true`, repinning fails instead of warning when locked crates have been yanked from their registries |
 | `CARGO_BAZEL_GENERATOR_SHA256` | The sha256 checksum of the file located at `CARGO_BAZEL_GENERATOR_URL` |
 | `CARGO_BAZEL_GENERATOR_URL` | The URL of a cargo-bazel binary. This variable takes precedence over attributes and can use `file://` for local paths |
 | `CARGO_BAZEL_ISOLATED` | An authoritative flag as to whether or not the `CARGO_HOME` environment variable should be isolated from the host configuration |
+| `CARGO_BAZEL_NETWORK_RETRIES` | The number of times downloads which fail due to network errors are retried during repinning (defaults to `3`). Cargo makes these retries itself unless `net.retry` is configured, in which case failed invocations of cargo are rerun with an exponential backoff instead. Crates downloaded by earlier attempts are reused and the errors of all attempts are reported together |
+| `CARGO_BAZEL_REGISTRY_CACHE` | A directory in which registry indexes are cached across repins and `crates_repository` rules. Cargo only makes conditional requests for the cached files of sparse (`sparse+https`) indexes, which speeds up repinning when `CARGO_HOME` is isolated. Repins sharing the cache lock it while running cargo. It's only used with an isolated `CARGO_HOME` |
 | `CARGO_BAZEL_REPIN` | An indicator that the dependencies represented by the rule should be regenerated. `REPIN` may also be used. See [Repinning / Updating Dependencies](#repinning--updating-dependencies) for more details. |
 | `CARGO_BAZEL_REPIN_ONLY` | A comma-delimited allowlist for rules to execute repinning. Can be useful if multiple instances of the repository rule are used in a Bazel workspace, but repinning should be limited to one of them. |
 
@@ -211,9 +216,14 @@ that is called behind the scenes to update dependencies.
 | --- | --- |
 | Any of [`true`, `1`, `yes`, `on`, `workspace`] | `cargo update --workspace` |
 | Any of [`full`, `eager`, `all`] | `cargo update` |
-| `package_name` | `cargo upgrade --package package_name` |
-| `package_name@1.2.3` | `cargo upgrade --package package_name@1.2.3` |
-| `package_name@1.2.3=4.5.6` | `cargo upgrade --package package_name@1.2.3 --precise=4.5.6` |
+| `package_name` | `cargo update --package package_name` |
+| `package_name@1.2.3` | `cargo update --package package_name@1.2.3` |
+| `package_name@1.2.3=4.5.6` | `cargo update --package package_name@1.2.3 --precise=4.5.6` |
+| `package_a,package_b@1.2.3` | `cargo update --package package_a --package package_b@1.2.3` |
+
+Updating specific packages leaves the pins of all other packages untouched unless a newer version of a
+requested package requires them to change, which keeps the resulting lockfile diff small and reviewable.
+Each package added, removed or updated by such a repin is logged.
 
 If the `crates_repository` is used multiple times in the same Bazel workspace (e.g. for multiple independent
 Rust workspaces), it may additionally be useful to use the `CARGO_BAZEL_REPIN_ONLY` environment variable, which
@@ -224,6 +234,30 @@ allowlist:
 CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_index
 ```
 
+### Private registries
+
+Crates can be resolved from [alternate registries](https://doc.rust-lang.org/cargo/reference/registries.html)
+defined in the `cargo_config` file. Cargo authenticates to them during resolution on its own, using the
+`token` of the registry, its `CARGO_REGISTRIES_{NAME}_TOKEN` environment variable or its
+[credential provider](https://doc.rust-lang.org/cargo/reference/registry-authentication.html).
+
+Crates are downloaded by Bazel though, which needs the same credentials. `cargo-bazel credential-helper`
+implements the Bazel [credential helper](https://github.com/EngFlow/credential-helper-spec) protocol for
+the registries of a Cargo config, resolving tokens from the environment, the config, the Cargo credentials
+file, `cargo:token-from-stdout` and external credential providers. It's registered for the hosts of the
+registries in the `.bazelrc` file:
+
+```text
+common --credential_helper=crates.example.com=%workspace%/tools/cargo_credential_helper.sh
+```
+
+With `tools/cargo_credential_helper.sh` calling a `cargo-bazel` binary:
+
+```shell
+#!/usr/bin/env bash
+exec cargo-bazel credential-helper --cargo-config="$(dirname "$0")/../.cargo/config.toml" "$@"
+```
+
 """,
     implementation = _crates_repository_impl,
     attrs = {
@@ -243,10 +277,31 @@ CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_i
             ),
             mandatory = True,
         ),
+        "cargo_profile": attr.string(
+            doc = (
+                "The name of a Cargo profile (e.g. `release`) defined in the root manifest or built into Cargo, " +
+                "whose settings are approximated with `rustc_flags` on every crate, including its " +
+                "`[profile.<name>.package.<spec>]` overrides. Settings which can't be mapped to `rustc` flags " +
+                "(such as `panic` or `lto`) are reported as warnings."
+            ),
+        ),
         "compressed_windows_toolchain_names": attr.bool(
             doc = "Wether or not the toolchain names of windows toolchains are expected to be in a `compressed` format.",
             default = True,
         ),
+        "feature_unification": attr.string(
+            doc = (
+                "The scope in which the features of dependencies are unified. `workspace` unifies features across " +
+                "all workspace members like Cargo does. `package` resolves the dependencies of each workspace member " +
+                "in isolation so features enabled by one member don't leak into another, rendering additional " +
+                "instances of crates which are resolved differently for some members."
+            ),
+            values = [
+                "package",
+                "workspace",
+            ],
+            default = "workspace",
+        ),
         "generate_binaries": attr.bool(
             doc = (
                 "Whether to generate `rust_binary` targets for all the binary crates in every package. " +
@@ -345,10 +400,34 @@ CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_i
                 "generate the value for this field. If unset, the defaults defined there will be used."
             ),
         ),
+        "strict_dev_dependencies": attr.bool(
+            doc = (
+                "If true, crates built for production targets don't get the features which are only enabled by the " +
+                "dev dependencies of workspace members, as with Cargo's `resolver = \"2\"` when not building tests. " +
+                "Crates shared with dev dependencies are then built with their production features only, so tests " +
+                "relying on features enabled by dev dependencies may need `crate.annotation(crate_features = ...)`. " +
+                "The crates and features which were pruned are logged."
+            ),
+            default = False,
+        ),
         "supported_platform_triples": attr.string_list(
-            doc = "A set of all platform triples to consider when generating dependencies.",
+            doc = (
+                "A set of all platform triples to consider when generating dependencies. Dependencies with `cfg` " +
+                "expressions involving `debug_assertions` are selected per platform and compilation mode using " +
+                "`selects.config_setting_group` from `bazel_skylib`, where `dbg` and `fastbuild` enable `debug_assertions`. " +
+                "Other non-platform predicates such as `test`, `proc_macro` or `feature` never hold."
+            ),
             default = SUPPORTED_PLATFORM_TRIPLES,
         ),
+        "target_specs": attr.string_dict(
+            doc = (
+                "A mapping of platform triples which are not built into `rustc` (or recognized by crate_universe) to " +
+                "the labels of their [custom target specifications](https://doc.rust-lang.org/rustc/targets/custom.html). " +
+                "These triples must also be listed in `supported_platform_triples`. Note that custom target " +
+                "specifications require a nightly `rustc`. Use the `platform_labels` parameter of `render_config` to " +
+                "choose the platform constraints used for them in generated `select`s."
+            ),
+        ),
     },
     environ = CRATES_REPOSITORY_ENVIRON,
 )
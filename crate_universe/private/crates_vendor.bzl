@@ -124,6 +124,17 @@ exit %ERRORLEVEL%
 
 CARGO_BAZEL_GENERATOR_PATH = "CARGO_BAZEL_GENERATOR_PATH"
 
+CratesVendorGroupInfo = provider(
+    doc = "The inputs for vendoring a `crates_vendor` target as one of the `groups` of another.",
+    fields = {
+        "cargo_lockfile": "File: The `Cargo.lock` file of the group, or `None`.",
+        "config": "File: The cargo-bazel config of the group.",
+        "name": "str: The name identifying the group in diagnostics.",
+        "runfiles": "depset[File]: The files required for vendoring the group.",
+        "splicing_manifest": "File: The splicing manifest of the group.",
+    },
+)
+
 def _default_render_config():
     return json.decode(generate_render_config())
 
@@ -206,6 +217,88 @@ def _prepare_manifest_path(target):
 
     return "${build_workspace_directory}/" + manifest.short_path
 
+def _prepare_lockfile_path(file):
+    """Generate a path to a lockfile which is resolvable by `cargo_bazel::cli::vendor::VendorGroup`
+
+    Args:
+        file (File): A `Cargo.lock` file.
+
+    Returns:
+        str: A string representing the path to the lockfile.
+    """
+    if file.owner.workspace_root.startswith("external"):
+        return file.short_path.replace("../", "${output_base}/external/", 1)
+
+    return "${build_workspace_directory}/" + file.short_path
+
+def relative_path(from_dir, to):
+    """Compute the path of `to` relative to the directory `from_dir`.
+
+    Args:
+        from_dir (str): A directory, relative to the same root as `to`.
+        to (str): The path of a file.
+
+    Returns:
+        str: The relative path from `from_dir` to `to`.
+    """
+    from_parts = [part for part in from_dir.split("/") if part]
+    to_parts = to.split("/")
+    common = 0
+    for index in range(min(len(from_parts), len(to_parts) - 1)):
+        if from_parts[index] != to_parts[index]:
+            break
+        common += 1
+
+    return "/".join([".."] * (len(from_parts) - common) + to_parts[common:])
+
+def generate_groups_file(*, groups, groups_dir):
+    """Render the file listing the groups vendored by a `crates_vendor` target.
+
+    Args:
+        groups (list): A list of `CratesVendorGroupInfo`-like structs whose files are given as
+            runfiles paths.
+        groups_dir (str): The runfiles path of the directory the groups file is written to.
+
+    Returns:
+        str: The contents of the groups file.
+    """
+    return json.encode_indent(
+        [
+            {
+                "cargo_lockfile": group.cargo_lockfile,
+                "config": relative_path(groups_dir, group.config),
+                "name": group.name,
+                "splicing_manifest": relative_path(groups_dir, group.splicing_manifest),
+            }
+            for group in groups
+        ],
+        indent = " " * 4,
+    )
+
+def _write_groups_file(ctx, groups):
+    groups_file = ctx.actions.declare_file("{}.cargo-bazel-groups.json".format(ctx.label.name))
+    ctx.actions.write(
+        output = groups_file,
+        content = generate_groups_file(
+            groups = [
+                struct(
+                    cargo_lockfile = _prepare_lockfile_path(group.cargo_lockfile) if group.cargo_lockfile else None,
+                    config = _rlocationpath(group.config, ctx.workspace_name),
+                    name = group.name,
+                    splicing_manifest = _rlocationpath(group.splicing_manifest, ctx.workspace_name),
+                )
+                for group in groups
+            ],
+            groups_dir = _rlocationpath(groups_file, ctx.workspace_name).rpartition("/")[0],
+        ),
+    )
+
+    is_windows = _is_windows(ctx)
+    env = [_sys_runfile_env(ctx, "GROUPS", groups_file, is_windows)]
+    args = ["--groups", _expand_env("GROUPS", is_windows)]
+    runfiles = depset([groups_file], transitive = [group.runfiles for group in groups])
+    return args, env, runfiles
+
 def _write_splicing_manifest(ctx):
     # Manifests are required to be single files
     manifests = {_prepare_manifest_path(m): str(m.label) for m in ctx.attr.manifests}
@@ -233,7 +326,7 @@ def _write_splicing_manifest(ctx):
     env = [_sys_runfile_env(ctx, "SPLICING_MANIFEST", manifest, is_windows)]
     args = ["--splicing-manifest", _expand_env("SPLICING_MANIFEST", is_windows)]
     runfiles = [manifest] + ctx.files.manifests + ([ctx.file.cargo_config] if ctx.attr.cargo_config else [])
-    return args, env, runfiles
+    return manifest, args, env, runfiles
 
 def generate_splicing_manifest(*, packages, splicing_config, cargo_config, manifests, manifest_to_path):
     # Deserialize information about direct packages
@@ -274,6 +367,9 @@ def _write_config_file(ctx):
             output_pkg = _get_output_package(ctx),
             workspace_name = workspace_name,
             render_config = dict(json.decode(ctx.attr.render_config)) if ctx.attr.render_config else None,
+            feature_unification = ctx.attr.feature_unification,
+            strict_dev_dependencies = ctx.attr.strict_dev_dependencies,
+            cargo_profile = ctx.attr.cargo_profile,
         ),
     )
 
@@ -281,7 +377,7 @@ def _write_config_file(ctx):
     env = [_sys_runfile_env(ctx, "CONFIG", config, is_windows)]
     args = ["--config", _expand_env("CONFIG", is_windows)]
     runfiles = [config] + ctx.files.manifests
-    return args, env, runfiles
+    return config, args, env, runfiles
 
 def generate_config_file(
         ctx,
@@ -295,7 +391,11 @@ def generate_config_file(
         output_pkg,
         workspace_name,
         render_config,
-        repository_ctx = None):
+        repository_ctx = None,
+        target_specs = {},
+        feature_unification = "workspace",
+        strict_dev_dependencies = False,
+        cargo_profile = None):
     """Writes the rendering config to cargo-bazel-config.json.
 
     Args:
@@ -313,6 +413,14 @@ def generate_config_file(
         render_config: The render config to use.
         repository_ctx (repository_ctx, optional): A repository context object
             used for enabling certain functionality.
+        target_specs (dict, optional): A mapping of platform triples to the labels
+            of their custom target specifications.
+        feature_unification (str, optional): The scope in which the features of
+            dependencies are unified, either `workspace` or `package`.
+        strict_dev_dependencies (bool, optional): Whether to exclude features only
+            enabled by dev dependencies from crates built for production targets.
+        cargo_profile (str, optional): The name of a Cargo profile whose settings are
+            approximated with `rustc_flags` on every crate.
 
     Returns:
         file: The cargo-bazel-config.json written.
@@ -387,6 +495,10 @@ def generate_config_file(
         supported_platform_triples = supported_platform_triples,
         repository_name = repository_name or ctx.label.name,
         repository_ctx = repository_ctx,
+        target_specs = target_specs,
+        feature_unification = feature_unification,
+        strict_dev_dependencies = strict_dev_dependencies,
+        cargo_profile = cargo_profile,
     )
 
     return json.encode_indent(
@@ -420,22 +532,42 @@ def _crates_vendor_impl(ctx):
         ))
 
     # Generate config file
-    config_args, config_env, config_runfiles = _write_config_file(ctx)
-    environ.extend(config_env)
-    args.extend(config_args)
-    cargo_bazel_runfiles.extend(config_runfiles)
+    config, config_args, config_env, config_runfiles = _write_config_file(ctx)
 
     # Generate splicing manifest
-    splicing_manifest_args, splicing_manifest_env, splicing_manifest_runfiles = _write_splicing_manifest(ctx)
-    environ.extend(splicing_manifest_env)
-    args.extend(splicing_manifest_args)
-    cargo_bazel_runfiles.extend(splicing_manifest_runfiles)
+    splicing_manifest, splicing_manifest_args, splicing_manifest_env, splicing_manifest_runfiles = _write_splicing_manifest(ctx)
+
+    group_info = CratesVendorGroupInfo(
+        cargo_lockfile = ctx.file.cargo_lockfile,
+        config = config,
+        name = str(ctx.label),
+        runfiles = depset(config_runfiles + splicing_manifest_runfiles + ctx.files.cargo_lockfile),
+        splicing_manifest = splicing_manifest,
+    )
+
+    cargo_bazel_transitive_runfiles = [toolchain.all_files]
+    if ctx.attr.groups:
+        # This target is only vendored as a group of its own if it has any dependencies.
+        groups = [group_info] if ctx.attr.manifests or ctx.attr.packages else []
+        groups.extend([group[CratesVendorGroupInfo] for group in ctx.attr.groups])
+        groups_args, groups_env, groups_runfiles = _write_groups_file(ctx, groups)
+        environ.extend(groups_env)
+        args.extend(groups_args)
+        cargo_bazel_transitive_runfiles.append(groups_runfiles)
+    else:
+        environ.extend(config_env)
+        args.extend(config_args)
+        cargo_bazel_runfiles.extend(config_runfiles)
+
+        environ.extend(splicing_manifest_env)
+        args.extend(splicing_manifest_args)
+        cargo_bazel_runfiles.extend(splicing_manifest_runfiles)
 
-    # Add an optional `Cargo.lock` file.
-    if ctx.attr.cargo_lockfile:
-        environ.append(_sys_runfile_env(ctx, "CARGO_LOCK", ctx.file.cargo_lockfile, is_windows))
-        args.extend(["--cargo-lockfile", _expand_env("CARGO_LOCK", is_windows)])
-        cargo_bazel_runfiles.extend([ctx.file.cargo_lockfile])
+        # Add an optional `Cargo.lock` file.
+        if ctx.attr.cargo_lockfile:
+            environ.append(_sys_runfile_env(ctx, "CARGO_LOCK", ctx.file.cargo_lockfile, is_windows))
+            args.extend(["--cargo-lockfile", _expand_env("CARGO_LOCK", is_windows)])
+            cargo_bazel_runfiles.extend([ctx.file.cargo_lockfile])
 
     # Optionally include buildifier
     if ctx.attr.buildifier:
@@ -449,6 +581,14 @@ def _crates_vendor_impl(ctx):
         args.extend(["--bazel", _expand_env("BAZEL_REAL", is_windows)])
         cargo_bazel_runfiles.append(ctx.executable.bazel)
 
+    args.extend(["--vendor-integrity", ctx.attr.vendor_integrity])
+    if ctx.attr.vendor_prune:
+        args.append("--vendor-prune")
+        for path in ctx.attr.vendor_prune_paths:
+            args.extend(["--vendor-prune-path", path])
+        for path in ctx.attr.vendor_prune_keep:
+            args.extend(["--vendor-prune-keep", path])
+
     # Determine platform specific settings
     if is_windows:
         extension = ".bat"
@@ -472,14 +612,20 @@ def _crates_vendor_impl(ctx):
     if runner.basename.endswith(".sh"):
         cargo_bazel_runfiles.append(ctx.file._bash_runfiles)
 
-    return DefaultInfo(
+    providers = [DefaultInfo(
         files = depset([runner]),
         runfiles = ctx.runfiles(
             files = cargo_bazel_runfiles,
-            transitive_files = toolchain.all_files,
+            transitive_files = depset(transitive = cargo_bazel_transitive_runfiles),
         ),
         executable = runner,
-    )
+    )]
+
+    # Targets vendoring groups can't be vendored as a group themselves.
+    if not ctx.attr.groups:
+        providers.append(group_info)
+
+    return providers
 
 CRATES_VENDOR_ATTRS = {
     "annotations": attr.string_list_dict(
@@ -516,6 +662,27 @@ CRATES_VENDOR_ATTRS = {
         doc = "The path to an existing `Cargo.lock` file",
         allow_single_file = True,
     ),
+    "cargo_profile": attr.string(
+        doc = (
+            "The name of a Cargo profile (e.g. `release`) defined in the root manifest or built into Cargo, " +
+            "whose settings are approximated with `rustc_flags` on every crate, including its " +
+            "`[profile.<name>.package.<spec>]` overrides. Settings which can't be mapped to `rustc` flags " +
+            "(such as `panic` or `lto`) are reported as warnings."
+        ),
+    ),
+    "feature_unification": attr.string(
+        doc = (
+            "The scope in which the features of dependencies are unified. `workspace` unifies features across " +
+            "all workspace members like Cargo does. `package` resolves the dependencies of each workspace member " +
+            "in isolation so features enabled by one member don't leak into another, rendering additional " +
+            "instances of crates which are resolved differently for some members."
+        ),
+        values = [
+            "package",
+            "workspace",
+        ],
+        default = "workspace",
+    ),
     "generate_binaries": attr.bool(
         doc = (
             "Whether to generate `rust_binary` targets for all the binary crates in every package. " +
@@ -534,6 +701,16 @@ CRATES_VENDOR_ATTRS = {
         doc = "DEPRECATED: Moved to `render_config`.",
         default = True,
     ),
+    "groups": attr.label_list(
+        doc = (
+            "Other `crates_vendor` targets to vendor in the same invocation, each into its own `vendor_path` " +
+            "and hub repository. The groups share the cargo home, so crates and registry indexes downloaded " +
+            "by one group are reused by the groups after it. This target is vendored as a group too if it has " +
+            "any `manifests` or `packages`. The `vendor_*`, `bazel` and `buildifier` attributes of this target " +
+            "apply to all groups. See [Vendoring several dependency groups](#crates_vendor_groups)."
+        ),
+        providers = [CratesVendorGroupInfo],
+    ),
     "manifests": attr.label_list(
         doc = "A list of Cargo manifests (`Cargo.toml` files).",
         allow_files = ["Cargo.toml"],
@@ -568,10 +745,59 @@ CRATES_VENDOR_ATTRS = {
             "generate the value for this field. If unset, the defaults defined there will be used."
         ),
     ),
+    "strict_dev_dependencies": attr.bool(
+        doc = (
+            "If true, crates built for production targets don't get the features which are only enabled by the " +
+            "dev dependencies of workspace members, as with Cargo's `resolver = \"2\"` when not building tests. " +
+            "Crates shared with dev dependencies are then built with their production features only, so tests " +
+            "relying on features enabled by dev dependencies may need `crate.annotation(crate_features = ...)`. " +
+            "The crates and features which were pruned are logged."
+        ),
+        default = False,
+    ),
     "supported_platform_triples": attr.string_list(
-        doc = "A set of all platform triples to consider when generating dependencies.",
+        doc = (
+            "A set of all platform triples to consider when generating dependencies. Dependencies with `cfg` " +
+            "expressions involving `debug_assertions` are selected per platform and compilation mode using " +
+            "`selects.config_setting_group` from `bazel_skylib`, where `dbg` and `fastbuild` enable `debug_assertions`. " +
+            "Other non-platform predicates such as `test`, `proc_macro` or `feature` never hold."
+        ),
         default = SUPPORTED_PLATFORM_TRIPLES,
     ),
+    "vendor_integrity": attr.string(
+        doc = (
+            "How to handle vendored crates (in `local` mode) which were modified since they were vendored or " +
+            "don't match the checksums recorded by `cargo vendor`. The checksum of each vendored crate is " +
+            "recorded in `cargo-bazel-vendor-lock.json` within `vendor_path`. `fail` is an error, `warn` only " +
+            "prints a warning."
+        ),
+        values = [
+            "fail",
+            "warn",
+        ],
+        default = "fail",
+    ),
+    "vendor_prune": attr.bool(
+        doc = (
+            "If true, files of crates vendored in `local` mode which aren't needed to build them are removed. " +
+            "By default these are the top level directories holding only the sources of tests, benches and " +
+            "examples (e.g. `tests/`). The removed files are dropped from each crate's `.cargo-checksum.json` " +
+            "so the vendored sources still verify."
+        ),
+        default = False,
+    ),
+    "vendor_prune_keep": attr.string_list(
+        doc = (
+            "Glob patterns, relative to the directory of each vendored crate, of files to keep when " +
+            "`vendor_prune` is set, e.g. fixtures read by build scripts."
+        ),
+    ),
+    "vendor_prune_paths": attr.string_list(
+        doc = (
+            "Glob patterns, relative to the directory of each vendored crate, of additional files to remove " +
+            "when `vendor_prune` is set, e.g. `docs/**`."
+        ),
+    ),
     "vendor_path": attr.string(
         doc = "The path to a directory to write files into. Absolute paths will be treated as relative to the workspace root",
         default = "crates",
@@ -654,8 +880,38 @@ call against the generated workspace. The following table describes how to contr
 | --- | --- |
 | Any of [`true`, `1`, `yes`, `on`, `workspace`] | `cargo update --workspace` |
 | Any of [`full`, `eager`, `all`] | `cargo update` |
-| `package_name` | `cargo upgrade --package package_name` |
-| `package_name@1.2.3` | `cargo upgrade --package package_name --precise 1.2.3` |
+| `package_name` | `cargo update --package package_name` |
+| `package_name@1.2.3` | `cargo update --package package_name@1.2.3` |
+| `package_name@1.2.3=4.5.6` | `cargo update --package package_name@1.2.3 --precise=4.5.6` |
+| `package_a,package_b@1.2.3` | `cargo update --package package_a --package package_b@1.2.3` |
+
+Updating specific packages leaves the pins of all other packages untouched unless a newer version of a
+requested package requires them to change, which keeps the resulting lockfile diff small and reviewable.
+Each package added, removed or updated by such a repin is logged.
+
+<a id="#crates_vendor_groups"></a>
+
+### Vendoring several dependency groups
+
+Isolated dependency universes, e.g. for production code, tools and fuzzing, can each be defined by a
+`crates_vendor` target of their own and vendored together by listing them in the `groups` of another:
+
+```python
+crates_vendor(
+    name = "crates_vendor",
+    groups = [
+        "//3rdparty/prod:crates_vendor",
+        "//3rdparty/tools:crates_vendor",
+    ],
+)
+```
+
+```shell
+bazel run //3rdparty:crates_vendor -- --repin
+```
+
+Every group is rendered into its own `vendor_path` and hub repository, which must not overlap, while
+crates are only downloaded once. Each group can still be vendored on its own by running its target.
 
 """,
     attrs = CRATES_VENDOR_ATTRS,
@@ -2,7 +2,9 @@
 
 load(
     ":common_utils.bzl",
+    "CARGO_BAZEL_ADVISORY_DB",
     "CARGO_BAZEL_DEBUG",
+    "CARGO_BAZEL_FAIL_ON_YANKED",
     "CARGO_BAZEL_ISOLATED",
     "REPIN_ALLOWLIST_ENV_VAR",
     "REPIN_ENV_VARS",
@@ -21,6 +23,8 @@ CRATES_REPOSITORY_ENVIRON = GENERATOR_ENV_VARS + REPIN_ENV_VARS + [
     REPIN_ALLOWLIST_ENV_VAR,
     CARGO_BAZEL_ISOLATED,
     CARGO_BAZEL_DEBUG,
+    CARGO_BAZEL_FAIL_ON_YANKED,
+    CARGO_BAZEL_ADVISORY_DB,
 ]
 
 def get_generator(repository_ctx, host_triple):
@@ -89,6 +93,7 @@ def get_generator(repository_ctx, host_triple):
 
 def render_config(
         build_file_template = "//:BUILD.{name}-{version}.bazel",
+        build_file_layout = "flat",
         crate_label_template = "@{repository}__{name}-{version}//:{target}",
         crate_alias_template = "//:{name}-{version}",
         crate_repository_template = "{repository}__{name}-{version}",
@@ -96,10 +101,14 @@ def render_config(
         default_alias_rule = "alias",
         default_package_name = None,
         generate_target_compatible_with = True,
+        package_aliases = None,
         platforms_template = "@rules_rust//rust/platform:{triple}",
+        platform_labels = {},
         regen_command = None,
         vendor_mode = None,
-        generate_rules_license_metadata = False):
+        generate_rules_license_metadata = False,
+        templates = {},
+        templates_version = None):
     """Various settings used to configure rendered outputs
 
     The template parameters each support a select number of format keys. A description of each key
@@ -117,6 +126,9 @@ def render_config(
     Args:
         build_file_template (str, optional): The base template to use for BUILD file names. The available format keys
             are [`{name}`, {version}`].
+        build_file_layout (str, optional): How crate BUILD files are laid out. `flat` names them after
+            `build_file_template`. `per_crate` renders a `BUILD.bazel` file in a `{name}-{version}` directory
+            for each crate next to the crates module, keeping the package of the crates module small.
         crate_label_template (str, optional): The base template to use for crate labels. The available format keys
             are [`{repository}`, `{name}`, `{version}`, `{target}`].
         crate_repository_template (str, optional): The base template to use for Crate label repository names. The
@@ -134,17 +146,30 @@ def render_config(
         generate_target_compatible_with (bool, optional):  Whether to generate `target_compatible_with` annotations on
             the generated BUILD files.  This catches a `target_triple`being targeted that isn't declared in
             `supported_platform_triples`.
+        package_aliases (str, optional): Stable aliases to render for each package so labels referring to
+            them don't change when they're upgraded. `unversioned` renders e.g. `@crates//:serde` for packages
+            resolved at a single version. `major` additionally renders an alias per semver compatible series,
+            e.g. `@crates//:serde-1` or `@crates//:rand-0.8`.
         platforms_template (str, optional): The base template to use for platform names.
             See [platforms documentation](https://docs.bazel.build/versions/main/platforms.html). The available format
             keys are [`{triple}`].
+        platform_labels (dict, optional): A mapping of platform triples to the labels of the platform constraints
+            used for them in place of `platforms_template`, e.g. for custom targets.
         regen_command (str, optional): An optional command to demonstrate how generated files should be regenerated.
         vendor_mode (str, optional): An optional configuration for rendirng content to be rendered into repositories.
         generate_rules_license_metadata (bool, optional): Whether to generate rules license metedata
+        templates (dict, optional): A mapping of the names of rendering templates, e.g.
+            `partials/crate/loads.j2` and `partials/crate/footer.j2` which extend each crate's BUILD file,
+            to [Tera](https://keats.github.io/tera/) templates overriding them.
+        templates_version (int, optional): The version of the rendering templates `templates` were written
+            against. Rendering fails if this does not match the version of the default templates, e.g. after
+            an upgrade which changed them.
 
     Returns:
         string: A json encoded struct to match the Rust `config::RenderConfig` struct
     """
     return json.encode(struct(
+        build_file_layout = build_file_layout,
         build_file_template = build_file_template,
         crate_alias_template = crate_alias_template,
         crate_label_template = crate_label_template,
@@ -154,8 +179,12 @@ def render_config(
         default_package_name = default_package_name,
         generate_rules_license_metadata = generate_rules_license_metadata,
         generate_target_compatible_with = generate_target_compatible_with,
+        package_aliases = package_aliases,
+        platform_labels = platform_labels,
         platforms_template = platforms_template,
         regen_command = regen_command,
+        templates = templates,
+        templates_version = templates_version,
         vendor_mode = vendor_mode,
     ))
 
@@ -243,7 +272,11 @@ def compile_config(
         render_config,
         supported_platform_triples,
         repository_name,
-        repository_ctx = None):
+        repository_ctx = None,
+        target_specs = {},
+        feature_unification = "workspace",
+        strict_dev_dependencies = False,
+        cargo_profile = None):
     """Create a config file for generating crate targets
 
     [cargo_config]: https://doc.rust-lang.org/cargo/reference/config.html
@@ -260,6 +293,14 @@ def compile_config(
         repository_name (str): The name of the repository being generated
         repository_ctx (repository_ctx, optional): A repository context object used for enabling
             certain functionality.
+        target_specs (dict, optional): A mapping of platform triples which are not built into `rustc`
+            to the labels of their [custom target specifications](https://doc.rust-lang.org/rustc/targets/custom.html).
+        feature_unification (str, optional): The scope in which the features of dependencies are unified,
+            either `workspace` or `package`.
+        strict_dev_dependencies (bool, optional): Whether to exclude features only enabled by dev dependencies
+            from crates built for production targets.
+        cargo_profile (str, optional): The name of a Cargo profile whose settings are approximated with
+            `rustc_flags` on every crate.
 
     Returns:
         struct: A struct matching a `cargo_bazel::config::Config`.
@@ -283,6 +324,13 @@ def compile_config(
     if unexpected:
         fail("The following annotations use `additive_build_file` which is not supported for {}: {}".format(repository_name, unexpected))
 
+    if target_specs and not repository_ctx:
+        fail("`target_specs` are not supported for {}".format(repository_name))
+    specs = {
+        triple: json.decode(repository_ctx.read(Label(spec)))
+        for triple, spec in target_specs.items()
+    }
+
     # Deprecated: Apply `generate_target_compatible_with` to `render_config`.
     if not generate_target_compatible_with:
         # buildifier: disable=print
@@ -299,6 +347,10 @@ def compile_config(
             repository_name = repository_name,
         ),
         supported_platform_triples = supported_platform_triples,
+        target_specs = specs,
+        feature_unification = feature_unification,
+        strict_dev_dependencies = strict_dev_dependencies,
+        cargo_profile = cargo_profile or None,
     )
 
     return config
@@ -323,6 +375,10 @@ def generate_config(repository_ctx):
         supported_platform_triples = repository_ctx.attr.supported_platform_triples,
         repository_name = repository_ctx.name,
         repository_ctx = repository_ctx,
+        target_specs = repository_ctx.attr.target_specs,
+        feature_unification = repository_ctx.attr.feature_unification,
+        strict_dev_dependencies = repository_ctx.attr.strict_dev_dependencies,
+        cargo_profile = repository_ctx.attr.cargo_profile,
     )
 
     config_path = repository_ctx.path("cargo-bazel.json")
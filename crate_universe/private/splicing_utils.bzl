@@ -2,21 +2,55 @@
 
 load(":common_utils.bzl", "CARGO_BAZEL_DEBUG", "CARGO_BAZEL_REPIN", "REPIN")
 
-def splicing_config(resolver_version = "2"):
+def splicing_config(
+        resolver_version = "2",
+        index_mirrors = None,
+        download_mirrors = None,
+        ca_info = None,
+        report_duplicates = False,
+        dedup_strategy = None,
+        dedup_pins = None):
     """Various settings used to configure Cargo manifest splicing behavior.
 
     [rv]: https://doc.rust-lang.org/cargo/reference/resolver.html#resolver-versions
+    [sr]: https://doc.rust-lang.org/cargo/reference/source-replacement.html
 
     Args:
         resolver_version (str, optional): The [resolver version][rv] to use in generated Cargo
             manifests. This flag is **only** used when splicing a manifest from direct package
             definitions. See `crates_repository::packages`.
+        index_mirrors (dict, optional): A mapping of registry index urls (e.g.
+            `sparse+https://index.crates.io/`) to the urls of the indexes mirroring them. Cargo is
+            configured to [replace][sr] each registry with its mirror when resolving dependencies.
+        download_mirrors (dict, optional): A mapping of url prefixes of `.crate` downloads (e.g.
+            `https://static.crates.io/crates/`) to the prefixes replacing them in the rendered
+            repository definitions.
+        ca_info (str, optional): The absolute path to a file of PEM encoded certificate
+            authorities to trust instead of the system's when Cargo connects to registries.
+        report_duplicates (bool, optional): Whether to warn about each crate resolved at several
+            semver incompatible versions along with the dependency chains pulling in each version.
+        dedup_strategy (str, optional): The strategy used to deduplicate crates resolved at several
+            versions when repinning. `minimal-versions` updates every copy of a crate to its lowest
+            resolved version and `highest` to its highest. Copies whose dependents don't allow the
+            update are left in place.
+        dedup_pins (dict, optional): A mapping of crate names to the version every copy of the crate
+            is updated to when repinning. Pins take precedence over `dedup_strategy`.
 
     Returns:
         str: A json encoded string of the parameters provided
     """
     return json.encode(struct(
         resolver_version = resolver_version,
+        mirrors = struct(
+            indexes = index_mirrors or {},
+            downloads = download_mirrors or {},
+            ca_info = ca_info or None,
+        ),
+        duplicates = struct(
+            report = report_duplicates,
+            strategy = dedup_strategy or None,
+            pins = dedup_pins or {},
+        ),
     ))
 
 def kebab_case_keys(data):
@@ -2,3 +2,4 @@
 //! While it has no formal compatibility guarantees, it is much less likely to break than other types in this library.
 
 pub mod lockfile;
+pub mod pipeline;
@@ -42,11 +42,21 @@ struct CargoBazelLockfileImpl(Context);
 
 impl CargoBazelLockfile for CargoBazelLockfileImpl {
     fn workspace_members(&self) -> BTreeSet<CrateId> {
-        self.0.workspace_members.keys().cloned().collect()
+        self.0.workspace_members()
     }
 
     fn crate_info(&self, crate_id: &CrateId) -> Option<CrateInfo> {
-        let crate_context = self.0.crates.get(crate_id)?;
+        self.0.crate_info(crate_id)
+    }
+}
+
+impl CargoBazelLockfile for Context {
+    fn workspace_members(&self) -> BTreeSet<CrateId> {
+        self.workspace_members.keys().cloned().collect()
+    }
+
+    fn crate_info(&self, crate_id: &CrateId) -> Option<CrateInfo> {
+        let crate_context = self.crates.get(crate_id)?;
         Some(CrateInfo {
             name: crate_context.name.clone(),
             version: crate_context.version.clone(),
@@ -176,6 +186,7 @@ mod test {
                     },
                     target: String::from("anyhow"),
                     alias: None,
+                    instance: None,
                 },
                 CrateDependency {
                     id: CrateId {
@@ -184,6 +195,7 @@ mod test {
                     },
                     target: String::from("reqwest"),
                     alias: None,
+                    instance: None,
                 },
             ],
         );
@@ -0,0 +1,242 @@
+//! The pipeline module exposes the stages `cargo-bazel` runs to turn Cargo manifests into Bazel
+//! targets, so tools can embed them rather than running the `cargo-bazel` binary and parsing
+//! the files it writes.
+//!
+//! A typical pipeline [splice]s the manifests of a [SplicingManifest] into a single workspace,
+//! [resolve]s its dependencies into a [Lockfile] and [render]s the lockfile into BUILD files:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use cargo_bazel::api::pipeline::{render, resolve, splice, Config, SplicingManifest, Tools};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let config = Config::from_path(Path::new("config.json"))?;
+//! let splicing_manifest = SplicingManifest::from_path(Path::new("splicing_manifest.json"))?;
+//! let tools = Tools::new("/usr/bin/cargo".into(), "/usr/bin/rustc".into());
+//!
+//! let workspace_dir = tempfile::tempdir()?;
+//! let spliced = splice(&splicing_manifest, workspace_dir.path())?;
+//! let lockfile = resolve(&spliced, &splicing_manifest, &config, &tools, None, Path::new("."))?;
+//! lockfile.write(Path::new("cargo-bazel-lock.json"))?;
+//!
+//! render(&lockfile, &config)?.write(Path::new("crates"))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Like the rest of [crate::api], these types have no formal compatibility guarantees, but are
+//! much less likely to change than the internals of `cargo-bazel`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context as AnyhowContext, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::api::lockfile::{CargoBazelLockfile, CrateId, CrateInfo};
+use crate::config::{self, FeatureUnification};
+use crate::context::Context;
+use crate::lockfile::{lock_context, write_lockfile};
+use crate::metadata::{
+    Annotations, Cargo, Generator, IsolatedTreeResolverMetadata, MetadataGenerator, TreeResolver,
+};
+use crate::rendering::{write_outputs, Renderer};
+use crate::splicing::{self, generate_lockfile, SplicedManifest, Splicer, WorkspaceMetadata};
+use crate::utils::normalize_cargo_file_paths;
+
+/// The settings of a repository of crates, as written by the `crates_repository` and
+/// `crates_vendor` rules or the `crate` module extension.
+#[derive(Debug, Clone)]
+pub struct Config(config::Config);
+
+impl Config {
+    /// Load a config from a `json` file.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        config::Config::try_from_path(path).map(Self)
+    }
+}
+
+/// The Cargo manifests, configs and packages which make up a repository of crates.
+#[derive(Debug, Clone)]
+pub struct SplicingManifest(splicing::SplicingManifest);
+
+impl SplicingManifest {
+    /// Load a splicing manifest from a `json` file.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        splicing::SplicingManifest::try_from_path(path).map(Self)
+    }
+}
+
+/// The Cargo and rustc binaries used to resolve dependencies.
+#[derive(Debug, Clone)]
+pub struct Tools {
+    cargo: PathBuf,
+    rustc: PathBuf,
+}
+
+impl Tools {
+    pub fn new(cargo: PathBuf, rustc: PathBuf) -> Self {
+        Self { cargo, rustc }
+    }
+
+    fn cargo(&self) -> Cargo {
+        Cargo::new(self.cargo.clone(), self.rustc.clone())
+    }
+}
+
+/// A Cargo workspace combining the manifests of a [SplicingManifest].
+#[derive(Debug)]
+pub struct SplicedWorkspace(SplicedManifest);
+
+impl SplicedWorkspace {
+    /// The path to the root manifest of the workspace.
+    pub fn manifest_path(&self) -> &Path {
+        self.0.as_path_buf().as_std_path()
+    }
+}
+
+/// Splice the manifests of `splicing_manifest` into a single Cargo workspace in `workspace_dir`.
+pub fn splice(
+    splicing_manifest: &SplicingManifest,
+    workspace_dir: &Path,
+) -> Result<SplicedWorkspace> {
+    let workspace_dir = Utf8PathBuf::from_path_buf(workspace_dir.to_path_buf())
+        .map_err(|path| anyhow::anyhow!("{} is not valid UTF-8", path.display()))?;
+    let splicer = Splicer::new(workspace_dir, splicing_manifest.0.clone())
+        .context("Failed to create splicer")?;
+    splicer
+        .splice_workspace()
+        .map(SplicedWorkspace)
+        .context("Failed to splice workspace")
+}
+
+/// Resolve the dependencies of a spliced workspace into a [Lockfile].
+///
+/// The versions locked in `cargo_lockfile` are kept where possible. `bazel_workspace_dir` is the
+/// root of the Bazel workspace, which path dependencies are made relative to.
+pub fn resolve(
+    spliced: &SplicedWorkspace,
+    splicing_manifest: &SplicingManifest,
+    config: &Config,
+    tools: &Tools,
+    cargo_lockfile: Option<&Path>,
+    bazel_workspace_dir: &Path,
+) -> Result<Lockfile> {
+    let config = &config.0;
+    let splicing_manifest = &splicing_manifest.0;
+    let cargo = tools.cargo();
+    let manifest_path = spliced.0.as_path_buf();
+
+    let cargo_lockfile = generate_lockfile(
+        &spliced.0,
+        &cargo_lockfile.map(Path::to_path_buf),
+        cargo.clone(),
+        &None,
+        &splicing_manifest.duplicates,
+    )
+    .context("Failed to generate lockfile")?;
+
+    let tree_resolver = TreeResolver::new(cargo.clone())
+        .with_strict_dev_dependencies(config.strict_dev_dependencies);
+    let (resolver_data, exec_resolver_data, target_cfgs) = tree_resolver
+        .generate(
+            manifest_path,
+            &config.supported_platform_triples,
+            &config.target_specs,
+        )
+        .context("Failed to generate features")?;
+    let isolated_resolver_data = match config.feature_unification {
+        FeatureUnification::Workspace => IsolatedTreeResolverMetadata::new(),
+        FeatureUnification::Package => tree_resolver
+            .generate_isolated(
+                manifest_path,
+                &config.supported_platform_triples,
+                &config.target_specs,
+            )
+            .context("Failed to generate features for each workspace member")?,
+    };
+    WorkspaceMetadata::write_registry_urls_and_feature_map(
+        &cargo,
+        &cargo_lockfile,
+        resolver_data,
+        exec_resolver_data,
+        isolated_resolver_data,
+        target_cfgs,
+        manifest_path,
+        manifest_path,
+        &splicing_manifest.mirrors,
+    )
+    .context("Failed to write registry URLs and feature map")?;
+
+    let (cargo_metadata, cargo_lockfile) = Generator::new()
+        .with_cargo(cargo.clone())
+        .with_rustc(tools.rustc.clone())
+        .generate(manifest_path)
+        .context("Failed to generate cargo metadata")?;
+
+    let bazel_workspace_dir = Utf8Path::from_path(bazel_workspace_dir)
+        .with_context(|| format!("{} is not valid UTF-8", bazel_workspace_dir.display()))?;
+    let annotations = Annotations::new(
+        cargo_metadata,
+        cargo_lockfile,
+        config.clone(),
+        bazel_workspace_dir,
+    )?;
+    let context = Context::new(annotations, config.rendering.are_sources_present())?;
+
+    lock_context(context, config, splicing_manifest, &cargo, &tools.rustc).map(Lockfile)
+}
+
+/// A `cargo-bazel` lockfile, describing the resolved crates of a repository.
+#[derive(Debug, Clone)]
+pub struct Lockfile(Context);
+
+impl Lockfile {
+    /// Load a lockfile from disk.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        Context::try_from_path(path).map(Self)
+    }
+
+    /// Write the lockfile to disk.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write_lockfile(self.0.clone(), path, false)
+    }
+}
+
+impl CargoBazelLockfile for Lockfile {
+    fn workspace_members(&self) -> BTreeSet<CrateId> {
+        self.0.workspace_members()
+    }
+
+    fn crate_info(&self, crate_id: &CrateId) -> Option<CrateInfo> {
+        self.0.crate_info(crate_id)
+    }
+}
+
+/// The BUILD and `.bzl` files rendered for a [Lockfile].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedOutput(BTreeMap<PathBuf, String>);
+
+impl RenderedOutput {
+    /// The content of each file keyed by its path relative to the output directory.
+    pub fn files(&self) -> &BTreeMap<PathBuf, String> {
+        &self.0
+    }
+
+    /// Write the files to `dir`.
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        write_outputs(normalize_cargo_file_paths(self.0.clone(), dir), false)
+    }
+}
+
+/// Render the BUILD and `.bzl` files of the crates in `lockfile`.
+pub fn render(lockfile: &Lockfile, config: &Config) -> Result<RenderedOutput> {
+    Renderer::new(
+        Arc::new(config.0.rendering.clone()),
+        Arc::new(config.0.supported_platform_triples.clone()),
+    )
+    .render(&lockfile.0, None)
+    .map(RenderedOutput)
+}
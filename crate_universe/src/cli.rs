@@ -1,10 +1,16 @@
 //! Command line interface entry points and utilities
 
+mod credential_helper;
+mod explain;
+mod export_lockfile;
+mod features;
 mod generate;
 mod query;
 mod render;
+mod sbom;
 mod splice;
 mod vendor;
+mod verify;
 
 use clap::Parser;
 use tracing::{Level, Subscriber};
@@ -14,18 +20,30 @@ use tracing_subscriber::fmt::{FormatEvent, FormatFields};
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::FmtSubscriber;
 
+pub use self::credential_helper::CredentialHelperOptions;
+pub use self::explain::ExplainOptions;
+pub use self::export_lockfile::ExportLockfileOptions;
+pub use self::features::FeaturesOptions;
 pub use self::generate::GenerateOptions;
 pub use self::query::QueryOptions;
 pub use self::render::RenderOptions;
+pub use self::sbom::{SbomFormat, SbomOptions};
 pub use self::splice::SpliceOptions;
 pub use self::vendor::VendorOptions;
+pub use self::verify::VerifyOptions;
 
 // Entrypoints
+pub use credential_helper::credential_helper;
+pub use explain::explain;
+pub use export_lockfile::export_lockfile;
+pub use features::features;
 pub use generate::generate;
 pub use query::query;
 pub use render::render;
+pub use sbom::sbom;
 pub use splice::splice;
 pub use vendor::vendor;
+pub use verify::verify;
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -48,6 +66,24 @@ pub enum Options {
 
     /// Render a BUILD file for a single crate.
     Render(RenderOptions),
+
+    /// Explain why a crate is part of the resolved dependency graph.
+    Explain(ExplainOptions),
+
+    /// Check that a lockfile and rendered BUILD files are up to date with the Cargo manifests.
+    Verify(VerifyOptions),
+
+    /// Write a software bill of materials describing the crates of a lockfile.
+    Sbom(SbomOptions),
+
+    /// Write the crates of a lockfile as a `Cargo.lock` file.
+    ExportLockfile(ExportLockfileOptions),
+
+    /// Describe the features enabled on a crate and the crates requesting them.
+    Features(FeaturesOptions),
+
+    /// Provide Bazel with the credentials of private Cargo registries.
+    CredentialHelper(CredentialHelperOptions),
 }
 
 // Convenience wrappers to avoid dependencies in the binary
@@ -57,7 +93,18 @@ pub fn parse_args() -> Options {
     Options::parse()
 }
 
-const EXPECTED_LOGGER_NAMES: [&str; 5] = ["Generate", "Splice", "Query", "Vendor", "Render"];
+const EXPECTED_LOGGER_NAMES: [&str; 10] = [
+    "Generate",
+    "Splice",
+    "Query",
+    "Vendor",
+    "Render",
+    "Explain",
+    "Verify",
+    "Sbom",
+    "ExportLockfile",
+    "Features",
+];
 
 /// A wrapper for the tracing-subscriber default [FormatEvent]
 /// that prepends the name of the active CLI option.
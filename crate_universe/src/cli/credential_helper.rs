@@ -0,0 +1,151 @@
+//! The cli entrypoint for the `credential-helper` subcommand
+
+use std::env;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::splicing::cargo_config::CargoConfig;
+use crate::splicing::credentials::RegistryCredentials;
+
+/// Command line options for the `credential-helper` subcommand
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Command line options for the `credential-helper` subcommand",
+    version
+)]
+pub struct CredentialHelperOptions {
+    /// The Cargo config defining the registries. Defaults to the nearest `.cargo/config.toml`
+    /// of the current directory.
+    #[clap(long, env = "CARGO_BAZEL_CARGO_CONFIG")]
+    pub cargo_config: Option<PathBuf>,
+
+    /// The credential helper command sent by Bazel. Only `get` is supported.
+    #[clap(value_parser = ["get"])]
+    pub command: String,
+}
+
+/// A request of the [Bazel credential helper protocol](https://github.com/EngFlow/credential-helper-spec/blob/main/spec.md)
+#[derive(Debug, Deserialize)]
+struct GetCredentialsRequest {
+    uri: String,
+}
+
+/// A response of the [Bazel credential helper protocol](https://github.com/EngFlow/credential-helper-spec/blob/main/spec.md)
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+struct GetCredentialsResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<Headers>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct Headers {
+    #[serde(rename = "Authorization")]
+    authorization: Vec<String>,
+}
+
+/// Provide Bazel with the credentials of the Cargo registry serving a url
+pub fn credential_helper(opt: CredentialHelperOptions) -> Result<()> {
+    let config_path = match opt.cargo_config {
+        Some(path) => Some(path),
+        None => find_cargo_config(&env::current_dir()?),
+    };
+    let config = match config_path {
+        Some(path) => CargoConfig::try_from_path(&path)
+            .with_context(|| format!("Failed to load Cargo config `{}`", path.display()))?,
+        None => CargoConfig::default(),
+    };
+
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    let request: GetCredentialsRequest =
+        serde_json::from_str(&input).context("Failed to parse credential helper request")?;
+
+    let response = get_credentials(&RegistryCredentials::new(&config), &request.uri)?;
+    println!("{}", serde_json::to_string(&response)?);
+
+    Ok(())
+}
+
+/// Find the Cargo config file of a directory the way Cargo does, preferring `config.toml`.
+fn find_cargo_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|ancestor| {
+            ["config.toml", "config"].map(|name| ancestor.join(".cargo").join(name))
+        })
+        .find(|path| path.is_file())
+}
+
+fn get_credentials(credentials: &RegistryCredentials, uri: &str) -> Result<GetCredentialsResponse> {
+    let Some(registry) = credentials.registry_for_url(uri) else {
+        return Ok(GetCredentialsResponse::default());
+    };
+
+    let headers = credentials.token(registry)?.map(|token| Headers {
+        authorization: vec![token],
+    });
+
+    Ok(GetCredentialsResponse { headers })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs;
+    use std::str::FromStr;
+
+    #[test]
+    fn get_credentials_for_registry() {
+        let config = CargoConfig::from_str(indoc::indoc! {r#"
+            [registries.private]
+            index = "sparse+https://private.example.com/index/"
+            token = "secret"
+        "#})
+        .unwrap();
+        let credentials = RegistryCredentials::new(&config);
+
+        assert_eq!(
+            serde_json::to_string(
+                &get_credentials(
+                    &credentials,
+                    "https://private.example.com/api/v1/crates/foo/1.0.0/download"
+                )
+                .unwrap()
+            )
+            .unwrap(),
+            r#"{"headers":{"Authorization":["secret"]}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(
+                &get_credentials(&credentials, "https://static.crates.io/crates/foo/1.0.0")
+                    .unwrap()
+            )
+            .unwrap(),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn find_nearest_cargo_config() {
+        let workspace = tempfile::tempdir().unwrap();
+        let package = workspace.path().join("package");
+        fs::create_dir_all(package.join(".cargo")).unwrap();
+        fs::create_dir_all(workspace.path().join(".cargo")).unwrap();
+        fs::write(workspace.path().join(".cargo/config.toml"), "").unwrap();
+
+        assert_eq!(
+            find_cargo_config(&package),
+            Some(workspace.path().join(".cargo/config.toml"))
+        );
+
+        fs::write(package.join(".cargo/config"), "").unwrap();
+        assert_eq!(
+            find_cargo_config(&package),
+            Some(package.join(".cargo/config"))
+        );
+    }
+}
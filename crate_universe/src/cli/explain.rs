@@ -0,0 +1,484 @@
+//! The cli entrypoint for the `explain` subcommand
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context as AnyhowContext, Result};
+use clap::Parser;
+
+use crate::config::CrateId;
+use crate::context::{Context, CrateContext};
+use crate::metadata::FeatureReport;
+
+/// Command line options for the `explain` subcommand
+#[derive(Parser, Debug)]
+#[clap(about = "Command line options for the `explain` subcommand", version)]
+pub struct ExplainOptions {
+    /// The lockfile path for reproducible Cargo->Bazel renderings
+    #[clap(long)]
+    pub lockfile: PathBuf,
+
+    /// The crate to explain, either `{name}` or `{name}@{version}`
+    #[clap(value_name = "CRATE")]
+    pub krate: String,
+
+    /// The path to a feature report written by `generate --feature-report`. Each dependency
+    /// edge is annotated with the features it requests when given.
+    #[clap(long)]
+    pub feature_report: Option<PathBuf>,
+}
+
+/// Print why a crate is part of the resolved dependency graph
+pub fn explain(opt: ExplainOptions) -> Result<()> {
+    let context = Context::try_from_path(&opt.lockfile)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.lockfile.display()))?;
+
+    let report = opt
+        .feature_report
+        .as_deref()
+        .map(FeatureReport::try_from_path)
+        .transpose()?;
+
+    print!("{}", explain_crate(&context, report.as_ref(), &opt.krate)?);
+
+    Ok(())
+}
+
+/// The kinds of dependencies tracked by a [CrateContext]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum DependencyKind {
+    Normal,
+    Dev,
+    ProcMacro,
+    ProcMacroDev,
+    Build,
+    BuildLink,
+    BuildProcMacro,
+}
+
+impl DependencyKind {
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Dev => "dev",
+            Self::ProcMacro => "proc-macro",
+            Self::ProcMacroDev => "proc-macro dev",
+            Self::Build => "build",
+            Self::BuildLink => "build link",
+            Self::BuildProcMacro => "build proc-macro",
+        }
+    }
+}
+
+/// A dependency of a crate and the configuration it is conditional on, if any
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Edge {
+    id: CrateId,
+    kind: DependencyKind,
+    configuration: Option<String>,
+}
+
+/// Collect all dependencies of a crate
+fn dependencies(crate_context: &CrateContext) -> BTreeSet<Edge> {
+    let common_attrs = &crate_context.common_attrs;
+    let mut selects = vec![
+        (DependencyKind::Normal, &common_attrs.deps),
+        (DependencyKind::Dev, &common_attrs.deps_dev),
+        (DependencyKind::ProcMacro, &common_attrs.proc_macro_deps),
+        (
+            DependencyKind::ProcMacroDev,
+            &common_attrs.proc_macro_deps_dev,
+        ),
+    ];
+    if let Some(attrs) = &crate_context.build_script_attrs {
+        selects.extend([
+            (DependencyKind::Build, &attrs.deps),
+            (DependencyKind::BuildLink, &attrs.link_deps),
+            (DependencyKind::BuildProcMacro, &attrs.proc_macro_deps),
+        ]);
+    }
+
+    selects
+        .into_iter()
+        .flat_map(|(kind, select)| {
+            select
+                .items()
+                .into_iter()
+                .map(move |(configuration, dep)| Edge {
+                    id: dep.id,
+                    kind,
+                    configuration,
+                })
+        })
+        .collect()
+}
+
+/// Parse a crate query of the form `{name}` or `{name}@{version}`
+pub(crate) fn parse_query(query: &str) -> Result<(&str, Option<semver::Version>)> {
+    match query.split_once('@') {
+        Some((name, version)) => {
+            let version = semver::Version::parse(version)
+                .with_context(|| format!("Invalid version in crate query `{query}`"))?;
+            Ok((name, Some(version)))
+        }
+        None => Ok((query, None)),
+    }
+}
+
+/// Describe the platforms a configuration applies to
+fn describe_configuration(context: &Context, configuration: &Option<String>) -> String {
+    let Some(configuration) = configuration else {
+        return "on all platforms".to_owned();
+    };
+
+    match context.conditions.get(configuration) {
+        Some(triples) if triples.is_empty() => {
+            format!("on `{configuration}` (no supported platforms)")
+        }
+        Some(triples) => format!(
+            "on `{configuration}` ({})",
+            triples
+                .iter()
+                .map(|triple| triple.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        None => format!("on `{configuration}`"),
+    }
+}
+
+/// Describe the features `dependent` requests on `dependency`, e.g. ` [default, std]`, if the
+/// feature report has any.
+fn describe_requested_features(
+    report: Option<&FeatureReport>,
+    dependent: &CrateId,
+    dependency: &CrateId,
+) -> String {
+    let features: Vec<String> = report
+        .and_then(|report| report.crates.get(dependency))
+        .into_iter()
+        .flatten()
+        .filter_map(|(feature, activation)| {
+            let requests: Vec<&Option<String>> = activation
+                .requested_by
+                .iter()
+                .filter(|request| &request.krate == dependent)
+                .map(|request| &request.feature)
+                .collect();
+            if requests.is_empty() {
+                return None;
+            }
+            // Features requested by the dependency declaration itself need no further explanation.
+            if requests.iter().any(|feature| feature.is_none()) {
+                return Some(feature.clone());
+            }
+            let through: Vec<String> = requests
+                .into_iter()
+                .flatten()
+                .map(|feature| format!("`{feature}`"))
+                .collect();
+            Some(format!("{feature} through {}", through.join(", ")))
+        })
+        .collect();
+
+    if features.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", features.join(", "))
+    }
+}
+
+/// Render an explanation of why the crates matching `query` are in the dependency graph. With a
+/// feature report, each dependency edge is annotated with the features it requests.
+pub(crate) fn explain_crate(
+    context: &Context,
+    report: Option<&FeatureReport>,
+    query: &str,
+) -> Result<String> {
+    let (name, version) = parse_query(query)?;
+    let matches: Vec<&CrateId> = context
+        .crates
+        .keys()
+        .filter(|id| id.name == name && version.as_ref().is_none_or(|v| &id.version == v))
+        .collect();
+    if matches.is_empty() {
+        bail!("The crate `{query}` is not in the resolved dependency graph");
+    }
+
+    let dependencies: BTreeMap<&CrateId, BTreeSet<Edge>> = context
+        .crates
+        .iter()
+        .map(|(id, crate_context)| (id, dependencies(crate_context)))
+        .collect();
+
+    // Find the shortest chain of dependents from a workspace member to each crate.
+    let mut parents: BTreeMap<&CrateId, Option<&CrateId>> = BTreeMap::new();
+    let mut queue: VecDeque<&CrateId> = VecDeque::new();
+    for member in context.workspace_members.keys() {
+        parents.insert(member, None);
+        queue.push_back(member);
+    }
+    while let Some(id) = queue.pop_front() {
+        for edge in dependencies.get(id).into_iter().flatten() {
+            if let Some((dependency, _)) = context.crates.get_key_value(&edge.id) {
+                if !parents.contains_key(dependency) {
+                    parents.insert(dependency, Some(id));
+                    queue.push_back(dependency);
+                }
+            }
+        }
+    }
+    // Each crate of the chain is followed by the features its dependent requests on it.
+    let chain = |id: &CrateId| -> Option<String> {
+        let mut chain = vec![id];
+        let mut current = parents.get_key_value(id)?;
+        while let (_, Some(parent)) = current {
+            chain.push(*parent);
+            current = parents.get_key_value(*parent)?;
+        }
+        chain.reverse();
+        let mut rendered = chain[0].to_string();
+        for edge in chain.windows(2) {
+            rendered.push_str(&format!(
+                " -> {}{}",
+                edge[1],
+                describe_requested_features(report, edge[0], edge[1])
+            ));
+        }
+        Some(rendered)
+    };
+
+    let mut output = String::new();
+    for (index, id) in matches.into_iter().enumerate() {
+        if index > 0 {
+            writeln!(output)?;
+        }
+        writeln!(output, "{id}")?;
+
+        if let Some(path) = context.workspace_members.get(id) {
+            writeln!(output, "  Workspace member at `{path}`")?;
+        }
+
+        let mut features: BTreeMap<Option<String>, Vec<String>> = BTreeMap::new();
+        for (configuration, feature) in context.crates[id].common_attrs.crate_features.items() {
+            features.entry(configuration).or_default().push(feature);
+        }
+        if features.is_empty() {
+            writeln!(output, "  Features: none")?;
+        } else {
+            writeln!(output, "  Features:")?;
+            for (configuration, features) in &features {
+                writeln!(
+                    output,
+                    "    {}: {}",
+                    describe_configuration(context, configuration),
+                    features.join(", ")
+                )?;
+            }
+        }
+
+        let dependents: Vec<(&CrateId, &Edge)> = dependencies
+            .iter()
+            .flat_map(|(dependent, edges)| edges.iter().map(move |edge| (*dependent, edge)))
+            .filter(|(_, edge)| &edge.id == id)
+            .collect();
+        if dependents.is_empty() {
+            writeln!(output, "  Dependents: none")?;
+        } else {
+            writeln!(output, "  Dependents:")?;
+            for (dependent, edge) in &dependents {
+                writeln!(
+                    output,
+                    "    {dependent} ({} dependency) {}{}",
+                    edge.kind.description(),
+                    describe_configuration(context, &edge.configuration),
+                    describe_requested_features(report, dependent, id)
+                )?;
+            }
+
+            writeln!(output, "  Paths from workspace members:")?;
+            let dependents: BTreeSet<&CrateId> =
+                dependents.iter().map(|(dependent, _)| *dependent).collect();
+            for dependent in dependents {
+                let features = describe_requested_features(report, dependent, id);
+                match chain(dependent) {
+                    Some(chain) => writeln!(output, "    {chain} -> {id}{features}")?,
+                    None => writeln!(
+                        output,
+                        "    {dependent} -> {id}{features} (not reachable from a workspace member)"
+                    )?,
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use camino::Utf8Path;
+
+    use crate::config::Config;
+    use crate::metadata::{Annotations, FeatureActivation, FeatureRequest};
+
+    fn mock_context(metadata: cargo_metadata::Metadata, lockfile: cargo_lock::Lockfile) -> Context {
+        let annotations = Annotations::new(
+            metadata,
+            lockfile,
+            Config::default(),
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap();
+
+        Context::new(annotations, false).unwrap()
+    }
+
+    #[test]
+    fn parse_crate_query() {
+        assert_eq!(parse_query("serde").unwrap(), ("serde", None));
+        assert_eq!(
+            parse_query("serde@1.0.217").unwrap(),
+            ("serde", Some(semver::Version::new(1, 0, 217)))
+        );
+        assert!(parse_query("serde@1.0").is_err());
+    }
+
+    #[test]
+    fn explain_direct_dependency() {
+        let mut context = mock_context(
+            crate::test::metadata::common(),
+            crate::test::lockfile::common(),
+        );
+        let id = CrateId::new("bitflags".to_owned(), semver::Version::new(1, 3, 2));
+        let crate_features = &mut context
+            .crates
+            .get_mut(&id)
+            .unwrap()
+            .common_attrs
+            .crate_features;
+        crate_features.insert("default".to_owned(), None);
+        crate_features.insert("example_generated".to_owned(), Some("cfg(test)".to_owned()));
+
+        assert_eq!(
+            explain_crate(&context, None, "bitflags").unwrap(),
+            indoc::indoc! {r#"
+                bitflags 1.3.2
+                  Features:
+                    on all platforms: default
+                    on `cfg(test)`: example_generated
+                  Dependents:
+                    common 0.1.0 (normal dependency) on all platforms
+                  Paths from workspace members:
+                    common 0.1.0 -> bitflags 1.3.2
+            "#}
+        );
+    }
+
+    #[test]
+    fn explain_platform_specific_dependency() {
+        let context = mock_context(
+            crate::test::metadata::multi_cfg_dep(),
+            crate::test::lockfile::multi_cfg_dep(),
+        );
+
+        assert_eq!(
+            explain_crate(&context, None, "libc@0.2.117").unwrap(),
+            indoc::indoc! {r#"
+                libc 0.2.117
+                  Features: none
+                  Dependents:
+                    cpufeatures 0.2.7 (normal dependency) on `aarch64-linux-android` (no supported platforms)
+                    cpufeatures 0.2.7 (normal dependency) on `cfg(all(target_arch = "aarch64", target_os = "linux"))` (no supported platforms)
+                    cpufeatures 0.2.7 (normal dependency) on `cfg(all(target_arch = "aarch64", target_vendor = "apple"))` (no supported platforms)
+                  Paths from workspace members:
+                    multi_cfg_dep 0.1.0 -> cpufeatures 0.2.7 -> libc 0.2.117
+            "#}
+        );
+    }
+
+    #[test]
+    fn explain_with_feature_report() {
+        let context = mock_context(
+            crate::test::metadata::multi_cfg_dep(),
+            crate::test::lockfile::multi_cfg_dep(),
+        );
+        let multi_cfg_dep = CrateId::new("multi_cfg_dep".to_owned(), semver::Version::new(0, 1, 0));
+        let cpufeatures = CrateId::new("cpufeatures".to_owned(), semver::Version::new(0, 2, 7));
+        let libc = CrateId::new("libc".to_owned(), semver::Version::new(0, 2, 117));
+        let activation = |requested_by: &[(&CrateId, Option<&str>)]| FeatureActivation {
+            platforms: None,
+            requested_by: requested_by
+                .iter()
+                .map(|(krate, feature)| FeatureRequest {
+                    krate: (*krate).clone(),
+                    feature: feature.map(str::to_owned),
+                })
+                .collect(),
+        };
+        let report = FeatureReport {
+            crates: BTreeMap::from([
+                (
+                    cpufeatures.clone(),
+                    BTreeMap::from([("default".to_owned(), activation(&[(&multi_cfg_dep, None)]))]),
+                ),
+                (
+                    libc.clone(),
+                    BTreeMap::from([
+                        ("default".to_owned(), activation(&[(&libc, Some("std"))])),
+                        (
+                            "std".to_owned(),
+                            activation(&[(&cpufeatures, Some("default"))]),
+                        ),
+                    ]),
+                ),
+            ]),
+        };
+
+        let explanation = explain_crate(&context, Some(&report), "libc@0.2.117").unwrap();
+        assert!(
+            explanation.contains(
+                "cpufeatures 0.2.7 (normal dependency) on `aarch64-linux-android` (no supported platforms) [std through `default`]\n"
+            ),
+            "{explanation}"
+        );
+        assert!(
+            explanation.contains(
+                "multi_cfg_dep 0.1.0 -> cpufeatures 0.2.7 [default] -> libc 0.2.117 [std through `default`]\n"
+            ),
+            "{explanation}"
+        );
+    }
+
+    #[test]
+    fn explain_workspace_member() {
+        let context = mock_context(
+            crate::test::metadata::common(),
+            crate::test::lockfile::common(),
+        );
+
+        assert_eq!(
+            explain_crate(&context, None, "common@0.1.0").unwrap(),
+            indoc::indoc! {r#"
+                common 0.1.0
+                  Workspace member at ``
+                  Features: none
+                  Dependents: none
+            "#}
+        );
+    }
+
+    #[test]
+    fn explain_missing_crate() {
+        let context = mock_context(
+            crate::test::metadata::common(),
+            crate::test::lockfile::common(),
+        );
+
+        assert!(explain_crate(&context, None, "serde").is_err());
+        assert!(explain_crate(&context, None, "bitflags@2.0.0").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! The cli entrypoint for the `export-lockfile` subcommand
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{Context as AnyhowContext, Result};
+use cargo_lock::package::{Checksum, SourceId};
+use cargo_lock::{Dependency, Lockfile, Name, Package, ResolveVersion};
+use clap::Parser;
+
+use crate::config::CrateId;
+use crate::context::{Context, CrateContext};
+use crate::metadata::SourceAnnotation;
+
+/// Command line options for the `export-lockfile` subcommand
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Command line options for the `export-lockfile` subcommand",
+    version
+)]
+pub struct ExportLockfileOptions {
+    /// The lockfile path for reproducible Cargo->Bazel renderings
+    #[clap(long)]
+    pub lockfile: PathBuf,
+
+    /// A path to write the `Cargo.lock` file to. Defaults to stdout.
+    #[clap(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Write the crates of a lockfile as a `Cargo.lock` file for tools which don't understand
+/// cargo-bazel lockfiles, such as security scanners.
+pub fn export_lockfile(opt: ExportLockfileOptions) -> Result<()> {
+    let context = Context::try_from_path(&opt.lockfile)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.lockfile.display()))?;
+
+    let content = cargo_lockfile(&context)?.to_string();
+    match &opt.output {
+        Some(path) => fs::write(path, content)
+            .with_context(|| format!("Failed to write `{}`", path.display()))?,
+        None => print!("{content}"),
+    }
+
+    Ok(())
+}
+
+/// Build a `Cargo.lock` file from the crates of `context`. Version 3 of the format is used as
+/// it's understood by the widest range of tools.
+fn cargo_lockfile(context: &Context) -> Result<Lockfile> {
+    let packages = context
+        .crates
+        .iter()
+        .map(|(id, crate_context)| package(context, id, crate_context))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Lockfile {
+        version: ResolveVersion::V3,
+        packages,
+        root: None,
+        metadata: Default::default(),
+        patch: Default::default(),
+    })
+}
+
+fn package(context: &Context, id: &CrateId, crate_context: &CrateContext) -> Result<Package> {
+    let source = source_id(crate_context)?;
+
+    // Only crates downloaded from registries have checksums in `Cargo.lock`.
+    let checksum = match (&source, &crate_context.repository) {
+        (Some(source), Some(SourceAnnotation::Http { sha256, .. })) if source.is_registry() => {
+            sha256
+                .as_deref()
+                .map(Checksum::from_str)
+                .transpose()
+                .with_context(|| format!("Invalid checksum of {id}"))?
+        }
+        _ => None,
+    };
+
+    let dependencies = dependencies(crate_context)
+        .into_iter()
+        .filter_map(|dep| context.crates.get(&dep).map(|krate| (dep, krate)))
+        .map(|(dep, krate)| {
+            Ok(Dependency {
+                name: Name::from_str(&dep.name)?,
+                version: dep.version,
+                source: source_id(krate)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Package {
+        name: Name::from_str(&id.name)?,
+        version: id.version.clone(),
+        source,
+        checksum,
+        dependencies,
+        replace: None,
+    })
+}
+
+fn source_id(crate_context: &CrateContext) -> Result<Option<SourceId>> {
+    crate_context
+        .source
+        .as_deref()
+        .map(SourceId::from_url)
+        .transpose()
+        .with_context(|| {
+            format!(
+                "Invalid source of {} {}",
+                crate_context.name, crate_context.version
+            )
+        })
+}
+
+/// Collect the crates a crate depends on, including build and dev dependencies as `Cargo.lock`
+/// does, across all platforms.
+fn dependencies(crate_context: &CrateContext) -> BTreeSet<CrateId> {
+    let common_attrs = &crate_context.common_attrs;
+    let mut selects = vec![
+        &common_attrs.deps,
+        &common_attrs.deps_dev,
+        &common_attrs.proc_macro_deps,
+        &common_attrs.proc_macro_deps_dev,
+    ];
+    if let Some(attrs) = &crate_context.build_script_attrs {
+        selects.extend([&attrs.deps, &attrs.link_deps, &attrs.proc_macro_deps]);
+    }
+
+    let artifact_deps = std::iter::once(&common_attrs.artifact_deps)
+        .chain(
+            crate_context
+                .build_script_attrs
+                .as_ref()
+                .map(|attrs| &attrs.artifact_deps),
+        )
+        .flat_map(|select| select.values())
+        .map(|dep| dep.id);
+
+    selects
+        .into_iter()
+        .flat_map(|select| select.values())
+        .map(|dep| dep.id)
+        .chain(artifact_deps)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use camino::Utf8Path;
+
+    use crate::config::Config;
+    use crate::metadata::Annotations;
+
+    #[test]
+    fn export_common_lockfile() {
+        let lockfile = crate::test::lockfile::common();
+        let annotations = Annotations::new(
+            crate::test::metadata::common(),
+            lockfile.clone(),
+            Config::default(),
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap();
+        let context = Context::new(annotations, false).unwrap();
+
+        // The exported lockfile can be parsed again.
+        let exported = Lockfile::from_str(&cargo_lockfile(&context).unwrap().to_string()).unwrap();
+
+        let describe = |lockfile: &Lockfile| {
+            lockfile
+                .packages
+                .iter()
+                .map(|package| {
+                    let mut dependencies = package
+                        .dependencies
+                        .iter()
+                        .map(|dep| format!("{} {}", dep.name, dep.version))
+                        .collect::<Vec<_>>();
+                    dependencies.sort();
+                    (
+                        format!("{} {}", package.name, package.version),
+                        (
+                            package.source.as_ref().map(ToString::to_string),
+                            package.checksum.as_ref().map(ToString::to_string),
+                            dependencies,
+                        ),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+        assert_eq!(describe(&exported), describe(&lockfile));
+    }
+}
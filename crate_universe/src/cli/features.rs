@@ -0,0 +1,164 @@
+//! The cli entrypoint for the `features` subcommand
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::cli::explain::parse_query;
+use crate::metadata::{FeatureReport, FeatureRequest};
+
+/// Command line options for the `features` subcommand
+#[derive(Parser, Debug)]
+#[clap(about = "Command line options for the `features` subcommand", version)]
+pub struct FeaturesOptions {
+    /// The path to a feature report written by `generate --feature-report`
+    #[clap(long)]
+    pub report: PathBuf,
+
+    /// The crate to describe, either `{name}` or `{name}@{version}`
+    #[clap(value_name = "CRATE")]
+    pub krate: String,
+
+    /// Only describe the given feature of the crate
+    #[clap(long)]
+    pub feature: Option<String>,
+}
+
+/// Print the features enabled on a crate and the crates requesting them
+pub fn features(opt: FeaturesOptions) -> Result<()> {
+    let report = FeatureReport::try_from_path(&opt.report)?;
+
+    print!(
+        "{}",
+        describe_features(&report, &opt.krate, opt.feature.as_deref())?
+    );
+
+    Ok(())
+}
+
+fn describe_request(request: &FeatureRequest) -> String {
+    match &request.feature {
+        Some(feature) => format!("{} through its `{feature}` feature", request.krate),
+        None => request.krate.to_string(),
+    }
+}
+
+/// Render the features of the crates matching `query`, optionally limited to `feature`
+fn describe_features(report: &FeatureReport, query: &str, feature: Option<&str>) -> Result<String> {
+    let (name, version) = parse_query(query)?;
+    let matches: Vec<_> = report
+        .crates
+        .iter()
+        .filter(|(id, _)| id.name == name && version.as_ref().is_none_or(|v| &id.version == v))
+        .collect();
+    if matches.is_empty() {
+        bail!("No features of the crate `{query}` are enabled");
+    }
+
+    let mut output = String::new();
+    for (index, (id, features)) in matches.into_iter().enumerate() {
+        if index > 0 {
+            writeln!(output)?;
+        }
+        writeln!(output, "{id}")?;
+
+        let features: Vec<_> = features
+            .iter()
+            .filter(|(name, _)| feature.is_none_or(|feature| feature == name.as_str()))
+            .collect();
+        if features.is_empty() {
+            writeln!(output, "  `{}` is not enabled", feature.unwrap_or_default())?;
+            continue;
+        }
+        for (name, activation) in features {
+            match &activation.platforms {
+                Some(platforms) => writeln!(
+                    output,
+                    "  {name} (on {})",
+                    platforms.iter().cloned().collect::<Vec<_>>().join(", ")
+                )?,
+                None => writeln!(output, "  {name}")?,
+            }
+            if activation.requested_by.is_empty() {
+                writeln!(output, "    Requested by: the workspace")?;
+            }
+            for request in &activation.requested_by {
+                writeln!(output, "    Requested by: {}", describe_request(request))?;
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use crate::config::CrateId;
+    use crate::metadata::FeatureActivation;
+
+    fn mock_report() -> FeatureReport {
+        let tokio = CrateId::new("tokio".to_owned(), semver::Version::new(1, 38, 0));
+        let hyper = CrateId::new("hyper".to_owned(), semver::Version::new(1, 0, 0));
+        FeatureReport {
+            crates: BTreeMap::from([(
+                tokio.clone(),
+                BTreeMap::from([
+                    (
+                        "full".to_owned(),
+                        FeatureActivation {
+                            platforms: Some(BTreeSet::from(
+                                ["x86_64-unknown-linux-gnu".to_owned()],
+                            )),
+                            requested_by: BTreeSet::from([FeatureRequest {
+                                krate: hyper.clone(),
+                                feature: Some("server".to_owned()),
+                            }]),
+                        },
+                    ),
+                    (
+                        "rt".to_owned(),
+                        FeatureActivation {
+                            platforms: None,
+                            requested_by: BTreeSet::from([
+                                FeatureRequest {
+                                    krate: hyper,
+                                    feature: None,
+                                },
+                                FeatureRequest {
+                                    krate: tokio,
+                                    feature: Some("full".to_owned()),
+                                },
+                            ]),
+                        },
+                    ),
+                ]),
+            )]),
+        }
+    }
+
+    #[test]
+    fn describe_crate_features() {
+        assert_eq!(
+            describe_features(&mock_report(), "tokio", None).unwrap(),
+            indoc::indoc! {"
+                tokio 1.38.0
+                  full (on x86_64-unknown-linux-gnu)
+                    Requested by: hyper 1.0.0 through its `server` feature
+                  rt
+                    Requested by: hyper 1.0.0
+                    Requested by: tokio 1.38.0 through its `full` feature
+            "}
+        );
+        assert_eq!(
+            describe_features(&mock_report(), "tokio@1.38.0", Some("default")).unwrap(),
+            "tokio 1.38.0\n  `default` is not enabled\n"
+        );
+        assert!(describe_features(&mock_report(), "serde", None).is_err());
+    }
+}
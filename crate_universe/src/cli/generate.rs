@@ -13,8 +13,12 @@ use clap::Parser;
 use crate::config::Config;
 use crate::context::Context;
 use crate::lockfile::{lock_context, write_lockfile};
-use crate::metadata::{load_metadata, Annotations, Cargo, SourceAnnotation};
+use crate::metadata::{
+    audit_advisories, duplicate_crate_report, load_metadata, Annotations, Cargo, FeatureReport,
+    SourceAnnotation,
+};
 use crate::rendering::{write_outputs, Renderer};
+use crate::splicing::cargo_config::find_workspace_cargo_config;
 use crate::splicing::SplicingManifest;
 use crate::utils::normalize_cargo_file_paths;
 use crate::utils::starlark::Label;
@@ -96,6 +100,25 @@ pub struct GenerateOptions {
     /// so this provides a way for the repository rule to force printing.
     #[clap(long)]
     pub warnings_output_path: PathBuf,
+
+    /// Whether or not to fail instead of warning when locked crates have been yanked from their registries.
+    #[clap(long, env = "CARGO_BAZEL_FAIL_ON_YANKED")]
+    pub fail_on_yanked: bool,
+
+    /// The path to a checkout of the [RustSec advisory database](https://github.com/rustsec/advisory-db)
+    /// to audit the resolved crates against.
+    #[clap(long, env = "CARGO_BAZEL_ADVISORY_DB")]
+    pub advisory_db: Option<PathBuf>,
+
+    /// A path to write a `json` report of the advisories affecting the resolved crates to.
+    /// Requires `--advisory-db`.
+    #[clap(long, requires = "advisory_db")]
+    pub advisory_report: Option<PathBuf>,
+
+    /// A path to write a `json` report of the features enabled on each crate and the crates
+    /// requesting them to. Only written when repinning.
+    #[clap(long)]
+    pub feature_report: Option<PathBuf>,
 }
 
 pub fn generate(opt: GenerateOptions) -> Result<()> {
@@ -106,6 +129,9 @@ pub fn generate(opt: GenerateOptions) -> Result<()> {
     if !opt.repin {
         if let Some(lockfile) = &opt.lockfile {
             let context = Context::try_from_path(lockfile)?;
+            if let Some(metadata) = &context.metadata {
+                metadata.validate_version()?;
+            }
 
             // Render build files
             let outputs = Renderer::new(
@@ -125,12 +151,13 @@ pub fn generate(opt: GenerateOptions) -> Result<()> {
             write_paths_to_track(
                 &opt.paths_to_track,
                 &opt.warnings_output_path,
-                splicing_manifest.manifests.keys().cloned(),
+                tracked_manifests(&splicing_manifest),
                 context
                     .crates
                     .values()
                     .filter_map(|crate_context| crate_context.repository.as_ref()),
                 context.unused_patches.iter(),
+                Vec::new(),
             )?;
 
             return Ok(());
@@ -170,12 +197,33 @@ pub fn generate(opt: GenerateOptions) -> Result<()> {
 
     let splicing_manifest = SplicingManifest::try_from_path(&opt.splicing_manifest)?;
 
+    let mut additional_warnings = annotations
+        .metadata
+        .workspace_metadata
+        .yanked_crate_warnings(opt.fail_on_yanked)?;
+    additional_warnings.extend(annotations.unmapped_profile_settings.iter().cloned());
+    if splicing_manifest.duplicates.report {
+        additional_warnings.extend(duplicate_crate_report(&cargo_lockfile));
+    }
+    if let Some(advisory_db) = &opt.advisory_db {
+        additional_warnings.extend(audit_advisories(
+            advisory_db,
+            opt.advisory_report.as_deref(),
+            &annotations,
+        )?);
+    }
+
+    if let Some(path) = &opt.feature_report {
+        FeatureReport::new(&annotations).write(path)?;
+    }
+
     write_paths_to_track(
         &opt.paths_to_track,
         &opt.warnings_output_path,
-        splicing_manifest.manifests.keys().cloned(),
+        tracked_manifests(&splicing_manifest),
         annotations.lockfile.crates.values(),
         cargo_lockfile.patch.unused.iter(),
+        additional_warnings,
     )?;
 
     // Generate renderable contexts for each package
@@ -222,6 +270,30 @@ fn update_cargo_lockfile(path: &Path, cargo_lockfile: Lockfile) -> Result<()> {
     Ok(())
 }
 
+/// The manifests of `splicing_manifest` along with the Cargo configs of their workspaces whose
+/// registry settings are applied when no explicit config is given.
+fn tracked_manifests(
+    splicing_manifest: &SplicingManifest,
+) -> impl Iterator<Item = Utf8PathBuf> + '_ {
+    let cargo_configs: BTreeSet<Utf8PathBuf> = if splicing_manifest.cargo_config.is_none() {
+        splicing_manifest
+            .manifests
+            .keys()
+            .filter_map(|manifest| manifest.parent())
+            .filter_map(|dir| find_workspace_cargo_config(dir.as_std_path()))
+            .filter_map(|path| Utf8PathBuf::from_path_buf(path).ok())
+            .collect()
+    } else {
+        BTreeSet::new()
+    };
+
+    splicing_manifest
+        .manifests
+        .keys()
+        .cloned()
+        .chain(cargo_configs)
+}
+
 fn write_paths_to_track<
     'a,
     SourceAnnotations: Iterator<Item = &'a SourceAnnotation>,
@@ -233,6 +305,7 @@ fn write_paths_to_track<
     manifests: Paths,
     source_annotations: SourceAnnotations,
     unused_patches: UnusedPatches,
+    additional_warnings: Vec<String>,
 ) -> Result<()> {
     let source_annotation_manifests: BTreeSet<_> = source_annotations
         .filter_map(|v| {
@@ -261,6 +334,7 @@ fn write_paths_to_track<
     for unused_patch in unused_patches {
         warnings.push(format!("You have a [patch] Cargo.toml entry that is being ignored by cargo. Unused patch: {} {}{}", unused_patch.name, unused_patch.version, if let Some(source) = unused_patch.source.as_ref() { format!(" ({})", source) } else { String::new() }));
     }
+    warnings.extend(additional_warnings);
 
     std::fs::write(
         warnings_output_path,
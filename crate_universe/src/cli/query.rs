@@ -8,7 +8,7 @@ use clap::Parser;
 
 use crate::config::Config;
 use crate::context::Context;
-use crate::lockfile::Digest;
+use crate::lockfile::{Digest, LockfileMetadata};
 use crate::metadata::Cargo;
 use crate::splicing::SplicingManifest;
 
@@ -66,12 +66,23 @@ pub fn query(opt: QueryOptions) -> Result<()> {
 
     let splicing_manifest = SplicingManifest::try_from_path(&opt.splicing_manifest)?;
 
+    let cargo_bin = Cargo::new(opt.cargo, opt.rustc.clone());
+
+    // Describe what changed since the lockfile was generated, if it's recorded
+    if let Some(metadata) = &lockfile.metadata {
+        metadata.validate(&LockfileMetadata::new(
+            &config,
+            &splicing_manifest,
+            &cargo_bin,
+        )?)?;
+    }
+
     // Generate a new digest so we can compare it with the one in the lockfile
     let expected = Digest::new(
         &lockfile,
         &config,
         &splicing_manifest,
-        &Cargo::new(opt.cargo, opt.rustc.clone()),
+        &cargo_bin,
         &opt.rustc,
     )?;
 
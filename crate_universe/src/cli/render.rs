@@ -30,12 +30,16 @@ pub fn render(opt: RenderOptions) -> Result<()> {
         config,
         supported_platform_triples,
         platform_conditions,
+        debug_assertions_conditions,
         crate_context,
     } = deserialized_options;
 
     let renderer = Renderer::new(config, supported_platform_triples);
-    let platforms = renderer.render_platform_labels(Arc::clone(&platform_conditions));
-    let engine = renderer.create_engine(platform_conditions);
+    let platforms = renderer.render_platform_labels(
+        Arc::clone(&platform_conditions),
+        &debug_assertions_conditions,
+    );
+    let engine = renderer.create_engine(platform_conditions, debug_assertions_conditions)?;
     let output = renderer
         .render_one_build_file(&engine, &platforms, &crate_context)
         .with_context(|| {
@@ -0,0 +1,609 @@
+//! The cli entrypoint for the `sbom` subcommand
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context as AnyhowContext, Result};
+use clap::Parser;
+use hex::ToHex;
+use serde::Serialize;
+use sha2::{Digest as Sha2Digest, Sha256};
+
+use crate::config::{Commitish, CrateId};
+use crate::context::{Context, CrateContext};
+use crate::metadata::SourceAnnotation;
+
+/// The version of `cargo-bazel` recorded as the creator of a bill of materials.
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Command line options for the `sbom` subcommand
+#[derive(Parser, Debug)]
+#[clap(about = "Command line options for the `sbom` subcommand", version)]
+pub struct SbomOptions {
+    /// The lockfile path for reproducible Cargo->Bazel renderings
+    #[clap(long)]
+    pub lockfile: PathBuf,
+
+    /// The format of the bill of materials. Either `spdx` or `cyclonedx`.
+    #[clap(long, default_value = "spdx")]
+    pub format: SbomFormat,
+
+    /// The name of the document describing the crates of the lockfile.
+    #[clap(long, default_value = "crates")]
+    pub name: String,
+
+    /// The time the document was created at in seconds since the Unix epoch. Defaults to the
+    /// current time.
+    #[clap(long, env = "SOURCE_DATE_EPOCH")]
+    pub timestamp: Option<u64>,
+
+    /// A path to write the bill of materials to as `json`. Defaults to stdout.
+    #[clap(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// The supported formats of software bills of materials.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// [SPDX 2.3](https://spdx.github.io/spdx-spec/v2.3/)
+    #[default]
+    Spdx,
+
+    /// [CycloneDX 1.5](https://cyclonedx.org/docs/1.5/json/)
+    CycloneDx,
+}
+
+impl FromStr for SbomFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spdx" => Ok(Self::Spdx),
+            "cyclonedx" => Ok(Self::CycloneDx),
+            _ => bail!("Unexpected SBOM format `{s}`. Expected one of `spdx` or `cyclonedx`."),
+        }
+    }
+}
+
+/// Write a software bill of materials describing every crate of a lockfile.
+pub fn sbom(opt: SbomOptions) -> Result<()> {
+    let content = fs::read_to_string(&opt.lockfile)
+        .with_context(|| format!("Failed to read lockfile `{}`", opt.lockfile.display()))?;
+    let context = Context::try_from_path(&opt.lockfile)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.lockfile.display()))?;
+
+    let timestamp = match opt.timestamp {
+        Some(timestamp) => timestamp,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("The system clock is set before the Unix epoch")?
+            .as_secs(),
+    };
+    let document = Document {
+        name: opt.name,
+        namespace: Sha256::digest(content.as_bytes()).encode_hex::<String>(),
+        created: format_timestamp(timestamp),
+    };
+
+    let sbom = match opt.format {
+        SbomFormat::Spdx => serde_json::to_string_pretty(&spdx(&context, &document))?,
+        SbomFormat::CycloneDx => serde_json::to_string_pretty(&cyclonedx(&context, &document))?,
+    };
+    match &opt.output {
+        Some(path) => fs::write(path, sbom + "\n")
+            .with_context(|| format!("Failed to write `{}`", path.display()))?,
+        None => println!("{sbom}"),
+    }
+
+    Ok(())
+}
+
+/// Information about the bill of materials itself.
+struct Document {
+    name: String,
+    /// A value unique to the lockfile the document was generated from.
+    namespace: String,
+    created: String,
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp.
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Convert days since the epoch to a civil date. See
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// The [package url](https://github.com/package-url/purl-spec) of a crate.
+fn purl(id: &CrateId) -> String {
+    format!("pkg:cargo/{}@{}", id.name, id.version)
+}
+
+/// The location a crate's sources are downloaded from, if it's not a workspace member.
+fn download_location(crate_context: &CrateContext) -> Option<String> {
+    match crate_context.repository.as_ref()? {
+        SourceAnnotation::Http { url, .. } => Some(url.clone()),
+        SourceAnnotation::Git {
+            remote, commitish, ..
+        } => {
+            let revision = match commitish {
+                Commitish::Tag(v) | Commitish::Branch(v) | Commitish::Rev(v) => v,
+            };
+            Some(format!("git+{remote}@{revision}"))
+        }
+        SourceAnnotation::Path { .. } => None,
+    }
+}
+
+/// The sha256 checksum of the archive of a crate's sources.
+fn sha256(crate_context: &CrateContext) -> Option<String> {
+    match crate_context.repository.as_ref()? {
+        SourceAnnotation::Http { sha256, .. } => sha256.clone(),
+        _ => None,
+    }
+}
+
+/// Collect the crates a crate depends on when built, ignoring dev dependencies.
+fn dependencies(crate_context: &CrateContext) -> BTreeSet<CrateId> {
+    let common_attrs = &crate_context.common_attrs;
+    let mut selects = vec![&common_attrs.deps, &common_attrs.proc_macro_deps];
+    if let Some(attrs) = &crate_context.build_script_attrs {
+        selects.extend([&attrs.deps, &attrs.link_deps, &attrs.proc_macro_deps]);
+    }
+
+    selects
+        .into_iter()
+        .flat_map(|select| select.values())
+        .map(|dep| dep.id)
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxDocument {
+    spdx_version: &'static str,
+    data_license: &'static str,
+    #[serde(rename = "SPDXID")]
+    spdx_id: &'static str,
+    name: String,
+    document_namespace: String,
+    creation_info: SpdxCreationInfo,
+    packages: Vec<SpdxPackage>,
+    relationships: Vec<SpdxRelationship>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxCreationInfo {
+    created: String,
+    creators: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxPackage {
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    name: String,
+    version_info: String,
+    download_location: String,
+    files_analyzed: bool,
+    license_concluded: String,
+    license_declared: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checksums: Vec<SpdxChecksum>,
+    external_refs: Vec<SpdxExternalRef>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxChecksum {
+    algorithm: &'static str,
+    checksum_value: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxExternalRef {
+    reference_category: &'static str,
+    reference_type: &'static str,
+    reference_locator: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxRelationship {
+    spdx_element_id: String,
+    relationship_type: &'static str,
+    related_spdx_element: String,
+}
+
+/// SPDX uses `NOASSERTION` for information which is unknown.
+const SPDX_NOASSERTION: &str = "NOASSERTION";
+
+/// An SPDX identifier of a crate. These may only contain letters, numbers, `.` and `-`.
+fn spdx_id(id: &CrateId) -> String {
+    format!("SPDXRef-Package-{}-{}", id.name, id.version)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+fn spdx(context: &Context, document: &Document) -> SpdxDocument {
+    let packages = context
+        .crates
+        .iter()
+        .map(|(id, crate_context)| SpdxPackage {
+            spdx_id: spdx_id(id),
+            name: id.name.clone(),
+            version_info: id.version.to_string(),
+            download_location: download_location(crate_context)
+                .unwrap_or_else(|| SPDX_NOASSERTION.to_owned()),
+            files_analyzed: false,
+            license_concluded: SPDX_NOASSERTION.to_owned(),
+            license_declared: crate_context
+                .license
+                .clone()
+                .unwrap_or_else(|| SPDX_NOASSERTION.to_owned()),
+            checksums: sha256(crate_context)
+                .map(|checksum_value| SpdxChecksum {
+                    algorithm: "SHA256",
+                    checksum_value,
+                })
+                .into_iter()
+                .collect(),
+            external_refs: vec![SpdxExternalRef {
+                reference_category: "PACKAGE-MANAGER",
+                reference_type: "purl",
+                reference_locator: purl(id),
+            }],
+        })
+        .collect();
+
+    let describes = context.workspace_members.keys().map(|id| SpdxRelationship {
+        spdx_element_id: "SPDXRef-DOCUMENT".to_owned(),
+        relationship_type: "DESCRIBES",
+        related_spdx_element: spdx_id(id),
+    });
+    let depends_on = context.crates.iter().flat_map(|(id, crate_context)| {
+        dependencies(crate_context)
+            .into_iter()
+            .map(move |dep| SpdxRelationship {
+                spdx_element_id: spdx_id(id),
+                relationship_type: "DEPENDS_ON",
+                related_spdx_element: spdx_id(&dep),
+            })
+    });
+
+    SpdxDocument {
+        spdx_version: "SPDX-2.3",
+        data_license: "CC0-1.0",
+        spdx_id: "SPDXRef-DOCUMENT",
+        name: document.name.clone(),
+        document_namespace: format!(
+            "https://spdx.org/spdxdocs/{}-{}",
+            document.name, document.namespace
+        ),
+        creation_info: SpdxCreationInfo {
+            created: document.created.clone(),
+            creators: vec![format!("Tool: cargo-bazel-{TOOL_VERSION}")],
+        },
+        packages,
+        relationships: describes.chain(depends_on).collect(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDxDocument {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: CycloneDxMetadata,
+    components: Vec<CycloneDxComponent>,
+    dependencies: Vec<CycloneDxDependency>,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxMetadata {
+    timestamp: String,
+    tools: Vec<CycloneDxTool>,
+    component: CycloneDxMetadataComponent,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxTool {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxMetadataComponent {
+    #[serde(rename = "type")]
+    component_type: &'static str,
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxComponent {
+    #[serde(rename = "type")]
+    component_type: &'static str,
+    #[serde(rename = "bom-ref")]
+    bom_ref: String,
+    name: String,
+    version: String,
+    purl: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    licenses: Vec<CycloneDxLicense>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hashes: Vec<CycloneDxHash>,
+    #[serde(rename = "externalReferences", skip_serializing_if = "Vec::is_empty")]
+    external_references: Vec<CycloneDxExternalReference>,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxLicense {
+    expression: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxHash {
+    alg: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxExternalReference {
+    #[serde(rename = "type")]
+    reference_type: &'static str,
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDxDependency {
+    #[serde(rename = "ref")]
+    reference: String,
+    depends_on: Vec<String>,
+}
+
+fn cyclonedx(context: &Context, document: &Document) -> CycloneDxDocument {
+    let components = context
+        .crates
+        .iter()
+        .map(|(id, crate_context)| CycloneDxComponent {
+            component_type: "library",
+            bom_ref: purl(id),
+            name: id.name.clone(),
+            version: id.version.to_string(),
+            purl: purl(id),
+            licenses: crate_context
+                .license
+                .iter()
+                .map(|expression| CycloneDxLicense {
+                    expression: expression.clone(),
+                })
+                .collect(),
+            hashes: sha256(crate_context)
+                .map(|content| CycloneDxHash {
+                    alg: "SHA-256",
+                    content,
+                })
+                .into_iter()
+                .collect(),
+            external_references: download_location(crate_context)
+                .map(|url| CycloneDxExternalReference {
+                    reference_type: "distribution",
+                    url,
+                })
+                .into_iter()
+                .collect(),
+        })
+        .collect();
+
+    let dependencies = context
+        .crates
+        .iter()
+        .map(|(id, crate_context)| CycloneDxDependency {
+            reference: purl(id),
+            depends_on: dependencies(crate_context).iter().map(purl).collect(),
+        })
+        .collect();
+
+    CycloneDxDocument {
+        bom_format: "CycloneDX",
+        spec_version: "1.5",
+        version: 1,
+        metadata: CycloneDxMetadata {
+            timestamp: document.created.clone(),
+            tools: vec![CycloneDxTool {
+                name: "cargo-bazel",
+                version: TOOL_VERSION,
+            }],
+            component: CycloneDxMetadataComponent {
+                component_type: "application",
+                name: document.name.clone(),
+            },
+        },
+        components,
+        dependencies,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use camino::Utf8Path;
+    use serde_json::json;
+
+    use crate::config::Config;
+    use crate::metadata::Annotations;
+
+    fn mock_context() -> Context {
+        let annotations = Annotations::new(
+            crate::test::metadata::common(),
+            crate::test::lockfile::common(),
+            Config::default(),
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap();
+
+        Context::new(annotations, false).unwrap()
+    }
+
+    fn mock_document() -> Document {
+        Document {
+            name: "crates".to_owned(),
+            namespace: "abc123".to_owned(),
+            created: format_timestamp(0),
+        }
+    }
+
+    #[test]
+    fn format_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1760614245), "2025-10-16T11:30:45Z");
+    }
+
+    #[test]
+    fn parse_sbom_format() {
+        assert_eq!(SbomFormat::from_str("spdx").unwrap(), SbomFormat::Spdx);
+        assert_eq!(
+            SbomFormat::from_str("cyclonedx").unwrap(),
+            SbomFormat::CycloneDx
+        );
+        assert!(SbomFormat::from_str("swid").is_err());
+    }
+
+    #[test]
+    fn spdx_packages() {
+        let context = mock_context();
+        let document = serde_json::to_value(spdx(&context, &mock_document())).unwrap();
+
+        assert_eq!(document["spdxVersion"], json!("SPDX-2.3"));
+        assert_eq!(
+            document["documentNamespace"],
+            json!("https://spdx.org/spdxdocs/crates-abc123")
+        );
+        assert_eq!(
+            document["creationInfo"]["created"],
+            json!("1970-01-01T00:00:00Z")
+        );
+
+        let packages = document["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), context.crates.len());
+        let bitflags = packages
+            .iter()
+            .find(|package| package["name"] == json!("bitflags"))
+            .unwrap();
+        assert_eq!(
+            bitflags,
+            &json!({
+                "SPDXID": "SPDXRef-Package-bitflags-1.3.2",
+                "name": "bitflags",
+                "versionInfo": "1.3.2",
+                "downloadLocation": "https://static.crates.io/crates/bitflags/1.3.2/download",
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "MIT/Apache-2.0",
+                "checksums": [{
+                    "algorithm": "SHA256",
+                    "checksumValue": "bef38d45163c2f1dde094a7dfd33ccf595c92905c8f8f4fdc18d06fb1037718a",
+                }],
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": "pkg:cargo/bitflags@1.3.2",
+                }],
+            })
+        );
+
+        let relationships = document["relationships"].as_array().unwrap();
+        assert!(relationships.contains(&json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": "SPDXRef-Package-common-0.1.0",
+        })));
+        assert!(relationships.contains(&json!({
+            "spdxElementId": "SPDXRef-Package-common-0.1.0",
+            "relationshipType": "DEPENDS_ON",
+            "relatedSpdxElement": "SPDXRef-Package-bitflags-1.3.2",
+        })));
+    }
+
+    #[test]
+    fn cyclonedx_components() {
+        let context = mock_context();
+        let document = serde_json::to_value(cyclonedx(&context, &mock_document())).unwrap();
+
+        assert_eq!(document["bomFormat"], json!("CycloneDX"));
+        assert_eq!(
+            document["metadata"]["timestamp"],
+            json!("1970-01-01T00:00:00Z")
+        );
+
+        let components = document["components"].as_array().unwrap();
+        assert_eq!(components.len(), context.crates.len());
+        let bitflags = components
+            .iter()
+            .find(|component| component["name"] == json!("bitflags"))
+            .unwrap();
+        assert_eq!(
+            bitflags,
+            &json!({
+                "type": "library",
+                "bom-ref": "pkg:cargo/bitflags@1.3.2",
+                "name": "bitflags",
+                "version": "1.3.2",
+                "purl": "pkg:cargo/bitflags@1.3.2",
+                "licenses": [{"expression": "MIT/Apache-2.0"}],
+                "hashes": [{
+                    "alg": "SHA-256",
+                    "content": "bef38d45163c2f1dde094a7dfd33ccf595c92905c8f8f4fdc18d06fb1037718a",
+                }],
+                "externalReferences": [{
+                    "type": "distribution",
+                    "url": "https://static.crates.io/crates/bitflags/1.3.2/download",
+                }],
+            })
+        );
+
+        let dependencies = document["dependencies"].as_array().unwrap();
+        let common = dependencies
+            .iter()
+            .find(|dependency| dependency["ref"] == json!("pkg:cargo/common@0.1.0"))
+            .unwrap();
+        assert!(common["dependsOn"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("pkg:cargo/bitflags@1.3.2")));
+    }
+}
@@ -7,9 +7,10 @@ use camino::Utf8PathBuf;
 use clap::Parser;
 
 use crate::cli::Result;
-use crate::config::Config;
+use crate::config::{Config, FeatureUnification};
 use crate::metadata::{
-    write_metadata, Cargo, CargoUpdateRequest, Generator, MetadataGenerator, TreeResolver,
+    write_metadata, Cargo, CargoUpdateRequest, CrateCache, Generator, IsolatedTreeResolverMetadata,
+    MetadataGenerator, NetworkRetries, TreeResolver,
 };
 use crate::splicing::{generate_lockfile, Splicer, SplicingManifest, WorkspaceMetadata};
 
@@ -57,6 +58,24 @@ pub struct SpliceOptions {
     /// The path to a rustc binary for use with Cargo
     #[clap(long, env = "RUSTC")]
     pub rustc: PathBuf,
+
+    /// A directory in which the archives of downloaded crates are cached by their checksum,
+    /// so unchanged crates are reused across repins and repositories.
+    #[clap(long, env = "CARGO_BAZEL_CRATE_CACHE")]
+    pub crate_cache: Option<PathBuf>,
+
+    /// A directory in which registry indexes are cached across invocations. This is useful
+    /// when `CARGO_HOME` is isolated, which otherwise starts every resolve with an empty cache.
+    /// Invocations sharing the directory lock it while running cargo, and it's not used with the
+    /// `CARGO_HOME` of the user.
+    #[clap(long, env = "CARGO_BAZEL_REGISTRY_CACHE")]
+    pub registry_cache: Option<PathBuf>,
+
+    /// The number of times downloads which fail due to network errors are retried. Cargo
+    /// retries them itself unless `net.retry` is configured, otherwise failed invocations of
+    /// cargo are rerun with an exponential backoff until this many retries were made.
+    #[clap(long, env = "CARGO_BAZEL_NETWORK_RETRIES", default_value_t = NetworkRetries::default().retries)]
+    pub network_retries: u32,
 }
 
 /// Combine a set of disjoint manifests into a single workspace.
@@ -77,9 +96,20 @@ pub fn splice(opt: SpliceOptions) -> Result<()> {
     };
 
     // Generate a splicer for creating a Cargo workspace manifest
+    let mirrors = splicing_manifest.mirrors.clone();
+    let duplicates = splicing_manifest.duplicates.clone();
     let splicer = Splicer::new(splicing_dir, splicing_manifest)?;
 
-    let cargo = Cargo::new(opt.cargo, opt.rustc.clone());
+    let mut cargo = Cargo::new(opt.cargo, opt.rustc.clone()).with_network_retries(NetworkRetries {
+        retries: opt.network_retries,
+        ..NetworkRetries::default()
+    });
+    if let Some(registry_cache) = &opt.registry_cache {
+        cargo.use_registry_cache(registry_cache)?;
+    }
+    if let Some(crate_cache) = &opt.crate_cache {
+        cargo = cargo.with_crate_cache(CrateCache::new(crate_cache.clone()));
+    }
 
     // Splice together the manifest
     let manifest_path = splicer
@@ -92,35 +122,54 @@ pub fn splice(opt: SpliceOptions) -> Result<()> {
         &opt.cargo_lockfile,
         cargo.clone(),
         &opt.repin,
+        &duplicates,
     )
     .context("Failed to generate lockfile")?;
 
     let config = Config::try_from_path(&opt.config).context("Failed to parse config")?;
 
-    let resolver_data = TreeResolver::new(cargo.clone())
+    let tree_resolver = TreeResolver::new(cargo.clone())
+        .with_strict_dev_dependencies(config.strict_dev_dependencies);
+    let (resolver_data, exec_resolver_data, target_cfgs) = tree_resolver
         .generate(
             manifest_path.as_path_buf(),
             &config.supported_platform_triples,
+            &config.target_specs,
         )
         .context("Failed to generate features")?;
+    let isolated_resolver_data = match config.feature_unification {
+        FeatureUnification::Workspace => IsolatedTreeResolverMetadata::new(),
+        FeatureUnification::Package => tree_resolver
+            .generate_isolated(
+                manifest_path.as_path_buf(),
+                &config.supported_platform_triples,
+                &config.target_specs,
+            )
+            .context("Failed to generate features for each workspace member")?,
+    };
     // Write the registry url info to the manifest now that a lockfile has been generated
     WorkspaceMetadata::write_registry_urls_and_feature_map(
         &cargo,
         &cargo_lockfile,
         resolver_data,
+        exec_resolver_data,
+        isolated_resolver_data,
+        target_cfgs,
         manifest_path.as_path_buf(),
         manifest_path.as_path_buf(),
+        &mirrors,
     )
     .context("Failed to write registry URLs and feature map")?;
 
     let output_dir = opt.output_dir.clone();
 
     // Write metadata to the workspace for future reuse
-    let (cargo_metadata, _) = Generator::new()
-        .with_cargo(cargo)
+    let (cargo_metadata, cargo_lockfile) = Generator::new()
+        .with_cargo(cargo.clone())
         .with_rustc(opt.rustc)
         .generate(manifest_path.as_path_buf())
         .context("Failed to generate cargo metadata")?;
+    cargo.cache_crates(&cargo_lockfile)?;
 
     let cargo_lockfile_path = manifest_path
         .as_path_buf()
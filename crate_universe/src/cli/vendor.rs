@@ -1,6 +1,6 @@
 //! The cli entrypoint for the `vendor` subcommand
 
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -10,12 +10,17 @@ use std::sync::Arc;
 use anyhow::{anyhow, bail, Context as AnyhowContext};
 use camino::Utf8PathBuf;
 use clap::Parser;
+use serde::Deserialize;
 
-use crate::config::{Config, VendorMode};
+use crate::config::{Config, FeatureUnification, RenderConfig, VendorMode};
 use crate::context::Context;
 use crate::metadata::CargoUpdateRequest;
-use crate::metadata::TreeResolver;
-use crate::metadata::{Annotations, Cargo, Generator, MetadataGenerator, VendorGenerator};
+use crate::metadata::{
+    duplicate_crate_report, Annotations, Cargo, CrateCache, Generator, MetadataGenerator,
+    NetworkRetries, VendorChecksums, VendorGenerator, VendorIntegrity, VendorPruning,
+    VENDOR_LOCKFILE_NAME,
+};
+use crate::metadata::{IsolatedTreeResolverMetadata, TreeResolver};
 use crate::rendering::{render_module_label, write_outputs, Renderer};
 use crate::splicing::{generate_lockfile, Splicer, SplicingManifest, WorkspaceMetadata};
 use crate::utils::normalize_cargo_file_paths;
@@ -32,22 +37,46 @@ pub struct VendorOptions {
     #[clap(long, env = "RUSTC")]
     pub rustc: PathBuf,
 
+    /// A directory in which the archives of downloaded crates are cached by their checksum,
+    /// so unchanged crates are reused across repins and repositories.
+    #[clap(long, env = "CARGO_BAZEL_CRATE_CACHE")]
+    pub crate_cache: Option<PathBuf>,
+
+    /// A directory in which registry indexes are cached across invocations. This is useful
+    /// when `CARGO_HOME` is isolated, which otherwise starts every resolve with an empty cache.
+    /// Invocations sharing the directory lock it while running cargo, and it's not used with the
+    /// `CARGO_HOME` of the user.
+    #[clap(long, env = "CARGO_BAZEL_REGISTRY_CACHE")]
+    pub registry_cache: Option<PathBuf>,
+
+    /// The number of times downloads which fail due to network errors are retried. Cargo
+    /// retries them itself unless `net.retry` is configured, otherwise failed invocations of
+    /// cargo are rerun with an exponential backoff until this many retries were made.
+    #[clap(long, env = "CARGO_BAZEL_NETWORK_RETRIES", default_value_t = NetworkRetries::default().retries)]
+    pub network_retries: u32,
+
     /// The path to a buildifier binary for formatting generated BUILD files
     #[clap(long)]
     pub buildifier: Option<PathBuf>,
 
     /// The config file with information about the Bazel and Cargo workspace
-    #[clap(long)]
-    pub config: PathBuf,
+    #[clap(long, required_unless_present = "groups", conflicts_with = "groups")]
+    pub config: Option<PathBuf>,
 
     /// A generated manifest of splicing inputs
-    #[clap(long)]
-    pub splicing_manifest: PathBuf,
+    #[clap(long, required_unless_present = "groups", conflicts_with = "groups")]
+    pub splicing_manifest: Option<PathBuf>,
 
     /// The path to a [Cargo.lock](https://doc.rust-lang.org/cargo/guide/cargo-toml-vs-cargo-lock.html) file.
-    #[clap(long)]
+    #[clap(long, conflicts_with = "groups")]
     pub cargo_lockfile: Option<PathBuf>,
 
+    /// The path to a `json` file listing several [VendorGroup]s to vendor in a single invocation,
+    /// each into its own hub repository. Groups share the cargo home and with it any crates
+    /// and registry indexes downloaded by the groups before them.
+    #[clap(long)]
+    pub groups: Option<PathBuf>,
+
     /// A [Cargo config](https://doc.rust-lang.org/cargo/reference/config.html#configuration)
     /// file to use when gathering metadata
     #[clap(long)]
@@ -76,6 +105,26 @@ pub struct VendorOptions {
     #[clap(long)]
     pub dry_run: bool,
 
+    /// How to handle vendored crates which were modified locally or don't match the
+    /// checksums recorded by `cargo vendor`. Either `fail` or `warn`.
+    #[clap(long, default_value = "fail")]
+    pub vendor_integrity: VendorIntegrity,
+
+    /// Whether to remove the files of vendored crates which aren't needed to build them,
+    /// such as the sources of their tests, benches and examples.
+    #[clap(long)]
+    pub vendor_prune: bool,
+
+    /// Glob patterns, relative to the directory of each vendored crate, of additional
+    /// files to remove when pruning.
+    #[clap(long = "vendor-prune-path", requires = "vendor_prune")]
+    pub vendor_prune_paths: Vec<String>,
+
+    /// Glob patterns, relative to the directory of each vendored crate, of files to
+    /// keep when pruning, e.g. fixtures read by build scripts.
+    #[clap(long, requires = "vendor_prune")]
+    pub vendor_prune_keep: Vec<String>,
+
     /// The path to the Bazel root workspace (i.e. the directory containing the WORKSPACE.bazel file or similar).
     /// BE CAREFUL with this value. We never want to include it in a lockfile hash (to keep lockfiles portable),
     /// which means you also should not use it anywhere that _should_ be guarded by a lockfile hash.
@@ -84,6 +133,65 @@ pub struct VendorOptions {
     pub nonhermetic_root_bazel_workspace_dir: Utf8PathBuf,
 }
 
+/// A dependency universe vendored into its own hub repository, listed in the file passed
+/// to `--groups`. Relative paths are resolved against the directory of that file, while
+/// `${build_workspace_directory}` and `${output_base}` are substituted as in a [SplicingManifest].
+#[derive(Debug, Deserialize)]
+pub struct VendorGroup {
+    /// A name identifying the group in diagnostics.
+    pub name: String,
+
+    /// The config file with information about the Bazel and Cargo workspace
+    pub config: PathBuf,
+
+    /// A generated manifest of splicing inputs
+    pub splicing_manifest: PathBuf,
+
+    /// The path to a [Cargo.lock](https://doc.rust-lang.org/cargo/guide/cargo-toml-vs-cargo-lock.html) file.
+    #[serde(default)]
+    pub cargo_lockfile: Option<PathBuf>,
+}
+
+impl VendorGroup {
+    /// Load the groups listed in the `json` file at `path`.
+    fn try_from_path(
+        path: &Path,
+        workspace_dir: &Path,
+        output_base: &Path,
+    ) -> anyhow::Result<Vec<Self>> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read groups file {}", path.display()))?;
+        let mut groups: Vec<Self> = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse groups file {}", path.display()))?;
+
+        let mut names = BTreeSet::new();
+        for group in &groups {
+            if !names.insert(group.name.as_str()) {
+                bail!("The group `{}` is listed more than once", group.name);
+            }
+        }
+
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let resolve = |path: &Path| {
+            base_dir.join(
+                path.to_string_lossy()
+                    .replace(
+                        "${build_workspace_directory}",
+                        &workspace_dir.to_string_lossy(),
+                    )
+                    .replace("${output_base}", &output_base.to_string_lossy()),
+            )
+        };
+        for group in &mut groups {
+            group.config = resolve(&group.config);
+            group.splicing_manifest = resolve(&group.splicing_manifest);
+            group.cargo_lockfile = group.cargo_lockfile.as_deref().map(resolve);
+        }
+
+        Ok(groups)
+    }
+}
+
 /// Run buildifier on a given file.
 fn buildifier_format(bin: &Path, file: &Path) -> anyhow::Result<ExitStatus> {
     let status = process::Command::new(bin)
@@ -196,8 +304,80 @@ impl TryFrom<HashMap<String, String>> for BazelInfo {
 pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
     let bazel_info = BazelInfo::try_new(&opt.bazel, &opt.workspace_dir)?;
 
+    let groups = match &opt.groups {
+        Some(path) => {
+            VendorGroup::try_from_path(path, &opt.workspace_dir, &bazel_info.output_base)?
+        }
+        None => vec![VendorGroup {
+            name: "default".to_owned(),
+            config: opt.config.clone().context("`--config` is required")?,
+            splicing_manifest: opt
+                .splicing_manifest
+                .clone()
+                .context("`--splicing-manifest` is required")?,
+            cargo_lockfile: opt.cargo_lockfile.clone(),
+        }],
+    };
+
+    // All groups share a single cargo so crates are only downloaded once.
+    let mut cargo =
+        Cargo::new(opt.cargo.clone(), opt.rustc.clone()).with_network_retries(NetworkRetries {
+            retries: opt.network_retries,
+            ..NetworkRetries::default()
+        });
+    if let Some(registry_cache) = &opt.registry_cache {
+        cargo.use_registry_cache(registry_cache)?;
+    }
+    if let Some(crate_cache) = &opt.crate_cache {
+        cargo = cargo.with_crate_cache(CrateCache::new(crate_cache.clone()));
+    }
+
+    // Each group replaces the contents of its vendor directory so they must not overlap.
+    let mut vendor_dirs: BTreeMap<PathBuf, &str> = BTreeMap::new();
+    for group in &groups {
+        let config = Config::try_from_path(&group.config)?;
+        let vendor_dir = vendor_dir(&opt.workspace_dir, &config.rendering)?;
+        if let Some(other) = vendor_dirs.insert(vendor_dir.clone(), &group.name) {
+            bail!(
+                "The groups `{}` and `{}` are both vendored into {}",
+                other,
+                group.name,
+                vendor_dir.display()
+            );
+        }
+    }
+
+    for group in &groups {
+        vendor_group(&opt, &bazel_info, &cargo, group)
+            .with_context(|| format!("Failed to vendor group `{}`", group.name))?;
+    }
+
+    // Optionally perform bazel mod tidy to update the MODULE.bazel file
+    if bazel_info.release >= semver::Version::new(7, 0, 0) {
+        let module_bazel = opt.workspace_dir.join("MODULE.bazel");
+        if module_bazel.exists() {
+            bzlmod_tidy(&opt.bazel, &opt.workspace_dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The directory crates are vendored into when rendered with `render_config`.
+fn vendor_dir(workspace_dir: &Path, render_config: &RenderConfig) -> anyhow::Result<PathBuf> {
+    let vendor_dir_label = render_module_label(&render_config.crates_module_template, "BUILD")?;
+    Ok(workspace_dir.join(vendor_dir_label.package().unwrap()))
+}
+
+/// Vendor the crates of a single `group` into their hub repository.
+fn vendor_group(
+    opt: &VendorOptions,
+    bazel_info: &BazelInfo,
+    cargo: &Cargo,
+    group: &VendorGroup,
+) -> anyhow::Result<()> {
     // Load the all config files required for splicing a workspace
-    let splicing_manifest = SplicingManifest::try_from_path(&opt.splicing_manifest)?
+    let splicing_manifest = SplicingManifest::try_from_path(&group.splicing_manifest)?
         .resolve(&opt.workspace_dir, &bazel_info.output_base);
 
     let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
@@ -205,11 +385,11 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
         .unwrap_or_else(|path| panic!("Temporary directory wasn't valid UTF-8: {:?}", path));
 
     // Generate a splicer for creating a Cargo workspace manifest
+    let mirrors = splicing_manifest.mirrors.clone();
+    let duplicates = splicing_manifest.duplicates.clone();
     let splicer =
         Splicer::new(temp_dir_path, splicing_manifest).context("Failed to create splicer")?;
 
-    let cargo = Cargo::new(opt.cargo, opt.rustc.clone());
-
     // Splice together the manifest
     let manifest_path = splicer
         .splice_workspace()
@@ -218,26 +398,42 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
     // Gather a cargo lockfile
     let cargo_lockfile = generate_lockfile(
         &manifest_path,
-        &opt.cargo_lockfile,
+        &group.cargo_lockfile,
         cargo.clone(),
         &opt.repin,
+        &duplicates,
     )?;
 
     // Load the config from disk
-    let config = Config::try_from_path(&opt.config)?;
+    let config = Config::try_from_path(&group.config)?;
 
-    let resolver_data = TreeResolver::new(cargo.clone()).generate(
+    let tree_resolver = TreeResolver::new(cargo.clone())
+        .with_strict_dev_dependencies(config.strict_dev_dependencies);
+    let (resolver_data, exec_resolver_data, target_cfgs) = tree_resolver.generate(
         manifest_path.as_path_buf(),
         &config.supported_platform_triples,
+        &config.target_specs,
     )?;
+    let isolated_resolver_data = match config.feature_unification {
+        FeatureUnification::Workspace => IsolatedTreeResolverMetadata::new(),
+        FeatureUnification::Package => tree_resolver.generate_isolated(
+            manifest_path.as_path_buf(),
+            &config.supported_platform_triples,
+            &config.target_specs,
+        )?,
+    };
 
     // Write the registry url info to the manifest now that a lockfile has been generated
     WorkspaceMetadata::write_registry_urls_and_feature_map(
-        &cargo,
+        cargo,
         &cargo_lockfile,
         resolver_data,
+        exec_resolver_data,
+        isolated_resolver_data,
+        target_cfgs,
         manifest_path.as_path_buf(),
         manifest_path.as_path_buf(),
+        &mirrors,
     )?;
 
     // Write metadata to the workspace for future reuse
@@ -245,6 +441,7 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
         .with_cargo(cargo.clone())
         .with_rustc(opt.rustc.clone())
         .generate(manifest_path.as_path_buf())?;
+    cargo.cache_crates(&cargo_lockfile)?;
 
     // Annotate metadata
     let annotations = Annotations::new(
@@ -253,6 +450,23 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
         config.clone(),
         &opt.nonhermetic_root_bazel_workspace_dir,
     )?;
+    for setting in &annotations.unmapped_profile_settings {
+        tracing::warn!("{}", setting);
+    }
+    if duplicates.report {
+        for duplicate in duplicate_crate_report(&cargo_lockfile) {
+            tracing::warn!("{}", duplicate);
+        }
+    }
+
+    // The packages whose sources may be pruned once vendored
+    let vendored_packages: Vec<_> = annotations
+        .metadata
+        .packages
+        .values()
+        .filter(|package| package.source.is_some())
+        .cloned()
+        .collect();
 
     // Generate renderable contexts for earch package
     let context = Context::new(annotations, config.rendering.are_sources_present())?;
@@ -265,23 +479,47 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
     .render(&context, None)?;
 
     // First ensure vendoring and rendering happen in a clean directory
-    let vendor_dir_label = render_module_label(&config.rendering.crates_module_template, "BUILD")?;
-    let vendor_dir = opt.workspace_dir.join(vendor_dir_label.package().unwrap());
+    let vendor_dir = vendor_dir(&opt.workspace_dir, &config.rendering)?;
+    let vendor_lockfile = vendor_dir.join(VENDOR_LOCKFILE_NAME);
+    if vendor_lockfile.exists() {
+        let issues = VendorChecksums::try_from_path(&vendor_lockfile)?.verify(&vendor_dir)?;
+        opt.vendor_integrity.report(
+            &format!(
+                "Vendored crates in {} were modified and will be replaced",
+                vendor_dir.display()
+            ),
+            &issues,
+        )?;
+    }
     if vendor_dir.exists() {
         fs::remove_dir_all(&vendor_dir)
             .with_context(|| format!("Failed to delete {}", vendor_dir.display()))?;
     }
 
     // Store the updated Cargo.lock
-    if let Some(path) = &opt.cargo_lockfile {
+    if let Some(path) = &group.cargo_lockfile {
         fs::write(path, cargo_lockfile.to_string())
             .context("Failed to write Cargo.lock file back to the workspace.")?;
     }
 
     if matches!(config.rendering.vendor_mode, Some(VendorMode::Local)) {
-        VendorGenerator::new(cargo, opt.rustc.clone())
+        VendorGenerator::new(cargo.clone(), opt.rustc.clone())
             .generate(manifest_path.as_path_buf(), &vendor_dir)
             .context("Failed to vendor dependencies")?;
+
+        if opt.vendor_prune {
+            let pruned = VendorPruning::new(&opt.vendor_prune_paths, &opt.vendor_prune_keep)?
+                .prune(&vendor_dir, &vendored_packages)?;
+            tracing::info!("Pruned {} files from vendored crates", pruned);
+        }
+
+        // Record the checksums of all vendored crates
+        let (checksums, issues) = VendorChecksums::compute(&vendor_dir)?;
+        opt.vendor_integrity.report(
+            "Vendored crates do not match the checksums recorded by `cargo vendor`",
+            &issues,
+        )?;
+        checksums.write(&vendor_lockfile)?;
     }
 
     // make cargo versioned crates compatible with bazel labels
@@ -294,22 +532,14 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
     write_outputs(normalized_outputs, opt.dry_run).context("Failed writing output files")?;
 
     // Optionally apply buildifier fixes
-    if let Some(buildifier_bin) = opt.buildifier {
+    if let Some(buildifier_bin) = &opt.buildifier {
         for file in file_names {
             let file_path = opt.workspace_dir.join(file);
-            buildifier_format(&buildifier_bin, &file_path)
+            buildifier_format(buildifier_bin, &file_path)
                 .with_context(|| format!("Failed to run buildifier on {}", file_path.display()))?;
         }
     }
 
-    // Optionally perform bazel mod tidy to update the MODULE.bazel file
-    if bazel_info.release >= semver::Version::new(7, 0, 0) {
-        let module_bazel = opt.workspace_dir.join("MODULE.bazel");
-        if module_bazel.exists() {
-            bzlmod_tidy(&opt.bazel, &opt.workspace_dir)?;
-        }
-    }
-
     Ok(())
 }
 
@@ -358,4 +588,67 @@ mod tests {
         );
         assert_eq!(PathBuf::from("/tmp/output_base"), info.output_base);
     }
+
+    #[test]
+    fn vendor_groups_from_path() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("groups.json");
+        fs::write(
+            &path,
+            r#"[
+                {
+                    "name": "prod",
+                    "config": "prod/config.json",
+                    "splicing_manifest": "prod/splicing_manifest.json",
+                    "cargo_lockfile": "${build_workspace_directory}/prod/Cargo.lock"
+                },
+                {
+                    "name": "tools",
+                    "config": "/abs/tools/config.json",
+                    "splicing_manifest": "tools/splicing_manifest.json"
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        let groups =
+            VendorGroup::try_from_path(&path, Path::new("/workspace"), Path::new("/output_base"))
+                .unwrap();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "prod");
+        assert_eq!(groups[0].config, temp_dir.path().join("prod/config.json"));
+        assert_eq!(
+            groups[0].cargo_lockfile,
+            Some(PathBuf::from("/workspace/prod/Cargo.lock"))
+        );
+        assert_eq!(groups[1].config, PathBuf::from("/abs/tools/config.json"));
+        assert_eq!(
+            groups[1].splicing_manifest,
+            temp_dir.path().join("tools/splicing_manifest.json")
+        );
+        assert_eq!(groups[1].cargo_lockfile, None);
+    }
+
+    #[test]
+    fn vendor_groups_with_duplicate_names() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("groups.json");
+        fs::write(
+            &path,
+            r#"[
+                {"name": "prod", "config": "a.json", "splicing_manifest": "b.json"},
+                {"name": "prod", "config": "c.json", "splicing_manifest": "d.json"}
+            ]"#,
+        )
+        .unwrap();
+
+        let error =
+            VendorGroup::try_from_path(&path, Path::new("/workspace"), Path::new("/output_base"))
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The group `prod` is listed more than once"
+        );
+    }
 }
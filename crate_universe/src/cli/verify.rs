@@ -0,0 +1,456 @@
+//! The cli entrypoint for the `verify` subcommand
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context as AnyhowContext, Result};
+use camino::Utf8PathBuf;
+use clap::Parser;
+use serde::Serialize;
+
+use crate::config::{Config, CrateId};
+use crate::context::Context;
+use crate::lockfile::lock_context;
+use crate::metadata::{audit_advisories, load_metadata, Annotations, Cargo};
+use crate::rendering::Renderer;
+use crate::splicing::SplicingManifest;
+
+/// Command line options for the `verify` subcommand
+#[derive(Parser, Debug)]
+#[clap(about = "Command line options for the `verify` subcommand", version)]
+pub struct VerifyOptions {
+    /// The path to a Cargo binary to use for gathering metadata
+    #[clap(long, env = "CARGO")]
+    pub cargo: PathBuf,
+
+    /// The path to a rustc binary for use with Cargo
+    #[clap(long, env = "RUSTC")]
+    pub rustc: PathBuf,
+
+    /// The config file with information about the Bazel and Cargo workspace
+    #[clap(long)]
+    pub config: PathBuf,
+
+    /// A generated manifest of splicing inputs
+    #[clap(long)]
+    pub splicing_manifest: PathBuf,
+
+    /// The lockfile path for reproducible Cargo->Bazel renderings
+    #[clap(long)]
+    pub lockfile: PathBuf,
+
+    /// The path to the [Cargo.lock](https://doc.rust-lang.org/cargo/guide/cargo-toml-vs-cargo-lock.html) file to check.
+    #[clap(long)]
+    pub cargo_lockfile: Option<PathBuf>,
+
+    /// The path to a Cargo metadata `json` file of the spliced workspace. This file must be next
+    /// to a `Cargo.toml` and `Cargo.lock` file.
+    #[clap(long)]
+    pub metadata: PathBuf,
+
+    /// The directory containing previously rendered BUILD files to check, e.g. a vendor directory.
+    #[clap(long)]
+    pub repository_dir: Option<PathBuf>,
+
+    /// The path to the Bazel root workspace (i.e. the directory containing the WORKSPACE.bazel file or similar).
+    #[clap(long)]
+    pub nonhermetic_root_bazel_workspace_dir: Utf8PathBuf,
+
+    /// A path to write the difference to as `json`. Defaults to stdout.
+    #[clap(long)]
+    pub output: Option<PathBuf>,
+
+    /// Whether or not to fail instead of warning when locked crates have been yanked from their registries.
+    #[clap(long, env = "CARGO_BAZEL_FAIL_ON_YANKED")]
+    pub fail_on_yanked: bool,
+
+    /// The path to a checkout of the [RustSec advisory database](https://github.com/rustsec/advisory-db)
+    /// to audit the resolved crates against.
+    #[clap(long, env = "CARGO_BAZEL_ADVISORY_DB")]
+    pub advisory_db: Option<PathBuf>,
+
+    /// A path to write a `json` report of the advisories affecting the resolved crates to.
+    /// Requires `--advisory-db`.
+    #[clap(long, requires = "advisory_db")]
+    pub advisory_report: Option<PathBuf>,
+}
+
+/// A crate whose version changed between the locked and the expected dependency graph.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct VersionChange {
+    name: String,
+    old: semver::Version,
+    new: semver::Version,
+}
+
+/// The features of a crate which differ between the locked and the expected dependency graph.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct FeatureChange {
+    #[serde(rename = "crate")]
+    krate: CrateId,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    added: BTreeMap<String, BTreeSet<String>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    removed: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Rendered files which differ from the ones on disk.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+struct OutputChanges {
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    added: BTreeSet<PathBuf>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    removed: BTreeSet<PathBuf>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    changed: BTreeSet<PathBuf>,
+}
+
+impl OutputChanges {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The difference between a lockfile and the dependency graph described by the manifests.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+struct VerifyDiff {
+    /// Whether or not the lockfile's checksum is out of date.
+    checksum_changed: bool,
+    /// Whether or not the `Cargo.lock` file is out of date.
+    cargo_lockfile_changed: bool,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    added_crates: BTreeSet<CrateId>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    removed_crates: BTreeSet<CrateId>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changed_crates: Vec<VersionChange>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    feature_changes: Vec<FeatureChange>,
+    #[serde(skip_serializing_if = "OutputChanges::is_empty")]
+    outputs: OutputChanges,
+}
+
+impl VerifyDiff {
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Check that the lockfile and rendered files are up to date without writing anything.
+pub fn verify(opt: VerifyOptions) -> Result<()> {
+    let config = Config::try_from_path(&opt.config)?;
+    let splicing_manifest = SplicingManifest::try_from_path(&opt.splicing_manifest)?;
+    let locked = Context::try_from_path(&opt.lockfile)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.lockfile.display()))?;
+
+    let (cargo_metadata, cargo_lockfile) = load_metadata(&opt.metadata)?;
+    let cargo_lockfile_changed = match &opt.cargo_lockfile {
+        Some(path) => fs::read_to_string(path).ok() != Some(cargo_lockfile.to_string()),
+        None => false,
+    };
+
+    let annotations = Annotations::new(
+        cargo_metadata,
+        cargo_lockfile,
+        config.clone(),
+        &opt.nonhermetic_root_bazel_workspace_dir,
+    )?;
+    for warning in annotations
+        .metadata
+        .workspace_metadata
+        .yanked_crate_warnings(opt.fail_on_yanked)?
+    {
+        tracing::warn!("{}", warning);
+    }
+    if let Some(advisory_db) = &opt.advisory_db {
+        for warning in audit_advisories(advisory_db, opt.advisory_report.as_deref(), &annotations)?
+        {
+            tracing::warn!("{}", warning);
+        }
+    }
+    let context = Context::new(annotations, config.rendering.are_sources_present())?;
+    let cargo_bin = Cargo::new(opt.cargo, opt.rustc.clone());
+    let expected = lock_context(context, &config, &splicing_manifest, &cargo_bin, &opt.rustc)?;
+
+    let mut diff = diff_contexts(&locked, &expected);
+    diff.cargo_lockfile_changed = cargo_lockfile_changed;
+
+    if let Some(repository_dir) = &opt.repository_dir {
+        let renderer = Renderer::new(
+            Arc::new(config.rendering.clone()),
+            Arc::new(config.supported_platform_triples.clone()),
+        );
+        let locked_outputs = renderer.render(&locked, None)?;
+        let expected_outputs = renderer.render(&expected, None)?;
+        diff.outputs = diff_outputs(repository_dir, locked_outputs, expected_outputs);
+    }
+
+    let content = serde_json::to_string_pretty(&diff)?;
+    match &opt.output {
+        Some(path) => fs::write(path, content + "\n")
+            .with_context(|| format!("Failed to write `{}`", path.display()))?,
+        None => println!("{content}"),
+    }
+
+    if !diff.is_empty() {
+        bail!(
+            "The lockfile `{}` is out of date. Repin the dependencies to update it.",
+            opt.lockfile.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Collect the features of a crate keyed by the configuration they're enabled for.
+fn features_by_configuration(
+    context: &Context,
+    id: &CrateId,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut features: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (configuration, feature) in context.crates[id].common_attrs.crate_features.items() {
+        features
+            .entry(configuration.unwrap_or_else(|| "common".to_owned()))
+            .or_default()
+            .insert(feature);
+    }
+    features
+}
+
+/// Subtract the features of `rhs` from `lhs`.
+fn subtract_features(
+    lhs: &BTreeMap<String, BTreeSet<String>>,
+    rhs: &BTreeMap<String, BTreeSet<String>>,
+) -> BTreeMap<String, BTreeSet<String>> {
+    lhs.iter()
+        .filter_map(|(configuration, features)| {
+            let difference: BTreeSet<String> = match rhs.get(configuration) {
+                Some(other) => features.difference(other).cloned().collect(),
+                None => features.clone(),
+            };
+            (!difference.is_empty()).then(|| (configuration.clone(), difference))
+        })
+        .collect()
+}
+
+fn diff_contexts(locked: &Context, expected: &Context) -> VerifyDiff {
+    let locked_ids: BTreeSet<&CrateId> = locked.crates.keys().collect();
+    let expected_ids: BTreeSet<&CrateId> = expected.crates.keys().collect();
+
+    let mut added_crates: BTreeSet<CrateId> = expected_ids
+        .difference(&locked_ids)
+        .map(|id| (*id).clone())
+        .collect();
+    let mut removed_crates: BTreeSet<CrateId> = locked_ids
+        .difference(&expected_ids)
+        .map(|id| (*id).clone())
+        .collect();
+
+    // A crate with a single version on both sides is reported as a version change.
+    let single_version = |ids: &BTreeSet<CrateId>, name: &str| -> Option<CrateId> {
+        let mut matches = ids.iter().filter(|id| id.name == name);
+        match (matches.next(), matches.next()) {
+            (Some(id), None) => Some(id.clone()),
+            _ => None,
+        }
+    };
+    let names: BTreeSet<String> = removed_crates.iter().map(|id| id.name.clone()).collect();
+    let mut changed_crates = Vec::new();
+    for name in names {
+        if let (Some(old), Some(new)) = (
+            single_version(&removed_crates, &name),
+            single_version(&added_crates, &name),
+        ) {
+            removed_crates.remove(&old);
+            added_crates.remove(&new);
+            changed_crates.push(VersionChange {
+                name,
+                old: old.version,
+                new: new.version,
+            });
+        }
+    }
+
+    let feature_changes = locked_ids
+        .intersection(&expected_ids)
+        .filter_map(|id| {
+            let locked_features = features_by_configuration(locked, id);
+            let expected_features = features_by_configuration(expected, id);
+            let change = FeatureChange {
+                krate: (*id).clone(),
+                added: subtract_features(&expected_features, &locked_features),
+                removed: subtract_features(&locked_features, &expected_features),
+            };
+            (!change.added.is_empty() || !change.removed.is_empty()).then_some(change)
+        })
+        .collect();
+
+    VerifyDiff {
+        checksum_changed: locked.checksum != expected.checksum,
+        cargo_lockfile_changed: false,
+        added_crates,
+        removed_crates,
+        changed_crates,
+        feature_changes,
+        outputs: OutputChanges::default(),
+    }
+}
+
+/// The path rendered outputs are written to. See [crate::utils::normalize_cargo_file_paths].
+fn output_path(repository_dir: &Path, path: &Path) -> PathBuf {
+    let path_str = path.to_string_lossy();
+    match path.parent() {
+        Some(parent) if parent.to_string_lossy().contains('+') => {
+            repository_dir.join(path_str.replace('+', "-"))
+        }
+        _ => repository_dir.join(path),
+    }
+}
+
+fn diff_outputs(
+    repository_dir: &Path,
+    locked_outputs: BTreeMap<PathBuf, String>,
+    expected_outputs: BTreeMap<PathBuf, String>,
+) -> OutputChanges {
+    let expected_outputs: BTreeMap<PathBuf, String> = expected_outputs
+        .into_iter()
+        .map(|(path, content)| (output_path(repository_dir, &path), content))
+        .collect();
+
+    let mut changes = OutputChanges::default();
+    for (path, content) in &expected_outputs {
+        match fs::read_to_string(path) {
+            Ok(existing) if &existing == content => {}
+            Ok(_) => {
+                changes.changed.insert(path.clone());
+            }
+            Err(_) => {
+                changes.added.insert(path.clone());
+            }
+        }
+    }
+
+    // Files rendered for the locked dependency graph which would no longer be generated.
+    changes.removed = locked_outputs
+        .into_keys()
+        .map(|path| output_path(repository_dir, &path))
+        .filter(|path| !expected_outputs.contains_key(path) && path.exists())
+        .collect();
+
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::context::CrateContext;
+
+    fn mock_crate(context: &mut Context, name: &str, version: semver::Version, features: &[&str]) {
+        let mut crate_context: CrateContext = serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": version.to_string(),
+            "license_ids": [],
+        }))
+        .unwrap();
+        for feature in features {
+            crate_context
+                .common_attrs
+                .crate_features
+                .insert((*feature).to_owned(), None);
+        }
+        context
+            .crates
+            .insert(CrateId::new(name.to_owned(), version), crate_context);
+    }
+
+    #[test]
+    fn diff_identical_contexts() {
+        let mut context = Context::default();
+        mock_crate(
+            &mut context,
+            "serde",
+            semver::Version::new(1, 0, 0),
+            &["std"],
+        );
+
+        assert!(diff_contexts(&context, &context).is_empty());
+    }
+
+    #[test]
+    fn diff_changed_contexts() {
+        let mut locked = Context {
+            checksum: Some(serde_json::from_value(serde_json::json!("old")).unwrap()),
+            ..Context::default()
+        };
+        mock_crate(
+            &mut locked,
+            "serde",
+            semver::Version::new(1, 0, 0),
+            &["std"],
+        );
+        mock_crate(&mut locked, "libc", semver::Version::new(0, 2, 0), &[]);
+
+        let mut expected = Context {
+            checksum: Some(serde_json::from_value(serde_json::json!("new")).unwrap()),
+            ..Context::default()
+        };
+        mock_crate(
+            &mut expected,
+            "serde",
+            semver::Version::new(1, 0, 0),
+            &["derive"],
+        );
+        mock_crate(&mut expected, "libc", semver::Version::new(0, 2, 1), &[]);
+        mock_crate(&mut expected, "log", semver::Version::new(0, 4, 0), &[]);
+
+        let diff = diff_contexts(&locked, &expected);
+        assert_eq!(
+            serde_json::to_value(&diff).unwrap(),
+            serde_json::json!({
+                "checksum_changed": true,
+                "cargo_lockfile_changed": false,
+                "added_crates": ["log 0.4.0"],
+                "changed_crates": [{"name": "libc", "old": "0.2.0", "new": "0.2.1"}],
+                "feature_changes": [{
+                    "crate": "serde 1.0.0",
+                    "added": {"common": ["derive"]},
+                    "removed": {"common": ["std"]},
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn diff_rendered_outputs() {
+        let repository_dir = tempfile::tempdir().unwrap();
+        fs::write(repository_dir.path().join("BUILD.bazel"), "unchanged").unwrap();
+        fs::write(repository_dir.path().join("BUILD.serde-1.0.0.bazel"), "old").unwrap();
+        fs::write(repository_dir.path().join("BUILD.libc-0.2.0.bazel"), "old").unwrap();
+
+        let changes = diff_outputs(
+            repository_dir.path(),
+            BTreeMap::from([
+                (PathBuf::from("BUILD.bazel"), "unchanged".to_owned()),
+                (PathBuf::from("BUILD.libc-0.2.0.bazel"), "old".to_owned()),
+                (PathBuf::from("BUILD.serde-1.0.0.bazel"), "old".to_owned()),
+            ]),
+            BTreeMap::from([
+                (PathBuf::from("BUILD.bazel"), "unchanged".to_owned()),
+                (PathBuf::from("BUILD.libc-0.2.1.bazel"), "new".to_owned()),
+                (PathBuf::from("BUILD.serde-1.0.0.bazel"), "new".to_owned()),
+            ]),
+        );
+
+        assert_eq!(
+            changes,
+            OutputChanges {
+                added: BTreeSet::from([repository_dir.path().join("BUILD.libc-0.2.1.bazel")]),
+                removed: BTreeSet::from([repository_dir.path().join("BUILD.libc-0.2.0.bazel")]),
+                changed: BTreeSet::from([repository_dir.path().join("BUILD.serde-1.0.0.bazel")]),
+            }
+        );
+    }
+}
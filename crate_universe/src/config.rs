@@ -19,7 +19,7 @@ use serde::{Deserialize, Serialize, Serializer};
 
 use crate::select::{Select, Selectable};
 use crate::utils::starlark::Label;
-use crate::utils::target_triple::TargetTriple;
+use crate::utils::target_triple::{TargetSpec, TargetTriple};
 
 /// Representations of different kinds of crate vendoring into workspaces.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -44,6 +44,38 @@ impl std::fmt::Display for VendorMode {
     }
 }
 
+/// How the BUILD files of crates are laid out in the rendered outputs
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BuildFileLayout {
+    /// BUILD files are named after the `build_file_template`, usually next to the crates module.
+    #[default]
+    Flat,
+
+    /// Each crate has a `BUILD.bazel` file in its own directory within the package of the
+    /// crates module, keeping the crates module package small.
+    PerCrate,
+}
+
+/// Which stable aliases are rendered for each package in the crates module, in addition to
+/// the aliases of the dependencies of workspace members.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PackageAliases {
+    /// An alias named after each package, e.g. `serde`, for packages resolved at a single version.
+    Unversioned,
+
+    /// Unversioned aliases along with an alias for each semver compatible series of a package,
+    /// e.g. `serde-1` or `rand-0.8`.
+    Major,
+}
+
+impl BuildFileLayout {
+    fn is_flat(&self) -> bool {
+        *self == Self::Flat
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct RenderConfig {
@@ -55,6 +87,11 @@ pub(crate) struct RenderConfig {
     #[serde(default = "default_build_file_template")]
     pub(crate) build_file_template: String,
 
+    /// How BUILD files are laid out. `build_file_template` is ignored unless
+    /// this is [BuildFileLayout::Flat].
+    #[serde(default, skip_serializing_if = "BuildFileLayout::is_flat")]
+    pub(crate) build_file_layout: BuildFileLayout,
+
     /// The pattern to use for a crate target.
     /// Eg. `@{repository}__{name}-{version}//:{target}`
     #[serde(default = "default_crate_label_template")]
@@ -94,15 +131,35 @@ pub(crate) struct RenderConfig {
     #[serde(default = "default_platforms_template")]
     pub(crate) platforms_template: String,
 
+    /// Platform constraints to use for specific triples in place of `platforms_template`,
+    /// e.g. for custom targets which have no `config_setting` in `@rules_rust//rust/platform`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) platform_labels: BTreeMap<TargetTriple, String>,
+
     /// The command to use for regenerating generated files.
     pub(crate) regen_command: String,
 
     /// An optional configuration for rendering content to be rendered into repositories.
     pub(crate) vendor_mode: Option<VendorMode>,
 
+    /// Stable aliases to render for each package, so labels referring to them don't change
+    /// when they are upgraded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) package_aliases: Option<PackageAliases>,
+
     /// Whether to generate package metadata
     #[serde(default = "default_generate_rules_license_metadata")]
     pub(crate) generate_rules_license_metadata: bool,
+
+    /// Tera templates overriding the default rendering templates of the same name, e.g.
+    /// `partials/crate/loads.j2` to add load statements to each crate's BUILD file.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) templates: BTreeMap<String, String>,
+
+    /// The version of the rendering templates `templates` were written against. Rendering fails
+    /// unless this matches the version of the default templates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) templates_version: Option<u32>,
 }
 
 // Default is manually implemented so that the default values match the default
@@ -113,6 +170,7 @@ impl Default for RenderConfig {
         RenderConfig {
             repository_name: String::default(),
             build_file_template: default_build_file_template(),
+            build_file_layout: BuildFileLayout::default(),
             crate_label_template: default_crate_label_template(),
             crate_alias_template: default_crate_alias_template(),
             crates_module_template: default_crates_module_template(),
@@ -121,9 +179,13 @@ impl Default for RenderConfig {
             default_package_name: Option::default(),
             generate_target_compatible_with: default_generate_target_compatible_with(),
             platforms_template: default_platforms_template(),
+            platform_labels: BTreeMap::default(),
             regen_command: String::default(),
             vendor_mode: Option::default(),
+            package_aliases: Option::default(),
             generate_rules_license_metadata: default_generate_rules_license_metadata(),
+            templates: BTreeMap::default(),
+            templates_version: Option::default(),
         }
     }
 }
@@ -246,6 +308,17 @@ pub(crate) struct CrateAnnotations {
     /// [crate_features](https://bazelbuild.github.io/rules_rust/defs.html#rust_library-crate_features) attribute.
     pub(crate) crate_features: Option<Select<BTreeSet<String>>>,
 
+    /// Features which replace the ones resolved by Cargo for the target's
+    /// [crate_features](https://bazelbuild.github.io/rules_rust/defs.html#rust_library-crate_features) attribute.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) override_crate_features: Option<BTreeSet<String>>,
+
+    /// Features to remove from the target's
+    /// [crate_features](https://bazelbuild.github.io/rules_rust/defs.html#rust_library-crate_features) attribute,
+    /// e.g. ones activated by `default`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) remove_crate_features: Option<BTreeSet<String>>,
+
     /// Additional data to pass to  the target's
     /// [data](https://bazelbuild.github.io/rules_rust/defs.html#rust_library-data) attribute.
     pub(crate) data: Option<Select<BTreeSet<Label>>>,
@@ -265,6 +338,16 @@ pub(crate) struct CrateAnnotations {
     /// If true, disables pipelining for library targets generated for this crate.
     pub(crate) disable_pipelining: bool,
 
+    /// If true, the `CARGO_PKG_*` environment variables Cargo derives from the crate's manifest
+    /// (e.g. `CARGO_PKG_DESCRIPTION` or `CARGO_PKG_README`) are set for the crate and its build script.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) cargo_pkg_env: bool,
+
+    /// If true, generates a [rust_doc](https://bazelbuild.github.io/rules_rust/rust_doc.html#rust_doc)
+    /// target for the crate's library.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) gen_docs: bool,
+
     /// Additional data to pass to  the target's
     /// [rustc_env](https://bazelbuild.github.io/rules_rust/defs.html#rust_library-rustc_env) attribute.
     pub(crate) rustc_env: Option<Select<BTreeMap<String, String>>>,
@@ -347,6 +430,16 @@ pub(crate) struct CrateAnnotations {
 
     /// The crates to use instead of the generated one.
     pub(crate) override_targets: Option<BTreeMap<String, Label>>,
+
+    /// The directory of a local fork to use as the crate's sources instead of the ones from its
+    /// registry, either a package of the root workspace (`//third_party/forks/foo`) or a path
+    /// relative to it. The crate is otherwise rendered from the metadata of the locked version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) override_path: Option<String>,
+
+    /// The ids of [RustSec](https://rustsec.org/) advisories which should not be reported for the crate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) ignore_advisories: Option<BTreeSet<String>>,
 }
 
 macro_rules! joined_extra_member {
@@ -395,9 +488,13 @@ impl Add for CrateAnnotations {
             deps: select_merge(self.deps, rhs.deps),
             proc_macro_deps: select_merge(self.proc_macro_deps, rhs.proc_macro_deps),
             crate_features: select_merge(self.crate_features, rhs.crate_features),
+            override_crate_features: self.override_crate_features.or(rhs.override_crate_features),
+            remove_crate_features: joined_extra_member!(self.remove_crate_features, rhs.remove_crate_features, BTreeSet::new, BTreeSet::extend),
             data: select_merge(self.data, rhs.data),
             data_glob: joined_extra_member!(self.data_glob, rhs.data_glob, BTreeSet::new, BTreeSet::extend),
             disable_pipelining: self.disable_pipelining || rhs.disable_pipelining,
+            cargo_pkg_env: self.cargo_pkg_env || rhs.cargo_pkg_env,
+            gen_docs: self.gen_docs || rhs.gen_docs,
             compile_data: select_merge(self.compile_data, rhs.compile_data),
             compile_data_glob: joined_extra_member!(self.compile_data_glob, rhs.compile_data_glob, BTreeSet::new, BTreeSet::extend),
             rustc_env: select_merge(self.rustc_env, rhs.rustc_env),
@@ -422,6 +519,8 @@ impl Add for CrateAnnotations {
             extra_aliased_targets: joined_extra_member!(self.extra_aliased_targets, rhs.extra_aliased_targets, BTreeMap::new, BTreeMap::extend),
             alias_rule: self.alias_rule.or(rhs.alias_rule),
             override_targets: self.override_targets.or(rhs.override_targets),
+            override_path: self.override_path.or(rhs.override_path),
+            ignore_advisories: joined_extra_member!(self.ignore_advisories, rhs.ignore_advisories, BTreeSet::new, BTreeSet::extend),
         };
 
         output
@@ -686,6 +785,44 @@ pub(crate) struct Config {
     /// A set of platform triples to use in generated select statements
     #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
     pub(crate) supported_platform_triples: BTreeSet<TargetTriple>,
+
+    /// [Custom target specifications](https://doc.rust-lang.org/rustc/targets/custom.html) for
+    /// any of the `supported_platform_triples` which are not built into `rustc`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) target_specs: BTreeMap<TargetTriple, TargetSpec>,
+
+    /// The scope in which the features of dependencies are unified
+    #[serde(default, skip_serializing_if = "FeatureUnification::is_workspace")]
+    pub(crate) feature_unification: FeatureUnification,
+
+    /// Whether crates built for production targets exclude the features only enabled by the
+    /// dev dependencies of workspace members
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) strict_dev_dependencies: bool,
+
+    /// The name of a Cargo profile of the root manifest whose settings are approximated with
+    /// `rustc` flags on each crate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) cargo_profile: Option<String>,
+}
+
+/// The scope in which the features enabled on a crate by its dependents are unified
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FeatureUnification {
+    /// Like Cargo, features are unified across all workspace members.
+    #[default]
+    Workspace,
+
+    /// Features are resolved for each workspace member in isolation. Crates which are resolved
+    /// differently for some members are rendered once for each distinct resolution.
+    Package,
+}
+
+impl FeatureUnification {
+    fn is_workspace(&self) -> bool {
+        *self == Self::Workspace
+    }
 }
 
 impl Config {
@@ -9,16 +9,18 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::Result;
+use cargo_metadata::PackageId;
 use serde::{Deserialize, Serialize};
 
 use crate::config::{CrateId, RenderConfig};
 use crate::context::platforms::resolve_cfg_platforms;
-use crate::lockfile::Digest;
+use crate::lockfile::{Digest, LockfileMetadata};
 use crate::metadata::{Annotations, Dependency};
 use crate::select::Select;
 use crate::utils::target_triple::TargetTriple;
 
 pub(crate) use self::crate_context::*;
+pub(crate) use self::platforms::DebugAssertionsConditions;
 
 /// A struct containing information about a Cargo dependency graph in an easily to consume
 /// format for rendering reproducible Bazel targets.
@@ -27,6 +29,10 @@ pub(crate) struct Context {
     /// The collective checksum of all inputs to the context
     pub(crate) checksum: Option<Digest>,
 
+    /// The tools and inputs the context was generated with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) metadata: Option<LockfileMetadata>,
+
     /// The collection of all crates that make up the dependency graph
     pub(crate) crates: BTreeMap<CrateId, CrateContext>,
 
@@ -40,6 +46,11 @@ pub(crate) struct Context {
     /// A mapping of `cfg` flags to platform triples supporting the configuration
     pub(crate) conditions: BTreeMap<String, BTreeSet<TargetTriple>>,
 
+    /// A mapping of `cfg` flags to platform triples which only support the configuration
+    /// when `debug_assertions` are enabled (`true`) or disabled (`false`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) debug_assertions_conditions: DebugAssertionsConditions,
+
     /// A list of crates visible to any bazel module.
     pub(crate) direct_deps: BTreeSet<CrateId>,
 
@@ -53,6 +64,12 @@ pub(crate) struct Context {
     // we can remove the default fallback because existing lockfiles should have the key present.
     #[serde(default)]
     pub(crate) unused_patches: BTreeSet<cargo_lock::Dependency>,
+
+    /// The dev dependencies of each crate which depend on the crate itself. Cargo allows these
+    /// cycles as tests are built separately, so they are broken by leaving these dependencies
+    /// out of the crate's test targets.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) dev_dependency_cycles: BTreeMap<CrateId, BTreeSet<CrateId>>,
 }
 
 impl Context {
@@ -62,6 +79,23 @@ impl Context {
     }
 
     pub(crate) fn new(annotations: Annotations, sources_are_present: bool) -> anyhow::Result<Self> {
+        // Collect the binaries other crates depend on through artifact dependencies
+        let mut required_binaries: BTreeMap<PackageId, BTreeSet<String>> = BTreeMap::new();
+        for annotation in annotations.metadata.crates.values() {
+            let deps = &annotation.deps;
+            for (_, dep) in deps
+                .artifact_deps
+                .items()
+                .into_iter()
+                .chain(deps.build_artifact_deps.items())
+            {
+                required_binaries
+                    .entry(dep.package_id)
+                    .or_default()
+                    .extend(dep.bins);
+            }
+        }
+
         // Build a map of crate contexts
         let crates: BTreeMap<CrateId, CrateContext> = annotations
             .metadata
@@ -74,6 +108,15 @@ impl Context {
                     &annotations.lockfile.crates,
                     &annotations.pairred_extras,
                     &annotations.metadata.workspace_metadata.tree_metadata,
+                    &annotations
+                        .metadata
+                        .workspace_metadata
+                        .isolated_tree_metadata,
+                    &annotations.metadata.workspace_metadata.exec_tree_metadata,
+                    &annotations.metadata.crate_instances,
+                    required_binaries
+                        .get(&annotation.node.id)
+                        .unwrap_or(&BTreeSet::new()),
                     annotations.config.generate_binaries,
                     annotations.config.generate_build_scripts,
                     sources_are_present,
@@ -94,9 +137,11 @@ impl Context {
 
         // Given a list of all conditional dependencies, build a set of platform
         // triples which satisfy the conditions.
-        let conditions = resolve_cfg_platforms(
+        let (conditions, debug_assertions_conditions) = resolve_cfg_platforms(
             crates.values().collect(),
             &annotations.config.supported_platform_triples,
+            &annotations.config.target_specs,
+            &annotations.metadata.workspace_metadata.target_cfgs,
         )?;
 
         // Generate a list of all workspace members
@@ -149,16 +194,20 @@ impl Context {
         }
 
         let unused_patches = annotations.lockfile.unused_patches;
+        let dev_dependency_cycles = annotations.metadata.dev_dependency_cycles;
 
         Ok(Self {
             checksum: None,
+            metadata: None,
             crates,
             binary_crates,
             workspace_members,
             conditions,
+            debug_assertions_conditions,
             direct_dev_deps: direct_dev_deps.difference(&direct_deps).cloned().collect(),
             direct_deps,
             unused_patches,
+            dev_dependency_cycles,
         })
     }
 
@@ -249,6 +298,10 @@ pub struct SingleBuildFileRenderContext {
     /// See Context::conditions.
     pub(crate) platform_conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
 
+    /// See Context::debug_assertions_conditions.
+    #[serde(default)]
+    pub(crate) debug_assertions_conditions: Arc<DebugAssertionsConditions>,
+
     /// The CrateContext for the crate being rendered.
     pub(crate) crate_context: Arc<CrateContext>,
 }
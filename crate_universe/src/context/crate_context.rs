@@ -2,12 +2,15 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 
+use camino::Utf8Path;
 use cargo_metadata::{Node, Package, PackageId};
 use serde::{Deserialize, Serialize};
 
-use crate::config::{AliasRule, CrateId, GenBinaries};
+use crate::config::{AliasRule, CrateAnnotations, CrateId, GenBinaries};
 use crate::metadata::{
-    CrateAnnotation, Dependency, PairedExtras, SourceAnnotation, TreeResolverMetadata,
+    ArtifactDependency, CrateAnnotation, CrateInstances, Dependency, ExecTreeResolverMetadata,
+    IsolatedTreeResolverMetadata, PairedExtras, SourceAnnotation, TreeResolverMetadata,
+    EXEC_INSTANCE,
 };
 use crate::select::Select;
 use crate::utils::sanitize_module_name;
@@ -25,6 +28,34 @@ pub struct CrateDependency {
     /// Some dependencies are assigned aliases. This is tracked here
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub alias: Option<String>,
+
+    /// The name of the [CrateInstance] of the dependency to use, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+}
+
+impl CrateDependency {
+    /// The name of the Bazel target of the dependency, accounting for its instance.
+    pub(crate) fn instance_target(&self) -> String {
+        match &self.instance {
+            Some(instance) => format!("{}-{instance}", self.target),
+            None => self.target.clone(),
+        }
+    }
+}
+
+/// A dependency on the binaries of another crate. See
+/// [artifact dependencies](https://doc.rust-lang.org/nightly/cargo/reference/unstable.html#artifact-dependencies).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CrateArtifactDependency {
+    /// The [CrateId] of the dependency
+    pub id: CrateId,
+
+    /// The name of the dependency as written in the dependent's manifest.
+    pub name: String,
+
+    /// The names of the binary targets of the dependency.
+    pub bins: BTreeSet<String>,
 }
 
 #[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone)]
@@ -112,6 +143,9 @@ pub(crate) struct CommonAttributes {
     #[serde(skip_serializing_if = "Select::is_empty")]
     pub(crate) deps_dev: Select<BTreeSet<CrateDependency>>,
 
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) artifact_deps: Select<BTreeSet<CrateArtifactDependency>>,
+
     pub(crate) edition: String,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -153,6 +187,7 @@ impl Default for CommonAttributes {
             deps: Default::default(),
             extra_deps: Default::default(),
             deps_dev: Default::default(),
+            artifact_deps: Default::default(),
             edition: Default::default(),
             linker_script: Default::default(),
             proc_macro_deps: Default::default(),
@@ -213,6 +248,9 @@ pub(crate) struct BuildScriptAttributes {
     #[serde(skip_serializing_if = "Select::is_empty")]
     pub(crate) extra_link_deps: Select<BTreeSet<Label>>,
 
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) artifact_deps: Select<BTreeSet<CrateArtifactDependency>>,
+
     #[serde(skip_serializing_if = "Select::is_empty")]
     pub(crate) build_script_env: Select<BTreeMap<String, String>>,
 
@@ -261,6 +299,7 @@ impl Default for BuildScriptAttributes {
             extra_deps: Default::default(),
             link_deps: Default::default(),
             extra_link_deps: Default::default(),
+            artifact_deps: Default::default(),
             build_script_env: Default::default(),
             rundir: Default::default(),
             extra_proc_macro_deps: Default::default(),
@@ -294,6 +333,12 @@ pub(crate) struct CrateContext {
     #[serde(default)]
     pub(crate) repository: Option<SourceAnnotation>,
 
+    /// The source of the crate as recorded in `Cargo.lock`, e.g.
+    /// `registry+https://github.com/rust-lang/crates.io-index`. Workspace members and path
+    /// dependencies have no source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) source: Option<String>,
+
     /// A list of all targets (lib, proc-macro, bin) associated with this package
     #[serde(default)]
     pub(crate) targets: BTreeSet<Rule>,
@@ -335,6 +380,11 @@ pub(crate) struct CrateContext {
     #[serde(default)]
     pub(crate) disable_pipelining: bool,
 
+    /// If true, a `rust_doc` target is generated for the crate's library
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default)]
+    pub(crate) gen_docs: bool,
+
     /// Extra targets that should be aliased.
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde(default)]
@@ -349,6 +399,44 @@ pub(crate) struct CrateContext {
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde(default)]
     pub(crate) override_targets: BTreeMap<String, Label>,
+
+    /// Additional instances of the crate's library and build script targets for workspace
+    /// members which resolve the crate differently when features are not unified.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    pub(crate) instances: BTreeMap<String, CrateInstance>,
+}
+
+/// The features and dependencies of a crate as resolved for a subset of workspace members.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct CrateInstance {
+    /// The workspace members using the instance.
+    pub(crate) workspace_members: BTreeSet<CrateId>,
+
+    /// See [CommonAttributes::crate_features].
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) crate_features: Select<BTreeSet<String>>,
+
+    /// See [CommonAttributes::deps].
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) deps: Select<BTreeSet<CrateDependency>>,
+
+    /// See [CommonAttributes::proc_macro_deps].
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) proc_macro_deps: Select<BTreeSet<CrateDependency>>,
+
+    /// See [BuildScriptAttributes::deps].
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) build_deps: Select<BTreeSet<CrateDependency>>,
+
+    /// See [BuildScriptAttributes::link_deps].
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) build_link_deps: Select<BTreeSet<CrateDependency>>,
+
+    /// See [BuildScriptAttributes::proc_macro_deps].
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) build_proc_macro_deps: Select<BTreeSet<CrateDependency>>,
 }
 
 impl CrateContext {
@@ -359,6 +447,10 @@ impl CrateContext {
         source_annotations: &BTreeMap<PackageId, SourceAnnotation>,
         extras: &BTreeMap<CrateId, PairedExtras>,
         resolver_data: &TreeResolverMetadata,
+        isolated_resolver_data: &IsolatedTreeResolverMetadata,
+        exec_resolver_data: &ExecTreeResolverMetadata,
+        crate_instances: &CrateInstances,
+        required_binaries: &BTreeSet<String>,
         include_binaries: bool,
         include_build_scripts: bool,
         sources_are_present: bool,
@@ -366,49 +458,100 @@ impl CrateContext {
         let package: &Package = &packages[&annotation.node.id];
         let current_crate_id = CrateId::new(package.name.clone(), package.version.clone());
 
-        let new_crate_dep = |dep: Dependency| -> CrateDependency {
-            let pkg = &packages[&dep.package_id];
+        // Workspace members depend on the instances of crates resolved for them. Crates which are
+        // resolved the same way for all members using them depend on the same instances for
+        // each, so any one of these members will do.
+        let workspace_member = if isolated_resolver_data.contains_key(&current_crate_id) {
+            Some(&current_crate_id)
+        } else {
+            isolated_resolver_data
+                .iter()
+                .find(|(_, tree_data)| tree_data.contains_key(&current_crate_id))
+                .map(|(member, _)| member)
+        };
+
+        // Dependencies of targets built in the exec configuration (build scripts and the targets
+        // of crates only built by them or by proc macros) use the exec instances of crates.
+        let is_exec_only = exec_resolver_data.exec_only.contains(&current_crate_id);
+
+        let new_crate_dep =
+            |dep: Dependency, member: Option<&CrateId>, is_exec: bool| -> CrateDependency {
+                let pkg = &packages[&dep.package_id];
+                let id = CrateId::new(pkg.name.clone(), pkg.version.clone());
+
+                // Unfortunately, The package graph and resolve graph of cargo metadata have different representations
+                // for the crate names (resolve graph sanitizes names to match module names) so to get the rest of this
+                // content to align when rendering, the dependency target needs to be explicitly sanitized.
+                let target = sanitize_module_name(&dep.target_name);
 
-            // Unfortunately, The package graph and resolve graph of cargo metadata have different representations
-            // for the crate names (resolve graph sanitizes names to match module names) so to get the rest of this
-            // content to align when rendering, the dependency target needs to be explicitly sanitized.
-            let target = sanitize_module_name(&dep.target_name);
+                let instance = if is_exec && exec_resolver_data.instances.contains_key(&id) {
+                    Some(EXEC_INSTANCE.to_owned())
+                } else {
+                    member.and_then(|member| crate_instances.get(&id)?.get(member).cloned())
+                };
 
-            CrateDependency {
+                CrateDependency {
+                    id,
+                    target,
+                    alias: dep.alias,
+                    instance,
+                }
+            };
+        let new_crate_deps =
+            |deps: &Select<BTreeSet<Dependency>>, member: Option<&CrateId>, is_exec: bool| {
+                deps.clone().map(|dep| new_crate_dep(dep, member, is_exec))
+            };
+
+        let new_artifact_dep = |dep: ArtifactDependency| -> CrateArtifactDependency {
+            let pkg = &packages[&dep.package_id];
+            CrateArtifactDependency {
                 id: CrateId::new(pkg.name.clone(), pkg.version.clone()),
-                target,
-                alias: dep.alias,
+                name: dep.name,
+                bins: dep.bins,
             }
         };
 
         // Convert the dependencies into renderable strings
-        let deps = annotation.deps.normal_deps.clone().map(new_crate_dep);
-        let deps_dev = annotation.deps.normal_dev_deps.clone().map(new_crate_dep);
-        let proc_macro_deps = annotation.deps.proc_macro_deps.clone().map(new_crate_dep);
-        let proc_macro_deps_dev = annotation
-            .deps
-            .proc_macro_dev_deps
-            .clone()
-            .map(new_crate_dep);
-
-        let crate_features = resolver_data
-            .get(&current_crate_id)
-            .map(|tree_data| {
-                let mut select = Select::<BTreeSet<String>>::new();
-                for (config, data) in tree_data.items() {
-                    for feature in data.features {
-                        select.insert(feature, config.clone());
+        let deps = new_crate_deps(&annotation.deps.normal_deps, workspace_member, is_exec_only);
+        let deps_dev = new_crate_deps(
+            &annotation.deps.normal_dev_deps,
+            workspace_member,
+            is_exec_only,
+        );
+        let proc_macro_deps = new_crate_deps(
+            &annotation.deps.proc_macro_deps,
+            workspace_member,
+            is_exec_only,
+        );
+        let proc_macro_deps_dev = new_crate_deps(
+            &annotation.deps.proc_macro_dev_deps,
+            workspace_member,
+            is_exec_only,
+        );
+        let artifact_deps = annotation.deps.artifact_deps.clone().map(new_artifact_dep);
+
+        let features_of = |resolver_data: &TreeResolverMetadata| {
+            resolver_data
+                .get(&current_crate_id)
+                .map(|tree_data| {
+                    let mut select = Select::<BTreeSet<String>>::new();
+                    for (config, data) in tree_data.items() {
+                        for feature in data.features {
+                            select.insert(feature, config.clone());
+                        }
                     }
-                }
-                select
-            })
-            .unwrap_or_default();
+                    select
+                })
+                .unwrap_or_default()
+        };
+        let crate_features = features_of(resolver_data);
 
         // Gather all "common" attributes
         let mut common_attrs = CommonAttributes {
             crate_features,
             deps,
             deps_dev,
+            artifact_deps,
             edition: package.edition.as_str().to_string(),
             proc_macro_deps,
             proc_macro_deps_dev,
@@ -424,20 +567,35 @@ impl CrateContext {
         let include_build_scripts =
             Self::crate_includes_build_script(package_extra, include_build_scripts);
 
-        let gen_none = GenBinaries::Some(BTreeSet::new());
-        let gen_binaries = package_extra
+        // Cargo sets `CARGO_PKG_*` variables from the manifest of the package
+        let cargo_pkg_env = package_extra
+            .is_some_and(|(_, settings)| settings.crate_extra.cargo_pkg_env)
+            .then(|| Self::cargo_pkg_env(package));
+        if let Some(cargo_pkg_env) = &cargo_pkg_env {
+            common_attrs.rustc_env = Select::from_value(cargo_pkg_env.clone());
+        }
+
+        let gen_binaries = match package_extra
             .and_then(|(_, settings)| settings.crate_extra.gen_binaries.as_ref())
-            .unwrap_or(if include_binaries {
-                &GenBinaries::All
-            } else {
-                &gen_none
-            });
+        {
+            Some(gen_binaries) => gen_binaries.clone(),
+            None if include_binaries => GenBinaries::All,
+            None => GenBinaries::Some(BTreeSet::new()),
+        };
+
+        // Binaries required by artifact dependencies are always generated
+        let gen_binaries = match gen_binaries {
+            GenBinaries::All => GenBinaries::All,
+            GenBinaries::Some(set) => {
+                GenBinaries::Some(set.union(required_binaries).cloned().collect())
+            }
+        };
 
         // Iterate over each target and produce a Bazel target for all supported "kinds"
         let targets = Self::collect_targets(
             &annotation.node,
             packages,
-            gen_binaries,
+            &gen_binaries,
             include_build_scripts,
             sources_are_present,
         )?;
@@ -472,25 +630,37 @@ impl CrateContext {
             // Track the build script dependency
             common_attrs.deps.insert(
                 CrateDependency {
-                    id: current_crate_id,
+                    id: current_crate_id.clone(),
                     target: target.crate_name.clone(),
                     alias: None,
+                    instance: None,
                 },
                 None,
             );
 
-            let build_deps = annotation.deps.build_deps.clone().map(new_crate_dep);
-            let build_link_deps = annotation.deps.build_link_deps.clone().map(new_crate_dep);
-            let build_proc_macro_deps = annotation
+            let build_deps = new_crate_deps(&annotation.deps.build_deps, workspace_member, true);
+            let build_link_deps = new_crate_deps(
+                &annotation.deps.build_link_deps,
+                workspace_member,
+                is_exec_only,
+            );
+            let build_proc_macro_deps = new_crate_deps(
+                &annotation.deps.build_proc_macro_deps,
+                workspace_member,
+                true,
+            );
+            let build_artifact_deps = annotation
                 .deps
-                .build_proc_macro_deps
+                .build_artifact_deps
                 .clone()
-                .map(new_crate_dep);
+                .map(new_artifact_dep);
 
             Some(BuildScriptAttributes {
                 deps: build_deps,
                 link_deps: build_link_deps,
                 proc_macro_deps: build_proc_macro_deps,
+                artifact_deps: build_artifact_deps,
+                build_script_env: Select::from_value(cargo_pkg_env.unwrap_or_default()),
                 links: package.links.clone(),
                 ..Default::default()
             })
@@ -498,6 +668,59 @@ impl CrateContext {
             None
         };
 
+        // Resolve each instance from the perspective of the workspace members using it. These
+        // all resolve the crate and the instances of its dependencies the same way. The exec
+        // instance is resolved for the exec configuration instead.
+        let instances = annotation
+            .instances
+            .iter()
+            .filter_map(|(name, instance)| {
+                let (member, instance_resolver_data, is_exec) = if name == EXEC_INSTANCE {
+                    (None, &exec_resolver_data.instances, true)
+                } else {
+                    let member = instance.workspace_members.first()?;
+                    (Some(member), &isolated_resolver_data[member], is_exec_only)
+                };
+                let mut deps = new_crate_deps(&instance.deps.normal_deps, member, is_exec);
+                if let Some(target) = build_script_target {
+                    deps.insert(
+                        CrateDependency {
+                            id: current_crate_id.clone(),
+                            target: target.crate_name.clone(),
+                            alias: None,
+                            instance: Some(name.clone()),
+                        },
+                        None,
+                    );
+                }
+
+                Some((
+                    name.clone(),
+                    CrateInstance {
+                        workspace_members: instance.workspace_members.clone(),
+                        crate_features: features_of(instance_resolver_data),
+                        deps,
+                        proc_macro_deps: new_crate_deps(
+                            &instance.deps.proc_macro_deps,
+                            member,
+                            is_exec,
+                        ),
+                        build_deps: new_crate_deps(&instance.deps.build_deps, member, true),
+                        build_link_deps: new_crate_deps(
+                            &instance.deps.build_link_deps,
+                            member,
+                            is_exec,
+                        ),
+                        build_proc_macro_deps: new_crate_deps(
+                            &instance.deps.build_proc_macro_deps,
+                            member,
+                            true,
+                        ),
+                    },
+                ))
+            })
+            .collect();
+
         // Save the repository information for the current crate
         let repository = source_annotations.get(&package.id).cloned();
 
@@ -521,7 +744,7 @@ impl CrateContext {
         };
 
         // Create the crate's context and apply extra settings
-        Ok(CrateContext {
+        CrateContext {
             name: package.name.clone(),
             version: package.version.clone(),
             license: package.license.clone(),
@@ -529,17 +752,86 @@ impl CrateContext {
             license_file,
             package_url,
             repository,
+            source: package.source.as_ref().map(|source| source.repr.clone()),
             targets,
             library_target_name,
             common_attrs,
             build_script_attrs,
             additive_build_file_content: None,
             disable_pipelining: false,
+            gen_docs: false,
             extra_aliased_targets: BTreeMap::new(),
             alias_rule: None,
             override_targets: BTreeMap::new(),
+            instances,
+        }
+        .with_overrides(extras)
+        .with_binaries_for_enabled_features(package, &gen_binaries)
+    }
+
+    /// Like Cargo, skip binaries whose [required features](https://doc.rust-lang.org/cargo/reference/cargo-targets.html#the-required-features-field)
+    /// are not enabled. Binaries which were explicitly requested must have them enabled.
+    fn with_binaries_for_enabled_features(
+        mut self,
+        package: &Package,
+        gen_binaries: &GenBinaries,
+    ) -> anyhow::Result<Self> {
+        let enabled_features: BTreeSet<String> = self
+            .common_attrs
+            .crate_features
+            .values()
+            .into_iter()
+            .collect();
+
+        let mut missing_features = BTreeMap::new();
+        for target in &package.targets {
+            let missing: Vec<&str> = target
+                .required_features
+                .iter()
+                .filter(|feature| !enabled_features.contains(*feature))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                missing_features.insert(target.name.as_str(), missing);
+            }
+        }
+
+        let mut result = Ok(());
+        self.targets.retain(|rule| {
+            let Rule::Binary(target) = rule else {
+                return true;
+            };
+            let Some(missing) = missing_features.get(target.crate_name.as_str()) else {
+                return true;
+            };
+            if matches!(gen_binaries, GenBinaries::Some(_)) && result.is_ok() {
+                result = Err(anyhow::anyhow!(
+                    "The binary `{}` of {} {} was requested but requires features which are not enabled: {}. \
+                    Enable them on the crate to resolve its dependencies with them.",
+                    target.crate_name,
+                    self.name,
+                    self.version,
+                    missing.join(", "),
+                ));
+            }
+            false
+        });
+        result.map(|()| self)
+    }
+
+    /// A copy of the crate using the features and dependencies of one of its instances.
+    pub(crate) fn with_instance(&self, instance: &CrateInstance) -> Self {
+        let mut krate = self.clone();
+        krate.common_attrs.crate_features = instance.crate_features.clone();
+        krate.common_attrs.deps = instance.deps.clone();
+        krate.common_attrs.proc_macro_deps = instance.proc_macro_deps.clone();
+        if let Some(attrs) = &mut krate.build_script_attrs {
+            attrs.deps = instance.build_deps.clone();
+            attrs.link_deps = instance.build_link_deps.clone();
+            attrs.proc_macro_deps = instance.build_proc_macro_deps.clone();
         }
-        .with_overrides(extras))
+        krate.instances.clear();
+        krate
     }
 
     fn with_overrides(mut self, extras: &BTreeMap<CrateId, PairedExtras>) -> Self {
@@ -573,9 +865,13 @@ impl CrateContext {
             }
 
             // Crate features
-            if let Some(extra) = &crate_extra.crate_features {
-                self.common_attrs.crate_features =
-                    Select::merge(self.common_attrs.crate_features, extra.clone());
+            self.common_attrs.crate_features =
+                Self::with_feature_overrides(self.common_attrs.crate_features, crate_extra);
+            for instance in self.instances.values_mut() {
+                instance.crate_features = Self::with_feature_overrides(
+                    std::mem::take(&mut instance.crate_features),
+                    crate_extra,
+                );
             }
 
             // Data
@@ -593,6 +889,11 @@ impl CrateContext {
                 self.disable_pipelining = true;
             }
 
+            // Generate docs
+            if crate_extra.gen_docs {
+                self.gen_docs = true;
+            }
+
             // Rustc flags
             if let Some(extra) = &crate_extra.rustc_flags {
                 self.common_attrs.rustc_flags =
@@ -691,7 +992,9 @@ impl CrateContext {
 
             // Git shallow_since
             if let Some(SourceAnnotation::Git { shallow_since, .. }) = &mut self.repository {
-                shallow_since.clone_from(&crate_extra.shallow_since);
+                if let Some(annotated) = &crate_extra.shallow_since {
+                    *shallow_since = Some(annotated.clone());
+                }
             }
 
             // Patch attributes
@@ -731,6 +1034,82 @@ impl CrateContext {
         self
     }
 
+    /// Apply the feature overrides, additions and removals of a crate's annotations.
+    fn with_feature_overrides(
+        mut crate_features: Select<BTreeSet<String>>,
+        crate_extra: &CrateAnnotations,
+    ) -> Select<BTreeSet<String>> {
+        // Crate features override
+        if let Some(features) = &crate_extra.override_crate_features {
+            crate_features = Select::from_value(features.clone());
+        }
+
+        // Crate features
+        if let Some(extra) = &crate_extra.crate_features {
+            crate_features = Select::merge(crate_features, extra.clone());
+        }
+
+        // Removed crate features
+        if let Some(removed) = &crate_extra.remove_crate_features {
+            let mut remaining = Select::new();
+            for (configuration, feature) in crate_features.items() {
+                if !removed.contains(&feature) {
+                    remaining.insert(feature, configuration);
+                }
+            }
+            crate_features = remaining;
+        }
+
+        crate_features
+    }
+
+    /// The `CARGO_PKG_*` environment variables Cargo sets from a package's manifest which
+    /// rules_rust has no way of knowing about. Values are escaped to survive make variable
+    /// expansion and paths are relative to the root of the package.
+    fn cargo_pkg_env(package: &Package) -> BTreeMap<String, String> {
+        [
+            ("CARGO_PKG_AUTHORS", Some(package.authors.join(":"))),
+            ("CARGO_PKG_DESCRIPTION", package.description.clone()),
+            ("CARGO_PKG_HOMEPAGE", package.homepage.clone()),
+            ("CARGO_PKG_LICENSE", package.license.clone()),
+            (
+                "CARGO_PKG_LICENSE_FILE",
+                package
+                    .license_file
+                    .as_deref()
+                    .and_then(|path| Self::package_relative_path(package, path)),
+            ),
+            (
+                "CARGO_PKG_README",
+                package
+                    .readme
+                    .as_deref()
+                    .and_then(|path| Self::package_relative_path(package, path)),
+            ),
+            ("CARGO_PKG_REPOSITORY", package.repository.clone()),
+            (
+                "CARGO_PKG_RUST_VERSION",
+                package.rust_version.as_ref().map(ToString::to_string),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let value = value.filter(|value| !value.is_empty())?;
+            Some((key.to_owned(), value.replace('$', "$$")))
+        })
+        .collect()
+    }
+
+    /// `path` relative to the root of `package`, or `None` if it's outside of it.
+    fn package_relative_path(package: &Package, path: &Utf8Path) -> Option<String> {
+        let package_root = package.manifest_path.parent()?;
+        match path.strip_prefix(package_root) {
+            Ok(relative) => Some(relative.to_string()),
+            Err(_) if path.is_relative() => Some(path.to_string()),
+            Err(_) => None,
+        }
+    }
+
     fn locate_license_file(package: &Package) -> Option<String> {
         if let Some(license_file_path) = &package.license_file {
             return Some(license_file_path.to_string());
@@ -859,7 +1238,6 @@ impl CrateContext {
 mod test {
     use super::*;
 
-    use camino::Utf8Path;
     use semver::Version;
 
     use crate::config::CrateAnnotations;
@@ -892,6 +1270,13 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations
+                .metadata
+                .workspace_metadata
+                .isolated_tree_metadata,
+            &annotations.metadata.workspace_metadata.exec_tree_metadata,
+            &annotations.metadata.crate_instances,
+            &BTreeSet::new(),
             include_binaries,
             include_build_scripts,
             are_sources_present,
@@ -941,6 +1326,13 @@ mod test {
             &annotations.lockfile.crates,
             &pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations
+                .metadata
+                .workspace_metadata
+                .isolated_tree_metadata,
+            &annotations.metadata.workspace_metadata.exec_tree_metadata,
+            &annotations.metadata.crate_instances,
+            &BTreeSet::new(),
             include_binaries,
             include_build_scripts,
             are_sources_present,
@@ -1009,6 +1401,13 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations
+                .metadata
+                .workspace_metadata
+                .isolated_tree_metadata,
+            &annotations.metadata.workspace_metadata.exec_tree_metadata,
+            &annotations.metadata.crate_instances,
+            &BTreeSet::new(),
             include_binaries,
             include_build_scripts,
             are_sources_present,
@@ -1057,6 +1456,13 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations
+                .metadata
+                .workspace_metadata
+                .isolated_tree_metadata,
+            &annotations.metadata.workspace_metadata.exec_tree_metadata,
+            &annotations.metadata.crate_instances,
+            &BTreeSet::new(),
             include_binaries,
             include_build_scripts,
             are_sources_present,
@@ -1094,6 +1500,13 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations
+                .metadata
+                .workspace_metadata
+                .isolated_tree_metadata,
+            &annotations.metadata.workspace_metadata.exec_tree_metadata,
+            &annotations.metadata.crate_instances,
+            &BTreeSet::new(),
             include_binaries,
             include_build_scripts,
             are_sources_present,
@@ -1137,6 +1550,13 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations
+                .metadata
+                .workspace_metadata
+                .isolated_tree_metadata,
+            &annotations.metadata.workspace_metadata.exec_tree_metadata,
+            &annotations.metadata.crate_instances,
+            &BTreeSet::new(),
             include_binaries,
             include_build_scripts,
             are_sources_present,
@@ -1186,6 +1606,70 @@ mod test {
         );
     }
 
+    #[test]
+    fn context_with_cargo_pkg_env() {
+        let mut annotations = common_annotations();
+
+        let package_id = PackageId {
+            repr: "path+file://{TEMP_DIR}/common#0.1.0".to_owned(),
+        };
+        let package = annotations.metadata.packages.get_mut(&package_id).unwrap();
+        package.authors = vec!["Ferris".to_owned(), "Corro".to_owned()];
+        package.description = Some("Costs $0".to_owned());
+        package.readme = Some(package.manifest_path.with_file_name("README.md"));
+        package.rust_version = Some(semver::Version::new(1, 70, 0));
+        package.repository = None;
+
+        let new_context = |cargo_pkg_env| {
+            let pairred_extras = BTreeMap::from([(
+                CrateId::new("common".to_owned(), semver::Version::new(0, 1, 0)),
+                PairedExtras {
+                    package_id: package_id.clone(),
+                    crate_extra: CrateAnnotations {
+                        cargo_pkg_env,
+                        ..CrateAnnotations::default()
+                    },
+                },
+            )]);
+            CrateContext::new(
+                &annotations.metadata.crates[&package_id],
+                &annotations.metadata.packages,
+                &annotations.lockfile.crates,
+                &pairred_extras,
+                &annotations.metadata.workspace_metadata.tree_metadata,
+                &annotations
+                    .metadata
+                    .workspace_metadata
+                    .isolated_tree_metadata,
+                &annotations.metadata.workspace_metadata.exec_tree_metadata,
+                &annotations.metadata.crate_instances,
+                &BTreeSet::new(),
+                false,
+                false,
+                false,
+            )
+            .unwrap()
+        };
+
+        // The variables are only set for crates which opt into them.
+        let context = new_context(false);
+        assert!(context.common_attrs.rustc_env.is_empty());
+
+        let context = new_context(true);
+        let expected = BTreeMap::from([
+            ("CARGO_PKG_AUTHORS".to_owned(), "Ferris:Corro".to_owned()),
+            ("CARGO_PKG_DESCRIPTION".to_owned(), "Costs $$0".to_owned()),
+            ("CARGO_PKG_README".to_owned(), "README.md".to_owned()),
+            ("CARGO_PKG_RUST_VERSION".to_owned(), "1.70.0".to_owned()),
+        ]);
+        let (rustc_env, selects) = context.common_attrs.rustc_env.into_parts();
+        assert!(selects.is_empty());
+        for (key, value) in &expected {
+            assert_eq!(rustc_env.get(key), Some(value), "{key}");
+        }
+        assert!(!rustc_env.contains_key("CARGO_PKG_REPOSITORY"));
+    }
+
     #[test]
     fn context_package_url_with_only_repository() {
         package_context_test(
@@ -1270,6 +1754,13 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations
+                .metadata
+                .workspace_metadata
+                .isolated_tree_metadata,
+            &annotations.metadata.workspace_metadata.exec_tree_metadata,
+            &annotations.metadata.crate_instances,
+            &BTreeSet::new(),
             include_binaries,
             include_build_scripts,
             are_sources_present,
@@ -1282,6 +1773,147 @@ mod test {
         assert_eq!(context.common_attrs.crate_features, expected);
     }
 
+    #[test]
+    fn crate_context_pruned_features_from_annotations() {
+        let mut annotations = common_annotations();
+
+        let mut select = Select::new();
+        select.insert(
+            CargoTreeEntry {
+                features: BTreeSet::from(["default".to_owned(), "std".to_owned()]),
+                deps: BTreeSet::new(),
+            },
+            None,
+        );
+        select.insert(
+            CargoTreeEntry {
+                features: BTreeSet::from([
+                    "default".to_owned(),
+                    "std".to_owned(),
+                    "simd".to_owned(),
+                ]),
+                deps: BTreeSet::new(),
+            },
+            Some("x86_64-unknown-linux-gnu".to_owned()),
+        );
+        annotations
+            .metadata
+            .workspace_metadata
+            .tree_metadata
+            .insert(
+                CrateId::new("common".to_owned(), Version::new(0, 1, 0)),
+                select,
+            );
+
+        let package_id = PackageId {
+            repr: "path+file://{TEMP_DIR}/common#0.1.0".to_owned(),
+        };
+        let crate_annotation = &annotations.metadata.crates[&package_id];
+        let context = |crate_extra: CrateAnnotations| {
+            let pairred_extras = BTreeMap::from([(
+                CrateId::new("common".to_owned(), Version::new(0, 1, 0)),
+                PairedExtras {
+                    package_id: package_id.clone(),
+                    crate_extra,
+                },
+            )]);
+            CrateContext::new(
+                crate_annotation,
+                &annotations.metadata.packages,
+                &annotations.lockfile.crates,
+                &pairred_extras,
+                &annotations.metadata.workspace_metadata.tree_metadata,
+                &annotations
+                    .metadata
+                    .workspace_metadata
+                    .isolated_tree_metadata,
+                &annotations.metadata.workspace_metadata.exec_tree_metadata,
+                &annotations.metadata.crate_instances,
+                &BTreeSet::new(),
+                false,
+                false,
+                false,
+            )
+            .unwrap()
+        };
+
+        let removed = context(CrateAnnotations {
+            remove_crate_features: Some(BTreeSet::from(["std".to_owned(), "simd".to_owned()])),
+            ..CrateAnnotations::default()
+        });
+        let mut expected = Select::new();
+        expected.insert("default".to_owned(), None);
+        assert_eq!(removed.common_attrs.crate_features, expected);
+
+        let overridden = context(CrateAnnotations {
+            override_crate_features: Some(BTreeSet::from(["alloc".to_owned()])),
+            crate_features: Some(Select::from_value(BTreeSet::from(["extra".to_owned()]))),
+            ..CrateAnnotations::default()
+        });
+        let mut expected = Select::new();
+        expected.insert("alloc".to_owned(), None);
+        expected.insert("extra".to_owned(), None);
+        assert_eq!(overridden.common_attrs.crate_features, expected);
+    }
+
+    #[test]
+    fn binaries_with_required_features() {
+        let mut annotations = common_annotations();
+        let package_id = PackageId {
+            repr: "path+file://{TEMP_DIR}/common#0.1.0".to_owned(),
+        };
+
+        // Add a binary which requires a feature that isn't enabled.
+        let package = annotations.metadata.packages.get_mut(&package_id).unwrap();
+        let mut bin = package.targets[0].clone();
+        bin.name = "tool".to_owned();
+        bin.kind = vec![cargo_metadata::TargetKind::Bin];
+        bin.crate_types = vec![cargo_metadata::CrateType::Bin];
+        bin.required_features = vec!["cli".to_owned()];
+        package.targets.push(bin);
+
+        let new_context = |include_binaries: bool, required_binaries: BTreeSet<String>| {
+            CrateContext::new(
+                &annotations.metadata.crates[&package_id],
+                &annotations.metadata.packages,
+                &annotations.lockfile.crates,
+                &annotations.pairred_extras,
+                &annotations.metadata.workspace_metadata.tree_metadata,
+                &annotations
+                    .metadata
+                    .workspace_metadata
+                    .isolated_tree_metadata,
+                &annotations.metadata.workspace_metadata.exec_tree_metadata,
+                &annotations.metadata.crate_instances,
+                &required_binaries,
+                include_binaries,
+                false,
+                false,
+            )
+        };
+
+        // Like Cargo, binaries are skipped if their required features are missing.
+        let context = new_context(true, BTreeSet::new()).unwrap();
+        let binaries: BTreeSet<&str> = context
+            .targets
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Binary(target) => Some(target.crate_name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(binaries, BTreeSet::from(["common-bin"]));
+
+        // Unless they were explicitly requested.
+        let err = new_context(false, BTreeSet::from(["tool".to_owned()]))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("The binary `tool` of common 0.1.0 was requested but requires features which are not enabled: cli"),
+            "{err}"
+        );
+    }
+
     #[test]
     fn absolute_paths_for_srcs_are_errors() {
         let annotations = Annotations::new(
@@ -1305,6 +1937,13 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations
+                .metadata
+                .workspace_metadata
+                .isolated_tree_metadata,
+            &annotations.metadata.workspace_metadata.exec_tree_metadata,
+            &annotations.metadata.crate_instances,
+            &BTreeSet::new(),
             include_binaries,
             include_build_scripts,
             are_sources_present,
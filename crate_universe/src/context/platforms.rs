@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::{anyhow, Context, Result};
@@ -5,15 +6,28 @@ use cfg_expr::targets::{get_builtin_target_by_triple, TargetInfo};
 use cfg_expr::{Expression, Predicate};
 
 use crate::context::CrateContext;
-use crate::utils::target_triple::TargetTriple;
+use crate::utils::target_triple::{TargetCfgs, TargetSpec, TargetTriple};
+
+/// Configurations which only hold for some platform triples if `debug_assertions` are enabled
+/// (`true`) or disabled (`false`), mapped to the required value for each of those triples.
+pub(crate) type DebugAssertionsConditions = BTreeMap<String, BTreeMap<TargetTriple, bool>>;
 
 /// Walk through all dependencies in a [CrateContext] list for all configuration specific
 /// dependencies to produce a mapping of configurations/Cargo target_triples to compatible
-/// Bazel target_triples.  Also adds mappings for all known target_triples.
+/// Bazel target_triples.  Also adds mappings for all known target_triples. Triples which are not
+/// built into `rustc` are evaluated using their entry in `target_specs`. Target features and
+/// other options `cfg-expr` doesn't model are evaluated using the `cfg` options `rustc` reported
+/// for each triple in `target_cfgs`, if any. Configurations whose value for a triple depends on
+/// `debug_assertions` are returned separately.
 pub(crate) fn resolve_cfg_platforms(
     crates: Vec<&CrateContext>,
     supported_platform_triples: &BTreeSet<TargetTriple>,
-) -> Result<BTreeMap<String, BTreeSet<TargetTriple>>> {
+    target_specs: &BTreeMap<TargetTriple, TargetSpec>,
+    target_cfgs: &TargetCfgs,
+) -> Result<(
+    BTreeMap<String, BTreeSet<TargetTriple>>,
+    DebugAssertionsConditions,
+)> {
     // Collect all unique configurations from all dependencies into a single set
     let configurations: BTreeSet<String> = crates
         .iter()
@@ -25,11 +39,13 @@ pub(crate) fn resolve_cfg_platforms(
             configurations.extend(attr.deps_dev.configurations());
             configurations.extend(attr.proc_macro_deps.configurations());
             configurations.extend(attr.proc_macro_deps_dev.configurations());
+            configurations.extend(attr.artifact_deps.configurations());
 
             // Chain the build dependencies if some are defined
             if let Some(attr) = &ctx.build_script_attrs {
                 configurations.extend(attr.deps.configurations());
                 configurations.extend(attr.proc_macro_deps.configurations());
+                configurations.extend(attr.artifact_deps.configurations());
             }
 
             configurations
@@ -39,16 +55,22 @@ pub(crate) fn resolve_cfg_platforms(
     // Generate target information for each triple string
     let target_infos = supported_platform_triples
         .iter()
-        .map(
-            |target_triple| match get_builtin_target_by_triple(&target_triple.to_cargo()) {
-                Some(info) => Ok((target_triple, info)),
+        .map(|target_triple| {
+            if let Some(spec) = target_specs.get(target_triple) {
+                let info = spec.target_info(target_triple).with_context(|| {
+                    format!("Invalid target spec for platform triple {target_triple}")
+                })?;
+                return Ok((target_triple, Cow::Owned(info)));
+            }
+            match get_builtin_target_by_triple(&target_triple.to_cargo()) {
+                Some(info) => Ok((target_triple, Cow::Borrowed(info))),
                 None => Err(anyhow!(
-                    "Invalid platform triple in supported platforms: {}",
+                    "Invalid platform triple in supported platforms: {}. Triples which are not built into rustc require a target spec in `target_specs`",
                     target_triple
                 )),
-            },
-        )
-        .collect::<Result<BTreeMap<&TargetTriple, &'static TargetInfo>>>()?;
+            }
+        })
+        .collect::<Result<BTreeMap<&TargetTriple, Cow<'static, TargetInfo>>>>()?;
 
     // `cfg-expr` does not understand configurations that are simply platform triples
     // (`x86_64-unknown-linux-gnu` vs `cfg(target = "x86_64-unkonwn-linux-gnu")`). So
@@ -61,6 +83,7 @@ pub(crate) fn resolve_cfg_platforms(
         .map(|cfg| (rename(cfg), cfg.clone()))
         .collect();
 
+    let mut debug_assertions_conditions = DebugAssertionsConditions::new();
     let mut conditions = configurations
         .into_iter()
         // `cfg-expr` requires that the expressions be actual `cfg` expressions. Any time
@@ -74,20 +97,47 @@ pub(crate) fn resolve_cfg_platforms(
             let expression =
                 Expression::parse(&cfg).context(format!("Failed to parse expression: '{cfg}'"))?;
 
-            let triples = target_infos
-                .iter()
-                .filter(|(_, target_info)| {
-                    expression.eval(|p| match p {
-                        Predicate::Target(tp) => tp.matches(**target_info),
-                        Predicate::KeyValue { key, val } => {
-                            *key == "target" && val == &target_info.triple.as_str()
-                        }
-                        // For now there is no other kind of matching
-                        _ => false,
-                    })
+            let eval = |triple: &TargetTriple, target_info: &TargetInfo, debug_assertions: bool| {
+                let has_cfg = |cfg: String| {
+                    target_cfgs
+                        .get(triple)
+                        .is_some_and(|cfgs| cfgs.contains(&cfg))
+                };
+                expression.eval(|p| match p {
+                    Predicate::Target(tp) => tp.matches(target_info),
+                    Predicate::KeyValue { key, val } if *key == "target" => {
+                        val == &target_info.triple.as_str()
+                    }
+                    Predicate::KeyValue { key, val } => has_cfg(format!("{key}=\"{val}\"")),
+                    Predicate::TargetFeature(feature) => {
+                        has_cfg(format!("target_feature=\"{feature}\""))
+                    }
+                    Predicate::Flag(flag) => has_cfg(flag.to_string()),
+                    // Bazel enables `debug_assertions` depending on the compilation mode, so
+                    // expressions are evaluated for both values.
+                    Predicate::DebugAssertions => debug_assertions,
+                    // Dependencies are never built for tests or as proc-macros and Cargo doesn't
+                    // support features in dependency tables.
+                    _ => false,
                 })
-                .map(|(triple, _)| (*triple).clone())
-                .collect();
+            };
+
+            let mut triples = BTreeSet::new();
+            let mut debug_assertions_triples = BTreeMap::new();
+            for (triple, target_info) in &target_infos {
+                match (
+                    eval(triple, target_info, true),
+                    eval(triple, target_info, false),
+                ) {
+                    (true, true) => {
+                        triples.insert((*triple).clone());
+                    }
+                    (false, false) => {}
+                    (enabled, _) => {
+                        debug_assertions_triples.insert((*triple).clone(), enabled);
+                    }
+                }
+            }
 
             // Map any renamed configurations back to their original IDs
             let cfg = match original_cfgs.get(&cfg) {
@@ -95,6 +145,10 @@ pub(crate) fn resolve_cfg_platforms(
                 None => cfg,
             };
 
+            if !debug_assertions_triples.is_empty() {
+                debug_assertions_conditions.insert(cfg.clone(), debug_assertions_triples);
+            }
+
             Ok((cfg, triples))
         })
         .collect::<Result<BTreeMap<String, BTreeSet<TargetTriple>>>>()?;
@@ -105,7 +159,7 @@ pub(crate) fn resolve_cfg_platforms(
             .or_default()
             .insert(target_triple.clone());
     }
-    Ok(conditions)
+    Ok((conditions, debug_assertions_conditions))
 }
 
 #[cfg(test)]
@@ -135,6 +189,7 @@ mod test {
                 id: CrateId::new("mock_crate_b".to_owned(), VERSION_ZERO_ONE_ZERO),
                 target: "mock_crate_b".to_owned(),
                 alias: None,
+                instance: None,
             },
             None,
         );
@@ -144,6 +199,7 @@ mod test {
             version: VERSION_ZERO_ONE_ZERO,
             package_url: None,
             repository: None,
+            source: None,
             targets: BTreeSet::default(),
             library_target_name: None,
             common_attrs: CommonAttributes {
@@ -156,13 +212,20 @@ mod test {
             license_file: None,
             additive_build_file_content: None,
             disable_pipelining: false,
+            gen_docs: false,
             extra_aliased_targets: BTreeMap::default(),
             alias_rule: None,
             override_targets: BTreeMap::default(),
+            instances: BTreeMap::default(),
         };
 
-        let configurations =
-            resolve_cfg_platforms(vec![&context], &supported_platform_triples()).unwrap();
+        let (configurations, _) = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples(),
+            &BTreeMap::new(),
+            &TargetCfgs::new(),
+        )
+        .unwrap();
 
         assert_eq!(
             configurations,
@@ -193,6 +256,7 @@ mod test {
                 id: CrateId::new("mock_crate_b".to_owned(), VERSION_ZERO_ONE_ZERO),
                 target: "mock_crate_b".to_owned(),
                 alias: None,
+                instance: None,
             },
             Some(configuration),
         );
@@ -202,6 +266,7 @@ mod test {
             version: VERSION_ZERO_ONE_ZERO,
             package_url: None,
             repository: None,
+            source: None,
             targets: BTreeSet::default(),
             library_target_name: None,
             common_attrs: CommonAttributes {
@@ -214,12 +279,77 @@ mod test {
             license_file: None,
             additive_build_file_content: None,
             disable_pipelining: false,
+            gen_docs: false,
             extra_aliased_targets: BTreeMap::default(),
             alias_rule: None,
             override_targets: BTreeMap::default(),
+            instances: BTreeMap::default(),
         }
     }
 
+    #[test]
+    fn resolve_debug_assertions() {
+        let configuration = r#"cfg(all(debug_assertions, target_os = "macos"))"#.to_owned();
+        let context = mock_resolve_context(configuration.clone());
+
+        let (configurations, debug_assertions_conditions) = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples(),
+            &BTreeMap::new(),
+            &TargetCfgs::new(),
+        )
+        .unwrap();
+
+        assert_eq!(configurations[&configuration], BTreeSet::new());
+        assert_eq!(
+            debug_assertions_conditions,
+            BTreeMap::from([(
+                configuration,
+                BTreeMap::from([
+                    (
+                        TargetTriple::from_bazel("aarch64-apple-darwin".to_owned()),
+                        true
+                    ),
+                    (
+                        TargetTriple::from_bazel("i686-apple-darwin".to_owned()),
+                        true
+                    ),
+                ]),
+            )])
+        );
+
+        let configuration = r#"cfg(any(not(debug_assertions), unix))"#.to_owned();
+        let context = mock_resolve_context(configuration.clone());
+
+        let (configurations, debug_assertions_conditions) = resolve_cfg_platforms(
+            vec![&context],
+            &BTreeSet::from([
+                TargetTriple::from_bazel("x86_64-pc-windows-msvc".to_owned()),
+                TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned()),
+            ]),
+            &BTreeMap::new(),
+            &TargetCfgs::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            configurations[&configuration],
+            BTreeSet::from([TargetTriple::from_bazel(
+                "x86_64-unknown-linux-gnu".to_owned()
+            )])
+        );
+        assert_eq!(
+            debug_assertions_conditions,
+            BTreeMap::from([(
+                configuration,
+                BTreeMap::from([(
+                    TargetTriple::from_bazel("x86_64-pc-windows-msvc".to_owned()),
+                    false
+                )]),
+            )])
+        );
+    }
+
     #[test]
     fn resolve_targeted() {
         let data = BTreeMap::from([
@@ -241,8 +371,13 @@ mod test {
         data.into_iter().for_each(|(configuration, expectation)| {
             let context = mock_resolve_context(configuration.clone());
 
-            let configurations =
-                resolve_cfg_platforms(vec![&context], &supported_platform_triples()).unwrap();
+            let (configurations, _) = resolve_cfg_platforms(
+                vec![&context],
+                &supported_platform_triples(),
+                &BTreeMap::new(),
+                &TargetCfgs::new(),
+            )
+            .unwrap();
 
             assert_eq!(
                 configurations,
@@ -279,6 +414,7 @@ mod test {
                 id: CrateId::new("mock_crate_b".to_owned(), VERSION_ZERO_ONE_ZERO),
                 target: "mock_crate_b".to_owned(),
                 alias: None,
+                instance: None,
             },
             Some(configuration.clone()),
         );
@@ -288,6 +424,7 @@ mod test {
             version: VERSION_ZERO_ONE_ZERO,
             package_url: None,
             repository: None,
+            source: None,
             targets: BTreeSet::default(),
             library_target_name: None,
             common_attrs: CommonAttributes {
@@ -300,13 +437,20 @@ mod test {
             license_file: None,
             additive_build_file_content: None,
             disable_pipelining: false,
+            gen_docs: false,
             extra_aliased_targets: BTreeMap::default(),
             alias_rule: None,
             override_targets: BTreeMap::default(),
+            instances: BTreeMap::default(),
         };
 
-        let configurations =
-            resolve_cfg_platforms(vec![&context], &supported_platform_triples()).unwrap();
+        let (configurations, _) = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples(),
+            &BTreeMap::new(),
+            &TargetCfgs::new(),
+        )
+        .unwrap();
 
         assert_eq!(
             configurations,
@@ -345,6 +489,7 @@ mod test {
                 id: CrateId::new("mock_crate_b".to_owned(), VERSION_ZERO_ONE_ZERO),
                 target: "mock_crate_b".to_owned(),
                 alias: None,
+                instance: None,
             },
             Some(configuration.clone()),
         );
@@ -354,6 +499,7 @@ mod test {
             version: VERSION_ZERO_ONE_ZERO,
             package_url: None,
             repository: None,
+            source: None,
             targets: BTreeSet::default(),
             library_target_name: None,
             common_attrs: CommonAttributes {
@@ -366,13 +512,20 @@ mod test {
             license_file: None,
             additive_build_file_content: None,
             disable_pipelining: false,
+            gen_docs: false,
             extra_aliased_targets: BTreeMap::default(),
             alias_rule: None,
             override_targets: BTreeMap::default(),
+            instances: BTreeMap::default(),
         };
 
-        let configurations =
-            resolve_cfg_platforms(vec![&context], &supported_platform_triples()).unwrap();
+        let (configurations, _) = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples(),
+            &BTreeMap::new(),
+            &TargetCfgs::new(),
+        )
+        .unwrap();
 
         assert_eq!(
             configurations,
@@ -396,4 +549,138 @@ mod test {
             ])
         );
     }
+
+    #[test]
+    fn resolve_custom_target() {
+        let mut deps: Select<BTreeSet<CrateDependency>> = Select::default();
+        for configuration in [
+            r#"cfg(all(target_arch = "riscv32", target_os = "none"))"#,
+            r#"cfg(target_has_atomic = "64")"#,
+            r#"cfg(unix)"#,
+        ] {
+            deps.insert(
+                CrateDependency {
+                    id: CrateId::new("mock_crate_b".to_owned(), VERSION_ZERO_ONE_ZERO),
+                    target: "mock_crate_b".to_owned(),
+                    alias: None,
+                    instance: None,
+                },
+                Some(configuration.to_owned()),
+            );
+        }
+
+        let context = CrateContext {
+            name: "mock_crate_a".to_owned(),
+            version: VERSION_ZERO_ONE_ZERO,
+            package_url: None,
+            repository: None,
+            source: None,
+            targets: BTreeSet::default(),
+            library_target_name: None,
+            common_attrs: CommonAttributes {
+                deps,
+                ..CommonAttributes::default()
+            },
+            build_script_attrs: None,
+            license: None,
+            license_ids: BTreeSet::default(),
+            license_file: None,
+            additive_build_file_content: None,
+            disable_pipelining: false,
+            gen_docs: false,
+            extra_aliased_targets: BTreeMap::default(),
+            alias_rule: None,
+            override_targets: BTreeMap::default(),
+            instances: BTreeMap::default(),
+        };
+
+        let custom_triple = TargetTriple::from_bazel("riscv32imc-acme-none".to_owned());
+        let target_specs = BTreeMap::from([(
+            custom_triple.clone(),
+            serde_json::from_value(serde_json::json!({
+                "arch": "riscv32",
+                "data-layout": "e-m:e-p:32:32-i64:64-n32-S128",
+                "llvm-target": "riscv32",
+                "max-atomic-width": 32,
+                "panic-strategy": "abort",
+                "target-pointer-width": "32",
+                "vendor": "acme",
+            }))
+            .unwrap(),
+        )]);
+        let mut triples = supported_platform_triples();
+        triples.insert(custom_triple.clone());
+
+        let (configurations, _) =
+            resolve_cfg_platforms(vec![&context], &triples, &target_specs, &TargetCfgs::new())
+                .unwrap();
+
+        assert_eq!(
+            configurations[r#"cfg(all(target_arch = "riscv32", target_os = "none"))"#],
+            BTreeSet::from([custom_triple.clone()])
+        );
+        assert!(!configurations[r#"cfg(target_has_atomic = "64")"#].contains(&custom_triple));
+        assert!(!configurations["cfg(unix)"].contains(&custom_triple));
+        assert_eq!(
+            configurations["riscv32imc-acme-none"],
+            BTreeSet::from([custom_triple])
+        );
+
+        // Triples which are neither built in nor described by a spec are rejected.
+        assert!(resolve_cfg_platforms(
+            vec![&context],
+            &triples,
+            &BTreeMap::new(),
+            &TargetCfgs::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn resolve_wasm_targeted() {
+        let wasm = TargetTriple::from_bazel("wasm32-unknown-unknown".to_owned());
+        // The legacy name of `wasm32-wasip1`.
+        let wasi = TargetTriple::from_bazel("wasm32-wasi".to_owned());
+        let linux = TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned());
+        let triples = BTreeSet::from([wasm.clone(), wasi.clone(), linux.clone()]);
+        let target_cfgs = TargetCfgs::from([
+            (
+                wasm.clone(),
+                BTreeSet::from([r#"target_feature="sign-ext""#.to_owned()]),
+            ),
+            (
+                linux,
+                BTreeSet::from([r#"target_feature="sse2""#.to_owned()]),
+            ),
+        ]);
+
+        let data = BTreeMap::from([
+            (
+                r#"cfg(all(target_arch = "wasm32", target_os = "unknown"))"#,
+                BTreeSet::from([wasm.clone()]),
+            ),
+            (r#"cfg(target_os = "wasi")"#, BTreeSet::from([wasi])),
+            (
+                r#"cfg(all(target_arch = "wasm32", target_feature = "atomics"))"#,
+                BTreeSet::new(),
+            ),
+            (
+                r#"cfg(target_feature = "sign-ext")"#,
+                BTreeSet::from([wasm]),
+            ),
+        ]);
+
+        for (configuration, expectation) in data {
+            let context = mock_resolve_context(configuration.to_owned());
+
+            let (configurations, _) =
+                resolve_cfg_platforms(vec![&context], &triples, &BTreeMap::new(), &target_cfgs)
+                    .unwrap();
+
+            assert_eq!(
+                configurations[configuration], expectation,
+                "{configuration}"
+            );
+        }
+    }
 }
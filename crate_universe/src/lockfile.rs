@@ -25,16 +25,145 @@ pub(crate) fn lock_context(
 ) -> Result<Context> {
     // Ensure there is no existing checksum which could impact the lockfile results
     context.checksum = None;
+    context.metadata = None;
 
     let checksum = Digest::new(&context, config, splicing_manifest, cargo_bin, rustc_bin)
         .context("Failed to generate context digest")?;
+    let metadata = LockfileMetadata::new(config, splicing_manifest, cargo_bin)
+        .context("Failed to generate lockfile metadata")?;
 
     Ok(Context {
         checksum: Some(checksum),
+        metadata: Some(metadata),
         ..context
     })
 }
 
+/// The version of the lockfile format, which is bumped whenever lockfiles generated by earlier
+/// versions of cargo-bazel can no longer be read.
+const LOCKFILE_FORMAT_VERSION: u32 = 1;
+
+/// A record of the tools and inputs a lockfile was generated with, which explains why a lockfile
+/// is out of date. It's left out of the lockfile's [Digest] and holds no timestamps so repinning
+/// the same inputs always produces the same lockfile.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub(crate) struct LockfileMetadata {
+    /// The [LOCKFILE_FORMAT_VERSION] of the lockfile.
+    #[serde(default = "default_format_version")]
+    pub(crate) format_version: u32,
+
+    /// The version of cargo-bazel which generated the lockfile.
+    pub(crate) cargo_bazel_version: String,
+
+    /// The output of `cargo --version` for the Cargo binary used to resolve dependencies.
+    pub(crate) cargo_version: String,
+
+    /// A sha256 digest of the workspace config.
+    pub(crate) config_digest: String,
+
+    /// A sha256 digest of the splicing inputs, i.e. the Cargo manifests and configs.
+    pub(crate) splicing_inputs_digest: String,
+}
+
+impl LockfileMetadata {
+    pub(crate) fn new(
+        config: &Config,
+        splicing_manifest: &SplicingManifest,
+        cargo_bin: &Cargo,
+    ) -> Result<Self> {
+        let splicing_metadata = SplicingMetadata::try_from((*splicing_manifest).clone())?;
+        Ok(Self::compute(
+            config,
+            &splicing_metadata,
+            env!("CARGO_PKG_VERSION"),
+            &cargo_bin.full_version()?,
+        ))
+    }
+
+    fn compute(
+        config: &Config,
+        splicing_metadata: &SplicingMetadata,
+        cargo_bazel_version: &str,
+        cargo_version: &str,
+    ) -> Self {
+        let sha256 = |data: String| Sha256::digest(data.as_bytes()).encode_hex::<String>();
+        Self {
+            format_version: LOCKFILE_FORMAT_VERSION,
+            cargo_bazel_version: cargo_bazel_version.to_owned(),
+            cargo_version: cargo_version.to_owned(),
+            config_digest: sha256(serde_json::to_string(config).unwrap()),
+            splicing_inputs_digest: sha256(serde_json::to_string(splicing_metadata).unwrap()),
+        }
+    }
+
+    /// Check that a lockfile generated with this metadata is up to date with the `expected`
+    /// metadata of the current inputs, describing each difference otherwise.
+    pub(crate) fn validate(&self, expected: &Self) -> Result<()> {
+        let mut issues = Vec::new();
+        issues.extend(self.cargo_bazel_version_issue(&expected.cargo_bazel_version));
+        if self.cargo_version != expected.cargo_version {
+            issues.push(format!(
+                "The lockfile was generated with `{}` but `{}` is in use. Please repin with this cargo.",
+                self.cargo_version, expected.cargo_version,
+            ));
+        }
+        if self.config_digest != expected.config_digest {
+            issues.push(
+                "The crate_universe configuration changed since the lockfile was generated."
+                    .to_owned(),
+            );
+        }
+        if self.splicing_inputs_digest != expected.splicing_inputs_digest {
+            issues.push(
+                "The Cargo manifests or configs changed since the lockfile was generated."
+                    .to_owned(),
+            );
+        }
+
+        if !issues.is_empty() {
+            bail!("The lockfile is out of date:\n  {}", issues.join("\n  "));
+        }
+        Ok(())
+    }
+
+    /// Check that a lockfile generated with this metadata can be read by the running version of
+    /// cargo-bazel, which is the case unless the lockfile format changed. Lockfiles generated by
+    /// other versions of cargo-bazel in the same format only cause a warning.
+    pub(crate) fn validate_version(&self) -> Result<()> {
+        self.validate_format_version(LOCKFILE_FORMAT_VERSION)?;
+        if let Some(issue) = self.cargo_bazel_version_issue(env!("CARGO_PKG_VERSION")) {
+            tracing::warn!("{issue}");
+        }
+        Ok(())
+    }
+
+    fn validate_format_version(&self, format_version: u32) -> Result<()> {
+        if self.format_version != format_version {
+            bail!(
+                "The lockfile was generated by cargo-bazel {} in format version {} but version {format_version} is required. Please repin with cargo-bazel {}.",
+                self.cargo_bazel_version,
+                self.format_version,
+                env!("CARGO_PKG_VERSION"),
+            );
+        }
+        Ok(())
+    }
+
+    fn cargo_bazel_version_issue(&self, version: &str) -> Option<String> {
+        (self.cargo_bazel_version != version).then(|| {
+            format!(
+                "The lockfile was generated by cargo-bazel {} but cargo-bazel {version} is in use. Please repin with version {version}.",
+                self.cargo_bazel_version,
+            )
+        })
+    }
+}
+
+/// Lockfiles without a format version were generated in the first format.
+fn default_format_version() -> u32 {
+    1
+}
+
 /// Write a [crate::context::Context] to disk
 pub(crate) fn write_lockfile(lockfile: Context, path: &Path, dry_run: bool) -> Result<()> {
     let content = serde_json::to_string_pretty(&lockfile)?;
@@ -69,21 +198,22 @@ impl Digest {
         let rustc_version = Self::bin_version(rustc_bin)?;
         let cargo_bazel_version = env!("CARGO_PKG_VERSION");
 
-        // Ensure the checksum of a digest is not present before computing one
-        Ok(match context.checksum {
-            Some(_) => Self::compute(
-                &Context {
-                    checksum: None,
-                    ..context.clone()
-                },
+        // Ensure the checksum of a digest and the metadata are not present before computing one
+        Ok(match (&context.checksum, &context.metadata) {
+            (None, None) => Self::compute(
+                context,
                 config,
                 &splicing_metadata,
                 cargo_bazel_version,
                 &cargo_version,
                 &rustc_version,
             ),
-            None => Self::compute(
-                context,
+            _ => Self::compute(
+                &Context {
+                    checksum: None,
+                    metadata: None,
+                    ..context.clone()
+                },
                 config,
                 &splicing_metadata,
                 cargo_bazel_version,
@@ -115,6 +245,7 @@ impl Digest {
         // always None. This then allows us to have this method not return a
         // Result.
         debug_assert!(context.checksum.is_none());
+        debug_assert!(context.metadata.is_none());
 
         let mut hasher = Sha256::new();
 
@@ -222,7 +353,9 @@ impl PartialEq<String> for Digest {
 #[cfg(test)]
 mod test {
     use crate::config::{CrateAnnotations, CrateNameAndVersionReq};
+    use crate::metadata::DuplicateCrates;
     use crate::splicing::cargo_config::{AdditionalRegistry, CargoConfig, Registry};
+    use crate::splicing::RegistryMirrors;
     use crate::utils::target_triple::TargetTriple;
 
     use super::*;
@@ -309,6 +442,8 @@ mod test {
             )]),
             manifests: BTreeMap::new(),
             cargo_config: None,
+            mirrors: RegistryMirrors::default(),
+            duplicates: DuplicateCrates::default(),
         };
 
         let digest = Digest::compute(
@@ -338,6 +473,7 @@ mod test {
                         index: "https://artprod.mycompany/artifactory/git/cargo-remote.git"
                             .to_owned(),
                         token: None,
+                        credential_provider: None,
                     },
                 ),
                 (
@@ -345,14 +481,18 @@ mod test {
                     AdditionalRegistry {
                         index: "https://github.com/rust-lang/crates.io-index".to_owned(),
                         token: None,
+                        credential_provider: None,
                     },
                 ),
             ]),
             registry: Registry {
                 default: "art-crates-remote".to_owned(),
                 token: None,
+                credential_provider: None,
+                global_credential_providers: Vec::new(),
             },
             source: BTreeMap::new(),
+            credential_alias: BTreeMap::new(),
         };
 
         let splicing_metadata = SplicingMetadata {
@@ -374,4 +514,85 @@ mod test {
             digest,
         );
     }
+
+    #[test]
+    fn lockfile_metadata() {
+        let config = Config::default();
+        let splicing_metadata = SplicingMetadata::default();
+        let cargo_version = "cargo 1.57.0 (b2e52d7ca 2021-10-21)";
+
+        let metadata =
+            LockfileMetadata::compute(&config, &splicing_metadata, "0.1.0", cargo_version);
+        assert_eq!(
+            metadata,
+            LockfileMetadata::compute(&config, &splicing_metadata, "0.1.0", cargo_version)
+        );
+        metadata.validate(&metadata).unwrap();
+
+        let expected = LockfileMetadata::compute(
+            &Config {
+                generate_binaries: true,
+                ..Config::default()
+            },
+            &splicing_metadata,
+            "0.2.0",
+            cargo_version,
+        );
+        assert_eq!(
+            metadata.validate(&expected).unwrap_err().to_string(),
+            concat!(
+                "The lockfile is out of date:",
+                "\n  The lockfile was generated by cargo-bazel 0.1.0 but cargo-bazel 0.2.0 is in use. Please repin with version 0.2.0.",
+                "\n  The crate_universe configuration changed since the lockfile was generated.",
+            )
+        );
+
+        let expected = LockfileMetadata::compute(
+            &config,
+            &splicing_metadata,
+            "0.1.0",
+            "cargo 1.58.0 (f01b232bc 2022-01-19)",
+        );
+        assert_eq!(
+            metadata.validate(&expected).unwrap_err().to_string(),
+            concat!(
+                "The lockfile is out of date:",
+                "\n  The lockfile was generated with `cargo 1.57.0 (b2e52d7ca 2021-10-21)` but `cargo 1.58.0 (f01b232bc 2022-01-19)` is in use. Please repin with this cargo.",
+            )
+        );
+    }
+
+    #[test]
+    fn lockfile_metadata_format_version() {
+        let metadata = LockfileMetadata::compute(
+            &Config::default(),
+            &SplicingMetadata::default(),
+            "0.1.0",
+            "cargo 1.57.0 (b2e52d7ca 2021-10-21)",
+        );
+
+        // Lockfiles of other cargo-bazel versions are read as long as the format is the same.
+        metadata.validate_version().unwrap();
+        metadata
+            .validate_format_version(LOCKFILE_FORMAT_VERSION)
+            .unwrap();
+        assert_eq!(
+            metadata
+                .validate_format_version(LOCKFILE_FORMAT_VERSION + 1)
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "The lockfile was generated by cargo-bazel 0.1.0 in format version {} but version {} is required. Please repin with cargo-bazel {}.",
+                LOCKFILE_FORMAT_VERSION,
+                LOCKFILE_FORMAT_VERSION + 1,
+                env!("CARGO_PKG_VERSION"),
+            )
+        );
+
+        let metadata: LockfileMetadata = serde_json::from_str(
+            r#"{"cargo_bazel_version": "0.1.0", "cargo_version": "cargo", "config_digest": "", "splicing_inputs_digest": ""}"#,
+        )
+        .unwrap();
+        assert_eq!(metadata.format_version, 1);
+    }
 }
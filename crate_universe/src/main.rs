@@ -29,5 +29,27 @@ fn main() -> cli::Result<()> {
             cli::init_logging("Render", verbose_logging);
             cli::render(opt)
         }
+        cli::Options::Explain(opt) => {
+            cli::init_logging("Explain", verbose_logging);
+            cli::explain(opt)
+        }
+        cli::Options::Verify(opt) => {
+            cli::init_logging("Verify", verbose_logging);
+            cli::verify(opt)
+        }
+        cli::Options::Sbom(opt) => {
+            cli::init_logging("Sbom", verbose_logging);
+            cli::sbom(opt)
+        }
+        cli::Options::ExportLockfile(opt) => {
+            cli::init_logging("ExportLockfile", verbose_logging);
+            cli::export_lockfile(opt)
+        }
+        cli::Options::Features(opt) => {
+            cli::init_logging("Features", verbose_logging);
+            cli::features(opt)
+        }
+        // Logging is written to stdout, which is reserved for the credential helper protocol.
+        cli::Options::CredentialHelper(opt) => cli::credential_helper(opt),
     }
 }
@@ -1,11 +1,20 @@
 //! Tools for gathering various kinds of metadata (Cargo.lock, Cargo metadata, Crate Index info).
 
+mod advisories;
 mod cargo_bin;
+mod cargo_profile;
 mod cargo_tree_resolver;
+mod crate_cache;
 mod dependency;
+mod duplicates;
+mod feature_report;
+mod links;
 mod metadata_annotation;
+mod vendor_checksums;
+mod vendor_pruning;
 mod workspace_discoverer;
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -15,12 +24,20 @@ use anyhow::{bail, Context, Result};
 use camino::Utf8Path;
 use cargo_lock::Lockfile as CargoLockfile;
 use cargo_metadata::Metadata as CargoMetadata;
-use tracing::debug;
+use tracing::{debug, info};
 
+pub(crate) use self::advisories::*;
 pub(crate) use self::cargo_bin::*;
+pub(crate) use self::cargo_profile::*;
 pub(crate) use self::cargo_tree_resolver::*;
+pub(crate) use self::crate_cache::*;
 pub(crate) use self::dependency::*;
+pub(crate) use self::duplicates::*;
+pub(crate) use self::feature_report::*;
+pub(crate) use self::links::*;
 pub(crate) use self::metadata_annotation::*;
+pub(crate) use self::vendor_checksums::*;
+pub(crate) use self::vendor_pruning::*;
 pub(crate) use self::workspace_discoverer::*;
 
 // TODO: This should also return a set of [crate-index::IndexConfig]s for packages in metadata.packages
@@ -75,10 +92,10 @@ impl MetadataGenerator for Generator {
             cargo_lock::Lockfile::load(lock_path)?
         };
 
-        let metadata = self
+        let command = self
             .cargo_bin
-            .metadata_command_with_options(manifest_path.as_ref(), vec!["--locked".to_owned()])?
-            .exec()?;
+            .metadata_command_with_options(manifest_path.as_ref(), vec!["--locked".to_owned()])?;
+        let metadata = self.cargo_bin.metadata(&command)?;
 
         Ok((metadata, lockfile))
     }
@@ -101,6 +118,12 @@ pub enum CargoUpdateRequest {
         /// If set, the `--precise` value that pairs with `--package`.
         version: Option<String>,
     },
+
+    /// Translates to `cargo update --package foo --package bar` for a comma-separated list of crates.
+    Packages {
+        /// The names of the crates used with `--package`.
+        names: Vec<String>,
+    },
 }
 
 impl FromStr for CargoUpdateRequest {
@@ -117,6 +140,20 @@ impl FromStr for CargoUpdateRequest {
             return Ok(Self::Workspace);
         }
 
+        if s.contains(',') {
+            if s.contains('=') {
+                bail!("A `--precise` version can only be requested when updating a single package: `{s}`");
+            }
+            return Ok(Self::Packages {
+                names: s
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_owned)
+                    .collect(),
+            });
+        }
+
         let mut split = s.splitn(2, '=');
         Ok(Self::Package {
             name: split.next().map(|s| s.to_owned()).unwrap(),
@@ -141,16 +178,26 @@ impl CargoUpdateRequest {
 
                 update_args
             }
+            CargoUpdateRequest::Packages { names } => names
+                .iter()
+                .flat_map(|name| ["--package".to_owned(), name.clone()])
+                .collect(),
         }
     }
 
+    /// Whether or not the request is limited to specific packages, leaving the pins of
+    /// all other packages untouched unless an update of the requested ones forces them.
+    fn is_targeted(&self) -> bool {
+        matches!(self, Self::Package { .. } | Self::Packages { .. })
+    }
+
     /// Calls `cargo update` with arguments specific to the state of the current variant.
     pub(crate) fn update(&self, manifest: &Path, cargo_bin: &Cargo) -> Result<()> {
         let manifest_dir = manifest.parent().unwrap();
 
         // Simply invoke `cargo update`
-        let output = cargo_bin
-            .command()?
+        let mut command = cargo_bin.command()?;
+        command
             // Cargo detects config files based on `pwd` when running so
             // to ensure user provided Cargo config files are used, it's
             // critical to set the working directory to the manifest dir.
@@ -158,8 +205,9 @@ impl CargoUpdateRequest {
             .arg("update")
             .arg("--manifest-path")
             .arg(manifest)
-            .args(self.get_update_args())
-            .output()
+            .args(self.get_update_args());
+        let output = cargo_bin
+            .output_with_retries(&mut command)
             .with_context(|| {
                 format!(
                     "Error running cargo to update packages for manifest '{}'",
@@ -215,22 +263,41 @@ impl LockGenerator {
             fs::copy(lock, &generated_lockfile_path)?;
 
             if let Some(request) = update_request {
+                let previous = request
+                    .is_targeted()
+                    .then(|| CargoLockfile::load(&generated_lockfile_path))
+                    .transpose()
+                    .context("Failed to load the existing lockfile")?;
+
                 request.update(manifest_path.as_std_path(), &self.cargo_bin)?;
+
+                if let Some(previous) = previous {
+                    let updated = CargoLockfile::load(&generated_lockfile_path)
+                        .context("Failed to load the updated lockfile")?;
+                    for change in describe_lockfile_changes(&previous, &updated) {
+                        info!("{}", change);
+                    }
+                }
             }
 
+            // Restore cached crates before `cargo fetch` downloads them
+            self.cargo_bin
+                .restore_crates(generated_lockfile_path.as_std_path())?;
+
             // Ensure the Cargo cache is up to date to simulate the behavior
             // of having just generated a new one
-            let output = self
-                .cargo_bin
-                .command()?
+            let mut command = self.cargo_bin.command()?;
+            command
                 // Cargo detects config files based on `pwd` when running so
                 // to ensure user provided Cargo config files are used, it's
                 // critical to set the working directory to the manifest dir.
                 .current_dir(manifest_dir.as_std_path())
                 .arg("fetch")
                 .arg("--manifest-path")
-                .arg(manifest_path.as_std_path())
-                .output()
+                .arg(manifest_path.as_std_path());
+            let output = self
+                .cargo_bin
+                .output_with_retries(&mut command)
                 .context(format!(
                     "Error running cargo to fetch crates '{}'",
                     manifest_path
@@ -247,17 +314,18 @@ impl LockGenerator {
         } else {
             debug!("Generating new lockfile");
             // Simply invoke `cargo generate-lockfile`
-            let output = self
-                .cargo_bin
-                .command()?
+            let mut command = self.cargo_bin.command()?;
+            command
                 // Cargo detects config files based on `pwd` when running so
                 // to ensure user provided Cargo config files are used, it's
                 // critical to set the working directory to the manifest dir.
                 .current_dir(manifest_dir.as_std_path())
                 .arg("generate-lockfile")
                 .arg("--manifest-path")
-                .arg(manifest_path.as_std_path())
-                .output()
+                .arg(manifest_path.as_std_path());
+            let output = self
+                .cargo_bin
+                .output_with_retries(&mut command)
                 .context(format!(
                     "Error running cargo to generate lockfile '{}'",
                     manifest_path
@@ -268,6 +336,11 @@ impl LockGenerator {
                 eprintln!("{}", String::from_utf8_lossy(&output.stderr));
                 bail!(format!("Failed to generate lockfile: {}", output.status))
             }
+
+            // Generating a lockfile only fetches the index, so cached crates can be restored
+            // before any later invocation of cargo downloads them
+            self.cargo_bin
+                .restore_crates(generated_lockfile_path.as_std_path())?;
         }
 
         cargo_lock::Lockfile::load(&generated_lockfile_path).context(format!(
@@ -277,6 +350,45 @@ impl LockGenerator {
     }
 }
 
+/// Describe each package which was added, removed, or changed versions between two lockfiles.
+fn describe_lockfile_changes(before: &CargoLockfile, after: &CargoLockfile) -> Vec<String> {
+    let versions = |lockfile: &CargoLockfile| {
+        let mut versions: BTreeMap<String, BTreeSet<semver::Version>> = BTreeMap::new();
+        for package in &lockfile.packages {
+            versions
+                .entry(package.name.to_string())
+                .or_default()
+                .insert(package.version.clone());
+        }
+        versions
+    };
+    let before = versions(before);
+    let after = versions(after);
+
+    let join = |versions: &BTreeSet<semver::Version>| {
+        versions
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    before
+        .keys()
+        .chain(after.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|name| match (before.get(name), after.get(name)) {
+            (Some(old), Some(new)) if old != new => {
+                Some(format!("Updated {name} {} -> {}", join(old), join(new)))
+            }
+            (Some(old), None) => Some(format!("Removed {name} {}", join(old))),
+            (None, Some(new)) => Some(format!("Added {name} {}", join(new))),
+            _ => None,
+        })
+        .collect()
+}
+
 /// A generator which runs `cargo vendor` on a given manifest
 pub(crate) struct VendorGenerator {
     /// The path to a `cargo` binary
@@ -299,9 +411,8 @@ impl VendorGenerator {
         let manifest_dir = manifest_path.parent().unwrap();
 
         // Simply invoke `cargo generate-lockfile`
-        let output = self
-            .cargo_bin
-            .command()?
+        let mut command = self.cargo_bin.command()?;
+        command
             // Cargo detects config files based on `pwd` when running so
             // to ensure user provided Cargo config files are used, it's
             // critical to set the working directory to the manifest dir.
@@ -312,8 +423,10 @@ impl VendorGenerator {
             .arg("--locked")
             .arg("--versioned-dirs")
             .arg(output_dir)
-            .env("RUSTC", &self.rustc_bin)
-            .output()
+            .env("RUSTC", &self.rustc_bin);
+        let output = self
+            .cargo_bin
+            .output_with_retries(&mut command)
             .with_context(|| {
                 format!(
                     "Error running cargo to vendor sources for manifest '{}'",
@@ -428,4 +541,47 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn deserialize_cargo_update_request_for_packages() {
+        let request = CargoUpdateRequest::from_str("cargo-bazel, serde@1.0.0").unwrap();
+
+        assert_eq!(
+            request,
+            CargoUpdateRequest::Packages {
+                names: vec!["cargo-bazel".to_owned(), "serde@1.0.0".to_owned()],
+            }
+        );
+        assert_eq!(
+            request.get_update_args(),
+            vec!["--package", "cargo-bazel", "--package", "serde@1.0.0"]
+        );
+
+        assert!(CargoUpdateRequest::from_str("cargo-bazel,serde=1.0.0").is_err());
+    }
+
+    #[test]
+    fn lockfile_changes() {
+        let lockfile = |packages: &[(&str, &str)]| {
+            let mut content = "version = 3\n".to_owned();
+            for (name, version) in packages {
+                content.push_str(&format!(
+                    "\n[[package]]\nname = \"{name}\"\nversion = \"{version}\"\n"
+                ));
+            }
+            CargoLockfile::from_str(&content).unwrap()
+        };
+
+        let before = lockfile(&[("anyhow", "1.0.0"), ("libc", "0.2.0"), ("serde", "1.0.0")]);
+        let after = lockfile(&[("anyhow", "1.0.0"), ("serde", "1.0.1"), ("toml", "0.8.0")]);
+
+        assert_eq!(
+            describe_lockfile_changes(&before, &after),
+            vec![
+                "Removed libc 0.2.0".to_owned(),
+                "Updated serde 1.0.0 -> 1.0.1".to_owned(),
+                "Added toml 0.8.0".to_owned(),
+            ]
+        );
+    }
 }
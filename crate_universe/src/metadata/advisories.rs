@@ -0,0 +1,378 @@
+//! Auditing of resolved crates against the [RustSec advisory database](https://github.com/rustsec/advisory-db).
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CrateId;
+use crate::metadata::{Annotations, SourceAnnotation};
+
+/// The front matter of an advisory. See
+/// <https://github.com/rustsec/advisory-db/blob/main/CONTRIBUTING.md#advisory-format>.
+#[derive(Debug, Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMetadata,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdvisoryMetadata {
+    id: String,
+    package: String,
+    /// Advisories written in the legacy `toml` format carry their title here.
+    title: Option<String>,
+    url: Option<String>,
+    informational: Option<String>,
+    withdrawn: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AdvisoryVersions {
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+}
+
+/// A security advisory for a crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Advisory {
+    id: String,
+    package: String,
+    title: String,
+    url: Option<String>,
+    /// The kind of informational advisory (e.g. `unmaintained`) if this is not a vulnerability.
+    informational: Option<String>,
+    patched: Vec<VersionReq>,
+    unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+    /// Parse an advisory from either the markdown (`toml` front matter) or legacy `toml`
+    /// format. Withdrawn advisories are skipped.
+    fn parse(content: &str) -> Result<Option<Self>> {
+        let (front_matter, body) = match content.strip_prefix("```toml") {
+            Some(rest) => match rest.split_once("\n```") {
+                Some(parts) => parts,
+                None => bail!("The `toml` front matter of the advisory is not terminated"),
+            },
+            None => (content, ""),
+        };
+
+        let file: AdvisoryFile =
+            toml::from_str(front_matter).context("Failed to parse advisory front matter")?;
+        if file.advisory.withdrawn.is_some() {
+            return Ok(None);
+        }
+
+        let parse_reqs = |reqs: &[String]| -> Result<Vec<VersionReq>> {
+            reqs.iter()
+                .map(|req| {
+                    VersionReq::parse(req).with_context(|| {
+                        format!(
+                            "Invalid version requirement `{req}` in {}",
+                            file.advisory.id
+                        )
+                    })
+                })
+                .collect()
+        };
+
+        let title = body
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(|title| title.trim().to_owned())
+            .or_else(|| file.advisory.title.clone())
+            .unwrap_or_default();
+
+        Ok(Some(Self {
+            patched: parse_reqs(&file.versions.patched)?,
+            unaffected: parse_reqs(&file.versions.unaffected)?,
+            id: file.advisory.id,
+            package: file.advisory.package,
+            title,
+            url: file.advisory.url,
+            informational: file.advisory.informational,
+        }))
+    }
+
+    /// Whether or not `version` is neither patched nor unaffected by the advisory.
+    fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(self.unaffected.iter())
+            .any(|req| req.matches(version))
+    }
+}
+
+/// A collection of advisories keyed by the name of the crate they apply to.
+#[derive(Debug, Default)]
+pub(crate) struct AdvisoryDatabase {
+    advisories: BTreeMap<String, Vec<Advisory>>,
+}
+
+impl AdvisoryDatabase {
+    /// Load a checkout of the advisory database, where advisories are found at
+    /// `crates/{package}/{id}.md`.
+    pub(crate) fn try_from_path(path: &Path) -> Result<Self> {
+        let crates_dir = path.join("crates");
+        let entries = fs::read_dir(&crates_dir).with_context(|| {
+            format!(
+                "Failed to read advisory database at `{}`",
+                crates_dir.display()
+            )
+        })?;
+
+        let mut database = Self::default();
+        for entry in entries {
+            let package_dir = entry?.path();
+            if !package_dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&package_dir)? {
+                let advisory_path = entry?.path();
+                if !matches!(
+                    advisory_path.extension().and_then(|ext| ext.to_str()),
+                    Some("md" | "toml")
+                ) {
+                    continue;
+                }
+                let content = fs::read_to_string(&advisory_path)?;
+                let advisory = Advisory::parse(&content).with_context(|| {
+                    format!("Failed to parse advisory `{}`", advisory_path.display())
+                })?;
+                if let Some(advisory) = advisory {
+                    database.insert(advisory);
+                }
+            }
+        }
+
+        Ok(database)
+    }
+
+    fn insert(&mut self, advisory: Advisory) {
+        self.advisories
+            .entry(advisory.package.clone())
+            .or_default()
+            .push(advisory);
+    }
+
+    /// Find the advisories affecting the registry crates of `annotations`.
+    pub(crate) fn audit(&self, annotations: &Annotations) -> AdvisoryReport {
+        let ignored_advisories: BTreeMap<&CrateId, &BTreeSet<String>> = annotations
+            .pairred_extras
+            .iter()
+            .filter_map(|(id, extra)| Some((id, extra.crate_extra.ignore_advisories.as_ref()?)))
+            .collect();
+
+        let mut report = AdvisoryReport::default();
+        for (package_id, package) in &annotations.metadata.packages {
+            // Advisories only apply to crates published to a registry.
+            if !matches!(
+                annotations.lockfile.crates.get(package_id),
+                Some(SourceAnnotation::Http { .. })
+            ) {
+                continue;
+            }
+
+            let id = CrateId::from(package);
+            let advisories = self
+                .advisories
+                .get(&id.name)
+                .into_iter()
+                .flatten()
+                .filter(|advisory| advisory.affects(&id.version));
+            for advisory in advisories {
+                let finding = AdvisoryFinding {
+                    krate: id.clone(),
+                    id: advisory.id.clone(),
+                    title: advisory.title.clone(),
+                    url: advisory.url.clone(),
+                    informational: advisory.informational.clone(),
+                    patched: advisory.patched.iter().map(ToString::to_string).collect(),
+                };
+                let is_ignored = ignored_advisories
+                    .get(&id)
+                    .is_some_and(|ignored| ignored.contains(&advisory.id));
+                if is_ignored {
+                    report.ignored.push(finding);
+                } else {
+                    report.affected.push(finding);
+                }
+            }
+        }
+
+        report
+    }
+}
+
+/// An advisory affecting the resolved version of a crate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct AdvisoryFinding {
+    #[serde(rename = "crate")]
+    krate: CrateId,
+    id: String,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    informational: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    patched: Vec<String>,
+}
+
+/// The advisories affecting the crates of a dependency graph.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct AdvisoryReport {
+    /// Advisories which affect the resolved crates.
+    affected: Vec<AdvisoryFinding>,
+
+    /// Advisories which affect the resolved crates but are ignored by their annotations.
+    ignored: Vec<AdvisoryFinding>,
+}
+
+impl AdvisoryReport {
+    /// Describe each advisory which affects a crate and is not ignored.
+    pub(crate) fn warnings(&self) -> Vec<String> {
+        self.affected
+            .iter()
+            .map(|finding| {
+                let kind = match &finding.informational {
+                    Some(informational) => format!("{informational} advisory"),
+                    None => "advisory".to_owned(),
+                };
+                let mut warning = format!(
+                    "{} is affected by {kind} {}: {}",
+                    finding.krate, finding.id, finding.title
+                );
+                if !finding.patched.is_empty() {
+                    warning.push_str(&format!(
+                        " (patched versions: `{}`)",
+                        finding.patched.join("`, `")
+                    ));
+                }
+                warning
+            })
+            .collect()
+    }
+
+    /// Write the report to `path` as `json`.
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content + "\n")
+            .with_context(|| format!("Failed to write advisory report `{}`", path.display()))
+    }
+}
+
+/// Audit the crates of `annotations` against the advisory database at `advisory_db`, writing
+/// the full report to `report_path` if provided. A warning is returned for each crate affected
+/// by an advisory which isn't ignored.
+pub(crate) fn audit_advisories(
+    advisory_db: &Path,
+    report_path: Option<&Path>,
+    annotations: &Annotations,
+) -> Result<Vec<String>> {
+    let report = AdvisoryDatabase::try_from_path(advisory_db)?.audit(annotations);
+    if let Some(path) = report_path {
+        report.write(path)?;
+    }
+
+    Ok(report.warnings())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use camino::Utf8Path;
+
+    use crate::config::{Config, CrateAnnotations, CrateNameAndVersionReq};
+    use crate::test;
+
+    const ADVISORY: &str = indoc::indoc! {r#"
+        ```toml
+        [advisory]
+        id = "RUSTSEC-2099-0001"
+        package = "bitflags"
+        date = "2099-01-01"
+        url = "https://example.com/bitflags/issues/1"
+
+        [versions]
+        patched = [">= 1.3.3"]
+        unaffected = ["< 1.0.0"]
+        ```
+
+        # Flags may overlap
+
+        A description of the advisory.
+    "#};
+
+    fn mock_annotations(crate_extra: Option<CrateAnnotations>) -> Annotations {
+        let mut config = Config::default();
+        if let Some(crate_extra) = crate_extra {
+            config.annotations.insert(
+                CrateNameAndVersionReq::new("bitflags".to_owned(), "1.3.2".parse().unwrap()),
+                crate_extra,
+            );
+        }
+
+        Annotations::new(
+            test::metadata::common(),
+            test::lockfile::common(),
+            config,
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_advisory() {
+        let advisory = Advisory::parse(ADVISORY).unwrap().unwrap();
+
+        assert_eq!(advisory.id, "RUSTSEC-2099-0001");
+        assert_eq!(advisory.package, "bitflags");
+        assert_eq!(advisory.title, "Flags may overlap");
+        assert!(advisory.affects(&Version::new(1, 3, 2)));
+        assert!(!advisory.affects(&Version::new(1, 3, 3)));
+        assert!(!advisory.affects(&Version::new(0, 9, 0)));
+    }
+
+    #[test]
+    fn parse_withdrawn_advisory() {
+        let advisory = ADVISORY.replace(
+            "date = \"2099-01-01\"",
+            "date = \"2099-01-01\"\nwithdrawn = \"2099-02-01\"",
+        );
+
+        assert_eq!(Advisory::parse(&advisory).unwrap(), None);
+    }
+
+    #[test]
+    fn audit_affected_crates() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_dir = dir.path().join("crates").join("bitflags");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(package_dir.join("RUSTSEC-2099-0001.md"), ADVISORY).unwrap();
+        let database = AdvisoryDatabase::try_from_path(dir.path()).unwrap();
+
+        let report = database.audit(&mock_annotations(None));
+        assert!(report.ignored.is_empty());
+        assert_eq!(
+            report.warnings(),
+            vec!["bitflags 1.3.2 is affected by advisory RUSTSEC-2099-0001: Flags may overlap (patched versions: `>=1.3.3`)".to_owned()]
+        );
+
+        let report = database.audit(&mock_annotations(Some(CrateAnnotations {
+            ignore_advisories: Some(BTreeSet::from(["RUSTSEC-2099-0001".to_owned()])),
+            ..CrateAnnotations::default()
+        })));
+        assert!(report.affected.is_empty());
+        assert_eq!(report.ignored.len(), 1);
+    }
+}
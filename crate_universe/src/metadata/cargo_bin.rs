@@ -1,16 +1,25 @@
 //! Tools for invoking Cargo subcommands.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
 use std::ffi::OsString;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::sync::{Arc, Mutex};
+use std::process::{Command, Output};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
-use cargo_metadata::MetadataCommand;
+use cargo_lock::Lockfile;
+use cargo_metadata::{Metadata, MetadataCommand};
 use semver::Version;
 
 use crate::lockfile::Digest;
+use crate::metadata::CrateCache;
+use crate::utils::file_lock::FileLock;
+use crate::utils::symlink::{remove_symlink, symlink};
 
 /// Cargo encapsulates a path to a `cargo` binary.
 /// Any invocations of `cargo` (either as a `std::process::Command` or via `cargo_metadata`) should
@@ -21,6 +30,141 @@ pub(crate) struct Cargo {
     rustc_path: PathBuf,
     full_version: Arc<Mutex<Option<String>>>,
     cargo_home: Option<PathBuf>,
+    network_retries: NetworkRetries,
+    network_slots: Arc<NetworkSlots>,
+    crate_cache: Option<CrateCache>,
+    registry_cache: Option<Arc<RegistryCache>>,
+}
+
+/// The name of the file in a registry cache directory which is locked while cargo uses it.
+const REGISTRY_CACHE_LOCK: &str = ".cargo-bazel-lock";
+
+/// A registry index cache shared with other processes, see [Cargo::use_registry_cache].
+#[derive(Debug)]
+struct RegistryCache {
+    dir: PathBuf,
+
+    /// The number of invocations of cargo currently using the cache and, while there are any,
+    /// the lock held on it. Invocations of this process may use the cache concurrently, as cargo
+    /// locks the index of the `CARGO_HOME` they share.
+    users: Mutex<(usize, Option<FileLock>)>,
+}
+
+/// Keeps the registry cache locked for an invocation of cargo, see [Cargo::lock_registry_cache].
+struct RegistryCacheGuard<'a>(&'a RegistryCache);
+
+impl Drop for RegistryCacheGuard<'_> {
+    fn drop(&mut self) {
+        let mut users = self.0.users.lock().unwrap();
+        users.0 -= 1;
+        if users.0 == 0 {
+            users.1 = None;
+        }
+    }
+}
+
+/// Messages of curl and git in the output of cargo which indicate that it failed due to a
+/// transport error. Cargo's own messages, e.g. `failed to download`, are left out as they also
+/// wrap failures which retrying doesn't fix, like checksum mismatches and missing crates.
+const NETWORK_ERROR_MARKERS: &[&str] = &[
+    "Couldn't resolve host",
+    "Could not resolve host",
+    "Couldn't connect to server",
+    "Failed to connect to",
+    "Connection reset",
+    "Connection refused",
+    "Timeout was reached",
+    "Operation timed out",
+    "operation timed out",
+    "SSL connect error",
+    "Server returned nothing",
+    "Failure when receiving data from the peer",
+    "Failed sending data to the peer",
+    "Transferred a partial file",
+];
+
+/// The number of invocations of cargo which may access the network at the same time, see
+/// [Cargo::output_with_retries]. The invocations of a repin all download from the registries of
+/// the same workspace, so this bounds the concurrent connections to each of their hosts.
+const MAX_CONCURRENT_NETWORK_INVOCATIONS: usize = 4;
+
+/// A counting semaphore limiting the concurrent invocations of cargo which access the network.
+#[derive(Debug)]
+struct NetworkSlots {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A slot taken from [NetworkSlots], which is returned when it's dropped.
+struct NetworkSlot<'a>(&'a NetworkSlots);
+
+impl NetworkSlots {
+    fn new(slots: usize) -> Self {
+        Self {
+            available: Mutex::new(slots),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Take a slot, blocking until one is available.
+    fn acquire(&self) -> NetworkSlot<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+        NetworkSlot(self)
+    }
+}
+
+impl Drop for NetworkSlot<'_> {
+    fn drop(&mut self) {
+        *self.0.available.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// The longest delay between two attempts of a cargo invocation.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How cargo invocations which fail due to network errors are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NetworkRetries {
+    /// The number of times a download which fails due to a network error is retried. Cargo
+    /// retries each download itself, so invocations are only rerun for the retries cargo
+    /// wasn't configured to make, see [NetworkRetries::attempts].
+    pub(crate) retries: u32,
+
+    /// The delay before the first retry, which is doubled for every subsequent one.
+    pub(crate) backoff: Duration,
+}
+
+impl Default for NetworkRetries {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl NetworkRetries {
+    /// The delay before the retry following `attempt` (counting from zero).
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_BACKOFF)
+    }
+
+    /// The number of times an invocation is attempted when cargo retries each download
+    /// `cargo_retries` times, so that the attempts of all invocations together make at least
+    /// `retries` retries but not a multiple of them.
+    fn attempts(&self, cargo_retries: u32) -> u32 {
+        self.retries
+            .saturating_add(1)
+            .div_ceil(cargo_retries.saturating_add(1))
+            .max(1)
+    }
 }
 
 impl Cargo {
@@ -30,9 +174,26 @@ impl Cargo {
             rustc_path: rustc,
             full_version: Arc::new(Mutex::new(None)),
             cargo_home: None,
+            network_retries: NetworkRetries::default(),
+            network_slots: Arc::new(NetworkSlots::new(MAX_CONCURRENT_NETWORK_INVOCATIONS)),
+            crate_cache: None,
+            registry_cache: None,
         }
     }
 
+    /// Use `network_retries` for invocations of cargo which access the network.
+    pub(crate) fn with_network_retries(mut self, network_retries: NetworkRetries) -> Self {
+        self.network_retries = network_retries;
+        self
+    }
+
+    /// Seed this cargo's `CARGO_HOME` with the archives held by `crate_cache` before
+    /// invocations which may download crates, see [Cargo::restore_crates].
+    pub(crate) fn with_crate_cache(mut self, crate_cache: CrateCache) -> Self {
+        self.crate_cache = Some(crate_cache);
+        self
+    }
+
     /// Returns a new `Command` for running this cargo.
     pub(crate) fn command(&self) -> Result<Command> {
         let mut command = Command::new(&self.path);
@@ -43,6 +204,11 @@ impl Cargo {
         Ok(command)
     }
 
+    /// Returns a new `Command` for running the `rustc` used by this cargo.
+    pub(crate) fn rustc_command(&self) -> Command {
+        Command::new(&self.rustc_path)
+    }
+
     /// Returns a new `MetadataCommand` using this cargo.
     /// `manifest_path`, `current_dir`, and `other_options` should not be called on the resturned MetadataCommand - instead pass them as the relevant args.
     pub(crate) fn metadata_command_with_options(
@@ -117,6 +283,198 @@ impl Cargo {
         bail!("Couldn't parse cargo version");
     }
 
+    /// Share the registry index cache of this cargo's `CARGO_HOME` through `cache_dir`. Cargo keeps
+    /// the `ETag` and `Last-Modified` headers of sparse index files in this cache, so invocations
+    /// using an isolated `CARGO_HOME` only make conditional requests for index files fetched before.
+    /// This relies entirely on cargo's own handling of sparse indexes, nothing is fetched here.
+    ///
+    /// Cargo only locks the index of a `CARGO_HOME` for invocations using that `CARGO_HOME`, so
+    /// `cache_dir` is locked around every invocation run through [Cargo::output] or
+    /// [Cargo::metadata] in addition. The `CARGO_HOME` of the user is never linked to the cache,
+    /// as cargo invocations outside of cargo-bazel don't take that lock.
+    pub(crate) fn use_registry_cache(&mut self, cache_dir: &Path) -> Result<()> {
+        let Some(cargo_home) = self.isolated_cargo_home() else {
+            tracing::warn!(
+                "`CARGO_HOME` isn't isolated, the registry cache at {} is not used",
+                cache_dir.display()
+            );
+            return Ok(());
+        };
+        if link_registry_cache(&cargo_home, cache_dir)? {
+            self.registry_cache = Some(Arc::new(RegistryCache {
+                dir: cache_dir.to_path_buf(),
+                users: Mutex::new((0, None)),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Lock the registry cache, if one is used, for an invocation of cargo. The lock is held
+    /// until the returned guard is dropped.
+    fn lock_registry_cache(&self) -> io::Result<Option<RegistryCacheGuard<'_>>> {
+        let Some(registry_cache) = &self.registry_cache else {
+            return Ok(None);
+        };
+        let mut users = registry_cache.users.lock().unwrap();
+        if users.1.is_none() {
+            users.1 = Some(FileLock::exclusive(
+                &registry_cache.dir.join(REGISTRY_CACHE_LOCK),
+            )?);
+        }
+        users.0 += 1;
+        Ok(Some(RegistryCacheGuard(registry_cache)))
+    }
+
+    /// Run `command`, which was created by [Cargo::command], holding the lock of the registry
+    /// cache.
+    pub(crate) fn output(&self, command: &mut Command) -> io::Result<Output> {
+        let _lock = self.lock_registry_cache()?;
+        command.output()
+    }
+
+    /// Run `command`, which was created by [Cargo::metadata_command_with_options], holding the
+    /// lock of the registry cache.
+    pub(crate) fn metadata(&self, command: &MetadataCommand) -> cargo_metadata::Result<Metadata> {
+        let _lock = self.lock_registry_cache()?;
+        command.exec()
+    }
+
+    /// The name cargo gives the directories of the sparse crates.io registry in `CARGO_HOME`,
+    /// which is derived from a hash of its url. Cargo 1.85 changed how this hash is computed.
+    pub(crate) fn crates_io_registry_dir(&self) -> Result<Option<&'static str>> {
+        let full_version = self.full_version()?;
+        let version_str = full_version.split(' ').nth(1);
+        if let Some(version_str) = version_str {
+            let version = Version::parse(version_str).context("Failed to parse cargo version")?;
+            return Ok(if version.major >= 1 && version.minor >= 85 {
+                Some("index.crates.io-1949cf8c6b5b557f")
+            } else if self.use_sparse_registries_for_crates_io()? {
+                Some("index.crates.io-6f17d22bba15001f")
+            } else {
+                None
+            });
+        }
+        bail!("Couldn't parse cargo version");
+    }
+
+    /// Seed the registry caches of this cargo's `CARGO_HOME` with the archives held by its crate
+    /// cache for the crates in the lockfile at `lockfile_path`, so cargo doesn't download them
+    /// again. This must happen before the first invocation of cargo which may download crates.
+    pub(crate) fn restore_crates(&self, lockfile_path: &Path) -> Result<()> {
+        let (Some(crate_cache), Some(cargo_home)) = (&self.crate_cache, self.cargo_home()) else {
+            return Ok(());
+        };
+        let lockfile = Lockfile::load(lockfile_path)
+            .with_context(|| format!("Failed to load lockfile {}", lockfile_path.display()))?;
+        crate_cache
+            .restore(&cargo_home, &lockfile, self.crates_io_registry_dir()?)
+            .context("Failed to restore crates from the crate cache")?;
+        Ok(())
+    }
+
+    /// Add the archives of the crates in `lockfile` downloaded into this cargo's `CARGO_HOME`
+    /// to its crate cache.
+    pub(crate) fn cache_crates(&self, lockfile: &Lockfile) -> Result<()> {
+        let Some(crate_cache) = &self.crate_cache else {
+            return Ok(());
+        };
+        match self.cargo_home() {
+            Some(cargo_home) => crate_cache
+                .store(&cargo_home, lockfile)
+                .map(|_| ())
+                .context("Failed to store crates in the crate cache"),
+            None => {
+                tracing::warn!("Unable to locate `CARGO_HOME`, the crate cache is not used");
+                Ok(())
+            }
+        }
+    }
+
+    /// Run `command`, rerunning it with an exponential backoff for as long as it fails due to
+    /// network errors. Cargo keeps each crate it successfully downloaded in `CARGO_HOME` (and
+    /// verifies it against the lockfile), so every attempt resumes where the previous one left
+    /// off. The network errors of all attempts are reported once retries are exhausted and the
+    /// output of the last attempt is returned for the caller to handle.
+    ///
+    /// Cargo is told to retry each download [NetworkRetries::retries] times itself, unless the
+    /// user configured `net.retry`, and the number of attempts is capped accordingly. At most
+    /// [MAX_CONCURRENT_NETWORK_INVOCATIONS] invocations run at the same time.
+    pub(crate) fn output_with_retries(&self, command: &mut Command) -> io::Result<Output> {
+        let cargo_retries = match self.configured_net_retry(command.get_current_dir()) {
+            Some(cargo_retries) => cargo_retries,
+            None => {
+                command.env("CARGO_NET_RETRY", self.network_retries.retries.to_string());
+                self.network_retries.retries
+            }
+        };
+        let attempts = self.network_retries.attempts(cargo_retries);
+
+        let mut failures: Vec<Vec<String>> = Vec::new();
+        loop {
+            let output = {
+                let _slot = self.network_slots.acquire();
+                self.output(command)?
+            };
+            if output.status.success() {
+                return Ok(output);
+            }
+
+            let errors = network_errors(&String::from_utf8_lossy(&output.stderr));
+            if errors.is_empty() {
+                return Ok(output);
+            }
+
+            let attempt = failures.len() as u32;
+            failures.push(errors);
+            if attempt + 1 >= attempts {
+                tracing::error!("{}", describe_network_failures(command, &failures));
+                return Ok(output);
+            }
+
+            let delay = self.network_retries.delay(attempt);
+            tracing::warn!(
+                "Retrying `{}` in {}s after a network error: {}",
+                describe_command(command),
+                delay.as_secs_f32(),
+                failures[failures.len() - 1].join("; ")
+            );
+            thread::sleep(delay);
+        }
+    }
+
+    /// The `net.retry` setting of the user for invocations run in `dir`, from the
+    /// `CARGO_NET_RETRY` environment variable or the Cargo config files cargo reads there.
+    fn configured_net_retry(&self, dir: Option<&Path>) -> Option<u32> {
+        if let Some(retry) = env::var_os("CARGO_NET_RETRY") {
+            return retry.to_str().and_then(|retry| retry.parse().ok());
+        }
+        let workspace_dirs = dir
+            .into_iter()
+            .flat_map(Path::ancestors)
+            .map(|dir| dir.join(".cargo"));
+        workspace_dirs
+            .chain(self.cargo_home())
+            .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+            .find_map(|path| net_retry(&path))
+    }
+
+    fn cargo_home(&self) -> Option<PathBuf> {
+        self.explicit_cargo_home().or_else(user_cargo_home)
+    }
+
+    /// The `CARGO_HOME` this cargo was explicitly configured with.
+    fn explicit_cargo_home(&self) -> Option<PathBuf> {
+        if let Some(cargo_home) = &self.cargo_home {
+            return Some(cargo_home.clone());
+        }
+        env::var_os("CARGO_HOME").map(PathBuf::from)
+    }
+
+    /// The `CARGO_HOME` of this cargo if it's isolated, i.e. not the `CARGO_HOME` of the user.
+    fn isolated_cargo_home(&self) -> Option<PathBuf> {
+        isolated_cargo_home(self.explicit_cargo_home(), user_cargo_home().as_deref())
+    }
+
     fn env(&self) -> Result<BTreeMap<String, OsString>> {
         let mut map = BTreeMap::new();
 
@@ -136,3 +494,331 @@ impl Cargo {
         Ok(map)
     }
 }
+
+/// The default `CARGO_HOME` of the user.
+fn user_cargo_home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".cargo"))
+}
+
+/// Returns `cargo_home`, the explicitly configured `CARGO_HOME`, unless it's missing or is the
+/// default `CARGO_HOME` of the user, `user_cargo_home`.
+fn isolated_cargo_home(
+    cargo_home: Option<PathBuf>,
+    user_cargo_home: Option<&Path>,
+) -> Option<PathBuf> {
+    cargo_home.filter(|cargo_home| Some(cargo_home.as_path()) != user_cargo_home)
+}
+
+/// The `net.retry` setting of the Cargo config file at `path`, if it exists and sets it.
+fn net_retry(path: &Path) -> Option<u32> {
+    let content = fs::read_to_string(path).ok()?;
+    let config: toml::Table = toml::from_str(&content).ok()?;
+    let retry = config.get("net")?.get("retry")?.as_integer()?;
+    u32::try_from(retry).ok()
+}
+
+/// The lines of cargo's `stderr` which describe network errors.
+fn network_errors(stderr: &str) -> Vec<String> {
+    stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            NETWORK_ERROR_MARKERS
+                .iter()
+                .any(|marker| line.contains(marker))
+        })
+        .map(str::to_owned)
+        .collect()
+}
+
+fn describe_command(command: &Command) -> String {
+    let program = Path::new(command.get_program())
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    std::iter::once(program)
+        .chain(
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned()),
+        )
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Summarize the distinct network errors seen across all attempts of `command`.
+fn describe_network_failures(command: &Command, failures: &[Vec<String>]) -> String {
+    let errors: BTreeSet<&String> = failures.iter().flatten().collect();
+    let mut description = format!(
+        "`{}` failed with network errors after {} attempt(s):",
+        describe_command(command),
+        failures.len()
+    );
+    for error in errors {
+        description.push_str("\n  ");
+        description.push_str(error);
+    }
+    description
+}
+
+/// Replace the registry index directory of `cargo_home` with a symlink to `cache_dir`. An existing
+/// index directory is left in place as it's already reused by every invocation using `cargo_home`.
+/// Returns whether `cargo_home` uses `cache_dir`.
+fn link_registry_cache(cargo_home: &Path, cache_dir: &Path) -> Result<bool> {
+    let index_dir = cargo_home.join("registry").join("index");
+    if let Ok(target) = fs::read_link(&index_dir) {
+        if target == cache_dir {
+            return Ok(true);
+        }
+        remove_symlink(&index_dir)
+            .with_context(|| format!("Failed to remove symlink {}", index_dir.display()))?;
+    } else if index_dir.exists() {
+        tracing::debug!(
+            "Not sharing the registry index at {} which already exists",
+            index_dir.display()
+        );
+        return Ok(false);
+    }
+
+    fs::create_dir_all(cache_dir).with_context(|| {
+        format!(
+            "Failed to create registry cache directory {}",
+            cache_dir.display()
+        )
+    })?;
+    fs::create_dir_all(index_dir.parent().unwrap())?;
+    symlink(cache_dir, &index_dir).with_context(|| {
+        format!(
+            "Failed to create symlink {} pointing at {}",
+            index_dir.display(),
+            cache_dir.display()
+        )
+    })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn link_registry_cache_into_cargo_home() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let cargo_home = temp_dir.path().join("cargo_home");
+
+        assert!(link_registry_cache(&cargo_home, &cache_dir).unwrap());
+        let index_dir = cargo_home.join("registry").join("index");
+        assert_eq!(fs::read_link(&index_dir).unwrap(), cache_dir);
+
+        // Linking again is a no-op.
+        assert!(link_registry_cache(&cargo_home, &cache_dir).unwrap());
+        assert_eq!(fs::read_link(&index_dir).unwrap(), cache_dir);
+
+        // A link to another cache is replaced.
+        let other_cache_dir = temp_dir.path().join("other_cache");
+        assert!(link_registry_cache(&cargo_home, &other_cache_dir).unwrap());
+        assert_eq!(fs::read_link(&index_dir).unwrap(), other_cache_dir);
+    }
+
+    #[test]
+    fn link_registry_cache_keeps_existing_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let cargo_home = temp_dir.path().join("cargo_home");
+        let index_dir = cargo_home.join("registry").join("index");
+        fs::create_dir_all(&index_dir).unwrap();
+
+        assert!(!link_registry_cache(&cargo_home, &cache_dir).unwrap());
+        assert!(fs::read_link(&index_dir).is_err());
+        assert!(!cache_dir.exists());
+    }
+
+    #[test]
+    fn user_cargo_home_is_not_isolated() {
+        let user_cargo_home = Path::new("/home/user/.cargo");
+        assert_eq!(
+            isolated_cargo_home(
+                Some(PathBuf::from("/cache/.cargo_home")),
+                Some(user_cargo_home)
+            ),
+            Some(PathBuf::from("/cache/.cargo_home"))
+        );
+        assert_eq!(
+            isolated_cargo_home(Some(user_cargo_home.to_path_buf()), Some(user_cargo_home)),
+            None
+        );
+        assert_eq!(isolated_cargo_home(None, Some(user_cargo_home)), None);
+    }
+
+    #[test]
+    fn registry_cache_lock_is_shared_by_concurrent_invocations() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+        let mut cargo = Cargo::new(PathBuf::from("cargo"), PathBuf::from("rustc"));
+        cargo.cargo_home = Some(temp_dir.path().join("cargo_home"));
+        cargo.use_registry_cache(&cache_dir).unwrap();
+
+        let registry_cache = cargo.registry_cache.clone().unwrap();
+        let first = cargo.lock_registry_cache().unwrap();
+        let second = cargo.lock_registry_cache().unwrap();
+        assert_eq!(registry_cache.users.lock().unwrap().0, 2);
+        drop(first);
+        assert!(registry_cache.users.lock().unwrap().1.is_some());
+        drop(second);
+        assert!(registry_cache.users.lock().unwrap().1.is_none());
+
+        // Other processes can lock the cache once no invocation uses it.
+        FileLock::exclusive(&cache_dir.join(REGISTRY_CACHE_LOCK)).unwrap();
+    }
+
+    #[test]
+    fn network_retry_delays() {
+        let network_retries = NetworkRetries {
+            retries: 10,
+            backoff: Duration::from_secs(2),
+        };
+
+        assert_eq!(network_retries.delay(0), Duration::from_secs(2));
+        assert_eq!(network_retries.delay(1), Duration::from_secs(4));
+        assert_eq!(network_retries.delay(2), Duration::from_secs(8));
+        assert_eq!(network_retries.delay(9), MAX_BACKOFF);
+        assert_eq!(network_retries.delay(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn network_retry_attempts() {
+        let network_retries = NetworkRetries {
+            retries: 3,
+            ..NetworkRetries::default()
+        };
+
+        // Cargo makes all retries itself.
+        assert_eq!(network_retries.attempts(3), 1);
+        assert_eq!(network_retries.attempts(10), 1);
+        // The invocation is rerun for the retries cargo doesn't make.
+        assert_eq!(network_retries.attempts(0), 4);
+        assert_eq!(network_retries.attempts(1), 2);
+        assert_eq!(network_retries.attempts(2), 2);
+        assert_eq!(network_retries.attempts(u32::MAX), 1);
+    }
+
+    #[test]
+    fn configured_net_retry_of_cargo_configs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cargo_home = temp_dir.path().join("cargo_home");
+        let workspace = temp_dir.path().join("workspace");
+        let member = workspace.join("member");
+        fs::create_dir_all(&cargo_home).unwrap();
+        fs::create_dir_all(workspace.join(".cargo")).unwrap();
+        fs::create_dir_all(member.join(".cargo")).unwrap();
+        let mut cargo = Cargo::new(PathBuf::from("cargo"), PathBuf::from("rustc"));
+        cargo.cargo_home = Some(cargo_home.clone());
+
+        assert_eq!(net_retry(&cargo_home.join("config.toml")), None);
+        assert_eq!(cargo.configured_net_retry(Some(&member)), None);
+
+        fs::write(cargo_home.join("config.toml"), "[net]\nretry = 5\n").unwrap();
+        assert_eq!(cargo.configured_net_retry(Some(&member)), Some(5));
+        assert_eq!(cargo.configured_net_retry(None), Some(5));
+
+        // Configs closer to the directory take precedence.
+        fs::write(workspace.join(".cargo").join("config"), "net.retry = 1\n").unwrap();
+        fs::write(
+            member.join(".cargo").join("config.toml"),
+            "[net]\ngit-fetch-with-cli = true\n",
+        )
+        .unwrap();
+        assert_eq!(cargo.configured_net_retry(Some(&member)), Some(1));
+    }
+
+    #[test]
+    fn network_slots_block_until_released() {
+        let slots = Arc::new(NetworkSlots::new(2));
+        let first = slots.acquire();
+        let _second = slots.acquire();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let waiter = thread::spawn({
+            let slots = slots.clone();
+            move || {
+                let _slot = slots.acquire();
+                sender.send(()).unwrap();
+            }
+        });
+
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(first);
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn detect_network_errors() {
+        let stderr = indoc::indoc! {r#"
+            error: failed to download from `https://static.crates.io/crates/serde/1.0.0/download`
+
+            Caused by:
+              [28] Timeout was reached (download of `serde v1.0.0` failed to transfer more than 10 bytes in 30s)
+        "#};
+
+        assert_eq!(
+            network_errors(stderr),
+            vec![
+                "[28] Timeout was reached (download of `serde v1.0.0` failed to transfer more than 10 bytes in 30s)".to_owned(),
+            ]
+        );
+        assert_eq!(
+            network_errors(
+                "error: Operation timed out after 30000 milliseconds with 0 bytes received"
+            ),
+            vec![
+                "error: Operation timed out after 30000 milliseconds with 0 bytes received"
+                    .to_owned()
+            ]
+        );
+        assert!(network_errors("error: failed to parse manifest").is_empty());
+
+        // Failures which retrying doesn't fix aren't network errors.
+        let not_found = indoc::indoc! {r#"
+            error: failed to download from `https://static.crates.io/crates/serde/9.9.9/download`
+
+            Caused by:
+              failed to get successful HTTP response from `https://static.crates.io/crates/serde/9.9.9/download` (52.1.2.3), got 404
+        "#};
+        assert!(network_errors(not_found).is_empty());
+        let checksum = indoc::indoc! {r#"
+            error: failed to download replaced source registry `crates-io`
+
+            Caused by:
+              failed to verify the checksum of `serde v1.0.0 (registry `crates-io`)`
+        "#};
+        assert!(network_errors(checksum).is_empty());
+        assert!(network_errors("error: build script of `foo` timed out").is_empty());
+    }
+
+    #[test]
+    fn summarize_network_failures() {
+        let mut command = Command::new("/usr/bin/cargo");
+        command.arg("fetch");
+        let failures = vec![
+            vec!["error: Couldn't resolve host".to_owned()],
+            vec![
+                "error: Couldn't resolve host".to_owned(),
+                "error: Connection reset".to_owned(),
+            ],
+        ];
+
+        assert_eq!(
+            describe_network_failures(&command, &failures),
+            indoc::indoc! {"
+                `cargo fetch` failed with network errors after 2 attempt(s):
+                  error: Connection reset
+                  error: Couldn't resolve host"}
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! Approximating the settings of [Cargo profiles](https://doc.rust-lang.org/cargo/reference/profiles.html)
+//! with `rustc` flags, as Bazel doesn't read profiles from manifests.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Result};
+use cargo_metadata::Package;
+
+/// The profiles built into Cargo along with the profiles they inherit from.
+const BUILT_IN_PROFILES: &[(&str, Option<&str>)] = &[
+    ("dev", None),
+    ("release", None),
+    ("test", Some("dev")),
+    ("bench", Some("release")),
+    ("doc", Some("dev")),
+];
+
+/// The `rustc` flags approximating a Cargo profile, including its per-package overrides.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ProfileFlags {
+    /// The flags of the profile itself, which apply to every crate.
+    rustc_flags: Vec<String>,
+
+    /// The flags of the `[profile.*.package."*"]` override, which apply to all crates which
+    /// aren't workspace members.
+    dependency_rustc_flags: Vec<String>,
+
+    /// The flags of `[profile.*.package.<spec>]` overrides keyed by their package spec.
+    package_rustc_flags: BTreeMap<String, Vec<String>>,
+
+    /// A description of each setting which couldn't be mapped to `rustc` flags.
+    pub(crate) unmapped: Vec<String>,
+}
+
+impl ProfileFlags {
+    /// Map the profile `name` defined in `profiles` (the `[profile]` table of the root
+    /// manifest) or built into Cargo.
+    pub(crate) fn new(profiles: &BTreeMap<String, toml::Value>, name: &str) -> Result<Self> {
+        let profile = resolve_profile(profiles, name)?;
+
+        let mut flags = Self::default();
+        map_settings(
+            &profile,
+            &format!("profile.{name}"),
+            &mut flags.rustc_flags,
+            &mut flags.unmapped,
+        );
+        if let Some(toml::Value::Table(packages)) = profile.get("package") {
+            for (spec, settings) in packages {
+                let toml::Value::Table(settings) = settings else {
+                    continue;
+                };
+                let mut package_flags = Vec::new();
+                map_settings(
+                    settings,
+                    &format!("profile.{name}.package.{spec}"),
+                    &mut package_flags,
+                    &mut flags.unmapped,
+                );
+                if spec == "*" {
+                    flags.dependency_rustc_flags = package_flags;
+                } else {
+                    flags
+                        .package_rustc_flags
+                        .insert(spec.clone(), package_flags);
+                }
+            }
+        }
+
+        Ok(flags)
+    }
+
+    /// The flags of `package`. Flags of more specific overrides come last so they take
+    /// precedence, as with Cargo.
+    pub(crate) fn rustc_flags(&self, package: &Package, is_workspace_member: bool) -> Vec<String> {
+        let mut flags = self.rustc_flags.clone();
+        if !is_workspace_member {
+            flags.extend(self.dependency_rustc_flags.iter().cloned());
+        }
+        let name_and_version = format!("{}@{}", package.name, package.version);
+        for (spec, package_flags) in &self.package_rustc_flags {
+            if spec == package.name.as_str() || *spec == name_and_version {
+                flags.extend(package_flags.iter().cloned());
+            }
+        }
+        flags
+    }
+}
+
+/// Collect the settings of profile `name`, including those it inherits.
+fn resolve_profile(
+    profiles: &BTreeMap<String, toml::Value>,
+    name: &str,
+) -> Result<toml::map::Map<String, toml::Value>> {
+    // The chain of profiles from `name` to the built-in profile it's based on.
+    let mut chain = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut current = Some(name.to_owned());
+    while let Some(name) = current.take() {
+        if !visited.insert(name.clone()) {
+            bail!("The Cargo profile `{name}` inherits from itself");
+        }
+        let built_in = BUILT_IN_PROFILES
+            .iter()
+            .find(|(built_in, _)| *built_in == name);
+        let profile = match profiles.get(&name) {
+            Some(toml::Value::Table(profile)) => profile.clone(),
+            Some(_) => bail!("The Cargo profile `{name}` is not a table"),
+            None if built_in.is_some() => toml::map::Map::new(),
+            None => bail!("The Cargo profile `{name}` is not defined"),
+        };
+        current = match (built_in, profile.get("inherits")) {
+            (Some((_, inherits)), _) => inherits.map(str::to_owned),
+            (None, Some(toml::Value::String(inherits))) => Some(inherits.clone()),
+            (None, _) => bail!("The custom Cargo profile `{name}` must specify `inherits`"),
+        };
+        chain.push(profile);
+    }
+
+    // Apply the settings of each profile over the ones it inherits.
+    let mut resolved = toml::map::Map::new();
+    for profile in chain.into_iter().rev() {
+        for (key, value) in profile {
+            match (resolved.get_mut(&key), value) {
+                (Some(toml::Value::Table(inherited)), toml::Value::Table(table))
+                    if key == "package" =>
+                {
+                    inherited.extend(table);
+                }
+                (_, value) => {
+                    resolved.insert(key, value);
+                }
+            }
+        }
+    }
+    resolved.remove("inherits");
+
+    Ok(resolved)
+}
+
+/// Map the `settings` of the profile table at `path` to `rustc` flags.
+fn map_settings(
+    settings: &toml::map::Map<String, toml::Value>,
+    path: &str,
+    flags: &mut Vec<String>,
+    unmapped: &mut Vec<String>,
+) {
+    for (key, value) in settings {
+        let flag = match (key.as_str(), value) {
+            // Overrides are mapped separately.
+            ("package", _) => continue,
+            ("opt-level", toml::Value::Integer(level)) => Some(format!("-Copt-level={level}")),
+            ("opt-level", toml::Value::String(level)) if level == "s" || level == "z" => {
+                Some(format!("-Copt-level={level}"))
+            }
+            ("debug", toml::Value::Boolean(debug)) => {
+                Some(format!("-Cdebuginfo={}", if *debug { 2 } else { 0 }))
+            }
+            ("debug", toml::Value::Integer(level)) => Some(format!("-Cdebuginfo={level}")),
+            ("debug", toml::Value::String(level)) => match level.as_str() {
+                "none" => Some("-Cdebuginfo=0".to_owned()),
+                "limited" => Some("-Cdebuginfo=1".to_owned()),
+                "full" => Some("-Cdebuginfo=2".to_owned()),
+                "line-tables-only" | "line-directives-only" => Some(format!("-Cdebuginfo={level}")),
+                _ => None,
+            },
+            ("debug-assertions", toml::Value::Boolean(enabled)) => Some(format!(
+                "-Cdebug-assertions={}",
+                if *enabled { "on" } else { "off" }
+            )),
+            ("overflow-checks", toml::Value::Boolean(enabled)) => Some(format!(
+                "-Coverflow-checks={}",
+                if *enabled { "on" } else { "off" }
+            )),
+            ("codegen-units", toml::Value::Integer(units)) => {
+                Some(format!("-Ccodegen-units={units}"))
+            }
+            _ => None,
+        };
+        match flag {
+            Some(flag) => flags.push(flag),
+            None => unmapped.push(format!(
+                "`{path}.{key} = {value}` can't be applied to crates rendered by cargo-bazel"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn profiles(content: &str) -> BTreeMap<String, toml::Value> {
+        let manifest: toml::Table = toml::from_str(content).unwrap();
+        manifest["profile"]
+            .as_table()
+            .unwrap()
+            .clone()
+            .into_iter()
+            .collect()
+    }
+
+    fn package(name: &str, version: &str) -> Package {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": version,
+            "id": format!("registry+https://github.com/rust-lang/crates.io-index#{name}@{version}"),
+            "dependencies": [],
+            "targets": [],
+            "features": {},
+            "manifest_path": format!("/tmp/{name}/Cargo.toml"),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn map_profile_with_overrides() {
+        let profiles = profiles(
+            r#"
+            [profile.release]
+            opt-level = "z"
+            debug = "line-tables-only"
+            panic = "abort"
+
+            [profile.release.package."*"]
+            opt-level = 2
+
+            [profile.release.package.foo]
+            debug-assertions = true
+            overflow-checks = true
+            "#,
+        );
+
+        let flags = ProfileFlags::new(&profiles, "release").unwrap();
+
+        assert_eq!(
+            flags.rustc_flags(&package("member", "0.1.0"), true),
+            vec!["-Cdebuginfo=line-tables-only", "-Copt-level=z"]
+        );
+        assert_eq!(
+            flags.rustc_flags(&package("bar", "1.0.0"), false),
+            vec![
+                "-Cdebuginfo=line-tables-only",
+                "-Copt-level=z",
+                "-Copt-level=2"
+            ]
+        );
+        assert_eq!(
+            flags.rustc_flags(&package("foo", "1.0.0"), false),
+            vec![
+                "-Cdebuginfo=line-tables-only",
+                "-Copt-level=z",
+                "-Copt-level=2",
+                "-Cdebug-assertions=on",
+                "-Coverflow-checks=on",
+            ]
+        );
+        assert_eq!(
+            flags.unmapped,
+            vec![
+                "`profile.release.panic = \"abort\"` can't be applied to crates rendered by cargo-bazel"
+            ]
+        );
+    }
+
+    #[test]
+    fn map_inherited_profile() {
+        let profiles = profiles(
+            r#"
+            [profile.release]
+            opt-level = 3
+            codegen-units = 1
+
+            [profile.release.package.foo]
+            opt-level = 1
+
+            [profile.fast]
+            inherits = "release"
+            codegen-units = 16
+
+            [profile.fast.package.bar]
+            opt-level = 0
+            "#,
+        );
+
+        let flags = ProfileFlags::new(&profiles, "fast").unwrap();
+
+        assert_eq!(
+            flags.rustc_flags(&package("foo", "1.0.0"), false),
+            vec!["-Ccodegen-units=16", "-Copt-level=3", "-Copt-level=1"]
+        );
+        assert_eq!(
+            flags.rustc_flags(&package("bar", "1.0.0"), false),
+            vec!["-Ccodegen-units=16", "-Copt-level=3", "-Copt-level=0"]
+        );
+        assert!(flags.unmapped.is_empty());
+    }
+
+    #[test]
+    fn map_built_in_profile() {
+        let flags = ProfileFlags::new(&BTreeMap::new(), "bench").unwrap();
+        assert_eq!(flags, ProfileFlags::default());
+    }
+
+    #[test]
+    fn map_invalid_profiles() {
+        let profiles = profiles(
+            r#"
+            [profile.custom]
+            opt-level = 1
+
+            [profile.cycle]
+            inherits = "cycle"
+            "#,
+        );
+
+        assert_eq!(
+            ProfileFlags::new(&profiles, "missing")
+                .unwrap_err()
+                .to_string(),
+            "The Cargo profile `missing` is not defined"
+        );
+        assert_eq!(
+            ProfileFlags::new(&profiles, "custom")
+                .unwrap_err()
+                .to_string(),
+            "The custom Cargo profile `custom` must specify `inherits`"
+        );
+        assert_eq!(
+            ProfileFlags::new(&profiles, "cycle")
+                .unwrap_err()
+                .to_string(),
+            "The Cargo profile `cycle` inherits from itself"
+        );
+    }
+}
@@ -3,20 +3,20 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::BufRead;
 use std::path::{Path, PathBuf};
-use std::process::Child;
 
 use anyhow::{anyhow, bail, Context, Result};
 use camino::Utf8Path;
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, info, warn};
 use url::Url;
 
 use crate::config::CrateId;
 use crate::metadata::cargo_bin::Cargo;
 use crate::select::{Select, SelectableScalar};
+use crate::utils::parallel;
 use crate::utils::symlink::symlink;
-use crate::utils::target_triple::TargetTriple;
+use crate::utils::target_triple::{TargetCfgs, TargetSpec, TargetTriple};
 
 /// A list platform triples that support host tools
 ///
@@ -88,6 +88,60 @@ impl SelectableScalar for CargoTreeEntry {}
 /// Feature and dependency metadata generated from [TreeResolver].
 pub(crate) type TreeResolverMetadata = BTreeMap<CrateId, Select<CargoTreeEntry>>;
 
+/// Feature and dependency metadata generated from [TreeResolver] for each workspace member
+/// in isolation.
+pub(crate) type IsolatedTreeResolverMetadata = BTreeMap<CrateId, TreeResolverMetadata>;
+
+/// Feature and dependency metadata of crates built in the exec configuration, i.e. by build
+/// scripts and proc macros. Crates built for both the exec and target configurations which
+/// are resolved differently for each get an additional instance for the exec configuration.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ExecTreeResolverMetadata {
+    /// Crates which are only ever built in the exec configuration. [TreeResolverMetadata]
+    /// holds their exec resolution.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) exec_only: BTreeSet<CrateId>,
+
+    /// The exec resolution of crates needing an instance for the exec configuration.
+    /// [TreeResolverMetadata] holds the target resolution of these.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) instances: TreeResolverMetadata,
+}
+
+impl ExecTreeResolverMetadata {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// The name of the package proc macros are added to in virtual workspaces.
+const FAKE_PROC_MACRO_ROOT: &str = "rules_rust_fake_proc_macro_root";
+
+/// A copy of a Cargo workspace in which `cargo tree` is run.
+struct TreeResolverWorkspace {
+    /// The temporary directory containing the copy, which is deleted when dropped.
+    _tempdir: tempfile::TempDir,
+
+    /// The manifest of the copied workspace.
+    manifest_path: PathBuf,
+
+    /// The package proc macros were added to if one was created for them.
+    proc_macro_root: Option<String>,
+
+    /// See [TreeResolver::create_rustc_wrapper].
+    rustc_wrapper: PathBuf,
+
+    /// The paths of the custom target specifications written for `cargo tree`.
+    target_spec_paths: BTreeMap<TargetTriple, PathBuf>,
+
+    /// The platforms with host tools to resolve host dependencies for.
+    host_triples: BTreeSet<TargetTriple>,
+}
+
 /// Generates metadata about a Cargo workspace tree which supplements the inaccuracies in
 /// standard [Cargo metadata](https://doc.rust-lang.org/cargo/commands/cargo-metadata.html)
 /// due lack of [Feature resolver 2](https://doc.rust-lang.org/cargo/reference/resolver.html#feature-resolver-version-2)
@@ -96,28 +150,40 @@ pub(crate) type TreeResolverMetadata = BTreeMap<CrateId, Select<CargoTreeEntry>>
 pub(crate) struct TreeResolver {
     /// The path to a `cargo` binary
     cargo_bin: Cargo,
+
+    /// Whether features enabled only by dev dependencies are excluded from the features of
+    /// crates which are also built for production targets.
+    strict_dev_dependencies: bool,
 }
 
 impl TreeResolver {
     pub(crate) fn new(cargo_bin: Cargo) -> Self {
-        Self { cargo_bin }
+        Self {
+            cargo_bin,
+            strict_dev_dependencies: false,
+        }
     }
 
-    /// Execute `cargo tree` for each target triple and return the stdout
-    /// streams containing structured output.
+    pub(crate) fn with_strict_dev_dependencies(mut self, strict_dev_dependencies: bool) -> Self {
+        self.strict_dev_dependencies = strict_dev_dependencies;
+        self
+    }
+
+    /// Execute `cargo tree` for each target triple and each set of `packages` (the whole
+    /// workspace for an empty set) and return the parsed target and host tree data of each,
+    /// in the order of `package_sets`.
     fn execute_cargo_tree(
         &self,
-        manifest_path: &Path,
-        host_triples: &BTreeSet<TargetTriple>,
+        workspace: &TreeResolverWorkspace,
         target_triples: &BTreeSet<TargetTriple>,
-        rustc_wrapper: &Path,
-    ) -> Result<BTreeMap<TargetTriple, BTreeMap<TargetTriple, Vec<u8>>>> {
-        // A collection of all stdout logs from each process
-        let mut stdouts: BTreeMap<TargetTriple, BTreeMap<TargetTriple, Vec<u8>>> = BTreeMap::new();
+        package_sets: &[Vec<String>],
+        edges: &str,
+    ) -> Result<Vec<CargoTreeOutputs>> {
+        let manifest_path = &workspace.manifest_path;
 
         // We only want to spawn processes for unique cargo platforms
         let mut cargo_host_triples = BTreeMap::<String, BTreeSet<&TargetTriple>>::new();
-        for triple in host_triples {
+        for triple in &workspace.host_triples {
             cargo_host_triples
                 .entry(triple.to_cargo())
                 .or_default()
@@ -125,93 +191,115 @@ impl TreeResolver {
         }
         let mut cargo_target_triples = BTreeMap::<String, BTreeSet<&TargetTriple>>::new();
         for triple in target_triples {
+            // Custom targets are passed to cargo as the path to their spec.
+            let cargo_target = match workspace.target_spec_paths.get(triple) {
+                Some(path) => path.display().to_string(),
+                None => triple.to_cargo(),
+            };
             cargo_target_triples
-                .entry(triple.to_cargo())
+                .entry(cargo_target)
                 .or_default()
                 .insert(triple);
         }
 
-        for host_triple in cargo_host_triples.keys() {
-            // Note that for each host triple `cargo tree` child processes are spawned and then
-            // immediately waited upon so that we don't end up with `{HOST_TRIPLES} * {TARGET_TRIPLES}`
-            // number of processes (which can be +400 and hit operating system limitations).
-            let mut target_triple_to_child = BTreeMap::<String, Child>::new();
+        // A job for each set of packages on each pair of platforms. The jobs are run on a pool
+        // so that we don't end up with `{HOST_TRIPLES} * {TARGET_TRIPLES}` number of processes
+        // (which can be +400 and hit operating system limitations).
+        let jobs: Vec<(usize, &String, &String)> = (0..package_sets.len())
+            .flat_map(|index| {
+                cargo_host_triples.keys().flat_map({
+                    let cargo_target_triples = &cargo_target_triples;
+                    move |host_triple| {
+                        cargo_target_triples
+                            .keys()
+                            .map(move |target_triple| (index, host_triple, target_triple))
+                    }
+                })
+            })
+            .collect();
 
-            for target_triple in cargo_target_triples.keys() {
+        let outputs = parallel::map(
+            "Running `cargo tree`",
+            jobs.clone(),
+            parallel::default_jobs(),
+            |(index, host_triple, target_triple)| {
                 // We use `cargo tree` here because `cargo metadata` doesn't report
                 // back target-specific features (enabled with `resolver = "2"`).
                 // This is unfortunately a bit of a hack. See:
                 // - https://github.com/rust-lang/cargo/issues/9863
                 // - https://github.com/bazelbuild/rules_rust/issues/1662
-                let child = self
-                    .cargo_bin
-                    .command()?
+                let mut command = self.cargo_bin.command()?;
+                command
                     // These next two environment variables are used to hack cargo into using a custom
                     // host triple instead of the host triple detected by rustc.
-                    .env("RUSTC_WRAPPER", rustc_wrapper)
+                    .env("RUSTC_WRAPPER", &workspace.rustc_wrapper)
                     .env("HOST_TRIPLE", host_triple)
                     .env("CARGO_CACHE_RUSTC_INFO", "0")
-                    .current_dir(manifest_path.parent().expect("All manifests should have a valid parent."))
+                    .current_dir(
+                        manifest_path
+                            .parent()
+                            .expect("All manifests should have a valid parent."),
+                    )
                     .arg("tree")
                     .arg("--manifest-path")
                     .arg(manifest_path)
                     .arg("--edges")
-                    .arg("normal,build,dev")
+                    .arg(edges)
                     .arg("--prefix=indent")
                     // https://doc.rust-lang.org/cargo/commands/cargo-tree.html#tree-formatting-options
                     .arg("--format=;{p};{f};")
                     .arg("--color=never")
                     .arg("--charset=ascii")
-                    .arg("--workspace")
                     .arg("--target")
-                    .arg(target_triple)
-                    .stdout(std::process::Stdio::piped())
-                    .stderr(std::process::Stdio::piped())
-                    .spawn()
+                    .arg(target_triple);
+                let packages = &package_sets[index];
+                if packages.is_empty() {
+                    command.arg("--workspace");
+                }
+                for package in packages {
+                    command.arg("--package").arg(package);
+                }
+                let output = self
+                    .cargo_bin
+                    .output_with_retries(&mut command)
                     .with_context(|| {
                         format!(
-                            "Error spawning cargo in child process to compute features for target '{}', manifest path '{}'",
-                            target_triple,
-                            manifest_path.display()
-                        )
-                    })?;
-                target_triple_to_child.insert(target_triple.clone(), child);
-            }
-
-            debug!(
-                "Spawned `cargo tree` processes for host `{}`: {}",
-                host_triple,
-                target_triple_to_child.len(),
-            );
-
-            for (target_triple, child) in target_triple_to_child.into_iter() {
-                let output = child.wait_with_output().with_context(|| {
-                    format!(
                         "Error running `cargo tree --target={}` (host = '{}'), manifest path '{}'",
                         target_triple,
                         host_triple,
                         manifest_path.display()
                     )
-                })?;
+                    })?;
                 if !output.status.success() {
                     eprintln!("{}", String::from_utf8_lossy(&output.stdout));
                     eprintln!("{}", String::from_utf8_lossy(&output.stderr));
                     bail!(format!("Failed to run cargo tree: {}", output.status))
                 }
 
-                // Replicate outputs for any de-duplicated platforms
-                for host_plat in cargo_host_triples[host_triple].iter() {
-                    for target_plat in cargo_target_triples[&target_triple].iter() {
-                        stdouts
-                            .entry((*host_plat).clone())
-                            .or_default()
-                            .insert((*target_plat).clone(), output.stdout.clone());
-                    }
+                debug!(
+                    "Parsing (host={}) `cargo tree --target {}` output:\n```\n{}\n```",
+                    host_triple,
+                    target_triple,
+                    String::from_utf8_lossy(&output.stdout),
+                );
+                parse_cargo_tree_output(output.stdout.lines())
+            },
+        )?;
+
+        // Replicate outputs for any de-duplicated platforms
+        let mut results = vec![CargoTreeOutputs::new(); package_sets.len()];
+        for ((index, host_triple, target_triple), output) in jobs.into_iter().zip(outputs) {
+            for host_plat in cargo_host_triples[host_triple].iter() {
+                for target_plat in cargo_target_triples[target_triple].iter() {
+                    results[index]
+                        .entry((*host_plat).clone())
+                        .or_default()
+                        .insert((*target_plat).clone(), output.clone());
                 }
             }
         }
 
-        Ok(stdouts)
+        Ok(results)
     }
 
     // The use of this wrapper should __never__ escape this class.
@@ -301,21 +389,138 @@ impl TreeResolver {
         Self::create_rustc_wrapper_impl(output_dir)
     }
 
-    /// Computes the set of enabled features for each target triplet for each crate.
+    /// Computes the set of enabled features for each target triplet for each crate, along with
+    /// the resolution of crates built in the exec configuration and the `cfg` options of each
+    /// target triplet.
     #[tracing::instrument(name = "TreeResolver::generate", skip_all)]
     pub(crate) fn generate(
         &self,
         pristine_manifest_path: &Utf8Path,
         target_triples: &BTreeSet<TargetTriple>,
-    ) -> Result<TreeResolverMetadata> {
+        target_specs: &BTreeMap<TargetTriple, TargetSpec>,
+    ) -> Result<(TreeResolverMetadata, ExecTreeResolverMetadata, TargetCfgs)> {
         debug!(
             "Generating features for manifest {}",
             pristine_manifest_path
         );
 
+        let workspace =
+            self.prepare_workspace(pristine_manifest_path, target_triples, target_specs)?;
+        let (target_tree_data, host_tree_data) = self
+            .resolve(&workspace, target_triples, &[Vec::new()])?
+            .remove(0);
+        let (tree_data, exec_tree_data) = split_tree_data(target_tree_data, host_tree_data);
+        let target_cfgs = self.target_cfgs(&workspace, target_triples)?;
+        Ok((tree_data, exec_tree_data, target_cfgs))
+    }
+
+    /// Query `rustc` for the `cfg` options of each target triple. Unlike the data of `cfg-expr`,
+    /// these include the target features enabled by default (e.g. `simd128` on some `wasm32`
+    /// targets). Triples the `rustc` in use doesn't know are skipped.
+    fn target_cfgs(
+        &self,
+        workspace: &TreeResolverWorkspace,
+        target_triples: &BTreeSet<TargetTriple>,
+    ) -> Result<TargetCfgs> {
+        let mut target_cfgs = TargetCfgs::new();
+        for triple in target_triples {
+            let target = match workspace.target_spec_paths.get(triple) {
+                Some(path) => path.display().to_string(),
+                None => triple.to_cargo(),
+            };
+            let output = self
+                .cargo_bin
+                .rustc_command()
+                .arg("--print=cfg")
+                .arg("--target")
+                .arg(&target)
+                .output()
+                .with_context(|| format!("Error running `rustc --print=cfg --target={target}`"))?;
+            if !output.status.success() {
+                warn!(
+                    "Failed to query the cfg options of {}: {}",
+                    triple,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                continue;
+            }
+            target_cfgs.insert(
+                triple.clone(),
+                parse_rustc_cfgs(&String::from_utf8_lossy(&output.stdout)),
+            );
+        }
+        Ok(target_cfgs)
+    }
+
+    /// Computes the set of enabled features for each target triplet for each crate as seen by
+    /// each workspace member on its own, as if features were not unified across the workspace.
+    #[tracing::instrument(name = "TreeResolver::generate_isolated", skip_all)]
+    pub(crate) fn generate_isolated(
+        &self,
+        pristine_manifest_path: &Utf8Path,
+        target_triples: &BTreeSet<TargetTriple>,
+        target_specs: &BTreeMap<TargetTriple, TargetSpec>,
+    ) -> Result<IsolatedTreeResolverMetadata> {
+        debug!(
+            "Generating features for each member of manifest {}",
+            pristine_manifest_path
+        );
+
+        let mut command = self.cargo_bin.metadata_command_with_options(
+            pristine_manifest_path.as_std_path(),
+            vec!["--locked".to_owned(), "--no-deps".to_owned()],
+        )?;
+        command.manifest_path(pristine_manifest_path.as_std_path());
+        let cargo_metadata = self
+            .cargo_bin
+            .metadata(&command)
+            .context("Failed to run cargo metadata to list workspace members")?;
+        let workspace_members: BTreeSet<CrateId> = cargo_metadata
+            .workspace_packages()
+            .into_iter()
+            .map(CrateId::from)
+            .collect();
+
+        let workspace =
+            self.prepare_workspace(pristine_manifest_path, target_triples, target_specs)?;
+
+        let package_sets: Vec<Vec<String>> = workspace_members
+            .iter()
+            .map(|member| {
+                let mut packages = vec![format!("{}@{}", member.name, member.version)];
+                // Proc macros are still resolved for all platforms. See
+                // `copy_project_with_explicit_deps_on_all_transitive_proc_macros`.
+                if let Some(proc_macro_root) = &workspace.proc_macro_root {
+                    packages.push(proc_macro_root.clone());
+                }
+                packages
+            })
+            .collect();
+        let tree_data = self
+            .resolve(&workspace, target_triples, &package_sets)
+            .context("Failed to resolve features for each workspace member")?;
+
+        // Crates are not split by configuration for each workspace member.
+        Ok(workspace_members
+            .into_iter()
+            .zip(tree_data)
+            .map(|(member, (mut target_tree_data, host_tree_data))| {
+                merge_tree_data(&mut target_tree_data, host_tree_data);
+                (member, collapse_tree_data(target_tree_data))
+            })
+            .collect())
+    }
+
+    /// Copy a workspace into a temporary directory in which `cargo tree` can be run.
+    fn prepare_workspace(
+        &self,
+        pristine_manifest_path: &Utf8Path,
+        target_triples: &BTreeSet<TargetTriple>,
+        target_specs: &BTreeMap<TargetTriple, TargetSpec>,
+    ) -> Result<TreeResolverWorkspace> {
         let tempdir = tempfile::tempdir().context("Failed to make tempdir")?;
 
-        let manifest_path_with_transitive_proc_macros = self
+        let (manifest_path, proc_macro_root) = self
             .copy_project_with_explicit_deps_on_all_transitive_proc_macros(
                 pristine_manifest_path,
                 &tempdir.path().join("explicit_proc_macro_deps"),
@@ -324,6 +529,15 @@ impl TreeResolver {
 
         let rustc_wrapper = Self::create_rustc_wrapper(tempdir.path())?;
 
+        let target_specs_dir = tempdir.path().join("target_specs");
+        if !target_specs.is_empty() {
+            std::fs::create_dir_all(&target_specs_dir)?;
+        }
+        let target_spec_paths = target_specs
+            .iter()
+            .map(|(triple, spec)| Ok((triple.clone(), spec.write(&target_specs_dir, triple)?)))
+            .collect::<Result<BTreeMap<TargetTriple, PathBuf>>>()?;
+
         let host_triples: BTreeSet<TargetTriple> = target_triples
             .iter()
             // Only query triples for platforms that have host tools.
@@ -333,99 +547,61 @@ impl TreeResolver {
             .cloned()
             .collect();
 
-        // This is a very expensive process. Here we iterate over all target triples
-        // and generate tree data as though they were also the host triple
-        let deps_tree_streams: BTreeMap<TargetTriple, BTreeMap<TargetTriple, Vec<u8>>> = self
-            .execute_cargo_tree(
-                &manifest_path_with_transitive_proc_macros,
-                &host_triples,
-                target_triples,
-                &rustc_wrapper,
-            )?;
-
-        let mut metadata: BTreeMap<CrateId, BTreeMap<TargetTriple, CargoTreeEntry>> =
-            BTreeMap::new();
-
-        for (host_triple, target_streams) in deps_tree_streams.into_iter() {
-            for (target_triple, stdout) in target_streams.into_iter() {
-                debug!(
-                    "Parsing (host={}) `cargo tree --target {}` output:\n```\n{}\n```",
-                    host_triple,
-                    target_triple,
-                    String::from_utf8_lossy(&stdout),
-                );
-
-                let (target_tree_data, host_tree_data) = parse_cargo_tree_output(stdout.lines())?;
+        Ok(TreeResolverWorkspace {
+            _tempdir: tempdir,
+            manifest_path,
+            proc_macro_root,
+            rustc_wrapper,
+            target_spec_paths,
+            host_triples,
+        })
+    }
 
-                for (entry, tree_data) in target_tree_data {
-                    metadata
-                        .entry(entry.as_crate_id().clone())
-                        .or_default()
-                        .entry(target_triple.clone())
-                        .or_default()
-                        .consume(tree_data);
-                }
-                for (entry, tree_data) in host_tree_data {
-                    metadata
-                        .entry(entry.as_crate_id().clone())
-                        .or_default()
-                        .entry(host_triple.clone())
-                        .or_default()
-                        .consume(tree_data);
+    /// Run `cargo tree` for each set of packages (or the whole workspace for an empty set) and
+    /// collect the features and dependencies of each crate per platform and configuration.
+    fn resolve(
+        &self,
+        workspace: &TreeResolverWorkspace,
+        target_triples: &BTreeSet<TargetTriple>,
+        package_sets: &[Vec<String>],
+    ) -> Result<Vec<(TreeData, TreeData)>> {
+        let mut tree_data =
+            self.collect_tree_data(workspace, target_triples, package_sets, "normal,build,dev")?;
+        if self.strict_dev_dependencies {
+            let production_tree_data =
+                self.collect_tree_data(workspace, target_triples, package_sets, "normal,build")?;
+            for ((target_tree_data, host_tree_data), (production_target, production_host)) in
+                tree_data.iter_mut().zip(production_tree_data)
+            {
+                for report in [
+                    prune_dev_dependencies(target_tree_data, production_target),
+                    prune_dev_dependencies(host_tree_data, production_host),
+                ] {
+                    for line in report.lines() {
+                        info!("{}", line);
+                    }
                 }
             }
         }
 
-        // Collect all metadata into a mapping of crate to it's metadata per target.
-        let mut result = TreeResolverMetadata::new();
-        for (crate_id, tree_data) in metadata.into_iter() {
-            let common = CargoTreeEntry {
-                features: tree_data
-                    .iter()
-                    .fold(
-                        None,
-                        |common: Option<BTreeSet<String>>, (_, data)| match common {
-                            Some(common) => {
-                                Some(common.intersection(&data.features).cloned().collect())
-                            }
-                            None => Some(data.features.clone()),
-                        },
-                    )
-                    .unwrap_or_default(),
-                deps: tree_data
-                    .iter()
-                    .fold(
-                        None,
-                        |common: Option<BTreeSet<CrateId>>, (_, data)| match common {
-                            Some(common) => {
-                                Some(common.intersection(&data.deps).cloned().collect())
-                            }
-                            None => Some(data.deps.clone()),
-                        },
-                    )
-                    .unwrap_or_default(),
-            };
-            let mut select: Select<CargoTreeEntry> = Select::default();
-            for (target_triple, data) in tree_data {
-                let mut entry = CargoTreeEntry::new();
-                entry.features.extend(
-                    data.features
-                        .into_iter()
-                        .filter(|f| !common.features.contains(f)),
-                );
-                entry
-                    .deps
-                    .extend(data.deps.into_iter().filter(|d| !common.deps.contains(d)));
-                if !entry.is_empty() {
-                    select.insert(entry, Some(target_triple.to_bazel()));
-                }
-            }
-            if !common.is_empty() {
-                select.insert(common, None);
-            }
-            result.insert(crate_id, select);
-        }
-        Ok(result)
+        Ok(tree_data)
+    }
+
+    /// Run `cargo tree` following the given kinds of `edges` and collect the features and
+    /// dependencies of each crate per platform for each set of packages, separately for crates
+    /// built for the target and for the exec configuration.
+    fn collect_tree_data(
+        &self,
+        workspace: &TreeResolverWorkspace,
+        target_triples: &BTreeSet<TargetTriple>,
+        package_sets: &[Vec<String>],
+        edges: &str,
+    ) -> Result<Vec<(TreeData, TreeData)>> {
+        // This is a very expensive process. Here we iterate over all target triples
+        // and generate tree data as though they were also the host triple
+        let outputs = self.execute_cargo_tree(workspace, target_triples, package_sets, edges)?;
+
+        Ok(outputs.into_iter().map(platform_tree_data).collect())
     }
 
     // Artificially inject all proc macros as dependency roots.
@@ -450,7 +626,7 @@ impl TreeResolver {
         &self,
         pristine_manifest_path: &Utf8Path,
         output_dir: &Path,
-    ) -> Result<PathBuf> {
+    ) -> Result<(PathBuf, Option<String>)> {
         if !output_dir.exists() {
             std::fs::create_dir_all(output_dir)?;
         }
@@ -480,14 +656,14 @@ impl TreeResolver {
             )
         })?;
 
+        let mut command = self.cargo_bin.metadata_command_with_options(
+            pristine_manifest_path.as_std_path(),
+            vec!["--locked".to_owned()],
+        )?;
+        command.manifest_path(pristine_manifest_path.as_std_path());
         let cargo_metadata = self
             .cargo_bin
-            .metadata_command_with_options(
-                pristine_manifest_path.as_std_path(),
-                vec!["--locked".to_owned()],
-            )?
-            .manifest_path(pristine_manifest_path.as_std_path())
-            .exec()
+            .metadata(&command)
             .context("Failed to run cargo metadata to list transitive proc macros")?;
         let proc_macros = cargo_metadata
             .packages
@@ -534,15 +710,14 @@ impl TreeResolver {
 
         // To add dependencies to a virtual workspace, we need to add them to a package inside the workspace,
         // we can't just add them to the workspace directly.
+        let mut proc_macro_root = None;
         if !proc_macros.is_empty() && manifest.package.is_none() {
             if let Some(ref mut workspace) = &mut manifest.workspace {
                 if !workspace.members.contains(&".".to_owned()) {
                     workspace.members.push(".".to_owned());
                 }
-                manifest.package = Some(cargo_toml::Package::new(
-                    "rules_rust_fake_proc_macro_root",
-                    "0.0.0",
-                ));
+                manifest.package = Some(cargo_toml::Package::new(FAKE_PROC_MACRO_ROOT, "0.0.0"));
+                proc_macro_root = Some(FAKE_PROC_MACRO_ROOT.to_owned());
             }
             if manifest.lib.is_none() && manifest.bin.is_empty() {
                 manifest.bin.push(cargo_toml::Product {
@@ -564,7 +739,7 @@ impl TreeResolver {
         }
         let manifest_path_with_transitive_proc_macros = output_dir.join("Cargo.toml");
         crate::splicing::write_manifest(&manifest_path_with_transitive_proc_macros, &manifest)?;
-        Ok(manifest_path_with_transitive_proc_macros)
+        Ok((manifest_path_with_transitive_proc_macros, proc_macro_root))
     }
 }
 
@@ -759,14 +934,272 @@ impl From<TreeDepCompileKind> for CrateId {
     }
 }
 
-/// Parses the output of `cargo tree --format=|{p}|{f}|`. Other flags may be
-/// passed to `cargo tree` as well, but this format is critical.
-fn parse_cargo_tree_output<I, S, E>(
-    lines: I,
-) -> Result<(
+/// The tree data of the target and host dependencies parsed from `cargo tree --target`.
+type ParsedCargoTree = (
     BTreeMap<TreeDepCompileKind, CargoTreeEntry>,
     BTreeMap<TreeDepCompileKind, CargoTreeEntry>,
-)>
+);
+
+/// The parsed `cargo tree` output for each host and target platform.
+type CargoTreeOutputs = BTreeMap<TargetTriple, BTreeMap<TargetTriple, ParsedCargoTree>>;
+
+/// The features and dependencies of each crate per platform.
+type TreeData = BTreeMap<CrateId, BTreeMap<TargetTriple, CargoTreeEntry>>;
+
+/// Key the `cargo tree` output of each pair of host and target platforms by the platform each
+/// crate is built for, returning the tree data of the target and host dependencies.
+///
+/// Resolver v2 resolves features separately for the target and the host, and only for the
+/// platform passed to `cargo tree --target`, so each target triple gets exactly the features
+/// cargo computes for it. Bazel selects features on the platform a crate is configured for, so
+/// target dependencies are keyed by the target triple and host dependencies by the host triple.
+/// Since an exec configuration can build for any target, host features are the union over all
+/// target triples.
+fn platform_tree_data(output: CargoTreeOutputs) -> (TreeData, TreeData) {
+    let mut target_metadata = TreeData::new();
+    let mut host_metadata = TreeData::new();
+    for (host_triple, target_outputs) in output.into_iter() {
+        for (target_triple, (target_tree_data, host_tree_data)) in target_outputs.into_iter() {
+            for (entry, tree_data) in target_tree_data {
+                target_metadata
+                    .entry(entry.as_crate_id().clone())
+                    .or_default()
+                    .entry(target_triple.clone())
+                    .or_default()
+                    .consume(tree_data);
+            }
+            for (entry, tree_data) in host_tree_data {
+                host_metadata
+                    .entry(entry.as_crate_id().clone())
+                    .or_default()
+                    .entry(host_triple.clone())
+                    .or_default()
+                    .consume(tree_data);
+            }
+        }
+    }
+    (target_metadata, host_metadata)
+}
+
+/// Add the tree data of `other` to `tree_data`.
+fn merge_tree_data(tree_data: &mut TreeData, other: TreeData) {
+    for (crate_id, platforms) in other {
+        let merged = tree_data.entry(crate_id).or_default();
+        for (triple, entry) in platforms {
+            merged.entry(triple).or_default().consume(entry);
+        }
+    }
+}
+
+/// Combine the tree data of crates built for the target (`target_tree_data`) and exec
+/// (`host_tree_data`) configurations. Crates built for both keep their target resolution and
+/// get an exec instance when resolved differently on any exec platform, or when any of their
+/// dependencies in the exec configuration have an exec instance.
+fn split_tree_data(
+    target_tree_data: TreeData,
+    mut host_tree_data: TreeData,
+) -> (TreeResolverMetadata, ExecTreeResolverMetadata) {
+    let mut needs_instance: BTreeSet<CrateId> = host_tree_data
+        .iter()
+        .filter(|(crate_id, host_platforms)| {
+            let Some(target_platforms) = target_tree_data.get(*crate_id) else {
+                return false;
+            };
+            host_platforms.iter().any(|(triple, host_entry)| {
+                target_platforms
+                    .get(triple)
+                    .is_none_or(|target_entry| target_entry != host_entry)
+            })
+        })
+        .map(|(crate_id, _)| crate_id.clone())
+        .collect();
+
+    // Crates depending on an exec instance need one of their own to depend on it through.
+    loop {
+        let dependents: BTreeSet<CrateId> = host_tree_data
+            .iter()
+            .filter(|(crate_id, _)| {
+                target_tree_data.contains_key(*crate_id) && !needs_instance.contains(*crate_id)
+            })
+            .filter(|(_, host_platforms)| {
+                host_platforms
+                    .values()
+                    .any(|entry| entry.deps.iter().any(|dep| needs_instance.contains(dep)))
+            })
+            .map(|(crate_id, _)| crate_id.clone())
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        needs_instance.extend(dependents);
+    }
+
+    let mut exec_metadata = ExecTreeResolverMetadata::new();
+    let mut instances = TreeData::new();
+    for crate_id in &needs_instance {
+        instances.insert(crate_id.clone(), host_tree_data.remove(crate_id).unwrap());
+    }
+    exec_metadata.instances = collapse_tree_data(instances);
+
+    let mut tree_data = target_tree_data;
+    for (crate_id, host_platforms) in host_tree_data {
+        if tree_data.contains_key(&crate_id) {
+            continue;
+        }
+        exec_metadata.exec_only.insert(crate_id.clone());
+        tree_data.insert(crate_id, host_platforms);
+    }
+
+    (collapse_tree_data(tree_data), exec_metadata)
+}
+
+/// Collapse the tree data of each crate into a [Select] of the data common to all platforms
+/// and the data specific to each.
+fn collapse_tree_data(tree_data: TreeData) -> TreeResolverMetadata {
+    // Collect all metadata into a mapping of crate to it's metadata per target.
+    let mut result = TreeResolverMetadata::new();
+    for (crate_id, tree_data) in tree_data.into_iter() {
+        let common = CargoTreeEntry {
+            features: tree_data
+                .iter()
+                .fold(
+                    None,
+                    |common: Option<BTreeSet<String>>, (_, data)| match common {
+                        Some(common) => {
+                            Some(common.intersection(&data.features).cloned().collect())
+                        }
+                        None => Some(data.features.clone()),
+                    },
+                )
+                .unwrap_or_default(),
+            deps: tree_data
+                .iter()
+                .fold(
+                    None,
+                    |common: Option<BTreeSet<CrateId>>, (_, data)| match common {
+                        Some(common) => Some(common.intersection(&data.deps).cloned().collect()),
+                        None => Some(data.deps.clone()),
+                    },
+                )
+                .unwrap_or_default(),
+        };
+        let mut select: Select<CargoTreeEntry> = Select::default();
+        for (target_triple, data) in tree_data {
+            let mut entry = CargoTreeEntry::new();
+            entry.features.extend(
+                data.features
+                    .into_iter()
+                    .filter(|f| !common.features.contains(f)),
+            );
+            entry
+                .deps
+                .extend(data.deps.into_iter().filter(|d| !common.deps.contains(d)));
+            if !entry.is_empty() {
+                select.insert(entry, Some(target_triple.to_bazel()));
+            }
+        }
+        if !common.is_empty() {
+            select.insert(common, None);
+        }
+        result.insert(crate_id, select);
+    }
+    result
+}
+
+/// The crates and features which were only required by dev dependencies.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct DevDependencyReport {
+    /// Crates which are only reachable through dev dependencies on some platforms.
+    dev_only_crates: BTreeMap<CrateId, BTreeSet<TargetTriple>>,
+
+    /// Features of crates built for production targets which only dev dependencies enable.
+    pruned_features: BTreeMap<CrateId, BTreeMap<TargetTriple, BTreeSet<String>>>,
+}
+
+impl DevDependencyReport {
+    fn lines(&self) -> Vec<String> {
+        let triples = |triples: &mut dyn Iterator<Item = &TargetTriple>| {
+            triples
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut lines = Vec::new();
+        for (crate_id, platforms) in &self.dev_only_crates {
+            lines.push(format!(
+                "{crate_id} is only used by dev dependencies on: {}",
+                triples(&mut platforms.iter())
+            ));
+        }
+        for (crate_id, platforms) in &self.pruned_features {
+            let mut by_features: BTreeMap<&BTreeSet<String>, Vec<&TargetTriple>> = BTreeMap::new();
+            for (triple, features) in platforms {
+                by_features.entry(features).or_default().push(triple);
+            }
+            for (features, platforms) in by_features {
+                lines.push(format!(
+                    "Features of {crate_id} only enabled by dev dependencies were pruned on {}: {}",
+                    triples(&mut platforms.into_iter()),
+                    features.iter().cloned().collect::<Vec<_>>().join(", "),
+                ));
+            }
+        }
+        lines
+    }
+}
+
+/// Replace the data of crates built for production targets with their data from a resolution
+/// which ignores dev dependencies. Crates which are only used by dev dependencies are kept as is.
+fn prune_dev_dependencies(
+    tree_data: &mut TreeData,
+    mut production_tree_data: TreeData,
+) -> DevDependencyReport {
+    let mut report = DevDependencyReport::default();
+    for (crate_id, platforms) in tree_data.iter_mut() {
+        let mut production_platforms = production_tree_data.remove(crate_id).unwrap_or_default();
+        for (triple, entry) in platforms.iter_mut() {
+            let Some(production_entry) = production_platforms.remove(triple) else {
+                report
+                    .dev_only_crates
+                    .entry(crate_id.clone())
+                    .or_default()
+                    .insert(triple.clone());
+                continue;
+            };
+            let pruned: BTreeSet<String> = entry
+                .features
+                .difference(&production_entry.features)
+                .cloned()
+                .collect();
+            if !pruned.is_empty() {
+                report
+                    .pruned_features
+                    .entry(crate_id.clone())
+                    .or_default()
+                    .insert(triple.clone(), pruned);
+            }
+            // Dependencies are only consulted for optional dependencies, which dev dependencies
+            // can't be, so only the production dependencies are needed.
+            *entry = production_entry;
+        }
+    }
+    report
+}
+
+/// Parses the output of `rustc --print=cfg`, ignoring `debug_assertions` which Bazel sets
+/// depending on the compilation mode.
+fn parse_rustc_cfgs(output: &str) -> BTreeSet<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && *line != "debug_assertions")
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Parses the output of `cargo tree --format=|{p}|{f}|`. Other flags may be
+/// passed to `cargo tree` as well, but this format is critical.
+fn parse_cargo_tree_output<I, S, E>(lines: I) -> Result<ParsedCargoTree>
 where
     I: Iterator<Item = std::result::Result<S, E>>,
     S: AsRef<str>,
@@ -807,8 +1240,15 @@ where
 
                 continue;
             } else if line.ends_with("[dev-dependencies]") {
-                // Dev dependencies are not treated any differently than normal dependencies
-                // when we enter these blocks, continue to collect deps as usual.
+                // Dev dependencies are not treated any differently than normal dependencies.
+                // `cargo tree` lists them after any build dependencies of the same crate so
+                // the crate must stop being tracked as a consumer of host dependencies.
+                let dev_depth = (line.chars().count() - "[dev-dependencies]".chars().count()) / 4;
+
+                if let TreeDepCompileKind::TargetWithHostDep(crate_id) = &parents[dev_depth] {
+                    parents[dev_depth] = TreeDepCompileKind::Target(crate_id.clone());
+                }
+
                 continue;
             }
             bail!("Unexpected line '{}'", line);
@@ -948,6 +1388,26 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn parse_rustc_cfgs_output() {
+        let output = indoc::indoc! {r#"
+            debug_assertions
+            panic="abort"
+            target_arch="wasm32"
+            target_feature="sign-ext"
+            target_os="unknown"
+        "#};
+        assert_eq!(
+            parse_rustc_cfgs(output),
+            BTreeSet::from([
+                r#"panic="abort""#.to_owned(),
+                r#"target_arch="wasm32""#.to_owned(),
+                r#"target_feature="sign-ext""#.to_owned(),
+                r#"target_os="unknown""#.to_owned(),
+            ])
+        );
+    }
+
     #[test]
     fn parse_sparse_source() {
         let source = Source::parse(
@@ -1676,6 +2136,82 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_cargo_tree_output_dev_deps_after_build_deps() {
+        let cc_id = CrateId {
+            name: "cc".to_owned(),
+            version: Version::new(1, 1, 0),
+        };
+        let member_id = CrateId {
+            name: "member".to_owned(),
+            version: Version::new(0, 1, 0),
+        };
+        let serde_id = CrateId {
+            name: "serde".to_owned(),
+            version: Version::new(1, 0, 210),
+        };
+        let tokio_id = CrateId {
+            name: "tokio".to_owned(),
+            version: Version::new(1, 40, 0),
+        };
+
+        let (target_output, host_output) = parse_cargo_tree_output(
+            textwrap::dedent(
+                r#"
+                ;member v0.1.0 (/rules_rust/crate_universe/test_data/metadata/member);;
+                |-- ;serde v1.0.210;default,std;
+                [build-dependencies]
+                `-- ;cc v1.1.0;parallel;
+                [dev-dependencies]
+                `-- ;tokio v1.40.0;macros,rt;
+                "#,
+            )
+            .lines()
+            .map(Ok::<&str, std::io::Error>),
+        )
+        .unwrap();
+
+        assert_eq!(
+            BTreeMap::from([(
+                TreeDepCompileKind::Host(cc_id.clone()),
+                CargoTreeEntry {
+                    features: BTreeSet::from(["parallel".to_owned()]),
+                    deps: BTreeSet::new(),
+                },
+            )]),
+            host_output,
+            "Failed checking host dependencies."
+        );
+
+        assert_eq!(
+            BTreeMap::from([
+                (
+                    TreeDepCompileKind::Target(member_id),
+                    CargoTreeEntry {
+                        features: BTreeSet::new(),
+                        deps: BTreeSet::from([cc_id, serde_id.clone(), tokio_id.clone()]),
+                    },
+                ),
+                (
+                    TreeDepCompileKind::Target(serde_id),
+                    CargoTreeEntry {
+                        features: BTreeSet::from(["default".to_owned(), "std".to_owned()]),
+                        deps: BTreeSet::new(),
+                    },
+                ),
+                (
+                    TreeDepCompileKind::Target(tokio_id),
+                    CargoTreeEntry {
+                        features: BTreeSet::from(["macros".to_owned(), "rt".to_owned()]),
+                        deps: BTreeSet::new(),
+                    },
+                ),
+            ]),
+            target_output,
+            "Failed checking target dependencies."
+        );
+    }
+
     #[test]
     fn parse_cargo_tree_output_same_level_host_deps() {
         let autocfg_id = CrateId {
@@ -1718,4 +2254,226 @@ mod test {
             "Failed checking host dependencies."
         );
     }
+
+    #[test]
+    fn prune_dev_dependencies_from_production_crates() {
+        let id = |name: &str| CrateId::new(name.to_owned(), Version::new(1, 0, 0));
+        let linux = TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned());
+        let windows = TargetTriple::from_bazel("x86_64-pc-windows-msvc".to_owned());
+        let entry = |features: &[&str], deps: &[&str]| CargoTreeEntry {
+            features: features.iter().map(|f| f.to_string()).collect(),
+            deps: deps.iter().map(|dep| id(dep)).collect(),
+        };
+
+        let mut tree_data = TreeData::from([
+            (
+                id("tokio"),
+                BTreeMap::from([
+                    (linux.clone(), entry(&["rt", "test-util"], &["mio"])),
+                    (windows.clone(), entry(&["rt", "test-util"], &["mio"])),
+                ]),
+            ),
+            (
+                id("mockall"),
+                BTreeMap::from([(linux.clone(), entry(&[], &[]))]),
+            ),
+        ]);
+        let production_tree_data = TreeData::from([(
+            id("tokio"),
+            BTreeMap::from([
+                (linux.clone(), entry(&["rt"], &["mio"])),
+                (windows.clone(), entry(&["rt"], &["mio"])),
+            ]),
+        )]);
+
+        let report = prune_dev_dependencies(&mut tree_data, production_tree_data);
+
+        assert_eq!(
+            tree_data[&id("tokio")],
+            BTreeMap::from([
+                (linux.clone(), entry(&["rt"], &["mio"])),
+                (windows.clone(), entry(&["rt"], &["mio"])),
+            ])
+        );
+        // Crates only used by dev dependencies are kept.
+        assert_eq!(
+            tree_data[&id("mockall")],
+            BTreeMap::from([(linux.clone(), entry(&[], &[]))])
+        );
+        assert_eq!(
+            report.lines(),
+            vec![
+                "mockall 1.0.0 is only used by dev dependencies on: x86_64-unknown-linux-gnu"
+                    .to_owned(),
+                "Features of tokio 1.0.0 only enabled by dev dependencies were pruned on x86_64-pc-windows-msvc, x86_64-unknown-linux-gnu: test-util".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_tree_data_by_configuration() {
+        let id = |name: &str| CrateId::new(name.to_owned(), Version::new(1, 0, 0));
+        let linux = TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned());
+        let wasm = TargetTriple::from_bazel("wasm32-unknown-unknown".to_owned());
+        let entry = |features: &[&str], deps: &[&str]| CargoTreeEntry {
+            features: features.iter().map(|f| f.to_string()).collect(),
+            deps: deps.iter().map(|dep| id(dep)).collect(),
+        };
+
+        let target_tree_data = TreeData::from([
+            (
+                id("quote"),
+                BTreeMap::from([
+                    (linux.clone(), entry(&[], &["proc-macro2"])),
+                    (wasm.clone(), entry(&[], &["proc-macro2"])),
+                ]),
+            ),
+            (
+                id("proc-macro2"),
+                BTreeMap::from([
+                    (linux.clone(), entry(&["proc-macro", "span-locations"], &[])),
+                    (wasm.clone(), entry(&["proc-macro", "span-locations"], &[])),
+                ]),
+            ),
+            (
+                id("libc"),
+                BTreeMap::from([(linux.clone(), entry(&["std"], &[]))]),
+            ),
+        ]);
+        let host_tree_data = TreeData::from([
+            (
+                id("quote"),
+                BTreeMap::from([(linux.clone(), entry(&[], &["proc-macro2"]))]),
+            ),
+            (
+                id("proc-macro2"),
+                BTreeMap::from([(linux.clone(), entry(&["proc-macro"], &[]))]),
+            ),
+            (
+                id("libc"),
+                BTreeMap::from([(linux.clone(), entry(&["std"], &[]))]),
+            ),
+            (
+                id("serde_derive"),
+                BTreeMap::from([(linux.clone(), entry(&[], &["quote"]))]),
+            ),
+        ]);
+
+        let (tree_metadata, exec_metadata) = split_tree_data(target_tree_data, host_tree_data);
+
+        // Crates are resolved for the target configuration where they are built for it.
+        assert_eq!(
+            tree_metadata[&id("proc-macro2")],
+            Select::from_value(Some(entry(&["proc-macro", "span-locations"], &[])))
+        );
+        assert_eq!(
+            tree_metadata[&id("serde_derive")],
+            Select::from_value(Some(entry(&[], &["quote"])))
+        );
+        assert_eq!(
+            exec_metadata.exec_only,
+            BTreeSet::from([id("serde_derive")])
+        );
+        // `proc-macro2` is resolved differently for the exec configuration and `quote` depends
+        // on it there, while `libc` is resolved the same for both.
+        assert_eq!(
+            exec_metadata.instances.keys().collect::<Vec<_>>(),
+            vec![&id("proc-macro2"), &id("quote")]
+        );
+        assert_eq!(
+            exec_metadata.instances[&id("proc-macro2")],
+            Select::from_value(Some(entry(&["proc-macro"], &[])))
+        );
+    }
+
+    #[test]
+    fn platform_conditional_features_per_triple() {
+        let id = |name: &str, version: Version| CrateId::new(name.to_owned(), version);
+        let app = id("app", Version::new(0, 1, 0));
+        let cc = id("cc", Version::new(1, 1, 0));
+        let mio = id("mio", Version::new(1, 0, 0));
+        let tokio = id("tokio", Version::new(1, 40, 0));
+        let linux = TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned());
+        let windows = TargetTriple::from_bazel("x86_64-pc-windows-msvc".to_owned());
+        let parse = |output: &str| {
+            parse_cargo_tree_output(
+                textwrap::dedent(output)
+                    .lines()
+                    .map(Ok::<&str, std::io::Error>),
+            )
+            .unwrap()
+        };
+        let entry = |features: &[&str], deps: &[&CrateId]| CargoTreeEntry {
+            features: features.iter().map(|f| f.to_string()).collect(),
+            deps: deps.iter().map(|dep| (*dep).clone()).collect(),
+        };
+
+        // `app` enables the `net` feature of `tokio` and the `parallel` feature of its build
+        // dependency `cc` only in `[target.'cfg(windows)'.*dependencies]`.
+        let outputs = CargoTreeOutputs::from([(
+            linux.clone(),
+            BTreeMap::from([
+                (
+                    linux.clone(),
+                    parse(
+                        r#"
+                        ;app v0.1.0 (/workspace/app);;
+                        |-- ;tokio v1.40.0;rt;
+                        [build-dependencies]
+                        `-- ;cc v1.1.0;;
+                        "#,
+                    ),
+                ),
+                (
+                    windows.clone(),
+                    parse(
+                        r#"
+                        ;app v0.1.0 (/workspace/app);;
+                        |-- ;tokio v1.40.0;net,rt;
+                        |   `-- ;mio v1.0.0;net,os-poll;
+                        [build-dependencies]
+                        `-- ;cc v1.1.0;parallel;
+                        "#,
+                    ),
+                ),
+            ]),
+        )]);
+
+        let (target_tree_data, host_tree_data) = platform_tree_data(outputs);
+
+        // Each target triple gets exactly the features cargo resolved for it.
+        assert_eq!(
+            target_tree_data[&tokio],
+            BTreeMap::from([
+                (linux.clone(), entry(&["rt"], &[])),
+                (windows.clone(), entry(&["net", "rt"], &[&mio])),
+            ])
+        );
+        assert_eq!(
+            target_tree_data[&mio],
+            BTreeMap::from([(windows.clone(), entry(&["net", "os-poll"], &[]))])
+        );
+        // Host dependencies are built for the exec configuration, which can build for any
+        // target, so their features are the union over all target triples.
+        assert_eq!(
+            host_tree_data[&cc],
+            BTreeMap::from([(linux.clone(), entry(&["parallel"], &[]))])
+        );
+
+        let (tree_metadata, exec_metadata) = split_tree_data(target_tree_data, host_tree_data);
+        let mut tokio_select = Select::default();
+        tokio_select.insert(entry(&["rt"], &[]), None);
+        tokio_select.insert(entry(&["net"], &[&mio]), Some(windows.to_bazel()));
+        assert_eq!(tree_metadata[&tokio], tokio_select);
+        // `mio` is only a dependency on Windows, so its features are common to where it's built.
+        assert_eq!(
+            tree_metadata[&mio],
+            Select::from_value(Some(entry(&["net", "os-poll"], &[])))
+        );
+        assert_eq!(
+            tree_metadata[&app],
+            Select::from_value(Some(entry(&[], &[&cc, &tokio])))
+        );
+        assert_eq!(exec_metadata.exec_only, BTreeSet::from([cc]));
+    }
 }
@@ -0,0 +1,338 @@
+//! A content-addressed cache of the `.crate` archives cargo downloads from registries.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use cargo_lock::package::SourceId;
+use cargo_lock::{Lockfile, Package};
+use hex::ToHex;
+use sha2::{Digest, Sha256};
+
+/// The host of the sparse crates.io index, which cargo uses for the default registry.
+const CRATES_IO_SPARSE_HOST: &str = "index.crates.io";
+
+/// A directory of `.crate` archives keyed by their checksum. As archives are keyed by their
+/// content rather than by the registry or the `CARGO_HOME` they were downloaded into, a cache
+/// can be shared by any number of repositories on the same machine.
+#[derive(Debug, Clone)]
+pub(crate) struct CrateCache {
+    dir: PathBuf,
+}
+
+impl CrateCache {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Place the cached archives of the crates in `lockfile` in the registry caches of
+    /// `cargo_home`, so cargo considers them downloaded. crates.io is seeded under
+    /// `crates_io_registry_dir`, the directory cargo names its caches, while other registries
+    /// are only seeded once their index was fetched. Returns the number of archives restored.
+    pub(crate) fn restore(
+        &self,
+        cargo_home: &Path,
+        lockfile: &Lockfile,
+        crates_io_registry_dir: Option<&str>,
+    ) -> Result<usize> {
+        let mut restored = 0;
+        for (package, checksum) in registry_packages(lockfile) {
+            let cached = self.path(&checksum);
+            if !cached.exists() {
+                continue;
+            }
+            for registry_cache in registry_cache_dirs(cargo_home, package, crates_io_registry_dir)?
+            {
+                let archive = registry_cache.join(archive_name(package));
+                if archive.exists() {
+                    continue;
+                }
+                fs::create_dir_all(&registry_cache).with_context(|| {
+                    format!("Failed to create directory {}", registry_cache.display())
+                })?;
+                link_or_copy(&cached, &archive)?;
+                restored += 1;
+            }
+        }
+
+        tracing::debug!("Restored {} crates from {}", restored, self.dir.display());
+        Ok(restored)
+    }
+
+    /// Add the archives cargo downloaded into `cargo_home` for the crates in `lockfile` to the
+    /// cache. Archives which don't match the checksum recorded in `lockfile` are skipped.
+    /// Returns the number of archives stored.
+    pub(crate) fn store(&self, cargo_home: &Path, lockfile: &Lockfile) -> Result<usize> {
+        let mut stored = 0;
+        for (package, checksum) in registry_packages(lockfile) {
+            let cached = self.path(&checksum);
+            if cached.exists() {
+                continue;
+            }
+            for registry_cache in registry_cache_dirs(cargo_home, package, None)? {
+                let archive = registry_cache.join(archive_name(package));
+                let Ok(content) = fs::read(&archive) else {
+                    continue;
+                };
+                if Sha256::digest(&content).encode_hex::<String>() != checksum {
+                    tracing::debug!(
+                        "Not caching {} which does not match its checksum",
+                        archive.display()
+                    );
+                    continue;
+                }
+                write_atomic(&cached, &content)?;
+                stored += 1;
+                break;
+            }
+        }
+
+        tracing::debug!("Stored {} crates in {}", stored, self.dir.display());
+        Ok(stored)
+    }
+
+    /// The location of the archive with the given `checksum`, sharded by its first two characters
+    /// to keep directories small.
+    fn path(&self, checksum: &str) -> PathBuf {
+        self.dir
+            .join("sha256")
+            .join(&checksum[..2])
+            .join(format!("{checksum}.crate"))
+    }
+}
+
+/// The packages of `lockfile` downloaded from a remote registry, along with their checksum.
+fn registry_packages(lockfile: &Lockfile) -> impl Iterator<Item = (&Package, String)> {
+    lockfile.packages.iter().filter_map(|package| {
+        let source = package.source.as_ref()?;
+        if !source.is_remote_registry() {
+            return None;
+        }
+        let checksum = package.checksum.as_ref()?;
+        if !checksum.is_sha256() {
+            return None;
+        }
+        Some((package, checksum.to_string()))
+    })
+}
+
+fn archive_name(package: &Package) -> String {
+    format!("{}-{}.crate", package.name, package.version)
+}
+
+/// The host cargo names the directories of `source` after in `CARGO_HOME/registry`.
+fn registry_host(source: &SourceId) -> Option<String> {
+    if source.is_default_registry() {
+        return Some(CRATES_IO_SPARSE_HOST.to_owned());
+    }
+    source.url().host_str().map(str::to_owned)
+}
+
+/// The registry caches of `cargo_home` which may hold the archive of `package`. Cargo names
+/// the directories of each registry `{host}-{hash}` where the hash is derived from its url, so
+/// any fetched index of the registry's host is considered, along with `crates_io_registry_dir`
+/// for crates.io whose index may not have been fetched yet.
+fn registry_cache_dirs(
+    cargo_home: &Path,
+    package: &Package,
+    crates_io_registry_dir: Option<&str>,
+) -> Result<Vec<PathBuf>> {
+    let Some(source) = package.source.as_ref() else {
+        return Ok(Vec::new());
+    };
+    let Some(host) = registry_host(source) else {
+        return Ok(Vec::new());
+    };
+    let mut dirs = Vec::new();
+    if let Some(name) = crates_io_registry_dir.filter(|_| source.is_default_registry()) {
+        dirs.push(cargo_home.join("registry").join("cache").join(name));
+    }
+    let index_dir = cargo_home.join("registry").join("index");
+    if !index_dir.exists() {
+        return Ok(dirs);
+    }
+
+    let prefix = format!("{host}-");
+    for entry in fs::read_dir(&index_dir)
+        .with_context(|| format!("Failed to read {}", index_dir.display()))?
+    {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let is_registry = name
+            .strip_prefix(&prefix)
+            .is_some_and(|hash| hash.chars().all(|c| c.is_ascii_hexdigit()));
+        if is_registry {
+            dirs.push(cargo_home.join("registry").join("cache").join(name));
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+
+    Ok(dirs)
+}
+
+/// Hard link `src` to `dest`, copying it if linking isn't possible (e.g. across file systems).
+fn link_or_copy(src: &Path, dest: &Path) -> Result<()> {
+    if fs::hard_link(src, dest).is_ok() {
+        return Ok(());
+    }
+    fs::copy(src, dest)
+        .map(|_| ())
+        .with_context(|| format!("Failed to copy {} to {}", src.display(), dest.display()))
+}
+
+/// Write `content` to `path` through a temporary file so concurrent readers never observe a
+/// partially written archive.
+fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let parent = path.parent().expect("Cache paths always have a parent");
+    fs::create_dir_all(parent)
+        .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    let temp_path = parent.join(format!(
+        ".{}.{}.tmp",
+        path.file_name().unwrap().to_string_lossy(),
+        std::process::id()
+    ));
+    fs::write(&temp_path, content)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).with_context(|| {
+        format!(
+            "Failed to move {} to {}",
+            temp_path.display(),
+            path.display()
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::str::FromStr;
+
+    const CRATE_CONTENT: &[u8] = b"not really a tarball";
+
+    fn mock_lockfile(checksum: &str) -> Lockfile {
+        Lockfile::from_str(&format!(
+            r#"
+version = 3
+
+[[package]]
+name = "direct-cargo-bazel-deps"
+version = "0.0.1"
+dependencies = [
+ "serde",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "{checksum}"
+"#
+        ))
+        .unwrap()
+    }
+
+    fn mock_cargo_home(path: &Path) -> PathBuf {
+        let index_dir = path
+            .join("registry")
+            .join("index")
+            .join("index.crates.io-1949cf8c6b5b557f");
+        fs::create_dir_all(index_dir).unwrap();
+        path.join("registry")
+            .join("cache")
+            .join("index.crates.io-1949cf8c6b5b557f")
+    }
+
+    #[test]
+    fn store_and_restore() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = CrateCache::new(temp_dir.path().join("cache"));
+        let checksum = Sha256::digest(CRATE_CONTENT).encode_hex::<String>();
+        let lockfile = mock_lockfile(&checksum);
+
+        // Archives downloaded by one `CARGO_HOME` are cached.
+        let registry_cache = mock_cargo_home(&temp_dir.path().join("first"));
+        fs::create_dir_all(&registry_cache).unwrap();
+        fs::write(registry_cache.join("serde-1.0.0.crate"), CRATE_CONTENT).unwrap();
+        assert_eq!(
+            cache
+                .store(&temp_dir.path().join("first"), &lockfile)
+                .unwrap(),
+            1
+        );
+        assert_eq!(fs::read(cache.path(&checksum)).unwrap(), CRATE_CONTENT);
+
+        // And restored into another.
+        let registry_cache = mock_cargo_home(&temp_dir.path().join("second"));
+        assert_eq!(
+            cache
+                .restore(&temp_dir.path().join("second"), &lockfile, None)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            fs::read(registry_cache.join("serde-1.0.0.crate")).unwrap(),
+            CRATE_CONTENT
+        );
+
+        // Archives already in place are left alone.
+        assert_eq!(
+            cache
+                .restore(&temp_dir.path().join("second"), &lockfile, None)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn store_skips_checksum_mismatch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = CrateCache::new(temp_dir.path().join("cache"));
+        let checksum = Sha256::digest(b"other content").encode_hex::<String>();
+        let lockfile = mock_lockfile(&checksum);
+
+        let registry_cache = mock_cargo_home(temp_dir.path());
+        fs::create_dir_all(&registry_cache).unwrap();
+        fs::write(registry_cache.join("serde-1.0.0.crate"), CRATE_CONTENT).unwrap();
+
+        assert_eq!(cache.store(temp_dir.path(), &lockfile).unwrap(), 0);
+        assert!(!cache.path(&checksum).exists());
+    }
+
+    #[test]
+    fn restore_without_fetched_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = CrateCache::new(temp_dir.path().join("cache"));
+        let checksum = Sha256::digest(CRATE_CONTENT).encode_hex::<String>();
+        write_atomic(&cache.path(&checksum), CRATE_CONTENT).unwrap();
+        let lockfile = mock_lockfile(&checksum);
+
+        // Without knowing where cargo keeps crates.io archives, nothing can be restored.
+        let cargo_home = temp_dir.path().join("cargo_home");
+        assert_eq!(cache.restore(&cargo_home, &lockfile, None).unwrap(), 0);
+        assert!(!cargo_home.exists());
+
+        // Otherwise crates.io archives are restored before the index is first fetched.
+        assert_eq!(
+            cache
+                .restore(
+                    &cargo_home,
+                    &lockfile,
+                    Some("index.crates.io-1949cf8c6b5b557f")
+                )
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            fs::read(
+                cargo_home
+                    .join("registry")
+                    .join("cache")
+                    .join("index.crates.io-1949cf8c6b5b557f")
+                    .join("serde-1.0.0.crate")
+            )
+            .unwrap(),
+            CRATE_CONTENT
+        );
+    }
+}
@@ -1,8 +1,10 @@
 //! Gathering dependencies is the largest part of annotating.
 
 use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
 
 use anyhow::{bail, Result};
+use camino::Utf8Path;
 use cargo_metadata::{
     DependencyKind, Metadata as CargoMetadata, Node, NodeDep, Package, PackageId, Target,
 };
@@ -27,6 +29,20 @@ pub(crate) struct Dependency {
     pub(crate) alias: Option<String>,
 }
 
+/// A dependency on the binaries of a crate through a Cargo
+/// [artifact dependency](https://doc.rust-lang.org/nightly/cargo/reference/unstable.html#artifact-dependencies).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct ArtifactDependency {
+    /// The PackageId of the target
+    pub(crate) package_id: PackageId,
+
+    /// The name of the dependency from the perspective of the current package
+    pub(crate) name: String,
+
+    /// The names of the required binary targets
+    pub(crate) bins: BTreeSet<String>,
+}
+
 /// A collection of [Dependency]s sorted by dependency kind.
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct DependencySet {
@@ -37,6 +53,10 @@ pub(crate) struct DependencySet {
     pub(crate) build_deps: Select<BTreeSet<Dependency>>,
     pub(crate) build_link_deps: Select<BTreeSet<Dependency>>,
     pub(crate) build_proc_macro_deps: Select<BTreeSet<Dependency>>,
+    #[serde(default)]
+    pub(crate) artifact_deps: Select<BTreeSet<ArtifactDependency>>,
+    #[serde(default)]
+    pub(crate) build_artifact_deps: Select<BTreeSet<ArtifactDependency>>,
 }
 
 impl DependencySet {
@@ -65,6 +85,14 @@ impl DependencySet {
             CrateId::from(package)
         };
 
+        // Dependencies only on the binaries of a package are not linked
+        let artifacts = ArtifactSpecs::new(&metadata[&node.id], &metadata.workspace_root);
+        let is_linked = |dep: &&NodeDep| {
+            artifacts
+                .get(&metadata[&dep.pkg])
+                .is_none_or(|artifact| artifact.lib)
+        };
+
         let (normal_dev_deps, normal_deps) = {
             let (dev, normal) = node
                 .deps
@@ -72,7 +100,9 @@ impl DependencySet {
                 // Do not track workspace members as dependencies. Users are expected to maintain those connections
                 .filter(|dep| !is_workspace_member(dep, metadata))
                 .filter(|dep| is_lib_package(&metadata[&dep.pkg]))
+                .filter(is_linked)
                 .filter(|dep| is_normal_dependency(dep) || is_dev_dependency(dep))
+                .filter(|dep| !closes_dev_dependency_cycle(node, dep, metadata))
                 .partition(|dep| is_dev_dependency(dep));
 
             (
@@ -100,7 +130,9 @@ impl DependencySet {
                 // Do not track workspace members as dependencies. Users are expected to maintain those connections
                 .filter(|dep| !is_workspace_member(dep, metadata))
                 .filter(|dep| is_proc_macro_package(&metadata[&dep.pkg]))
+                .filter(is_linked)
                 .filter(|dep| is_normal_dependency(dep) || is_dev_dependency(dep))
+                .filter(|dep| !closes_dev_dependency_cycle(node, dep, metadata))
                 .partition(|dep| is_dev_dependency(dep));
 
             (
@@ -131,6 +163,7 @@ impl DependencySet {
                 .filter(|dep| !is_workspace_member(dep, metadata))
                 .filter(|dep| is_build_dependency(dep))
                 .filter(|dep| !is_dev_dependency(dep))
+                .filter(is_linked)
                 .partition(|dep| is_proc_macro_package(&metadata[&dep.pkg]));
 
             (
@@ -169,6 +202,48 @@ impl DependencySet {
             build_link_deps.insert(dependency.clone(), configuration.clone());
         }
 
+        let mut artifact_deps: Select<BTreeSet<ArtifactDependency>> = Select::default();
+        let mut build_artifact_deps: Select<BTreeSet<ArtifactDependency>> = Select::default();
+        for dep in node
+            .deps
+            .iter()
+            .filter(|dep| !is_workspace_member(dep, metadata))
+        {
+            let dep_pkg = &metadata[&dep.pkg];
+            let Some(artifact) = artifacts.get(dep_pkg) else {
+                continue;
+            };
+
+            let artifact_dependency = ArtifactDependency {
+                package_id: dep.pkg.clone(),
+                name: artifact.name.clone(),
+                bins: dep_pkg
+                    .targets
+                    .iter()
+                    .filter(|target| is_bin_target(target))
+                    .map(|target| target.name.clone())
+                    .filter(|name| {
+                        artifact
+                            .bins
+                            .as_ref()
+                            .is_none_or(|bins| bins.contains(name))
+                    })
+                    .collect(),
+            };
+            for kind_info in &dep.dep_kinds {
+                let configuration = kind_info.target.as_ref().map(ToString::to_string);
+                match kind_info.kind {
+                    DependencyKind::Normal => {
+                        artifact_deps.insert(artifact_dependency.clone(), configuration)
+                    }
+                    DependencyKind::Build => {
+                        build_artifact_deps.insert(artifact_dependency.clone(), configuration)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
         Self {
             normal_deps,
             normal_dev_deps,
@@ -177,7 +252,132 @@ impl DependencySet {
             build_deps,
             build_link_deps,
             build_proc_macro_deps,
+            artifact_deps,
+            build_artifact_deps,
+        }
+    }
+}
+
+/// The `artifact` settings of a dependency in a package's manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ArtifactSpec {
+    /// The name of the dependency
+    name: String,
+
+    /// The required binaries, or `None` for all of them
+    bins: Option<BTreeSet<String>>,
+
+    /// Whether or not the library of the dependency is linked as well
+    lib: bool,
+}
+
+/// The artifact dependencies of a package, keyed by the name of the package they depend on.
+struct ArtifactSpecs(BTreeMap<String, ArtifactSpec>);
+
+impl ArtifactSpecs {
+    fn new(package: &Package, workspace_root: &Utf8Path) -> Self {
+        let read_manifest = |path: &Utf8Path| {
+            fs::read_to_string(path)
+                .ok()
+                .and_then(|content| content.parse::<toml::Table>().ok())
+                .unwrap_or_default()
+        };
+        let manifest = read_manifest(&package.manifest_path);
+
+        // Only local packages can inherit dependencies from the workspace as
+        // the manifests of published packages are normalized.
+        let workspace_manifest = if package.source.is_none() {
+            read_manifest(&workspace_root.join("Cargo.toml"))
+        } else {
+            toml::Table::default()
+        };
+        let workspace_dependencies = workspace_manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies"))
+            .and_then(toml::Value::as_table);
+
+        Self(Self::from_manifest(&manifest, workspace_dependencies))
+    }
+
+    fn from_manifest(
+        manifest: &toml::Table,
+        workspace_dependencies: Option<&toml::Table>,
+    ) -> BTreeMap<String, ArtifactSpec> {
+        let platform_tables = manifest
+            .get("target")
+            .and_then(toml::Value::as_table)
+            .into_iter()
+            .flat_map(|targets| targets.values().filter_map(toml::Value::as_table));
+
+        std::iter::once(manifest)
+            .chain(platform_tables)
+            .flat_map(|table| {
+                ["dependencies", "build-dependencies"]
+                    .into_iter()
+                    .filter_map(|key| table.get(key).and_then(toml::Value::as_table))
+            })
+            .flat_map(|dependencies| dependencies.iter())
+            .filter_map(|(name, dependency)| {
+                let mut dependency = dependency.as_table()?;
+
+                // The `artifact` settings of inherited dependencies are defined by the workspace
+                if dependency.get("workspace").and_then(toml::Value::as_bool) == Some(true) {
+                    dependency = workspace_dependencies?.get(name)?.as_table()?;
+                }
+
+                let kinds: Vec<&str> = match dependency.get("artifact")? {
+                    toml::Value::String(kind) => vec![kind.as_str()],
+                    toml::Value::Array(kinds) => {
+                        kinds.iter().filter_map(toml::Value::as_str).collect()
+                    }
+                    _ => return None,
+                };
+
+                let mut bins = Some(BTreeSet::new());
+                for kind in kinds {
+                    match (kind, &mut bins) {
+                        ("bin", _) => bins = None,
+                        (kind, Some(bins)) if kind.starts_with("bin:") => {
+                            bins.insert(kind["bin:".len()..].to_owned());
+                        }
+                        _ => {}
+                    }
+                }
+
+                let package = dependency
+                    .get("package")
+                    .and_then(toml::Value::as_str)
+                    .unwrap_or(name);
+                Some((
+                    package.to_owned(),
+                    ArtifactSpec {
+                        name: name.clone(),
+                        bins,
+                        lib: dependency
+                            .get("lib")
+                            .and_then(toml::Value::as_bool)
+                            .unwrap_or(false),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// The artifact settings of a dependency on `package`. Packages without a library can only
+    /// be depended on as artifacts, so this falls back to all of their binaries.
+    fn get(&self, package: &Package) -> Option<ArtifactSpec> {
+        if let Some(spec) = self.0.get(&package.name) {
+            return Some(spec.clone());
         }
+
+        let has_bins = package.targets.iter().any(is_bin_target);
+        (has_bins && !is_lib_package(package) && !is_proc_macro_package(package)).then(|| {
+            ArtifactSpec {
+                name: package.name.clone(),
+                bins: None,
+                lib: false,
+            }
+        })
     }
 }
 
@@ -276,6 +476,10 @@ fn is_ignored_package_target(target: &Target) -> bool {
     })
 }
 
+fn is_bin_target(target: &Target) -> bool {
+    target.kind.contains(&cargo_metadata::TargetKind::Bin)
+}
+
 fn is_lib_package(package: &Package) -> bool {
     package.targets.iter().any(|target| {
         target.crate_types.iter().any(|t| {
@@ -309,6 +513,63 @@ fn is_dev_dependency(node_dep: &NodeDep) -> bool {
     !is_normal_dep && is_dev_dep
 }
 
+/// Whether `node_dep` is a dev dependency of `node` which depends on `node` itself through
+/// normal or build dependencies, e.g. a crate whose tests use a crate depending on it.
+fn closes_dev_dependency_cycle(node: &Node, node_dep: &NodeDep, metadata: &CargoMetadata) -> bool {
+    if !is_dev_dependency(node_dep) {
+        return false;
+    }
+    let Some(resolve) = &metadata.resolve else {
+        return false;
+    };
+
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![&node_dep.pkg];
+    while let Some(id) = pending.pop() {
+        if id == &node.id {
+            return true;
+        }
+        if !visited.insert(id) {
+            continue;
+        }
+        let Some(dep_node) = resolve.nodes.iter().find(|dep_node| &dep_node.id == id) else {
+            continue;
+        };
+        pending.extend(
+            dep_node
+                .deps
+                .iter()
+                .filter(|dep| is_normal_dependency(dep) || is_build_dependency(dep))
+                .map(|dep| &dep.pkg),
+        );
+    }
+    false
+}
+
+/// The dev dependencies of each package which depend on the package itself. Cargo allows these
+/// cycles as tests are built separately from the library they test, but they are left out of
+/// the dependencies of the package's test targets so the rendered targets don't form a cycle.
+pub(crate) fn dev_dependency_cycles(
+    metadata: &CargoMetadata,
+) -> BTreeMap<CrateId, BTreeSet<CrateId>> {
+    let Some(resolve) = &metadata.resolve else {
+        return BTreeMap::new();
+    };
+
+    let mut cycles: BTreeMap<CrateId, BTreeSet<CrateId>> = BTreeMap::new();
+    for node in &resolve.nodes {
+        for dep in &node.deps {
+            if closes_dev_dependency_cycle(node, dep, metadata) {
+                cycles
+                    .entry(CrateId::from(&metadata[&node.id]))
+                    .or_default()
+                    .insert(CrateId::from(&metadata[&dep.pkg]));
+            }
+        }
+    }
+    cycles
+}
+
 fn is_build_dependency(node_dep: &NodeDep) -> bool {
     node_dep
         .dep_kinds
@@ -398,6 +659,80 @@ mod test {
     use crate::metadata::CargoTreeEntry;
     use crate::test::*;
 
+    #[test]
+    fn parse_artifact_specs() {
+        let manifest: toml::Table = indoc::indoc! {r#"
+            [package]
+            name = "mock"
+            version = "0.1.0"
+
+            [dependencies]
+            regular = "1.0"
+            tool = { version = "1.0", artifact = "bin" }
+
+            [build-dependencies]
+            codegen = { version = "1.0", artifact = ["bin:gen", "bin:fmt"], lib = true }
+            renamed = { package = "real-name", version = "1.0", artifact = "bin:real" }
+
+            [target.'cfg(windows)'.build-dependencies]
+            windows-tool = { version = "1.0", artifact = ["bin", "cdylib"] }
+            inherited-tool = { workspace = true, features = ["cli"] }
+        "#}
+        .parse()
+        .unwrap();
+        let workspace_dependencies: toml::Table = indoc::indoc! {r#"
+            inherited-tool = { version = "1.0", artifact = "bin:inherited" }
+        "#}
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            ArtifactSpecs::from_manifest(&manifest, Some(&workspace_dependencies)),
+            BTreeMap::from([
+                (
+                    "codegen".to_owned(),
+                    ArtifactSpec {
+                        name: "codegen".to_owned(),
+                        bins: Some(BTreeSet::from(["fmt".to_owned(), "gen".to_owned()])),
+                        lib: true,
+                    }
+                ),
+                (
+                    "inherited-tool".to_owned(),
+                    ArtifactSpec {
+                        name: "inherited-tool".to_owned(),
+                        bins: Some(BTreeSet::from(["inherited".to_owned()])),
+                        lib: false,
+                    }
+                ),
+                (
+                    "real-name".to_owned(),
+                    ArtifactSpec {
+                        name: "renamed".to_owned(),
+                        bins: Some(BTreeSet::from(["real".to_owned()])),
+                        lib: false,
+                    }
+                ),
+                (
+                    "tool".to_owned(),
+                    ArtifactSpec {
+                        name: "tool".to_owned(),
+                        bins: None,
+                        lib: false,
+                    }
+                ),
+                (
+                    "windows-tool".to_owned(),
+                    ArtifactSpec {
+                        name: "windows-tool".to_owned(),
+                        bins: None,
+                        lib: false,
+                    }
+                ),
+            ])
+        );
+    }
+
     #[test]
     fn get_expected_lib_target_name() {
         let mut package = mock_cargo_metadata_package();
@@ -582,6 +917,57 @@ mod test {
         assert_eq!(async_executor.alias, Some("executor".to_owned()));
     }
 
+    #[test]
+    fn dev_dependency_cycle() {
+        let mut metadata = metadata::common();
+        let common_id = find_metadata_node("common", &metadata).id.clone();
+        let bitflags_id = find_metadata_node("bitflags", &metadata).id.clone();
+
+        // `common` uses `bitflags` in its tests only, which in turn depends on `common`.
+        let nodes = &mut metadata.resolve.as_mut().unwrap().nodes;
+        for node in nodes.iter_mut() {
+            if node.id == common_id {
+                let dep = node
+                    .deps
+                    .iter_mut()
+                    .find(|dep| dep.pkg == bitflags_id)
+                    .unwrap();
+                dep.dep_kinds = serde_json::from_value(serde_json::json!([
+                    {"kind": "dev", "target": null}
+                ]))
+                .unwrap();
+            } else if node.id == bitflags_id {
+                node.deps.push(
+                    serde_json::from_value(serde_json::json!({
+                        "name": "common",
+                        "pkg": common_id.repr,
+                        "dep_kinds": [{"kind": null, "target": null}],
+                    }))
+                    .unwrap(),
+                );
+            }
+        }
+
+        let node = find_metadata_node("common", &metadata);
+        let dependencies =
+            DependencySet::new_for_node(node, &metadata, &TreeResolverMetadata::default());
+        assert!(!dependencies
+            .normal_dev_deps
+            .values()
+            .iter()
+            .chain(dependencies.normal_deps.values().iter())
+            .any(|dep| dep.package_id == bitflags_id));
+
+        assert_eq!(
+            dev_dependency_cycles(&metadata),
+            BTreeMap::from([(
+                CrateId::from(&metadata[&common_id]),
+                BTreeSet::from([CrateId::from(&metadata[&bitflags_id])]),
+            )])
+        );
+        assert!(dev_dependency_cycles(&metadata::common()).is_empty());
+    }
+
     #[test]
     fn sys_dependencies() {
         let metadata = metadata::build_scripts();
@@ -0,0 +1,300 @@
+//! Reporting and deduplication of crates resolved at several semver incompatible versions.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use cargo_lock::Lockfile as CargoLockfile;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::metadata::Cargo;
+
+/// The version each copy of a duplicated crate is updated to when deduplicating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum DedupStrategy {
+    /// Update every copy to the lowest resolved version.
+    MinimalVersions,
+
+    /// Update every copy to the highest resolved version.
+    Highest,
+}
+
+/// Settings for reporting and deduplicating crates resolved at several versions.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DuplicateCrates {
+    /// Whether to warn about each crate resolved at several versions along with the
+    /// dependency chains which pull in each version.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) report: bool,
+
+    /// The strategy used to deduplicate crates when repinning.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) strategy: Option<DedupStrategy>,
+
+    /// A mapping of crate names to the version every copy of the crate is updated to when
+    /// repinning. Pins take precedence over `strategy`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) pins: BTreeMap<String, Version>,
+}
+
+impl DuplicateCrates {
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The version the copies of the crate `name` resolved at `versions` should be updated to.
+    fn target_version<'a>(
+        &'a self,
+        name: &str,
+        versions: &'a BTreeSet<Version>,
+    ) -> Option<&'a Version> {
+        if let Some(pin) = self.pins.get(name) {
+            return Some(pin);
+        }
+        match self.strategy? {
+            DedupStrategy::MinimalVersions => versions.first(),
+            DedupStrategy::Highest => versions.last(),
+        }
+    }
+
+    /// Attempt to update every copy of each duplicated crate in the lockfile next to
+    /// `manifest_path` to a single version. Copies which can't be updated as the requirements
+    /// of their dependents don't allow it are left in place. Returns whether the lockfile changed.
+    pub(crate) fn deduplicate(&self, manifest_path: &Path, cargo_bin: &Cargo) -> Result<bool> {
+        if self.strategy.is_none() && self.pins.is_empty() {
+            return Ok(false);
+        }
+
+        let manifest_dir = manifest_path.parent().unwrap();
+        let lockfile_path = manifest_dir.join("Cargo.lock");
+        let lockfile = CargoLockfile::load(&lockfile_path)
+            .context("Failed to load the lockfile to deduplicate")?;
+
+        let mut changed = false;
+        for (name, versions) in find_duplicates(&lockfile) {
+            let Some(target) = self.target_version(&name, &versions) else {
+                continue;
+            };
+            for version in versions.iter().filter(|version| *version != target) {
+                let mut command = cargo_bin.command()?;
+                command
+                    // Cargo detects config files based on `pwd` when running so
+                    // to ensure user provided Cargo config files are used, it's
+                    // critical to set the working directory to the manifest dir.
+                    .current_dir(manifest_dir)
+                    .arg("update")
+                    .arg("--manifest-path")
+                    .arg(manifest_path)
+                    .arg("--package")
+                    .arg(format!("{name}@{version}"))
+                    .arg("--precise")
+                    .arg(target.to_string());
+                let output = cargo_bin
+                    .output_with_retries(&mut command)
+                    .with_context(|| format!("Error running cargo to deduplicate {name}"))?;
+
+                if output.status.success() {
+                    info!("Deduplicated {name} {version} -> {target}");
+                    changed = true;
+                } else {
+                    debug!(
+                        "Could not update {name} {version} to {target}: {}",
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+/// The names of crates resolved at several versions along with their versions.
+fn find_duplicates(lockfile: &CargoLockfile) -> BTreeMap<String, BTreeSet<Version>> {
+    let mut versions: BTreeMap<String, BTreeSet<Version>> = BTreeMap::new();
+    for package in &lockfile.packages {
+        versions
+            .entry(package.name.to_string())
+            .or_default()
+            .insert(package.version.clone());
+    }
+    versions.retain(|_, versions| versions.len() > 1);
+    versions
+}
+
+/// Describe each crate resolved at several versions in `lockfile` along with the shortest
+/// dependency chain from a workspace member to each of its versions.
+pub(crate) fn duplicate_crate_report(lockfile: &CargoLockfile) -> Vec<String> {
+    let duplicates = find_duplicates(lockfile);
+    if duplicates.is_empty() {
+        return Vec::new();
+    }
+
+    // The dependencies of each package, keyed by name and version.
+    let dependencies: BTreeMap<(String, Version), Vec<(String, Version)>> = lockfile
+        .packages
+        .iter()
+        .map(|package| {
+            (
+                (package.name.to_string(), package.version.clone()),
+                package
+                    .dependencies
+                    .iter()
+                    .map(|dep| (dep.name.to_string(), dep.version.clone()))
+                    .collect(),
+            )
+        })
+        .collect();
+
+    // Find the shortest chain to each package with a breadth first search from the
+    // workspace members, which are the only packages without a source.
+    let mut chains: BTreeMap<(String, Version), Vec<(String, Version)>> = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    for package in lockfile.packages.iter().filter(|pkg| pkg.source.is_none()) {
+        let id = (package.name.to_string(), package.version.clone());
+        chains.insert(id.clone(), vec![id.clone()]);
+        queue.push_back(id);
+    }
+    while let Some(id) = queue.pop_front() {
+        for dep in dependencies.get(&id).into_iter().flatten() {
+            if chains.contains_key(dep) {
+                continue;
+            }
+            let mut chain = chains[&id].clone();
+            chain.push(dep.clone());
+            chains.insert(dep.clone(), chain);
+            queue.push_back(dep.clone());
+        }
+    }
+
+    duplicates
+        .into_iter()
+        .map(|(name, versions)| {
+            let details = versions
+                .into_iter()
+                .map(|version| {
+                    let chain = match chains.get(&(name.clone(), version.clone())) {
+                        Some(chain) => chain
+                            .iter()
+                            .map(|(name, version)| format!("{name} {version}"))
+                            .collect::<Vec<_>>()
+                            .join(" -> "),
+                        None => "not reachable from a workspace member".to_owned(),
+                    };
+                    format!("\n  {version}: {chain}")
+                })
+                .collect::<String>();
+            format!("{name} is resolved at multiple versions:{details}")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::str::FromStr;
+
+    fn mock_lockfile() -> CargoLockfile {
+        CargoLockfile::from_str(
+            r#"
+version = 3
+
+[[package]]
+name = "direct-cargo-bazel-deps"
+version = "0.0.1"
+dependencies = [
+ "foo",
+ "syn 2.0.0",
+]
+
+[[package]]
+name = "foo"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+dependencies = [
+ "syn 1.0.109",
+]
+
+[[package]]
+name = "syn"
+version = "1.0.109"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+[[package]]
+name = "syn"
+version = "2.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn report_duplicates() {
+        assert_eq!(
+            duplicate_crate_report(&mock_lockfile()),
+            vec![concat!(
+                "syn is resolved at multiple versions:",
+                "\n  1.0.109: direct-cargo-bazel-deps 0.0.1 -> foo 1.0.0 -> syn 1.0.109",
+                "\n  2.0.0: direct-cargo-bazel-deps 0.0.1 -> syn 2.0.0",
+            )]
+        );
+    }
+
+    #[test]
+    fn target_versions() {
+        let versions = BTreeSet::from([Version::new(1, 0, 109), Version::new(2, 0, 0)]);
+
+        let duplicates = DuplicateCrates::default();
+        assert_eq!(duplicates.target_version("syn", &versions), None);
+
+        let duplicates = DuplicateCrates {
+            strategy: Some(DedupStrategy::MinimalVersions),
+            ..DuplicateCrates::default()
+        };
+        assert_eq!(
+            duplicates.target_version("syn", &versions),
+            Some(&Version::new(1, 0, 109))
+        );
+
+        let duplicates = DuplicateCrates {
+            strategy: Some(DedupStrategy::Highest),
+            pins: BTreeMap::from([("syn".to_owned(), Version::new(1, 0, 100))]),
+            ..DuplicateCrates::default()
+        };
+        assert_eq!(
+            duplicates.target_version("syn", &versions),
+            Some(&Version::new(1, 0, 100))
+        );
+        assert_eq!(
+            duplicates.target_version("quote", &versions),
+            Some(&Version::new(2, 0, 0))
+        );
+    }
+
+    #[test]
+    fn deserialize_duplicate_crates() {
+        let duplicates: DuplicateCrates = serde_json::from_value(serde_json::json!({
+            "report": true,
+            "strategy": "minimal-versions",
+            "pins": {"syn": "2.0.0"},
+        }))
+        .unwrap();
+        assert_eq!(
+            duplicates,
+            DuplicateCrates {
+                report: true,
+                strategy: Some(DedupStrategy::MinimalVersions),
+                pins: BTreeMap::from([("syn".to_owned(), Version::new(2, 0, 0))]),
+            }
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! A report of the features enabled on each crate of a dependency graph along with the crates
+//! and features requesting them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CrateId;
+use crate::metadata::Annotations;
+
+/// A crate or feature requesting a feature of one of its dependencies, or one of its own.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct FeatureRequest {
+    /// The requesting crate.
+    #[serde(rename = "crate")]
+    pub(crate) krate: CrateId,
+
+    /// The feature of the requesting crate which enables the feature, or `None` if the
+    /// dependency declaration of the requesting crate does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) feature: Option<String>,
+}
+
+/// A feature enabled on a crate.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FeatureActivation {
+    /// The platforms the feature is enabled on, or `None` if it is enabled on all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) platforms: Option<BTreeSet<String>>,
+
+    /// The crates and features requesting the feature.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) requested_by: BTreeSet<FeatureRequest>,
+}
+
+/// The features enabled on each crate of a dependency graph as resolved for the target
+/// configuration, keyed by crate and feature.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FeatureReport {
+    pub(crate) crates: BTreeMap<CrateId, BTreeMap<String, FeatureActivation>>,
+}
+
+impl FeatureReport {
+    /// Collect the features of the crates of `annotations` and the requests enabling them.
+    pub(crate) fn new(annotations: &Annotations) -> Self {
+        let metadata = &annotations.metadata;
+
+        let mut crates: BTreeMap<CrateId, BTreeMap<String, FeatureActivation>> = BTreeMap::new();
+        for (crate_id, select) in &metadata.workspace_metadata.tree_metadata {
+            let mut configurations: BTreeMap<String, BTreeSet<Option<String>>> = BTreeMap::new();
+            for (configuration, entry) in select.items() {
+                for feature in entry.features {
+                    configurations
+                        .entry(feature)
+                        .or_default()
+                        .insert(configuration.clone());
+                }
+            }
+            let features: BTreeMap<String, FeatureActivation> = configurations
+                .into_iter()
+                .map(|(feature, configurations)| {
+                    let platforms = configurations.into_iter().collect::<Option<BTreeSet<_>>>();
+                    (
+                        feature,
+                        FeatureActivation {
+                            platforms,
+                            requested_by: BTreeSet::new(),
+                        },
+                    )
+                })
+                .collect();
+            if !features.is_empty() {
+                crates.insert(crate_id.clone(), features);
+            }
+        }
+
+        let mut requests: Vec<(CrateId, String, FeatureRequest)> = Vec::new();
+        for (package_id, package) in &metadata.packages {
+            let requester = CrateId::from(package);
+            let enabled: BTreeSet<&String> = crates
+                .get(&requester)
+                .into_iter()
+                .flat_map(BTreeMap::keys)
+                .collect();
+
+            // Features of the crate enabling other features of its own.
+            for (feature, entries) in &package.features {
+                if !enabled.contains(feature) {
+                    continue;
+                }
+                for entry in entries {
+                    if entry.contains('/') || entry.starts_with("dep:") {
+                        continue;
+                    }
+                    requests.push((
+                        requester.clone(),
+                        entry.clone(),
+                        FeatureRequest {
+                            krate: requester.clone(),
+                            feature: Some(feature.clone()),
+                        },
+                    ));
+                }
+            }
+
+            let Some(annotation) = metadata.crates.get(package_id) else {
+                continue;
+            };
+            for node_dep in &annotation.node.deps {
+                let Some(dependency) = metadata.packages.get(&node_dep.pkg) else {
+                    continue;
+                };
+                let dependency_id = CrateId::from(dependency);
+                let declarations = package.dependencies.iter().filter(|dep| {
+                    dep.name == dependency.name && dep.req.matches(&dependency.version)
+                });
+                for declaration in declarations {
+                    // Features requested by the dependency declaration.
+                    let declared = declaration.features.iter().cloned().chain(
+                        declaration
+                            .uses_default_features
+                            .then(|| "default".to_owned()),
+                    );
+                    for feature in declared {
+                        requests.push((
+                            dependency_id.clone(),
+                            feature,
+                            FeatureRequest {
+                                krate: requester.clone(),
+                                feature: None,
+                            },
+                        ));
+                    }
+
+                    // Features of the crate enabling `{dependency}/{feature}`.
+                    let key = declaration.rename.as_ref().unwrap_or(&declaration.name);
+                    for (feature, entries) in &package.features {
+                        if !enabled.contains(feature) {
+                            continue;
+                        }
+                        for entry in entries {
+                            let Some((dep, dep_feature)) = entry.split_once('/') else {
+                                continue;
+                            };
+                            if dep.trim_end_matches('?') != key {
+                                continue;
+                            }
+                            requests.push((
+                                dependency_id.clone(),
+                                dep_feature.to_owned(),
+                                FeatureRequest {
+                                    krate: requester.clone(),
+                                    feature: Some(feature.clone()),
+                                },
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Only requests for features which are enabled are kept, e.g. requests of dev
+        // dependencies pruned by `strict_dev_dependencies` are not.
+        for (crate_id, feature, request) in requests {
+            if let Some(activation) = crates
+                .get_mut(&crate_id)
+                .and_then(|features| features.get_mut(&feature))
+            {
+                activation.requested_by.insert(request);
+            }
+        }
+
+        Self { crates }
+    }
+
+    /// Load a report written by [FeatureReport::write].
+    pub(crate) fn try_from_path(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read feature report `{}`", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse feature report `{}`", path.display()))
+    }
+
+    /// Write the report to `path` as `json`.
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content + "\n")
+            .with_context(|| format!("Failed to write feature report `{}`", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use camino::Utf8Path;
+
+    use crate::config::Config;
+    use crate::metadata::CargoTreeEntry;
+    use crate::select::Select;
+    use crate::test;
+
+    #[test]
+    fn report_feature_requests() {
+        let mut metadata = test::metadata::common();
+        for package in metadata.packages.iter_mut() {
+            if package.name == "bitflags" {
+                package
+                    .features
+                    .insert("default".to_owned(), vec!["example_generated".to_owned()]);
+            }
+        }
+        let mut annotations = Annotations::new(
+            metadata,
+            test::lockfile::common(),
+            Config::default(),
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap();
+
+        let bitflags = CrateId::new("bitflags".to_owned(), semver::Version::new(1, 3, 2));
+        let common = CrateId::new("common".to_owned(), semver::Version::new(0, 1, 0));
+        let mut select = Select::new();
+        select.insert(
+            CargoTreeEntry {
+                features: BTreeSet::from(["default".to_owned()]),
+                deps: BTreeSet::new(),
+            },
+            None,
+        );
+        select.insert(
+            CargoTreeEntry {
+                features: BTreeSet::from(["example_generated".to_owned()]),
+                deps: BTreeSet::new(),
+            },
+            Some("x86_64-unknown-linux-gnu".to_owned()),
+        );
+        let tree_metadata = &mut annotations.metadata.workspace_metadata.tree_metadata;
+        tree_metadata.insert(bitflags.clone(), select);
+        tree_metadata.insert(common.clone(), Select::new());
+
+        let report = FeatureReport::new(&annotations);
+        assert_eq!(
+            report.crates,
+            BTreeMap::from([(
+                bitflags.clone(),
+                BTreeMap::from([
+                    (
+                        "default".to_owned(),
+                        FeatureActivation {
+                            platforms: None,
+                            requested_by: BTreeSet::from([FeatureRequest {
+                                krate: common,
+                                feature: None,
+                            }]),
+                        },
+                    ),
+                    (
+                        "example_generated".to_owned(),
+                        FeatureActivation {
+                            platforms: Some(BTreeSet::from(
+                                ["x86_64-unknown-linux-gnu".to_owned()]
+                            )),
+                            requested_by: BTreeSet::from([FeatureRequest {
+                                krate: bitflags,
+                                feature: Some("default".to_owned()),
+                            }]),
+                        },
+                    ),
+                ]),
+            )])
+        );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("features.json");
+        report.write(&path).unwrap();
+        assert_eq!(FeatureReport::try_from_path(&path).unwrap(), report);
+    }
+}
@@ -0,0 +1,108 @@
+//! Detection of crates declaring the same [`links`](https://doc.rust-lang.org/cargo/reference/build-scripts.html#the-links-manifest-key)
+//! value, which can't be linked into the same binary.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use anyhow::{bail, Result};
+use cargo_metadata::{Metadata as CargoMetadata, PackageId};
+
+/// Fail if several crates of the resolve graph in `metadata` declare the same `links` value,
+/// describing the shortest dependency path from a workspace member to each of them.
+pub(crate) fn check_links_conflicts(metadata: &CargoMetadata) -> Result<()> {
+    let mut links: BTreeMap<&str, BTreeSet<&PackageId>> = BTreeMap::new();
+    for package in &metadata.packages {
+        if let Some(name) = &package.links {
+            links.entry(name.as_str()).or_default().insert(&package.id);
+        }
+    }
+    links.retain(|_, packages| packages.len() > 1);
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let paths = dependency_paths(metadata);
+    let describe = |id: &PackageId| {
+        let package = &metadata[id];
+        let path = match paths.get(id) {
+            Some(path) => path
+                .iter()
+                .map(|id| format!("{} {}", metadata[*id].name, metadata[*id].version))
+                .collect::<Vec<_>>()
+                .join(" -> "),
+            None => "not reachable from a workspace member".to_owned(),
+        };
+        format!("\n    {} {}: {path}", package.name, package.version)
+    };
+
+    let conflicts = links
+        .into_iter()
+        .map(|(name, packages)| {
+            let details = packages.into_iter().map(describe).collect::<String>();
+            format!("\n  `links = \"{name}\"` is declared by:{details}")
+        })
+        .collect::<String>();
+    bail!("Multiple crates link to the same native library:{conflicts}")
+}
+
+/// The shortest path from a workspace member to each package of the resolve graph.
+fn dependency_paths(metadata: &CargoMetadata) -> BTreeMap<&PackageId, Vec<&PackageId>> {
+    let Some(resolve) = &metadata.resolve else {
+        return BTreeMap::new();
+    };
+    let nodes: BTreeMap<&PackageId, _> =
+        resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+
+    let mut paths: BTreeMap<&PackageId, Vec<&PackageId>> = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    for member in &metadata.workspace_members {
+        paths.insert(member, vec![member]);
+        queue.push_back(member);
+    }
+    while let Some(id) = queue.pop_front() {
+        let Some(node) = nodes.get(id) else {
+            continue;
+        };
+        for dep in &node.deps {
+            if paths.contains_key(&dep.pkg) {
+                continue;
+            }
+            let mut path = paths[id].clone();
+            path.push(&dep.pkg);
+            paths.insert(&dep.pkg, path);
+            queue.push_back(&dep.pkg);
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test;
+
+    #[test]
+    fn no_links_conflicts() {
+        check_links_conflicts(&test::metadata::common()).unwrap();
+    }
+
+    #[test]
+    fn links_conflicts() {
+        let mut metadata = test::metadata::common();
+        let mut linked = Vec::new();
+        for package in metadata.packages.iter_mut() {
+            if package.name == "bitflags" || package.name == "cfg-if" {
+                package.links = Some("foo".to_owned());
+                linked.push(format!("{} {}", package.name, package.version));
+            }
+        }
+
+        let error = check_links_conflicts(&metadata).unwrap_err().to_string();
+        assert!(error.starts_with(
+            "Multiple crates link to the same native library:\n  `links = \"foo\"` is declared by:"
+        ));
+        for package in linked {
+            assert!(error.contains(&format!("\n    {package}: ")), "{error}");
+        }
+    }
+}
@@ -2,17 +2,23 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
+use std::process::Command;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use cargo_metadata::{Node, Package, PackageId};
 use hex::ToHex;
 use serde::{Deserialize, Serialize};
 
 use crate::config::{Commitish, Config, CrateAnnotations, CrateId};
-use crate::metadata::dependency::DependencySet;
-use crate::metadata::TreeResolverMetadata;
+use crate::metadata::dependency::{dev_dependency_cycles, DependencySet};
+use crate::metadata::{
+    check_links_conflicts, ExecTreeResolverMetadata, IsolatedTreeResolverMetadata, ProfileFlags,
+    TreeResolverMetadata,
+};
+use crate::select::Select;
 use crate::splicing::{SourceInfo, WorkspaceMetadata};
+use crate::utils::sanitize_module_name;
 
 pub(crate) type CargoMetadata = cargo_metadata::Metadata;
 pub(crate) type CargoLockfile = cargo_lock::Lockfile;
@@ -25,8 +31,31 @@ pub(crate) struct CrateAnnotation {
 
     /// The crate's sorted dependencies.
     pub(crate) deps: DependencySet,
+
+    /// Instances of the crate for workspace members resolving it differently when features are
+    /// not unified across the workspace, keyed by the name of the instance. Crates resolved
+    /// differently for the exec configuration also have an [EXEC_INSTANCE].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) instances: BTreeMap<String, CrateInstanceAnnotation>,
 }
 
+/// A crate as resolved for a subset of workspace members.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CrateInstanceAnnotation {
+    /// The workspace members using the instance.
+    pub(crate) workspace_members: BTreeSet<CrateId>,
+
+    /// The instance's sorted dependencies.
+    pub(crate) deps: DependencySet,
+}
+
+/// The name of the instance of crates built in the exec configuration by build scripts and
+/// proc macros, which has no workspace members.
+pub(crate) const EXEC_INSTANCE: &str = "exec";
+
+/// A mapping of crates to the name of the instance used by each workspace member.
+pub(crate) type CrateInstances = BTreeMap<CrateId, BTreeMap<CrateId, String>>;
+
 /// Additional information about a Cargo workspace's metadata.
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct MetadataAnnotation {
@@ -44,6 +73,14 @@ pub(crate) struct MetadataAnnotation {
 
     /// Information on the Cargo workspace.
     pub(crate) workspace_metadata: WorkspaceMetadata,
+
+    /// The instances of crates used by each workspace member.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) crate_instances: CrateInstances,
+
+    /// The dev dependencies of each crate which depend on the crate itself.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) dev_dependency_cycles: BTreeMap<CrateId, BTreeSet<CrateId>>,
 }
 
 impl MetadataAnnotation {
@@ -68,6 +105,9 @@ impl MetadataAnnotation {
             .map(|node| node.id.clone())
             .collect();
 
+        let crate_instances = partition_crate_instances(&workspace_metadata.isolated_tree_metadata);
+        let dev_dependency_cycles = dev_dependency_cycles(&metadata);
+
         let crates = resolve
             .nodes
             .iter()
@@ -78,6 +118,9 @@ impl MetadataAnnotation {
                         node.clone(),
                         &metadata,
                         &workspace_metadata.tree_metadata,
+                        &workspace_metadata.isolated_tree_metadata,
+                        &workspace_metadata.exec_tree_metadata,
+                        &crate_instances,
                     ),
                 )
             })
@@ -95,6 +138,8 @@ impl MetadataAnnotation {
             workspace_members,
             workspace_root: PathBuf::from(metadata.workspace_root.as_std_path()),
             workspace_metadata,
+            crate_instances,
+            dev_dependency_cycles,
         }
     }
 
@@ -102,14 +147,128 @@ impl MetadataAnnotation {
         node: Node,
         metadata: &CargoMetadata,
         resolver_data: &TreeResolverMetadata,
+        isolated_resolver_data: &IsolatedTreeResolverMetadata,
+        exec_resolver_data: &ExecTreeResolverMetadata,
+        crate_instances: &CrateInstances,
     ) -> CrateAnnotation {
         // Gather all dependencies
         let deps = DependencySet::new_for_node(&node, metadata, resolver_data);
 
-        CrateAnnotation { node, deps }
+        // Gather the dependencies of each instance as resolved for its workspace members
+        let mut instances: BTreeMap<String, CrateInstanceAnnotation> = BTreeMap::new();
+        let crate_id = CrateId::from(&metadata[&node.id]);
+        for (member, name) in crate_instances.get(&crate_id).into_iter().flatten() {
+            instances
+                .entry(name.clone())
+                .or_insert_with(|| CrateInstanceAnnotation {
+                    workspace_members: BTreeSet::new(),
+                    deps: DependencySet::new_for_node(
+                        &node,
+                        metadata,
+                        &isolated_resolver_data[member],
+                    ),
+                })
+                .workspace_members
+                .insert(member.clone());
+        }
+
+        // Gather the dependencies of the crate as resolved for the exec configuration
+        if exec_resolver_data.instances.contains_key(&crate_id) {
+            instances.insert(
+                EXEC_INSTANCE.to_owned(),
+                CrateInstanceAnnotation {
+                    workspace_members: BTreeSet::new(),
+                    deps: DependencySet::new_for_node(
+                        &node,
+                        metadata,
+                        &exec_resolver_data.instances,
+                    ),
+                },
+            );
+        }
+
+        CrateAnnotation {
+            node,
+            deps,
+            instances,
+        }
     }
 }
 
+/// Group the workspace members using each crate by how the crate is resolved for them, taking
+/// into account how its dependencies are grouped. Crates which are resolved the same way for
+/// all of their users are omitted. Instances are named after the first member using them.
+fn partition_crate_instances(isolated: &IsolatedTreeResolverMetadata) -> CrateInstances {
+    let mut users: BTreeMap<&CrateId, BTreeSet<&CrateId>> = BTreeMap::new();
+    for (member, tree_data) in isolated {
+        for id in tree_data.keys() {
+            // Workspace members are only ever built once.
+            if !isolated.contains_key(id) {
+                users.entry(id).or_default().insert(member);
+            }
+        }
+    }
+
+    // Refine the groups until the groups of all dependencies are accounted for. Crates start
+    // out grouped by their own features and dependencies alone.
+    let mut groups: BTreeMap<&CrateId, BTreeMap<&CrateId, &CrateId>> = BTreeMap::new();
+    loop {
+        let mut refined: BTreeMap<&CrateId, BTreeMap<&CrateId, &CrateId>> = BTreeMap::new();
+        for (id, members) in &users {
+            let mut signatures = Vec::new();
+            for member in members {
+                let tree_data = &isolated[*member][*id];
+                let dep_groups: BTreeSet<(CrateId, Option<&CrateId>)> = tree_data
+                    .values()
+                    .into_iter()
+                    .flat_map(|entry| entry.deps)
+                    .map(|dep| {
+                        let group = groups
+                            .get(&dep)
+                            .and_then(|dep_groups| dep_groups.get(member))
+                            .copied();
+                        (dep, group)
+                    })
+                    .collect();
+
+                let first_member = match signatures
+                    .iter()
+                    .find(|(data, deps, _)| *data == tree_data && *deps == dep_groups)
+                {
+                    Some((_, _, first_member)) => *first_member,
+                    None => {
+                        signatures.push((tree_data, dep_groups, *member));
+                        *member
+                    }
+                };
+                refined
+                    .entry(*id)
+                    .or_default()
+                    .insert(*member, first_member);
+            }
+        }
+
+        if refined == groups {
+            break;
+        }
+        groups = refined;
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, members)| members.values().collect::<BTreeSet<_>>().len() > 1)
+        .map(|(id, members)| {
+            let instances = members
+                .into_iter()
+                .map(|(member, first_member)| {
+                    (member.clone(), sanitize_module_name(&first_member.name))
+                })
+                .collect();
+            (id.clone(), instances)
+        })
+        .collect()
+}
+
 /// Additional information about how and where to acquire a crate's source code from.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub(crate) enum SourceAnnotation {
@@ -142,6 +301,11 @@ pub(crate) enum SourceAnnotation {
         /// See [git_repository::patches](https://docs.bazel.build/versions/main/repo/git.html#git_repository-patches)
         #[serde(default, skip_serializing_if = "Option::is_none")]
         patches: Option<BTreeSet<String>>,
+
+        /// The commits of the (possibly nested) submodules of the repository, keyed by their
+        /// paths relative to the repository root. These are pinned by `commitish`.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        submodules: BTreeMap<String, String>,
     },
     Http {
         /// See [http_archive::url](https://docs.bazel.build/versions/main/repo/http.html#http_archive-url)
@@ -229,10 +393,10 @@ impl LockfileAnnotation {
         workspace_metadata: &WorkspaceMetadata,
         nonhermetic_root_bazel_workspace_dir: &Utf8Path,
     ) -> Result<SourceAnnotation> {
-        let pkg = &metadata[&node.id];
+        let mut pkg = &metadata[&node.id];
 
         // Locate the matching lock package for the current crate
-        let lock_pkg = match cargo_meta_pkg_to_locked_pkg(pkg, &lockfile.packages) {
+        let mut lock_pkg = match cargo_meta_pkg_to_locked_pkg(pkg, &lockfile.packages) {
             Some(lock_pkg) => lock_pkg,
             None => bail!(
                 "Could not find lockfile entry matching metadata package '{}'",
@@ -240,6 +404,32 @@ impl LockfileAnnotation {
             ),
         };
 
+        // Crates overridden by a `[replace]` entry are sourced from their replacement
+        if let Some(replacement) = &lock_pkg.replace {
+            lock_pkg = lockfile
+                .packages
+                .iter()
+                .find(|candidate| {
+                    candidate.name == replacement.name
+                        && candidate.version == replacement.version
+                        && candidate.source == replacement.source
+                })
+                .with_context(|| {
+                    format!(
+                        "Could not find lockfile entry for `{}`, the replacement of '{}'",
+                        replacement, pkg.name
+                    )
+                })?;
+            let source = lock_pkg.source.as_ref().map(ToString::to_string);
+            if let Some(replacement_pkg) = metadata.packages.iter().find(|candidate| {
+                candidate.name == lock_pkg.name.as_str()
+                    && candidate.version == lock_pkg.version
+                    && candidate.source.as_ref().map(|s| &s.repr) == source.as_ref()
+            }) {
+                pkg = replacement_pkg;
+            }
+        }
+
         // Check for spliced information about a crate's network source.
         let spliced_source_info = Self::find_source_annotation(lock_pkg, workspace_metadata);
 
@@ -295,18 +485,37 @@ impl LockfileAnnotation {
         // Handle any git repositories
         if let Some(git_ref) = source.git_reference() {
             let strip_prefix = Self::extract_git_strip_prefix(pkg)?;
+            let commitish = source
+                .precise()
+                .map(|rev| Commitish::Rev(rev.to_string()))
+                .unwrap_or(Commitish::from(git_ref.clone()));
+
+            // Git is only run for the checkouts of git repositories, which Bazel needs git to
+            // fetch as well.
+            let mut shallow_since = None;
+            let mut submodules = BTreeMap::new();
+            if let Some(checkout) = Self::extract_git_checkout(pkg) {
+                // Bazel only fetches the history of a pinned commit since this date.
+                if let Commitish::Rev(_) = commitish {
+                    shallow_since =
+                        Some(git_commit_date(checkout).with_context(|| {
+                            format!("Failed to get the commit date of {}", pkg.id)
+                        })?);
+                }
+                collect_git_submodules(checkout, "", &mut submodules).with_context(|| {
+                    format!("Failed to collect the git submodules of {}", pkg.id)
+                })?;
+            }
 
             return Ok(SourceAnnotation::Git {
                 remote: source.url().to_string(),
-                commitish: source
-                    .precise()
-                    .map(|rev| Commitish::Rev(rev.to_string()))
-                    .unwrap_or(Commitish::from(git_ref.clone())),
-                shallow_since: None,
+                commitish,
+                shallow_since,
                 strip_prefix,
                 patch_args: None,
                 patch_tool: None,
                 patches: None,
+                submodules,
             });
         }
 
@@ -391,6 +600,98 @@ impl LockfileAnnotation {
         }
         bail!("Expected git package to have a manifest path of pattern {{CARGO_HOME}}/git/checkouts/[name]-[hash]/[short-sha]/.../Cargo.toml but {:?} had manifest path {}", pkg.id, pkg.manifest_path);
     }
+
+    /// Find the checkout of the git repository of `pkg` in which cargo checked out its commit.
+    fn extract_git_checkout(pkg: &Package) -> Option<&Utf8Path> {
+        // {CARGO_HOME}/git/checkouts/name-hash/short-sha/[strip_prefix...]/Cargo.toml
+        pkg.manifest_path
+            .ancestors()
+            .filter(|path| {
+                let mut parents = path.ancestors().skip(2).map(Utf8Path::file_name);
+                parents.next() == Some(Some("checkouts")) && parents.next() == Some(Some("git"))
+            })
+            .last()
+            .filter(|checkout| checkout.join(".git").exists())
+    }
+}
+
+/// Run git in `repository` and return its output.
+fn git(repository: &Utf8Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repository)
+        .args(args)
+        .output()
+        .context("Failed to run git, which is required for crates from git repositories")?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed in {}: {}",
+            args.join(" "),
+            repository,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    String::from_utf8(output.stdout).context("Unexpected git output")
+}
+
+/// The date of the commit checked out in `repository`, in the raw format used for
+/// [git_repository::shallow_since](https://bazel.build/rules/lib/repo/git#git_repository-shallow_since).
+fn git_commit_date(repository: &Utf8Path) -> Result<String> {
+    Ok(git(
+        repository,
+        &["log", "-1", "--date=raw", "--format=%cd", "HEAD"],
+    )?
+    .trim()
+    .to_owned())
+}
+
+/// Record the commits of the submodules of the git checkout at `repository`, recursing into
+/// submodules which were checked out themselves, whose checkouts must match the records.
+fn collect_git_submodules(
+    repository: &Utf8Path,
+    prefix: &str,
+    submodules: &mut BTreeMap<String, String>,
+) -> Result<()> {
+    if !repository.join(".gitmodules").exists() {
+        return Ok(());
+    }
+
+    let ls_tree = git(repository, &["ls-tree", "-r", "-z", "HEAD"])?;
+    for (path, commit) in parse_gitlinks(ls_tree.as_bytes())? {
+        let submodule = repository.join(&path);
+        let path = format!("{prefix}{path}");
+        if submodule.join(".git").exists() {
+            let checked_out = git(&submodule, &["rev-parse", "HEAD"])?;
+            if checked_out.trim() != commit {
+                bail!(
+                    "The submodule {} is checked out at {} but the repository records {}",
+                    path,
+                    checked_out.trim(),
+                    commit
+                );
+            }
+            collect_git_submodules(&submodule, &format!("{path}/"), submodules)?;
+        }
+        submodules.insert(path, commit);
+    }
+
+    Ok(())
+}
+
+/// Parse the paths and commits of submodules from the output of `git ls-tree -z`.
+fn parse_gitlinks(ls_tree: &[u8]) -> Result<Vec<(String, String)>> {
+    let mut gitlinks = Vec::new();
+    for entry in ls_tree.split(|byte| *byte == 0) {
+        // Entries are formatted as `{mode} {type} {object}\t{path}`.
+        let entry = std::str::from_utf8(entry).context("Unexpected git tree entry")?;
+        let Some((info, path)) = entry.split_once('\t') else {
+            continue;
+        };
+        if let ["160000", "commit", commit] = info.split(' ').collect::<Vec<_>>()[..] {
+            gitlinks.push((path.to_owned(), commit.to_owned()));
+        }
+    }
+    Ok(gitlinks)
 }
 
 /// A pairing of a crate's package identifier to its annotations.
@@ -417,6 +718,9 @@ pub(crate) struct Annotations {
 
     /// Pairred crate annotations
     pub(crate) pairred_extras: BTreeMap<CrateId, PairedExtras>,
+
+    /// The settings of the selected Cargo profile which couldn't be approximated.
+    pub(crate) unmapped_profile_settings: Vec<String>,
 }
 
 impl Annotations {
@@ -426,7 +730,10 @@ impl Annotations {
         config: Config,
         nonhermetic_root_bazel_workspace_dir: &Utf8Path,
     ) -> Result<Self> {
-        let lockfile_annotation = LockfileAnnotation::new(
+        // Crates linking the same native library would only fail deep into the build
+        check_links_conflicts(&cargo_metadata)?;
+
+        let mut lockfile_annotation = LockfileAnnotation::new(
             cargo_lockfile,
             &cargo_metadata,
             nonhermetic_root_bazel_workspace_dir,
@@ -437,8 +744,16 @@ impl Annotations {
 
         let mut unused_extra_annotations = config.annotations.clone();
 
+        // Approximate the settings of the selected Cargo profile
+        let profile_flags = config
+            .cargo_profile
+            .as_deref()
+            .map(|name| ProfileFlags::new(&metadata_annotation.workspace_metadata.profiles, name))
+            .transpose()
+            .context("Failed to map the Cargo profile")?;
+
         // Ensure each override matches a particular package
-        let pairred_extras = metadata_annotation
+        let pairred_extras: BTreeMap<CrateId, PairedExtras> = metadata_annotation
             .packages
             .iter()
             .filter_map(|(pkg_id, pkg)| {
@@ -458,6 +773,20 @@ impl Annotations {
 
                 crate_extra.apply_defaults_from_package_metadata(&pkg.metadata);
 
+                if let Some(profile_flags) = &profile_flags {
+                    let flags = profile_flags
+                        .rustc_flags(pkg, metadata_annotation.workspace_members.contains(pkg_id));
+                    if !flags.is_empty() {
+                        // Flags from annotations come last to take precedence.
+                        crate_extra.rustc_flags = Some(match crate_extra.rustc_flags.take() {
+                            Some(rustc_flags) => {
+                                Select::merge(Select::from_value(flags), rustc_flags)
+                            }
+                            None => Select::from_value(flags),
+                        });
+                    }
+                }
+
                 if crate_extra == CrateAnnotations::default() {
                     None
                 } else {
@@ -480,16 +809,165 @@ impl Annotations {
             );
         }
 
+        // Replace the sources of crates overridden by a local fork
+        for (crate_id, extras) in &pairred_extras {
+            let Some(override_path) = &extras.crate_extra.override_path else {
+                continue;
+            };
+            let Some(source) = lockfile_annotation.crates.get_mut(&extras.package_id) else {
+                bail!(
+                    "The `override_path` annotation of {} can't be applied to workspace members",
+                    crate_id
+                );
+            };
+            *source = SourceAnnotation::Path {
+                path: resolve_override_path(nonhermetic_root_bazel_workspace_dir, override_path)?,
+            };
+        }
+
+        // Ensure features pruned by annotations are not required by any dependents
+        for extras in pairred_extras.values() {
+            validate_pruned_features(
+                &metadata_annotation,
+                &extras.package_id,
+                &extras.crate_extra,
+            )?;
+        }
+
         // Annotate metadata
         Ok(Annotations {
             metadata: metadata_annotation,
             lockfile: lockfile_annotation,
             config,
             pairred_extras,
+            unmapped_profile_settings: profile_flags
+                .map(|profile_flags| profile_flags.unmapped)
+                .unwrap_or_default(),
         })
     }
 }
 
+/// Resolve the `override_path` annotation of a crate, either a package of the root workspace
+/// (`//path/to/fork`) or a path relative to it, to the directory holding the fork's sources.
+fn resolve_override_path(
+    nonhermetic_root_bazel_workspace_dir: &Utf8Path,
+    override_path: &str,
+) -> Result<Utf8PathBuf> {
+    if override_path.starts_with('@') {
+        bail!("`override_path` must be a package of the root workspace, found `{override_path}`");
+    }
+    let path = override_path.strip_prefix("//").unwrap_or(override_path);
+    if path.contains(':') {
+        bail!(
+            "`override_path` must name a directory rather than a target, found `{override_path}`"
+        );
+    }
+
+    Ok(nonhermetic_root_bazel_workspace_dir.join(path))
+}
+
+/// Ensure the features a crate's annotations remove or override are not explicitly
+/// enabled by any of its dependents, as they would fail to compile without them.
+fn validate_pruned_features(
+    metadata: &MetadataAnnotation,
+    package_id: &PackageId,
+    crate_extra: &CrateAnnotations,
+) -> Result<()> {
+    if crate_extra.override_crate_features.is_none() && crate_extra.remove_crate_features.is_none()
+    {
+        return Ok(());
+    }
+
+    let added_features: BTreeSet<String> = crate_extra
+        .crate_features
+        .iter()
+        .flat_map(|features| features.items())
+        .filter_map(|(configuration, feature)| configuration.is_none().then_some(feature))
+        .collect();
+    let is_pruned = |feature: &String| {
+        let removed = crate_extra
+            .remove_crate_features
+            .as_ref()
+            .is_some_and(|removed| removed.contains(feature));
+        let overridden = crate_extra
+            .override_crate_features
+            .as_ref()
+            .is_some_and(|features| {
+                !features.contains(feature) && !added_features.contains(feature)
+            });
+        removed || overridden
+    };
+
+    let package = &metadata.packages[package_id];
+    let mut violations: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (dependent_id, annotation) in &metadata.crates {
+        if !annotation
+            .node
+            .deps
+            .iter()
+            .any(|dep| &dep.pkg == package_id)
+        {
+            continue;
+        }
+
+        let dependent = &metadata.packages[dependent_id];
+        let requested = dependent
+            .dependencies
+            .iter()
+            .filter(|dep| dep.name == package.name && dep.req.matches(&package.version))
+            .flat_map(|dep| dep.features.iter().cloned());
+        for feature in implied_features(package, requested) {
+            if is_pruned(&feature) {
+                violations
+                    .entry(feature)
+                    .or_default()
+                    .insert(format!("{} {}", dependent.name, dependent.version));
+            }
+        }
+    }
+
+    if !violations.is_empty() {
+        bail!(
+            "The annotations of {} {} remove features which its dependents enable: {}",
+            package.name,
+            package.version,
+            violations
+                .iter()
+                .map(|(feature, dependents)| format!(
+                    "`{feature}` (required by {})",
+                    dependents.iter().cloned().collect::<Vec<_>>().join(", ")
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The features of a package enabled by `features`, including the ones they imply.
+fn implied_features(
+    package: &Package,
+    features: impl IntoIterator<Item = String>,
+) -> BTreeSet<String> {
+    let mut enabled = BTreeSet::new();
+    let mut queue: Vec<String> = features.into_iter().collect();
+    while let Some(feature) = queue.pop() {
+        if let Some(implied) = package.features.get(&feature) {
+            // Only features of the package itself, not `dep:` or `{dep}/{feature}` entries
+            queue.extend(
+                implied
+                    .iter()
+                    .filter(|implied| package.features.contains_key(*implied))
+                    .filter(|implied| !enabled.contains(*implied))
+                    .cloned(),
+            );
+        }
+        enabled.insert(feature);
+    }
+    enabled
+}
+
 fn find_workspace_metadata(cargo_metadata: &CargoMetadata) -> Option<WorkspaceMetadata> {
     WorkspaceMetadata::try_from(cargo_metadata.workspace_metadata.clone()).ok()
 }
@@ -517,9 +995,17 @@ fn cargo_meta_pkg_to_locked_pkg<'a>(
     pkg: &Package,
     lock_packages: &'a [cargo_lock::Package],
 ) -> Option<&'a cargo_lock::Package> {
-    lock_packages
+    let mut candidates = lock_packages
         .iter()
-        .find(|lock_pkg| lock_pkg.name.as_str() == pkg.name && lock_pkg.version == pkg.version)
+        .filter(|lock_pkg| lock_pkg.name.as_str() == pkg.name && lock_pkg.version == pkg.version);
+
+    // A crate may be locked from multiple sources, e.g. when it's replaced or patched
+    // with a git repository, so the entry of the package's source is preferred.
+    let source = pkg.source.as_ref().map(|source| source.repr.as_str());
+    candidates
+        .clone()
+        .find(|lock_pkg| lock_pkg.source.as_ref().map(ToString::to_string).as_deref() == source)
+        .or_else(|| candidates.next())
 }
 
 #[cfg(test)]
@@ -531,7 +1017,6 @@ mod test {
 
     use crate::config::CrateNameAndVersionReq;
     use crate::metadata::CargoTreeEntry;
-    use crate::select::Select;
     use crate::test::*;
 
     #[test]
@@ -542,6 +1027,34 @@ mod test {
         assert!(cargo_meta_pkg_to_locked_pkg(&pkg, &vec![lock_pkg]).is_some())
     }
 
+    #[test]
+    fn test_cargo_meta_pkg_to_locked_pkg_prefers_source() {
+        let pkg = mock_cargo_metadata_package();
+        let mut git_lock_pkg = mock_cargo_lock_package();
+        git_lock_pkg.source = Some(
+            "git+https://github.com/mock/mock-pkg.git#3a5dc3d9f0c3e2e2b1e6e5f3e2c1e7d7f0a1b2c3"
+                .parse()
+                .unwrap(),
+        );
+        git_lock_pkg.checksum = None;
+        let lock_pkg = mock_cargo_lock_package();
+
+        let lock_packages = vec![git_lock_pkg.clone(), lock_pkg.clone()];
+        assert_eq!(
+            cargo_meta_pkg_to_locked_pkg(&pkg, &lock_packages),
+            Some(&lock_pkg)
+        );
+
+        let mut git_pkg = pkg.clone();
+        git_pkg.source =
+            serde_json::from_value(json!(git_lock_pkg.source.as_ref().unwrap().to_string()))
+                .unwrap();
+        assert_eq!(
+            cargo_meta_pkg_to_locked_pkg(&git_pkg, &lock_packages),
+            Some(&git_lock_pkg)
+        );
+    }
+
     #[test]
     fn annotate_metadata_with_aliases() {
         let annotations = MetadataAnnotation::new(test::metadata::alias());
@@ -645,6 +1158,88 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_submodule_gitlinks() {
+        let ls_tree = [
+            "100644 blob 3b18e512dba79e4c8300dd08aeb37f8e728b8dad\t.gitmodules",
+            "160000 commit 1e09e50e8d15580b5929adbade9c782a6833e4a0\tthird_party/zlib",
+            "100644 blob 8ab686eafeb1f44702738c8b0f24f2567c36da6d\tsrc/lib.rs",
+            "160000 commit 0a7e3c2b7d0c8e8d1b6f1f5e2a0a4b1c9d3e5f7a\tvendor/with space",
+            "",
+        ]
+        .join("\0");
+
+        assert_eq!(
+            parse_gitlinks(ls_tree.as_bytes()).unwrap(),
+            vec![
+                (
+                    "third_party/zlib".to_owned(),
+                    "1e09e50e8d15580b5929adbade9c782a6833e4a0".to_owned()
+                ),
+                (
+                    "vendor/with space".to_owned(),
+                    "0a7e3c2b7d0c8e8d1b6f1f5e2a0a4b1c9d3e5f7a".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_submodules_of_checkout() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(temp_dir.path()).unwrap();
+        let run = |dir: &Utf8Path, args: &[&str]| {
+            let config = [
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "-c",
+                "protocol.file.allow=always",
+            ];
+            git(dir, &[&config[..], args].concat()).unwrap()
+        };
+
+        let sub = root.join("sub");
+        std::fs::create_dir(&sub).unwrap();
+        run(&sub, &["init", "-q"]);
+        run(&sub, &["commit", "-q", "--allow-empty", "-m", "first"]);
+        let first = run(&sub, &["rev-parse", "HEAD"]).trim().to_owned();
+        run(&sub, &["commit", "-q", "--allow-empty", "-m", "second"]);
+
+        let checkout = root.join("checkout");
+        std::fs::create_dir(&checkout).unwrap();
+        run(&checkout, &["init", "-q"]);
+        run(
+            &checkout,
+            &["submodule", "add", "-q", sub.as_str(), "vendor/sub"],
+        );
+        run(&checkout.join("vendor/sub"), &["checkout", "-q", &first]);
+        run(&checkout, &["commit", "-q", "-am", "add submodule"]);
+
+        let mut submodules = BTreeMap::new();
+        collect_git_submodules(&checkout, "", &mut submodules).unwrap();
+        assert_eq!(
+            submodules,
+            BTreeMap::from([("vendor/sub".to_owned(), first.clone())])
+        );
+
+        let date = git_commit_date(&checkout).unwrap();
+        assert!(
+            date.split_once(' ').is_some_and(
+                |(time, zone)| time.parse::<u64>().is_ok() && zone.starts_with(['+', '-'])
+            ),
+            "{date}"
+        );
+
+        // Checkouts of submodules which don't match the recorded commits are rejected.
+        run(
+            &checkout.join("vendor/sub"),
+            &["checkout", "-q", "HEAD@{1}"],
+        );
+        assert!(collect_git_submodules(&checkout, "", &mut BTreeMap::new()).is_err());
+    }
+
     #[test]
     fn detect_unused_annotation() {
         // Create a config with some random annotation
@@ -712,6 +1307,107 @@ mod test {
         assert_eq!(*extras, expected);
     }
 
+    #[test]
+    fn prune_features_required_by_dependents() {
+        let annotate = |crate_extra: CrateAnnotations| {
+            let mut config = Config::default();
+            config.annotations.insert(
+                CrateNameAndVersionReq::new("libz-sys".to_owned(), "1.1.8".parse().unwrap()),
+                crate_extra,
+            );
+            Annotations::new(
+                test::metadata::build_scripts(),
+                test::lockfile::build_scripts(),
+                config,
+                Utf8Path::new("/tmp/bazelworkspace"),
+            )
+        };
+
+        // `stock-zlib` is only enabled by `default`
+        annotate(CrateAnnotations {
+            remove_crate_features: Some(BTreeSet::from(["stock-zlib".to_owned()])),
+            ..CrateAnnotations::default()
+        })
+        .unwrap();
+        annotate(CrateAnnotations {
+            override_crate_features: Some(BTreeSet::from(["libc".to_owned()])),
+            ..CrateAnnotations::default()
+        })
+        .unwrap();
+
+        // `libc` is explicitly enabled by `libgit2-sys` and `libssh2-sys`
+        let err = annotate(CrateAnnotations {
+            remove_crate_features: Some(BTreeSet::from(["libc".to_owned()])),
+            ..CrateAnnotations::default()
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("`libc` (required by libgit2-sys"), "{err}");
+        assert!(err.contains("libssh2-sys"), "{err}");
+        annotate(CrateAnnotations {
+            override_crate_features: Some(BTreeSet::from(["static".to_owned()])),
+            ..CrateAnnotations::default()
+        })
+        .unwrap_err();
+        annotate(CrateAnnotations {
+            override_crate_features: Some(BTreeSet::from(["static".to_owned()])),
+            crate_features: Some(Select::from_value(BTreeSet::from(["libc".to_owned()]))),
+            ..CrateAnnotations::default()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn override_path_replaces_crate_source() {
+        let annotate = |override_path: &str| {
+            let mut config = Config::default();
+            config.annotations.insert(
+                CrateNameAndVersionReq::new("libz-sys".to_owned(), "1.1.8".parse().unwrap()),
+                CrateAnnotations {
+                    override_path: Some(override_path.to_owned()),
+                    ..CrateAnnotations::default()
+                },
+            );
+            Annotations::new(
+                test::metadata::build_scripts(),
+                test::lockfile::build_scripts(),
+                config,
+                Utf8Path::new("/tmp/bazelworkspace"),
+            )
+        };
+
+        let annotations = annotate("//third_party/forks/libz-sys").unwrap();
+        let (_, source) = annotations
+            .lockfile
+            .crates
+            .iter()
+            .find(|(id, _)| id.repr.contains("libz-sys"))
+            .unwrap();
+        assert_eq!(
+            source,
+            &SourceAnnotation::Path {
+                path: Utf8PathBuf::from("/tmp/bazelworkspace/third_party/forks/libz-sys"),
+            }
+        );
+
+        // Other crates keep their registry sources.
+        assert!(annotations
+            .lockfile
+            .crates
+            .iter()
+            .filter(|(id, _)| !id.repr.contains("libz-sys"))
+            .all(|(_, source)| matches!(source, SourceAnnotation::Http { .. })));
+
+        let annotations = annotate("third_party/forks/libz-sys").unwrap();
+        assert!(annotations.lockfile.crates.values().any(|source| source
+            == &SourceAnnotation::Path {
+                path: Utf8PathBuf::from("/tmp/bazelworkspace/third_party/forks/libz-sys"),
+            }));
+
+        assert!(annotate("@other//third_party/forks/libz-sys").is_err());
+        assert!(annotate("//third_party/forks:libz-sys").is_err());
+    }
+
     #[test]
     fn test_find_workspace_metadata() {
         let mut metadata = metadata::common();
@@ -765,4 +1461,44 @@ mod test {
 
         assert_eq!(expected, result.tree_metadata);
     }
+
+    #[test]
+    fn partition_crate_instances_by_member_resolution() {
+        let id = |name: &str| CrateId::new(name.to_owned(), Version::new(1, 0, 0));
+        let entry = |features: &[&str], deps: &[&str]| {
+            Select::from_value(Some(CargoTreeEntry {
+                features: features.iter().map(|f| f.to_string()).collect(),
+                deps: deps.iter().map(|dep| id(dep)).collect(),
+            }))
+        };
+        let member = |serde_features: &[&str]| {
+            TreeResolverMetadata::from([
+                (id("serde"), entry(serde_features, &[])),
+                (id("serde_json"), entry(&["std"], &["serde", "log"])),
+                (id("log"), entry(&[], &[])),
+            ])
+        };
+        let isolated = IsolatedTreeResolverMetadata::from([
+            (id("member-a"), member(&["std"])),
+            (id("member-b"), member(&["alloc"])),
+            (id("member-c"), member(&["std"])),
+        ]);
+
+        let instances = partition_crate_instances(&isolated);
+
+        let expected_instances = BTreeMap::from([
+            (id("member-a"), "member_a".to_owned()),
+            (id("member-b"), "member_b".to_owned()),
+            (id("member-c"), "member_a".to_owned()),
+        ]);
+        assert_eq!(
+            instances,
+            CrateInstances::from([
+                // `serde` is resolved with different features.
+                (id("serde"), expected_instances.clone()),
+                // `serde_json` is resolved the same way, but depends on different instances.
+                (id("serde_json"), expected_instances),
+            ])
+        );
+    }
 }
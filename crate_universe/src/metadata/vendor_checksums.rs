@@ -0,0 +1,370 @@
+//! Integrity checks for crates vendored with [cargo vendor](https://doc.rust-lang.org/cargo/commands/cargo-vendor.html).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use hex::ToHex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::utils::parallel;
+
+/// The name of the lockfile written to the vendor directory.
+pub(crate) const VENDOR_LOCKFILE_NAME: &str = "cargo-bazel-vendor-lock.json";
+
+/// The file `cargo vendor` writes to each crate listing the checksums of its files.
+pub(crate) const CARGO_CHECKSUM_FILE_NAME: &str = ".cargo-checksum.json";
+
+/// How integrity issues of vendored crates are surfaced.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VendorIntegrity {
+    /// Issues are reported as errors.
+    #[default]
+    Fail,
+
+    /// Issues are logged as warnings.
+    Warn,
+}
+
+impl FromStr for VendorIntegrity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fail" => Ok(Self::Fail),
+            "warn" => Ok(Self::Warn),
+            _ => bail!("Unexpected vendor integrity mode `{s}`, expected `fail` or `warn`"),
+        }
+    }
+}
+
+impl VendorIntegrity {
+    /// Surface the `issues` found in vendored crates, prefixed by `description`.
+    pub(crate) fn report(&self, description: &str, issues: &[IntegrityIssue]) -> Result<()> {
+        if issues.is_empty() {
+            return Ok(());
+        }
+
+        let message = format!(
+            "{description}:\n  {}",
+            issues
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        );
+        match self {
+            Self::Fail => bail!(message),
+            Self::Warn => {
+                tracing::warn!("{message}");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A problem found with the sources of a vendored crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum IntegrityIssue {
+    /// The crate directory or its `.cargo-checksum.json` file is missing.
+    MissingCrate { krate: String },
+
+    /// A file of the crate is missing, e.g. due to a truncated extraction.
+    MissingFile { krate: String, file: String },
+
+    /// A file of the crate no longer matches the checksum `cargo vendor` recorded for it.
+    ModifiedFile { krate: String, file: String },
+
+    /// The checksum of the crate no longer matches the one recorded in the lockfile.
+    ChecksumMismatch { krate: String },
+
+    /// The crate was vendored but is not recorded in the lockfile.
+    Unrecorded { krate: String },
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingCrate { krate } => write!(f, "{krate}: the crate is missing"),
+            Self::MissingFile { krate, file } => {
+                write!(f, "{krate}: `{file}` is missing (truncated extraction?)")
+            }
+            Self::ModifiedFile { krate, file } => {
+                write!(f, "{krate}: `{file}` was modified")
+            }
+            Self::ChecksumMismatch { krate } => write!(
+                f,
+                "{krate}: the checksum does not match the one recorded in `{VENDOR_LOCKFILE_NAME}`"
+            ),
+            Self::Unrecorded { krate } => {
+                write!(
+                    f,
+                    "{krate}: the crate is not recorded in `{VENDOR_LOCKFILE_NAME}`"
+                )
+            }
+        }
+    }
+}
+
+/// The contents of a `.cargo-checksum.json` file.
+#[derive(Debug, Deserialize)]
+struct CargoChecksums {
+    files: BTreeMap<String, String>,
+}
+
+/// The SHA-256 checksums of all crates in a vendor directory, keyed by the
+/// name of their directory (`{name}-{version}`).
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct VendorChecksums {
+    pub(crate) crates: BTreeMap<String, String>,
+}
+
+impl VendorChecksums {
+    /// Compute the checksums of all crates in `vendor_dir` along with any files
+    /// which don't match the checksums `cargo vendor` recorded for them.
+    pub(crate) fn compute(vendor_dir: &Path) -> Result<(Self, Vec<IntegrityIssue>)> {
+        let crates = vendored_crates(vendor_dir)?;
+        let results = parallel::map(
+            "Computing vendored crate checksums",
+            crates.clone(),
+            parallel::default_jobs(),
+            |krate| crate_checksum(vendor_dir, &krate),
+        )?;
+
+        let mut checksums = Self::default();
+        let mut issues = Vec::new();
+        for (krate, (checksum, crate_issues)) in crates.into_iter().zip(results) {
+            if let Some(checksum) = checksum {
+                checksums.crates.insert(krate, checksum);
+            }
+            issues.extend(crate_issues);
+        }
+
+        Ok((checksums, issues))
+    }
+
+    pub(crate) fn try_from_path(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read `{}`", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse `{}`", path.display()))
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write `{}`", path.display()))
+    }
+
+    /// Check the crates in `vendor_dir` against the recorded checksums.
+    pub(crate) fn verify(&self, vendor_dir: &Path) -> Result<Vec<IntegrityIssue>> {
+        let (actual, mut issues) = Self::compute(vendor_dir)?;
+
+        for (krate, checksum) in &self.crates {
+            match actual.crates.get(krate) {
+                Some(actual) if actual == checksum => {}
+                Some(_) => issues.push(IntegrityIssue::ChecksumMismatch {
+                    krate: krate.clone(),
+                }),
+                None => issues.push(IntegrityIssue::MissingCrate {
+                    krate: krate.clone(),
+                }),
+            }
+        }
+        issues.extend(
+            actual
+                .crates
+                .into_keys()
+                .filter(|krate| !self.crates.contains_key(krate))
+                .map(|krate| IntegrityIssue::Unrecorded { krate }),
+        );
+
+        Ok(issues)
+    }
+}
+
+/// The names of the crate directories in `vendor_dir`.
+fn vendored_crates(vendor_dir: &Path) -> Result<Vec<String>> {
+    let mut crates = Vec::new();
+    if !vendor_dir.exists() {
+        return Ok(crates);
+    }
+
+    for entry in fs::read_dir(vendor_dir)
+        .with_context(|| format!("Failed to read `{}`", vendor_dir.display()))?
+    {
+        let entry = entry?;
+        if entry.path().join(CARGO_CHECKSUM_FILE_NAME).exists() {
+            crates.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    crates.sort();
+
+    Ok(crates)
+}
+
+fn sha256(content: &[u8]) -> String {
+    Sha256::digest(content).encode_hex::<String>()
+}
+
+/// Compute the checksum of a vendored crate from the files listed in its `.cargo-checksum.json`.
+fn crate_checksum(vendor_dir: &Path, krate: &str) -> Result<(Option<String>, Vec<IntegrityIssue>)> {
+    let crate_dir = vendor_dir.join(krate);
+    let Ok(content) = fs::read_to_string(crate_dir.join(CARGO_CHECKSUM_FILE_NAME)) else {
+        let issue = IntegrityIssue::MissingCrate {
+            krate: krate.to_owned(),
+        };
+        return Ok((None, vec![issue]));
+    };
+    let cargo_checksums: CargoChecksums = serde_json::from_str(&content).with_context(|| {
+        format!(
+            "Failed to parse `{}`",
+            crate_dir.join(CARGO_CHECKSUM_FILE_NAME).display()
+        )
+    })?;
+
+    let mut hasher = Sha256::new();
+    let mut issues = Vec::new();
+    for (file, expected) in &cargo_checksums.files {
+        let actual = match fs::read(crate_dir.join(file)) {
+            Ok(content) => sha256(&content),
+            Err(_) => {
+                issues.push(IntegrityIssue::MissingFile {
+                    krate: krate.to_owned(),
+                    file: file.clone(),
+                });
+                String::new()
+            }
+        };
+        if !actual.is_empty() && &actual != expected {
+            issues.push(IntegrityIssue::ModifiedFile {
+                krate: krate.to_owned(),
+                file: file.clone(),
+            });
+        }
+
+        hasher.update(file.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(actual.as_bytes());
+        hasher.update(b"\n");
+    }
+
+    Ok((Some(hasher.finalize().encode_hex::<String>()), issues))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mock_crate(vendor_dir: &Path, krate: &str, files: &[(&str, &str)]) {
+        let crate_dir = vendor_dir.join(krate);
+        let mut checksums = BTreeMap::new();
+        for (file, content) in files {
+            let path = crate_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+            checksums.insert(file.to_string(), sha256(content.as_bytes()));
+        }
+        fs::write(
+            crate_dir.join(CARGO_CHECKSUM_FILE_NAME),
+            serde_json::json!({"files": checksums, "package": null}).to_string(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn parse_vendor_integrity() {
+        assert_eq!(
+            VendorIntegrity::from_str("fail").unwrap(),
+            VendorIntegrity::Fail
+        );
+        assert_eq!(
+            VendorIntegrity::from_str("WARN").unwrap(),
+            VendorIntegrity::Warn
+        );
+        assert!(VendorIntegrity::from_str("ignore").is_err());
+    }
+
+    #[test]
+    fn verify_unmodified_crates() {
+        let vendor_dir = tempfile::tempdir().unwrap();
+        mock_crate(
+            vendor_dir.path(),
+            "a-1.0.0",
+            &[("Cargo.toml", "[package]"), ("src/lib.rs", "")],
+        );
+        mock_crate(vendor_dir.path(), "b-0.1.0", &[("lib.rs", "fn b() {}")]);
+        fs::write(vendor_dir.path().join("BUILD.bazel"), "").unwrap();
+
+        let (checksums, issues) = VendorChecksums::compute(vendor_dir.path()).unwrap();
+        assert!(issues.is_empty());
+        assert_eq!(
+            checksums.crates.keys().collect::<Vec<_>>(),
+            ["a-1.0.0", "b-0.1.0"]
+        );
+
+        // Files which are not part of the crates are ignored
+        fs::write(vendor_dir.path().join("a-1.0.0/BUILD.bazel"), "").unwrap();
+        assert!(checksums.verify(vendor_dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn verify_modified_crates() {
+        let vendor_dir = tempfile::tempdir().unwrap();
+        mock_crate(
+            vendor_dir.path(),
+            "a-1.0.0",
+            &[("Cargo.toml", "[package]"), ("src/lib.rs", "")],
+        );
+        mock_crate(vendor_dir.path(), "b-0.1.0", &[("lib.rs", "fn b() {}")]);
+        let (checksums, _) = VendorChecksums::compute(vendor_dir.path()).unwrap();
+
+        fs::write(vendor_dir.path().join("a-1.0.0/src/lib.rs"), "fn a() {}").unwrap();
+        fs::remove_file(vendor_dir.path().join("b-0.1.0/lib.rs")).unwrap();
+        mock_crate(vendor_dir.path(), "c-2.0.0", &[("lib.rs", "")]);
+
+        assert_eq!(
+            checksums.verify(vendor_dir.path()).unwrap(),
+            vec![
+                IntegrityIssue::ModifiedFile {
+                    krate: "a-1.0.0".to_owned(),
+                    file: "src/lib.rs".to_owned(),
+                },
+                IntegrityIssue::MissingFile {
+                    krate: "b-0.1.0".to_owned(),
+                    file: "lib.rs".to_owned(),
+                },
+                IntegrityIssue::ChecksumMismatch {
+                    krate: "a-1.0.0".to_owned(),
+                },
+                IntegrityIssue::ChecksumMismatch {
+                    krate: "b-0.1.0".to_owned(),
+                },
+                IntegrityIssue::Unrecorded {
+                    krate: "c-2.0.0".to_owned(),
+                },
+            ]
+        );
+
+        fs::remove_dir_all(vendor_dir.path().join("c-2.0.0")).unwrap();
+        fs::remove_dir_all(vendor_dir.path().join("a-1.0.0")).unwrap();
+        assert!(checksums.verify(vendor_dir.path()).unwrap().contains(
+            &IntegrityIssue::MissingCrate {
+                krate: "a-1.0.0".to_owned()
+            }
+        ));
+
+        assert!(VendorIntegrity::Warn
+            .report("Vendored sources were modified", &[])
+            .is_ok());
+        assert!(VendorIntegrity::Fail
+            .report(
+                "Vendored sources were modified",
+                &checksums.verify(vendor_dir.path()).unwrap()
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! Removal of the files of vendored crates which aren't needed to build them, such as tests,
+//! benches and examples, to keep vendored trees small.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path};
+
+use anyhow::{Context, Result};
+use cargo_metadata::{Package, TargetKind};
+use glob::Pattern;
+use walkdir::WalkDir;
+
+use crate::metadata::CARGO_CHECKSUM_FILE_NAME;
+
+/// Files which are kept regardless of the pruning settings.
+const ALWAYS_KEPT: &[&str] = &["Cargo.toml", CARGO_CHECKSUM_FILE_NAME];
+
+/// Settings for pruning vendored crates.
+///
+/// By default only the top level directories holding the sources of a crate's tests, benches and
+/// examples (e.g. `tests/`) are removed, unless they also hold sources of its library, binaries or
+/// build script. Additional files can be removed with `paths` and any file can be kept with `keep`,
+/// e.g. for fixtures a build script reads. Patterns are relative to the directory of each crate.
+#[derive(Debug, Default)]
+pub(crate) struct VendorPruning {
+    paths: Vec<Pattern>,
+    keep: Vec<Pattern>,
+}
+
+impl VendorPruning {
+    pub(crate) fn new(paths: &[String], keep: &[String]) -> Result<Self> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern)
+                        .with_context(|| format!("Invalid vendor pruning pattern `{pattern}`"))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            paths: parse(paths)?,
+            keep: parse(keep)?,
+        })
+    }
+
+    /// Prune each of `packages` vendored in `vendor_dir`, dropping the removed files from its
+    /// `.cargo-checksum.json` so Cargo and the integrity checks only verify the remaining ones.
+    /// Returns the number of files removed.
+    pub(crate) fn prune<'a>(
+        &self,
+        vendor_dir: &Path,
+        packages: impl IntoIterator<Item = &'a Package>,
+    ) -> Result<usize> {
+        let mut removed = 0;
+        for package in packages {
+            let crate_dir = vendor_dir.join(format!("{}-{}", package.name, package.version));
+            if !crate_dir.join(CARGO_CHECKSUM_FILE_NAME).exists() {
+                continue;
+            }
+
+            let files = self.pruned_files(package, &crate_dir)?;
+            if files.is_empty() {
+                continue;
+            }
+            for file in &files {
+                let path = crate_dir.join(file);
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove `{}`", path.display()))?;
+            }
+            remove_empty_dirs(&crate_dir)?;
+            update_cargo_checksums(&crate_dir, &files)?;
+
+            tracing::debug!("Pruned {} files from {}", files.len(), crate_dir.display());
+            removed += files.len();
+        }
+
+        Ok(removed)
+    }
+
+    /// The files of `package` vendored in `crate_dir` to remove, relative to `crate_dir`.
+    fn pruned_files(&self, package: &Package, crate_dir: &Path) -> Result<BTreeSet<String>> {
+        let pruned_dirs = pruned_dirs(package);
+
+        let mut files = BTreeSet::new();
+        for entry in WalkDir::new(crate_dir) {
+            let entry =
+                entry.with_context(|| format!("Failed to walk `{}`", crate_dir.display()))?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path().strip_prefix(crate_dir)?;
+            let file = path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            if ALWAYS_KEPT.contains(&file.as_str())
+                || self.keep.iter().any(|pattern| pattern.matches(&file))
+            {
+                continue;
+            }
+            let in_pruned_dir = match path.components().next() {
+                Some(Component::Normal(dir)) if path.components().count() > 1 => {
+                    pruned_dirs.contains(dir.to_string_lossy().as_ref())
+                }
+                _ => false,
+            };
+            if in_pruned_dir || self.paths.iter().any(|pattern| pattern.matches(&file)) {
+                files.insert(file);
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// The top level directories of `package` which only hold the sources of tests, benches and
+/// examples.
+fn pruned_dirs(package: &Package) -> BTreeSet<String> {
+    let Some(package_dir) = package.manifest_path.parent() else {
+        return BTreeSet::new();
+    };
+    let top_level_dir = |src_path: &camino::Utf8Path| {
+        let path = src_path.strip_prefix(package_dir).ok()?;
+        if path.components().count() < 2 {
+            return None;
+        }
+        path.components().next().map(|dir| dir.as_str().to_owned())
+    };
+
+    let mut pruned = BTreeSet::new();
+    let mut kept = BTreeSet::new();
+    for target in &package.targets {
+        let Some(dir) = top_level_dir(&target.src_path) else {
+            continue;
+        };
+        let is_auxiliary = target.kind.iter().all(|kind| {
+            matches!(
+                kind,
+                TargetKind::Test | TargetKind::Bench | TargetKind::Example
+            )
+        });
+        if is_auxiliary {
+            pruned.insert(dir);
+        } else {
+            kept.insert(dir);
+        }
+    }
+
+    pruned.retain(|dir| !kept.contains(dir));
+    pruned
+}
+
+/// Remove the directories under `dir` left empty by pruning.
+fn remove_empty_dirs(dir: &Path) -> Result<()> {
+    for entry in WalkDir::new(dir).min_depth(1).contents_first(true) {
+        let entry = entry.with_context(|| format!("Failed to walk `{}`", dir.display()))?;
+        if entry.file_type().is_dir() && fs::read_dir(entry.path())?.next().is_none() {
+            fs::remove_dir(entry.path())
+                .with_context(|| format!("Failed to remove `{}`", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Drop the removed `files` from the `.cargo-checksum.json` file of the crate in `crate_dir`,
+/// preserving the rest of its content.
+fn update_cargo_checksums(crate_dir: &Path, files: &BTreeSet<String>) -> Result<()> {
+    let path = crate_dir.join(CARGO_CHECKSUM_FILE_NAME);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read `{}`", path.display()))?;
+    let mut checksums: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+    if let Some(checksum_files) = checksums
+        .get_mut("files")
+        .and_then(serde_json::Value::as_object_mut)
+    {
+        checksum_files.retain(|file, _| !files.contains(file));
+    }
+    fs::write(&path, serde_json::to_string(&checksums)?)
+        .with_context(|| format!("Failed to write `{}`", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use hex::ToHex;
+    use sha2::{Digest, Sha256};
+
+    use crate::metadata::VendorChecksums;
+
+    fn mock_package(targets: &[(&str, &str)]) -> Package {
+        serde_json::from_value(serde_json::json!({
+            "name": "foo",
+            "version": "1.0.0",
+            "id": "registry+https://github.com/rust-lang/crates.io-index#foo@1.0.0",
+            "dependencies": [],
+            "targets": targets
+                .iter()
+                .map(|(kind, src_path)| serde_json::json!({
+                    "name": "foo",
+                    "kind": [kind],
+                    "src_path": format!("/registry/foo-1.0.0/{src_path}"),
+                }))
+                .collect::<Vec<_>>(),
+            "features": {},
+            "manifest_path": "/registry/foo-1.0.0/Cargo.toml",
+        }))
+        .unwrap()
+    }
+
+    fn mock_crate(vendor_dir: &Path, files: &[&str]) {
+        let crate_dir = vendor_dir.join("foo-1.0.0");
+        let mut checksums = BTreeMap::new();
+        for file in files {
+            let path = crate_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+            checksums.insert(
+                file.to_string(),
+                Sha256::digest(file.as_bytes()).encode_hex::<String>(),
+            );
+        }
+        fs::write(
+            crate_dir.join(CARGO_CHECKSUM_FILE_NAME),
+            serde_json::json!({"files": checksums, "package": "abc"}).to_string(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn prune_auxiliary_targets() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        mock_crate(
+            temp_dir.path(),
+            &[
+                "Cargo.toml",
+                "build.rs",
+                "src/lib.rs",
+                "src/bin/tool.rs",
+                "tests/it.rs",
+                "tests/fixtures/data.txt",
+                "benches/bench.rs",
+                "examples/demo.rs",
+                "docs/guide.md",
+            ],
+        );
+        let package = mock_package(&[
+            ("lib", "src/lib.rs"),
+            ("bin", "src/bin/tool.rs"),
+            ("custom-build", "build.rs"),
+            ("test", "tests/it.rs"),
+            ("bench", "benches/bench.rs"),
+            ("example", "examples/demo.rs"),
+            // Sharing a directory with the library keeps it.
+            ("test", "src/tests.rs"),
+        ]);
+
+        let pruning = VendorPruning::new(&[], &["tests/fixtures/**".to_owned()]).unwrap();
+        assert_eq!(pruning.prune(temp_dir.path(), [&package]).unwrap(), 3);
+
+        let crate_dir = temp_dir.path().join("foo-1.0.0");
+        for kept in [
+            "Cargo.toml",
+            "build.rs",
+            "src/lib.rs",
+            "src/bin/tool.rs",
+            "tests/fixtures/data.txt",
+            "docs/guide.md",
+        ] {
+            assert!(crate_dir.join(kept).exists(), "{kept}");
+        }
+        assert!(!crate_dir.join("tests/it.rs").exists());
+        assert!(!crate_dir.join("benches").exists());
+        assert!(!crate_dir.join("examples").exists());
+
+        // The checksums of the removed files are dropped so the crate still verifies.
+        let checksums: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(crate_dir.join(CARGO_CHECKSUM_FILE_NAME)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(checksums["package"], "abc");
+        assert_eq!(checksums["files"].as_object().unwrap().len(), 6);
+        let (_, issues) = VendorChecksums::compute(temp_dir.path()).unwrap();
+        assert!(issues.is_empty(), "{issues:?}");
+    }
+
+    #[test]
+    fn prune_additional_paths() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        mock_crate(
+            temp_dir.path(),
+            &["Cargo.toml", "src/lib.rs", "docs/guide.md", "docs/api.md"],
+        );
+        let package = mock_package(&[("lib", "src/lib.rs")]);
+
+        let pruning = VendorPruning::new(
+            &["docs/**".to_owned(), "Cargo.toml".to_owned()],
+            &["docs/api.md".to_owned()],
+        )
+        .unwrap();
+        assert_eq!(pruning.prune(temp_dir.path(), [&package]).unwrap(), 1);
+
+        let crate_dir = temp_dir.path().join("foo-1.0.0");
+        assert!(crate_dir.join("Cargo.toml").exists());
+        assert!(crate_dir.join("docs/api.md").exists());
+        assert!(!crate_dir.join("docs/guide.md").exists());
+
+        assert!(VendorPruning::new(&["[".to_owned()], &[]).is_err());
+    }
+}
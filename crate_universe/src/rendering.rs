@@ -10,17 +10,19 @@ use std::sync::Arc;
 
 use anyhow::{bail, Context as AnyhowContext, Result};
 use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use regex::Regex;
 
-use crate::config::{AliasRule, RenderConfig, VendorMode};
-use crate::context::crate_context::{CrateContext, CrateDependency, Rule};
-use crate::context::{Context, TargetAttributes};
+use crate::config::{AliasRule, BuildFileLayout, PackageAliases, RenderConfig, VendorMode};
+use crate::context::crate_context::{CrateArtifactDependency, CrateContext, CrateDependency, Rule};
+use crate::context::{Context, DebugAssertionsConditions, TargetAttributes};
 use crate::rendering::template_engine::TemplateEngine;
 use crate::select::Select;
 use crate::splicing::default_splicing_package_crate_id;
 use crate::utils::starlark::{
-    self, Alias, CargoBuildScript, CommonAttrs, Data, ExportsFiles, Filegroup, Glob, Label, Load,
-    Package, RustBinary, RustLibrary, RustProcMacro, SelectDict, SelectList, SelectScalar,
-    SelectSet, Starlark, TargetCompatibleWith,
+    self, Alias, CargoBuildScript, CommonAttrs, ConfigSetting, ConfigSettingGroup, Data,
+    ExportsFiles, Filegroup, Glob, Label, Load, Package, RustBinary, RustDoc, RustLibrary,
+    RustProcMacro, SelectDict, SelectList, SelectScalar, SelectSet, Starlark, TargetCompatibleWith,
 };
 use crate::utils::target_triple::TargetTriple;
 use crate::utils::{self, sanitize_repository_name};
@@ -29,6 +31,9 @@ use crate::utils::{self, sanitize_repository_name};
 // to platform labels like "@rules_rust//rust/platform:x86_64-unknown-linux-gnu".
 pub(crate) type Platforms = BTreeMap<String, BTreeSet<String>>;
 
+/// Bazel compilation modes and whether or not `debug_assertions` are enabled in them.
+const COMPILATION_MODES: [(&str, bool); 3] = [("dbg", true), ("fastbuild", true), ("opt", false)];
+
 pub(crate) struct Renderer {
     config: Arc<RenderConfig>,
     supported_platform_triples: Arc<BTreeSet<TargetTriple>>,
@@ -51,11 +56,15 @@ impl Renderer {
         generator: Option<Label>,
     ) -> Result<BTreeMap<PathBuf, String>> {
         let conditions = Arc::new(context.conditions.clone());
-        let engine = self.create_engine(Arc::clone(&conditions));
+        let debug_assertions_conditions = Arc::new(context.debug_assertions_conditions.clone());
+        let engine = self.create_engine(
+            Arc::clone(&conditions),
+            Arc::clone(&debug_assertions_conditions),
+        )?;
 
         let mut output = BTreeMap::new();
 
-        let platforms = self.render_platform_labels(conditions);
+        let platforms = self.render_platform_labels(conditions, &debug_assertions_conditions);
         output.extend(self.render_build_files(&engine, context, &platforms)?);
         output.extend(self.render_crates_module(&engine, context, &platforms, generator)?);
 
@@ -76,35 +85,66 @@ impl Renderer {
     pub(crate) fn create_engine(
         &self,
         conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
-    ) -> TemplateEngine {
+        debug_assertions_conditions: Arc<DebugAssertionsConditions>,
+    ) -> Result<TemplateEngine> {
         TemplateEngine::new(
             Arc::clone(&self.config),
             Arc::clone(&self.supported_platform_triples),
             Arc::clone(&conditions),
+            Arc::clone(&debug_assertions_conditions),
         )
     }
 
+    /// Render the labels satisfying each configuration. Platform triples for which any
+    /// configuration depends on `debug_assertions` are always rendered as one label per
+    /// compilation mode so that `select` statements never contain overlapping keys.
     pub(crate) fn render_platform_labels(
         &self,
         conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
+        debug_assertions_conditions: &DebugAssertionsConditions,
     ) -> BTreeMap<String, BTreeSet<String>> {
-        conditions
+        let split_triples = compilation_mode_triples(debug_assertions_conditions);
+
+        let mut platforms: Platforms = conditions
             .iter()
             .map(|(cfg, target_triples)| {
-                (
-                    cfg.clone(),
-                    target_triples
-                        .iter()
-                        .map(|target_triple| {
-                            render_platform_constraint_label(
-                                &self.config.platforms_template,
+                let labels = target_triples
+                    .iter()
+                    .flat_map(|target_triple| {
+                        if split_triples.contains(target_triple) {
+                            COMPILATION_MODES
+                                .iter()
+                                .map(|(mode, _)| {
+                                    render_compilation_mode_label(&self.config, target_triple, mode)
+                                })
+                                .collect()
+                        } else {
+                            vec![render_platform_constraint_label(
+                                &self.config,
                                 target_triple,
-                            )
-                        })
-                        .collect(),
-                )
+                            )]
+                        }
+                    })
+                    .collect();
+                (cfg.clone(), labels)
             })
-            .collect()
+            .collect();
+
+        for (cfg, target_triples) in debug_assertions_conditions {
+            let labels = platforms.entry(cfg.clone()).or_default();
+            for (target_triple, debug_assertions) in target_triples {
+                labels.extend(
+                    COMPILATION_MODES
+                        .iter()
+                        .filter(|(_, enabled)| enabled == debug_assertions)
+                        .map(|(mode, _)| {
+                            render_compilation_mode_label(&self.config, target_triple, mode)
+                        }),
+                );
+            }
+        }
+
+        platforms
     }
 
     fn render_crates_module(
@@ -156,18 +196,31 @@ impl Renderer {
         starlark.push(Starlark::Verbatim(header));
 
         // Load any `alias_rule`s.
+        let package_aliases = self.package_aliases(context);
         let mut loads: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
         for alias_rule in Iterator::chain(
             std::iter::once(&self.config.default_alias_rule),
             context
                 .workspace_member_deps()
                 .iter()
-                .flat_map(|dep| &context.crates[&dep.id].alias_rule),
+                .flat_map(|dep| &context.crates[&dep.id].alias_rule)
+                .chain(
+                    package_aliases
+                        .iter()
+                        .flat_map(|(_, krate)| &krate.alias_rule),
+                ),
         ) {
             if let Some(bzl) = alias_rule.bzl() {
                 loads.entry(bzl).or_default().insert(alias_rule.rule());
             }
         }
+        let split_triples = compilation_mode_triples(&context.debug_assertions_conditions);
+        if !split_triples.is_empty() {
+            loads
+                .entry("@bazel_skylib//lib:selects.bzl".to_owned())
+                .or_default()
+                .insert("selects".to_owned());
+        }
         for (bzl, items) in loads {
             starlark.push(Starlark::Load(Load { bzl, items }))
         }
@@ -199,6 +252,27 @@ impl Renderer {
         };
         starlark.push(Starlark::Filegroup(filegroup));
 
+        // Settings for platforms on which configurations depend on `debug_assertions`.
+        if !split_triples.is_empty() {
+            for (mode, _) in COMPILATION_MODES {
+                starlark.push(Starlark::ConfigSetting(ConfigSetting {
+                    name: format!("compilation_mode_{mode}"),
+                    values: BTreeMap::from([("compilation_mode".to_owned(), mode.to_owned())]),
+                }));
+            }
+            for target_triple in &split_triples {
+                for (mode, _) in COMPILATION_MODES {
+                    starlark.push(Starlark::ConfigSettingGroup(ConfigSettingGroup {
+                        name: format!("{}_{mode}", target_triple.to_bazel()),
+                        match_all: vec![
+                            render_platform_constraint_label(&self.config, target_triple),
+                            format!(":compilation_mode_{mode}"),
+                        ],
+                    }));
+                }
+            }
+        }
+
         // An `alias` for each direct dependency of a workspace member crate.
         let mut dependencies = Vec::new();
         for dep in context.workspace_member_deps() {
@@ -239,7 +313,8 @@ impl Renderer {
                 // Add a shorthand for crate names as long as there isn't a duplicate
                 // entry. Shorthands for duplicate entries would lead to ambiguous
                 // dependencies.
-                if !context.has_duplicate_workspace_member_dep(&dep) {
+                let has_shorthand = !context.has_duplicate_workspace_member_dep(&dep);
+                if has_shorthand {
                     dependencies.push(Alias {
                         rule: alias_rule.rule(),
                         name: shorthand.clone(),
@@ -251,6 +326,28 @@ impl Renderer {
                         tags: BTreeSet::from(["manual".to_owned()]),
                     });
                 }
+
+                if krate.gen_docs {
+                    let doc_label = self.crate_label(
+                        &krate.name,
+                        &krate.version.to_string(),
+                        &format!("{library_target_name}_doc"),
+                    );
+                    dependencies.push(Alias {
+                        rule: AliasRule::default().rule(),
+                        name: format!("{}-{}_doc", krate.name, krate.version),
+                        actual: doc_label.clone(),
+                        tags: BTreeSet::from(["manual".to_owned()]),
+                    });
+                    if has_shorthand {
+                        dependencies.push(Alias {
+                            rule: AliasRule::default().rule(),
+                            name: format!("{shorthand}_doc"),
+                            actual: doc_label,
+                            tags: BTreeSet::from(["manual".to_owned()]),
+                        });
+                    }
+                }
             }
 
             for (alias, target) in &krate.extra_aliased_targets {
@@ -276,12 +373,45 @@ impl Renderer {
             duplicates
         );
 
+        // A stable `alias` for each package, unless it's already taken by the aliases above.
+        let taken: BTreeSet<String> = dependencies
+            .iter()
+            .map(|alias| alias.name.clone())
+            .collect();
+        let package_aliases: Vec<Alias> = package_aliases
+            .into_iter()
+            .filter(|(name, _)| !taken.contains(name))
+            .filter_map(|(name, krate)| {
+                let library_target_name = krate.library_target_name.as_ref()?;
+                Some(Alias {
+                    rule: krate
+                        .alias_rule
+                        .as_ref()
+                        .unwrap_or(&self.config.default_alias_rule)
+                        .rule(),
+                    name,
+                    actual: self.crate_label(
+                        &krate.name,
+                        &krate.version.to_string(),
+                        library_target_name,
+                    ),
+                    tags: BTreeSet::from(["manual".to_owned()]),
+                })
+            })
+            .collect();
+
         if !dependencies.is_empty() {
             let comment = "# Workspace Member Dependencies".to_owned();
             starlark.push(Starlark::Verbatim(comment));
             starlark.extend(dependencies.into_iter().map(Starlark::Alias));
         }
 
+        if !package_aliases.is_empty() {
+            let comment = "# Package Aliases".to_owned();
+            starlark.push(Starlark::Verbatim(comment));
+            starlark.extend(package_aliases.into_iter().map(Starlark::Alias));
+        }
+
         // An `alias` for each binary dependency.
         let mut binaries = Vec::new();
         for crate_id in &context.binary_crates {
@@ -316,6 +446,49 @@ impl Renderer {
         Ok(starlark)
     }
 
+    /// The names of the stable aliases configured by `package_aliases` along with the crates
+    /// they refer to. Names which would be ambiguous as several versions of a package are
+    /// resolved are skipped.
+    fn package_aliases<'a>(&self, context: &'a Context) -> Vec<(String, &'a CrateContext)> {
+        let Some(package_aliases) = self.config.package_aliases else {
+            return Vec::new();
+        };
+
+        let default_splicing_package_id = default_splicing_package_crate_id();
+        let mut candidates: BTreeMap<String, Vec<&CrateContext>> = BTreeMap::new();
+        for (id, krate) in &context.crates {
+            if id == &default_splicing_package_id
+                || context.workspace_members.contains_key(id)
+                || krate.library_target_name.is_none()
+            {
+                continue;
+            }
+            candidates
+                .entry(krate.name.clone())
+                .or_default()
+                .push(krate);
+            if package_aliases == PackageAliases::Major {
+                let series = match (&krate.version.major, &krate.version.minor) {
+                    (0, 0) => format!("0.0.{}", krate.version.patch),
+                    (0, minor) => format!("0.{minor}"),
+                    (major, _) => major.to_string(),
+                };
+                candidates
+                    .entry(format!("{}-{series}", krate.name))
+                    .or_default()
+                    .push(krate);
+            }
+        }
+
+        candidates
+            .into_iter()
+            .filter_map(|(name, crates)| match crates[..] {
+                [krate] => Some((name, krate)),
+                _ => None,
+            })
+            .collect()
+    }
+
     fn render_build_files(
         &self,
         engine: &TemplateEngine,
@@ -331,11 +504,11 @@ impl Renderer {
             // Do not render local packages
             .filter(|id| !context.workspace_members.contains_key(id))
             .map(|id| {
-                let label = match render_build_file_template(
-                    &self.config.build_file_template,
+                let label = match Label::from_str(&render_crate_build_file(
+                    &self.config,
                     &id.name,
                     &id.version.to_string(),
-                ) {
+                )) {
                     Ok(label) => label,
                     Err(e) => bail!(e),
                 };
@@ -468,16 +641,89 @@ impl Renderer {
             }
         }
 
+        // API documentation for the crate's library.
+        if krate.gen_docs {
+            if let Some(library_target_name) = &krate.library_target_name {
+                load("@rules_rust//rust:defs.bzl", "rust_doc");
+                starlark.push(Starlark::RustDoc(RustDoc {
+                    name: format!("{library_target_name}_doc"),
+                    krate: Label::from_str(library_target_name).unwrap(),
+                    tags: BTreeSet::from(["manual".to_owned()]),
+                }));
+            }
+        }
+
+        // Workspace members which resolve the crate differently from the rest of the workspace
+        // use their own instances of its library and build script.
+        for (instance_name, instance) in &krate.instances {
+            let instance_krate = krate.with_instance(instance);
+            let instance_target_name = |name: &str| format!("{name}-{instance_name}");
+            for rule in &krate.targets {
+                if let Some(override_target) =
+                    krate.override_targets.get(rule.override_target_key())
+                {
+                    if !matches!(rule, Rule::Binary(_)) {
+                        starlark.push(Starlark::Alias(Alias {
+                            rule: AliasRule::default().rule(),
+                            name: instance_target_name(rule.crate_name()),
+                            actual: override_target.clone(),
+                            tags: BTreeSet::from(["manual".to_owned()]),
+                        }));
+                    }
+                    continue;
+                }
+                match rule {
+                    Rule::BuildScript(target) => {
+                        let mut cargo_build_script =
+                            self.make_cargo_build_script(platforms, &instance_krate, target)?;
+                        cargo_build_script.name = format!("_bs-{instance_name}");
+                        starlark.push(Starlark::Alias(Alias {
+                            rule: AliasRule::default().rule(),
+                            name: instance_target_name(&target.crate_name),
+                            actual: Label::from_str(&cargo_build_script.name).unwrap(),
+                            tags: BTreeSet::from(["manual".to_owned()]),
+                        }));
+                        starlark.push(Starlark::CargoBuildScript(cargo_build_script));
+                    }
+                    Rule::ProcMacro(target) => {
+                        let mut rust_proc_macro =
+                            self.make_rust_proc_macro(platforms, &instance_krate, target)?;
+                        rust_proc_macro.name = instance_target_name(&target.crate_name);
+                        rust_proc_macro.common.crate_name = Some(target.crate_name.clone());
+                        starlark.push(Starlark::RustProcMacro(rust_proc_macro));
+                    }
+                    Rule::Library(target) => {
+                        let mut rust_library =
+                            self.make_rust_library(platforms, &instance_krate, target)?;
+                        rust_library.name = instance_target_name(&target.crate_name);
+                        rust_library.common.crate_name = Some(target.crate_name.clone());
+                        starlark.push(Starlark::RustLibrary(rust_library));
+                    }
+                    // Binaries are only rendered for the workspace's resolution of the crate.
+                    Rule::Binary(_) => {}
+                }
+            }
+        }
+
         if let Some(additive_build_file_content) = &krate.additive_build_file_content {
             let comment = "# Additive BUILD file content".to_owned();
             starlark.push(Starlark::Verbatim(comment));
             starlark.push(Starlark::Verbatim(additive_build_file_content.clone()));
         }
 
-        // Insert all the loads immediately after the header banner comment.
-        let loads = loads
+        if let Some(footer) = engine.render_crate_partial("partials/crate/footer.j2", krate)? {
+            starlark.push(Starlark::Verbatim(footer));
+        }
+
+        // Insert all the loads immediately after the header banner comment, followed by any
+        // loads from the user's templates.
+        let mut loads = loads
             .into_iter()
-            .map(|(bzl, items)| Starlark::Load(Load { bzl, items }));
+            .map(|(bzl, items)| Starlark::Load(Load { bzl, items }))
+            .collect::<Vec<_>>();
+        if let Some(extra_loads) = engine.render_crate_partial("partials/crate/loads.j2", krate)? {
+            loads.push(Starlark::Verbatim(extra_loads));
+        }
         starlark.splice(1..1, loads);
 
         let starlark = starlark::serialize(&starlark)?;
@@ -491,6 +737,24 @@ impl Renderer {
         target: &TargetAttributes,
     ) -> Result<CargoBuildScript> {
         let attrs = krate.build_script_attrs.as_ref();
+        let (artifacts, artifacts_env) = self.make_artifact_deps(
+            &attrs
+                .map(|attrs| attrs.artifact_deps.clone())
+                .unwrap_or_default(),
+        );
+
+        let build_script_env = attrs
+            .map(|attrs| attrs.build_script_env.clone())
+            .unwrap_or_default();
+        let location_tools = make_location_tools(
+            &build_script_env,
+            attrs.into_iter().flat_map(|attrs| {
+                [&attrs.data, &attrs.compile_data, &attrs.tools]
+                    .into_iter()
+                    .flat_map(|labels| labels.values())
+            }),
+        )
+        .with_context(|| format!("Invalid build_script_env annotation of {}", krate.name))?;
 
         const COMPILE_DATA_GLOB_EXCLUDES: &[&str] = &["**/*.rs"];
 
@@ -507,9 +771,7 @@ impl Renderer {
             name: "_bs".to_string(),
             aliases: SelectDict::new(self.make_aliases(krate, true, false), platforms),
             build_script_env: SelectDict::new(
-                attrs
-                    .map(|attrs| attrs.build_script_env.clone())
-                    .unwrap_or_default(),
+                Select::merge(artifacts_env, build_script_env),
                 platforms,
             ),
             use_default_shell_env: krate
@@ -614,7 +876,13 @@ impl Renderer {
                 tags
             },
             tools: SelectSet::new(
-                attrs.map(|attrs| attrs.tools.clone()).unwrap_or_default(),
+                Select::merge(
+                    Select::merge(
+                        artifacts,
+                        attrs.map(|attrs| attrs.tools.clone()).unwrap_or_default(),
+                    ),
+                    location_tools,
+                ),
                 platforms,
             ),
             toolchains: attrs.map_or_else(BTreeSet::new, |attrs| attrs.toolchains.clone()),
@@ -717,13 +985,16 @@ impl Renderer {
         krate: &CrateContext,
         target: &TargetAttributes,
     ) -> Result<CommonAttrs> {
+        let (artifacts, artifacts_env) = self.make_artifact_deps(&krate.common_attrs.artifact_deps);
+
         Ok(CommonAttrs {
             compile_data: make_data(
                 platforms,
                 krate.common_attrs.compile_data_glob.clone(),
-                krate.common_attrs.compile_data.clone(),
+                Select::merge(artifacts, krate.common_attrs.compile_data.clone()),
             ),
             crate_features: SelectSet::new(krate.common_attrs.crate_features.clone(), platforms),
+            crate_name: None,
             crate_root: target.crate_root.clone(),
             data: make_data(
                 platforms,
@@ -732,7 +1003,10 @@ impl Renderer {
             ),
             edition: krate.common_attrs.edition.clone(),
             linker_script: krate.common_attrs.linker_script.clone(),
-            rustc_env: SelectDict::new(krate.common_attrs.rustc_env.clone(), platforms),
+            rustc_env: SelectDict::new(
+                Select::merge(artifacts_env, krate.common_attrs.rustc_env.clone()),
+                platforms,
+            ),
             rustc_env_files: SelectSet::new(krate.common_attrs.rustc_env_files.clone(), platforms),
             rustc_flags: SelectList::new(
                 // In most cases, warnings in 3rd party crates are not
@@ -760,10 +1034,7 @@ impl Renderer {
                     self.supported_platform_triples
                         .iter()
                         .map(|target_triple| {
-                            render_platform_constraint_label(
-                                &self.config.platforms_template,
-                                target_triple,
-                            )
+                            render_platform_constraint_label(&self.config, target_triple)
                         })
                         .collect(),
                 )
@@ -801,7 +1072,7 @@ impl Renderer {
                     let label = self.crate_label(
                         &dependency.id.name,
                         &dependency.id.version.to_string(),
-                        &dependency.target,
+                        &dependency.instance_target(),
                     );
                     aliases.insert((label, alias.clone()), configuration.clone());
                 }
@@ -817,12 +1088,49 @@ impl Renderer {
     ) -> Select<BTreeSet<Label>> {
         Select::merge(
             deps.map(|dep| {
-                self.crate_label(&dep.id.name, &dep.id.version.to_string(), &dep.target)
+                self.crate_label(
+                    &dep.id.name,
+                    &dep.id.version.to_string(),
+                    &dep.instance_target(),
+                )
             }),
             extra_deps,
         )
     }
 
+    /// Render the labels of the binaries required by artifact dependencies and the environment
+    /// variables Cargo would use to locate them.
+    fn make_artifact_deps(
+        &self,
+        deps: &Select<BTreeSet<CrateArtifactDependency>>,
+    ) -> (Select<BTreeSet<Label>>, Select<BTreeMap<String, String>>) {
+        let mut labels = Select::new();
+        let mut env = Select::new();
+        for (configuration, dep) in deps.items() {
+            let dep_name = dep.name.to_uppercase().replace('-', "_");
+            for bin in &dep.bins {
+                let label = self.crate_label(
+                    &dep.id.name,
+                    &dep.id.version.to_string(),
+                    &format!("{bin}__bin"),
+                );
+                let path = format!("$(execpath {label})");
+                if bin == &dep.id.name {
+                    env.insert(
+                        (format!("CARGO_BIN_FILE_{dep_name}"), path.clone()),
+                        configuration.clone(),
+                    );
+                }
+                env.insert(
+                    (format!("CARGO_BIN_FILE_{dep_name}_{bin}"), path),
+                    configuration.clone(),
+                );
+                labels.insert(label, configuration.clone());
+            }
+        }
+        (labels, env)
+    }
+
     fn render_vendor_support_files(
         &self,
         engine: &TemplateEngine,
@@ -917,11 +1225,25 @@ pub(crate) fn render_crate_bazel_repository(
         .replace("{version}", version)
 }
 
-/// Render the Bazel label of a crate
-pub(crate) fn render_crate_build_file(template: &str, name: &str, version: &str) -> String {
-    template
-        .replace("{name}", name)
-        .replace("{version}", version)
+/// Render the Bazel label of a crate's BUILD file
+pub(crate) fn render_crate_build_file(config: &RenderConfig, name: &str, version: &str) -> String {
+    match config.build_file_layout {
+        BuildFileLayout::Flat => config
+            .build_file_template
+            .replace("{name}", name)
+            .replace("{version}", version),
+        BuildFileLayout::PerCrate => {
+            let package = config
+                .crates_module_template
+                .rsplit_once(':')
+                .map_or("//", |(package, _)| package);
+            let separator = if package.ends_with('/') { "" } else { "/" };
+            format!(
+                "{package}{separator}{}:BUILD.bazel",
+                sanitize_repository_name(&format!("{name}-{version}"))
+            )
+        }
+    }
 }
 
 /// Render the Bazel label of a vendor module label
@@ -930,18 +1252,68 @@ pub(crate) fn render_module_label(template: &str, name: &str) -> Result<Label> {
 }
 
 /// Render the Bazel label of a platform triple
-fn render_platform_constraint_label(template: &str, target_triple: &TargetTriple) -> String {
-    template.replace("{triple}", &target_triple.to_bazel())
+fn render_platform_constraint_label(config: &RenderConfig, target_triple: &TargetTriple) -> String {
+    match config.platform_labels.get(target_triple) {
+        Some(label) => label.clone(),
+        None => config
+            .platforms_template
+            .replace("{triple}", &target_triple.to_bazel()),
+    }
 }
 
-fn render_build_file_template(template: &str, name: &str, version: &str) -> Result<Label> {
-    Label::from_str(
-        &template
-            .replace("{name}", name)
-            .replace("{version}", version),
+/// Render the label of the setting matching a platform triple in the given compilation mode.
+fn render_compilation_mode_label(
+    config: &RenderConfig,
+    target_triple: &TargetTriple,
+    compilation_mode: &str,
+) -> String {
+    config.crates_module_template.replace(
+        "{file}",
+        &format!("{}_{compilation_mode}", target_triple.to_bazel()),
     )
 }
 
+/// The platform triples for which any configuration depends on `debug_assertions`.
+fn compilation_mode_triples(
+    debug_assertions_conditions: &DebugAssertionsConditions,
+) -> BTreeSet<&TargetTriple> {
+    debug_assertions_conditions
+        .values()
+        .flat_map(BTreeMap::keys)
+        .collect()
+}
+
+/// The labels referenced by location expansions such as `$(execpath ...)` in the values of
+/// `env`, which `cargo_build_script` can only expand for targets among its dependencies. Labels
+/// which aren't already in `dependencies` are added as tools, as they're typically binaries the
+/// build script runs. Other make variables are expanded by Bazel from the `toolchains` of the
+/// build script.
+fn make_location_tools(
+    env: &Select<BTreeMap<String, String>>,
+    dependencies: impl Iterator<Item = Label>,
+) -> Result<Select<BTreeSet<Label>>> {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(
+            r"\$\((?:location|locations|execpath|execpaths|rootpath|rootpaths|rlocationpath|rlocationpaths) ([^)\s]+)\)",
+        )
+        .unwrap()
+    });
+
+    let dependencies = dependencies.collect::<BTreeSet<_>>();
+    let mut tools = Select::new();
+    for (configuration, (_, value)) in env.items() {
+        for cap in re.captures_iter(&value) {
+            let label = Label::from_str(&cap[1])?;
+            if !dependencies.contains(&label) {
+                tools.insert(label, configuration.clone());
+            }
+        }
+    }
+
+    Ok(tools)
+}
+
 fn make_data_with_exclude(
     platforms: &Platforms,
     include: BTreeSet<String>,
@@ -987,8 +1359,10 @@ mod test {
     use indoc::indoc;
 
     use crate::config::{Config, CrateId};
+    use crate::context::crate_context::CrateInstance;
     use crate::context::{BuildScriptAttributes, CommonAttributes};
     use crate::metadata::Annotations;
+    use crate::rendering::template_engine::TEMPLATES_VERSION;
     use crate::test;
     use crate::utils::normalize_cargo_file_paths;
 
@@ -1049,6 +1423,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1058,9 +1433,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1087,6 +1464,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1096,9 +1474,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: true,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1112,6 +1492,52 @@ mod test {
         assert!(build_file_content.contains("disable_pipelining = True"));
     }
 
+    #[test]
+    fn render_docs() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                source: None,
+                targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
+                library_target_name: Some("mock_crate".to_owned()),
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                gen_docs: true,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        assert!(build_file_content.contains(indoc! {r#"
+            rust_doc(
+                name = "mock_crate_doc",
+                crate = ":mock_crate",
+                tags = ["manual"],
+            )
+        "#}));
+        assert!(build_file_content.contains(r#""rust_doc""#));
+    }
+
     #[test]
     fn render_cargo_build_script() {
         let mut context = Context::default();
@@ -1124,6 +1550,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::BuildScript(TargetAttributes {
                     crate_name: "build_script_build".to_owned(),
                     crate_root: Some("build.rs".to_owned()),
@@ -1138,9 +1565,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1181,6 +1610,227 @@ mod test {
         assert!(build_file_content.contains("name = \"_bs\""));
     }
 
+    #[test]
+    fn render_crate_instances() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let dep_id = CrateId::new("mock_dep".to_owned(), VERSION_ZERO_ONE_ZERO);
+
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name.clone(),
+                version: crate_id.version.clone(),
+                package_url: None,
+                repository: None,
+                source: None,
+                targets: BTreeSet::from([
+                    Rule::Library(mock_target_attributes()),
+                    Rule::BuildScript(TargetAttributes {
+                        crate_name: "build_script_build".to_owned(),
+                        crate_root: Some("build.rs".to_owned()),
+                        ..TargetAttributes::default()
+                    }),
+                ]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: Some(BuildScriptAttributes::default()),
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                gen_docs: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                instances: BTreeMap::from([(
+                    "member_a".to_owned(),
+                    CrateInstance {
+                        workspace_members: BTreeSet::from([CrateId::new(
+                            "member-a".to_owned(),
+                            VERSION_ZERO_ONE_ZERO,
+                        )]),
+                        crate_features: Select::from_value(BTreeSet::from(["std".to_owned()])),
+                        deps: Select::from_value(BTreeSet::from([
+                            CrateDependency {
+                                id: dep_id.clone(),
+                                target: dep_id.name.clone(),
+                                alias: None,
+                                instance: Some("member_a".to_owned()),
+                            },
+                            CrateDependency {
+                                id: crate_id.clone(),
+                                target: "build_script_build".to_owned(),
+                                alias: None,
+                                instance: Some("member_a".to_owned()),
+                            },
+                        ])),
+                        ..CrateInstance::default()
+                    },
+                )]),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        for expected in [
+            "name = \"mock_crate-member_a\"",
+            "crate_name = \"mock_crate\"",
+            "\"@test_rendering__mock_dep-0.1.0//:mock_dep-member_a\"",
+            "name = \"_bs-member_a\"",
+            "name = \"build_script_build-member_a\"",
+            "\"@test_rendering__mock_crate-0.1.0//:build_script_build-member_a\"",
+        ] {
+            assert!(
+                build_file_content.contains(expected),
+                "{expected}\n```\n{build_file_content}```\n"
+            );
+        }
+    }
+
+    #[test]
+    fn render_cargo_build_script_artifact_deps() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+
+        let mut artifact_deps = Select::new();
+        artifact_deps.insert(
+            CrateArtifactDependency {
+                id: CrateId::new("mock-tool".to_owned(), VERSION_ZERO_ONE_ZERO),
+                name: "mock-tool".to_owned(),
+                bins: BTreeSet::from(["mock-tool".to_owned(), "helper".to_owned()]),
+            },
+            None,
+        );
+
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                source: None,
+                targets: BTreeSet::from([Rule::BuildScript(TargetAttributes {
+                    crate_name: "build_script_build".to_owned(),
+                    crate_root: Some("build.rs".to_owned()),
+                    ..TargetAttributes::default()
+                })]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: Some(BuildScriptAttributes {
+                    artifact_deps,
+                    ..BuildScriptAttributes::default()
+                }),
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                gen_docs: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        for expected in [
+            "\"CARGO_BIN_FILE_MOCK_TOOL\": \"$(execpath @test_rendering__mock-tool-0.1.0//:mock-tool__bin)\"",
+            "\"CARGO_BIN_FILE_MOCK_TOOL_mock-tool\": \"$(execpath @test_rendering__mock-tool-0.1.0//:mock-tool__bin)\"",
+            "\"CARGO_BIN_FILE_MOCK_TOOL_helper\": \"$(execpath @test_rendering__mock-tool-0.1.0//:helper__bin)\"",
+            "\"@test_rendering__mock-tool-0.1.0//:helper__bin\"",
+        ] {
+            assert!(
+                build_file_content.contains(expected),
+                "Missing `{}` in:\n```\n{}```\n",
+                expected,
+                build_file_content
+            );
+        }
+    }
+
+    #[test]
+    fn render_cargo_build_script_env_locations() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+
+        let mut build_script_env = Select::new();
+        build_script_env.insert(
+            (
+                "PROTOC".to_owned(),
+                "$(execpath @protobuf//:protoc)".to_owned(),
+            ),
+            None,
+        );
+        build_script_env.insert(
+            (
+                "DATA".to_owned(),
+                "$(rootpath @//:data.txt) $(TARGET_CPU)".to_owned(),
+            ),
+            None,
+        );
+        let mut data = Select::new();
+        data.insert(Label::from_str("@//:data.txt").unwrap(), None);
+
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                source: None,
+                targets: BTreeSet::from([Rule::BuildScript(TargetAttributes {
+                    crate_name: "build_script_build".to_owned(),
+                    crate_root: Some("build.rs".to_owned()),
+                    ..TargetAttributes::default()
+                })]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: Some(BuildScriptAttributes {
+                    build_script_env,
+                    data,
+                    ..BuildScriptAttributes::default()
+                }),
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                gen_docs: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        // Only labels which aren't already dependencies of the build script are added as tools.
+        assert!(build_file_content.contains("\"@protobuf//:protoc\""));
+        assert_eq!(build_file_content.matches("\"@//:data.txt\"").count(), 1);
+    }
+
     #[test]
     fn render_cargo_build_script_complex() {
         let mut context = Context::default();
@@ -1198,6 +1848,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::BuildScript(TargetAttributes {
                     crate_name: "build_script_build".to_owned(),
                     crate_root: Some("build.rs".to_owned()),
@@ -1212,9 +1863,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1266,6 +1919,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::ProcMacro(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1275,9 +1929,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1304,6 +1960,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Binary(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1313,9 +1970,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1342,6 +2001,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Binary(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1353,9 +2013,11 @@ mod test {
                     "# Hello World from additive section!".to_owned(),
                 ),
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1369,6 +2031,77 @@ mod test {
         assert!(build_file_content.contains("# Hello World from additive section!"));
     }
 
+    #[test]
+    fn render_user_templates() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                source: None,
+                targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                gen_docs: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
+            },
+        );
+
+        let mut config = RenderConfig {
+            repository_name: "test_rendering".to_owned(),
+            templates: BTreeMap::from([
+                (
+                    "partials/crate/loads.j2".to_owned(),
+                    r#"load("@//tools:rust.bzl", "company_checks")"#.to_owned(),
+                ),
+                (
+                    "partials/crate/footer.j2".to_owned(),
+                    r#"company_checks(name = "{{ crate.name }}_checks")"#.to_owned(),
+                ),
+            ]),
+            templates_version: Some(TEMPLATES_VERSION),
+            ..RenderConfig::default()
+        };
+        let renderer = Renderer::new(Arc::new(config.clone()), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        let loads = build_file_content
+            .find(r#"load("@//tools:rust.bzl", "company_checks")"#)
+            .unwrap();
+        assert!(build_file_content.find("rust_library(").unwrap() > loads);
+        assert!(build_file_content.contains(r#"company_checks(name = "mock_crate_checks")"#));
+
+        // Templates written for other versions are rejected
+        config.templates_version = None;
+        let renderer = Renderer::new(Arc::new(config.clone()), mock_supported_platform_triples());
+        assert!(renderer.render(&context, None).is_err());
+
+        // As are templates which don't exist
+        config.templates_version = Some(TEMPLATES_VERSION);
+        config
+            .templates
+            .insert("partials/crate/unknown.j2".to_owned(), String::new());
+        let renderer = Renderer::new(Arc::new(config), mock_supported_platform_triples());
+        assert!(renderer.render(&context, None).is_err());
+    }
+
     #[test]
     fn render_aliases() {
         let config = Config {
@@ -1393,6 +2126,43 @@ mod test {
         assert!(build_file_content.contains(r#"name = "names-0.13.0__names","#));
     }
 
+    #[test]
+    fn render_package_aliases() {
+        let annotations = Annotations::new(
+            test::metadata::alias(),
+            test::lockfile::alias(),
+            Config::default(),
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap();
+        let context = Context::new(annotations, false).unwrap();
+
+        let mut config = mock_render_config(None);
+        Arc::get_mut(&mut config).unwrap().package_aliases = Some(PackageAliases::Major);
+
+        let renderer = Renderer::new(config, mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output.get(&PathBuf::from("BUILD.bazel")).unwrap();
+
+        assert!(build_file_content.contains("# Package Aliases"));
+        for (name, actual) in [
+            ("adler", "@test_rendering__adler-1.0.2//:adler"),
+            ("adler-1", "@test_rendering__adler-1.0.2//:adler"),
+            ("ahash-0.7", "@test_rendering__ahash-0.7.8//:ahash"),
+            ("ahash-0.8", "@test_rendering__ahash-0.8.11//:ahash"),
+        ] {
+            assert!(
+                build_file_content.contains(&format!(
+                    "    name = \"{name}\",\n    actual = \"{actual}\","
+                )),
+                "{name}"
+            );
+        }
+        // Several versions of `ahash` are resolved so it has no unversioned alias.
+        assert!(!build_file_content.contains("name = \"ahash\","));
+    }
+
     #[test]
     fn render_crate_repositories() {
         let mut context = Context::default();
@@ -1404,6 +2174,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1413,9 +2184,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1427,6 +2200,69 @@ mod test {
         assert!(defs_module.contains("def crate_repositories():"));
     }
 
+    #[test]
+    fn render_per_crate_build_file_labels() {
+        let mut config = RenderConfig {
+            build_file_layout: BuildFileLayout::PerCrate,
+            ..RenderConfig::default()
+        };
+        assert_eq!(
+            render_crate_build_file(&config, "mock_crate", "0.1.0"),
+            "//mock_crate-0.1.0:BUILD.bazel"
+        );
+
+        config.crates_module_template = "@vendor//3rdparty/crates:{file}".to_owned();
+        assert_eq!(
+            render_crate_build_file(&config, "libbpf-sys", "1.4.0+v1.4.0"),
+            "@vendor//3rdparty/crates/libbpf-sys-1.4.0-v1.4.0:BUILD.bazel"
+        );
+
+        config.build_file_layout = BuildFileLayout::Flat;
+        assert_eq!(
+            render_crate_build_file(&config, "mock_crate", "0.1.0"),
+            "//:BUILD.mock_crate-0.1.0.bazel"
+        );
+    }
+
+    #[test]
+    fn render_per_crate_build_files() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                source: None,
+                targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                gen_docs: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
+            },
+        );
+
+        let mut config = mock_render_config(None);
+        Arc::get_mut(&mut config).unwrap().build_file_layout = BuildFileLayout::PerCrate;
+
+        let renderer = Renderer::new(config, mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        assert!(output.contains_key(&PathBuf::from("mock_crate-0.1.0/BUILD.bazel")));
+        assert!(!output.contains_key(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel")));
+    }
+
     #[test]
     fn remote_remote_vendor_mode() {
         let mut context = Context::default();
@@ -1438,6 +2274,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1447,9 +2284,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1478,6 +2317,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1487,9 +2327,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1527,6 +2369,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes {
@@ -1539,9 +2382,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1647,6 +2492,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes {
@@ -1659,9 +2505,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1697,6 +2545,7 @@ mod test {
                 version: crate_id.version,
                 package_url: Some("http://www.mock_crate.com/".to_owned()),
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1706,9 +2555,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1755,15 +2606,18 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
                 repository: None,
+                source: None,
                 license: None,
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1821,15 +2675,18 @@ mod test {
                 license_file: Some("LICENSE.txt".to_owned()),
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
                 repository: None,
+                source: None,
                 license: None,
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1891,6 +2748,7 @@ mod test {
                 version: crate_id.version,
                 package_url: None,
                 repository: None,
+                source: None,
                 targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
@@ -1900,9 +2758,11 @@ mod test {
                 license_file: None,
                 additive_build_file_content: None,
                 disable_pipelining: false,
+                gen_docs: false,
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                instances: BTreeMap::default(),
             },
         );
 
@@ -1947,4 +2807,109 @@ mod test {
         }
         assert!(found);
     }
+
+    #[test]
+    fn render_platform_labels_for_custom_targets() {
+        let custom_triple = TargetTriple::from_bazel("riscv32imc-acme-none".to_owned());
+        let config = RenderConfig {
+            platform_labels: BTreeMap::from([(
+                custom_triple.clone(),
+                "@//platforms:acme".to_owned(),
+            )]),
+            ..RenderConfig::default()
+        };
+        let renderer = Renderer::new(Arc::new(config), mock_supported_platform_triples());
+
+        let conditions = Arc::new(BTreeMap::from([(
+            r#"cfg(target_arch = "riscv32")"#.to_owned(),
+            BTreeSet::from([
+                custom_triple,
+                TargetTriple::from_bazel("riscv32imc-unknown-none-elf".to_owned()),
+            ]),
+        )]));
+
+        assert_eq!(
+            renderer.render_platform_labels(conditions, &DebugAssertionsConditions::new()),
+            BTreeMap::from([(
+                r#"cfg(target_arch = "riscv32")"#.to_owned(),
+                BTreeSet::from([
+                    "@//platforms:acme".to_owned(),
+                    "@rules_rust//rust/platform:riscv32imc-unknown-none-elf".to_owned(),
+                ]),
+            )])
+        );
+    }
+
+    #[test]
+    fn render_debug_assertions_conditions() {
+        let linux = TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned());
+        let windows = TargetTriple::from_bazel("x86_64-pc-windows-msvc".to_owned());
+        let mut context = Context {
+            conditions: BTreeMap::from([
+                ("cfg(unix)".to_owned(), BTreeSet::from([linux.clone()])),
+                ("cfg(debug_assertions)".to_owned(), BTreeSet::new()),
+            ]),
+            debug_assertions_conditions: BTreeMap::from([(
+                "cfg(debug_assertions)".to_owned(),
+                BTreeMap::from([(linux.clone(), true), (windows, true)]),
+            )]),
+            ..Context::default()
+        };
+        context.conditions.insert(
+            "x86_64-unknown-linux-gnu".to_owned(),
+            BTreeSet::from([linux]),
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+
+        assert_eq!(
+            renderer.render_platform_labels(
+                Arc::new(context.conditions.clone()),
+                &context.debug_assertions_conditions
+            ),
+            BTreeMap::from([
+                (
+                    "cfg(debug_assertions)".to_owned(),
+                    BTreeSet::from([
+                        "//:x86_64-pc-windows-msvc_dbg".to_owned(),
+                        "//:x86_64-pc-windows-msvc_fastbuild".to_owned(),
+                        "//:x86_64-unknown-linux-gnu_dbg".to_owned(),
+                        "//:x86_64-unknown-linux-gnu_fastbuild".to_owned(),
+                    ]),
+                ),
+                (
+                    "cfg(unix)".to_owned(),
+                    BTreeSet::from([
+                        "//:x86_64-unknown-linux-gnu_dbg".to_owned(),
+                        "//:x86_64-unknown-linux-gnu_fastbuild".to_owned(),
+                        "//:x86_64-unknown-linux-gnu_opt".to_owned(),
+                    ]),
+                ),
+                (
+                    "x86_64-unknown-linux-gnu".to_owned(),
+                    BTreeSet::from([
+                        "//:x86_64-unknown-linux-gnu_dbg".to_owned(),
+                        "//:x86_64-unknown-linux-gnu_fastbuild".to_owned(),
+                        "//:x86_64-unknown-linux-gnu_opt".to_owned(),
+                    ]),
+                ),
+            ])
+        );
+
+        let output = renderer.render(&context, None).unwrap();
+        let build_file_content = output.get(&PathBuf::from("BUILD.bazel")).unwrap();
+
+        assert!(build_file_content.contains(r#"load("@bazel_skylib//lib:selects.bzl", "selects")"#));
+        assert!(build_file_content.contains(r#"name = "compilation_mode_opt","#));
+        assert!(build_file_content.contains(r#""compilation_mode": "opt""#));
+        assert!(build_file_content.contains(indoc! {r#"
+            selects.config_setting_group(
+                name = "x86_64-pc-windows-msvc_fastbuild",
+                match_all = [
+                    "@rules_rust//rust/platform:x86_64-pc-windows-msvc",
+                    ":compilation_mode_fastbuild",
+                ],
+            )
+        "#}));
+    }
 }
@@ -3,11 +3,12 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 
-use anyhow::{Context as AnyhowContext, Result};
+use anyhow::{bail, Context as AnyhowContext, Result};
+use itertools::Itertools;
 use serde_json::{from_value, to_value, Value};
 
 use crate::config::RenderConfig;
-use crate::context::{Context, SingleBuildFileRenderContext};
+use crate::context::{Context, DebugAssertionsConditions, SingleBuildFileRenderContext};
 use crate::rendering::{
     render_crate_bazel_label, render_crate_bazel_repository, render_crate_build_file,
     render_module_label, CrateContext, Platforms,
@@ -17,6 +18,10 @@ use crate::utils::sanitize_repository_name;
 use crate::utils::starlark::Label;
 use crate::utils::target_triple::TargetTriple;
 
+/// The version of the rendering templates. This must be bumped whenever the templates or the
+/// data available to them change in a way which may break templates provided by users.
+pub(crate) const TEMPLATES_VERSION: u32 = 1;
+
 pub(crate) struct TemplateEngine {
     engine: tera::Tera,
     context: tera::Context,
@@ -27,9 +32,9 @@ impl TemplateEngine {
         render_config: Arc<RenderConfig>,
         supported_platform_triples: Arc<BTreeSet<TargetTriple>>,
         platform_conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
-    ) -> Self {
-        let mut tera = tera::Tera::default();
-        tera.add_raw_templates(vec![
+        debug_assertions_conditions: Arc<DebugAssertionsConditions>,
+    ) -> Result<Self> {
+        let mut templates = vec![
             (
                 "partials/module/aliases_map.j2",
                 include_str!(concat!(
@@ -44,6 +49,20 @@ impl TemplateEngine {
                     "/src/rendering/templates/partials/module/deps_map.j2"
                 )),
             ),
+            (
+                "partials/crate/footer.j2",
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/src/rendering/templates/partials/crate/footer.j2"
+                )),
+            ),
+            (
+                "partials/crate/loads.j2",
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/src/rendering/templates/partials/crate/loads.j2"
+                )),
+            ),
             (
                 "partials/module/repo_git.j2",
                 include_str!(concat!(
@@ -79,12 +98,41 @@ impl TemplateEngine {
                     "/src/rendering/templates/vendor_module.j2"
                 )),
             ),
-        ])
-        .unwrap();
+        ];
+
+        // Swap in any templates provided by the user, ensuring they were written for the
+        // current templates so upgrades don't silently render broken files.
+        if !render_config.templates.is_empty()
+            && render_config.templates_version != Some(TEMPLATES_VERSION)
+        {
+            bail!(
+                "The rendering templates provided for `{}` target version {} but version {} is required. Please review the changes to the default templates and update the provided ones and `templates_version` accordingly.",
+                render_config.repository_name,
+                render_config
+                    .templates_version
+                    .map(|version| version.to_string())
+                    .unwrap_or_else(|| "(unset)".to_owned()),
+                TEMPLATES_VERSION,
+            );
+        }
+        for (name, content) in render_config.templates.iter() {
+            match templates.iter_mut().find(|(template, _)| template == name) {
+                Some(template) => template.1 = content,
+                None => bail!(
+                    "`{}` is not a rendering template. Available templates are: {}",
+                    name,
+                    templates.iter().map(|(template, _)| *template).join(", ")
+                ),
+            }
+        }
+
+        let mut tera = tera::Tera::default();
+        tera.add_raw_templates(templates)
+            .context("Failed to parse rendering templates")?;
 
         tera.register_function(
             "crate_build_file",
-            crate_build_file_fn_generator(render_config.build_file_template.clone()),
+            crate_build_file_fn_generator(Arc::clone(&render_config)),
         );
         tera.register_function(
             "crate_label",
@@ -117,6 +165,7 @@ impl TemplateEngine {
                 Arc::clone(&render_config),
                 supported_platform_triples,
                 platform_conditions,
+                debug_assertions_conditions,
             ),
         );
 
@@ -134,10 +183,10 @@ impl TemplateEngine {
             },
         );
 
-        Self {
+        Ok(Self {
             engine: tera,
             context,
-        }
+        })
     }
 
     fn new_tera_ctx(&self) -> tera::Context {
@@ -154,6 +203,24 @@ impl TemplateEngine {
         Ok(header)
     }
 
+    /// Render one of the `partials/crate` templates extending a crate's BUILD file. Returns
+    /// `None` if nothing was rendered.
+    pub(crate) fn render_crate_partial(
+        &self,
+        template: &str,
+        krate: &CrateContext,
+    ) -> Result<Option<String>> {
+        let mut context = self.new_tera_ctx();
+        context.insert("crate", krate);
+
+        let content = self
+            .engine
+            .render(template, &context)
+            .with_context(|| format!("Failed to render {} for {}", template, krate.name))?;
+        let content = content.trim();
+        Ok((!content.is_empty()).then(|| content.to_owned()))
+    }
+
     pub(crate) fn render_module_bzl(
         &self,
         data: &Context,
@@ -204,13 +271,13 @@ macro_rules! parse_tera_param {
 }
 
 /// Convert a crate name into a module name by applying transforms to invalid characters.
-fn crate_build_file_fn_generator(template: String) -> impl tera::Function {
+fn crate_build_file_fn_generator(render_config: Arc<RenderConfig>) -> impl tera::Function {
     Box::new(
         move |args: &HashMap<String, Value>| -> tera::Result<Value> {
             let name = parse_tera_param!("name", String, args);
             let version = parse_tera_param!("version", String, args);
 
-            match to_value(render_crate_build_file(&template, &name, &version)) {
+            match to_value(render_crate_build_file(&render_config, &name, &version)) {
                 Ok(v) => Ok(v),
                 Err(_) => Err(tera::Error::msg("Failed to generate crate's BUILD file")),
             }
@@ -305,17 +372,20 @@ fn local_crate_mirror_options_json_fn_generator(
     config: Arc<RenderConfig>,
     supported_platform_triples: Arc<BTreeSet<TargetTriple>>,
     platform_conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
+    debug_assertions_conditions: Arc<DebugAssertionsConditions>,
 ) -> impl tera::Function {
     Box::new(
         move |args: &HashMap<String, Value>| -> tera::Result<Value> {
             let config = Arc::clone(&config);
             let supported_platform_triples = Arc::clone(&supported_platform_triples);
             let platform_conditions = Arc::clone(&platform_conditions);
+            let debug_assertions_conditions = Arc::clone(&debug_assertions_conditions);
             let crate_context = Arc::new(parse_tera_param!("crate_context", CrateContext, args));
             let context = SingleBuildFileRenderContext {
                 config,
                 supported_platform_triples,
                 platform_conditions,
+                debug_assertions_conditions,
                 crate_context,
             };
             serde_json::to_string(&context)
@@ -2,6 +2,8 @@
 
 pub(crate) mod cargo_config;
 mod crate_index_lookup;
+pub(crate) mod credentials;
+mod mirrors;
 mod splicer;
 
 use std::collections::{BTreeMap, BTreeSet};
@@ -16,12 +18,17 @@ use cargo_toml::Manifest;
 use serde::{Deserialize, Serialize};
 
 use crate::config::CrateId;
-use crate::metadata::{Cargo, CargoUpdateRequest, LockGenerator, TreeResolverMetadata};
+use crate::metadata::{
+    Cargo, CargoUpdateRequest, DuplicateCrates, ExecTreeResolverMetadata,
+    IsolatedTreeResolverMetadata, LockGenerator, TreeResolverMetadata,
+};
 use crate::utils;
 use crate::utils::starlark::Label;
+use crate::utils::target_triple::TargetCfgs;
 
 use self::cargo_config::CargoConfig;
 use self::crate_index_lookup::CrateIndexLookup;
+pub(crate) use self::mirrors::*;
 pub(crate) use self::splicer::*;
 
 type DirectPackageManifest = BTreeMap<String, cargo_toml::DependencyDetail>;
@@ -41,6 +48,14 @@ pub(crate) struct SplicingManifest {
 
     /// The Cargo resolver version to use for splicing
     pub(crate) resolver_version: cargo_toml::Resolver,
+
+    /// Mirrors through which registry indexes and crates are downloaded
+    #[serde(default, skip_serializing_if = "RegistryMirrors::is_empty")]
+    pub(crate) mirrors: RegistryMirrors,
+
+    /// How crates resolved at several versions are reported and deduplicated
+    #[serde(default, skip_serializing_if = "DuplicateCrates::is_empty")]
+    pub(crate) duplicates: DuplicateCrates,
 }
 
 impl FromStr for SplicingManifest {
@@ -107,6 +122,14 @@ pub(crate) struct SplicingMetadata {
 
     /// The path of a Cargo config file
     pub(crate) cargo_config: Option<CargoConfig>,
+
+    /// Mirrors through which registry indexes and crates are downloaded
+    #[serde(skip_serializing_if = "RegistryMirrors::is_empty")]
+    pub(crate) mirrors: RegistryMirrors,
+
+    /// How crates resolved at several versions are reported and deduplicated
+    #[serde(skip_serializing_if = "DuplicateCrates::is_empty")]
+    pub(crate) duplicates: DuplicateCrates,
 }
 
 impl TryFrom<SplicingManifest> for SplicingMetadata {
@@ -145,6 +168,8 @@ impl TryFrom<SplicingManifest> for SplicingMetadata {
             direct_packages,
             manifests,
             cargo_config,
+            mirrors: value.mirrors,
+            duplicates: value.duplicates,
         })
     }
 }
@@ -158,6 +183,14 @@ pub(crate) struct SourceInfo {
     pub(crate) sha256: String,
 }
 
+/// A locked crate version which has been yanked from its registry.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct YankedCrate {
+    /// The newest semver compatible version of the crate which is not yanked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) replacement: Option<semver::Version>,
+}
+
 /// Information about the Cargo workspace relative to the Bazel workspace
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct WorkspaceMetadata {
@@ -175,6 +208,30 @@ pub(crate) struct WorkspaceMetadata {
     /// We store this here because it's computed during the splicing phase via
     /// calls to "cargo tree" which need the full spliced workspace.
     pub(crate) tree_metadata: TreeResolverMetadata,
+
+    /// Feature set for each target triplet and crate as resolved for each workspace member
+    /// when features are not unified across the workspace.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) isolated_tree_metadata: IsolatedTreeResolverMetadata,
+
+    /// Feature set for each exec platform and crate built by build scripts and proc macros,
+    /// where it differs from `tree_metadata`.
+    #[serde(default, skip_serializing_if = "ExecTreeResolverMetadata::is_empty")]
+    pub(crate) exec_tree_metadata: ExecTreeResolverMetadata,
+
+    /// The `cfg` options of each target triplet, used to evaluate the conditions of
+    /// platform specific dependencies more accurately than `cfg-expr` data alone allows.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) target_cfgs: TargetCfgs,
+
+    /// Locked crates whose versions have been yanked from their registries.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) yanked: BTreeMap<CrateId, YankedCrate>,
+
+    /// The `[profile]` tables of the root manifest, keyed by profile name. These are not
+    /// part of `cargo metadata` so they are recorded here for rendering.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) profiles: BTreeMap<String, toml::Value>,
 }
 
 impl TryFrom<toml::Value> for WorkspaceMetadata {
@@ -248,18 +305,28 @@ impl WorkspaceMetadata {
             workspace_prefix,
             package_prefixes,
             tree_metadata: TreeResolverMetadata::new(),
+            isolated_tree_metadata: IsolatedTreeResolverMetadata::new(),
+            exec_tree_metadata: ExecTreeResolverMetadata::new(),
+            target_cfgs: TargetCfgs::new(),
+            yanked: BTreeMap::new(),
+            profiles: BTreeMap::new(),
         })
     }
 
     /// Update an existing Cargo manifest with metadata about registry urls and target
     /// features that are needed in generator steps beyond splicing.
     #[tracing::instrument(skip_all)]
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn write_registry_urls_and_feature_map(
         cargo: &Cargo,
         lockfile: &cargo_lock::Lockfile,
         resolver_data: TreeResolverMetadata,
+        exec_resolver_data: ExecTreeResolverMetadata,
+        isolated_resolver_data: IsolatedTreeResolverMetadata,
+        target_cfgs: TargetCfgs,
         input_manifest_path: &Utf8Path,
         output_manifest_path: &Utf8Path,
+        mirrors: &RegistryMirrors,
     ) -> Result<()> {
         let mut manifest = read_manifest(input_manifest_path)?;
 
@@ -363,7 +430,9 @@ impl WorkspaceMetadata {
             .collect::<Result<BTreeMap<String, _>>>()
             .context("Failed to locate crate indexes")?;
 
-        // Get the download URL of each package based on it's registry url.
+        // Get the download URL of each package based on it's registry url
+        // and check whether or not its locked version has been yanked.
+        let mut yanked = BTreeMap::new();
         let additional_sources = pkg_sources
             .iter()
             .map(|pkg| {
@@ -375,11 +444,16 @@ impl WorkspaceMetadata {
                         source_id
                     )
                 })?;
+                let crate_id = CrateId::new(pkg.name.as_str().to_owned(), pkg.version.clone());
+                if let Some(yanked_crate) = lookup.find_yanked(pkg)? {
+                    yanked.insert(crate_id.clone(), yanked_crate);
+                }
                 lookup.get_source_info(pkg).map(|source_info| {
-                    (
-                        CrateId::new(pkg.name.as_str().to_owned(), pkg.version.clone()),
-                        source_info,
-                    )
+                    let source_info = source_info.map(|source_info| SourceInfo {
+                        url: mirrors.rewrite_download_url(&source_info.url),
+                        ..source_info
+                    });
+                    (crate_id, source_info)
                 })
             })
             .collect::<Result<Vec<_>>>()?;
@@ -394,6 +468,14 @@ impl WorkspaceMetadata {
                     }),
             );
         workspace_metaata.tree_metadata = resolver_data;
+        workspace_metaata.isolated_tree_metadata = isolated_resolver_data;
+        workspace_metaata.exec_tree_metadata = exec_resolver_data;
+        workspace_metaata.target_cfgs = target_cfgs;
+        workspace_metaata.yanked = yanked;
+        workspace_metaata.profiles = match toml::Value::try_from(&manifest.profile)? {
+            toml::Value::Table(profiles) => profiles.into_iter().collect(),
+            _ => BTreeMap::new(),
+        };
         workspace_metaata.inject_into(&mut manifest)?;
 
         write_root_manifest(output_manifest_path.as_std_path(), manifest)?;
@@ -421,6 +503,32 @@ impl WorkspaceMetadata {
 
         Ok(())
     }
+
+    /// Describe each locked crate whose version has been yanked from its registry. If
+    /// `fail_on_yanked` is set, an error listing these crates is returned instead.
+    pub(crate) fn yanked_crate_warnings(&self, fail_on_yanked: bool) -> Result<Vec<String>> {
+        let warnings: Vec<String> = self
+            .yanked
+            .iter()
+            .map(|(id, yanked)| match &yanked.replacement {
+                Some(replacement) => format!(
+                    "The locked version of {id} has been yanked. The newest compatible version is {replacement}."
+                ),
+                None => format!(
+                    "The locked version of {id} has been yanked and no compatible version is available."
+                ),
+            })
+            .collect();
+
+        if fail_on_yanked && !warnings.is_empty() {
+            bail!(
+                "Yanked crates are locked. Repin the dependencies to update them.\n{}",
+                warnings.join("\n")
+            );
+        }
+
+        Ok(warnings)
+    }
 }
 
 #[derive(Debug)]
@@ -450,6 +558,7 @@ pub(crate) fn generate_lockfile(
     existing_lock: &Option<PathBuf>,
     cargo_bin: Cargo,
     update_request: &Option<CargoUpdateRequest>,
+    duplicates: &DuplicateCrates,
 ) -> Result<cargo_lock::Lockfile> {
     let manifest_dir = manifest_path
         .as_path_buf()
@@ -464,12 +573,20 @@ pub(crate) fn generate_lockfile(
     }
 
     // Generate the new lockfile
-    let lockfile = LockGenerator::new(cargo_bin).generate(
+    let mut lockfile = LockGenerator::new(cargo_bin.clone()).generate(
         manifest_path.as_path_buf(),
         existing_lock,
         update_request,
     )?;
 
+    // Duplicates are only resolved when repinning so existing pins are left untouched
+    let is_repin = existing_lock.is_none() || update_request.is_some();
+    if is_repin && duplicates.deduplicate(manifest_path.as_path_buf().as_std_path(), &cargo_bin)? {
+        lockfile = cargo_lock::Lockfile::load(&root_lockfile_path)
+            .context("Failed to load the deduplicated lockfile")?;
+        cargo_bin.restore_crates(root_lockfile_path.as_std_path())?;
+    }
+
     // Write the lockfile to disk
     if !root_lockfile_path.exists() {
         bail!("Failed to generate Cargo.lock file")
@@ -652,6 +769,8 @@ mod test {
             ]),
             cargo_config: None,
             resolver_version: cargo_toml::Resolver::V2,
+            mirrors: RegistryMirrors::default(),
+            duplicates: DuplicateCrates::default(),
         };
         let metadata = SplicingMetadata::try_from(manifest).unwrap();
         let metadata = serde_json::to_string(&metadata).unwrap();
@@ -660,4 +779,36 @@ mod test {
             "serialized metadata should not contain absolute path"
         );
     }
+
+    #[test]
+    fn yanked_crate_warnings() {
+        let metadata = WorkspaceMetadata {
+            yanked: BTreeMap::from([
+                (
+                    CrateId::new("foo".to_owned(), semver::Version::new(1, 2, 3)),
+                    YankedCrate {
+                        replacement: Some(semver::Version::new(1, 2, 4)),
+                    },
+                ),
+                (
+                    CrateId::new("bar".to_owned(), semver::Version::new(0, 1, 0)),
+                    YankedCrate { replacement: None },
+                ),
+            ]),
+            ..WorkspaceMetadata::default()
+        };
+
+        assert_eq!(
+            metadata.yanked_crate_warnings(false).unwrap(),
+            vec![
+                "The locked version of bar 0.1.0 has been yanked and no compatible version is available.".to_owned(),
+                "The locked version of foo 1.2.3 has been yanked. The newest compatible version is 1.2.4.".to_owned(),
+            ]
+        );
+        assert!(metadata.yanked_crate_warnings(true).is_err());
+        assert!(WorkspaceMetadata::default()
+            .yanked_crate_warnings(true)
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -1,23 +1,63 @@
 //! Tools for parsing [Cargo configuration](https://doc.rust-lang.org/cargo/reference/config.html) files
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use crate::utils;
-use anyhow::{bail, Result};
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// The tables of a Cargo config which affect where crates are resolved and downloaded from.
+const REGISTRY_TABLES: &[&str] = &["registries", "registry", "source"];
 
 /// The [`[registry]`](https://doc.rust-lang.org/cargo/reference/config.html#registry)
 /// table controls the default registry used when one is not specified.
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub(crate) struct Registry {
     /// name of the default registry
+    #[serde(default = "default_registry_name")]
     pub(crate) default: String,
 
     /// authentication token for crates.io
     pub(crate) token: Option<String>,
+
+    /// credential provider for crates.io
+    #[serde(
+        rename = "credential-provider",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) credential_provider: Option<CredentialProvider>,
+
+    /// credential providers for registries without a `credential-provider`,
+    /// where later providers take precedence over earlier ones
+    #[serde(
+        rename = "global-credential-providers",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub(crate) global_credential_providers: Vec<CredentialProvider>,
+}
+
+/// A [credential provider](https://doc.rust-lang.org/cargo/reference/registry-authentication.html)
+/// command, given either as a single string of space separated arguments or as a list.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub(crate) enum CredentialProvider {
+    String(String),
+    List(Vec<String>),
+}
+
+impl CredentialProvider {
+    /// The provider and its arguments.
+    pub(crate) fn args(&self) -> Vec<String> {
+        match self {
+            Self::String(command) => command.split_whitespace().map(str::to_owned).collect(),
+            Self::List(args) => args.clone(),
+        }
+    }
 }
 
 /// The [`[source]`](https://doc.rust-lang.org/cargo/reference/config.html#source)
@@ -29,8 +69,23 @@ pub(crate) struct Source {
     pub(crate) replace_with: Option<String>,
 
     /// URL to a registry source
-    #[serde(default = "default_registry_url")]
-    pub(crate) registry: String,
+    #[serde(default, serialize_with = "serialize_source_registry")]
+    pub(crate) registry: Option<String>,
+
+    /// A directory of vendored crates, as produced by `cargo vendor`
+    #[serde(default, skip_serializing)]
+    pub(crate) directory: Option<String>,
+
+    /// A directory holding a local registry
+    #[serde(rename = "local-registry", default, skip_serializing)]
+    pub(crate) local_registry: Option<String>,
+}
+
+impl Source {
+    /// Whether the crates of this source are read from disk rather than from a registry.
+    fn is_local(&self) -> bool {
+        self.directory.is_some() || self.local_registry.is_some()
+    }
 }
 
 /// This is the default registry url per what's defined by Cargo.
@@ -38,6 +93,23 @@ fn default_registry_url() -> String {
     utils::CRATES_IO_INDEX_URL.to_owned()
 }
 
+/// Sources without a registry were always serialized with the crates.io index, which is kept
+/// so the digests of existing lockfiles don't change.
+fn serialize_source_registry<S: Serializer>(
+    registry: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match registry {
+        Some(registry) => serializer.serialize_str(registry),
+        None => serializer.serialize_str(utils::CRATES_IO_INDEX_URL),
+    }
+}
+
+/// Registry urls are compared regardless of the `sparse+` protocol prefix and trailing slashes.
+fn normalize_registry_url(url: &str) -> &str {
+    url.trim_start_matches("sparse+").trim_end_matches('/')
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
 /// registries other than crates.io
 pub(crate) struct AdditionalRegistry {
@@ -46,6 +118,14 @@ pub(crate) struct AdditionalRegistry {
 
     /// authentication token for the registry
     pub(crate) token: Option<String>,
+
+    /// credential provider for the registry
+    #[serde(
+        rename = "credential-provider",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) credential_provider: Option<CredentialProvider>,
 }
 
 /// A subset of a Cargo configuration file. The schema here is only what
@@ -64,6 +144,14 @@ pub(crate) struct CargoConfig {
     /// source definition and replacement
     #[serde(default = "BTreeMap::new")]
     pub(crate) source: BTreeMap<String, Source>,
+
+    /// aliases for credential providers
+    #[serde(
+        rename = "credential-alias",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub(crate) credential_alias: BTreeMap<String, CredentialProvider>,
 }
 
 /// Each Cargo config is expected to have a default `crates-io` registry.
@@ -74,16 +162,24 @@ fn default_registries() -> BTreeMap<String, AdditionalRegistry> {
         AdditionalRegistry {
             index: default_registry_url(),
             token: None,
+            credential_provider: None,
         },
     );
     registries
 }
 
+/// The name of the default registry per what's defined by Cargo.
+fn default_registry_name() -> String {
+    "crates-io".to_owned()
+}
+
 /// Each Cargo config has a default registry for `crates.io`.
 fn default_registry() -> Registry {
     Registry {
-        default: "crates-io".to_owned(),
+        default: default_registry_name(),
         token: None,
+        credential_provider: None,
+        global_credential_providers: Vec::new(),
     }
 }
 
@@ -92,11 +188,13 @@ impl Default for CargoConfig {
         let registries = default_registries();
         let registry = default_registry();
         let source = Default::default();
+        let credential_alias = Default::default();
 
         Self {
             registries,
             registry,
             source,
+            credential_alias,
         }
     }
 }
@@ -110,6 +208,7 @@ impl FromStr for CargoConfig {
         config.registries.extend(incoming.registries);
         config.source.extend(incoming.source);
         config.registry = incoming.registry;
+        config.credential_alias.extend(incoming.credential_alias);
         Ok(config)
     }
 }
@@ -123,42 +222,83 @@ impl CargoConfig {
 
     /// Look up a registry [Source] by its url.
     pub(crate) fn get_source_from_url(&self, url: &str) -> Option<&Source> {
-        if let Some(found) = self.source.values().find(|v| v.registry == url) {
-            Some(found)
-        } else if url == utils::CRATES_IO_INDEX_URL {
-            self.source.get("crates-io")
-        } else {
-            None
-        }
+        let url = normalize_registry_url(url);
+        self.source
+            .iter()
+            .find(|(name, _)| {
+                self.source_url(name)
+                    .is_some_and(|source_url| normalize_registry_url(source_url) == url)
+            })
+            .map(|(_, source)| source)
+    }
+
+    /// The index url of the source `name`, which may also name a registry. Sources without
+    /// a `registry` key, such as the `crates-io` source, use the index of that registry.
+    fn source_url(&self, name: &str) -> Option<&str> {
+        self.source
+            .get(name)
+            .and_then(|source| source.registry.as_deref())
+            .or_else(|| self.get_registry_index_url_by_name(name))
     }
 
     pub(crate) fn get_registry_index_url_by_name(&self, name: &str) -> Option<&str> {
         if let Some(registry) = self.registries.get(name) {
             Some(&registry.index)
-        } else if let Some(source) = self.source.get(name) {
-            Some(&source.registry)
+        } else if let Some(registry) = self.source.get(name).and_then(|s| s.registry.as_deref()) {
+            Some(registry)
+        } else if name == "crates-io" {
+            Some(utils::CRATES_IO_INDEX_URL)
         } else {
             None
         }
     }
 
+    /// Follow the `replace-with` settings of the source of `url`, as Cargo does, to the url of
+    /// the registry crates are downloaded from. Replacements with local sources, e.g. vendored
+    /// directories, are not followed as crates can't be downloaded from them.
     pub(crate) fn resolve_replacement_url<'a>(&'a self, url: &'a str) -> Result<&'a str> {
-        if let Some(source) = self.get_source_from_url(url) {
-            if let Some(replace_with) = &source.replace_with {
-                if let Some(replacement) = self.get_registry_index_url_by_name(replace_with) {
-                    Ok(replacement)
-                } else {
-                    bail!("Tried to replace registry {} with registry named {} but didn't have metadata about the replacement", url, replace_with);
-                }
-            } else {
-                Ok(url)
+        let mut current = url;
+        let mut replaced = BTreeSet::new();
+        while let Some(source) = self.get_source_from_url(current) {
+            let Some(replace_with) = &source.replace_with else {
+                break;
+            };
+            if !replaced.insert(normalize_registry_url(current)) {
+                bail!("The replacements of registry {url} in the Cargo config form a cycle");
             }
-        } else {
-            Ok(url)
+            if self.source.get(replace_with).is_some_and(Source::is_local) {
+                tracing::debug!(
+                    "Not resolving the replacement of {current} with local source {replace_with}"
+                );
+                break;
+            }
+            current = self
+                .get_registry_index_url_by_name(replace_with)
+                .ok_or_else(|| anyhow!("Tried to replace registry {} with registry named {} but didn't have metadata about the replacement", current, replace_with))?;
         }
+        Ok(current)
     }
 }
 
+/// The Cargo config of the workspace in `dir`, which Cargo would use when run from it.
+pub(crate) fn find_workspace_cargo_config(dir: &Path) -> Option<PathBuf> {
+    ["config.toml", "config"]
+        .into_iter()
+        .map(|name| dir.join(".cargo").join(name))
+        .find(|path| path.exists())
+}
+
+/// The source replacement and registry settings of the Cargo config at `path`, leaving out
+/// settings such as `build.rustflags` which don't apply to Bazel builds.
+pub(crate) fn registry_settings(path: &Path) -> Result<toml::Table> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut config: toml::Table =
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+    config.retain(|key, _| REGISTRY_TABLES.contains(&key));
+    Ok(config)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -197,6 +337,7 @@ mod test {
                             index: "https://artprod.mycompany/artifactory/git/cargo-remote.git"
                                 .to_owned(),
                             token: None,
+                            credential_provider: None,
                         },
                     ),
                     (
@@ -204,14 +345,18 @@ mod test {
                         AdditionalRegistry {
                             index: "https://github.com/rust-lang/crates.io-index".to_owned(),
                             token: None,
+                            credential_provider: None,
                         },
                     ),
                 ]),
                 registry: Registry {
                     default: "art-crates-remote".to_owned(),
                     token: None,
+                    credential_provider: None,
+                    global_credential_providers: Vec::new(),
                 },
                 source: BTreeMap::new(),
+                credential_alias: BTreeMap::new(),
             },
         )
     }
@@ -283,7 +428,7 @@ mod test {
         assert_eq!(
             config
                 .get_source_from_url("https://artmirror.mycompany/artifactory/cargo-mirror.git")
-                .map(|s| s.registry.as_str()),
+                .and_then(|s| s.registry.as_deref()),
             Some("https://artmirror.mycompany/artifactory/cargo-mirror.git"),
         );
     }
@@ -392,4 +537,143 @@ mod test {
             "https://artprod.mycompany/artifactory/git/cargo-remote.git"
         );
     }
+
+    #[test]
+    fn resolve_replacement_url_chain() {
+        let config = CargoConfig::from_str(&textwrap::dedent(
+            r#"
+                [source.crates-io]
+                replace-with = "mirror"
+
+                [source.mirror]
+                registry = "sparse+https://mirror.mycompany/index/"
+                replace-with = "internal"
+
+                [registries]
+                internal = { index = "sparse+https://crates.mycompany/index/" }
+
+                [source.internal]
+                replace-with = "vendored"
+
+                [source.vendored]
+                directory = "vendor"
+            "#,
+        ))
+        .unwrap();
+
+        // Replacements are followed up to the local source crates can't be downloaded from.
+        assert_eq!(
+            config
+                .resolve_replacement_url(utils::CRATES_IO_INDEX_URL)
+                .unwrap(),
+            "sparse+https://crates.mycompany/index/"
+        );
+        assert_eq!(
+            config
+                .resolve_replacement_url("https://mirror.mycompany/index")
+                .unwrap(),
+            "sparse+https://crates.mycompany/index/"
+        );
+    }
+
+    #[test]
+    fn resolve_replacement_url_cycle() {
+        let config = CargoConfig::from_str(&textwrap::dedent(
+            r#"
+                [source.crates-io]
+                replace-with = "mirror"
+
+                [source.mirror]
+                registry = "https://mirror.mycompany/index"
+                replace-with = "crates-io"
+            "#,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            config
+                .resolve_replacement_url(utils::CRATES_IO_INDEX_URL)
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "The replacements of registry {} in the Cargo config form a cycle",
+                utils::CRATES_IO_INDEX_URL
+            )
+        );
+    }
+
+    #[test]
+    fn workspace_registry_settings() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert_eq!(find_workspace_cargo_config(temp_dir.as_ref()), None);
+
+        let dot_cargo_dir = temp_dir.as_ref().join(".cargo");
+        fs::create_dir(&dot_cargo_dir).unwrap();
+        fs::write(dot_cargo_dir.join("config"), "").unwrap();
+        fs::write(
+            dot_cargo_dir.join("config.toml"),
+            textwrap::dedent(
+                r#"
+                [build]
+                rustflags = ["-Ctarget-cpu=native"]
+
+                [source.crates-io]
+                replace-with = "mirror"
+
+                [source.mirror]
+                registry = "https://mirror.mycompany/index"
+                "#,
+            ),
+        )
+        .unwrap();
+
+        let path = find_workspace_cargo_config(temp_dir.as_ref()).unwrap();
+        assert_eq!(path, dot_cargo_dir.join("config.toml"));
+
+        let settings = super::registry_settings(&path).unwrap();
+        assert_eq!(settings.keys().collect::<Vec<_>>(), vec!["source"]);
+    }
+
+    #[test]
+    fn credential_provider_settings() {
+        let config = CargoConfig::from_str(&textwrap::dedent(
+            r#"
+                [registry]
+                global-credential-providers = ["cargo:token", "my-keychain"]
+
+                [registries]
+                private = { index = "sparse+https://crates.mycompany/index/", credential-provider = ["cargo:token-from-stdout", "get-token", "--registry=private"] }
+                other = { index = "sparse+https://other.mycompany/index/", credential-provider = "my-keychain" }
+
+                [credential-alias]
+                my-keychain = "/usr/bin/keychain-provider --store=cargo"
+            "#,
+        ))
+        .unwrap();
+
+        assert_eq!(config.registry.default, "crates-io");
+        assert_eq!(
+            config.registry.global_credential_providers,
+            vec![
+                CredentialProvider::String("cargo:token".to_owned()),
+                CredentialProvider::String("my-keychain".to_owned()),
+            ]
+        );
+        assert_eq!(
+            config.registries["private"]
+                .credential_provider
+                .as_ref()
+                .unwrap()
+                .args(),
+            vec!["cargo:token-from-stdout", "get-token", "--registry=private"]
+        );
+        assert_eq!(
+            config.registries["other"].credential_provider,
+            Some(CredentialProvider::String("my-keychain".to_owned()))
+        );
+        assert_eq!(
+            config.credential_alias["my-keychain"].args(),
+            vec!["/usr/bin/keychain-provider", "--store=cargo"]
+        );
+    }
 }
@@ -1,7 +1,8 @@
-use crate::splicing::SourceInfo;
+use crate::splicing::{SourceInfo, YankedCrate};
 use anyhow::{Context, Result};
 use crates_index::IndexConfig;
 use hex::ToHex;
+use semver::{Version, VersionReq};
 
 pub(crate) enum CrateIndexLookup {
     Git(crates_index::GitIndex),
@@ -13,16 +14,7 @@ impl CrateIndexLookup {
         let index_config = self
             .index_config()
             .context("Failed to get crate index config")?;
-        let crate_ = match self {
-            // The crates we care about should all be in the cache already,
-            // because `cargo metadata` ran which should have fetched them.
-            Self::Http(index) => Some(
-                index
-                    .crate_from_cache(pkg.name.as_str())
-                    .with_context(|| format!("Failed to get crate from cache for {pkg:?}"))?,
-            ),
-            Self::Git(index) => index.crate_(pkg.name.as_str()),
-        };
+        let crate_ = self.crate_(pkg)?;
         let source_info = crate_.and_then(|crate_idx| {
             crate_idx
                 .versions()
@@ -42,6 +34,46 @@ impl CrateIndexLookup {
         Ok(source_info)
     }
 
+    /// Determine whether the locked version of `pkg` was yanked from the index and if so,
+    /// which version it could be replaced with.
+    pub(crate) fn find_yanked(&self, pkg: &cargo_lock::Package) -> Result<Option<YankedCrate>> {
+        let crate_ = match self.crate_(pkg)? {
+            Some(crate_) => crate_,
+            None => return Ok(None),
+        };
+
+        let is_yanked = crate_
+            .versions()
+            .iter()
+            .any(|v| v.is_yanked() && v.version() == pkg.version.to_string());
+        if !is_yanked {
+            return Ok(None);
+        }
+
+        let available = crate_
+            .versions()
+            .iter()
+            .filter(|v| !v.is_yanked())
+            .filter_map(|v| Version::parse(v.version()).ok());
+
+        Ok(Some(YankedCrate {
+            replacement: compatible_replacement(&pkg.version, available),
+        }))
+    }
+
+    fn crate_(&self, pkg: &cargo_lock::Package) -> Result<Option<crates_index::Crate>> {
+        match self {
+            // The crates we care about should all be in the cache already,
+            // because `cargo metadata` ran which should have fetched them.
+            Self::Http(index) => Ok(Some(
+                index
+                    .crate_from_cache(pkg.name.as_str())
+                    .with_context(|| format!("Failed to get crate from cache for {pkg:?}"))?,
+            )),
+            Self::Git(index) => Ok(index.crate_(pkg.name.as_str())),
+        }
+    }
+
     #[allow(clippy::result_large_err)]
     fn index_config(&self) -> Result<IndexConfig, crates_index::Error> {
         match self {
@@ -51,8 +83,18 @@ impl CrateIndexLookup {
     }
 }
 
+/// Find the newest version in `available` which `cargo update` could select in place of `version`.
+fn compatible_replacement(
+    version: &Version,
+    available: impl Iterator<Item = Version>,
+) -> Option<Version> {
+    let req = VersionReq::parse(&format!("^{version}")).ok()?;
+    available.filter(|v| v != version && req.matches(v)).max()
+}
+
 #[cfg(test)]
 mod test {
+    use super::compatible_replacement;
     use crate::splicing::crate_index_lookup::CrateIndexLookup;
     use semver::Version;
     use std::ffi::OsString;
@@ -125,6 +167,39 @@ mod test {
         }
     }
 
+    #[test]
+    fn compatible_replacement_of_yanked_versions() {
+        let versions = |versions: &[&str]| {
+            versions
+                .iter()
+                .map(|v| Version::parse(v).unwrap())
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+
+        assert_eq!(
+            compatible_replacement(
+                &Version::parse("1.2.3").unwrap(),
+                versions(&["1.2.2", "1.3.0", "1.4.1", "2.0.0", "1.5.0-rc.1"])
+            ),
+            Some(Version::parse("1.4.1").unwrap())
+        );
+        assert_eq!(
+            compatible_replacement(
+                &Version::parse("0.3.1").unwrap(),
+                versions(&["0.3.0", "0.3.2", "0.4.0"])
+            ),
+            Some(Version::parse("0.3.2").unwrap())
+        );
+        assert_eq!(
+            compatible_replacement(
+                &Version::parse("0.3.1").unwrap(),
+                versions(&["0.2.9", "0.4.0"])
+            ),
+            None
+        );
+    }
+
     struct EnvVarResetter {
         key: OsString,
         value: Option<OsString>,
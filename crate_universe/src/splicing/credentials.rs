@@ -0,0 +1,484 @@
+//! Tools for resolving [registry credentials](https://doc.rust-lang.org/cargo/reference/registry-authentication.html)
+//! the same way Cargo does.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use url::Url;
+
+use crate::splicing::cargo_config::{CargoConfig, CredentialProvider};
+
+/// The name Cargo uses for the crates.io registry.
+const CRATES_IO: &str = "crates-io";
+
+/// The provider Cargo uses when none is configured.
+const DEFAULT_PROVIDER: &str = "cargo:token";
+
+/// The subset of a [Cargo credentials file](https://doc.rust-lang.org/cargo/reference/config.html#credentials)
+/// required for reading tokens.
+#[derive(Debug, Default, Deserialize)]
+struct CredentialsFile {
+    #[serde(default)]
+    registry: Option<CredentialsEntry>,
+
+    #[serde(default)]
+    registries: BTreeMap<String, CredentialsEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CredentialsEntry {
+    token: Option<String>,
+}
+
+/// The first message sent by a credential provider, listing the protocol versions it supports.
+#[derive(Debug, Deserialize)]
+struct ProviderHello {
+    v: Vec<u32>,
+}
+
+/// The reply of a credential provider to a `get` request.
+#[derive(Debug, Deserialize)]
+enum ProviderResponse {
+    Ok(ProviderToken),
+    Err(ProviderError),
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderToken {
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderError {
+    kind: String,
+    message: Option<String>,
+}
+
+/// Resolves the tokens of the registries defined in a [CargoConfig].
+pub(crate) struct RegistryCredentials<'a> {
+    config: &'a CargoConfig,
+    env: BTreeMap<String, String>,
+}
+
+impl<'a> RegistryCredentials<'a> {
+    pub(crate) fn new(config: &'a CargoConfig) -> Self {
+        Self {
+            config,
+            env: env::vars().collect(),
+        }
+    }
+
+    #[cfg(test)]
+    fn with_env(config: &'a CargoConfig, env: BTreeMap<String, String>) -> Self {
+        Self { config, env }
+    }
+
+    /// The environment variable Cargo reads the token of a registry from.
+    pub(crate) fn token_env_var(name: &str) -> String {
+        if name == CRATES_IO {
+            "CARGO_REGISTRY_TOKEN".to_owned()
+        } else {
+            format!(
+                "CARGO_REGISTRIES_{}_TOKEN",
+                name.to_uppercase().replace('-', "_")
+            )
+        }
+    }
+
+    /// Find the name of the registry serving `url`, either its index or one of its downloads.
+    pub(crate) fn registry_for_url(&self, url: &str) -> Option<&'a str> {
+        let url = Url::parse(url).ok()?;
+        self.config
+            .registries
+            .iter()
+            .find(|(_, registry)| {
+                let index = registry
+                    .index
+                    .strip_prefix("sparse+")
+                    .unwrap_or(&registry.index);
+                Url::parse(index).is_ok_and(|index| {
+                    index.host_str() == url.host_str()
+                        && index.port_or_known_default() == url.port_or_known_default()
+                })
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Resolve the token of a registry by trying each of its credential providers in turn.
+    pub(crate) fn token(&self, name: &str) -> Result<Option<String>> {
+        let index_url = self
+            .config
+            .registries
+            .get(name)
+            .map(|registry| registry.index.as_str())
+            .ok_or_else(|| anyhow!("No registry named `{name}` is defined"))?;
+
+        for provider in self.providers(name) {
+            let args = self.expand_alias(provider);
+            let token = match args.first().map(String::as_str) {
+                None => bail!("An empty credential provider is configured for registry `{name}`"),
+                Some(DEFAULT_PROVIDER) => self.configured_token(name)?,
+                Some("cargo:token-from-stdout") => {
+                    Some(self.token_from_stdout(name, index_url, &args[1..])?)
+                }
+                Some(builtin) if builtin.starts_with("cargo:") => {
+                    tracing::warn!(
+                        "The credential provider `{builtin}` is not supported, skipping it for registry `{name}`"
+                    );
+                    None
+                }
+                Some(_) => self.token_from_provider(name, index_url, &args)?,
+            };
+
+            if token.is_some() {
+                return Ok(token);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The credential providers of a registry, in the order they should be tried.
+    fn providers(&self, name: &str) -> Vec<CredentialProvider> {
+        let registry_provider = if name == CRATES_IO {
+            self.config.registry.credential_provider.as_ref()
+        } else {
+            None
+        }
+        .or_else(|| {
+            self.config
+                .registries
+                .get(name)
+                .and_then(|registry| registry.credential_provider.as_ref())
+        });
+
+        if let Some(provider) = registry_provider {
+            vec![provider.clone()]
+        } else if !self.config.registry.global_credential_providers.is_empty() {
+            self.config
+                .registry
+                .global_credential_providers
+                .iter()
+                .rev()
+                .cloned()
+                .collect()
+        } else {
+            vec![CredentialProvider::String(DEFAULT_PROVIDER.to_owned())]
+        }
+    }
+
+    /// Replace a provider naming a `[credential-alias]` with the aliased command.
+    fn expand_alias(&self, provider: CredentialProvider) -> Vec<String> {
+        let args = provider.args();
+        match args.as_slice() {
+            [alias] => self
+                .config
+                .credential_alias
+                .get(alias)
+                .map(CredentialProvider::args)
+                .unwrap_or(args),
+            _ => args,
+        }
+    }
+
+    /// The `cargo:token` provider, reading the token from the environment,
+    /// the Cargo config or the Cargo credentials file.
+    fn configured_token(&self, name: &str) -> Result<Option<String>> {
+        if let Some(token) = self.env.get(&Self::token_env_var(name)) {
+            return Ok(Some(token.clone()));
+        }
+
+        let config_token = if name == CRATES_IO {
+            self.config.registry.token.clone()
+        } else {
+            None
+        }
+        .or_else(|| {
+            self.config
+                .registries
+                .get(name)
+                .and_then(|registry| registry.token.clone())
+        });
+        if config_token.is_some() {
+            return Ok(config_token);
+        }
+
+        let Some(cargo_home) = self.cargo_home() else {
+            return Ok(None);
+        };
+        for file_name in ["credentials.toml", "credentials"] {
+            let path = cargo_home.join(file_name);
+            if !path.exists() {
+                continue;
+            }
+
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let mut credentials: CredentialsFile = toml::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            let entry = if name == CRATES_IO {
+                credentials.registry
+            } else {
+                credentials.registries.remove(name)
+            };
+            return Ok(entry.and_then(|entry| entry.token));
+        }
+
+        Ok(None)
+    }
+
+    fn cargo_home(&self) -> Option<PathBuf> {
+        if let Some(cargo_home) = self.env.get("CARGO_HOME") {
+            return Some(PathBuf::from(cargo_home));
+        }
+        self.env
+            .get("HOME")
+            .or_else(|| self.env.get("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(".cargo"))
+    }
+
+    /// The `cargo:token-from-stdout` provider, reading the token from the output of a command.
+    fn token_from_stdout(&self, name: &str, index_url: &str, args: &[String]) -> Result<String> {
+        let (program, args) = args.split_first().ok_or_else(|| {
+            anyhow!(
+                "The `cargo:token-from-stdout` provider of registry `{name}` requires a command"
+            )
+        })?;
+
+        let output = Command::new(program)
+            .args(args)
+            .env("CARGO_REGISTRY_INDEX_URL", index_url)
+            .env("CARGO_REGISTRY_NAME_OPT", name)
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("Failed to run `{program}` for registry `{name}`"))?;
+        if !output.status.success() {
+            bail!(
+                "`{program}` failed to provide a token for registry `{name}`: {}",
+                output.status
+            );
+        }
+
+        let stdout = String::from_utf8(output.stdout)
+            .with_context(|| format!("`{program}` printed a token which isn't valid UTF-8"))?;
+        let token = stdout.lines().next().unwrap_or_default().trim();
+        if token.is_empty() {
+            bail!("`{program}` printed an empty token for registry `{name}`");
+        }
+
+        Ok(token.to_owned())
+    }
+
+    /// An external provider, queried over version 1 of the
+    /// [credential provider protocol](https://doc.rust-lang.org/cargo/reference/credential-provider-protocol.html).
+    fn token_from_provider(
+        &self,
+        name: &str,
+        index_url: &str,
+        args: &[String],
+    ) -> Result<Option<String>> {
+        let (program, args) = args.split_first().ok_or_else(|| {
+            anyhow!("An empty credential provider is configured for registry `{name}`")
+        })?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .arg("--cargo-plugin")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("Failed to spawn credential provider `{program}`"))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        let mut line = String::new();
+        stdout.read_line(&mut line)?;
+        let hello: ProviderHello = serde_json::from_str(&line)
+            .with_context(|| format!("Unexpected greeting from credential provider `{program}`"))?;
+        if !hello.v.contains(&1) {
+            bail!("The credential provider `{program}` doesn't support version 1 of the protocol");
+        }
+
+        let request = serde_json::json!({
+            "v": 1,
+            "registry": {
+                "index-url": index_url,
+                "name": name,
+            },
+            "kind": "get",
+            "operation": "read",
+            "args": [],
+        });
+        writeln!(stdin, "{request}")?;
+
+        line.clear();
+        stdout.read_line(&mut line)?;
+        drop(stdin);
+        child.wait()?;
+
+        let response: ProviderResponse = serde_json::from_str(&line)
+            .with_context(|| format!("Unexpected response from credential provider `{program}`"))?;
+        match response {
+            ProviderResponse::Ok(ProviderToken { token }) => Ok(Some(token)),
+            ProviderResponse::Err(ProviderError { kind, .. }) if kind == "not-found" => Ok(None),
+            ProviderResponse::Err(ProviderError { kind, message }) => bail!(
+                "The credential provider `{program}` failed for registry `{name}` ({kind}): {}",
+                message.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::str::FromStr;
+
+    fn config(content: &str) -> CargoConfig {
+        CargoConfig::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn token_env_var() {
+        assert_eq!(
+            RegistryCredentials::token_env_var("crates-io"),
+            "CARGO_REGISTRY_TOKEN"
+        );
+        assert_eq!(
+            RegistryCredentials::token_env_var("my-registry"),
+            "CARGO_REGISTRIES_MY_REGISTRY_TOKEN"
+        );
+    }
+
+    #[test]
+    fn token_from_env_and_config() {
+        let config = config(indoc::indoc! {r#"
+            [registries.in-config]
+            index = "sparse+https://in-config.example.com/index/"
+            token = "config token"
+
+            [registries.in-env]
+            index = "sparse+https://in-env.example.com/index/"
+            token = "config token"
+
+            [registries.missing]
+            index = "sparse+https://missing.example.com/index/"
+        "#});
+        let credentials = RegistryCredentials::with_env(
+            &config,
+            BTreeMap::from([(
+                "CARGO_REGISTRIES_IN_ENV_TOKEN".to_owned(),
+                "env token".to_owned(),
+            )]),
+        );
+
+        assert_eq!(
+            credentials.token("in-config").unwrap().as_deref(),
+            Some("config token")
+        );
+        assert_eq!(
+            credentials.token("in-env").unwrap().as_deref(),
+            Some("env token")
+        );
+        assert_eq!(credentials.token("missing").unwrap(), None);
+        assert!(credentials.token("undefined").is_err());
+    }
+
+    #[test]
+    fn token_from_credentials_file() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        fs::write(
+            cargo_home.path().join("credentials.toml"),
+            indoc::indoc! {r#"
+                [registries.private]
+                token = "file token"
+            "#},
+        )
+        .unwrap();
+
+        let config = config(indoc::indoc! {r#"
+            [registries.private]
+            index = "https://private.example.com/index.git"
+        "#});
+        let credentials = RegistryCredentials::with_env(
+            &config,
+            BTreeMap::from([(
+                "CARGO_HOME".to_owned(),
+                cargo_home.path().to_string_lossy().to_string(),
+            )]),
+        );
+
+        assert_eq!(
+            credentials.token("private").unwrap().as_deref(),
+            Some("file token")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn token_from_stdout_provider() {
+        let config = config(indoc::indoc! {r#"
+            [registries.private]
+            index = "sparse+https://private.example.com/index/"
+            credential-provider = "printer"
+
+            [credential-alias]
+            printer = ["cargo:token-from-stdout", "sh", "-c", "echo \"$CARGO_REGISTRY_NAME_OPT token\""]
+        "#});
+        let credentials = RegistryCredentials::with_env(&config, BTreeMap::new());
+
+        assert_eq!(
+            credentials.token("private").unwrap().as_deref(),
+            Some("private token")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn token_from_global_providers() {
+        let config = config(indoc::indoc! {r#"
+            [registry]
+            global-credential-providers = ["cargo:token-from-stdout echo fallback", "cargo:token"]
+
+            [registries.private]
+            index = "sparse+https://private.example.com/index/"
+        "#});
+        let credentials = RegistryCredentials::with_env(&config, BTreeMap::new());
+
+        assert_eq!(
+            credentials.token("private").unwrap().as_deref(),
+            Some("fallback")
+        );
+    }
+
+    #[test]
+    fn registry_for_url() {
+        let config = config(indoc::indoc! {r#"
+            [registries.private]
+            index = "sparse+https://private.example.com/index/"
+        "#});
+        let credentials = RegistryCredentials::with_env(&config, BTreeMap::new());
+
+        assert_eq!(
+            credentials
+                .registry_for_url("https://private.example.com/api/v1/crates/foo/1.0.0/download"),
+            Some("private")
+        );
+        assert_eq!(
+            credentials.registry_for_url("https://private.example.com:8080/foo"),
+            None
+        );
+        assert_eq!(
+            credentials.registry_for_url("https://other.example.com/foo"),
+            None
+        );
+    }
+}
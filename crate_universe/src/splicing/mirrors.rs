@@ -0,0 +1,225 @@
+//! Routing of registry traffic through mirrors, e.g. in corporate networks where crates may
+//! only be downloaded through an internal proxy.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// The url of the sparse crates.io index.
+const CRATES_IO_SPARSE_INDEX_URL: &str = "sparse+https://index.crates.io/";
+
+/// Mirrors for registry indexes and `.crate` downloads along with the certificate
+/// authorities used to reach them.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RegistryMirrors {
+    /// A mapping of registry index urls to the urls of the indexes mirroring them,
+    /// e.g. `sparse+https://index.crates.io/` to `sparse+https://mirror.example.com/index/`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) indexes: BTreeMap<String, String>,
+
+    /// A mapping of url prefixes of `.crate` downloads to the prefixes replacing them,
+    /// e.g. `https://static.crates.io/crates/` to `https://mirror.example.com/crates/`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) downloads: BTreeMap<String, String>,
+
+    /// The path to a file of PEM encoded certificate authorities to trust instead of the
+    /// system's when connecting to registries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) ca_info: Option<String>,
+}
+
+impl RegistryMirrors {
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Rewrite the url of a `.crate` download using the longest matching prefix of `downloads`.
+    pub(crate) fn rewrite_download_url(&self, url: &str) -> String {
+        self.downloads
+            .iter()
+            .filter_map(|(prefix, mirror)| Some((prefix, mirror, url.strip_prefix(prefix)?)))
+            .max_by_key(|(prefix, _, _)| prefix.len())
+            .map(|(_, mirror, suffix)| format!("{mirror}{suffix}"))
+            .unwrap_or_else(|| url.to_owned())
+    }
+
+    /// Add [source replacements](https://doc.rust-lang.org/cargo/reference/source-replacement.html)
+    /// for each mirrored index and the certificate authorities to a Cargo config file, creating
+    /// the file if it doesn't exist.
+    pub(crate) fn install(&self, cargo_config_path: &Path) -> Result<()> {
+        if self.indexes.is_empty() && self.ca_info.is_none() {
+            return Ok(());
+        }
+
+        let content = if cargo_config_path.exists() {
+            fs::read_to_string(cargo_config_path).with_context(|| {
+                format!(
+                    "Failed to read cargo config `{}`",
+                    cargo_config_path.display()
+                )
+            })?
+        } else {
+            String::new()
+        };
+
+        let config = self.apply(&content)?;
+
+        if let Some(parent) = cargo_config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(cargo_config_path, config).with_context(|| {
+            format!(
+                "Failed to write cargo config `{}`",
+                cargo_config_path.display()
+            )
+        })
+    }
+
+    /// Apply the mirrors to the contents of a Cargo config file.
+    fn apply(&self, content: &str) -> Result<String> {
+        let mut config: toml::Table =
+            toml::from_str(content).context("Failed to parse cargo config")?;
+
+        let sources = config
+            .entry("source")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .context("The `source` field of the cargo config is expected to be a table")?;
+        for (i, (index, mirror)) in self.indexes.iter().enumerate() {
+            let mirror_name = format!("cargo-bazel-mirror-{i}");
+            let mut replaced = toml::Table::new();
+            let replaced_name =
+                if index == utils::CRATES_IO_INDEX_URL || index == CRATES_IO_SPARSE_INDEX_URL {
+                    "crates-io".to_owned()
+                } else {
+                    replaced.insert("registry".to_owned(), index.clone().into());
+                    format!("cargo-bazel-mirrored-{i}")
+                };
+            replaced.insert("replace-with".to_owned(), mirror_name.clone().into());
+
+            if sources.contains_key(&replaced_name) {
+                bail!("The registry `{index}` is mirrored but the cargo config already defines the source `{replaced_name}`");
+            }
+            sources.insert(replaced_name, replaced.into());
+            sources.insert(
+                mirror_name,
+                toml::Table::from_iter([("registry".to_owned(), mirror.clone().into())]).into(),
+            );
+        }
+
+        if let Some(ca_info) = &self.ca_info {
+            config
+                .entry("http")
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .context("The `http` field of the cargo config is expected to be a table")?
+                .insert("cainfo".to_owned(), ca_info.clone().into());
+        }
+
+        toml::to_string(&config).context("Failed to serialize cargo config")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::splicing::cargo_config::CargoConfig;
+
+    #[test]
+    fn rewrite_download_urls() {
+        let mirrors = RegistryMirrors {
+            downloads: BTreeMap::from([
+                (
+                    "https://static.crates.io/".to_owned(),
+                    "https://mirror.example.com/".to_owned(),
+                ),
+                (
+                    "https://static.crates.io/crates/".to_owned(),
+                    "https://mirror.example.com/api/crates/".to_owned(),
+                ),
+            ]),
+            ..RegistryMirrors::default()
+        };
+
+        assert_eq!(
+            mirrors.rewrite_download_url("https://static.crates.io/crates/serde/1.0.0/download"),
+            "https://mirror.example.com/api/crates/serde/1.0.0/download"
+        );
+        assert_eq!(
+            mirrors.rewrite_download_url("https://static.crates.io/other"),
+            "https://mirror.example.com/other"
+        );
+        assert_eq!(
+            mirrors.rewrite_download_url("https://example.com/serde.crate"),
+            "https://example.com/serde.crate"
+        );
+    }
+
+    #[test]
+    fn install_source_replacements() {
+        let mirrors = RegistryMirrors {
+            indexes: BTreeMap::from([
+                (
+                    CRATES_IO_SPARSE_INDEX_URL.to_owned(),
+                    "sparse+https://mirror.example.com/index/".to_owned(),
+                ),
+                (
+                    "https://example.com/git/index".to_owned(),
+                    "https://mirror.example.com/git/index".to_owned(),
+                ),
+            ]),
+            ca_info: Some("/etc/ssl/corp.pem".to_owned()),
+            ..RegistryMirrors::default()
+        };
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_path = temp_dir.path().join(".cargo").join("config.toml");
+        mirrors.install(&config_path).unwrap();
+
+        let config = CargoConfig::try_from_path(&config_path).unwrap();
+        assert_eq!(
+            config
+                .resolve_replacement_url(utils::CRATES_IO_INDEX_URL)
+                .unwrap(),
+            "sparse+https://mirror.example.com/index/"
+        );
+        assert_eq!(
+            config
+                .resolve_replacement_url("https://example.com/git/index")
+                .unwrap(),
+            "https://mirror.example.com/git/index"
+        );
+
+        let content: toml::Table =
+            toml::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        assert_eq!(
+            content["http"]["cainfo"],
+            toml::Value::from("/etc/ssl/corp.pem")
+        );
+    }
+
+    #[test]
+    fn install_conflicting_source_replacement() {
+        let mirrors = RegistryMirrors {
+            indexes: BTreeMap::from([(
+                utils::CRATES_IO_INDEX_URL.to_owned(),
+                "sparse+https://mirror.example.com/index/".to_owned(),
+            )]),
+            ..RegistryMirrors::default()
+        };
+
+        assert!(mirrors
+            .apply(indoc::indoc! {r#"
+                [source.crates-io]
+                replace-with = "other"
+            "#})
+            .is_err());
+    }
+}
@@ -5,12 +5,13 @@ use std::fs;
 use std::path::Path;
 
 use anyhow::{bail, Context, Result};
-use camino::{Utf8Path, Utf8PathBuf};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
 use cargo_toml::Manifest;
 
 use crate::config::CrateId;
 use crate::metadata::discover_workspaces;
-use crate::splicing::{SplicedManifest, SplicingManifest};
+use crate::splicing::cargo_config::{find_workspace_cargo_config, registry_settings};
+use crate::splicing::{RegistryMirrors, SplicedManifest, SplicingManifest};
 use crate::utils::starlark::Label;
 use crate::utils::symlink::{remove_symlink, symlink};
 
@@ -23,6 +24,7 @@ pub(crate) enum SplicerKind<'a> {
     Workspace {
         path: &'a Utf8PathBuf,
         manifest: &'a Manifest,
+        manifests: &'a BTreeMap<Utf8PathBuf, Manifest>,
         splicing_manifest: &'a SplicingManifest,
     },
     /// Splice a manifest for a single package. This includes cases where
@@ -96,6 +98,7 @@ impl<'a> SplicerKind<'a> {
             Ok(Self::Workspace {
                 path,
                 manifest,
+                manifests,
                 splicing_manifest,
             })
         } else if manifests.len() == 1 {
@@ -120,8 +123,11 @@ impl<'a> SplicerKind<'a> {
             SplicerKind::Workspace {
                 path,
                 manifest,
+                manifests,
                 splicing_manifest,
-            } => Self::splice_workspace(workspace_dir, path, manifest, splicing_manifest),
+            } => {
+                Self::splice_workspace(workspace_dir, path, manifest, manifests, splicing_manifest)
+            }
             SplicerKind::Package {
                 path,
                 manifest,
@@ -140,6 +146,7 @@ impl<'a> SplicerKind<'a> {
         workspace_dir: &Utf8Path,
         path: &&Utf8PathBuf,
         manifest: &&Manifest,
+        manifests: &&BTreeMap<Utf8PathBuf, Manifest>,
         splicing_manifest: &&SplicingManifest,
     ) -> Result<SplicedManifest> {
         let mut manifest = (*manifest).clone();
@@ -147,6 +154,11 @@ impl<'a> SplicerKind<'a> {
             .parent()
             .expect("Every manifest should havee a parent directory");
 
+        // Ensure `[patch]`, `[replace]`, `[workspace.dependencies]` and dependency paths outside
+        // of the linked sources still resolve
+        Self::resolve_override_paths(&mut manifest, manifest_dir, false);
+        Self::resolve_dependency_paths(&mut manifest, manifest_dir, manifest_dir);
+
         // Link the sources of the root manifest into the new workspace
         symlink_roots(
             manifest_dir.as_std_path(),
@@ -154,8 +166,31 @@ impl<'a> SplicerKind<'a> {
             Some(IGNORE_LIST),
         )?;
 
+        // Members depending on paths outside of the workspace get a copy of their manifest
+        // with those paths resolved.
+        for (member_path, member_manifest) in manifests.iter() {
+            let member_dir = member_path
+                .parent()
+                .expect("Every manifest should havee a parent directory");
+            let Ok(relative_dir) = member_dir.strip_prefix(manifest_dir) else {
+                continue;
+            };
+            if relative_dir.as_str().is_empty() {
+                continue;
+            }
+            let mut member_manifest = member_manifest.clone();
+            if Self::resolve_dependency_paths(&mut member_manifest, member_dir, manifest_dir) {
+                Self::write_linked_manifest(workspace_dir, relative_dir, member_manifest)?;
+            }
+        }
+
         // Optionally install the cargo config after contents have been symlinked
-        Self::setup_cargo_config(&splicing_manifest.cargo_config, workspace_dir.as_std_path())?;
+        Self::setup_cargo_config(
+            &splicing_manifest.cargo_config,
+            Some(manifest_dir.as_std_path()),
+            &splicing_manifest.mirrors,
+            workspace_dir.as_std_path(),
+        )?;
 
         // Add any additional depeendencies to the root package
         if !splicing_manifest.direct_packages.is_empty() {
@@ -195,10 +230,17 @@ impl<'a> SplicerKind<'a> {
         )?;
 
         // Optionally install the cargo config after contents have been symlinked
-        Self::setup_cargo_config(&splicing_manifest.cargo_config, workspace_dir.as_std_path())?;
+        Self::setup_cargo_config(
+            &splicing_manifest.cargo_config,
+            Some(manifest_dir.as_std_path()),
+            &splicing_manifest.mirrors,
+            workspace_dir.as_std_path(),
+        )?;
 
         // Ensure the root package manifest has a populated `workspace` member
         let mut manifest = (*manifest).clone();
+        Self::resolve_override_paths(&mut manifest, manifest_dir, false);
+        Self::resolve_dependency_paths(&mut manifest, manifest_dir, manifest_dir);
         if manifest.workspace.is_none() {
             manifest.workspace =
                 default_cargo_workspace_manifest(&splicing_manifest.resolver_version).workspace
@@ -232,13 +274,35 @@ impl<'a> SplicerKind<'a> {
         let mut manifest = default_cargo_workspace_manifest(&splicing_manifest.resolver_version);
 
         // Optionally install a cargo config file into the workspace root.
-        Self::setup_cargo_config(&splicing_manifest.cargo_config, workspace_dir.as_std_path())?;
+        Self::setup_cargo_config(
+            &splicing_manifest.cargo_config,
+            None,
+            &splicing_manifest.mirrors,
+            workspace_dir.as_std_path(),
+        )?;
 
         let installations =
             Self::inject_workspace_members(&mut manifest, manifests, workspace_dir.as_std_path())?;
 
-        // Collect all patches from the manifests provided
-        for (_, sub_manifest) in manifests.iter() {
+        // Collect all patches and replacements from the manifests provided
+        for (path, sub_manifest) in manifests.iter() {
+            let manifest_dir = path
+                .parent()
+                .expect("Every manifest should havee a parent directory");
+            let mut sub_manifest = sub_manifest.clone();
+
+            // Each package is linked into its own directory so any dependency outside of it
+            // needs to be resolved in a copy of its manifest.
+            if Self::resolve_dependency_paths(&mut sub_manifest, manifest_dir, manifest_dir) {
+                Self::write_linked_manifest(
+                    workspace_dir,
+                    Utf8Path::new(&installations[path]),
+                    sub_manifest.clone(),
+                )?;
+            }
+
+            Self::resolve_override_paths(&mut sub_manifest, manifest_dir, true);
+
             Self::inject_patches(&mut manifest, &sub_manifest.patch).with_context(|| {
                 format!(
                     "Duplicate `[patch]` entries detected in {:#?}",
@@ -248,6 +312,16 @@ impl<'a> SplicerKind<'a> {
                         .collect::<Vec<String>>()
                 )
             })?;
+            #[allow(deprecated)]
+            Self::inject_replacements(&mut manifest, &sub_manifest.replace).with_context(|| {
+                format!(
+                    "Duplicate `[replace]` entries detected in {:#?}",
+                    manifests
+                        .keys()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<String>>()
+                )
+            })?;
         }
 
         // Write the generated metadata to the manifest
@@ -267,11 +341,24 @@ impl<'a> SplicerKind<'a> {
     }
 
     /// A helper for installing Cargo config files into the spliced workspace while also
-    /// ensuring no other linked config file is available
+    /// ensuring no other linked config file is available. Without an explicit config, the
+    /// registry settings of the config of the workspace in `source_dir` are installed, so
+    /// crates are resolved from the same sources as when running Cargo in the workspace.
     fn setup_cargo_config(
         cargo_config_path: &Option<Utf8PathBuf>,
+        source_dir: Option<&Path>,
+        mirrors: &RegistryMirrors,
         workspace_dir: &Path,
     ) -> Result<()> {
+        // Read the workspace's own config before the linked copy is removed below
+        let registry_settings = match (cargo_config_path, source_dir) {
+            (None, Some(source_dir)) => find_workspace_cargo_config(source_dir)
+                .map(|path| registry_settings(&path))
+                .transpose()?
+                .filter(|settings| !settings.is_empty()),
+            _ => None,
+        };
+
         // If the `.cargo` dir is a symlink, we'll need to relink it and ensure
         // a Cargo config file is omitted
         let dot_cargo_dir = workspace_dir.join(".cargo");
@@ -350,8 +437,20 @@ impl<'a> SplicerKind<'a> {
             }
 
             fs::copy(cargo_config_path, dot_cargo_dir.join("config.toml"))?;
+        } else if let Some(registry_settings) = registry_settings {
+            if !dot_cargo_dir.exists() {
+                fs::create_dir_all(&dot_cargo_dir)?;
+            }
+
+            fs::write(
+                dot_cargo_dir.join("config.toml"),
+                toml::to_string(&registry_settings)?,
+            )?;
         }
 
+        // Route registry traffic through any mirrors
+        mirrors.install(&dot_cargo_dir.join("config.toml"))?;
+
         Ok(())
     }
 
@@ -463,6 +562,137 @@ impl<'a> SplicerKind<'a> {
 
         Ok(())
     }
+
+    #[allow(deprecated)] // `[replace]` is superseded by `[patch]` but still supported
+    fn inject_replacements(
+        manifest: &mut Manifest,
+        replacements: &cargo_toml::DepsSet,
+    ) -> Result<()> {
+        for (spec, info) in replacements.iter() {
+            if let Some(existing_info) = manifest.replace.get(spec) {
+                // Only error if the replacements are not identical
+                if existing_info != info {
+                    bail!("Duplicate replacements were found for `[replace] {}`", spec);
+                }
+            }
+            manifest.replace.insert(spec.clone(), info.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Resolve the relative paths of `[patch]`, `[replace]` and `[workspace.dependencies]` entries
+    /// against the directory of the manifest defining them. Unless `all` is set, only paths outside
+    /// of the manifest's directory are resolved as the ones within are linked into the spliced
+    /// workspace.
+    #[allow(deprecated)]
+    fn resolve_override_paths(manifest: &mut Manifest, manifest_dir: &Utf8Path, all: bool) {
+        let overrides = manifest
+            .patch
+            .values_mut()
+            .flat_map(|patches| patches.values_mut())
+            .chain(manifest.replace.values_mut())
+            .chain(
+                manifest
+                    .workspace
+                    .iter_mut()
+                    .flat_map(|workspace| workspace.dependencies.values_mut()),
+            );
+        for dependency in overrides {
+            let cargo_toml::Dependency::Detailed(details) = dependency else {
+                continue;
+            };
+            let Some(path) = details.path.as_mut() else {
+                continue;
+            };
+            let relative_path = Utf8Path::new(path.as_str());
+            if relative_path.is_absolute() || !(all || relative_path.starts_with("..")) {
+                continue;
+            }
+            *path = manifest_dir.join(relative_path).to_string();
+        }
+    }
+
+    /// Resolve the relative paths of dependencies, including target specific ones, which point
+    /// outside of `linked_dir`, the directory linked into the spliced workspace, against the
+    /// directory of the manifest declaring them. Returns whether or not any path was resolved.
+    fn resolve_dependency_paths(
+        manifest: &mut Manifest,
+        manifest_dir: &Utf8Path,
+        linked_dir: &Utf8Path,
+    ) -> bool {
+        let dependencies = manifest
+            .dependencies
+            .values_mut()
+            .chain(manifest.dev_dependencies.values_mut())
+            .chain(manifest.build_dependencies.values_mut())
+            .chain(manifest.target.values_mut().flat_map(|target| {
+                target
+                    .dependencies
+                    .values_mut()
+                    .chain(target.dev_dependencies.values_mut())
+                    .chain(target.build_dependencies.values_mut())
+            }));
+        let mut resolved = false;
+        for dependency in dependencies {
+            let cargo_toml::Dependency::Detailed(details) = dependency else {
+                continue;
+            };
+            let Some(path) = details.path.as_mut() else {
+                continue;
+            };
+            let relative_path = Utf8Path::new(path.as_str());
+            if relative_path.is_absolute()
+                || normalize_path(&manifest_dir.join(relative_path)).starts_with(linked_dir)
+            {
+                continue;
+            }
+            *path = manifest_dir.join(relative_path).to_string();
+            resolved = true;
+        }
+        resolved
+    }
+
+    /// Replace the linked `Cargo.toml` file in `relative_dir` of the spliced workspace with
+    /// `manifest`. Any linked directory leading to it is replaced by a directory of links to
+    /// its contents so the original sources are never modified.
+    fn write_linked_manifest(
+        workspace_dir: &Utf8Path,
+        relative_dir: &Utf8Path,
+        manifest: Manifest,
+    ) -> Result<()> {
+        let mut dir = workspace_dir.to_path_buf();
+        for component in relative_dir.components() {
+            dir.push(component);
+            let is_symlink = dir
+                .symlink_metadata()
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(false);
+            if is_symlink {
+                let real_path = dir.canonicalize()?;
+                remove_symlink(dir.as_std_path())
+                    .with_context(|| format!("Failed to remove existing symlink {}", dir))?;
+                symlink_roots(&real_path, dir.as_std_path(), Some(IGNORE_LIST))?;
+            }
+        }
+
+        write_root_manifest(dir.join("Cargo.toml").as_std_path(), manifest)
+    }
+}
+
+/// Lexically normalize a path by resolving `.` and `..` components the way Cargo does.
+fn normalize_path(path: &Utf8Path) -> Utf8PathBuf {
+    let mut normalized = Utf8PathBuf::new();
+    for component in path.components() {
+        match component {
+            Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
 }
 
 pub(crate) struct Splicer {
@@ -1212,6 +1442,94 @@ mod test {
         cargo_lock::Lockfile::load(workspace_root.as_ref().join("Cargo.lock")).unwrap();
     }
 
+    #[test]
+    fn splice_workspace_with_inherited_dependencies() {
+        let (mut splicing_manifest, cache_dir) = mock_splicing_manifest_with_workspace_in_root();
+
+        // Inherit the version, a dependency outside of the workspace and its features
+        fs::write(
+            cache_dir.as_ref().join("Cargo.toml"),
+            indoc::indoc! {r#"
+                [workspace]
+                members = [
+                    "sub_pkg_a",
+                    "sub_pkg_b",
+                ]
+
+                [workspace.package]
+                version = "0.0.1"
+
+                [workspace.dependencies]
+                shared = { path = "../shared", features = ["extra"] }
+            "#},
+        )
+        .unwrap();
+        let sub_pkg_a = cache_dir.as_ref().join("sub_pkg_a/Cargo.toml");
+        fs::write(
+            &sub_pkg_a,
+            indoc::indoc! {r#"
+                [package]
+                name = "sub_pkg_a"
+                version.workspace = true
+
+                [lib]
+                path = "lib.rs"
+
+                [dependencies]
+                shared = { workspace = true, features = ["more"] }
+            "#},
+        )
+        .unwrap();
+        let shared_dir = cache_dir.as_ref().parent().unwrap().join("shared");
+        fs::create_dir_all(&shared_dir).unwrap();
+        fs::write(shared_dir.join("lib.rs"), "").unwrap();
+        fs::write(
+            shared_dir.join("Cargo.toml"),
+            indoc::indoc! {r#"
+                [package]
+                name = "shared"
+                version = "0.3.0"
+
+                [lib]
+                path = "lib.rs"
+
+                [features]
+                extra = []
+                more = []
+            "#},
+        )
+        .unwrap();
+        splicing_manifest.manifests.insert(
+            Utf8PathBuf::try_from(sub_pkg_a).unwrap(),
+            Label::from_str("//sub_pkg_a:Cargo.toml").unwrap(),
+        );
+
+        // Splice the workspace into a directory with a different parent
+        let workspace_root = tempfile::tempdir().unwrap();
+        let workspace_manifest = Splicer::new(
+            tempdir_utf8pathbuf(&workspace_root).join("spliced"),
+            splicing_manifest,
+        )
+        .unwrap()
+        .splice_workspace()
+        .unwrap();
+
+        let metadata = generate_metadata(workspace_manifest.as_path_buf());
+        let sub_pkg_a = metadata
+            .packages
+            .iter()
+            .find(|pkg| pkg.name == "sub_pkg_a")
+            .unwrap();
+        assert_eq!(sub_pkg_a.version, semver::Version::new(0, 0, 1));
+        let shared = sub_pkg_a
+            .dependencies
+            .iter()
+            .find(|dep| dep.name == "shared")
+            .unwrap();
+        assert_eq!(shared.features, vec!["extra", "more"]);
+        assert!(metadata.packages.iter().any(|pkg| pkg.name == "shared"));
+    }
+
     #[test]
     fn splice_package() {
         let (splicing_manifest, _cache_dir) = mock_splicing_manifest_with_package();
@@ -1574,6 +1892,228 @@ mod test {
         assert!(err_str.starts_with("Duplicate `[patch]` entries detected in"));
     }
 
+    #[test]
+    fn splice_workspace_with_external_path_dependency() {
+        let (splicing_manifest, cache_dir) = mock_splicing_manifest_with_workspace();
+
+        // Depend on a package outside of the workspace from one of its members
+        mock_cargo_toml(
+            cache_dir.as_ref().join("outside").join("Cargo.toml"),
+            "outside",
+        );
+        let member_manifest_path = cache_dir
+            .as_ref()
+            .join("root_pkg")
+            .join("sub_pkg_a")
+            .join("Cargo.toml");
+        mock_cargo_toml_with_dependencies(
+            &member_manifest_path,
+            "sub_pkg_a",
+            &[r#"outside = { path = "../../outside" }"#],
+        );
+        let original_manifest = fs::read_to_string(&member_manifest_path).unwrap();
+
+        // Splice the workspace
+        let workspace_root = tempfile::tempdir().unwrap();
+        let workspace_manifest =
+            Splicer::new(tempdir_utf8pathbuf(&workspace_root), splicing_manifest)
+                .unwrap()
+                .splice_workspace()
+                .unwrap();
+
+        // Ensure the package outside of the workspace was resolved
+        let metadata = generate_metadata(workspace_manifest.as_path_buf());
+        assert!(metadata.packages.iter().any(|pkg| pkg.name == "outside"));
+
+        // The spliced member manifest is a copy, leaving the original untouched
+        let spliced_member_dir = workspace_root.as_ref().join("sub_pkg_a");
+        for path in [
+            spliced_member_dir.clone(),
+            spliced_member_dir.join("Cargo.toml"),
+        ] {
+            assert!(!path.symlink_metadata().unwrap().file_type().is_symlink());
+        }
+        assert_eq!(
+            fs::read_to_string(&member_manifest_path).unwrap(),
+            original_manifest
+        );
+    }
+
+    #[test]
+    fn splice_multi_package_with_external_path_dependency() {
+        let (mut splicing_manifest, cache_dir) = mock_splicing_manifest_with_multi_package();
+
+        // Depend on an untracked package next to the spliced packages
+        mock_cargo_toml(cache_dir.as_ref().join("pkg_d").join("Cargo.toml"), "pkg_d");
+        let manifest_path =
+            Utf8PathBuf::try_from(cache_dir.as_ref().join("pkg_e").join("Cargo.toml")).unwrap();
+        mock_cargo_toml_with_dependencies(
+            &manifest_path,
+            "pkg_e",
+            &[r#"pkg_d = { path = "../pkg_d" }"#],
+        );
+        splicing_manifest
+            .manifests
+            .insert(manifest_path, Label::from_str("//:Cargo.toml").unwrap());
+
+        // Splice the workspace
+        let workspace_root = tempfile::tempdir().unwrap();
+        let workspace_manifest =
+            Splicer::new(tempdir_utf8pathbuf(&workspace_root), splicing_manifest)
+                .unwrap()
+                .splice_workspace()
+                .unwrap();
+
+        // Ensure the package outside of the spliced packages was resolved
+        let metadata = generate_metadata(workspace_manifest.as_path_buf());
+        assert!(metadata.packages.iter().any(|pkg| pkg.name == "pkg_d"));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn resolve_override_paths() {
+        let mut manifest = cargo_toml::Manifest::from_str(indoc::indoc! {r#"
+            [package]
+            name = "root_pkg"
+            version = "0.0.1"
+
+            [lib]
+            path = "lib.rs"
+
+            [patch.crates-io]
+            internal = { path = "vendor/internal" }
+            external = { path = "../external" }
+            absolute = { path = "/opt/absolute" }
+            remote = { git = "https://github.com/mock/remote.git", rev = "abc123" }
+
+            [replace]
+            "replaced:1.0.0" = { path = "../replaced" }
+
+            [workspace.dependencies]
+            inherited = { path = "../inherited" }
+        "#})
+        .unwrap();
+        let manifest_dir = Utf8Path::new("/workspace/root_pkg");
+        let path = |manifest: &Manifest, name: &str| {
+            let dependency = manifest
+                .patch
+                .get("crates-io")
+                .and_then(|patches| patches.get(name))
+                .or_else(|| manifest.replace.get(name))
+                .or_else(|| manifest.workspace.as_ref()?.dependencies.get(name))
+                .unwrap();
+            dependency.detail().unwrap().path.clone()
+        };
+
+        SplicerKind::resolve_override_paths(&mut manifest, manifest_dir, false);
+        assert_eq!(path(&manifest, "internal").unwrap(), "vendor/internal");
+        assert_eq!(
+            path(&manifest, "external").unwrap(),
+            "/workspace/root_pkg/../external"
+        );
+        assert_eq!(path(&manifest, "absolute").unwrap(), "/opt/absolute");
+        assert_eq!(path(&manifest, "remote"), None);
+        assert_eq!(
+            path(&manifest, "replaced:1.0.0").unwrap(),
+            "/workspace/root_pkg/../replaced"
+        );
+        assert_eq!(
+            path(&manifest, "inherited").unwrap(),
+            "/workspace/root_pkg/../inherited"
+        );
+
+        SplicerKind::resolve_override_paths(&mut manifest, manifest_dir, true);
+        assert_eq!(
+            path(&manifest, "internal").unwrap(),
+            "/workspace/root_pkg/vendor/internal"
+        );
+        assert_eq!(
+            path(&manifest, "external").unwrap(),
+            "/workspace/root_pkg/../external"
+        );
+    }
+
+    #[test]
+    fn resolve_dependency_paths() {
+        let mut manifest = cargo_toml::Manifest::from_str(indoc::indoc! {r#"
+            [package]
+            name = "member"
+            version = "0.0.1"
+
+            [lib]
+            path = "lib.rs"
+
+            [dependencies]
+            sibling = { path = "../sibling" }
+            outside = { path = "../../outside" }
+            remote = "1.0"
+
+            [build-dependencies]
+            absolute = { path = "/opt/absolute" }
+
+            [target.'cfg(unix)'.dev-dependencies]
+            platform = { path = "../../platform" }
+        "#})
+        .unwrap();
+        let manifest_dir = Utf8Path::new("/workspace/root_pkg/member");
+        let linked_dir = Utf8Path::new("/workspace/root_pkg");
+        let path = |manifest: &Manifest, name: &str| {
+            let dependency = manifest
+                .dependencies
+                .get(name)
+                .or_else(|| manifest.build_dependencies.get(name))
+                .or_else(|| manifest.target["cfg(unix)"].dev_dependencies.get(name))
+                .unwrap();
+            dependency.detail().and_then(|detail| detail.path.clone())
+        };
+
+        assert!(SplicerKind::resolve_dependency_paths(
+            &mut manifest,
+            manifest_dir,
+            linked_dir
+        ));
+        assert_eq!(path(&manifest, "sibling").unwrap(), "../sibling");
+        assert_eq!(
+            path(&manifest, "outside").unwrap(),
+            "/workspace/root_pkg/member/../../outside"
+        );
+        assert_eq!(path(&manifest, "remote"), None);
+        assert_eq!(path(&manifest, "absolute").unwrap(), "/opt/absolute");
+        assert_eq!(
+            path(&manifest, "platform").unwrap(),
+            "/workspace/root_pkg/member/../../platform"
+        );
+
+        // Nothing is left to resolve
+        assert!(!SplicerKind::resolve_dependency_paths(
+            &mut manifest,
+            manifest_dir,
+            linked_dir
+        ));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn inject_replacements() {
+        let replacement = |path: &str| {
+            cargo_toml::Dependency::Detailed(Box::new(cargo_toml::DependencyDetail {
+                path: Some(path.to_owned()),
+                ..Default::default()
+            }))
+        };
+
+        let mut manifest = default_cargo_workspace_manifest(&cargo_toml::Resolver::V2);
+        let replacements =
+            cargo_toml::DepsSet::from([("foo:1.0.0".to_owned(), replacement("/foo"))]);
+        SplicerKind::inject_replacements(&mut manifest, &replacements).unwrap();
+        SplicerKind::inject_replacements(&mut manifest, &replacements).unwrap();
+        assert_eq!(manifest.replace, replacements);
+
+        let conflicting =
+            cargo_toml::DepsSet::from([("foo:1.0.0".to_owned(), replacement("/other/foo"))]);
+        assert!(SplicerKind::inject_replacements(&mut manifest, &conflicting).is_err());
+    }
+
     #[test]
     fn cargo_config_setup() {
         let (mut splicing_manifest, _cache_dir) = mock_splicing_manifest_with_workspace_in_root();
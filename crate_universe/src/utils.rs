@@ -1,5 +1,7 @@
 //! Common utilities
 
+pub(crate) mod file_lock;
+pub(crate) mod parallel;
 pub(crate) mod starlark;
 pub(crate) mod symlink;
 pub(crate) mod target_triple;
@@ -0,0 +1,101 @@
+//! Advisory locks on files, which coordinate processes sharing a directory.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// An exclusive lock on a file, which is released when it's dropped.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    // Closing the file releases the lock.
+    _file: File,
+}
+
+impl FileLock {
+    /// Lock the file at `path`, which is created if it doesn't exist, blocking until no other
+    /// process holds a lock on it.
+    #[cfg(target_family = "unix")]
+    pub(crate) fn exclusive(path: &Path) -> io::Result<Self> {
+        use std::os::raw::c_int;
+        use std::os::unix::io::AsRawFd;
+
+        extern "C" {
+            fn flock(fd: c_int, operation: c_int) -> c_int;
+        }
+        const LOCK_EX: c_int = 2;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        loop {
+            // Safe as the file descriptor stays open for the duration of the call.
+            if unsafe { flock(file.as_raw_fd(), LOCK_EX) } == 0 {
+                return Ok(Self { _file: file });
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Lock the file at `path`, which is created if it doesn't exist, blocking until no other
+    /// process holds a lock on it. Windows denies opening a file without sharing while another
+    /// handle to it is open, which makes that handle a lock.
+    #[cfg(target_family = "windows")]
+    pub(crate) fn exclusive(path: &Path) -> io::Result<Self> {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+
+        loop {
+            match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .share_mode(0)
+                .open(path)
+            {
+                Ok(file) => return Ok(Self { _file: file }),
+                Err(error) if error.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn exclusive_lock_blocks_until_released() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join(".lock");
+
+        let lock = FileLock::exclusive(&path).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let waiter = thread::spawn({
+            let path = path.clone();
+            move || {
+                let _lock = FileLock::exclusive(&path).unwrap();
+                sender.send(()).unwrap();
+            }
+        });
+
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(lock);
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        waiter.join().unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+//! A minimal thread pool for running independent jobs in parallel.
+
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use tracing::{debug, info};
+
+/// The number of jobs to run at once, matching the available parallelism of the host.
+pub(crate) fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+/// Apply `func` to each of `items` using up to `jobs` threads, each taking the next pending item
+/// as soon as it is done with one so slow items don't hold up the rest. Progress is reported
+/// under `description` and the results are returned in the order of `items`. The first error
+/// stops any pending items from being started.
+pub(crate) fn map<T, R, F>(description: &str, items: Vec<T>, jobs: usize, func: F) -> Result<Vec<R>>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> Result<R> + Sync,
+{
+    let total = items.len();
+    let jobs = jobs.clamp(1, total.max(1));
+    let pending = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new((0..total).map(|_| None).collect::<Vec<Option<R>>>());
+    let error = Mutex::new(None);
+    let failed = AtomicBool::new(false);
+    let completed = AtomicUsize::new(0);
+
+    debug!(
+        "{}: running {} jobs on {} threads",
+        description, total, jobs
+    );
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                while !failed.load(Ordering::SeqCst) {
+                    let Some((index, item)) = pending.lock().unwrap().next() else {
+                        break;
+                    };
+                    match func(item) {
+                        Ok(result) => {
+                            results.lock().unwrap()[index] = Some(result);
+                            let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                            // Report progress in steps of 10% to avoid flooding the logs.
+                            if done * 10 / total != (done - 1) * 10 / total {
+                                info!("{}: {}/{}", description, done, total);
+                            }
+                        }
+                        Err(err) => {
+                            failed.store(true, Ordering::SeqCst);
+                            error.lock().unwrap().get_or_insert(err);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    if let Some(err) = error.into_inner().unwrap() {
+        return Err(err);
+    }
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.ok_or_else(|| anyhow!("{}: a job did not complete", description)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use anyhow::bail;
+
+    #[test]
+    fn map_preserves_order() {
+        let items: Vec<usize> = (0..100).collect();
+        let results = map("squares", items, 8, |item| Ok(item * item)).unwrap();
+        assert_eq!(
+            results,
+            (0..100).map(|item| item * item).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn map_empty() {
+        let results = map("empty", Vec::<usize>::new(), 8, Ok).unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn map_reports_errors() {
+        let items: Vec<usize> = (0..100).collect();
+        let result = map("failing", items, 4, |item| {
+            if item == 42 {
+                bail!("Job {} failed", item)
+            }
+            Ok(item)
+        });
+        assert_eq!(result.unwrap_err().to_string(), "Job 42 failed");
+    }
+}
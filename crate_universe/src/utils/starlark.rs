@@ -10,7 +10,7 @@ mod select_set;
 mod serialize;
 mod target_compatible_with;
 
-use std::collections::BTreeSet as Set;
+use std::collections::{BTreeMap, BTreeSet as Set};
 
 use serde::{Serialize, Serializer};
 use serde_starlark::{Error as StarlarkError, FunctionCall};
@@ -33,6 +33,8 @@ pub(crate) enum Starlark {
     License(License),
     ExportsFiles(ExportsFiles),
     Filegroup(Filegroup),
+    ConfigSetting(ConfigSetting),
+    ConfigSettingGroup(ConfigSettingGroup),
     Alias(Alias),
     CargoBuildScript(CargoBuildScript),
     #[serde(serialize_with = "serialize::rust_proc_macro")]
@@ -41,6 +43,7 @@ pub(crate) enum Starlark {
     RustLibrary(RustLibrary),
     #[serde(serialize_with = "serialize::rust_binary")]
     RustBinary(RustBinary),
+    RustDoc(RustDoc),
 
     #[serde(skip_serializing)]
     Verbatim(String),
@@ -81,6 +84,20 @@ pub(crate) struct Filegroup {
     pub(crate) srcs: Glob,
 }
 
+#[derive(Serialize)]
+#[serde(rename = "config_setting")]
+pub(crate) struct ConfigSetting {
+    pub(crate) name: String,
+    pub(crate) values: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "selects.config_setting_group")]
+pub(crate) struct ConfigSettingGroup {
+    pub(crate) name: String,
+    pub(crate) match_all: Vec<String>,
+}
+
 pub(crate) struct Alias {
     pub(crate) rule: String,
     pub(crate) name: String,
@@ -180,6 +197,15 @@ pub(crate) struct RustBinary {
     pub(crate) common: CommonAttrs,
 }
 
+#[derive(Serialize)]
+#[serde(rename = "rust_doc")]
+pub(crate) struct RustDoc {
+    pub(crate) name: String,
+    #[serde(rename = "crate")]
+    pub(crate) krate: Label,
+    pub(crate) tags: Set<String>,
+}
+
 #[derive(Serialize)]
 pub(crate) struct CommonAttrs {
     #[serde(skip_serializing_if = "Data::is_empty")]
@@ -187,6 +213,8 @@ pub(crate) struct CommonAttrs {
     #[serde(skip_serializing_if = "SelectSet::is_empty")]
     pub(crate) crate_features: SelectSet<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) crate_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) crate_root: Option<String>,
     #[serde(skip_serializing_if = "Data::is_empty")]
     pub(crate) data: Data,
@@ -1,11 +1,24 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use anyhow::{anyhow, Context};
+use cfg_expr::targets::{
+    Abi, Arch, Endian, Env, Families, Family, HasAtomic, HasAtomics, Os, Panic, TargetInfo, Triple,
+    Vendor,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(transparent)]
 pub(crate) struct TargetTriple(String);
 
+/// The `cfg` options `rustc` sets for each platform triple, as printed by `rustc --print=cfg`
+/// (e.g. `target_feature="simd128"`).
+pub(crate) type TargetCfgs = BTreeMap<TargetTriple, BTreeSet<String>>;
+
 impl TargetTriple {
     #[cfg(test)]
     pub(crate) fn from_bazel(bazel: String) -> Self {
@@ -17,6 +30,12 @@ impl TargetTriple {
     }
 
     pub(crate) fn to_cargo(&self) -> String {
+        // `rustc` renamed `wasm32-wasi` to `wasm32-wasip1` and no longer knows the former,
+        // which Bazel platforms may still use.
+        if self.0 == "wasm32-wasi" {
+            return "wasm32-wasip1".to_owned();
+        }
+
         // While Bazel is NixOS aware (via `@platforms//os:nixos`), `rustc`
         // is not, so any target triples for `nixos` get remapped to `linux`
         // for the purposes of determining `cargo metadata`, resolving `cfg`
@@ -35,3 +54,124 @@ impl Display for TargetTriple {
         }
     }
 }
+
+/// A [custom target specification](https://doc.rust-lang.org/rustc/targets/custom.html) for a
+/// target triple which is not built into `rustc`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct TargetSpec(Map<String, Value>);
+
+impl TargetSpec {
+    fn string(&self, key: &str) -> anyhow::Result<Option<&str>> {
+        match self.0.get(key) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value.as_str())),
+            Some(value) => Err(anyhow!("Expected `{key}` to be a string, found `{value}`")),
+        }
+    }
+
+    fn non_empty_string(&self, key: &str) -> anyhow::Result<Option<&str>> {
+        Ok(self.string(key)?.filter(|value| !value.is_empty()))
+    }
+
+    fn integer(&self, key: &str) -> anyhow::Result<Option<u64>> {
+        match self.0.get(key) {
+            None => Ok(None),
+            // Older specs write numbers as strings, e.g. `"target-pointer-width": "32"`.
+            Some(Value::String(value)) => value
+                .parse()
+                .map(Some)
+                .with_context(|| format!("Expected `{key}` to be an integer, found `{value}`")),
+            Some(value) => value
+                .as_u64()
+                .map(Some)
+                .ok_or_else(|| anyhow!("Expected `{key}` to be an integer, found `{value}`")),
+        }
+    }
+
+    /// Determine the values of the `cfg` options `rustc` sets for the target.
+    pub(crate) fn target_info(&self, triple: &TargetTriple) -> anyhow::Result<TargetInfo> {
+        let arch = self
+            .string("arch")?
+            .ok_or_else(|| anyhow!("The target spec of {triple} has no `arch`"))?;
+        let pointer_width = self
+            .integer("target-pointer-width")?
+            .ok_or_else(|| anyhow!("The target spec of {triple} has no `target-pointer-width`"))?;
+        let pointer_width = u8::try_from(pointer_width)
+            .with_context(|| format!("Invalid `target-pointer-width` for {triple}"))?;
+
+        let families = match self.0.get("target-family") {
+            None => Vec::new(),
+            Some(Value::String(family)) => vec![family.clone()],
+            Some(Value::Array(families)) => families
+                .iter()
+                .map(|family| {
+                    family.as_str().map(str::to_owned).ok_or_else(|| {
+                        anyhow!("Expected `target-family` to contain strings, found `{family}`")
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+            Some(value) => {
+                return Err(anyhow!(
+                    "Expected `target-family` to be a list, found `{value}`"
+                ))
+            }
+        };
+
+        let endian = match self.string("target-endian")?.unwrap_or("little") {
+            "little" => Endian::little,
+            "big" => Endian::big,
+            endian => return Err(anyhow!("Invalid `target-endian` for {triple}: `{endian}`")),
+        };
+
+        // Atomics are supported up to `max-atomic-width`, which defaults to the pointer width.
+        let min_atomic_width = self.integer("min-atomic-width")?.unwrap_or(8);
+        let max_atomic_width = self
+            .integer("max-atomic-width")?
+            .unwrap_or(u64::from(pointer_width));
+        let mut has_atomics: Vec<HasAtomic> = [8, 16, 32, 64, 128]
+            .into_iter()
+            .filter(|width| (min_atomic_width..=max_atomic_width).contains(width))
+            .map(|width| HasAtomic::IntegerSize(width as u16))
+            .collect();
+        if (min_atomic_width..=max_atomic_width).contains(&u64::from(pointer_width)) {
+            has_atomics.push(HasAtomic::Pointer);
+        }
+
+        Ok(TargetInfo {
+            triple: Triple::new(triple.to_cargo()),
+            os: self
+                .non_empty_string("os")?
+                .filter(|os| *os != "none")
+                .map(|os| Os::new(os.to_owned())),
+            abi: self
+                .non_empty_string("abi")?
+                .map(|abi| Abi::new(abi.to_owned())),
+            arch: Arch::new(arch.to_owned()),
+            env: self
+                .non_empty_string("env")?
+                .map(|env| Env::new(env.to_owned())),
+            vendor: Some(Vendor::new(
+                self.string("vendor")?.unwrap_or("unknown").to_owned(),
+            )),
+            families: Families::new(families.into_iter().map(Family::new)),
+            pointer_width,
+            endian,
+            has_atomics: HasAtomics::new(has_atomics),
+            panic: Panic::new(
+                self.string("panic-strategy")?
+                    .unwrap_or("unwind")
+                    .to_owned(),
+            ),
+        })
+    }
+
+    /// Write the spec to `{triple}.json` within `dir`, the path of which can be passed to
+    /// `--target` in place of the triple.
+    pub(crate) fn write(&self, dir: &Path, triple: &TargetTriple) -> anyhow::Result<PathBuf> {
+        let path = dir.join(format!("{}.json", triple.to_cargo()));
+        fs::write(&path, serde_json::to_string_pretty(&self.0)?)
+            .with_context(|| format!("Failed to write target spec {}", path.display()))?;
+        Ok(path)
+    }
+}
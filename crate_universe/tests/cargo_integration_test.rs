@@ -115,6 +115,9 @@ fn run(repository_name: &str, manifests: HashMap<String, String>, lockfile: &str
         config,
         cargo,
         rustc,
+        crate_cache: None,
+        registry_cache: None,
+        network_retries: 3,
     })
     .unwrap();
 
@@ -0,0 +1,138 @@
+//! cargo_bazel integration test that splices the same workspace twice with a crate cache.
+//! This runs in its own process as it changes `CARGO_HOME` between splices.
+
+extern crate cargo_bazel;
+extern crate serde_json;
+extern crate tempfile;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use cargo_bazel::cli::{splice, SpliceOptions};
+use serde_json::json;
+
+fn should_skip_test() -> bool {
+    // The first splice downloads crates. See `cargo_integration_test.rs`.
+    use std::net::ToSocketAddrs;
+    if "github.com:443".to_socket_addrs().is_err() {
+        eprintln!("This test case requires network access.");
+        true
+    } else {
+        false
+    }
+}
+
+fn splice_with_caches(
+    scratch: &Path,
+    cargo_home: &Path,
+    crate_cache: &Path,
+    registry_cache: &Path,
+) -> anyhow::Result<()> {
+    let runfiles = runfiles::Runfiles::create().unwrap();
+    let cargo = runfiles::rlocation!(runfiles, env::var("CARGO").unwrap()).unwrap();
+    let rustc = runfiles::rlocation!(runfiles, env::var("RUSTC").unwrap()).unwrap();
+    env::set_var("CARGO_HOME", cargo_home);
+    fs::create_dir_all(cargo_home).unwrap();
+    fs::create_dir_all(scratch).unwrap();
+
+    let manifest = runfiles::rlocation!(
+        runfiles,
+        "rules_rust/crate_universe/test_data/metadata/common/Cargo.toml"
+    )
+    .unwrap();
+    let splicing_manifest = scratch.join("splicing_manifest.json");
+    fs::write(
+        &splicing_manifest,
+        serde_json::to_string(&json!({
+            "manifests": HashMap::from([(
+                manifest.to_string_lossy().to_string(),
+                "//:test_input".to_string(),
+            )]),
+            "direct_packages": {},
+            "resolver_version": "2"
+        }))
+        .unwrap(),
+    )
+    .unwrap();
+
+    let config = scratch.join("config.json");
+    fs::write(
+        &config,
+        serde_json::to_string(&json!({
+            "generate_binaries": false,
+            "generate_build_scripts": false,
+            "rendering": {
+                "repository_name": "crate_cache_test",
+                "regen_command": "//crate_universe:crate_cache_integration_test"
+            },
+            "supported_platform_triples": [
+                "x86_64-unknown-linux-gnu",
+            ]
+        }))
+        .unwrap(),
+    )
+    .unwrap();
+
+    splice(SpliceOptions {
+        splicing_manifest,
+        cargo_lockfile: Some(
+            runfiles::rlocation!(
+                runfiles,
+                "rules_rust/crate_universe/test_data/metadata/common/Cargo.lock"
+            )
+            .unwrap(),
+        ),
+        repin: None,
+        workspace_dir: None,
+        output_dir: scratch.join("out"),
+        dry_run: false,
+        cargo_config: None,
+        config,
+        cargo,
+        rustc,
+        crate_cache: Some(crate_cache.to_path_buf()),
+        registry_cache: Some(registry_cache.to_path_buf()),
+        network_retries: 0,
+    })
+}
+
+#[test]
+fn second_splice_does_not_download_crates() {
+    if should_skip_test() {
+        eprintln!("Skipping!");
+        return;
+    }
+
+    let scratch = tempfile::tempdir().unwrap();
+    let crate_cache = scratch.path().join("crate_cache");
+    let registry_cache = scratch.path().join("registry_cache");
+
+    splice_with_caches(
+        &scratch.path().join("first"),
+        &scratch.path().join("first_cargo_home"),
+        &crate_cache,
+        &registry_cache,
+    )
+    .unwrap();
+
+    // A fresh `CARGO_HOME` which can't access the network only has the caches to go by.
+    env::set_var("CARGO_NET_OFFLINE", "true");
+    let cargo_home = scratch.path().join("second_cargo_home");
+    splice_with_caches(
+        &scratch.path().join("second"),
+        &cargo_home,
+        &crate_cache,
+        &registry_cache,
+    )
+    .unwrap();
+    env::remove_var("CARGO_NET_OFFLINE");
+
+    let restored: Vec<_> = fs::read_dir(cargo_home.join("registry").join("cache"))
+        .unwrap()
+        .flat_map(|registry| fs::read_dir(registry.unwrap().path()).unwrap())
+        .map(|archive| archive.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(restored.len(), 2, "{restored:?}");
+}
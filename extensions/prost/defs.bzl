@@ -11,8 +11,15 @@ using [Prost][prost] and [Tonic][tonic]
 
 ## Rules
 
+- [rust_prost_breaking_change_test](#rust_prost_breaking_change_test)
+- [rust_prost_descriptor_set](#rust_prost_descriptor_set)
+- [rust_prost_fixture](#rust_prost_fixture)
 - [rust_prost_library](#rust_prost_library)
+- [rust_prost_lint_test](#rust_prost_lint_test)
+- [rust_prost_proto_deps_test](#rust_prost_proto_deps_test)
+- [rust_prost_reflection_test](#rust_prost_reflection_test)
 - [rust_prost_toolchain](#rust_prost_toolchain)
+- [rust_prost_tonic_scaffold](#rust_prost_tonic_scaffold)
 
 ## Setup
 
@@ -141,6 +148,18 @@ register_toolchains("//toolchains:prost_toolchain")
 ---
 """
 
+load(
+    "//private:breaking_change.bzl",
+    _rust_prost_breaking_change_test = "rust_prost_breaking_change_test",
+)
+load(
+    "//private:descriptor_set.bzl",
+    _rust_prost_descriptor_set = "rust_prost_descriptor_set",
+)
+load(
+    "//private:fixture.bzl",
+    _rust_prost_fixture = "rust_prost_fixture",
+)
 load(
     "//private:prost.bzl",
     _rust_prost_library = "rust_prost_library",
@@ -150,7 +169,30 @@ load(
     "//private:prost_transform.bzl",
     _rust_prost_transform = "rust_prost_transform",
 )
+load(
+    "//private:proto_deps.bzl",
+    _rust_prost_proto_deps_test = "rust_prost_proto_deps_test",
+)
+load(
+    "//private:proto_lint.bzl",
+    _rust_prost_lint_test = "rust_prost_lint_test",
+)
+load(
+    "//private:reflection.bzl",
+    _rust_prost_reflection_test = "rust_prost_reflection_test",
+)
+load(
+    "//private:scaffold.bzl",
+    _rust_prost_tonic_scaffold = "rust_prost_tonic_scaffold",
+)
 
+rust_prost_breaking_change_test = _rust_prost_breaking_change_test
+rust_prost_descriptor_set = _rust_prost_descriptor_set
+rust_prost_fixture = _rust_prost_fixture
 rust_prost_library = _rust_prost_library
+rust_prost_lint_test = _rust_prost_lint_test
+rust_prost_proto_deps_test = _rust_prost_proto_deps_test
+rust_prost_reflection_test = _rust_prost_reflection_test
 rust_prost_toolchain = _rust_prost_toolchain
+rust_prost_tonic_scaffold = _rust_prost_tonic_scaffold
 rust_prost_transform = _rust_prost_transform
@@ -0,0 +1,102 @@
+"""Rules for checking `proto_library` targets for wire-breaking changes."""
+
+load("@rules_proto//proto:defs.bzl", "ProtoInfo")
+
+def _rust_prost_breaking_change_test_impl(ctx):
+    # The executable of a test target must be the output of an action in the
+    # rule implementation. This file is simply a symlink to the detector.
+    is_windows = ctx.executable._detector.extension == "exe"
+    runner = ctx.actions.declare_file("{}{}".format(
+        ctx.label.name,
+        ".exe" if is_windows else "",
+    ))
+    ctx.actions.symlink(
+        output = runner,
+        target_file = ctx.executable._detector,
+        is_executable = True,
+    )
+
+    # Tests run from the root of the runfiles of the main repository, from which
+    # the short paths of all runfiles are valid.
+    descriptor_set = ctx.attr.proto[ProtoInfo].direct_descriptor_set
+    baseline = ctx.file.baseline
+    args_file = ctx.actions.declare_file("{}.args".format(ctx.label.name))
+    ctx.actions.write(
+        output = args_file,
+        content = "\n".join([
+            "--label={}".format(ctx.attr.proto.label),
+            "--descriptor_set={}".format(descriptor_set.short_path),
+            "--baseline={}".format(baseline.short_path),
+            "--fail_on_findings",
+        ]),
+    )
+
+    return [
+        DefaultInfo(
+            files = depset([runner]),
+            runfiles = ctx.runfiles(
+                files = [args_file, baseline, descriptor_set],
+            ).merge(ctx.attr._detector[DefaultInfo].default_runfiles),
+            executable = runner,
+        ),
+        RunEnvironmentInfo(
+            environment = {
+                "BREAKING_CHANGE_DETECTOR_ARGS": args_file.short_path,
+            },
+        ),
+    ]
+
+rust_prost_breaking_change_test = rule(
+    doc = """\
+A test checking the sources of a `proto_library` for wire-breaking changes against a baseline.
+
+The baseline is a descriptor set checked in alongside the sources, such as the output of building
+the `proto_library` at the last release. The test fails if a message or enum defined in both the
+baseline and the sources renumbers a field or enum value, changes a field to a type with an
+incompatible encoding, changes a field from or to `repeated` or `required`, or removes or adds a
+`required` field. The failure lists the changes of each message and enum. E.g.
+```python
+load("@rules_proto//proto:defs.bzl", "proto_library")
+load("@rules_rust_prost//:defs.bzl", "rust_prost_breaking_change_test")
+
+proto_library(
+    name = "a_proto",
+    srcs = [
+        "a.proto",
+    ],
+)
+
+rust_prost_breaking_change_test(
+    name = "a_proto_breaking_change_test",
+    baseline = "a_proto_baseline.binpb",
+    proto = ":a_proto",
+)
+```
+
+The baseline can be updated by copying the descriptor set of the `proto_library`:
+```shell
+bazel build //pkg:a_proto
+cp bazel-bin/pkg/a_proto-descriptor-set.proto.bin pkg/a_proto_baseline.binpb
+```
+""",
+    implementation = _rust_prost_breaking_change_test_impl,
+    attrs = {
+        "baseline": attr.label(
+            doc = "The descriptor set the sources of `proto` are compared against.",
+            allow_single_file = True,
+            mandatory = True,
+        ),
+        "proto": attr.label(
+            doc = "The `proto_library` whose sources are checked.",
+            providers = [ProtoInfo],
+            mandatory = True,
+        ),
+        "_detector": attr.label(
+            doc = "The breaking change detector.",
+            cfg = "target",
+            executable = True,
+            default = Label("//private:breaking_change_detector"),
+        ),
+    },
+    test = True,
+)
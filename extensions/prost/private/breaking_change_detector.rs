@@ -0,0 +1,735 @@
+//! Reports the wire-breaking changes of the messages and enums of a
+//! `proto_library` against a checked-in baseline descriptor set.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    FileDescriptorSet,
+};
+
+/// The environment variable naming a file of arguments, one per line, used when
+/// the detector runs as a test.
+const ARGS_FILE_ENV: &str = "BREAKING_CHANGE_DETECTOR_ARGS";
+
+/// The parsed command-line arguments.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Args {
+    /// The label of the checked `proto_library`.
+    label: String,
+
+    /// The descriptor set of the sources of the checked `proto_library`.
+    descriptor_set: PathBuf,
+
+    /// The descriptor set the sources are compared against.
+    baseline: PathBuf,
+
+    /// The path to write the report to.
+    output: Option<PathBuf>,
+
+    /// Whether to exit with an error if any breaking changes are found.
+    fail_on_findings: bool,
+}
+
+impl Args {
+    /// Parse the given arguments.
+    fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut label = None;
+        let mut descriptor_set = None;
+        let mut baseline = None;
+
+        for arg in args {
+            if arg == "--fail_on_findings" {
+                parsed.fail_on_findings = true;
+                continue;
+            }
+
+            let (flag, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("Unexpected argument `{}`.", arg))?;
+            match flag {
+                "--label" => label = Some(value.to_string()),
+                "--descriptor_set" => descriptor_set = Some(PathBuf::from(value)),
+                "--baseline" => baseline = Some(PathBuf::from(value)),
+                "--output" => parsed.output = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unexpected argument `{}`.", arg)),
+            }
+        }
+
+        parsed.label = label.ok_or("No `--label` value was found.")?;
+        parsed.descriptor_set = descriptor_set.ok_or("No `--descriptor_set` value was found.")?;
+        parsed.baseline = baseline.ok_or("No `--baseline` value was found.")?;
+
+        Ok(parsed)
+    }
+}
+
+/// A change of a message or enum which breaks the decoding of data written with
+/// the baseline, or of data written with the current definitions by readers
+/// using the baseline.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Change {
+    /// A field kept its name but changed its number.
+    FieldRenumbered {
+        /// The name of the field.
+        field: String,
+
+        /// The number of the field in the baseline.
+        from: i32,
+
+        /// The current number of the field.
+        to: i32,
+    },
+
+    /// A field number changed to a type with an incompatible encoding.
+    FieldTypeChanged {
+        /// The current name of the field.
+        field: String,
+
+        /// The number of the field.
+        number: i32,
+
+        /// The type of the field in the baseline.
+        from: String,
+
+        /// The current type of the field.
+        to: String,
+    },
+
+    /// A field number changed from or to `repeated` or `required`.
+    FieldLabelChanged {
+        /// The current name of the field.
+        field: String,
+
+        /// The number of the field.
+        number: i32,
+
+        /// The label of the field in the baseline.
+        from: &'static str,
+
+        /// The current label of the field.
+        to: &'static str,
+    },
+
+    /// A `required` field of the baseline was removed.
+    RequiredFieldRemoved {
+        /// The name of the field in the baseline.
+        field: String,
+
+        /// The number of the field.
+        number: i32,
+    },
+
+    /// A `required` field was added, which data written with the baseline lacks.
+    RequiredFieldAdded {
+        /// The name of the field.
+        field: String,
+
+        /// The number of the field.
+        number: i32,
+    },
+
+    /// An enum value kept its name but changed its number.
+    EnumValueRenumbered {
+        /// The name of the value.
+        value: String,
+
+        /// The number of the value in the baseline.
+        from: i32,
+
+        /// The current number of the value.
+        to: i32,
+    },
+}
+
+impl Change {
+    /// Describe the change.
+    fn message(&self) -> String {
+        match self {
+            Change::FieldRenumbered { field, from, to } => {
+                format!("Field `{}` was renumbered from {} to {}.", field, from, to)
+            }
+            Change::FieldTypeChanged {
+                field,
+                number,
+                from,
+                to,
+            } => format!(
+                "Field {} (`{}`) changed type from `{}` to `{}`.",
+                number, field, from, to
+            ),
+            Change::FieldLabelChanged {
+                field,
+                number,
+                from,
+                to,
+            } => format!(
+                "Field {} (`{}`) changed from `{}` to `{}`.",
+                number, field, from, to
+            ),
+            Change::RequiredFieldRemoved { field, number } => {
+                format!("Required field {} (`{}`) was removed.", number, field)
+            }
+            Change::RequiredFieldAdded { field, number } => {
+                format!("Required field {} (`{}`) was added.", number, field)
+            }
+            Change::EnumValueRenumbered { value, from, to } => {
+                format!("Value `{}` was renumbered from {} to {}.", value, from, to)
+            }
+        }
+    }
+}
+
+/// The messages and enums of some files, by fully qualified name without a
+/// leading `.`.
+#[derive(Debug, Default)]
+struct Definitions {
+    /// The messages, including nested messages.
+    messages: BTreeMap<String, DescriptorProto>,
+
+    /// The enums, including nested enums.
+    enums: BTreeMap<String, EnumDescriptorProto>,
+}
+
+impl Definitions {
+    /// Collect the definitions of the given files.
+    fn from_files(files: &[FileDescriptorProto]) -> Self {
+        /// Collect a message along with its nested messages and enums.
+        fn visit(definitions: &mut Definitions, prefix: &str, message: &DescriptorProto) {
+            let name = qualify(prefix, message.name());
+            for enum_type in message.enum_type.iter() {
+                definitions
+                    .enums
+                    .insert(qualify(&name, enum_type.name()), enum_type.clone());
+            }
+            for nested_type in message.nested_type.iter() {
+                visit(definitions, &name, nested_type);
+            }
+            definitions.messages.insert(name, message.clone());
+        }
+
+        let mut definitions = Definitions::default();
+        for file in files {
+            for message in file.message_type.iter() {
+                visit(&mut definitions, file.package(), message);
+            }
+            for enum_type in file.enum_type.iter() {
+                definitions
+                    .enums
+                    .insert(qualify(file.package(), enum_type.name()), enum_type.clone());
+            }
+        }
+
+        definitions
+    }
+}
+
+/// Join a name to the scope it is defined in.
+fn qualify(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// The type of a field as written in `.proto` files.
+fn field_type(field: &FieldDescriptorProto) -> String {
+    match field.r#type() {
+        Type::Message | Type::Enum | Type::Group => field
+            .type_name()
+            .strip_prefix('.')
+            .unwrap_or(field.type_name())
+            .to_string(),
+        other => other
+            .as_str_name()
+            .trim_start_matches("TYPE_")
+            .to_lowercase(),
+    }
+}
+
+/// The label of a field as written in `.proto` files.
+fn field_label(field: &FieldDescriptorProto) -> &'static str {
+    match field.label() {
+        Label::Optional => "optional",
+        Label::Required => "required",
+        Label::Repeated => "repeated",
+    }
+}
+
+/// Returns true if values of one field type can be decoded as the other.
+///
+/// This follows the compatible changes listed in the protobuf language guide:
+/// the varint types and enums are interchangeable, as are the zigzag-encoded
+/// types, the fixed-width types of the same size, and strings, bytes and
+/// messages. Messages, enums and groups must also keep their type name.
+fn is_wire_compatible(from: &FieldDescriptorProto, to: &FieldDescriptorProto) -> bool {
+    /// The encoding of a type, grouping the interchangeable types.
+    fn encoding(field_type: Type) -> Type {
+        match field_type {
+            Type::Int32 | Type::Uint32 | Type::Int64 | Type::Uint64 | Type::Bool | Type::Enum => {
+                Type::Int64
+            }
+            Type::Sint32 | Type::Sint64 => Type::Sint64,
+            Type::Fixed32 | Type::Sfixed32 => Type::Fixed32,
+            Type::Fixed64 | Type::Sfixed64 => Type::Fixed64,
+            Type::String | Type::Bytes | Type::Message => Type::Bytes,
+            other => other,
+        }
+    }
+
+    let is_named = |field_type| matches!(field_type, Type::Message | Type::Enum | Type::Group);
+    if from.r#type() == to.r#type() && is_named(from.r#type()) {
+        return from.type_name() == to.type_name();
+    }
+
+    encoding(from.r#type()) == encoding(to.r#type())
+}
+
+/// Find the breaking changes of the fields of a message.
+fn compare_message(baseline: &DescriptorProto, current: &DescriptorProto) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    let current_by_name = current
+        .field
+        .iter()
+        .map(|field| (field.name(), field))
+        .collect::<BTreeMap<_, _>>();
+    let current_by_number = current
+        .field
+        .iter()
+        .map(|field| (field.number(), field))
+        .collect::<BTreeMap<_, _>>();
+    let baseline_by_number = baseline
+        .field
+        .iter()
+        .map(|field| (field.number(), field))
+        .collect::<BTreeMap<_, _>>();
+
+    for field in baseline.field.iter() {
+        if let Some(renamed) = current_by_name.get(field.name()) {
+            if renamed.number() != field.number() {
+                changes.push(Change::FieldRenumbered {
+                    field: field.name().to_string(),
+                    from: field.number(),
+                    to: renamed.number(),
+                });
+            }
+        }
+
+        let Some(current_field) = current_by_number.get(&field.number()) else {
+            if field.label() == Label::Required {
+                changes.push(Change::RequiredFieldRemoved {
+                    field: field.name().to_string(),
+                    number: field.number(),
+                });
+            }
+            continue;
+        };
+
+        if !is_wire_compatible(field, current_field) {
+            changes.push(Change::FieldTypeChanged {
+                field: current_field.name().to_string(),
+                number: field.number(),
+                from: field_type(field),
+                to: field_type(current_field),
+            });
+        }
+
+        let (from, to) = (field.label(), current_field.label());
+        if from != to && (from != Label::Optional || to != Label::Optional) {
+            changes.push(Change::FieldLabelChanged {
+                field: current_field.name().to_string(),
+                number: field.number(),
+                from: field_label(field),
+                to: field_label(current_field),
+            });
+        }
+    }
+
+    for field in current.field.iter() {
+        if field.label() == Label::Required && !baseline_by_number.contains_key(&field.number()) {
+            changes.push(Change::RequiredFieldAdded {
+                field: field.name().to_string(),
+                number: field.number(),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Find the breaking changes of the values of an enum.
+fn compare_enum(baseline: &EnumDescriptorProto, current: &EnumDescriptorProto) -> Vec<Change> {
+    let current_by_name = current
+        .value
+        .iter()
+        .map(|value| (value.name(), value.number()))
+        .collect::<BTreeMap<_, _>>();
+
+    baseline
+        .value
+        .iter()
+        .filter_map(|value| {
+            let number = *current_by_name.get(value.name())?;
+            (number != value.number()).then(|| Change::EnumValueRenumbered {
+                value: value.name().to_string(),
+                from: value.number(),
+                to: number,
+            })
+        })
+        .collect()
+}
+
+/// Find the breaking changes of the messages and enums defined in both the
+/// baseline and the current files, by fully qualified name.
+///
+/// Definitions missing from either side are skipped: removing or adding a type
+/// does not change how any data is encoded.
+fn compare(baseline: &Definitions, current: &Definitions) -> BTreeMap<String, Vec<Change>> {
+    let messages = baseline.messages.iter().filter_map(|(name, message)| {
+        let current_message = current.messages.get(name)?;
+        Some((name.clone(), compare_message(message, current_message)))
+    });
+    let enums = baseline.enums.iter().filter_map(|(name, enum_type)| {
+        let current_enum = current.enums.get(name)?;
+        Some((name.clone(), compare_enum(enum_type, current_enum)))
+    });
+
+    messages
+        .chain(enums)
+        .filter(|(_, changes)| !changes.is_empty())
+        .collect()
+}
+
+/// Render the report of the breaking changes of the given target.
+fn render_report(target: &str, changes: &BTreeMap<String, Vec<Change>>) -> String {
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut report = String::new();
+    writeln!(
+        report,
+        "{} has wire-breaking changes against its baseline:",
+        target
+    )
+    .unwrap();
+    for (name, type_changes) in changes {
+        writeln!(report, "\n{}:", name).unwrap();
+        for change in type_changes {
+            writeln!(report, "  {}", change.message()).unwrap();
+        }
+    }
+
+    report
+}
+
+/// Read the files of a descriptor set.
+fn read_descriptor_set(path: &PathBuf) -> Vec<FileDescriptorProto> {
+    let bytes = fs::read(path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read descriptor set `{}`: {:?}",
+            path.display(),
+            e
+        )
+    });
+    FileDescriptorSet::decode(bytes.as_slice())
+        .unwrap_or_else(|e| {
+            panic!(
+                "Failed to decode descriptor set `{}`: {:?}",
+                path.display(),
+                e
+            )
+        })
+        .file
+}
+
+fn main() {
+    let raw_args = match env::var_os(ARGS_FILE_ENV) {
+        Some(args_file) => fs::read_to_string(&args_file)
+            .expect("Failed to read args file")
+            .lines()
+            .map(String::from)
+            .collect(),
+        None => env::args().skip(1).collect::<Vec<_>>(),
+    };
+    let args = Args::parse_from(raw_args).expect("Failed to parse args");
+
+    let baseline = Definitions::from_files(&read_descriptor_set(&args.baseline));
+    let current = Definitions::from_files(&read_descriptor_set(&args.descriptor_set));
+    let changes = compare(&baseline, &current);
+    let report = render_report(&args.label, &changes);
+
+    if let Some(output) = args.output {
+        fs::write(output, &report).expect("Failed to write file.");
+    }
+    if !report.is_empty() {
+        eprint!("{}", report);
+        if args.fail_on_findings {
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use prost_types::EnumValueDescriptorProto;
+
+    fn field(name: &str, number: i32, label: Label, field_type: Type) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(field_type as i32),
+            ..FieldDescriptorProto::default()
+        }
+    }
+
+    fn named_field(
+        name: &str,
+        number: i32,
+        field_type: Type,
+        type_name: &str,
+    ) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            type_name: Some(type_name.to_string()),
+            ..field(name, number, Label::Optional, field_type)
+        }
+    }
+
+    fn message(name: &str, fields: Vec<FieldDescriptorProto>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field: fields,
+            ..DescriptorProto::default()
+        }
+    }
+
+    fn enum_type(name: &str, values: &[(&str, i32)]) -> EnumDescriptorProto {
+        EnumDescriptorProto {
+            name: Some(name.to_string()),
+            value: values
+                .iter()
+                .map(|(name, number)| EnumValueDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(*number),
+                    ..EnumValueDescriptorProto::default()
+                })
+                .collect(),
+            ..EnumDescriptorProto::default()
+        }
+    }
+
+    fn file(package: &str, messages: Vec<DescriptorProto>) -> FileDescriptorProto {
+        FileDescriptorProto {
+            name: Some(format!("{}.proto", package)),
+            package: Some(package.to_string()),
+            message_type: messages,
+            ..FileDescriptorProto::default()
+        }
+    }
+
+    #[test]
+    fn parse_args_test() {
+        let args = Args::parse_from(
+            [
+                "--label=//pkg:a_proto",
+                "--descriptor_set=a.bin",
+                "--baseline=a_baseline.bin",
+                "--output=report.txt",
+                "--fail_on_findings",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+
+        assert_eq!(
+            args,
+            Args {
+                label: "//pkg:a_proto".to_string(),
+                descriptor_set: PathBuf::from("a.bin"),
+                baseline: PathBuf::from("a_baseline.bin"),
+                output: Some(PathBuf::from("report.txt")),
+                fail_on_findings: true,
+            }
+        );
+
+        assert_eq!(
+            Args::parse_from(["--label=//pkg:a_proto", "--descriptor_set=a.bin"].map(String::from)),
+            Err("No `--baseline` value was found.".to_string())
+        );
+    }
+
+    #[test]
+    fn definitions_test() {
+        let mut outer = message("Outer", vec![]);
+        outer.nested_type.push(message("Inner", vec![]));
+        outer.enum_type.push(enum_type("Kind", &[]));
+        let mut a = file("a.b", vec![outer]);
+        a.enum_type.push(enum_type("State", &[]));
+        let mut c = file("", vec![message("Root", vec![])]);
+        c.name = Some("c.proto".to_string());
+
+        let definitions = Definitions::from_files(&[a, c]);
+        assert_eq!(
+            definitions.messages.keys().collect::<Vec<_>>(),
+            ["Root", "a.b.Outer", "a.b.Outer.Inner"]
+        );
+        assert_eq!(
+            definitions.enums.keys().collect::<Vec<_>>(),
+            ["a.b.Outer.Kind", "a.b.State"]
+        );
+    }
+
+    #[test]
+    fn compare_message_test() {
+        let baseline = message(
+            "Message",
+            vec![
+                field("id", 1, Label::Optional, Type::Int32),
+                field("name", 2, Label::Optional, Type::String),
+                field("count", 3, Label::Optional, Type::Int64),
+                field("key", 4, Label::Required, Type::String),
+                field("tags", 5, Label::Repeated, Type::String),
+                named_field("child", 6, Type::Message, ".pkg.Child"),
+                field("flags", 7, Label::Optional, Type::Uint32),
+                field("payload", 8, Label::Optional, Type::Bytes),
+            ],
+        );
+        let current = message(
+            "Message",
+            vec![
+                // Compatible changes.
+                field("id", 1, Label::Optional, Type::Int64),
+                named_field("payload", 8, Type::Message, ".pkg.Payload"),
+                field("extra", 10, Label::Optional, Type::Bool),
+                // Breaking changes.
+                field("name", 9, Label::Optional, Type::String),
+                field("count", 3, Label::Optional, Type::Double),
+                field("tags", 5, Label::Optional, Type::String),
+                named_field("child", 6, Type::Message, ".pkg.OtherChild"),
+                named_field("flags", 7, Type::Enum, ".pkg.Flags"),
+                field("version", 11, Label::Required, Type::Int32),
+            ],
+        );
+
+        assert_eq!(
+            compare_message(&baseline, &current),
+            vec![
+                Change::FieldRenumbered {
+                    field: "name".to_string(),
+                    from: 2,
+                    to: 9,
+                },
+                Change::FieldTypeChanged {
+                    field: "count".to_string(),
+                    number: 3,
+                    from: "int64".to_string(),
+                    to: "double".to_string(),
+                },
+                Change::RequiredFieldRemoved {
+                    field: "key".to_string(),
+                    number: 4,
+                },
+                Change::FieldLabelChanged {
+                    field: "tags".to_string(),
+                    number: 5,
+                    from: "repeated",
+                    to: "optional",
+                },
+                Change::FieldTypeChanged {
+                    field: "child".to_string(),
+                    number: 6,
+                    from: "pkg.Child".to_string(),
+                    to: "pkg.OtherChild".to_string(),
+                },
+                Change::RequiredFieldAdded {
+                    field: "version".to_string(),
+                    number: 11,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn compare_test() {
+        let mut baseline_file = file(
+            "pkg",
+            vec![
+                message("Kept", vec![field("id", 1, Label::Optional, Type::Int32)]),
+                message(
+                    "Removed",
+                    vec![field("id", 1, Label::Required, Type::Int32)],
+                ),
+            ],
+        );
+        baseline_file
+            .enum_type
+            .push(enum_type("State", &[("STATE_UNSPECIFIED", 1)]));
+        let mut current_file = file(
+            "pkg",
+            vec![message(
+                "Kept",
+                vec![field("id", 1, Label::Optional, Type::String)],
+            )],
+        );
+        current_file
+            .enum_type
+            .push(enum_type("State", &[("STATE_UNSPECIFIED", 0)]));
+
+        let changes = compare(
+            &Definitions::from_files(&[baseline_file]),
+            &Definitions::from_files(&[current_file]),
+        );
+        assert_eq!(
+            changes,
+            BTreeMap::from([
+                (
+                    "pkg.State".to_string(),
+                    vec![Change::EnumValueRenumbered {
+                        value: "STATE_UNSPECIFIED".to_string(),
+                        from: 1,
+                        to: 0,
+                    }]
+                ),
+                (
+                    "pkg.Kept".to_string(),
+                    vec![Change::FieldTypeChanged {
+                        field: "id".to_string(),
+                        number: 1,
+                        from: "int32".to_string(),
+                        to: "string".to_string(),
+                    }]
+                ),
+            ])
+        );
+
+        assert_eq!(render_report("//pkg:a_proto", &BTreeMap::new()), "");
+        assert_eq!(
+            render_report("//pkg:a_proto", &changes),
+            [
+                "//pkg:a_proto has wire-breaking changes against its baseline:",
+                "",
+                "pkg.Kept:",
+                "  Field 1 (`id`) changed type from `int32` to `string`.",
+                "",
+                "pkg.State:",
+                "  Value `STATE_UNSPECIFIED` was renumbered from 1 to 0.",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Embeds a `FileDescriptorSet` into a Rust source file as a byte string constant.
+//!
+//! The constant can be used for dynamic messaging without generating code for
+//! the described messages. It may optionally be gzip compressed.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use prost::encoding::{
+    decode_key, decode_varint, encode_key, encode_varint, skip_field, DecodeContext, WireType,
+};
+use prost::{DecodeError, Message};
+use prost_types::FileDescriptorProto;
+
+/// The parsed command-line arguments.
+struct Args {
+    /// The descriptor sets to embed. Files described by more than one set are
+    /// only embedded once.
+    descriptor_sets: Vec<PathBuf>,
+
+    /// The path to write the Rust source file to.
+    output: PathBuf,
+
+    /// The name of the constant holding the descriptor set.
+    constant_name: String,
+
+    /// Whether to gzip compress the embedded descriptor set.
+    compress: bool,
+}
+
+impl Args {
+    /// Parse the given arguments.
+    fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut descriptor_sets = Vec::new();
+        let mut output = None;
+        let mut constant_name = None;
+        let mut compress = false;
+
+        for arg in args {
+            if arg == "--compress" {
+                compress = true;
+                continue;
+            }
+
+            let (flag, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("Unexpected argument `{}`.", arg))?;
+            match flag {
+                "--descriptor_set" => descriptor_sets.push(PathBuf::from(value)),
+                "--output" => output = Some(PathBuf::from(value)),
+                "--constant_name" => constant_name = Some(value.to_string()),
+                _ => return Err(format!("Unexpected argument `{}`.", arg)),
+            }
+        }
+
+        let constant_name = constant_name.ok_or("No `--constant_name` value was found.")?;
+        if !is_identifier(&constant_name) {
+            return Err(format!(
+                "`{}` is not a valid Rust identifier.",
+                constant_name
+            ));
+        }
+
+        Ok(Args {
+            descriptor_sets,
+            output: output.ok_or("No `--output` value was found.")?,
+            constant_name,
+            compress,
+        })
+    }
+}
+
+/// Rust keywords, which cannot name a constant.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+/// Returns true if the given string is a valid, non-keyword Rust identifier.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+
+    starts_well
+        && name != "_"
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
+
+/// Merge encoded descriptor sets, keeping the first description of every file.
+///
+/// Files are copied without being re-encoded so that options and fields unknown
+/// to `prost_types` are preserved.
+fn merge_descriptor_sets(descriptor_sets: &[Vec<u8>]) -> Result<Vec<u8>, DecodeError> {
+    let mut names = BTreeSet::new();
+    let mut merged = Vec::new();
+    for descriptor_set in descriptor_sets {
+        let mut buf = descriptor_set.as_slice();
+        while !buf.is_empty() {
+            let (tag, wire_type) = decode_key(&mut buf)?;
+            if tag != 1 || wire_type != WireType::LengthDelimited {
+                skip_field(wire_type, tag, &mut buf, DecodeContext::default())?;
+                continue;
+            }
+
+            let len = decode_varint(&mut buf)? as usize;
+            if len > buf.len() {
+                return Err(DecodeError::new("buffer underflow"));
+            }
+            let (file, rest) = buf.split_at(len);
+            buf = rest;
+
+            if names.insert(FileDescriptorProto::decode(file)?.name().to_string()) {
+                encode_key(1, WireType::LengthDelimited, &mut merged);
+                encode_varint(len as u64, &mut merged);
+                merged.extend_from_slice(file);
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Render a Rust source file defining a constant holding the given bytes.
+fn render_constant(constant_name: &str, bytes: &[u8], compressed: bool) -> String {
+    let mut contents = String::new();
+    contents.push_str("// @generated\n\n");
+    if compressed {
+        contents.push_str("/// A gzip compressed, encoded `google.protobuf.FileDescriptorSet`.\n");
+    } else {
+        contents.push_str("/// An encoded `google.protobuf.FileDescriptorSet`.\n");
+    }
+    writeln!(contents, "pub const {}: &[u8] = b\"\\", constant_name).unwrap();
+
+    for chunk in bytes.chunks(32) {
+        contents.push_str("    ");
+        for byte in chunk {
+            write!(contents, "\\x{:02x}", byte).unwrap();
+        }
+        contents.push_str("\\\n");
+    }
+    contents.push_str("\";\n");
+
+    contents
+}
+
+/// The compression level passed to miniz_oxide, the highest short of its
+/// non-standard `10`. The output only depends on the level and the version of
+/// miniz_oxide, keeping it reproducible.
+const COMPRESSION_LEVEL: u8 = 9;
+
+/// Compute the CRC-32 checksum used by gzip.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Compress data into the gzip format.
+fn gzip(data: &[u8]) -> Vec<u8> {
+    // The magic number, the DEFLATE method, no flags, no modification time, no
+    // extra flags and an unknown operating system, keeping outputs reproducible.
+    let mut output = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    output.extend(miniz_oxide::deflate::compress_to_vec(
+        data,
+        COMPRESSION_LEVEL,
+    ));
+    output.extend(crc32(data).to_le_bytes());
+    output.extend((data.len() as u32).to_le_bytes());
+    output
+}
+
+fn main() {
+    let args = Args::parse_from(env::args().skip(1)).expect("Failed to parse args");
+
+    let descriptor_sets = args
+        .descriptor_sets
+        .iter()
+        .map(|path| {
+            fs::read(path).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to read descriptor set `{}`: {:?}",
+                    path.display(),
+                    e
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut bytes =
+        merge_descriptor_sets(&descriptor_sets).expect("Failed to decode descriptor sets");
+    if args.compress {
+        bytes = gzip(&bytes);
+    }
+
+    fs::write(
+        &args.output,
+        render_constant(&args.constant_name, &bytes, args.compress),
+    )
+    .expect("Failed to write file.");
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use prost_types::FileDescriptorSet;
+
+    fn file(name: &str, package: &str) -> FileDescriptorProto {
+        FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some(package.to_string()),
+            ..FileDescriptorProto::default()
+        }
+    }
+
+    #[test]
+    fn parse_args_test() {
+        let args = Args::parse_from(
+            [
+                "--descriptor_set=a.bin",
+                "--descriptor_set=b.bin",
+                "--output=out.rs",
+                "--constant_name=DESCRIPTORS",
+                "--compress",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+
+        assert_eq!(
+            args.descriptor_sets,
+            vec![PathBuf::from("a.bin"), PathBuf::from("b.bin")]
+        );
+        assert_eq!(args.output, PathBuf::from("out.rs"));
+        assert_eq!(args.constant_name, "DESCRIPTORS");
+        assert!(args.compress);
+
+        assert_eq!(
+            Args::parse_from(["--output=out.rs", "--constant_name=type"].map(String::from)).err(),
+            Some("`type` is not a valid Rust identifier.".to_string())
+        );
+    }
+
+    #[test]
+    fn merge_descriptor_sets_test() {
+        let encode =
+            |files: Vec<FileDescriptorProto>| FileDescriptorSet { file: files }.encode_to_vec();
+
+        let merged = merge_descriptor_sets(&[
+            encode(vec![file("a.proto", "a"), file("b.proto", "b")]),
+            encode(vec![file("b.proto", "other"), file("c.proto", "c")]),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            FileDescriptorSet::decode(merged.as_slice()).unwrap().file,
+            vec![
+                file("a.proto", "a"),
+                file("b.proto", "b"),
+                file("c.proto", "c")
+            ]
+        );
+    }
+
+    #[test]
+    fn render_constant_test() {
+        assert_eq!(
+            render_constant("DESCRIPTORS", &[0x0a, 0x00, 0xff], false),
+            [
+                "// @generated",
+                "",
+                "/// An encoded `google.protobuf.FileDescriptorSet`.",
+                "pub const DESCRIPTORS: &[u8] = b\"\\",
+                "    \\x0a\\x00\\xff\\",
+                "\";",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn crc32_test() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn gzip_test() {
+        // Repetitive data longer than the window of DEFLATE, interleaved with
+        // pseudo-random bytes so back references span various distances.
+        let mut state = 1u32;
+        let mut data = Vec::new();
+        while data.len() < 100 * 1024 {
+            data.extend(b"google.protobuf.FileDescriptorProto");
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            data.extend(state.to_le_bytes());
+        }
+
+        for data in [&b""[..], b"a", &data] {
+            let compressed = gzip(data);
+            assert_eq!(compressed[..10], [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]);
+            let (deflated, trailer) = compressed[10..].split_at(compressed.len() - 18);
+            assert_eq!(
+                miniz_oxide::inflate::decompress_to_vec(deflated).unwrap(),
+                data
+            );
+            assert_eq!(trailer[..4], crc32(data).to_le_bytes());
+            assert_eq!(trailer[4..], (data.len() as u32).to_le_bytes());
+        }
+        assert!(gzip(&data).len() < data.len() / 2);
+    }
+}
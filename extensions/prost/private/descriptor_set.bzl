@@ -0,0 +1,86 @@
+"""Rules for embedding descriptor sets into Rust crates."""
+
+load("@rules_proto//proto:defs.bzl", "ProtoInfo")
+
+def _rust_prost_descriptor_set_impl(ctx):
+    descriptor_sets = ctx.attr.proto[ProtoInfo].transitive_descriptor_sets
+    output = ctx.actions.declare_file("{}.rs".format(ctx.label.name))
+
+    args = ctx.actions.args()
+    args.add_all(descriptor_sets, format_each = "--descriptor_set=%s")
+    args.add(output, format = "--output=%s")
+    args.add(ctx.attr.constant_name, format = "--constant_name=%s")
+    if ctx.attr.compress:
+        args.add("--compress")
+
+    ctx.actions.run(
+        mnemonic = "ProstEmbedDescriptorSet",
+        progress_message = "ProstEmbedDescriptorSet %{label}",
+        executable = ctx.executable._descriptor_embedder,
+        inputs = descriptor_sets,
+        outputs = [output],
+        arguments = [args],
+    )
+
+    return [DefaultInfo(files = depset([output]))]
+
+rust_prost_descriptor_set = rule(
+    doc = """\
+A rule for embedding the `FileDescriptorSet` of a `proto_library` and all of its dependencies into
+a Rust source file, for use with dynamic messaging libraries such as `prost-reflect`.
+
+The rule produces `<name>.rs` defining a `pub const <constant_name>: &[u8]` holding the encoded
+descriptor set. No code is generated for the described messages. E.g.
+```python
+load("@rules_proto//proto:defs.bzl", "proto_library")
+load("@rules_rust//rust:defs.bzl", "rust_library")
+load("@rules_rust_prost//:defs.bzl", "rust_prost_descriptor_set")
+
+proto_library(
+    name = "a_proto",
+    srcs = [
+        "a.proto",
+    ],
+)
+
+rust_prost_descriptor_set(
+    name = "descriptors",
+    compress = True,
+    proto = ":a_proto",
+)
+
+rust_library(
+    name = "a",
+    srcs = [
+        "lib.rs",
+        ":descriptors",
+    ],
+)
+```
+
+With `lib.rs` declaring `mod descriptors;`, the descriptor set is available as
+`descriptors::FILE_DESCRIPTOR_SET`.
+""",
+    implementation = _rust_prost_descriptor_set_impl,
+    attrs = {
+        "compress": attr.bool(
+            doc = "Whether to gzip compress the embedded descriptor set. It must then be decompressed before being decoded.",
+            default = False,
+        ),
+        "constant_name": attr.string(
+            doc = "The name of the constant holding the descriptor set.",
+            default = "FILE_DESCRIPTOR_SET",
+        ),
+        "proto": attr.label(
+            doc = "The `proto_library` target whose descriptor set, along with those of its dependencies, is embedded.",
+            providers = [ProtoInfo],
+            mandatory = True,
+        ),
+        "_descriptor_embedder": attr.label(
+            doc = "The tool rendering descriptor sets as Rust constants.",
+            cfg = "exec",
+            executable = True,
+            default = Label("//private:descriptor_embedder"),
+        ),
+    },
+)
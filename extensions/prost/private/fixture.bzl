@@ -0,0 +1,105 @@
+"""Rules for compiling textproto and JSON fixtures into binary protobuf messages."""
+
+load("@rules_proto//proto:defs.bzl", "ProtoInfo")
+
+def _rust_prost_fixture_impl(ctx):
+    proto_info = ctx.attr.proto[ProtoInfo]
+    descriptor_sets = proto_info.transitive_descriptor_sets
+
+    outputs = []
+    for src in ctx.files.srcs:
+        output = ctx.actions.declare_file("{}/{}.binpb".format(
+            ctx.label.name,
+            src.basename[:-len(src.extension) - 1],
+        ))
+        outputs.append(output)
+
+        args = ctx.actions.args()
+        args.add_all(descriptor_sets, format_each = "--descriptor_set=%s")
+        args.add(ctx.attr.message, format = "--message=%s")
+        args.add(src, format = "--input=%s")
+        args.add(output, format = "--output=%s")
+
+        ctx.actions.run(
+            mnemonic = "ProstFixture",
+            progress_message = "ProstFixture {}".format(src.short_path),
+            executable = ctx.executable._fixture_compiler,
+            inputs = depset([src], transitive = [descriptor_sets]),
+            outputs = [output],
+            arguments = [args],
+        )
+
+    return [DefaultInfo(
+        files = depset(outputs),
+        runfiles = ctx.runfiles(files = outputs),
+    )]
+
+rust_prost_fixture = rule(
+    doc = """\
+A rule for compiling textproto and JSON fixtures into binary encoded protobuf messages.
+
+Each fixture is validated against the schema of `message` at build time, so unknown fields, values
+of the wrong type and out of range integers fail the build instead of the test reading the fixture.
+The format of a fixture is determined by its extension: `.textproto`, `.txtpb` and `.pbtxt` files
+use the text format and `.json` files use the JSON mapping. Each fixture `<name>.<ext>` produces
+`<target name>/<name>.binpb`. E.g.
+```python
+load("@rules_proto//proto:defs.bzl", "proto_library")
+load("@rules_rust//rust:defs.bzl", "rust_test")
+load("@rules_rust_prost//:defs.bzl", "rust_prost_fixture", "rust_prost_library")
+
+proto_library(
+    name = "a_proto",
+    srcs = [
+        "a.proto",
+    ],
+)
+
+rust_prost_fixture(
+    name = "a_fixtures",
+    srcs = [
+        "a.textproto",
+    ],
+    message = "examples.A",
+    proto = ":a_proto",
+)
+
+rust_test(
+    name = "a_test",
+    srcs = ["a_test.rs"],
+    data = [":a_fixtures"],
+    rustc_env = {
+        "A_FIXTURE": "$(rootpath :a_fixtures)",
+    },
+    deps = [":a_rs_proto"],
+)
+```
+
+Well-known types are encoded as regular messages: the special JSON representations of types such as
+`google.protobuf.Timestamp`, along with extensions and `Any` type URLs in the text format, are not
+supported.
+""",
+    implementation = _rust_prost_fixture_impl,
+    attrs = {
+        "message": attr.string(
+            doc = "The fully qualified name of the message the fixtures encode (e.g. `examples.prost.helloworld.HelloRequest`).",
+            mandatory = True,
+        ),
+        "proto": attr.label(
+            doc = "The `proto_library` target defining `message` or depending on its definition.",
+            providers = [ProtoInfo],
+            mandatory = True,
+        ),
+        "srcs": attr.label_list(
+            doc = "The textproto or JSON fixtures to compile.",
+            allow_files = [".textproto", ".txtpb", ".pbtxt", ".json"],
+            mandatory = True,
+        ),
+        "_fixture_compiler": attr.label(
+            doc = "The tool compiling fixtures into binary protobuf messages.",
+            cfg = "exec",
+            executable = True,
+            default = Label("//private:fixture_compiler"),
+        ),
+    },
+)
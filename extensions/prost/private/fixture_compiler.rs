@@ -0,0 +1,1513 @@
+//! Compiles textproto and JSON fixtures into binary encoded protobuf messages.
+//!
+//! The fixture is validated against the schema of the message it encodes, which
+//! is read from the descriptor sets produced for the `proto_library` defining it.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use prost::encoding::{encode_key, encode_varint, WireType};
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+
+/// The format of a fixture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    TextProto,
+    Json,
+}
+
+impl Format {
+    /// Determine the format of a fixture from its file extension.
+    fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("textproto" | "txtpb" | "pbtxt") => Ok(Format::TextProto),
+            Some("json") => Ok(Format::Json),
+            _ => Err(format!(
+                "Unable to determine the format of `{}`. Expected a `.textproto`, `.txtpb`, `.pbtxt` or `.json` file.",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// The parsed command-line arguments.
+struct Args {
+    /// The descriptor sets defining the message and all types it depends on.
+    descriptor_sets: Vec<PathBuf>,
+
+    /// The fully qualified name of the message the fixture encodes.
+    message: String,
+
+    /// The fixture to compile.
+    input: PathBuf,
+
+    /// The path to write the binary encoded message to.
+    output: PathBuf,
+}
+
+impl Args {
+    /// Parse the given arguments.
+    fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut descriptor_sets = Vec::new();
+        let mut message = None;
+        let mut input = None;
+        let mut output = None;
+
+        for arg in args {
+            let (flag, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("Unexpected argument `{}`.", arg))?;
+            match flag {
+                "--descriptor_set" => descriptor_sets.push(PathBuf::from(value)),
+                "--message" => message = Some(value.trim_start_matches('.').to_string()),
+                "--input" => input = Some(PathBuf::from(value)),
+                "--output" => output = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unexpected argument `{}`.", arg)),
+            }
+        }
+
+        Ok(Args {
+            descriptor_sets,
+            message: message.ok_or("No `--message` value was found.")?,
+            input: input.ok_or("No `--input` value was found.")?,
+            output: output.ok_or("No `--output` value was found.")?,
+        })
+    }
+}
+
+/// An error found in a fixture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FixtureError {
+    /// The line the error was found on, if known.
+    line: Option<usize>,
+
+    /// The path of the field the error was found in, if any.
+    field_path: String,
+
+    /// A description of the error.
+    message: String,
+}
+
+impl FixtureError {
+    /// Create an error about a field.
+    fn field(field_path: &str, message: String) -> Self {
+        Self {
+            line: None,
+            field_path: field_path.to_string(),
+            message,
+        }
+    }
+
+    /// Create a syntax error on the given line.
+    fn syntax(line: usize, message: String) -> Self {
+        Self {
+            line: Some(line),
+            field_path: String::new(),
+            message,
+        }
+    }
+}
+
+impl Display for FixtureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if !self.field_path.is_empty() {
+            write!(f, "`{}`: ", self.field_path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A value parsed from a fixture, independent of its format.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// An unquoted identifier such as an enum value name, `true` or `inf`.
+    Identifier(String),
+
+    /// The text of a number.
+    Number(String),
+
+    /// The contents of a quoted string. Text format strings may hold arbitrary bytes.
+    String(Vec<u8>),
+
+    /// A JSON boolean.
+    Bool(bool),
+
+    /// A JSON `null`, leaving the field unset.
+    Null,
+
+    /// A list of values of a repeated field.
+    List(Vec<Node>),
+
+    /// The fields of a message in the order they appear.
+    Message(Vec<(String, Node)>),
+}
+
+/// The tokens of the text format.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(String),
+    String(Vec<u8>),
+    Symbol(char),
+}
+
+/// Split text format input into tokens along with the line each starts on.
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, FixtureError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '"' | '\'' => {
+                let quote = c;
+                chars.next();
+                let mut value = Vec::new();
+                loop {
+                    let c = chars.next().ok_or_else(|| {
+                        FixtureError::syntax(line, "Unterminated string.".to_string())
+                    })?;
+                    match c {
+                        '\n' => {
+                            return Err(FixtureError::syntax(
+                                line,
+                                "Unterminated string.".to_string(),
+                            ))
+                        }
+                        '\\' => parse_escape(&mut chars, &mut value)
+                            .map_err(|message| FixtureError::syntax(line, message))?,
+                        c if c == quote => break,
+                        c => value.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    }
+                }
+                // Adjacent strings are concatenated.
+                match tokens.last_mut() {
+                    Some((Token::String(previous), _)) => previous.extend(value),
+                    _ => tokens.push((Token::String(value), line)),
+                }
+            }
+            c if c.is_ascii_digit()
+                || c == '.' && !matches!(tokens.last(), Some((Token::Identifier(_), _))) =>
+            {
+                let mut number = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '_')
+                {
+                    number.push(c);
+                    // Exponents may be signed.
+                    if matches!(c, 'e' | 'E')
+                        && !number.starts_with("0x")
+                        && !number.starts_with("0X")
+                    {
+                        if let Some(sign) = chars.next_if(|c| *c == '-' || *c == '+') {
+                            number.push(sign);
+                        }
+                    }
+                }
+                tokens.push((Token::Number(number), line));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut identifier = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    identifier.push(c);
+                }
+                tokens.push((Token::Identifier(identifier), line));
+            }
+            ':' | '{' | '}' | '<' | '>' | '[' | ']' | ',' | ';' | '-' | '.' | '/' => {
+                tokens.push((Token::Symbol(c), line));
+                chars.next();
+            }
+            c => {
+                return Err(FixtureError::syntax(
+                    line,
+                    format!("Unexpected character `{}`.", c),
+                ))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parse the escape sequence following a `\` in a text format string.
+fn parse_escape(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    value: &mut Vec<u8>,
+) -> Result<(), String> {
+    let c = chars.next().ok_or("Unterminated escape sequence.")?;
+    let byte = match c {
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'a' => 0x07,
+        'b' => 0x08,
+        'f' => 0x0c,
+        'v' => 0x0b,
+        '\\' | '\'' | '"' | '?' => c as u8,
+        'x' | 'X' => {
+            let mut digits = String::new();
+            while digits.len() < 2 {
+                match chars.next_if(|c| c.is_ascii_hexdigit()) {
+                    Some(c) => digits.push(c),
+                    None => break,
+                }
+            }
+            u8::from_str_radix(&digits, 16)
+                .map_err(|_| format!("Invalid hex escape `\\{}{}`.", c, digits))?
+        }
+        '0'..='7' => {
+            let mut digits = c.to_string();
+            while digits.len() < 3 {
+                match chars.next_if(|c| ('0'..='7').contains(c)) {
+                    Some(c) => digits.push(c),
+                    None => break,
+                }
+            }
+            u8::from_str_radix(&digits, 8)
+                .map_err(|_| format!("Invalid octal escape `\\{}`.", digits))?
+        }
+        'u' | 'U' => {
+            let len = if c == 'u' { 4 } else { 8 };
+            let mut digits = String::new();
+            for _ in 0..len {
+                digits.push(
+                    chars
+                        .next_if(|c| c.is_ascii_hexdigit())
+                        .ok_or_else(|| format!("Invalid unicode escape `\\{}{}`.", c, digits))?,
+                );
+            }
+            let code_point = u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("Invalid unicode escape `\\{}{}`.", c, digits))?;
+            value.extend_from_slice(code_point.encode_utf8(&mut [0; 4]).as_bytes());
+            return Ok(());
+        }
+        c => return Err(format!("Invalid escape sequence `\\{}`.", c)),
+    };
+    value.push(byte);
+
+    Ok(())
+}
+
+/// A parser of the protobuf text format.
+struct TextParser {
+    /// The tokens of the input along with their lines.
+    tokens: Vec<(Token, usize)>,
+
+    /// The index of the next token.
+    position: usize,
+}
+
+impl TextParser {
+    /// Parse the fields of a text format message.
+    fn parse(input: &str) -> Result<Node, FixtureError> {
+        let mut parser = TextParser {
+            tokens: tokenize(input)?,
+            position: 0,
+        };
+        let fields = parser.parse_fields(None)?;
+
+        Ok(Node::Message(fields))
+    }
+
+    /// The line of the next token, or of the last token at the end of the input.
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    /// The next token, if any.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    /// Consume the next token if it is the given symbol.
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Consume the given symbol or fail.
+    fn expect(&mut self, symbol: char) -> Result<(), FixtureError> {
+        if self.eat(symbol) {
+            return Ok(());
+        }
+        Err(FixtureError::syntax(
+            self.line(),
+            format!("Expected `{}`.", symbol),
+        ))
+    }
+
+    /// Parse fields until the given closing symbol, or the end of the input.
+    fn parse_fields(&mut self, end: Option<char>) -> Result<Vec<(String, Node)>, FixtureError> {
+        let mut fields = Vec::new();
+        loop {
+            match (self.peek(), end) {
+                (None, None) => return Ok(fields),
+                (None, Some(end)) => {
+                    return Err(FixtureError::syntax(
+                        self.line(),
+                        format!("Expected `{}` before the end of the input.", end),
+                    ))
+                }
+                (Some(Token::Symbol(symbol)), Some(end)) if *symbol == end => {
+                    self.position += 1;
+                    return Ok(fields);
+                }
+                _ => {}
+            }
+
+            let name = match self.tokens.get(self.position) {
+                Some((Token::Identifier(name), _)) => name.clone(),
+                Some((Token::Symbol('['), line)) => {
+                    return Err(FixtureError::syntax(
+                        *line,
+                        "Extensions and `Any` type URLs are not supported.".to_string(),
+                    ))
+                }
+                _ => {
+                    return Err(FixtureError::syntax(
+                        self.line(),
+                        "Expected a field name.".to_string(),
+                    ))
+                }
+            };
+            self.position += 1;
+
+            let has_colon = self.eat(':');
+            let value = match self.peek() {
+                Some(Token::Symbol('{' | '<')) => self.parse_message()?,
+                Some(Token::Symbol('[')) if has_colon => self.parse_list()?,
+                _ if has_colon => self.parse_scalar()?,
+                _ => {
+                    return Err(FixtureError::syntax(
+                        self.line(),
+                        format!("Expected `:` after `{}`.", name),
+                    ))
+                }
+            };
+            fields.push((name, value));
+
+            if !self.eat(',') {
+                self.eat(';');
+            }
+        }
+    }
+
+    /// Parse a message value enclosed in `{}` or `<>`.
+    fn parse_message(&mut self) -> Result<Node, FixtureError> {
+        let end = if self.eat('{') {
+            '}'
+        } else {
+            self.expect('<')?;
+            '>'
+        };
+
+        Ok(Node::Message(self.parse_fields(Some(end))?))
+    }
+
+    /// Parse a list of values enclosed in `[]`.
+    fn parse_list(&mut self) -> Result<Node, FixtureError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        if self.eat(']') {
+            return Ok(Node::List(values));
+        }
+        loop {
+            values.push(match self.peek() {
+                Some(Token::Symbol('{' | '<')) => self.parse_message()?,
+                _ => self.parse_scalar()?,
+            });
+            if self.eat(']') {
+                return Ok(Node::List(values));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    /// Parse a scalar value, which may be negated.
+    fn parse_scalar(&mut self) -> Result<Node, FixtureError> {
+        let negative = self.eat('-');
+        let line = self.line();
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(token, _)| token.clone());
+        self.position += 1;
+        match token {
+            Some(Token::Number(number)) if negative => Ok(Node::Number(format!("-{}", number))),
+            Some(Token::Number(number)) => Ok(Node::Number(number)),
+            Some(Token::Identifier(identifier)) if negative => {
+                Ok(Node::Identifier(format!("-{}", identifier)))
+            }
+            Some(Token::Identifier(identifier)) => Ok(Node::Identifier(identifier)),
+            Some(Token::String(value)) if !negative => Ok(Node::String(value)),
+            _ => Err(FixtureError::syntax(line, "Expected a value.".to_string())),
+        }
+    }
+}
+
+/// A parser of JSON.
+struct JsonParser<'a> {
+    /// The remaining input.
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+
+    /// The current line.
+    line: usize,
+}
+
+impl JsonParser<'_> {
+    /// Parse a JSON document.
+    fn parse(input: &str) -> Result<Node, FixtureError> {
+        let mut parser = JsonParser {
+            chars: input.chars().peekable(),
+            line: 1,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.chars.peek().is_some() {
+            return Err(parser.error("Unexpected content after the JSON value."));
+        }
+
+        Ok(value)
+    }
+
+    /// Create a syntax error on the current line.
+    fn error(&self, message: &str) -> FixtureError {
+        FixtureError::syntax(self.line, message.to_string())
+    }
+
+    /// Skip whitespace, counting lines.
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.next_if(|c| c.is_whitespace()) {
+            if c == '\n' {
+                self.line += 1;
+            }
+        }
+    }
+
+    /// Consume the given character, after any whitespace, or fail.
+    fn expect(&mut self, expected: char) -> Result<(), FixtureError> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("Expected `{}`.", expected))),
+        }
+    }
+
+    /// Parse any JSON value.
+    fn parse_value(&mut self) -> Result<Node, FixtureError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => {
+                self.chars.next();
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.chars.next_if_eq(&'}').is_some() {
+                    return Ok(Node::Message(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let name = self.parse_string()?;
+                    self.expect(':')?;
+                    fields.push((name, self.parse_value()?));
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Node::Message(fields)),
+                        _ => return Err(self.error("Expected `,` or `}`.")),
+                    }
+                }
+            }
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.chars.next_if_eq(&']').is_some() {
+                    return Ok(Node::List(values));
+                }
+                loop {
+                    values.push(self.parse_value()?);
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Node::List(values)),
+                        _ => return Err(self.error("Expected `,` or `]`.")),
+                    }
+                }
+            }
+            Some('"') => Ok(Node::String(self.parse_string()?.into_bytes())),
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                {
+                    number.push(c);
+                }
+                Ok(Node::Number(number))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut literal = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    literal.push(c);
+                }
+                match literal.as_str() {
+                    "true" => Ok(Node::Bool(true)),
+                    "false" => Ok(Node::Bool(false)),
+                    "null" => Ok(Node::Null),
+                    _ => Err(self.error(&format!("Unexpected literal `{}`.", literal))),
+                }
+            }
+            _ => Err(self.error("Expected a value.")),
+        }
+    }
+
+    /// Parse a JSON string.
+    fn parse_string(&mut self) -> Result<String, FixtureError> {
+        if self.chars.next_if_eq(&'"').is_none() {
+            return Err(self.error("Expected a string."));
+        }
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                None | Some('\n') => return Err(self.error("Unterminated string.")),
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let c = match self.chars.next() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        Some('u') => self.parse_unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape sequence.")),
+                    };
+                    value.push(c);
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /// Parse the code point of a `\u` escape, combining surrogate pairs.
+    fn parse_unicode_escape(&mut self) -> Result<char, FixtureError> {
+        let parse_unit = |parser: &mut Self| -> Result<u32, FixtureError> {
+            let digits = (0..4)
+                .map(|_| parser.chars.next_if(|c| c.is_ascii_hexdigit()))
+                .collect::<Option<String>>()
+                .ok_or_else(|| parser.error("Invalid unicode escape."))?;
+            u32::from_str_radix(&digits, 16).map_err(|_| parser.error("Invalid unicode escape."))
+        };
+        let unit = parse_unit(self)?;
+        let code_point = if (0xd800..0xdc00).contains(&unit) {
+            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                return Err(self.error("Unpaired surrogate in unicode escape."));
+            }
+            let low = parse_unit(self)?;
+            0x10000 + ((unit - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            unit
+        };
+
+        char::from_u32(code_point).ok_or_else(|| self.error("Invalid unicode escape."))
+    }
+}
+
+/// The messages and enums of a set of descriptor sets, keyed by their fully
+/// qualified names without a leading `.`.
+#[derive(Debug, Default)]
+struct Schema {
+    /// Messages along with whether their file uses proto3 syntax.
+    messages: BTreeMap<String, (DescriptorProto, bool)>,
+
+    /// Enums.
+    enums: BTreeMap<String, EnumDescriptorProto>,
+}
+
+impl Schema {
+    /// Index the types of the given descriptor sets.
+    fn new(descriptor_sets: &[FileDescriptorSet]) -> Self {
+        /// Index a message and the types nested in it.
+        fn visit(schema: &mut Schema, prefix: &str, message: &DescriptorProto, proto3: bool) {
+            let name = join_name(prefix, message.name());
+            for nested in &message.nested_type {
+                visit(schema, &name, nested, proto3);
+            }
+            for enum_type in &message.enum_type {
+                schema
+                    .enums
+                    .insert(join_name(&name, enum_type.name()), enum_type.clone());
+            }
+            schema.messages.insert(name, (message.clone(), proto3));
+        }
+
+        let mut schema = Schema::default();
+        for file in descriptor_sets.iter().flat_map(|set| set.file.iter()) {
+            let proto3 = file.syntax() == "proto3";
+            for message in &file.message_type {
+                visit(&mut schema, file.package(), message, proto3);
+            }
+            for enum_type in &file.enum_type {
+                schema.enums.insert(
+                    join_name(file.package(), enum_type.name()),
+                    enum_type.clone(),
+                );
+            }
+        }
+
+        schema
+    }
+}
+
+/// Join a name to a dotted prefix.
+fn join_name(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        return name.to_string();
+    }
+    format!("{}.{}", prefix, name)
+}
+
+/// Encodes the nodes of a fixture as binary protobuf messages.
+struct Encoder<'a> {
+    /// The types available to the fixture.
+    schema: &'a Schema,
+
+    /// The format the fixture was written in, which determines how strings and
+    /// field names are interpreted.
+    format: Format,
+}
+
+impl Encoder<'_> {
+    /// Encode the fields of a message.
+    fn encode_message(
+        &self,
+        message_name: &str,
+        node: &Node,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), FixtureError> {
+        let (message, proto3) = self.schema.messages.get(message_name).ok_or_else(|| {
+            FixtureError::field(
+                message_name,
+                "The message is not defined by the given descriptor sets.".to_string(),
+            )
+        })?;
+        let fields = match node {
+            Node::Message(fields) => fields,
+            _ => {
+                return Err(FixtureError::field(
+                    message_name,
+                    if self.format == Format::Json && message_name.starts_with("google.protobuf.") {
+                        "The special JSON representations of well-known types are not supported. Use an object with the fields of the message instead.".to_string()
+                    } else {
+                        "Expected a message.".to_string()
+                    },
+                ))
+            }
+        };
+
+        // Values are grouped by field so they are encoded in field number order
+        // and repeated fields may be given more than once.
+        let mut values: BTreeMap<i32, (&FieldDescriptorProto, Vec<&Node>)> = BTreeMap::new();
+        let mut oneofs: BTreeMap<i32, &str> = BTreeMap::new();
+        for (name, value) in fields {
+            let field = message
+                .field
+                .iter()
+                .find(|field| {
+                    field.name() == name
+                        || self.format == Format::Json && field.json_name() == name
+                        || field.r#type() == Type::Group
+                            && field.type_name().rsplit('.').next() == Some(name.as_str())
+                })
+                .ok_or_else(|| {
+                    FixtureError::field(
+                        &join_name(message_name, name),
+                        "The field is not defined.".to_string(),
+                    )
+                })?;
+            let path = join_name(message_name, field.name());
+            if *value == Node::Null {
+                continue;
+            }
+
+            let repeated = field.label() == Label::Repeated;
+            let entry = values.entry(field.number()).or_insert((field, Vec::new()));
+            match value {
+                Node::List(items) if repeated => entry.1.extend(items),
+                Node::List(_) => {
+                    return Err(FixtureError::field(
+                        &path,
+                        "A list was given for a field which is not repeated.".to_string(),
+                    ))
+                }
+                value => entry.1.push(value),
+            }
+            if !repeated && entry.1.len() > 1 {
+                return Err(FixtureError::field(
+                    &path,
+                    "The field is not repeated but was given more than once.".to_string(),
+                ));
+            }
+
+            if let Some(oneof_index) = field.oneof_index.filter(|_| !field.proto3_optional()) {
+                if let Some(other) = oneofs.insert(oneof_index, field.name()) {
+                    if other != field.name() {
+                        return Err(FixtureError::field(
+                            &path,
+                            format!("`{}` is part of the same oneof and was already set.", other),
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (field, nodes) in values.values() {
+            self.encode_field(message_name, field, nodes, *proto3, buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the field is a map.
+    fn is_map(&self, field: &FieldDescriptorProto) -> bool {
+        field.r#type() == Type::Message
+            && self
+                .schema
+                .messages
+                .get(field.type_name().trim_start_matches('.'))
+                .and_then(|(message, _)| message.options.as_ref())
+                .is_some_and(|options| options.map_entry())
+    }
+
+    /// Encode all values of a field.
+    fn encode_field(
+        &self,
+        message_name: &str,
+        field: &FieldDescriptorProto,
+        nodes: &[&Node],
+        proto3: bool,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), FixtureError> {
+        let path = join_name(message_name, field.name());
+        let tag = field.number() as u32;
+        let type_name = field.type_name().trim_start_matches('.');
+
+        // JSON maps are objects keyed by the map keys.
+        let expanded;
+        let nodes = if self.format == Format::Json && self.is_map(field) {
+            expanded = nodes
+                .iter()
+                .map(|node| match node {
+                    Node::Message(entries) => Ok(entries
+                        .iter()
+                        .map(|(key, value)| {
+                            Node::Message(vec![
+                                ("key".to_string(), Node::String(key.clone().into_bytes())),
+                                ("value".to_string(), value.clone()),
+                            ])
+                        })
+                        .collect::<Vec<_>>()),
+                    _ => Err(FixtureError::field(
+                        &path,
+                        "Expected an object.".to_string(),
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            expanded.iter().collect::<Vec<_>>()
+        } else {
+            nodes.to_vec()
+        };
+
+        match field.r#type() {
+            Type::Message => {
+                for node in nodes {
+                    let mut message = Vec::new();
+                    self.encode_message(type_name, node, &mut message)
+                        .map_err(|err| nest_error(&path, type_name, err))?;
+                    encode_key(tag, WireType::LengthDelimited, buf);
+                    encode_varint(message.len() as u64, buf);
+                    buf.extend(message);
+                }
+            }
+            Type::Group => {
+                for node in nodes {
+                    encode_key(tag, WireType::StartGroup, buf);
+                    self.encode_message(type_name, node, buf)
+                        .map_err(|err| nest_error(&path, type_name, err))?;
+                    encode_key(tag, WireType::EndGroup, buf);
+                }
+            }
+            Type::String | Type::Bytes => {
+                for node in nodes {
+                    let value = self.bytes_value(&path, field.r#type(), node)?;
+                    encode_key(tag, WireType::LengthDelimited, buf);
+                    encode_varint(value.len() as u64, buf);
+                    buf.extend(value);
+                }
+            }
+            field_type => {
+                let values = nodes
+                    .iter()
+                    .map(|node| self.scalar_value(&path, field_type, type_name, node))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let packed = field.label() == Label::Repeated
+                    && field
+                        .options
+                        .as_ref()
+                        .and_then(|options| options.packed)
+                        .unwrap_or(proto3);
+                if packed {
+                    if values.is_empty() {
+                        return Ok(());
+                    }
+                    let mut packed_values: Vec<u8> = Vec::new();
+                    for (value, _) in &values {
+                        packed_values.extend(value);
+                    }
+                    encode_key(tag, WireType::LengthDelimited, buf);
+                    encode_varint(packed_values.len() as u64, buf);
+                    buf.extend(packed_values);
+                } else {
+                    for (value, wire_type) in values {
+                        encode_key(tag, wire_type, buf);
+                        buf.extend(value);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Interpret a node as the contents of a `string` or `bytes` field.
+    fn bytes_value(
+        &self,
+        path: &str,
+        field_type: Type,
+        node: &Node,
+    ) -> Result<Vec<u8>, FixtureError> {
+        let value = match node {
+            Node::String(value) => value.clone(),
+            _ => return Err(FixtureError::field(path, "Expected a string.".to_string())),
+        };
+        match (field_type, self.format) {
+            (Type::Bytes, Format::Json) => decode_base64(&value).ok_or_else(|| {
+                FixtureError::field(path, "Expected base64 encoded bytes.".to_string())
+            }),
+            (Type::String, _) => match String::from_utf8(value) {
+                Ok(value) => Ok(value.into_bytes()),
+                Err(_) => Err(FixtureError::field(
+                    path,
+                    "Strings must be valid UTF-8.".to_string(),
+                )),
+            },
+            _ => Ok(value),
+        }
+    }
+
+    /// Encode a scalar value without its key, returning the wire type it is encoded with.
+    fn scalar_value(
+        &self,
+        path: &str,
+        field_type: Type,
+        type_name: &str,
+        node: &Node,
+    ) -> Result<(Vec<u8>, WireType), FixtureError> {
+        let mut buf = Vec::new();
+        let err = |expected: &str| {
+            FixtureError::field(
+                path,
+                format!("Expected {}, got {}.", expected, describe(node)),
+            )
+        };
+        // JSON allows numbers to be given as strings.
+        let number = match node {
+            Node::Number(number) => Some(number.clone()),
+            Node::String(value) if self.format == Format::Json => {
+                String::from_utf8(value.clone()).ok()
+            }
+            _ => None,
+        };
+
+        let wire_type = match field_type {
+            Type::Bool => {
+                let value = match node {
+                    Node::Bool(value) => *value,
+                    Node::Identifier(value) => match value.as_str() {
+                        "true" | "True" | "t" => true,
+                        "false" | "False" | "f" => false,
+                        _ => return Err(err("a boolean")),
+                    },
+                    Node::Number(value) if value == "0" || value == "1" => value == "1",
+                    Node::String(value) if self.format == Format::Json => match value.as_slice() {
+                        b"true" => true,
+                        b"false" => false,
+                        _ => return Err(err("a boolean")),
+                    },
+                    _ => return Err(err("a boolean")),
+                };
+                encode_varint(value as u64, &mut buf);
+                WireType::Varint
+            }
+            Type::Enum => {
+                let enum_type = self.schema.enums.get(type_name).ok_or_else(|| {
+                    FixtureError::field(
+                        path,
+                        format!(
+                            "The enum `{}` is not defined by the given descriptor sets.",
+                            type_name
+                        ),
+                    )
+                })?;
+                let name = match node {
+                    Node::Identifier(name) => Some(name.as_str()),
+                    Node::String(name) if self.format == Format::Json => {
+                        std::str::from_utf8(name).ok()
+                    }
+                    _ => None,
+                };
+                let value = match (name, &number) {
+                    (Some(name), _)
+                        if !name.starts_with(|c: char| c == '-' || c.is_ascii_digit()) =>
+                    {
+                        enum_type
+                            .value
+                            .iter()
+                            .find(|value| value.name() == name)
+                            .map(|value| value.number())
+                            .ok_or_else(|| {
+                                FixtureError::field(
+                                    path,
+                                    format!("`{}` is not a value of `{}`.", name, type_name),
+                                )
+                            })?
+                    }
+                    (_, Some(number)) => parse_integer(number)
+                        .and_then(|value| i32::try_from(value).ok())
+                        .ok_or_else(|| err("an enum value"))?,
+                    _ => return Err(err("an enum value")),
+                };
+                encode_varint(value as i64 as u64, &mut buf);
+                WireType::Varint
+            }
+            Type::Float | Type::Double => {
+                let value = match (node, &number) {
+                    (Node::Identifier(value), _) => parse_float_identifier(value),
+                    (_, Some(number)) => parse_float_identifier(number)
+                        .or_else(|| number.trim_end_matches(['f', 'F']).parse::<f64>().ok()),
+                    _ => None,
+                }
+                .ok_or_else(|| err("a number"))?;
+                if field_type == Type::Float {
+                    if value.is_finite() && value.abs() > f32::MAX as f64 {
+                        return Err(err("a number in the range of `float`"));
+                    }
+                    buf.extend((value as f32).to_le_bytes());
+                    WireType::ThirtyTwoBit
+                } else {
+                    buf.extend(value.to_le_bytes());
+                    WireType::SixtyFourBit
+                }
+            }
+            integer_type => {
+                let value = number
+                    .as_deref()
+                    .and_then(parse_integer)
+                    .ok_or_else(|| err("an integer"))?;
+                let (min, max) = match integer_type {
+                    Type::Int32 | Type::Sint32 | Type::Sfixed32 => {
+                        (i32::MIN as i128, i32::MAX as i128)
+                    }
+                    Type::Uint32 | Type::Fixed32 => (0, u32::MAX as i128),
+                    Type::Int64 | Type::Sint64 | Type::Sfixed64 => {
+                        (i64::MIN as i128, i64::MAX as i128)
+                    }
+                    _ => (0, u64::MAX as i128),
+                };
+                if value < min || value > max {
+                    return Err(FixtureError::field(
+                        path,
+                        format!("`{}` is out of range for `{:?}`.", value, integer_type),
+                    ));
+                }
+                match integer_type {
+                    Type::Int32 | Type::Int64 => {
+                        encode_varint(value as i64 as u64, &mut buf);
+                        WireType::Varint
+                    }
+                    Type::Uint32 | Type::Uint64 => {
+                        encode_varint(value as u64, &mut buf);
+                        WireType::Varint
+                    }
+                    Type::Sint32 | Type::Sint64 => {
+                        let value = value as i64;
+                        encode_varint(((value << 1) ^ (value >> 63)) as u64, &mut buf);
+                        WireType::Varint
+                    }
+                    Type::Fixed32 | Type::Sfixed32 => {
+                        buf.extend((value as u32).to_le_bytes());
+                        WireType::ThirtyTwoBit
+                    }
+                    _ => {
+                        buf.extend((value as u64).to_le_bytes());
+                        WireType::SixtyFourBit
+                    }
+                }
+            }
+        };
+
+        Ok((buf, wire_type))
+    }
+}
+
+/// Replace the message name prefixing the field path of an error from a nested
+/// message with the path of the field holding it.
+fn nest_error(path: &str, type_name: &str, mut err: FixtureError) -> FixtureError {
+    if let Some(field_path) = err
+        .field_path
+        .strip_prefix(type_name)
+        .and_then(|field_path| field_path.strip_prefix('.'))
+    {
+        err.field_path = format!("{}.{}", path, field_path);
+    }
+    err
+}
+
+/// Describe a node for error messages.
+fn describe(node: &Node) -> String {
+    match node {
+        Node::Identifier(identifier) => format!("`{}`", identifier),
+        Node::Number(number) => format!("`{}`", number),
+        Node::String(value) => format!("\"{}\"", String::from_utf8_lossy(value)),
+        Node::Bool(value) => format!("`{}`", value),
+        Node::Null => "`null`".to_string(),
+        Node::List(_) => "a list".to_string(),
+        Node::Message(_) => "a message".to_string(),
+    }
+}
+
+/// Parse a decimal, hexadecimal or octal integer.
+fn parse_integer(text: &str) -> Option<i128> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i128::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8).ok()?
+    } else if !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+/// Parse the special floating point values of the text format and JSON.
+fn parse_float_identifier(text: &str) -> Option<f64> {
+    let (negative, name) = match text.strip_prefix('-') {
+        Some(name) => (true, name),
+        None => (false, text),
+    };
+    let value = match name.to_ascii_lowercase().as_str() {
+        "inf" | "infinity" => f64::INFINITY,
+        "nan" => f64::NAN,
+        _ => return None,
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+/// Decode standard or URL-safe base64, with or without padding.
+fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in input.iter().copied().filter(|byte| *byte != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Some(output)
+}
+
+/// Compile a fixture into the binary encoding of the given message.
+fn compile_fixture(
+    schema: &Schema,
+    message: &str,
+    format: Format,
+    input: &str,
+) -> Result<Vec<u8>, FixtureError> {
+    let node = match format {
+        Format::TextProto => TextParser::parse(input)?,
+        Format::Json => JsonParser::parse(input)?,
+    };
+    let mut buf = Vec::new();
+    Encoder { schema, format }.encode_message(message, &node, &mut buf)?;
+
+    Ok(buf)
+}
+
+fn main() {
+    let args = Args::parse_from(env::args().skip(1)).expect("Failed to parse args");
+
+    let descriptor_sets = args
+        .descriptor_sets
+        .iter()
+        .map(|path| {
+            let bytes = fs::read(path).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to read descriptor set `{}`: {:?}",
+                    path.display(),
+                    e
+                )
+            });
+            FileDescriptorSet::decode(bytes.as_slice()).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to decode descriptor set `{}`: {:?}",
+                    path.display(),
+                    e
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    let schema = Schema::new(&descriptor_sets);
+
+    let format = Format::from_path(&args.input).unwrap_or_else(|err| panic!("{}", err));
+    let input = fs::read_to_string(&args.input)
+        .unwrap_or_else(|e| panic!("Failed to read `{}`: {:?}", args.input.display(), e));
+    let encoded = compile_fixture(&schema, &args.message, format, &input)
+        .unwrap_or_else(|err| panic!("{}: {}", args.input.display(), err));
+
+    fs::write(&args.output, encoded).expect("Failed to write file.");
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use prost_types::{
+        EnumValueDescriptorProto, FieldOptions, FileDescriptorProto, MessageOptions,
+        OneofDescriptorProto,
+    };
+
+    fn json_name(name: &str) -> String {
+        let mut parts = name.split('_');
+        let first = parts.next().unwrap_or_default().to_string();
+        parts.fold(first, |json_name, part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(c) => format!("{}{}{}", json_name, c.to_ascii_uppercase(), chars.as_str()),
+                None => json_name,
+            }
+        })
+    }
+
+    fn field(name: &str, number: i32, field_type: Type, label: Label) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            json_name: Some(json_name(name)),
+            number: Some(number),
+            r#type: Some(field_type as i32),
+            label: Some(label as i32),
+            ..FieldDescriptorProto::default()
+        }
+    }
+
+    fn message_field(
+        name: &str,
+        number: i32,
+        type_name: &str,
+        label: Label,
+    ) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            type_name: Some(type_name.to_string()),
+            ..field(name, number, Type::Message, label)
+        }
+    }
+
+    /// A schema equivalent to the following proto file.
+    ///
+    /// ```proto
+    /// syntax = "proto3";
+    /// package test;
+    ///
+    /// enum Color { COLOR_UNSPECIFIED = 0; COLOR_RED = 1; }
+    ///
+    /// message Inner { string name = 1; }
+    ///
+    /// message Fixture {
+    ///   int32 count = 1;
+    ///   sint64 delta = 2;
+    ///   repeated uint32 ids = 3;
+    ///   Color color = 4;
+    ///   Inner inner = 5;
+    ///   repeated Inner items = 6;
+    ///   map<string, int32> scores = 7;
+    ///   bytes data = 8;
+    ///   double ratio = 9;
+    ///   oneof choice { string text = 10; bool flag = 11; }
+    ///   repeated fixed32 unpacked = 12 [packed = false];
+    ///   string display_name = 13;
+    /// }
+    /// ```
+    fn schema() -> Schema {
+        let mut choice_text = field("text", 10, Type::String, Label::Optional);
+        choice_text.oneof_index = Some(0);
+        let mut choice_flag = field("flag", 11, Type::Bool, Label::Optional);
+        choice_flag.oneof_index = Some(0);
+        let mut unpacked = field("unpacked", 12, Type::Fixed32, Label::Repeated);
+        unpacked.options = Some(FieldOptions {
+            packed: Some(false),
+            ..FieldOptions::default()
+        });
+        let mut color = field("color", 4, Type::Enum, Label::Optional);
+        color.type_name = Some(".test.Color".to_string());
+
+        Schema::new(&[FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("test.proto".to_string()),
+                package: Some("test".to_string()),
+                syntax: Some("proto3".to_string()),
+                enum_type: vec![EnumDescriptorProto {
+                    name: Some("Color".to_string()),
+                    value: vec![
+                        EnumValueDescriptorProto {
+                            name: Some("COLOR_UNSPECIFIED".to_string()),
+                            number: Some(0),
+                            ..EnumValueDescriptorProto::default()
+                        },
+                        EnumValueDescriptorProto {
+                            name: Some("COLOR_RED".to_string()),
+                            number: Some(1),
+                            ..EnumValueDescriptorProto::default()
+                        },
+                    ],
+                    ..EnumDescriptorProto::default()
+                }],
+                message_type: vec![
+                    DescriptorProto {
+                        name: Some("Inner".to_string()),
+                        field: vec![field("name", 1, Type::String, Label::Optional)],
+                        ..DescriptorProto::default()
+                    },
+                    DescriptorProto {
+                        name: Some("Fixture".to_string()),
+                        field: vec![
+                            field("count", 1, Type::Int32, Label::Optional),
+                            field("delta", 2, Type::Sint64, Label::Optional),
+                            field("ids", 3, Type::Uint32, Label::Repeated),
+                            color,
+                            message_field("inner", 5, ".test.Inner", Label::Optional),
+                            message_field("items", 6, ".test.Inner", Label::Repeated),
+                            message_field(
+                                "scores",
+                                7,
+                                ".test.Fixture.ScoresEntry",
+                                Label::Repeated,
+                            ),
+                            field("data", 8, Type::Bytes, Label::Optional),
+                            field("ratio", 9, Type::Double, Label::Optional),
+                            choice_text,
+                            choice_flag,
+                            unpacked,
+                            field("display_name", 13, Type::String, Label::Optional),
+                        ],
+                        nested_type: vec![DescriptorProto {
+                            name: Some("ScoresEntry".to_string()),
+                            field: vec![
+                                field("key", 1, Type::String, Label::Optional),
+                                field("value", 2, Type::Int32, Label::Optional),
+                            ],
+                            options: Some(MessageOptions {
+                                map_entry: Some(true),
+                                ..MessageOptions::default()
+                            }),
+                            ..DescriptorProto::default()
+                        }],
+                        oneof_decl: vec![OneofDescriptorProto {
+                            name: Some("choice".to_string()),
+                            ..OneofDescriptorProto::default()
+                        }],
+                        ..DescriptorProto::default()
+                    },
+                ],
+                ..FileDescriptorProto::default()
+            }],
+        }])
+    }
+
+    /// The encoding of the fixtures in the tests below.
+    const EXPECTED: &[u8] = &[
+        0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, // count: -1
+        0x10, 0x05, // delta: -3
+        0x1a, 0x04, 0x01, 0x02, 0x96, 0x01, // ids: [1, 2, 150]
+        0x20, 0x01, // color: COLOR_RED
+        0x2a, 0x03, 0x0a, 0x01, b'a', // inner { name: "a" }
+        0x32, 0x03, 0x0a, 0x01, b'b', // items { name: "b" }
+        0x32, 0x00, // items {}
+        0x3a, 0x05, 0x0a, 0x01, b'x', 0x10, 0x07, // scores { key: "x" value: 7 }
+        0x42, 0x02, 0xff, 0x00, // data: "\xff\x00"
+        0x49, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f, // ratio: 1.5
+        0x58, 0x01, // flag: true
+        0x65, 0x02, 0, 0, 0, // unpacked: 2
+        0x65, 0x03, 0, 0, 0, // unpacked: 3
+        0x6a, 0x02, b'h', b'i', // display_name: "hi"
+    ];
+
+    #[test]
+    fn compile_textproto_test() {
+        let input = r#"
+            # A comment.
+            count: -1
+            delta: -3
+            ids: [1, 0x2]
+            ids: 0226
+            color: COLOR_RED
+            inner { name: "a" }
+            items: < name: 'b' >
+            items {}
+            scores { key: "x" value: 7 }
+            data: "\377\x00"
+            ratio: 1.5
+            flag: true
+            unpacked: [2, 3]
+            display_name: "h" "i"
+        "#;
+
+        assert_eq!(
+            compile_fixture(&schema(), "test.Fixture", Format::TextProto, input),
+            Ok(EXPECTED.to_vec())
+        );
+    }
+
+    #[test]
+    fn compile_json_test() {
+        let input = r#"{
+            "count": -1,
+            "delta": "-3",
+            "ids": [1, 2, 150],
+            "color": "COLOR_RED",
+            "inner": {"name": "a"},
+            "items": [{"name": "b"}, {}],
+            "scores": {"x": 7},
+            "data": "/wA=",
+            "ratio": 1.5,
+            "text": null,
+            "flag": true,
+            "unpacked": [2, 3],
+            "displayName": "hi"
+        }"#;
+
+        assert_eq!(
+            compile_fixture(&schema(), "test.Fixture", Format::Json, input),
+            Ok(EXPECTED.to_vec())
+        );
+    }
+
+    #[test]
+    fn compile_fixture_errors_test() {
+        let schema = schema();
+        let compile = |format, input| {
+            compile_fixture(&schema, "test.Fixture", format, input)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            compile(Format::TextProto, "unknown: 1"),
+            "`test.Fixture.unknown`: The field is not defined."
+        );
+        assert_eq!(
+            compile(Format::TextProto, "count: 1\ncount: 2"),
+            "`test.Fixture.count`: The field is not repeated but was given more than once."
+        );
+        assert_eq!(
+            compile(Format::TextProto, "count: 3000000000"),
+            "`test.Fixture.count`: `3000000000` is out of range for `Int32`."
+        );
+        assert_eq!(
+            compile(Format::TextProto, "color: COLOR_BLUE"),
+            "`test.Fixture.color`: `COLOR_BLUE` is not a value of `test.Color`."
+        );
+        assert_eq!(
+            compile(Format::TextProto, "inner { name: 1 }"),
+            "`test.Fixture.inner.name`: Expected a string."
+        );
+        assert_eq!(
+            compile(Format::TextProto, "text: \"a\" flag: true"),
+            "`test.Fixture.flag`: `text` is part of the same oneof and was already set."
+        );
+        assert_eq!(
+            compile(Format::TextProto, "count: 1\ninner {"),
+            "line 2: Expected `}` before the end of the input."
+        );
+        assert_eq!(
+            compile(Format::Json, "{\"count\": 1,\n}"),
+            "line 2: Expected a string."
+        );
+    }
+
+    #[test]
+    fn parse_integer_test() {
+        assert_eq!(parse_integer("10"), Some(10));
+        assert_eq!(parse_integer("-0x10"), Some(-16));
+        assert_eq!(parse_integer("010"), Some(8));
+        assert_eq!(parse_integer("0"), Some(0));
+        assert_eq!(parse_integer("1.5"), None);
+        assert_eq!(parse_integer(""), None);
+    }
+
+    #[test]
+    fn decode_base64_test() {
+        assert_eq!(decode_base64(b"aGVsbG8="), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64(b"aGVsbG8"), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64(b"-_8"), Some(vec![0xfb, 0xff]));
+        assert_eq!(decode_base64(b"a b"), None);
+    }
+}
@@ -20,12 +20,28 @@ load("@rules_rust//rust/private:rustc.bzl", "rustc_compile_action")
 load("@rules_rust//rust/private:utils.bzl", "can_build_metadata")
 load("//:providers.bzl", "ProstProtoInfo")
 load(":prost_transform.bzl", "ProstTransformInfo")
+load(":proto_deps.bzl", "ProtoDepsInfo", "proto_deps_aspect", "proto_deps_validation")
 
 RUST_EDITION = "2021"
 
 TOOLCHAIN_TYPE = "@rules_rust_prost//:toolchain_type"
 
-def _create_proto_lang_toolchain(ctx, prost_toolchain):
+def _create_proto_lang_toolchain(ctx, prost_toolchain, protobuf = False):
+    if protobuf:
+        # protoc reserves `--rust_out` for its builtin generator so rust-protobuf is
+        # run as the `rs` plugin.
+        return proto_common.ProtoLangToolchainInfo(
+            out_replacement_format_flag = "--rs_out=%s",
+            plugin_format_flag = prost_toolchain.protobuf_plugin_flag,
+            plugin = prost_toolchain.protobuf_plugin[DefaultInfo].files_to_run,
+            runtime = prost_toolchain.protobuf_runtime,
+            provided_proto_sources = depset(),
+            proto_compiler = ctx.attr._protobuf_process_wrapper[DefaultInfo].files_to_run,
+            protoc_opts = prost_toolchain.protoc_opts,
+            progress_message = "ProtobufGenProto %{label}",
+            mnemonic = "ProtobufGenProto",
+        )
+
     proto_lang_toolchain = proto_common.ProtoLangToolchainInfo(
         out_replacement_format_flag = "--prost_out=%s",
         plugin_format_flag = prost_toolchain.prost_plugin_flag,
@@ -40,6 +56,40 @@ def _create_proto_lang_toolchain(ctx, prost_toolchain):
 
     return proto_lang_toolchain
 
+def _crate_version(target, crate_name):
+    """Determine the version of a crate from the name of the repository providing it.
+
+    Crates generated by `crate_universe` live in repositories named `<name>-<version>`.
+
+    Args:
+        target (Target): A target providing `CrateInfo`, or `CrateGroupInfo` in which case the
+            crate named `crate_name` is looked up in the group.
+        crate_name (str): The name of the crate in a group.
+
+    Returns:
+        str: The version of the crate or `None` if it could not be determined.
+    """
+    if rust_common.crate_info in target:
+        crate_info = target[rust_common.crate_info]
+    elif rust_common.crate_group_info in target:
+        crate_infos = [
+            dep_variant_info.crate_info
+            for dep_variant_info in target[rust_common.crate_group_info].dep_variant_infos.to_list()
+            if dep_variant_info.crate_info and dep_variant_info.crate_info.name == crate_name
+        ]
+        if not crate_infos:
+            return None
+        crate_info = crate_infos[0]
+    else:
+        return None
+
+    repository = crate_info.owner.workspace_name
+    _, _, version = repository.rpartition("-")
+    if not version or not version[0].isdigit() or "." not in version:
+        return None
+
+    return version
+
 def _compile_proto(
         *,
         ctx,
@@ -48,75 +98,290 @@ def _compile_proto(
         transform_infos,
         deps,
         prost_toolchain,
+        dep_aliases = {},
+        protobuf = False,
+        grpcio = False,
+        connect = False,
         rustfmt_toolchain = None):
     deps_info_file = ctx.actions.declare_file(ctx.label.name + ".prost_deps_info")
-    dep_package_infos = [dep[ProstProtoInfo].package_info for dep in deps]
+
+    # Renamed dependencies are recorded as `<path>\t<crate name>=<alias>`.
+    deps_info_lines = []
+    direct_crate_names = []
+    for dep in deps:
+        rust_proto_info = dep[ProstProtoInfo]
+        dep_crate_name = rust_proto_info.dep_variant_info.crate_info.name
+        alias = dep_aliases.get(dep.label)
+        if alias:
+            deps_info_lines.append("{}\t{}={}".format(rust_proto_info.package_info.path, dep_crate_name, alias))
+            direct_crate_names.append(alias)
+        else:
+            deps_info_lines.append(rust_proto_info.package_info.path)
+            direct_crate_names.append(dep_crate_name)
     ctx.actions.write(
         output = deps_info_file,
-        content = "\n".join([file.path for file in dep_package_infos]),
+        content = "\n".join(deps_info_lines),
     )
 
     package_info_file = ctx.actions.declare_file(ctx.label.name + ".prost_package_info")
+    protoc_args_file = ctx.actions.declare_file(ctx.label.name + ".prost_protoc_args")
     lib_rs = ctx.actions.declare_file("{}.lib.rs".format(ctx.label.name))
+    diagnostics = ctx.actions.declare_file("{}.prost_diagnostics.json".format(ctx.label.name))
+    module_tree = None
+    if any([transform_info.module_tree for transform_info in transform_infos]):
+        module_tree = ctx.actions.declare_directory("{}.prost_modules".format(ctx.label.name))
+    source_map = None
+    if any([transform_info.source_map for transform_info in transform_infos]):
+        source_map = ctx.actions.declare_file("{}.prost_source_map.json".format(ctx.label.name))
+    mocks_rs = None
+    if any([transform_info.mocks for transform_info in transform_infos]):
+        mocks_rs = ctx.actions.declare_file("{}.mocks.rs".format(ctx.label.name))
 
     proto_compiler = prost_toolchain.proto_compiler
     tools = depset([proto_compiler.executable])
 
     tonic_opts = []
     prost_opts = []
+    protoc_opts = []
     additional_srcs = []
     for transform_info in transform_infos:
         tonic_opts.extend(transform_info.tonic_opts)
         prost_opts.extend(transform_info.prost_opts)
+        protoc_opts.extend(transform_info.protoc_opts)
         additional_srcs.append(transform_info.srcs)
 
     all_additional_srcs = depset(transitive = additional_srcs)
-    direct_crate_names = [dep[ProstProtoInfo].dep_variant_info.crate_info.name for dep in deps]
     additional_args = ctx.actions.args()
 
     # Prost process wrapper specific args
     additional_args.add("--protoc={}".format(proto_compiler.executable.path))
     additional_args.add("--label={}".format(ctx.label))
     additional_args.add("--out_librs={}".format(lib_rs.path))
-    additional_args.add("--package_info_output={}".format("{}={}".format(crate_name, package_info_file.path)))
+    if module_tree:
+        additional_args.add("--out_module_dir={}".format(module_tree.path))
+    if source_map:
+        additional_args.add("--source_map_output={}".format(source_map.path))
+    additional_args.add("--crate_name={}".format(crate_name))
+    additional_args.add("--package_info_output={}".format(package_info_file.path))
+    additional_args.add("--diagnostics_out={}".format(diagnostics.path))
     additional_args.add("--deps_info={}".format(deps_info_file.path))
     additional_args.add("--direct_dep_crate_names={}".format(",".join(direct_crate_names)))
-    additional_args.add("--prost_opt=compile_well_known_types")
+
+    # Toolchain settings specific to prost are not applied to crates generated
+    # with rust-protobuf.
+    if not protobuf:
+        additional_args.add("--prost_opt=compile_well_known_types")
+        if prost_toolchain.incremental_state_dir:
+            additional_args.add("--incremental_state_dir={}".format(prost_toolchain.incremental_state_dir))
+        if prost_toolchain.prost_version:
+            additional_args.add("--prost_version={}".format(prost_toolchain.prost_version))
+        if prost_toolchain.size_budget_bytes:
+            additional_args.add("--size_budget_bytes={}".format(prost_toolchain.size_budget_bytes))
+        if prost_toolchain.size_budget_types:
+            additional_args.add("--size_budget_types={}".format(prost_toolchain.size_budget_types))
+        if prost_toolchain.strict_size_budget:
+            additional_args.add("--strict")
     additional_args.add("--descriptor_set={}".format(proto_info.direct_descriptor_set.path))
-    additional_args.add("--additional_srcs={}".format(",".join([f.path for f in all_additional_srcs.to_list()])))
-    additional_args.add_all(prost_toolchain.prost_opts + prost_opts, format_each = "--prost_opt=%s")
+    additional_args.add_all(all_additional_srcs, format_each = "--additional_src=%s")
+    additional_args.add("--protoc_args_file={}".format(protoc_args_file.path))
+
+    # Plugin options are written to a response file with one option per line so
+    # they are passed to protoc verbatim regardless of their contents.
+    protoc_args = []
+    if not protobuf:
+        protoc_args.extend(["--prost_opt={}".format(opt) for opt in prost_toolchain.prost_opts + prost_opts])
 
-    if prost_toolchain.tonic_plugin:
+    if prost_toolchain.tonic_plugin and not protobuf and not connect:
         tonic_plugin = prost_toolchain.tonic_plugin[DefaultInfo].files_to_run
         additional_args.add(prost_toolchain.tonic_plugin_flag % tonic_plugin.executable.path)
         additional_args.add("--tonic_opt=no_include")
         additional_args.add("--tonic_opt=compile_well_known_types")
         additional_args.add("--is_tonic")
+        if prost_toolchain.tonic_compat:
+            additional_args.add("--tonic_compat={}".format(prost_toolchain.tonic_compat))
+            if prost_toolchain.tonic_runtime_version:
+                additional_args.add("--tonic_runtime_version={}".format(prost_toolchain.tonic_runtime_version))
 
-        additional_args.add_all(prost_toolchain.tonic_opts + tonic_opts, format_each = "--tonic_opt=%s")
+        protoc_args.extend(["--tonic_opt={}".format(opt) for opt in prost_toolchain.tonic_opts + tonic_opts])
         tools = depset([tonic_plugin.executable], transitive = [tools])
 
+    if connect:
+        connect_plugin = prost_toolchain.connect_plugin[DefaultInfo].files_to_run
+        additional_args.add(prost_toolchain.connect_plugin_flag % connect_plugin.executable.path)
+        additional_args.add("--connect_opt=compile_well_known_types")
+        additional_args.add("--is_connect")
+
+        # Generating only clients or only servers is configured with the same
+        # options as tonic.
+        for opt in prost_toolchain.tonic_opts + tonic_opts:
+            if opt in ["no_client", "no_server"]:
+                protoc_args.append("--connect_opt={}".format(opt))
+        tools = depset([connect_plugin.executable], transitive = [tools])
+
+    if grpcio:
+        grpcio_plugin = prost_toolchain.grpcio_plugin[DefaultInfo].files_to_run
+        additional_args.add(prost_toolchain.grpcio_plugin_flag % grpcio_plugin.executable.path)
+        additional_args.add("--is_grpcio")
+        tools = depset([grpcio_plugin.executable], transitive = [tools])
+
+    protoc_args.extend(protoc_opts)
+    ctx.actions.write(
+        output = protoc_args_file,
+        content = "\n".join(protoc_args),
+    )
+
+    wkt_types = {}
+    for transform_info in transform_infos:
+        wkt_types.update(transform_info.wkt_types)
+    for proto_name, flavor in wkt_types.items():
+        additional_args.add("--wkt_mapping={}={}".format(proto_name, flavor))
+
+    if any([transform_info.enum_helpers for transform_info in transform_infos]):
+        additional_args.add("--enum_helpers")
+
+    if any([transform_info.any_helpers for transform_info in transform_infos]):
+        additional_args.add("--any_helpers")
+
+    if any([transform_info.name_constants for transform_info in transform_infos]):
+        additional_args.add("--name_constants")
+
+    if any([transform_info.health_service for transform_info in transform_infos]):
+        additional_args.add("--health_service")
+
+    if mocks_rs:
+        additional_args.add("--mocks_output={}".format(mocks_rs.path))
+
+    builder_packages = []
+    for transform_info in transform_infos:
+        builder_packages.extend(transform_info.builder_packages)
+    additional_args.add_all(builder_packages, format_each = "--builder_package=%s", uniquify = True)
+
     if rustfmt_toolchain:
         additional_args.add("--rustfmt={}".format(rustfmt_toolchain.rustfmt.path))
+        if prost_toolchain.skip_format_threshold:
+            additional_args.add("--skip_format_threshold={}".format(prost_toolchain.skip_format_threshold))
         tools = depset(transitive = [tools, rustfmt_toolchain.all_files])
 
     additional_inputs = depset(
-        [deps_info_file, proto_info.direct_descriptor_set] + [dep[ProstProtoInfo].package_info for dep in deps],
+        [deps_info_file, protoc_args_file, proto_info.direct_descriptor_set] + [dep[ProstProtoInfo].package_info for dep in deps],
         transitive = [all_additional_srcs],
     )
 
+    actions = ctx.actions
+    if not protobuf and prost_toolchain.incremental_state_dir:
+        # The incremental state directory is outside of the sandbox, only exists on the local
+        # machine and isn't tracked as an input, so the outputs can't be cached either.
+        actions = _actions_with_execution_requirements(actions, {
+            "no-cache": "1",
+            "no-remote": "1",
+            "no-sandbox": "1",
+        })
+
     proto_common.compile(
-        actions = ctx.actions,
+        actions = actions,
         proto_info = proto_info,
         additional_tools = tools.to_list(),
         additional_inputs = additional_inputs,
         additional_args = additional_args,
-        generated_files = [lib_rs, package_info_file],
-        proto_lang_toolchain_info = _create_proto_lang_toolchain(ctx, prost_toolchain),
+        generated_files = [lib_rs, package_info_file, diagnostics] + [f for f in [module_tree, source_map, mocks_rs] if f],
+        proto_lang_toolchain_info = _create_proto_lang_toolchain(ctx, prost_toolchain, protobuf),
         plugin_output = ctx.bin_dir.path,
     )
 
-    return lib_rs, package_info_file
+    scaffold = None
+    if prost_toolchain.tonic_plugin and not protobuf and not connect:
+        scaffold = _generate_tonic_scaffold(
+            ctx = ctx,
+            crate_name = crate_name,
+            proto_info = proto_info,
+            deps = deps,
+            prost_toolchain = prost_toolchain,
+            deps_info_file = deps_info_file,
+            protoc_args_file = protoc_args_file,
+            wkt_types = wkt_types,
+        )
+
+    return lib_rs, package_info_file, module_tree, source_map, diagnostics, scaffold, mocks_rs
+
+def _generate_tonic_scaffold(
+        *,
+        ctx,
+        crate_name,
+        proto_info,
+        deps,
+        prost_toolchain,
+        deps_info_file,
+        protoc_args_file,
+        wkt_types):
+    """Declares the action writing the `main.rs` of a server implementing the services of a target.
+
+    The scaffold is only in the `rust_generated_tonic_scaffold` output group, so the action only
+    runs for targets compiled by `rust_prost_tonic_scaffold`.
+
+    Args:
+        ctx (ctx): The context of the current target.
+        crate_name (str): The name of the crate of the generated code.
+        proto_info (ProtoInfo): The ProtoInfo of the target.
+        deps (list): The `ProstProtoInfo` providing dependencies of the target.
+        prost_toolchain (ProstToolchainInfo): The prost toolchain.
+        deps_info_file (File): The file listing the package info files of the dependencies.
+        protoc_args_file (File): The file listing the plugin options passed to protoc.
+        wkt_types (dict): The well known types mapped to their Rust flavors.
+
+    Returns:
+        File: The generated `main.rs`.
+    """
+    scaffold = ctx.actions.declare_file("{}.tonic_scaffold.rs".format(ctx.label.name))
+
+    args = ctx.actions.args()
+    args.add("--scaffold_only")
+    args.add("--is_tonic")
+    args.add("--label={}".format(ctx.label))
+    args.add("--crate_name={}".format(crate_name))
+    args.add("--descriptor_set={}".format(proto_info.direct_descriptor_set.path))
+    args.add("--deps_info={}".format(deps_info_file.path))
+    args.add("--protoc_args_file={}".format(protoc_args_file.path))
+    for proto_name, flavor in wkt_types.items():
+        args.add("--wkt_mapping={}={}".format(proto_name, flavor))
+    if prost_toolchain.prost_version:
+        args.add("--prost_version={}".format(prost_toolchain.prost_version))
+    args.add("--scaffold_output={}".format(scaffold.path))
+
+    ctx.actions.run(
+        mnemonic = "ProstTonicScaffold",
+        progress_message = "ProstTonicScaffold %{label}",
+        executable = ctx.executable._prost_process_wrapper,
+        arguments = [args],
+        inputs = depset(
+            [deps_info_file, protoc_args_file, proto_info.direct_descriptor_set] + [dep[ProstProtoInfo].package_info for dep in deps],
+        ),
+        outputs = [scaffold],
+    )
+
+    return scaffold
+
+def _actions_with_execution_requirements(actions, execution_requirements):
+    """Wraps `ctx.actions` so actions registered with `run` get `execution_requirements`.
+
+    `proto_common.compile` doesn't accept execution requirements for the action it registers.
+
+    Args:
+        actions (actions): The actions of the rule context.
+        execution_requirements (dict): The execution requirements of the registered actions.
+
+    Returns:
+        struct: An object providing the `args` and `run` methods of `ctx.actions`.
+    """
+
+    def _run(**kwargs):
+        requirements = dict(kwargs.pop("execution_requirements", None) or {})
+        requirements.update(execution_requirements)
+        actions.run(execution_requirements = requirements, **kwargs)
+
+    return struct(
+        args = actions.args,
+        run = _run,
+    )
 
 def _get_crate_info(providers):
     """Finds the CrateInfo provider in the list of providers."""
@@ -146,7 +411,8 @@ def _compile_rust(
         crate_name,
         src,
         deps,
-        edition):
+        edition,
+        aliases = {}):
     """Compiles a Rust source file.
 
     Args:
@@ -156,6 +422,7 @@ def _compile_rust(
       src (File): The crate root source file to be compiled.
       deps (List of DepVariantInfo): A list of dependencies needed.
       edition (str): The Rust edition to use.
+      aliases (dict): Dependency targets mapped to the names the crate refers to them by.
 
     Returns:
       A DepVariantInfo provider.
@@ -200,7 +467,7 @@ def _compile_rust(
             srcs = depset([src]),
             deps = depset(deps),
             proc_macro_deps = depset([]),
-            aliases = {},
+            aliases = aliases,
             output = lib,
             metadata = rmeta,
             edition = edition,
@@ -224,6 +491,25 @@ def _compile_rust(
         build_info = None,
     )
 
+def _dep_variant_infos(target):
+    """Collect the `DepVariantInfo`s of a crate or crate group used by generated code.
+
+    Args:
+        target (Target): A target providing `CrateInfo` or `CrateGroupInfo`.
+
+    Returns:
+        list: The `DepVariantInfo`s of the target.
+    """
+    if rust_common.crate_group_info in target:
+        return target[rust_common.crate_group_info].dep_variant_infos.to_list()
+
+    return [rust_common.dep_variant_info(
+        crate_info = target[rust_common.crate_info] if rust_common.crate_info in target else None,
+        dep_info = target[rust_common.dep_info] if rust_common.dep_info in target else None,
+        cc_info = target[CcInfo] if CcInfo in target else None,
+        build_info = None,
+    )]
+
 def _rust_prost_aspect_impl(target, ctx):
     if ProstProtoInfo in target:
         return []
@@ -232,7 +518,34 @@ def _rust_prost_aspect_impl(target, ctx):
 
     rustfmt_toolchain = ctx.toolchains["@rules_rust//rust/rustfmt:toolchain_type"]
     prost_toolchain = ctx.toolchains[TOOLCHAIN_TYPE]
-    for prost_runtime in [prost_toolchain.prost_runtime, prost_toolchain.tonic_runtime]:
+
+    transform_infos = []
+    for data_target in getattr(ctx.rule.attr, "data", []):
+        if ProstTransformInfo in data_target:
+            transform_infos.append(data_target[ProstTransformInfo])
+
+    # grpcio service stubs are built on rust-protobuf messages.
+    grpcio = any([transform_info.grpcio for transform_info in transform_infos])
+    protobuf = grpcio or any([transform_info.protobuf for transform_info in transform_infos])
+    connect = any([transform_info.connect for transform_info in transform_infos])
+    if connect and protobuf:
+        fail("{}: `connect` cannot be combined with `protobuf` or `grpcio`".format(ctx.label))
+    if connect:
+        if not prost_toolchain.connect_plugin or not prost_toolchain.connect_runtime:
+            fail("{}: `connect` requires the prost toolchain to set `connect_plugin` and `connect_runtime`".format(ctx.label))
+        runtimes = [prost_toolchain.prost_runtime, prost_toolchain.connect_runtime]
+    elif protobuf:
+        if not prost_toolchain.protobuf_plugin or not prost_toolchain.protobuf_runtime:
+            fail("{}: `protobuf` requires the prost toolchain to set `protobuf_plugin` and `protobuf_runtime`".format(ctx.label))
+        runtimes = [prost_toolchain.protobuf_runtime]
+        if grpcio:
+            if not prost_toolchain.grpcio_plugin or not prost_toolchain.grpcio_runtime:
+                fail("{}: `grpcio` requires the prost toolchain to set `grpcio_plugin` and `grpcio_runtime`".format(ctx.label))
+            runtimes.append(prost_toolchain.grpcio_runtime)
+    else:
+        runtimes = [prost_toolchain.prost_runtime, prost_toolchain.tonic_runtime]
+
+    for prost_runtime in runtimes:
         if not prost_runtime:
             continue
         if rust_common.crate_group_info in prost_runtime:
@@ -263,26 +576,38 @@ def _rust_prost_aspect_impl(target, ctx):
         if RustAnalyzerInfo in proto_dep:
             rust_analyzer_deps.append(proto_dep[RustAnalyzerInfo])
 
-    transform_infos = []
-    for data_target in getattr(ctx.rule.attr, "data", []):
-        if ProstTransformInfo in data_target:
-            transform_infos.append(data_target[ProstTransformInfo])
-
     rust_deps = runtime_deps + direct_deps
+    dep_aliases = {}
     for transform_info in transform_infos:
         rust_deps.extend(transform_info.deps)
+        dep_aliases.update(transform_info.aliases)
+
+    aliases = {proto_dep: dep_aliases[proto_dep.label] for proto_dep in proto_deps if proto_dep.label in dep_aliases}
+
+    # `Any` helpers and wrappers of mapped well-known types are built on `prost_types`.
+    if any([transform_info.wkt_types or transform_info.any_helpers for transform_info in transform_infos]):
+        rust_deps.extend(_dep_variant_infos(prost_toolchain.prost_types))
+
+    if any([transform_info.health_service for transform_info in transform_infos]):
+        if not prost_toolchain.tonic_plugin or not prost_toolchain.tonic_health:
+            fail("{}: `health_service` requires the prost toolchain to set the tonic attributes and `tonic_health`".format(ctx.label))
+        rust_deps.extend(_dep_variant_infos(prost_toolchain.tonic_health))
 
     crate_name = ctx.label.name.replace("-", "_").replace("/", "_")
 
     proto_info = target[ProtoInfo]
 
-    lib_rs, package_info_file = _compile_proto(
+    lib_rs, package_info_file, module_tree, source_map, diagnostics, scaffold, mocks_rs = _compile_proto(
         ctx = ctx,
         crate_name = crate_name,
         proto_info = proto_info,
         transform_infos = transform_infos,
         deps = proto_deps,
         prost_toolchain = prost_toolchain,
+        dep_aliases = dep_aliases,
+        protobuf = protobuf,
+        grpcio = grpcio,
+        connect = connect,
         rustfmt_toolchain = rustfmt_toolchain,
     )
 
@@ -293,14 +618,33 @@ def _rust_prost_aspect_impl(target, ctx):
         src = lib_rs,
         deps = rust_deps,
         edition = RUST_EDITION,
+        aliases = aliases,
     )
 
+    # The mocks are a crate of their own depending on the generated one, so dependents
+    # of the mocks and of the generated crate agree on a single build of the latter.
+    mocks_dep_variant_info = None
+    if mocks_rs:
+        mocks_dep_variant_info = _compile_rust(
+            ctx = ctx,
+            attr = ctx.rule.attr,
+            crate_name = crate_name + "_mocks",
+            src = mocks_rs,
+            deps = rust_deps + [dep_variant_info],
+            edition = RUST_EDITION,
+            aliases = aliases,
+        )
+
     # Always add `test` & `debug_assertions`. See rust-analyzer source code:
     # https://github.com/rust-analyzer/rust-analyzer/blob/2021-11-15/crates/project_model/src/workspace.rs#L529-L531
     cfgs = ["test", "debug_assertions"]
 
     rust_analyzer_info = write_rust_analyzer_spec_file(ctx, ctx.rule.attr, ctx.label, RustAnalyzerInfo(
-        aliases = {},
+        aliases = {
+            proto_dep[RustAnalyzerInfo]: alias
+            for proto_dep, alias in aliases.items()
+            if RustAnalyzerInfo in proto_dep
+        },
         crate = dep_variant_info.crate_info,
         cfgs = cfgs,
         env = dep_variant_info.crate_info.rustc_env,
@@ -317,16 +661,32 @@ def _rust_prost_aspect_impl(target, ctx):
         "rustfmt_checks": depset(),
     }
 
+    # Only the `proto_library` targets of the main repository are validated, as the `deps` of
+    # external ones can't be fixed.
+    validation_outputs = []
+    if prost_toolchain.proto_deps_validation and not target.label.workspace_name:
+        validation_outputs.append(proto_deps_validation(
+            ctx = ctx,
+            proto_deps_info = target[ProtoDepsInfo],
+            analyzer = ctx.executable._proto_deps_analyzer,
+        ))
+
     return [
         ProstProtoInfo(
             dep_variant_info = dep_variant_info,
+            mocks_dep_variant_info = mocks_dep_variant_info,
             transitive_dep_infos = depset(transitive = transitive_deps),
             package_info = package_info_file,
         ),
         rust_analyzer_info,
         OutputGroupInfo(
             rust_generated_srcs = [lib_rs],
+            rust_generated_module_tree = [module_tree] if module_tree else [],
+            rust_generated_source_map = [source_map] if source_map else [],
+            rust_generated_tonic_scaffold = [scaffold] if scaffold else [],
+            rust_prost_diagnostics = [diagnostics],
             proto_descriptor_set = [proto_info.direct_descriptor_set],
+            _validation = validation_outputs,
             **inhibit_output_groups
         ),
     ]
@@ -352,7 +712,22 @@ rust_prost_aspect = aspect(
             executable = True,
             default = Label("//private:protoc_wrapper"),
         ),
+        "_proto_deps_analyzer": attr.label(
+            doc = "The analyzer checking the `deps` of `proto_library` targets against their imports.",
+            cfg = "exec",
+            executable = True,
+            default = Label("//private:proto_deps_analyzer"),
+        ),
+        "_protobuf_process_wrapper": attr.label(
+            doc = "The wrapper script for the rust-protobuf protoc plugin.",
+            cfg = "exec",
+            executable = True,
+            default = Label("//private:protobuf_wrapper"),
+        ),
     } | RUSTC_ATTRS,
+    # The `deps` of `proto_library` targets are checked with the info collected
+    # by `proto_deps_aspect`.
+    required_aspect_providers = [ProtoDepsInfo],
     fragments = ["cpp"],
     toolchains = [
         TOOLCHAIN_TYPE,
@@ -367,24 +742,38 @@ def _rust_prost_library_impl(ctx):
     rust_proto_info = proto_dep[ProstProtoInfo]
     dep_variant_info = rust_proto_info.dep_variant_info
     rust_generated_srcs = proto_dep[OutputGroupInfo].rust_generated_srcs
+    rust_generated_module_tree = proto_dep[OutputGroupInfo].rust_generated_module_tree
+    rust_generated_source_map = proto_dep[OutputGroupInfo].rust_generated_source_map
+    rust_generated_tonic_scaffold = proto_dep[OutputGroupInfo].rust_generated_tonic_scaffold
+    rust_prost_diagnostics = proto_dep[OutputGroupInfo].rust_prost_diagnostics
     proto_descriptor_set = proto_dep[OutputGroupInfo].proto_descriptor_set
 
     prost_toolchain = ctx.toolchains[TOOLCHAIN_TYPE]
 
+    dep_variant_infos = [dep_variant_info]
+    if ctx.attr.mocks:
+        if not rust_proto_info.mocks_dep_variant_info:
+            fail("{}: `mocks` requires `rust_prost_transform.mocks` to be set on {}".format(ctx.label, proto_dep.label))
+        dep_variant_infos.append(rust_proto_info.mocks_dep_variant_info)
+
     transitive = []
     if prost_toolchain.include_transitive_deps:
         transitive = [rust_proto_info.transitive_dep_infos]
 
     return [
-        DefaultInfo(files = depset([dep_variant_info.crate_info.output])),
+        DefaultInfo(files = depset([info.crate_info.output for info in dep_variant_infos])),
         rust_common.crate_group_info(
             dep_variant_infos = depset(
-                [dep_variant_info],
+                dep_variant_infos,
                 transitive = transitive,
             ),
         ),
         OutputGroupInfo(
             rust_generated_srcs = rust_generated_srcs,
+            rust_generated_module_tree = rust_generated_module_tree,
+            rust_generated_source_map = rust_generated_source_map,
+            rust_generated_tonic_scaffold = rust_generated_tonic_scaffold,
+            rust_prost_diagnostics = rust_prost_diagnostics,
             proto_descriptor_set = proto_descriptor_set,
         ),
         RustAnalyzerGroupInfo(deps = [proto_dep[RustAnalyzerInfo]]),
@@ -394,10 +783,18 @@ rust_prost_library = rule(
     doc = "A rule for generating a Rust library using Prost.",
     implementation = _rust_prost_library_impl,
     attrs = {
+        "mocks": attr.bool(
+            doc = (
+                "Whether to also provide the crate with the service mocks of `rust_prost_transform.mocks`, " +
+                "named after the generated crate with a `_mocks` suffix. Meant for a separate `testonly` " +
+                "target used by tests."
+            ),
+            default = False,
+        ),
         "proto": attr.label(
             doc = "A `proto_library` target for which to generate Rust gencode.",
             providers = [ProtoInfo],
-            aspects = [rust_prost_aspect],
+            aspects = [proto_deps_aspect, rust_prost_aspect],
             mandatory = True,
         ),
         "_collect_cc_coverage": attr.label(
@@ -415,6 +812,18 @@ def _rust_prost_toolchain_impl(ctx):
     tonic_attrs = [ctx.attr.tonic_plugin_flag, ctx.attr.tonic_plugin, ctx.attr.tonic_runtime]
     if any(tonic_attrs) and not all(tonic_attrs):
         fail("When one tonic attribute is added, all must be added")
+    if ctx.attr.tonic_compat and not ctx.attr.tonic_plugin:
+        fail("`tonic_compat` requires the tonic attributes to be set")
+    if ctx.attr.tonic_health and not ctx.attr.tonic_plugin:
+        fail("`tonic_health` requires the tonic attributes to be set")
+    if bool(ctx.attr.protobuf_plugin) != bool(ctx.attr.protobuf_runtime):
+        fail("`protobuf_plugin` and `protobuf_runtime` must be set together")
+    if bool(ctx.attr.grpcio_plugin) != bool(ctx.attr.grpcio_runtime):
+        fail("`grpcio_plugin` and `grpcio_runtime` must be set together")
+    if ctx.attr.grpcio_plugin and not ctx.attr.protobuf_plugin:
+        fail("`grpcio_plugin` requires `protobuf_plugin` to be set")
+    if bool(ctx.attr.connect_plugin) != bool(ctx.attr.connect_runtime):
+        fail("`connect_plugin` and `connect_runtime` must be set together")
 
     proto_toolchain = proto_toolchains.find_toolchain(
         ctx,
@@ -422,6 +831,13 @@ def _rust_prost_toolchain_impl(ctx):
         toolchain_type = "@rules_proto//proto:toolchain_type",
     )
 
+    tonic_runtime_version = None
+    if ctx.attr.tonic_compat:
+        tonic_runtime_version = _crate_version(ctx.attr.tonic_runtime, "tonic")
+        if not tonic_runtime_version:
+            # buildifier: disable=print
+            print("WARN: The version of {}'s tonic_runtime `{}` could not be determined, so it isn't validated against tonic_compat. Versions are only known for `tonic` crates generated by crate_universe.".format(ctx.label, ctx.attr.tonic_runtime.label))
+
     if ctx.attr.proto_compiler:
         # buildifier: disable=print
         print("WARN: rust_prost_toolchain's proto_compiler attribute is deprecated. Make sure your rules_proto dependency is at least version 6.0.0 and stop setting proto_compiler")
@@ -430,18 +846,37 @@ def _rust_prost_toolchain_impl(ctx):
         proto_compiler = proto_toolchain.proto_compiler
 
     return [platform_common.ToolchainInfo(
+        connect_plugin = ctx.attr.connect_plugin,
+        connect_plugin_flag = ctx.attr.connect_plugin_flag,
+        connect_runtime = ctx.attr.connect_runtime,
+        grpcio_plugin = ctx.attr.grpcio_plugin,
+        grpcio_plugin_flag = ctx.attr.grpcio_plugin_flag,
+        grpcio_runtime = ctx.attr.grpcio_runtime,
         prost_opts = ctx.attr.prost_opts,
         prost_plugin = ctx.attr.prost_plugin,
         prost_plugin_flag = ctx.attr.prost_plugin_flag,
         prost_runtime = ctx.attr.prost_runtime,
         prost_types = ctx.attr.prost_types,
+        prost_version = ctx.attr.prost_version,
         proto_compiler = proto_compiler,
+        proto_deps_validation = ctx.attr.proto_deps_validation,
+        protobuf_plugin = ctx.attr.protobuf_plugin,
+        protobuf_plugin_flag = ctx.attr.protobuf_plugin_flag,
+        protobuf_runtime = ctx.attr.protobuf_runtime,
         protoc_opts = ctx.fragments.proto.experimental_protoc_opts,
+        size_budget_bytes = ctx.attr.size_budget_bytes,
+        size_budget_types = ctx.attr.size_budget_types,
+        skip_format_threshold = ctx.attr.skip_format_threshold,
+        strict_size_budget = ctx.attr.strict_size_budget,
+        tonic_compat = ctx.attr.tonic_compat,
+        tonic_health = ctx.attr.tonic_health,
         tonic_opts = ctx.attr.tonic_opts,
         tonic_plugin = ctx.attr.tonic_plugin,
         tonic_plugin_flag = ctx.attr.tonic_plugin_flag,
         tonic_runtime = ctx.attr.tonic_runtime,
+        tonic_runtime_version = tonic_runtime_version,
         include_transitive_deps = ctx.attr.include_transitive_deps,
+        incremental_state_dir = ctx.attr.incremental_state_dir,
     )]
 
 rust_prost_toolchain = rule(
@@ -449,10 +884,54 @@ rust_prost_toolchain = rule(
     doc = "Rust Prost toolchain rule.",
     fragments = ["proto"],
     attrs = dict({
+        "connect_plugin": attr.label(
+            doc = (
+                "A Connect-compatible protoc plugin generating Rust clients and servers, used instead of " +
+                "tonic for targets generated with the `connect` transform. Its outputs are merged into the " +
+                "module of the proto package of each service."
+            ),
+            cfg = "exec",
+            executable = True,
+        ),
+        "connect_plugin_flag": attr.string(
+            doc = "Connect plugin flag format. (e.g. `--plugin=protoc-gen-connect=%s`)",
+            default = "--plugin=protoc-gen-connect=%s",
+        ),
+        "connect_runtime": attr.label(
+            doc = "The runtime crates used by the services generated by `connect_plugin`.",
+            providers = [[rust_common.crate_info], [rust_common.crate_group_info]],
+        ),
+        "grpcio_plugin": attr.label(
+            doc = (
+                "The `grpc_rust_plugin` protoc plugin from the `grpcio-compiler` crate, used for targets " +
+                "generated with the `grpcio` transform."
+            ),
+            cfg = "exec",
+            executable = True,
+        ),
+        "grpcio_plugin_flag": attr.string(
+            doc = "grpcio plugin flag format. (e.g. `--plugin=protoc-gen-grpc=%s`)",
+            default = "--plugin=protoc-gen-grpc=%s",
+        ),
+        "grpcio_runtime": attr.label(
+            doc = "The `grpcio` runtime crates (`grpcio` and `futures`) used by generated service stubs.",
+            providers = [[rust_common.crate_info], [rust_common.crate_group_info]],
+        ),
         "include_transitive_deps": attr.bool(
             doc = "Whether to include transitive dependencies. If set to True, all transitive dependencies will directly accessible by the dependent crate.",
             default = False,
         ),
+        "incremental_state_dir": attr.string(
+            doc = (
+                "An absolute path to a directory persisted between builds. When set, protoc is only run for " +
+                "proto packages whose sources or transitive imports changed since the last build and the " +
+                "outputs of other packages are reused. Changes to protoc, the plugins or the generation options " +
+                "regenerate every package, and each configuration keeps its own state. Intended for local " +
+                "development only: the generation " +
+                "actions access the directory and therefore run locally, unsandboxed and uncached " +
+                "(`no-sandbox`, `no-remote` and `no-cache`)."
+            ),
+        ),
         "prost_opts": attr.string_list(
             doc = "Additional options to add to Prost.",
         ),
@@ -476,11 +955,79 @@ rust_prost_toolchain = rule(
             providers = [[rust_common.crate_info], [rust_common.crate_group_info]],
             mandatory = True,
         ),
+        "prost_version": attr.string(
+            doc = (
+                "The `major.minor` version of the prost runtime (e.g. `0.11`). Generated helpers and " +
+                "validation of `prost_opts` are adjusted to match. Defaults to the latest supported version."
+            ),
+        ),
         "proto_compiler": attr.label(
             doc = "The protoc compiler to use. Note that this attribute is deprecated - prefer to use --incompatible_enable_proto_toolchain_resolution.",
             cfg = "exec",
             executable = True,
         ),
+        "proto_deps_validation": attr.bool(
+            doc = (
+                "Whether to run a validation action for every `proto_library` of the main repository a " +
+                "`rust_prost_library` is generated for, failing the build if a source imports a file no direct dependency provides, " +
+                "imports a file without using it, or if a direct dependency is not imported. The failure " +
+                "lists the buildozer commands fixing the `deps`. See also `rust_prost_proto_deps_test`."
+            ),
+            default = False,
+        ),
+        "protobuf_plugin": attr.label(
+            doc = (
+                "The rust-protobuf `protoc-gen-rs` plugin from the `protobuf-codegen` crate, used for targets " +
+                "generated with the `protobuf` transform."
+            ),
+            cfg = "exec",
+            executable = True,
+        ),
+        "protobuf_plugin_flag": attr.string(
+            doc = "rust-protobuf plugin flag format. (e.g. `--plugin=protoc-gen-rs=%s`)",
+            default = "--plugin=protoc-gen-rs=%s",
+        ),
+        "protobuf_runtime": attr.label(
+            doc = "The `protobuf` runtime crate matching the version of `protobuf_plugin`.",
+            providers = [[rust_common.crate_info], [rust_common.crate_group_info]],
+        ),
+        "size_budget_bytes": attr.int(
+            doc = (
+                "The size in bytes of a generated `lib.rs` above which a warning listing the largest proto " +
+                "packages is reported, suggesting the target be split. A value of `0` disables the check."
+            ),
+            default = 0,
+        ),
+        "size_budget_types": attr.int(
+            doc = (
+                "The number of messages and enums in a generated crate above which a warning listing the " +
+                "largest proto packages is reported, suggesting the target be split. A value of `0` disables the check."
+            ),
+            default = 0,
+        ),
+        "skip_format_threshold": attr.int(
+            doc = (
+                "The size in bytes of generated sources above which rustfmt is not run. Modules of larger " +
+                "outputs are marked `#[rustfmt::skip]` instead. A value of `0` always formats the outputs."
+            ),
+            default = 0,
+        ),
+        "strict_size_budget": attr.bool(
+            doc = "Whether exceeding `size_budget_bytes` or `size_budget_types` fails the build instead of warning.",
+            default = False,
+        ),
+        "tonic_compat": attr.string(
+            doc = (
+                "The `major.minor` version of tonic (e.g. `0.12`) the generated code must be compatible with. " +
+                "When set, `tonic_opts` and the version of `tonic_runtime`, or of the `tonic` crate in it if it's a " +
+                "`rust_library_group`, are validated against it. The version is only known for crates generated by " +
+                "`crate_universe`, otherwise a warning is printed."
+            ),
+        ),
+        "tonic_health": attr.label(
+            doc = "The `tonic_health` crate used by crates generated with `health_service` enabled.",
+            providers = [[rust_common.crate_info], [rust_common.crate_group_info]],
+        ),
         "tonic_opts": attr.string_list(
             doc = "Additional options to add to Tonic.",
         ),
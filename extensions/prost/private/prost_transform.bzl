@@ -1,14 +1,29 @@
 """Prost rules."""
 
+load("@rules_proto//proto:defs.bzl", "ProtoInfo")
 load("@rules_rust//rust:defs.bzl", "rust_common")
 
 ProstTransformInfo = provider(
     doc = "Info about transformations to apply to Prost generated source code.",
     fields = {
+        "aliases": "Dict[Label, str]: `proto_library` dependencies mapped to the crate names to refer to them by.",
+        "any_helpers": "bool: Whether to generate `google.protobuf.Any` helpers for messages.",
+        "builder_packages": "List[str]: Proto packages for which message builders are generated.",
+        "connect": "bool: Whether to generate services with the Connect plugin instead of tonic.",
         "deps": "List[DepVariantInfo]: Additional dependencies to compile into the Prost target.",
+        "enum_helpers": "bool: Whether to generate conversion helpers for enums.",
+        "grpcio": "bool: Whether to generate grpcio service stubs along with rust-protobuf messages.",
+        "health_service": "bool: Whether to generate a module registering all services with `tonic_health`.",
+        "mocks": "bool: Whether to generate programmable mocks of tonic services.",
+        "module_tree": "bool: Whether to also generate a `mod.rs` style module hierarchy.",
+        "name_constants": "bool: Whether to generate constants holding the proto names of messages and enums.",
         "prost_opts": "List[str]: Additional prost flags.",
+        "protobuf": "bool: Whether to generate code with rust-protobuf instead of prost.",
+        "protoc_opts": "List[str]: Additional protoc flags, passed verbatim.",
+        "source_map": "bool: Whether to generate a JSON file mapping generated types to their proto definitions.",
         "srcs": "Depset[File]: Additional source files to include in generated Prost source code.",
         "tonic_opts": "List[str]: Additional tonic flags.",
+        "wkt_types": "Dict[str, str]: Well-known proto types mapped to the native Rust representation to use.",
     },
 )
 
@@ -26,10 +41,24 @@ def _rust_prost_transform_impl(ctx):
     # consumers of the `proto_library` target this rule is expected to be passed
     # to.
     return [ProstTransformInfo(
+        aliases = {target.label: alias for target, alias in ctx.attr.aliases.items()},
+        any_helpers = ctx.attr.any_helpers,
+        builder_packages = ctx.attr.builder_packages,
+        connect = ctx.attr.connect,
         deps = deps,
+        enum_helpers = ctx.attr.enum_helpers,
+        grpcio = ctx.attr.grpcio,
+        health_service = ctx.attr.health_service,
+        mocks = ctx.attr.mocks,
+        module_tree = ctx.attr.module_tree,
+        name_constants = ctx.attr.name_constants,
         prost_opts = ctx.attr.prost_opts,
+        protobuf = ctx.attr.protobuf,
+        protoc_opts = ctx.attr.protoc_opts,
+        source_map = ctx.attr.source_map,
         srcs = depset(ctx.files.srcs),
         tonic_opts = ctx.attr.tonic_opts,
+        wkt_types = ctx.attr.wkt_types,
     )]
 
 rust_prost_transform = rule(
@@ -70,13 +99,114 @@ actions with minimal impact to other consumers.
 """,
     implementation = _rust_prost_transform_impl,
     attrs = {
+        "aliases": attr.label_keyed_string_dict(
+            doc = (
+                "Direct `proto_library` dependencies mapped to the crate names the generated code refers to them " +
+                "by. Extern paths into and re-exports of renamed dependencies use the alias."
+            ),
+            providers = [ProtoInfo],
+        ),
+        "any_helpers": attr.bool(
+            doc = (
+                "Whether to generate a `TYPE_URL` constant and `pack_into_any`/`unpack_from_any` functions for " +
+                "every message, along with an `ANY_TYPE_URLS` constant at the root of the crate listing the type " +
+                "URLs of all messages."
+            ),
+            default = False,
+        ),
+        "builder_packages": attr.string_list(
+            doc = (
+                "Proto packages (e.g. `examples.prost.helloworld`) for which each message `Foo` gains a " +
+                "`Foo::builder()` constructor returning a `FooBuilder` with a setter per field."
+            ),
+        ),
+        "connect": attr.bool(
+            doc = (
+                "Whether to generate clients and servers with the Connect plugin instead of tonic, for RPC " +
+                "which is compatible with browsers. The `no_client` and `no_server` entries of `tonic_opts` " +
+                "apply to the Connect plugin as well. Requires `connect_plugin` and `connect_runtime` to be " +
+                "set on the `rust_prost_toolchain`."
+            ),
+            default = False,
+        ),
         "deps": attr.label_list(
             doc = "Additional dependencies to add to the compiled crate.",
             providers = [[rust_common.crate_info], [rust_common.crate_group_info]],
         ),
+        "enum_helpers": attr.bool(
+            doc = (
+                "Whether to generate `FromStr` implementations for all enums and extension traits " +
+                "(`<Message>EnumFields`) with typed `try_<field>` accessors for every enum field."
+            ),
+            default = False,
+        ),
+        "grpcio": attr.bool(
+            doc = (
+                "Whether to generate grpcio clients and services with `grpcio-compiler`. The stubs of each " +
+                "proto file are placed in the module of its messages, which are generated with rust-protobuf " +
+                "as if `protobuf` was set. Requires `grpcio_plugin` and `grpcio_runtime` to be set on the " +
+                "`rust_prost_toolchain`."
+            ),
+            default = False,
+        ),
+        "health_service": attr.bool(
+            doc = (
+                "Whether to generate a `health` module listing the names of all services in `SERVICE_NAMES` " +
+                "along with a `health_service()` function returning a `tonic_health` reporter and service " +
+                "with every service marked as serving. Requires `tonic_health` to be set on the " +
+                "`rust_prost_toolchain`."
+            ),
+            default = False,
+        ),
+        "mocks": attr.bool(
+            doc = (
+                "Whether to generate a `<crate>_mocks` crate with a `Mock<Service>Server` implementing " +
+                "every tonic service with programmable `on_<method>` handlers and a `Mock<Service>Client` " +
+                "calling it in process, in the modules of the packages of the services. The crate depends " +
+                "on the generated one, which is used as is, and is provided by a `rust_prost_library` " +
+                "with `mocks = True`, e.g. a `testonly` one used by tests only."
+            ),
+            default = False,
+        ),
+        "module_tree": attr.bool(
+            doc = (
+                "Whether to also generate the Prost outputs as a `mod.rs` style directory hierarchy with " +
+                "one directory per proto package. The hierarchy is available from the " +
+                "`rust_generated_module_tree` output group and can be embedded in an existing crate with " +
+                "`#[path = \"<dir>/mod.rs\"] mod generated;`."
+            ),
+            default = False,
+        ),
+        "name_constants": attr.bool(
+            doc = (
+                "Whether to generate a `FULL_NAME` constant holding the fully qualified proto name of every " +
+                "message and enum, along with a `VALUE_NAMES` table and `value_name` lookup for enums."
+            ),
+            default = False,
+        ),
         "prost_opts": attr.string_list(
             doc = "Additional options to add to Prost.",
         ),
+        "protobuf": attr.bool(
+            doc = (
+                "Whether to generate code with rust-protobuf (the `protobuf` crate) instead of prost. " +
+                "Requires `protobuf_plugin` and `protobuf_runtime` to be set on the `rust_prost_toolchain`. " +
+                "The generated crate has one module per proto file and can only import proto files " +
+                "which are also generated with rust-protobuf. Other transforms generating helpers are not supported."
+            ),
+            default = False,
+        ),
+        "protoc_opts": attr.string_list(
+            doc = "Additional options to pass to protoc. Each option is passed verbatim and may contain spaces or `=` characters.",
+        ),
+        "source_map": attr.bool(
+            doc = (
+                "Whether to generate a JSON file mapping the Rust path of every generated message, enum and " +
+                "service to the `file:line` of its proto definition. The file is available from the " +
+                "`rust_generated_source_map` output group."
+            ),
+            default = False,
+        ),
         "srcs": attr.label_list(
             doc = "Additional source files to include in generated Prost source code.",
             allow_files = True,
@@ -84,5 +214,15 @@ actions with minimal impact to other consumers.
         "tonic_opts": attr.string_list(
             doc = "Additional options to add to Tonic.",
         ),
+        "wkt_types": attr.string_dict(
+            doc = (
+                "Well-known proto types mapped to the native Rust representation fields of that type should use. " +
+                "Supported mappings are `google.protobuf.Timestamp` to `chrono` (`chrono::DateTime<Utc>`), " +
+                "`time` (`time::OffsetDateTime`) or `std` (`std::time::SystemTime`) and `google.protobuf.Duration` " +
+                "to `chrono` (`chrono::Duration`), `time` (`time::Duration`) or `std` (`std::time::Duration`). " +
+                "Fields use wrapper types from the generated `prost_wkt` module which convert to and from the native " +
+                "type. The `chrono` and `time` crates must be added to `deps` when used."
+            ),
+        ),
     },
 )
@@ -0,0 +1,207 @@
+"""Rules for checking the `deps` of `proto_library` targets against their imports."""
+
+load("@rules_proto//proto:defs.bzl", "ProtoInfo")
+
+ProtoDepsInfo = provider(
+    doc = "Info about the files `proto_library` targets provide to their dependents.",
+    fields = {
+        "deps": "List[ProtoDepsInfo]: The infos of the direct dependencies of the target.",
+        "descriptor_set": "File: The descriptor set of the sources of the target.",
+        "label": "Label: The label of the `proto_library`.",
+        "provided_descriptor_sets": "depset[File]: Descriptor sets of the sources of the target and of the targets it exports.",
+        "transitive_descriptor_sets": "depset[File]: The source descriptor sets of the target and all of its dependencies.",
+        "transitive_sources": "depset[struct(label, descriptor_set)]: The labels and source descriptor sets of the target and all of its dependencies.",
+    },
+)
+
+def _proto_deps_aspect_impl(target, ctx):
+    proto_info = target[ProtoInfo]
+    deps = [dep[ProtoDepsInfo] for dep in getattr(ctx.rule.attr, "deps", []) if ProtoDepsInfo in dep]
+    exports = [dep[ProtoDepsInfo] for dep in getattr(ctx.rule.attr, "exports", []) if ProtoDepsInfo in dep]
+
+    return [ProtoDepsInfo(
+        deps = deps,
+        descriptor_set = proto_info.direct_descriptor_set,
+        label = target.label,
+        provided_descriptor_sets = depset(
+            [proto_info.direct_descriptor_set],
+            transitive = [export.provided_descriptor_sets for export in exports],
+        ),
+        transitive_descriptor_sets = depset(
+            [proto_info.direct_descriptor_set],
+            transitive = [dep.transitive_descriptor_sets for dep in deps + exports],
+        ),
+        transitive_sources = depset(
+            [struct(label = str(target.label), descriptor_set = proto_info.direct_descriptor_set)],
+            transitive = [dep.transitive_sources for dep in deps + exports],
+        ),
+    )]
+
+proto_deps_aspect = aspect(
+    doc = "An aspect collecting the files `proto_library` targets provide to their dependents.",
+    implementation = _proto_deps_aspect_impl,
+    attr_aspects = ["deps", "exports"],
+    required_providers = [ProtoInfo],
+    provides = [ProtoDepsInfo],
+)
+
+def _path(file):
+    return file.path
+
+def _short_path(file):
+    return file.short_path
+
+def _transitive_dep_arg(source):
+    return "--transitive_dep={}={}".format(source.label, source.descriptor_set.path)
+
+def _transitive_dep_short_path_arg(source):
+    return "--transitive_dep={}={}".format(source.label, source.descriptor_set.short_path)
+
+def _proto_deps_analyzer_args(ctx, proto_deps_info, short_paths = False):
+    """Render the arguments of the proto dependency analyzer for a `proto_library`.
+
+    Args:
+        ctx (ctx): The context of the rule or aspect rendering the arguments.
+        proto_deps_info (ProtoDepsInfo): The info of the target to analyze.
+        short_paths (bool, optional): Whether to pass the short paths of files instead of their paths.
+
+    Returns:
+        tuple[Args, depset[File]]: The arguments and the descriptor sets they refer to.
+    """
+    path = _short_path if short_paths else _path
+    args = ctx.actions.args()
+    args.add(proto_deps_info.label, format = "--label=%s")
+    args.add_all([proto_deps_info.descriptor_set], map_each = path, format_each = "--descriptor_set=%s")
+    for dep in proto_deps_info.deps:
+        args.add_all(
+            dep.provided_descriptor_sets,
+            map_each = path,
+            format_each = "--dep={}=%s".format(str(dep.label).replace("%", "%%")),
+        )
+    args.add_all(
+        depset(transitive = [dep.transitive_sources for dep in proto_deps_info.deps]),
+        map_each = _transitive_dep_short_path_arg if short_paths else _transitive_dep_arg,
+    )
+
+    inputs = depset(
+        [proto_deps_info.descriptor_set],
+        transitive = [dep.provided_descriptor_sets for dep in proto_deps_info.deps] +
+                     [dep.transitive_descriptor_sets for dep in proto_deps_info.deps],
+    )
+    return args, inputs
+
+def proto_deps_validation(ctx, proto_deps_info, analyzer):
+    """Declare an action failing if the `deps` of a `proto_library` do not match its imports.
+
+    Args:
+        ctx (ctx): The context of the aspect analyzing the target.
+        proto_deps_info (ProtoDepsInfo): The info of the target to analyze.
+        analyzer (File): The proto dependency analyzer executable.
+
+    Returns:
+        File: The report of the analyzer, to be added to the `_validation` output group.
+    """
+    report = ctx.actions.declare_file("{}.proto_deps_report.txt".format(ctx.label.name))
+    args, inputs = _proto_deps_analyzer_args(ctx, proto_deps_info)
+    args.add(report, format = "--output=%s")
+    args.add("--fail_on_findings")
+
+    ctx.actions.run(
+        mnemonic = "ProstProtoDeps",
+        progress_message = "ProstProtoDeps %{label}",
+        executable = analyzer,
+        inputs = inputs,
+        outputs = [report],
+        arguments = [args],
+    )
+
+    return report
+
+def _rust_prost_proto_deps_test_impl(ctx):
+    # The executable of a test target must be the output of an action in the
+    # rule implementation. This file is simply a symlink to the analyzer.
+    is_windows = ctx.executable._analyzer.extension == "exe"
+    runner = ctx.actions.declare_file("{}{}".format(
+        ctx.label.name,
+        ".exe" if is_windows else "",
+    ))
+    ctx.actions.symlink(
+        output = runner,
+        target_file = ctx.executable._analyzer,
+        is_executable = True,
+    )
+
+    # Tests run from the root of the runfiles of the main repository, from which
+    # the short paths of all runfiles are valid.
+    args, inputs = _proto_deps_analyzer_args(ctx, ctx.attr.proto[ProtoDepsInfo], short_paths = True)
+    args.add("--fail_on_findings")
+    args.set_param_file_format("multiline")
+    args_file = ctx.actions.declare_file("{}.args".format(ctx.label.name))
+    ctx.actions.write(
+        output = args_file,
+        content = args,
+    )
+
+    return [
+        DefaultInfo(
+            files = depset([runner]),
+            runfiles = ctx.runfiles(
+                files = [args_file],
+                transitive_files = inputs,
+            ).merge(ctx.attr._analyzer[DefaultInfo].default_runfiles),
+            executable = runner,
+        ),
+        RunEnvironmentInfo(
+            environment = {
+                "PROTO_DEPS_ANALYZER_ARGS": args_file.short_path,
+            },
+        ),
+    ]
+
+rust_prost_proto_deps_test = rule(
+    doc = """\
+A test checking the `deps` of a `proto_library` against the imports of its sources.
+
+The test fails if a source imports a file which no direct dependency provides, imports a file
+without using any of its definitions, or if a direct dependency provides no imported file. The
+failure lists the buildozer commands adding the missing and removing the unused `deps`. E.g.
+```python
+load("@rules_proto//proto:defs.bzl", "proto_library")
+load("@rules_rust_prost//:defs.bzl", "rust_prost_proto_deps_test")
+
+proto_library(
+    name = "a_proto",
+    srcs = [
+        "a.proto",
+    ],
+    deps = [
+        ":b_proto",
+    ],
+)
+
+rust_prost_proto_deps_test(
+    name = "a_proto_deps_test",
+    proto = ":a_proto",
+)
+```
+
+The same check can run as a validation action of every `rust_prost_library` by setting
+`proto_deps_validation` on the `rust_prost_toolchain`.
+""",
+    implementation = _rust_prost_proto_deps_test_impl,
+    attrs = {
+        "proto": attr.label(
+            doc = "The `proto_library` whose `deps` are checked.",
+            providers = [ProtoInfo],
+            aspects = [proto_deps_aspect],
+            mandatory = True,
+        ),
+        "_analyzer": attr.label(
+            doc = "The proto dependency analyzer.",
+            cfg = "target",
+            executable = True,
+            default = Label("//private:proto_deps_analyzer"),
+        ),
+    },
+    test = True,
+)
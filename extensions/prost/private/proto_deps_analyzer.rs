@@ -0,0 +1,635 @@
+//! Reports the imports of a `proto_library` which are unused or missing from its
+//! `deps`, along with the buildozer commands fixing its `deps`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+/// The environment variable naming a file of arguments, one per line, used when
+/// the analyzer runs as a test.
+const ARGS_FILE_ENV: &str = "PROTO_DEPS_ANALYZER_ARGS";
+
+/// The parsed command-line arguments.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Args {
+    /// The label of the analyzed `proto_library`.
+    label: String,
+
+    /// The descriptor set of the sources of the analyzed `proto_library`.
+    descriptor_set: PathBuf,
+
+    /// The labels of the direct dependencies mapped to the descriptor sets of
+    /// the files they provide.
+    deps: BTreeMap<String, Vec<PathBuf>>,
+
+    /// The labels of all transitive dependencies mapped to the descriptor sets
+    /// of their sources, used to find the dependency providing a missing import.
+    transitive_deps: BTreeMap<String, Vec<PathBuf>>,
+
+    /// The path to write the report to.
+    output: Option<PathBuf>,
+
+    /// Whether to exit with an error if any problems are found.
+    fail_on_findings: bool,
+}
+
+impl Args {
+    /// Parse the given arguments.
+    fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut label = None;
+        let mut descriptor_set = None;
+
+        for arg in args {
+            if arg == "--fail_on_findings" {
+                parsed.fail_on_findings = true;
+                continue;
+            }
+
+            let (flag, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("Unexpected argument `{}`.", arg))?;
+            match flag {
+                "--label" => label = Some(value.to_string()),
+                "--descriptor_set" => descriptor_set = Some(PathBuf::from(value)),
+                "--dep" | "--transitive_dep" => {
+                    let (dep_label, path) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("`{}` must be `<label>=<descriptor set>`.", arg))?;
+                    let deps = if flag == "--dep" {
+                        &mut parsed.deps
+                    } else {
+                        &mut parsed.transitive_deps
+                    };
+                    deps.entry(dep_label.to_string())
+                        .or_default()
+                        .push(PathBuf::from(path));
+                }
+                "--output" => parsed.output = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unexpected argument `{}`.", arg)),
+            }
+        }
+
+        parsed.label = label.ok_or("No `--label` value was found.")?;
+        parsed.descriptor_set = descriptor_set.ok_or("No `--descriptor_set` value was found.")?;
+
+        Ok(parsed)
+    }
+}
+
+/// A problem with the dependencies of a `proto_library`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Finding {
+    /// A source file imports a file which no direct dependency provides.
+    MissingImport {
+        /// The importing source file.
+        file: String,
+
+        /// The imported file.
+        import: String,
+
+        /// The label of a transitive dependency providing the import, if any.
+        provider: Option<String>,
+    },
+
+    /// A source file imports a file without using any of its definitions.
+    UnusedImport {
+        /// The importing source file.
+        file: String,
+
+        /// The imported file.
+        import: String,
+    },
+
+    /// A direct dependency provides no file imported by any source file.
+    UnusedDep {
+        /// The label of the dependency.
+        label: String,
+    },
+}
+
+impl Finding {
+    /// Describe the finding.
+    fn message(&self) -> String {
+        match self {
+            Finding::MissingImport {
+                file,
+                import,
+                provider: Some(provider),
+            } => format!(
+                "`{}` imports `{}` which is provided by `{}` but no direct dependency.",
+                file, import, provider
+            ),
+            Finding::MissingImport {
+                file,
+                import,
+                provider: None,
+            } => format!(
+                "`{}` imports `{}` which is not provided by any dependency.",
+                file, import
+            ),
+            Finding::UnusedImport { file, import } => format!(
+                "`{}` imports `{}` but uses none of its definitions.",
+                file, import
+            ),
+            Finding::UnusedDep { label } => format!(
+                "The dependency `{}` provides no file imported by any source.",
+                label
+            ),
+        }
+    }
+
+    /// The buildozer command fixing the finding for the given target, if any.
+    fn buildozer_command(&self, target: &str) -> Option<String> {
+        match self {
+            Finding::MissingImport {
+                provider: Some(provider),
+                ..
+            } => Some(format!(
+                "buildozer 'add deps {}' {}",
+                buildozer_label(provider),
+                buildozer_label(target)
+            )),
+            Finding::UnusedDep { label } => Some(format!(
+                "buildozer 'remove deps {}' {}",
+                buildozer_label(label),
+                buildozer_label(target)
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Format a label the way it is written in `BUILD` files of the main repository.
+fn buildozer_label(label: &str) -> String {
+    let label = label.strip_prefix('@').unwrap_or(label);
+    let label = label.strip_prefix('@').unwrap_or(label);
+    if label.starts_with("//") {
+        return label.to_string();
+    }
+    format!("@{}", label)
+}
+
+/// The fully qualified names, with a leading `.`, of the types a file defines.
+fn defined_types(file: &FileDescriptorProto) -> BTreeSet<String> {
+    /// Collect the names of a message and its nested types.
+    fn visit(defined: &mut BTreeSet<String>, prefix: &str, message: &DescriptorProto) {
+        let name = format!("{}.{}", prefix, message.name());
+        for enum_type in message.enum_type.iter() {
+            defined.insert(format!("{}.{}", name, enum_type.name()));
+        }
+        for nested_type in message.nested_type.iter() {
+            visit(defined, &name, nested_type);
+        }
+        defined.insert(name);
+    }
+
+    let prefix = if file.package().is_empty() {
+        String::new()
+    } else {
+        format!(".{}", file.package())
+    };
+    let mut defined = BTreeSet::new();
+    for message in file.message_type.iter() {
+        visit(&mut defined, &prefix, message);
+    }
+    for enum_type in file.enum_type.iter() {
+        defined.insert(format!("{}.{}", prefix, enum_type.name()));
+    }
+
+    defined
+}
+
+/// The fully qualified names of the types a file refers to.
+fn used_types(file: &FileDescriptorProto) -> BTreeSet<&str> {
+    /// Collect the types the fields of a message and its nested types refer to.
+    fn visit<'a>(used: &mut BTreeSet<&'a str>, message: &'a DescriptorProto) {
+        for field in message.field.iter().chain(message.extension.iter()) {
+            used.extend(field.type_name.as_deref());
+            used.extend(field.extendee.as_deref());
+        }
+        for nested_type in message.nested_type.iter() {
+            visit(used, nested_type);
+        }
+    }
+
+    let mut used = BTreeSet::new();
+    for message in file.message_type.iter() {
+        visit(&mut used, message);
+    }
+    for field in file.extension.iter() {
+        used.extend(field.type_name.as_deref());
+        used.extend(field.extendee.as_deref());
+    }
+    for method in file
+        .service
+        .iter()
+        .flat_map(|service| service.method.iter())
+    {
+        used.extend([method.input_type(), method.output_type()]);
+    }
+
+    used
+}
+
+/// Returns true if a file defines extensions. Custom options are extensions
+/// whose uses are not visible in descriptors, so such imports are never
+/// reported as unused.
+fn defines_extensions(file: &FileDescriptorProto) -> bool {
+    fn visit(message: &DescriptorProto) -> bool {
+        !message.extension.is_empty() || message.nested_type.iter().any(visit)
+    }
+
+    !file.extension.is_empty() || file.message_type.iter().any(visit)
+}
+
+/// Expand a set of file names with the files they publicly import.
+fn with_public_imports(
+    names: impl IntoIterator<Item = String>,
+    files: &BTreeMap<String, FileDescriptorProto>,
+) -> BTreeSet<String> {
+    let mut expanded = BTreeSet::new();
+    let mut pending = names.into_iter().collect::<Vec<_>>();
+    while let Some(name) = pending.pop() {
+        if let Some(file) = files.get(&name) {
+            pending.extend(
+                file.public_dependency
+                    .iter()
+                    .filter_map(|index| file.dependency.get(*index as usize))
+                    .filter(|dependency| !expanded.contains(*dependency))
+                    .cloned(),
+            );
+        }
+        expanded.insert(name);
+    }
+
+    expanded
+}
+
+/// Find the problems with the dependencies of a `proto_library`.
+///
+/// `deps` maps the labels of the direct dependencies to the names of the files
+/// they provide and `transitive_deps` maps the labels of all dependencies to the
+/// names of their sources. `files` holds every known file by name.
+fn analyze(
+    sources: &[FileDescriptorProto],
+    deps: &BTreeMap<String, BTreeSet<String>>,
+    transitive_deps: &BTreeMap<String, BTreeSet<String>>,
+    files: &BTreeMap<String, FileDescriptorProto>,
+) -> Vec<Finding> {
+    let source_names = sources
+        .iter()
+        .map(|file| file.name().to_string())
+        .collect::<BTreeSet<_>>();
+    let provided_by_deps = deps
+        .iter()
+        .map(|(label, names)| (label, with_public_imports(names.iter().cloned(), files)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut findings = BTreeSet::new();
+    let mut imported = BTreeSet::new();
+    for source in sources {
+        let used = used_types(source);
+        for import in source.dependency.iter() {
+            imported.insert(import.clone());
+            if !source_names.contains(import)
+                && !provided_by_deps
+                    .values()
+                    .any(|names| names.contains(import))
+            {
+                findings.insert(Finding::MissingImport {
+                    file: source.name().to_string(),
+                    import: import.clone(),
+                    provider: transitive_deps
+                        .iter()
+                        .find(|(_, names)| names.contains(import))
+                        .map(|(label, _)| label.clone()),
+                });
+            }
+
+            // Imports whose definitions are unknown cannot be checked.
+            let imported_files = with_public_imports([import.clone()], files);
+            if imported_files.iter().any(|name| !files.contains_key(name)) {
+                continue;
+            }
+            let is_used = imported_files.iter().any(|name| {
+                let file = &files[name];
+                defines_extensions(file)
+                    || defined_types(file)
+                        .iter()
+                        .any(|type_name| used.contains(type_name.as_str()))
+            });
+            if !is_used {
+                findings.insert(Finding::UnusedImport {
+                    file: source.name().to_string(),
+                    import: import.clone(),
+                });
+            }
+        }
+    }
+
+    for (label, names) in provided_by_deps {
+        if names.is_disjoint(&imported) {
+            findings.insert(Finding::UnusedDep {
+                label: label.clone(),
+            });
+        }
+    }
+
+    findings.into_iter().collect()
+}
+
+/// Render the report of the findings for the given target.
+fn render_report(target: &str, findings: &[Finding]) -> String {
+    if findings.is_empty() {
+        return String::new();
+    }
+
+    let mut report = String::new();
+    for finding in findings {
+        writeln!(report, "{}: {}", target, finding.message()).unwrap();
+    }
+
+    let commands = findings
+        .iter()
+        .filter_map(|finding| finding.buildozer_command(target))
+        .collect::<Vec<_>>();
+    if !commands.is_empty() {
+        writeln!(report, "\nTo fix the dependencies of {}, run:", target).unwrap();
+        for command in commands {
+            writeln!(report, "{}", command).unwrap();
+        }
+    }
+
+    report
+}
+
+/// Read the files of a descriptor set.
+fn read_descriptor_set(path: &PathBuf) -> Vec<FileDescriptorProto> {
+    let bytes = fs::read(path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read descriptor set `{}`: {:?}",
+            path.display(),
+            e
+        )
+    });
+    FileDescriptorSet::decode(bytes.as_slice())
+        .unwrap_or_else(|e| {
+            panic!(
+                "Failed to decode descriptor set `{}`: {:?}",
+                path.display(),
+                e
+            )
+        })
+        .file
+}
+
+fn main() {
+    let raw_args = match env::var_os(ARGS_FILE_ENV) {
+        Some(args_file) => fs::read_to_string(&args_file)
+            .expect("Failed to read args file")
+            .lines()
+            .map(String::from)
+            .collect(),
+        None => env::args().skip(1).collect::<Vec<_>>(),
+    };
+    let args = Args::parse_from(raw_args).expect("Failed to parse args");
+
+    let mut files = BTreeMap::new();
+    let mut read_names = |paths: &[PathBuf]| {
+        let mut names = BTreeSet::new();
+        for file in paths.iter().flat_map(read_descriptor_set) {
+            names.insert(file.name().to_string());
+            files.insert(file.name().to_string(), file);
+        }
+        names
+    };
+    let deps = args
+        .deps
+        .iter()
+        .map(|(label, paths)| (label.clone(), read_names(paths)))
+        .collect::<BTreeMap<_, _>>();
+    let transitive_deps = args
+        .transitive_deps
+        .iter()
+        .map(|(label, paths)| (label.clone(), read_names(paths)))
+        .collect::<BTreeMap<_, _>>();
+    let sources = read_descriptor_set(&args.descriptor_set);
+    for file in sources.iter() {
+        files.insert(file.name().to_string(), file.clone());
+    }
+
+    let findings = analyze(&sources, &deps, &transitive_deps, &files);
+    let report = render_report(&args.label, &findings);
+
+    if let Some(output) = args.output {
+        fs::write(output, &report).expect("Failed to write file.");
+    }
+    if !report.is_empty() {
+        eprint!("{}", report);
+        if args.fail_on_findings {
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use prost_types::{FieldDescriptorProto, MethodDescriptorProto, ServiceDescriptorProto};
+
+    fn message(name: &str, field_types: &[&str]) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field: field_types
+                .iter()
+                .map(|type_name| FieldDescriptorProto {
+                    type_name: Some(type_name.to_string()),
+                    ..FieldDescriptorProto::default()
+                })
+                .collect(),
+            ..DescriptorProto::default()
+        }
+    }
+
+    fn file(name: &str, package: &str, dependency: &[&str]) -> FileDescriptorProto {
+        FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some(package.to_string()),
+            dependency: dependency.iter().map(|d| d.to_string()).collect(),
+            ..FileDescriptorProto::default()
+        }
+    }
+
+    fn names(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_test() {
+        let args = Args::parse_from(
+            [
+                "--label=//pkg:a_proto",
+                "--descriptor_set=a.bin",
+                "--dep=//pkg:b_proto=b.bin",
+                "--dep=//pkg:b_proto=b_exports.bin",
+                "--transitive_dep=//pkg:c_proto=c.bin",
+                "--output=report.txt",
+                "--fail_on_findings",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+
+        assert_eq!(
+            args,
+            Args {
+                label: "//pkg:a_proto".to_string(),
+                descriptor_set: PathBuf::from("a.bin"),
+                deps: BTreeMap::from([(
+                    "//pkg:b_proto".to_string(),
+                    vec![PathBuf::from("b.bin"), PathBuf::from("b_exports.bin")]
+                )]),
+                transitive_deps: BTreeMap::from([(
+                    "//pkg:c_proto".to_string(),
+                    vec![PathBuf::from("c.bin")]
+                )]),
+                output: Some(PathBuf::from("report.txt")),
+                fail_on_findings: true,
+            }
+        );
+    }
+
+    #[test]
+    fn defined_and_used_types_test() {
+        let mut outer = message("Outer", &[".b.Value"]);
+        outer.nested_type.push(message("Inner", &[".c.Other"]));
+        let mut a = file("a.proto", "a", &[]);
+        a.message_type.push(outer);
+        a.service.push(ServiceDescriptorProto {
+            name: Some("Service".to_string()),
+            method: vec![MethodDescriptorProto {
+                input_type: Some(".a.Outer".to_string()),
+                output_type: Some(".d.Response".to_string()),
+                ..MethodDescriptorProto::default()
+            }],
+            ..ServiceDescriptorProto::default()
+        });
+
+        assert_eq!(defined_types(&a), names(&[".a.Outer", ".a.Outer.Inner"]));
+        assert_eq!(
+            used_types(&a),
+            BTreeSet::from([".a.Outer", ".b.Value", ".c.Other", ".d.Response"])
+        );
+    }
+
+    #[test]
+    fn analyze_test() {
+        let mut a = file(
+            "a.proto",
+            "a",
+            &["b.proto", "c.proto", "d.proto", "options.proto"],
+        );
+        a.message_type
+            .push(message("A", &[".b.B", ".c.C", ".reexported.R"]));
+
+        let mut b = file("b.proto", "b", &[]);
+        b.message_type.push(message("B", &[]));
+        let mut c = file("c.proto", "c", &[]);
+        c.message_type.push(message("C", &[]));
+        let d = file("d.proto", "d", &[]);
+        let mut options = file("options.proto", "options", &["reexported.proto"]);
+        options.public_dependency.push(0);
+        options.extension.push(FieldDescriptorProto {
+            extendee: Some(".google.protobuf.FieldOptions".to_string()),
+            ..FieldDescriptorProto::default()
+        });
+        let mut reexported = file("reexported.proto", "reexported", &[]);
+        reexported.message_type.push(message("R", &[]));
+
+        let files = [b, c, d, options, reexported]
+            .into_iter()
+            .map(|file| (file.name().to_string(), file))
+            .collect::<BTreeMap<_, _>>();
+        let deps = BTreeMap::from([
+            ("//:b_proto".to_string(), names(&["b.proto"])),
+            ("//:options_proto".to_string(), names(&["options.proto"])),
+            ("//:unused_proto".to_string(), names(&["unused.proto"])),
+        ]);
+        let transitive_deps = BTreeMap::from([
+            ("//:c_proto".to_string(), names(&["c.proto"])),
+            ("//:d_proto".to_string(), names(&["d.proto"])),
+        ]);
+
+        assert_eq!(
+            analyze(&[a], &deps, &transitive_deps, &files),
+            vec![
+                Finding::MissingImport {
+                    file: "a.proto".to_string(),
+                    import: "c.proto".to_string(),
+                    provider: Some("//:c_proto".to_string()),
+                },
+                Finding::MissingImport {
+                    file: "a.proto".to_string(),
+                    import: "d.proto".to_string(),
+                    provider: Some("//:d_proto".to_string()),
+                },
+                Finding::UnusedImport {
+                    file: "a.proto".to_string(),
+                    import: "d.proto".to_string(),
+                },
+                Finding::UnusedDep {
+                    label: "//:unused_proto".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn render_report_test() {
+        let findings = [
+            Finding::MissingImport {
+                file: "a.proto".to_string(),
+                import: "c.proto".to_string(),
+                provider: Some("@@//pkg:c_proto".to_string()),
+            },
+            Finding::MissingImport {
+                file: "a.proto".to_string(),
+                import: "e.proto".to_string(),
+                provider: None,
+            },
+            Finding::UnusedImport {
+                file: "a.proto".to_string(),
+                import: "d.proto".to_string(),
+            },
+            Finding::UnusedDep {
+                label: "@@com_google_protobuf//:any_proto".to_string(),
+            },
+        ];
+
+        assert_eq!(render_report("@@//pkg:a_proto", &[]), "");
+        assert_eq!(
+            render_report("@@//pkg:a_proto", &findings),
+            [
+                "@@//pkg:a_proto: `a.proto` imports `c.proto` which is provided by `@@//pkg:c_proto` but no direct dependency.",
+                "@@//pkg:a_proto: `a.proto` imports `e.proto` which is not provided by any dependency.",
+                "@@//pkg:a_proto: `a.proto` imports `d.proto` but uses none of its definitions.",
+                "@@//pkg:a_proto: The dependency `@@com_google_protobuf//:any_proto` provides no file imported by any source.",
+                "",
+                "To fix the dependencies of @@//pkg:a_proto, run:",
+                "buildozer 'add deps //pkg:c_proto' //pkg:a_proto",
+                "buildozer 'remove deps @com_google_protobuf//:any_proto' //pkg:a_proto",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
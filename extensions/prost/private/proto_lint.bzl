@@ -0,0 +1,122 @@
+"""Rules for checking `proto_library` targets against style conventions."""
+
+load("@rules_proto//proto:defs.bzl", "ProtoInfo")
+
+def _rust_prost_lint_test_impl(ctx):
+    # The executable of a test target must be the output of an action in the
+    # rule implementation. This file is simply a symlink to the linter.
+    is_windows = ctx.executable._linter.extension == "exe"
+    runner = ctx.actions.declare_file("{}{}".format(
+        ctx.label.name,
+        ".exe" if is_windows else "",
+    ))
+    ctx.actions.symlink(
+        output = runner,
+        target_file = ctx.executable._linter,
+        is_executable = True,
+    )
+
+    # Tests run from the root of the runfiles of the main repository, from which
+    # the short paths of all runfiles are valid.
+    descriptor_set = ctx.attr.proto[ProtoInfo].direct_descriptor_set
+    args = [
+        "--label={}".format(ctx.attr.proto.label),
+        "--descriptor_set={}".format(descriptor_set.short_path),
+        "--fail_on_findings",
+    ]
+    files = [descriptor_set]
+    if ctx.file.ruleset:
+        args.append("--ruleset={}".format(ctx.file.ruleset.short_path))
+        files.append(ctx.file.ruleset)
+
+    args_file = ctx.actions.declare_file("{}.args".format(ctx.label.name))
+    ctx.actions.write(
+        output = args_file,
+        content = "\n".join(args),
+    )
+
+    return [
+        DefaultInfo(
+            files = depset([runner]),
+            runfiles = ctx.runfiles(
+                files = [args_file] + files,
+            ).merge(ctx.attr._linter[DefaultInfo].default_runfiles),
+            executable = runner,
+        ),
+        RunEnvironmentInfo(
+            environment = {
+                "PROTO_LINTER_ARGS": args_file.short_path,
+            },
+        ),
+    ]
+
+rust_prost_lint_test = rule(
+    doc = """\
+A test checking the sources of a `proto_library` against naming and style conventions.
+
+The following rules are checked, each of which can be disabled by a TOML `ruleset`:
+
+| Rule | Convention |
+| --- | --- |
+| `PACKAGE_LOWER_SNAKE_CASE` | Packages are dot-separated `lower_snake_case` segments. |
+| `MESSAGE_PASCAL_CASE` | Messages are `PascalCase`. |
+| `FIELD_LOWER_SNAKE_CASE` | Fields and oneofs are `lower_snake_case`. |
+| `ENUM_PASCAL_CASE` | Enums are `PascalCase`. |
+| `ENUM_VALUE_UPPER_SNAKE_CASE` | Enum values are `UPPER_SNAKE_CASE`. |
+| `ENUM_VALUE_PREFIX` | Enum values are prefixed with the `UPPER_SNAKE_CASE` name of their enum. |
+| `ENUM_ZERO_VALUE_SUFFIX` | The zero value of an enum is its prefix followed by `_UNSPECIFIED`. |
+| `SERVICE_SUFFIX` | Services are `PascalCase` and end with `Service`. |
+| `RPC_PASCAL_CASE` | Methods are `PascalCase`. |
+
+E.g.
+```python
+load("@rules_proto//proto:defs.bzl", "proto_library")
+load("@rules_rust_prost//:defs.bzl", "rust_prost_lint_test")
+
+proto_library(
+    name = "a_proto",
+    srcs = [
+        "a.proto",
+    ],
+)
+
+rust_prost_lint_test(
+    name = "a_proto_lint_test",
+    proto = ":a_proto",
+    ruleset = "lint.toml",
+)
+```
+
+with the `lint.toml` ruleset:
+```toml
+# Source files which are not checked.
+ignore_files = ["pkg/legacy.proto"]
+
+[rules]
+ENUM_VALUE_PREFIX = false
+
+[options]
+enum_zero_value_suffix = "_UNKNOWN"
+service_suffix = "Api"
+```
+""",
+    implementation = _rust_prost_lint_test_impl,
+    attrs = {
+        "proto": attr.label(
+            doc = "The `proto_library` whose sources are checked.",
+            providers = [ProtoInfo],
+            mandatory = True,
+        ),
+        "ruleset": attr.label(
+            doc = "A TOML file disabling rules, ignoring files or changing the expected suffixes. All rules are enabled by default.",
+            allow_single_file = [".toml"],
+        ),
+        "_linter": attr.label(
+            doc = "The proto linter.",
+            cfg = "target",
+            executable = True,
+            default = Label("//private:proto_linter"),
+        ),
+    },
+    test = True,
+)
@@ -0,0 +1,924 @@
+//! Checks the sources of a `proto_library` against naming and style conventions,
+//! configured by an optional TOML ruleset.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use heck::{ToShoutySnakeCase, ToSnakeCase, ToUpperCamelCase};
+use prost::Message;
+use prost_types::{DescriptorProto, EnumDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+/// The environment variable naming a file of arguments, one per line, used when
+/// the linter runs as a test.
+const ARGS_FILE_ENV: &str = "PROTO_LINTER_ARGS";
+
+/// The parsed command-line arguments.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Args {
+    /// The label of the linted `proto_library`.
+    label: String,
+
+    /// The descriptor set of the sources of the linted `proto_library`.
+    descriptor_set: PathBuf,
+
+    /// The TOML ruleset configuring the checks.
+    ruleset: Option<PathBuf>,
+
+    /// The path to write the report to.
+    output: Option<PathBuf>,
+
+    /// Whether to exit with an error if any problems are found.
+    fail_on_findings: bool,
+}
+
+impl Args {
+    /// Parse the given arguments.
+    fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut label = None;
+        let mut descriptor_set = None;
+
+        for arg in args {
+            if arg == "--fail_on_findings" {
+                parsed.fail_on_findings = true;
+                continue;
+            }
+
+            let (flag, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("Unexpected argument `{}`.", arg))?;
+            match flag {
+                "--label" => label = Some(value.to_string()),
+                "--descriptor_set" => descriptor_set = Some(PathBuf::from(value)),
+                "--ruleset" => parsed.ruleset = Some(PathBuf::from(value)),
+                "--output" => parsed.output = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unexpected argument `{}`.", arg)),
+            }
+        }
+
+        parsed.label = label.ok_or("No `--label` value was found.")?;
+        parsed.descriptor_set = descriptor_set.ok_or("No `--descriptor_set` value was found.")?;
+
+        Ok(parsed)
+    }
+}
+
+/// A style convention checked by the linter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Rule {
+    /// Packages are dot-separated `lower_snake_case` segments.
+    PackageLowerSnakeCase,
+
+    /// Messages are `PascalCase`.
+    MessagePascalCase,
+
+    /// Fields and oneofs are `lower_snake_case`.
+    FieldLowerSnakeCase,
+
+    /// Enums are `PascalCase`.
+    EnumPascalCase,
+
+    /// Enum values are `UPPER_SNAKE_CASE`.
+    EnumValueUpperSnakeCase,
+
+    /// Enum values are prefixed with the `UPPER_SNAKE_CASE` name of their enum.
+    EnumValuePrefix,
+
+    /// The zero value of an enum is named with the configured suffix.
+    EnumZeroValueSuffix,
+
+    /// Services are `PascalCase` and end with the configured suffix.
+    ServiceSuffix,
+
+    /// Methods are `PascalCase`.
+    RpcPascalCase,
+}
+
+impl Rule {
+    /// All rules, in the order they are reported.
+    const ALL: [Rule; 9] = [
+        Rule::PackageLowerSnakeCase,
+        Rule::MessagePascalCase,
+        Rule::FieldLowerSnakeCase,
+        Rule::EnumPascalCase,
+        Rule::EnumValueUpperSnakeCase,
+        Rule::EnumValuePrefix,
+        Rule::EnumZeroValueSuffix,
+        Rule::ServiceSuffix,
+        Rule::RpcPascalCase,
+    ];
+
+    /// The name of the rule in rulesets and reports.
+    fn id(&self) -> &'static str {
+        match self {
+            Rule::PackageLowerSnakeCase => "PACKAGE_LOWER_SNAKE_CASE",
+            Rule::MessagePascalCase => "MESSAGE_PASCAL_CASE",
+            Rule::FieldLowerSnakeCase => "FIELD_LOWER_SNAKE_CASE",
+            Rule::EnumPascalCase => "ENUM_PASCAL_CASE",
+            Rule::EnumValueUpperSnakeCase => "ENUM_VALUE_UPPER_SNAKE_CASE",
+            Rule::EnumValuePrefix => "ENUM_VALUE_PREFIX",
+            Rule::EnumZeroValueSuffix => "ENUM_ZERO_VALUE_SUFFIX",
+            Rule::ServiceSuffix => "SERVICE_SUFFIX",
+            Rule::RpcPascalCase => "RPC_PASCAL_CASE",
+        }
+    }
+}
+
+/// A value of the supported subset of TOML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TomlValue {
+    /// A `true` or `false` value.
+    Bool(bool),
+
+    /// A basic string.
+    String(String),
+
+    /// An array of basic strings.
+    Array(Vec<String>),
+}
+
+/// Parse a TOML document made of tables of booleans, basic strings and arrays of
+/// basic strings, which is all rulesets use. Keys are returned prefixed with the
+/// name of their table, e.g. `rules.ENUM_VALUE_PREFIX`.
+fn parse_toml(content: &str) -> Result<BTreeMap<String, TomlValue>, String> {
+    /// Remove a comment from the end of a line.
+    fn strip_comment(line: &str) -> &str {
+        let mut in_string = false;
+        let mut escaped = false;
+        for (index, c) in line.char_indices() {
+            match c {
+                '\\' if in_string => {
+                    escaped = !escaped;
+                    continue;
+                }
+                '"' if !escaped => in_string = !in_string,
+                '#' if !in_string => return &line[..index],
+                _ => {}
+            }
+            escaped = false;
+        }
+        line
+    }
+
+    /// Parse a basic string at the start of the input, returning it along with
+    /// the rest of the input.
+    fn parse_string(input: &str) -> Result<(String, &str), String> {
+        let mut chars = input
+            .strip_prefix('"')
+            .ok_or_else(|| format!("Expected a string at `{}`.", input))?
+            .char_indices();
+        let mut value = String::new();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => return Ok((value, &input[index + 2..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    other => return Err(format!("Unsupported escape `\\{:?}`.", other)),
+                },
+                _ => value.push(c),
+            }
+        }
+        Err(format!("Unterminated string `{}`.", input))
+    }
+
+    /// Parse a complete value.
+    fn parse_value(input: &str) -> Result<TomlValue, String> {
+        match input {
+            "true" => return Ok(TomlValue::Bool(true)),
+            "false" => return Ok(TomlValue::Bool(false)),
+            _ => {}
+        }
+
+        if let Some(mut rest) = input.strip_prefix('[') {
+            let mut values = Vec::new();
+            loop {
+                rest = rest.trim_start();
+                if let Some(after) = rest.strip_prefix(']') {
+                    if !after.trim().is_empty() {
+                        return Err(format!("Unexpected `{}` after an array.", after.trim()));
+                    }
+                    return Ok(TomlValue::Array(values));
+                }
+                let (value, after) = parse_string(rest)?;
+                values.push(value);
+                rest = after.trim_start();
+                rest = rest.strip_prefix(',').unwrap_or(rest);
+            }
+        }
+
+        let (value, rest) = parse_string(input)?;
+        if !rest.trim().is_empty() {
+            return Err(format!("Unexpected `{}` after a string.", rest.trim()));
+        }
+        Ok(TomlValue::String(value))
+    }
+
+    let mut values = BTreeMap::new();
+    let mut table = String::new();
+    let mut lines = content.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let with_line = |e: String| format!("Line {}: {}", index + 1, e);
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            table = name.trim().to_string();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| with_line(format!("Expected `key = value`, found `{}`.", line)))?;
+        let mut value = value.trim().to_string();
+
+        // Arrays may span multiple lines.
+        if value.starts_with('[') {
+            while !strip_comment(&value).trim_end().ends_with(']') {
+                let (_, next) = lines
+                    .next()
+                    .ok_or_else(|| with_line("Unterminated array.".to_string()))?;
+                value.push(' ');
+                value.push_str(strip_comment(next).trim());
+            }
+        }
+
+        let key = key.trim().trim_matches('"');
+        let key = if table.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", table, key)
+        };
+        let value = parse_value(strip_comment(&value).trim()).map_err(with_line)?;
+        if values.insert(key.clone(), value).is_some() {
+            return Err(with_line(format!("Duplicate key `{}`.", key)));
+        }
+    }
+
+    Ok(values)
+}
+
+/// The configuration of the checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Ruleset {
+    /// The enabled rules.
+    rules: BTreeSet<Rule>,
+
+    /// The suffix of the names of the zero values of enums.
+    enum_zero_value_suffix: String,
+
+    /// The suffix of the names of services.
+    service_suffix: String,
+
+    /// The names of source files which are not checked.
+    ignore_files: BTreeSet<String>,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Ruleset {
+            rules: Rule::ALL.into_iter().collect(),
+            enum_zero_value_suffix: "_UNSPECIFIED".to_string(),
+            service_suffix: "Service".to_string(),
+            ignore_files: BTreeSet::new(),
+        }
+    }
+}
+
+impl Ruleset {
+    /// Parse a TOML ruleset, e.g.
+    ///
+    /// ```toml
+    /// ignore_files = ["legacy/v1.proto"]
+    ///
+    /// [rules]
+    /// ENUM_VALUE_PREFIX = false
+    ///
+    /// [options]
+    /// enum_zero_value_suffix = "_UNKNOWN"
+    /// service_suffix = "Api"
+    /// ```
+    ///
+    /// Rules which are not listed are enabled.
+    fn from_toml(content: &str) -> Result<Self, String> {
+        let mut ruleset = Ruleset::default();
+        for (key, value) in parse_toml(content)? {
+            match (key.as_str(), value) {
+                ("ignore_files", TomlValue::Array(files)) => ruleset.ignore_files.extend(files),
+                ("options.enum_zero_value_suffix", TomlValue::String(suffix)) => {
+                    ruleset.enum_zero_value_suffix = suffix
+                }
+                ("options.service_suffix", TomlValue::String(suffix)) => {
+                    ruleset.service_suffix = suffix
+                }
+                (key, TomlValue::Bool(enabled)) if key.starts_with("rules.") => {
+                    let id = &key["rules.".len()..];
+                    let rule = Rule::ALL
+                        .into_iter()
+                        .find(|rule| rule.id() == id)
+                        .ok_or_else(|| format!("Unknown rule `{}`.", id))?;
+                    if enabled {
+                        ruleset.rules.insert(rule);
+                    } else {
+                        ruleset.rules.remove(&rule);
+                    }
+                }
+                (key, value) => {
+                    return Err(format!("Unexpected ruleset entry `{} = {:?}`.", key, value))
+                }
+            }
+        }
+
+        Ok(ruleset)
+    }
+}
+
+/// A violation of a rule.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Finding {
+    /// The source file defining the element.
+    file: String,
+
+    /// The fully qualified name of the element.
+    element: String,
+
+    /// The violated rule.
+    rule: Rule,
+
+    /// The description of the violation.
+    message: String,
+}
+
+/// Returns true if a name is `lower_snake_case`.
+fn is_lower_snake_case(name: &str) -> bool {
+    !name.is_empty() && name == name.to_snake_case()
+}
+
+/// Returns true if a name is `UPPER_SNAKE_CASE`.
+fn is_upper_snake_case(name: &str) -> bool {
+    !name.is_empty() && name == name.to_shouty_snake_case()
+}
+
+/// Returns true if a name is `PascalCase`.
+fn is_pascal_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase()) && name.chars().all(char::is_alphanumeric)
+}
+
+/// Collects the findings of a file.
+struct Linter<'a> {
+    /// The configuration of the checks.
+    ruleset: &'a Ruleset,
+
+    /// The name of the checked file.
+    file: &'a str,
+
+    /// The findings so far.
+    findings: Vec<Finding>,
+}
+
+impl Linter<'_> {
+    /// Record a finding if the rule is enabled and the check failed.
+    fn check(&mut self, rule: Rule, element: &str, passed: bool, message: impl FnOnce() -> String) {
+        if !passed && self.ruleset.rules.contains(&rule) {
+            self.findings.push(Finding {
+                file: self.file.to_string(),
+                element: element.to_string(),
+                rule,
+                message: message(),
+            });
+        }
+    }
+
+    /// Check a message along with its fields and nested types.
+    fn message(&mut self, prefix: &str, message: &DescriptorProto) {
+        let name = qualify(prefix, message.name());
+
+        // Map entries are generated and follow the naming of their field.
+        if !message
+            .options
+            .as_ref()
+            .is_some_and(|options| options.map_entry())
+        {
+            self.check(
+                Rule::MessagePascalCase,
+                &name,
+                is_pascal_case(message.name()),
+                || {
+                    format!(
+                        "Message `{}` should be PascalCase, e.g. `{}`.",
+                        message.name(),
+                        message.name().to_upper_camel_case()
+                    )
+                },
+            );
+        }
+
+        let fields = message.field.iter().map(|field| field.name());
+        let oneofs = message.oneof_decl.iter().map(|oneof| oneof.name());
+        for field in fields.chain(oneofs) {
+            self.check(
+                Rule::FieldLowerSnakeCase,
+                &qualify(&name, field),
+                is_lower_snake_case(field),
+                || {
+                    format!(
+                        "Field `{}` should be lower_snake_case, e.g. `{}`.",
+                        field,
+                        field.to_snake_case()
+                    )
+                },
+            );
+        }
+
+        for nested_type in message.nested_type.iter() {
+            self.message(&name, nested_type);
+        }
+        for enum_type in message.enum_type.iter() {
+            self.enum_type(&name, enum_type);
+        }
+    }
+
+    /// Check an enum along with its values.
+    fn enum_type(&mut self, prefix: &str, enum_type: &EnumDescriptorProto) {
+        let name = qualify(prefix, enum_type.name());
+        self.check(
+            Rule::EnumPascalCase,
+            &name,
+            is_pascal_case(enum_type.name()),
+            || {
+                format!(
+                    "Enum `{}` should be PascalCase, e.g. `{}`.",
+                    enum_type.name(),
+                    enum_type.name().to_upper_camel_case()
+                )
+            },
+        );
+
+        let value_prefix = format!("{}_", enum_type.name().to_shouty_snake_case());
+        for value in enum_type.value.iter() {
+            let value_name = qualify(prefix, value.name());
+            self.check(
+                Rule::EnumValueUpperSnakeCase,
+                &value_name,
+                is_upper_snake_case(value.name()),
+                || {
+                    format!(
+                        "Enum value `{}` should be UPPER_SNAKE_CASE, e.g. `{}`.",
+                        value.name(),
+                        value.name().to_shouty_snake_case()
+                    )
+                },
+            );
+            self.check(
+                Rule::EnumValuePrefix,
+                &value_name,
+                value.name().starts_with(&value_prefix),
+                || {
+                    format!(
+                        "Enum value `{}` should be prefixed with `{}`.",
+                        value.name(),
+                        value_prefix
+                    )
+                },
+            );
+        }
+
+        let expected_zero_value = format!(
+            "{}{}",
+            enum_type.name().to_shouty_snake_case(),
+            self.ruleset.enum_zero_value_suffix
+        );
+        let zero_value = enum_type.value.iter().find(|value| value.number() == 0);
+        self.check(
+            Rule::EnumZeroValueSuffix,
+            &name,
+            zero_value.is_some_and(|value| value.name() == expected_zero_value),
+            || match zero_value {
+                Some(value) => format!(
+                    "The zero value `{}` of enum `{}` should be named `{}`.",
+                    value.name(),
+                    enum_type.name(),
+                    expected_zero_value
+                ),
+                None => format!(
+                    "Enum `{}` should have a zero value named `{}`.",
+                    enum_type.name(),
+                    expected_zero_value
+                ),
+            },
+        );
+    }
+
+    /// Check all definitions of a file.
+    fn file(mut self, file: &FileDescriptorProto) -> Vec<Finding> {
+        let package = file.package();
+        self.check(
+            Rule::PackageLowerSnakeCase,
+            package,
+            package.split('.').all(is_lower_snake_case),
+            || {
+                if package.is_empty() {
+                    "The file should declare a package.".to_string()
+                } else {
+                    format!(
+                        "Package `{}` should be dot-separated lower_snake_case, e.g. `{}`.",
+                        package,
+                        package
+                            .split('.')
+                            .map(|segment| segment.to_snake_case())
+                            .collect::<Vec<_>>()
+                            .join(".")
+                    )
+                }
+            },
+        );
+
+        for message in file.message_type.iter() {
+            self.message(package, message);
+        }
+        for enum_type in file.enum_type.iter() {
+            self.enum_type(package, enum_type);
+        }
+
+        let suffix = self.ruleset.service_suffix.clone();
+        for service in file.service.iter() {
+            let name = qualify(package, service.name());
+            self.check(
+                Rule::ServiceSuffix,
+                &name,
+                is_pascal_case(service.name()) && service.name().ends_with(&suffix),
+                || {
+                    format!(
+                        "Service `{}` should be PascalCase and end with `{}`.",
+                        service.name(),
+                        suffix
+                    )
+                },
+            );
+
+            for method in service.method.iter() {
+                self.check(
+                    Rule::RpcPascalCase,
+                    &qualify(&name, method.name()),
+                    is_pascal_case(method.name()),
+                    || {
+                        format!(
+                            "Method `{}` should be PascalCase, e.g. `{}`.",
+                            method.name(),
+                            method.name().to_upper_camel_case()
+                        )
+                    },
+                );
+            }
+        }
+
+        self.findings
+    }
+}
+
+/// Join a name to the scope it is defined in.
+fn qualify(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// Find the violations of the ruleset in the given files.
+fn lint(files: &[FileDescriptorProto], ruleset: &Ruleset) -> Vec<Finding> {
+    let mut findings = files
+        .iter()
+        .filter(|file| !ruleset.ignore_files.contains(file.name()))
+        .flat_map(|file| {
+            Linter {
+                ruleset,
+                file: file.name(),
+                findings: Vec::new(),
+            }
+            .file(file)
+        })
+        .collect::<Vec<_>>();
+    findings.sort();
+    findings.dedup();
+
+    findings
+}
+
+/// Render the report of the findings for the given target.
+fn render_report(target: &str, findings: &[Finding]) -> String {
+    let mut report = String::new();
+    for finding in findings {
+        writeln!(
+            report,
+            "{}: {}: `{}`: {} ({})",
+            target,
+            finding.file,
+            finding.element,
+            finding.message,
+            finding.rule.id()
+        )
+        .unwrap();
+    }
+
+    report
+}
+
+fn main() {
+    let raw_args = match env::var_os(ARGS_FILE_ENV) {
+        Some(args_file) => fs::read_to_string(&args_file)
+            .expect("Failed to read args file")
+            .lines()
+            .map(String::from)
+            .collect(),
+        None => env::args().skip(1).collect::<Vec<_>>(),
+    };
+    let args = Args::parse_from(raw_args).expect("Failed to parse args");
+
+    let ruleset = match &args.ruleset {
+        Some(path) => {
+            let content = fs::read_to_string(path).expect("Failed to read ruleset");
+            Ruleset::from_toml(&content)
+                .unwrap_or_else(|e| panic!("Failed to parse ruleset `{}`: {}", path.display(), e))
+        }
+        None => Ruleset::default(),
+    };
+
+    let bytes = fs::read(&args.descriptor_set).expect("Failed to read descriptor set");
+    let files = FileDescriptorSet::decode(bytes.as_slice())
+        .expect("Failed to decode descriptor set")
+        .file;
+
+    let findings = lint(&files, &ruleset);
+    let report = render_report(&args.label, &findings);
+
+    if let Some(output) = args.output {
+        fs::write(output, &report).expect("Failed to write file.");
+    }
+    if !report.is_empty() {
+        eprint!("{}", report);
+        if args.fail_on_findings {
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use prost_types::{
+        EnumValueDescriptorProto, FieldDescriptorProto, MessageOptions, MethodDescriptorProto,
+        OneofDescriptorProto, ServiceDescriptorProto,
+    };
+
+    fn enum_type(name: &str, values: &[(&str, i32)]) -> EnumDescriptorProto {
+        EnumDescriptorProto {
+            name: Some(name.to_string()),
+            value: values
+                .iter()
+                .map(|(name, number)| EnumValueDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(*number),
+                    ..EnumValueDescriptorProto::default()
+                })
+                .collect(),
+            ..EnumDescriptorProto::default()
+        }
+    }
+
+    fn message(name: &str, fields: &[&str]) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field: fields
+                .iter()
+                .map(|name| FieldDescriptorProto {
+                    name: Some(name.to_string()),
+                    ..FieldDescriptorProto::default()
+                })
+                .collect(),
+            ..DescriptorProto::default()
+        }
+    }
+
+    fn rules(findings: &[Finding]) -> Vec<(&str, Rule)> {
+        findings
+            .iter()
+            .map(|finding| (finding.element.as_str(), finding.rule))
+            .collect()
+    }
+
+    #[test]
+    fn parse_args_test() {
+        let args = Args::parse_from(
+            [
+                "--label=//pkg:a_proto",
+                "--descriptor_set=a.bin",
+                "--ruleset=lint.toml",
+                "--output=report.txt",
+                "--fail_on_findings",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+
+        assert_eq!(
+            args,
+            Args {
+                label: "//pkg:a_proto".to_string(),
+                descriptor_set: PathBuf::from("a.bin"),
+                ruleset: Some(PathBuf::from("lint.toml")),
+                output: Some(PathBuf::from("report.txt")),
+                fail_on_findings: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_toml_test() {
+        let content = r#"
+            # Files kept for compatibility.
+            ignore_files = [
+                "legacy/v1.proto", # The first version.
+                "legacy/\"quoted\".proto",
+            ]
+
+            [rules]
+            ENUM_VALUE_PREFIX = false
+            SERVICE_SUFFIX = true
+
+            [options]
+            service_suffix = "Api # not a comment"
+        "#;
+
+        assert_eq!(
+            parse_toml(content).unwrap(),
+            BTreeMap::from([
+                (
+                    "ignore_files".to_string(),
+                    TomlValue::Array(vec![
+                        "legacy/v1.proto".to_string(),
+                        "legacy/\"quoted\".proto".to_string(),
+                    ])
+                ),
+                (
+                    "options.service_suffix".to_string(),
+                    TomlValue::String("Api # not a comment".to_string())
+                ),
+                (
+                    "rules.ENUM_VALUE_PREFIX".to_string(),
+                    TomlValue::Bool(false)
+                ),
+                ("rules.SERVICE_SUFFIX".to_string(), TomlValue::Bool(true)),
+            ])
+        );
+
+        assert_eq!(
+            parse_toml("[rules]\nA = true\nA = false\n"),
+            Err("Line 3: Duplicate key `rules.A`.".to_string())
+        );
+        assert_eq!(
+            parse_toml("level = 3\n"),
+            Err("Line 1: Expected a string at `3`.".to_string())
+        );
+    }
+
+    #[test]
+    fn ruleset_test() {
+        let ruleset = Ruleset::from_toml(
+            r#"
+            ignore_files = ["legacy.proto"]
+
+            [rules]
+            ENUM_VALUE_PREFIX = false
+
+            [options]
+            enum_zero_value_suffix = "_UNKNOWN"
+            "#,
+        )
+        .unwrap();
+
+        let mut expected = Ruleset {
+            enum_zero_value_suffix: "_UNKNOWN".to_string(),
+            ignore_files: BTreeSet::from(["legacy.proto".to_string()]),
+            ..Ruleset::default()
+        };
+        expected.rules.remove(&Rule::EnumValuePrefix);
+        assert_eq!(ruleset, expected);
+
+        assert_eq!(
+            Ruleset::from_toml("[rules]\nNO_SUCH_RULE = false\n"),
+            Err("Unknown rule `NO_SUCH_RULE`.".to_string())
+        );
+        assert_eq!(
+            Ruleset::from_toml("[options]\nservice_suffix = true\n"),
+            Err("Unexpected ruleset entry `options.service_suffix = Bool(true)`.".to_string())
+        );
+    }
+
+    #[test]
+    fn lint_test() {
+        let mut outer = message("outer_message", &["goodField", "good_field"]);
+        outer.oneof_decl.push(OneofDescriptorProto {
+            name: Some("Choice".to_string()),
+            ..OneofDescriptorProto::default()
+        });
+        let mut map_entry = message("labels_entry", &["key", "value"]);
+        map_entry.options = Some(MessageOptions {
+            map_entry: Some(true),
+            ..MessageOptions::default()
+        });
+        outer.nested_type.push(map_entry);
+        outer
+            .enum_type
+            .push(enum_type("Kind", &[("KIND_UNSPECIFIED", 0)]));
+
+        let files = [FileDescriptorProto {
+            name: Some("a.proto".to_string()),
+            package: Some("my.Package".to_string()),
+            message_type: vec![outer],
+            enum_type: vec![
+                enum_type("Status", &[("STATUS_UNKNOWN", 0), ("Active", 1)]),
+                enum_type("Color", &[("COLOR_RED", 1)]),
+            ],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Greeter".to_string()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("say_hello".to_string()),
+                    ..MethodDescriptorProto::default()
+                }],
+                ..ServiceDescriptorProto::default()
+            }],
+            ..FileDescriptorProto::default()
+        }];
+
+        let findings = lint(&files, &Ruleset::default());
+        assert_eq!(
+            rules(&findings),
+            [
+                ("my.Package", Rule::PackageLowerSnakeCase),
+                ("my.Package.Active", Rule::EnumValueUpperSnakeCase),
+                ("my.Package.Active", Rule::EnumValuePrefix),
+                ("my.Package.Color", Rule::EnumZeroValueSuffix),
+                ("my.Package.Greeter", Rule::ServiceSuffix),
+                ("my.Package.Greeter.say_hello", Rule::RpcPascalCase),
+                ("my.Package.Status", Rule::EnumZeroValueSuffix),
+                ("my.Package.outer_message", Rule::MessagePascalCase),
+                ("my.Package.outer_message.Choice", Rule::FieldLowerSnakeCase),
+                (
+                    "my.Package.outer_message.goodField",
+                    Rule::FieldLowerSnakeCase
+                ),
+            ]
+        );
+
+        assert_eq!(
+            render_report("//pkg:a_proto", &findings[3..5]),
+            [
+                "//pkg:a_proto: a.proto: `my.Package.Color`: Enum `Color` should have a zero value named `COLOR_UNSPECIFIED`. (ENUM_ZERO_VALUE_SUFFIX)",
+                "//pkg:a_proto: a.proto: `my.Package.Greeter`: Service `Greeter` should be PascalCase and end with `Service`. (SERVICE_SUFFIX)",
+                "",
+            ]
+            .join("\n")
+        );
+
+        let ruleset = Ruleset::from_toml(
+            r#"
+            [rules]
+            PACKAGE_LOWER_SNAKE_CASE = false
+            ENUM_VALUE_UPPER_SNAKE_CASE = false
+            ENUM_VALUE_PREFIX = false
+            RPC_PASCAL_CASE = false
+            MESSAGE_PASCAL_CASE = false
+            FIELD_LOWER_SNAKE_CASE = false
+
+            [options]
+            enum_zero_value_suffix = "_UNKNOWN"
+            service_suffix = "er"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules(&lint(&files, &ruleset)),
+            [
+                ("my.Package.Color", Rule::EnumZeroValueSuffix),
+                ("my.Package.outer_message.Kind", Rule::EnumZeroValueSuffix),
+            ]
+        );
+
+        let ignored = Ruleset {
+            ignore_files: BTreeSet::from(["a.proto".to_string()]),
+            ..Ruleset::default()
+        };
+        assert_eq!(lint(&files, &ignored), Vec::new());
+    }
+}
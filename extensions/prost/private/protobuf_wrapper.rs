@@ -0,0 +1,587 @@
+//! A process wrapper for running a Protobuf compiler configured for rust-protobuf output in a Bazel rule.
+//!
+//! Arguments are parsed and the generated crate is assembled by `protoc_wrapper_lib`, the same
+//! code as the prost wrapper, so crates generated by either can depend on each other's package info.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use prost_types::{DescriptorProto, FileDescriptorSet};
+use protoc_wrapper_lib::args::{os_flag, Args};
+use protoc_wrapper_lib::diagnostics::{Diagnostic, Diagnostics};
+use protoc_wrapper_lib::{
+    generate_lib_rs, get_and_create_output_dir, invoke_protoc, is_keyword,
+    parse_descriptor_set_file, read_additional_srcs, render_package_info, run_rustfmt,
+    write_module_tree, Module, ProtoPath, RustModulePath,
+};
+
+/// The doc comment rust-protobuf starts each generated file with, naming the proto file
+/// it was generated from.
+const GENERATED_FILE_DOC_PREFIX: &str = "//! Generated file from `";
+
+/// The suffix of the file grpcio-compiler generates next to the rust-protobuf output
+/// of each proto file with services.
+const GRPCIO_FILE_SUFFIX: &str = "_grpc.rs";
+
+/// The proto files whose types rust-protobuf maps to modules of the `protobuf` runtime.
+const RUNTIME_PROTO_FILE_PREFIX: &str = "google/protobuf/";
+
+/// Collect the flags of the prost wrapper which have no rust-protobuf equivalent.
+fn unsupported_flags(args: &Args) -> Vec<&'static str> {
+    [
+        ("--is_tonic", args.is_tonic),
+        ("--is_connect", args.is_connect),
+        ("--enum_helpers", args.enum_helpers),
+        ("--any_helpers", args.any_helpers),
+        ("--name_constants", args.name_constants),
+        ("--health_service", args.health_service),
+        ("--mocks_output", args.mocks_output.is_some()),
+        ("--scaffold_output", args.scaffold_output.is_some()),
+        ("--builder_package", !args.builder_packages.is_empty()),
+        ("--wkt_mapping", !args.wkt_mappings.is_empty()),
+        (
+            "--incremental_state_dir",
+            args.incremental_state_dir.is_some(),
+        ),
+        ("--source_map_output", args.source_map_output.is_some()),
+        ("--size_budget_bytes", args.size_budget.max_bytes.is_some()),
+        ("--size_budget_types", args.size_budget.max_types.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(flag, set)| set.then_some(flag))
+    .collect()
+}
+
+/// Returns true if the argument configures the prost, tonic or Connect plugins.
+fn is_prost_opt(arg: &OsString) -> bool {
+    arg.to_str().is_some_and(|arg| {
+        arg.starts_with("--prost_opt=")
+            || arg.starts_with("--tonic_opt=")
+            || arg.starts_with("--connect_opt=")
+    })
+}
+
+/// Locate rust-protobuf outputs in the protoc output directory, keyed by the proto
+/// file each was generated from. The `mod.rs` file rust-protobuf may write is not
+/// an output of any proto file and is skipped.
+fn find_generated_files(out_dir: &Path) -> Result<BTreeMap<String, PathBuf>, String> {
+    let mut generated_files = BTreeMap::new();
+    for entry in fs::read_dir(out_dir).map_err(|e| format!("Failed to read directory: {e}"))? {
+        let path = entry
+            .map_err(|e| format!("Failed to read entry: {e}"))?
+            .path();
+        if path.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read file `{}`: {}", path.display(), e))?;
+        let proto_file = content.lines().find_map(|line| {
+            line.strip_prefix(GENERATED_FILE_DOC_PREFIX)?
+                .strip_suffix('`')
+        });
+        if let Some(proto_file) = proto_file {
+            generated_files.insert(proto_file.to_string(), path);
+        }
+    }
+
+    Ok(generated_files)
+}
+
+/// Append the grpcio service stubs of a proto file to the module holding its messages.
+/// The stubs start with inner attributes which are only allowed at the start of a
+/// module so the generated header is dropped.
+fn merge_grpcio_stubs(messages: String, stubs: &str) -> String {
+    let body = stubs
+        .lines()
+        .skip_while(|line| {
+            let line = line.trim();
+            line.is_empty() || line.starts_with("//") || line.starts_with("#![")
+        })
+        .collect::<Vec<_>>();
+
+    format!("{}\n{}\n", messages, body.join("\n"))
+}
+
+/// The name rust-protobuf gives the type generated for a message, enum or oneof.
+fn type_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let name = match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+        None => String::new(),
+    };
+    if is_keyword(&name) {
+        return format!("{name}_");
+    }
+
+    name
+}
+
+/// The name of the module rust-protobuf places the types nested in a message in.
+fn nested_module_name(name: &str) -> String {
+    let mut module = String::new();
+    let mut previous_lowercase = false;
+    for c in name.chars() {
+        if c.is_uppercase() && previous_lowercase {
+            module.push('_');
+        }
+        previous_lowercase = c.is_lowercase();
+        module.extend(c.to_lowercase());
+    }
+    if is_keyword(&module) {
+        return format!("{module}_");
+    }
+
+    module
+}
+
+/// Compute the extern paths of all types in the descriptor set. rust-protobuf
+/// generates one module per proto file rather than per proto package.
+fn get_extern_paths(
+    descriptor_set: &FileDescriptorSet,
+    crate_name: &str,
+    file_modules: &BTreeMap<String, String>,
+) -> BTreeMap<ProtoPath, RustModulePath> {
+    let mut extern_paths = BTreeMap::new();
+    for file in &descriptor_set.file {
+        let module = match file_modules.get(file.name()) {
+            Some(module) => module,
+            None => continue,
+        };
+        let proto_path = ProtoPath::from(file.package());
+        let rust_path = RustModulePath::from(crate_name).join(module);
+        for message_type in &file.message_type {
+            message_type_to_extern_paths(&mut extern_paths, &proto_path, &rust_path, message_type);
+        }
+        for enum_type in &file.enum_type {
+            extern_paths.insert(
+                proto_path.join(enum_type.name()),
+                rust_path.join(&type_name(enum_type.name())),
+            );
+        }
+    }
+
+    extern_paths
+}
+
+/// Compute the extern paths of a message and the types nested in it.
+fn message_type_to_extern_paths(
+    extern_paths: &mut BTreeMap<ProtoPath, RustModulePath>,
+    proto_path: &ProtoPath,
+    rust_path: &RustModulePath,
+    message_type: &DescriptorProto,
+) {
+    // Map entries are represented as `HashMap`s rather than generated types.
+    if message_type
+        .options
+        .as_ref()
+        .is_some_and(|options| options.map_entry())
+    {
+        return;
+    }
+
+    let message_path = proto_path.join(message_type.name());
+    extern_paths.insert(
+        message_path.clone(),
+        rust_path.join(&type_name(message_type.name())),
+    );
+
+    let nested_path = rust_path.join(&nested_module_name(message_type.name()));
+    for nested_type in &message_type.nested_type {
+        message_type_to_extern_paths(extern_paths, &message_path, &nested_path, nested_type);
+    }
+    for enum_type in &message_type.enum_type {
+        extern_paths.insert(
+            message_path.join(enum_type.name()),
+            nested_path.join(&type_name(enum_type.name())),
+        );
+    }
+    for (index, oneof) in message_type.oneof_decl.iter().enumerate() {
+        // `optional` fields of proto3 messages are placed in synthetic oneofs which
+        // are not generated.
+        let synthetic = message_type
+            .field
+            .iter()
+            .any(|field| field.oneof_index == Some(index as i32) && field.proto3_optional());
+        if !synthetic {
+            extern_paths.insert(
+                message_path.join(oneof.name()),
+                nested_path.join(&type_name(oneof.name())),
+            );
+        }
+    }
+}
+
+/// Locate the modules of the proto files imported from dependencies. rust-protobuf
+/// refers to the types of other proto files through sibling modules, so these are
+/// re-exported from the root of the crate.
+fn get_dependency_modules(
+    descriptor_set: &FileDescriptorSet,
+    file_modules: &BTreeMap<String, String>,
+    dep_file_modules: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>, Diagnostic> {
+    let mut dependency_modules = BTreeMap::new();
+    for file in &descriptor_set.file {
+        for dependency in &file.dependency {
+            if dependency.starts_with(RUNTIME_PROTO_FILE_PREFIX)
+                || file_modules.contains_key(dependency)
+            {
+                continue;
+            }
+
+            let dep_module = dep_file_modules.get(dependency).ok_or_else(|| {
+                Diagnostic::error(
+                    file.name(),
+                    format!(
+                        "The import `{}` was not generated with rust-protobuf and cannot be referenced by rust-protobuf types.",
+                        dependency
+                    ),
+                    "Generate the `proto_library` providing it with the `protobuf` transform as well.",
+                )
+            })?;
+            let module = dep_module.rsplit("::").next().unwrap_or(dep_module);
+            let collides = file_modules.values().any(|local| local == module)
+                || dependency_modules
+                    .get(module)
+                    .is_some_and(|existing| existing != dep_module);
+            if collides {
+                return Err(Diagnostic::error(
+                    file.name(),
+                    format!(
+                        "The module `{}` generated for the import `{}` collides with the module of another proto file.",
+                        module, dependency
+                    ),
+                    "Rename one of the proto files.",
+                ));
+            }
+            dependency_modules.insert(module.to_string(), dep_module.clone());
+        }
+    }
+
+    Ok(dependency_modules)
+}
+
+/// Render the package info of the crate. The `file` entries locate the module of
+/// each proto file for dependent rust-protobuf crates.
+fn render_protobuf_package_info(
+    extern_paths: &BTreeMap<ProtoPath, RustModulePath>,
+    crate_name: &str,
+    file_modules: &BTreeMap<String, String>,
+) -> String {
+    let mut lines = vec![render_package_info(extern_paths, &BTreeMap::new())];
+    for (proto_file, module) in file_modules {
+        lines.push(format!(
+            "file {}=::{}",
+            proto_file,
+            RustModulePath::from(crate_name).join(module)
+        ));
+    }
+    lines.retain(|line| !line.is_empty());
+
+    lines.join("\n")
+}
+
+fn main() {
+    let args = Args::parse().expect("Failed to parse args");
+
+    let diagnostics = Diagnostics::new(args.diagnostics_out.clone());
+    diagnostics.install_panic_hook();
+
+    let unsupported_flags = unsupported_flags(&args);
+    if !unsupported_flags.is_empty() {
+        panic!(
+            "The following flags are not supported when generating code with rust-protobuf: {}",
+            unsupported_flags.join(", ")
+        );
+    }
+
+    let Args {
+        protoc,
+        out_dir,
+        crate_name,
+        label,
+        package_info_file,
+        proto_files,
+        additional_srcs,
+        includes,
+        descriptor_set,
+        out_librs,
+        out_module_dir,
+        skip_format_threshold,
+        rustfmt,
+        proto_paths,
+        direct_dep_crate_names,
+        is_grpcio,
+        dep_file_modules,
+        extra_args,
+        ..
+    } = args;
+
+    let out_dir = get_and_create_output_dir(&out_dir, &label);
+    let descriptor_set = parse_descriptor_set_file(&descriptor_set);
+    let additional_content = read_additional_srcs(additional_srcs);
+
+    if !is_grpcio {
+        for file in descriptor_set
+            .file
+            .iter()
+            .filter(|file| !file.service.is_empty())
+        {
+            diagnostics.warn(Diagnostic::warning(
+                file.name(),
+                "Service definitions will not be generated because grpcio stubs were not requested.".to_string(),
+                "Set `grpcio` on the `rust_prost_transform` of this target to generate services.",
+            ));
+        }
+    }
+
+    let mut args = vec![os_flag("--rs_out", &out_dir)];
+    if is_grpcio {
+        args.push(os_flag("--grpc_out", &out_dir));
+    }
+    args.extend(extra_args.into_iter().filter(|arg| !is_prost_opt(arg)));
+    args.extend(
+        proto_paths
+            .iter()
+            .map(|proto_path| os_flag("--proto_path", proto_path)),
+    );
+    args.extend(includes.iter().map(|include| {
+        let mut arg = OsString::from("-I");
+        arg.push(include);
+        arg
+    }));
+    args.extend(proto_files.iter().map(|f| f.as_os_str().to_os_string()));
+    invoke_protoc(&protoc, &out_dir.join("args.txt"), &args);
+
+    let generated_files = find_generated_files(&out_dir).unwrap_or_else(|err| panic!("{}", err));
+    let file_modules = generated_files
+        .iter()
+        .map(|(proto_file, path)| {
+            let module = path
+                .file_stem()
+                .expect("Failed to get file stem")
+                .to_string_lossy()
+                .to_string();
+            (proto_file.clone(), module)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let dependency_modules =
+        get_dependency_modules(&descriptor_set, &file_modules, &dep_file_modules)
+            .unwrap_or_else(|err| diagnostics.fail(err));
+
+    // Write outputs
+    let mut contents = vec!["// @generated".to_string(), "".to_string()];
+    for crate_name in direct_dep_crate_names {
+        contents.push(format!("pub use {crate_name};"));
+    }
+    for dep_module in dependency_modules.values() {
+        contents.push(format!("pub use {dep_module};"));
+    }
+    contents.push("".to_string());
+
+    let mut module_info = Module {
+        contents: contents.join("\n"),
+        ..Module::default()
+    };
+    for (proto_file, path) in &generated_files {
+        let mut content = fs::read_to_string(path).expect("Failed to read file");
+        let module = &file_modules[proto_file];
+        let stubs_file = path.with_file_name(format!("{}{}", module, GRPCIO_FILE_SUFFIX));
+        if is_grpcio && stubs_file.exists() {
+            let stubs = fs::read_to_string(&stubs_file).expect("Failed to read file");
+            content = merge_grpcio_stubs(content, &stubs);
+        }
+        module_info.insert(module.clone(), content);
+    }
+
+    let mut lib_rs = generate_lib_rs(&module_info, &additional_content, false);
+    let skip_format =
+        skip_format_threshold.is_some_and(|threshold| lib_rs.len() as u64 > threshold);
+    if skip_format {
+        lib_rs = generate_lib_rs(&module_info, &additional_content, true);
+    }
+    fs::write(&out_librs, lib_rs).expect("Failed to write file.");
+    if let Some(out_module_dir) = &out_module_dir {
+        fs::create_dir_all(out_module_dir).expect("Failed to create directory.");
+        write_module_tree(
+            out_module_dir,
+            &module_info,
+            &additional_content,
+            skip_format,
+        );
+    }
+
+    let extern_paths = get_extern_paths(&descriptor_set, &crate_name, &file_modules);
+    fs::write(
+        package_info_file,
+        render_protobuf_package_info(&extern_paths, &crate_name, &file_modules),
+    )
+    .expect("Failed to write file.");
+
+    if let Some(rustfmt) = rustfmt.filter(|_| !skip_format) {
+        run_rustfmt(&rustfmt, &out_librs, out_module_dir.as_deref());
+    }
+
+    diagnostics.write();
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use prost_types::{
+        EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto, MessageOptions,
+        OneofDescriptorProto,
+    };
+
+    #[test]
+    fn merge_grpcio_stubs_test() {
+        let stubs = "// This file is generated. Do not edit\n// @generated\n\n#![allow(unknown_lints)]\n#![allow(clippy::all)]\n\nconst METHOD_GREETER_SAY_HELLO: () = ();\n\npub struct GreeterClient;";
+
+        assert_eq!(
+            merge_grpcio_stubs("pub struct HelloRequest;".to_string(), stubs),
+            "pub struct HelloRequest;\nconst METHOD_GREETER_SAY_HELLO: () = ();\n\npub struct GreeterClient;\n"
+        );
+    }
+
+    #[test]
+    fn type_name_test() {
+        assert_eq!(type_name("Outer"), "Outer");
+        assert_eq!(type_name("fooBar"), "FooBar");
+        assert_eq!(type_name("my_choice"), "My_choice");
+        assert_eq!(type_name("Self"), "Self_");
+    }
+
+    #[test]
+    fn nested_module_name_test() {
+        assert_eq!(nested_module_name("Outer"), "outer");
+        assert_eq!(nested_module_name("fooBar"), "foo_bar");
+        assert_eq!(nested_module_name("HTTPRequest"), "httprequest");
+        assert_eq!(nested_module_name("Foo2Bar"), "foo2bar");
+        assert_eq!(nested_module_name("type"), "type_");
+    }
+
+    #[test]
+    fn get_extern_paths_test() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("a-file.proto".to_string()),
+                package: Some("foo.bar".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Outer".to_string()),
+                    field: vec![FieldDescriptorProto {
+                        name: Some("maybe".to_string()),
+                        oneof_index: Some(1),
+                        proto3_optional: Some(true),
+                        ..FieldDescriptorProto::default()
+                    }],
+                    nested_type: vec![
+                        DescriptorProto {
+                            name: Some("Inner".to_string()),
+                            ..DescriptorProto::default()
+                        },
+                        DescriptorProto {
+                            name: Some("ValuesEntry".to_string()),
+                            options: Some(MessageOptions {
+                                map_entry: Some(true),
+                                ..MessageOptions::default()
+                            }),
+                            ..DescriptorProto::default()
+                        },
+                    ],
+                    enum_type: vec![EnumDescriptorProto {
+                        name: Some("Kind".to_string()),
+                        ..EnumDescriptorProto::default()
+                    }],
+                    oneof_decl: vec![
+                        OneofDescriptorProto {
+                            name: Some("choice".to_string()),
+                            ..OneofDescriptorProto::default()
+                        },
+                        OneofDescriptorProto {
+                            name: Some("_maybe".to_string()),
+                            ..OneofDescriptorProto::default()
+                        },
+                    ],
+                    ..DescriptorProto::default()
+                }],
+                enum_type: vec![EnumDescriptorProto {
+                    name: Some("Top".to_string()),
+                    ..EnumDescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        };
+        let file_modules = BTreeMap::from([("a-file.proto".to_string(), "a_file".to_string())]);
+
+        let extern_paths = get_extern_paths(&descriptor_set, "a_proto", &file_modules)
+            .into_iter()
+            .map(|(proto_path, rust_path)| (proto_path.to_string(), rust_path.to_string()))
+            .collect::<BTreeMap<_, _>>();
+
+        assert_eq!(
+            extern_paths,
+            BTreeMap::from(
+                [
+                    ("foo.bar.Outer", "a_proto::a_file::Outer"),
+                    ("foo.bar.Outer.Inner", "a_proto::a_file::outer::Inner"),
+                    ("foo.bar.Outer.Kind", "a_proto::a_file::outer::Kind"),
+                    ("foo.bar.Outer.choice", "a_proto::a_file::outer::Choice"),
+                    ("foo.bar.Top", "a_proto::a_file::Top"),
+                ]
+                .map(|(proto_path, rust_path)| (proto_path.to_string(), rust_path.to_string()))
+            )
+        );
+    }
+
+    #[test]
+    fn get_dependency_modules_test() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("b.proto".to_string()),
+                dependency: vec![
+                    "a-file.proto".to_string(),
+                    "google/protobuf/timestamp.proto".to_string(),
+                    "c.proto".to_string(),
+                ],
+                ..FileDescriptorProto::default()
+            }],
+        };
+        let file_modules = BTreeMap::from([
+            ("b.proto".to_string(), "b".to_string()),
+            ("c.proto".to_string(), "c".to_string()),
+        ]);
+        let dep_file_modules =
+            BTreeMap::from([("a-file.proto".to_string(), "::a_proto::a_file".to_string())]);
+
+        assert_eq!(
+            get_dependency_modules(&descriptor_set, &file_modules, &dep_file_modules),
+            Ok(BTreeMap::from([(
+                "a_file".to_string(),
+                "::a_proto::a_file".to_string()
+            )]))
+        );
+
+        let err =
+            get_dependency_modules(&descriptor_set, &file_modules, &BTreeMap::new()).unwrap_err();
+        assert_eq!(err.proto_file.as_deref(), Some("b.proto"));
+        assert!(err.message.contains("`a-file.proto`"), "{}", err.message);
+    }
+
+    #[test]
+    fn render_protobuf_package_info_test() {
+        let extern_paths = BTreeMap::from([(
+            ProtoPath::from("foo.bar.Outer"),
+            RustModulePath::from("a_proto::a_file::Outer"),
+        )]);
+        let file_modules = BTreeMap::from([("a-file.proto".to_string(), "a_file".to_string())]);
+
+        assert_eq!(
+            render_protobuf_package_info(&extern_paths, "a_proto", &file_modules),
+            ".foo.bar.Outer=::a_proto::a_file::Outer\nfile a-file.proto=::a_proto::a_file"
+        );
+    }
+}
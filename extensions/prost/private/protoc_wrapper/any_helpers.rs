@@ -0,0 +1,144 @@
+//! Generation of helpers for packing messages into and unpacking them from `google.protobuf.Any`.
+
+use std::collections::BTreeMap;
+
+use prost_types::FileDescriptorSet;
+
+use super::{for_each_message, nested_rust_type_path};
+
+/// The prefix of the type URLs of messages packed into a `google.protobuf.Any`.
+const ANY_TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// Generate `google.protobuf.Any` helpers for all messages in the descriptor set.
+///
+/// Each message gains a `TYPE_URL` constant along with `pack_into_any` and
+/// `unpack_from_any` functions. The generated code is returned per proto package
+/// along with the type URLs of all messages.
+pub(crate) fn generate_any_helpers(
+    descriptor_set: &FileDescriptorSet,
+) -> (BTreeMap<String, String>, Vec<String>) {
+    let mut helpers: BTreeMap<String, String> = BTreeMap::new();
+    let mut type_urls = Vec::new();
+
+    for_each_message(
+        descriptor_set,
+        |package, parents, proto_path, message_type| {
+            let full_name = &proto_path.0;
+            let type_url = format!("{}{}", ANY_TYPE_URL_PREFIX, full_name);
+            let rust_path = nested_rust_type_path(parents, message_type.name());
+
+            helpers.entry(package.to_string()).or_default().push_str(&format!(
+            "impl {rust_path} {{
+    /// The type URL of `{full_name}` when packed into a `google.protobuf.Any`.
+    pub const TYPE_URL: &'static str = \"{type_url}\";
+
+    /// Pack the message into a `google.protobuf.Any`.
+    pub fn pack_into_any(&self) -> ::prost_types::Any {{
+        ::prost_types::Any {{
+            type_url: ::prost::alloc::string::ToString::to_string(Self::TYPE_URL),
+            value: ::prost::Message::encode_to_vec(self),
+        }}
+    }}
+
+    /// Unpack the message from a `google.protobuf.Any`, failing if it holds another type.
+    pub fn unpack_from_any(any: &::prost_types::Any) -> ::core::result::Result<Self, ::prost::DecodeError> {{
+        // Only the type name of a type URL is significant, the domain may be anything.
+        if any.type_url.rsplit('/').next() != ::core::option::Option::Some(\"{full_name}\") {{
+            return ::core::result::Result::Err(::prost::DecodeError::new(::prost::alloc::format!(
+                \"expected a `{full_name}` but the `Any` holds `{{}}`\",
+                any.type_url
+            )));
+        }}
+        <Self as ::prost::Message>::decode(any.value.as_slice())
+    }}
+}}
+"
+        ));
+            type_urls.push(type_url);
+        },
+    );
+    type_urls.sort();
+
+    (helpers, type_urls)
+}
+
+/// Generate a constant listing the type URLs of all messages in the crate.
+pub(crate) fn generate_any_type_url_registry(type_urls: &[String]) -> String {
+    format!(
+        "\n/// The `google.protobuf.Any` type URLs of all messages in this crate.\npub const ANY_TYPE_URLS: &[&str] = &[\n{}];\n",
+        type_urls
+            .iter()
+            .map(|type_url| format!("    \"{}\",\n", type_url))
+            .collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+    #[test]
+    fn generate_any_helpers_test() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![
+                FileDescriptorProto {
+                    name: Some("a.proto".to_string()),
+                    package: Some("pkg.a".to_string()),
+                    message_type: vec![DescriptorProto {
+                        name: Some("Outer".to_string()),
+                        nested_type: vec![
+                            DescriptorProto {
+                                name: Some("Inner".to_string()),
+                                ..DescriptorProto::default()
+                            },
+                            DescriptorProto {
+                                name: Some("ValuesEntry".to_string()),
+                                options: Some(prost_types::MessageOptions {
+                                    map_entry: Some(true),
+                                    ..prost_types::MessageOptions::default()
+                                }),
+                                ..DescriptorProto::default()
+                            },
+                        ],
+                        ..DescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                },
+                FileDescriptorProto {
+                    name: Some("b.proto".to_string()),
+                    message_type: vec![DescriptorProto {
+                        name: Some("Packageless".to_string()),
+                        ..DescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                },
+            ],
+        };
+
+        let (helpers, type_urls) = generate_any_helpers(&descriptor_set);
+        assert_eq!(
+            type_urls,
+            vec![
+                "type.googleapis.com/Packageless".to_string(),
+                "type.googleapis.com/pkg.a.Outer".to_string(),
+                "type.googleapis.com/pkg.a.Outer.Inner".to_string(),
+            ]
+        );
+
+        let pkg = &helpers["pkg.a"];
+        assert!(pkg.contains("impl Outer {"), "{}", pkg);
+        assert!(pkg.contains("impl outer::Inner {"), "{}", pkg);
+        assert!(pkg.contains(
+            "pub const TYPE_URL: &'static str = \"type.googleapis.com/pkg.a.Outer.Inner\";"
+        ));
+        assert!(!pkg.contains("ValuesEntry"), "{}", pkg);
+        assert!(helpers[""].contains("impl Packageless {"));
+
+        let registry = generate_any_type_url_registry(&type_urls);
+        assert!(registry.contains(
+            "pub const ANY_TYPE_URLS: &[&str] = &[\n    \"type.googleapis.com/Packageless\",\n"
+        ));
+    }
+}
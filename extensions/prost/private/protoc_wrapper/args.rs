@@ -0,0 +1,1250 @@
+//! Parsing of the arguments of the wrapper, which may be passed in an args file.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::diagnostics::SizeBudget;
+use super::wkt::WktMapping;
+use super::PackageInfo;
+
+/// The parsed command-line arguments.
+pub struct Args {
+    /// The path to the protoc binary.
+    pub protoc: PathBuf,
+
+    /// The path to the output directory.
+    pub out_dir: PathBuf,
+
+    /// The name of the crate.
+    pub crate_name: String,
+
+    /// The bazel label.
+    pub label: String,
+
+    /// The path to the package info file.
+    pub package_info_file: PathBuf,
+
+    /// The proto files to compile.
+    pub proto_files: Vec<PathBuf>,
+
+    /// Additional source files to append to the generated rust source.
+    pub additional_srcs: Vec<PathBuf>,
+
+    /// The include directories.
+    pub includes: Vec<OsString>,
+
+    /// Dependency descriptor sets.
+    pub descriptor_set: PathBuf,
+
+    /// The path to the generated lib.rs file.
+    pub out_librs: PathBuf,
+
+    /// The directory in which to write a `mod.rs` style module hierarchy, if any.
+    pub out_module_dir: Option<PathBuf>,
+
+    /// A directory persisted between runs used to skip regenerating unchanged packages.
+    pub incremental_state_dir: Option<PathBuf>,
+
+    /// The path of a JSON file mapping generated types to their proto definitions, if any.
+    pub source_map_output: Option<PathBuf>,
+
+    /// The path of a JSON file to write warnings and errors to, if any.
+    pub diagnostics_out: Option<PathBuf>,
+
+    /// The size in bytes above which outputs are marked `#[rustfmt::skip]` instead of formatted.
+    pub skip_format_threshold: Option<u64>,
+
+    /// Limits on the size of the generated crate.
+    pub size_budget: SizeBudget,
+
+    /// The proto include paths.
+    pub proto_paths: Vec<OsString>,
+
+    /// Direct dependency crate names.
+    pub direct_dep_crate_names: Vec<String>,
+
+    /// The path to the rustfmt binary.
+    pub rustfmt: Option<PathBuf>,
+
+    /// Whether to generate tonic code.
+    pub is_tonic: bool,
+
+    /// Whether to generate grpcio service stubs alongside rust-protobuf messages.
+    pub is_grpcio: bool,
+
+    /// Whether to generate services with a Connect-compatible plugin instead of tonic.
+    pub is_connect: bool,
+
+    /// Whether to generate conversion helpers for enums.
+    pub enum_helpers: bool,
+
+    /// Whether to generate `google.protobuf.Any` helpers for messages.
+    pub any_helpers: bool,
+
+    /// Whether to generate a module registering all services with `tonic_health`.
+    pub health_service: bool,
+
+    /// The path to write the root of a crate with programmable mocks of the tonic
+    /// services to, if any.
+    pub mocks_output: Option<PathBuf>,
+
+    /// The path to write the `main.rs` of a tonic server scaffold to, if any.
+    pub scaffold_output: Option<PathBuf>,
+
+    /// Whether only the scaffold is written, without running protoc. The outputs of
+    /// the generated crate aren't required then.
+    pub scaffold_only: bool,
+
+    /// Whether to generate constants holding the proto names of messages and enums.
+    pub name_constants: bool,
+
+    /// The version of prost the generated code is compiled against.
+    pub prost_version: ProstVersion,
+
+    /// Proto packages for which message builders should be generated.
+    pub builder_packages: BTreeSet<String>,
+
+    /// Fully qualified proto names of types from dependencies mapped to their Rust paths.
+    pub dep_extern_paths: BTreeMap<String, String>,
+
+    /// Proto files of dependencies generated by rust-protobuf mapped to their Rust modules.
+    pub dep_file_modules: BTreeMap<String, String>,
+
+    /// Well-known types which are represented by native Rust types.
+    pub wkt_mappings: Vec<&'static WktMapping>,
+
+    /// Extra arguments to pass to protoc.
+    pub extra_args: Vec<OsString>,
+}
+
+impl Args {
+    /// Parse the command-line arguments.
+    pub fn parse() -> Result<Args, String> {
+        Self::parse_from(env::args_os().skip(1))
+    }
+
+    /// Parse the given arguments, expanding any `@argfile`s.
+    ///
+    /// Flags take the form `--flag=value` where everything after the first `=` is
+    /// the value, so values are free to contain `=` characters and spaces. Paths are
+    /// not required to be valid UTF-8. Each line of an argfile is a single argument
+    /// and may be quoted using shell quoting rules.
+    fn parse_from(args: impl IntoIterator<Item = impl Into<OsString>>) -> Result<Args, String> {
+        let mut protoc: Option<PathBuf> = None;
+        let mut out_dir: Option<PathBuf> = None;
+        let mut crate_name: Option<String> = None;
+        let mut package_info_file: Option<PathBuf> = None;
+        let mut proto_files: Vec<PathBuf> = Vec::new();
+        let mut additional_srcs: Vec<PathBuf> = Vec::new();
+        let mut includes = Vec::new();
+        let mut descriptor_set = None;
+        let mut out_librs: Option<PathBuf> = None;
+        let mut out_module_dir: Option<PathBuf> = None;
+        let mut incremental_state_dir: Option<PathBuf> = None;
+        let mut source_map_output: Option<PathBuf> = None;
+        let mut scaffold_output: Option<PathBuf> = None;
+        let mut scaffold_only = false;
+        let mut diagnostics_out: Option<PathBuf> = None;
+        let mut skip_format_threshold: Option<u64> = None;
+        let mut size_budget = SizeBudget::default();
+        let mut rustfmt: Option<PathBuf> = None;
+        let mut proto_paths = Vec::new();
+        let mut label: Option<String> = None;
+        let mut tonic_or_prost_opts = Vec::new();
+        let mut direct_dep_crate_names = Vec::new();
+        let mut is_tonic = false;
+        let mut is_grpcio = false;
+        let mut is_connect = false;
+        let mut enum_helpers = false;
+        let mut any_helpers = false;
+        let mut name_constants = false;
+        let mut health_service = false;
+        let mut mocks_output: Option<PathBuf> = None;
+        let mut prost_version = ProstVersion::LATEST;
+        let mut tonic_compat: Option<TonicCompat> = None;
+        let mut wkt_mappings: BTreeMap<&'static str, &'static WktMapping> = BTreeMap::new();
+        let mut tonic_runtime_version: Option<String> = None;
+        let mut builder_packages = BTreeSet::new();
+        let mut dep_extern_paths = BTreeMap::new();
+        let mut dep_file_modules = BTreeMap::new();
+
+        let mut extra_args = Vec::new();
+
+        let mut handle_arg = |arg: OsString| -> Result<(), String> {
+            if !arg.to_string_lossy().starts_with('-') {
+                proto_files.push(PathBuf::from(arg));
+                return Ok(());
+            }
+
+            if let Some(include) = strip_os_str_prefix(&arg, "-I") {
+                includes.push(include.to_os_string());
+                return Ok(());
+            }
+
+            if arg == "--is_tonic" {
+                is_tonic = true;
+                return Ok(());
+            }
+
+            if arg == "--is_grpcio" {
+                is_grpcio = true;
+                return Ok(());
+            }
+
+            if arg == "--is_connect" {
+                is_connect = true;
+                return Ok(());
+            }
+
+            if arg == "--enum_helpers" {
+                enum_helpers = true;
+                return Ok(());
+            }
+
+            if arg == "--any_helpers" {
+                any_helpers = true;
+                return Ok(());
+            }
+
+            if arg == "--name_constants" {
+                name_constants = true;
+                return Ok(());
+            }
+
+            if arg == "--health_service" {
+                health_service = true;
+                return Ok(());
+            }
+
+            if arg == "--scaffold_only" {
+                scaffold_only = true;
+                return Ok(());
+            }
+
+            if arg == "--strict" {
+                size_budget.strict = true;
+                return Ok(());
+            }
+
+            let (flag, value) = match split_flag(&arg) {
+                Some(parts) => parts,
+                None => {
+                    extra_args.push(arg);
+                    return Ok(());
+                }
+            };
+
+            match flag {
+                "--protoc" => {
+                    protoc = Some(PathBuf::from(value));
+                }
+                // rust-protobuf is run as the `rs` plugin since protoc reserves `--rust_out`.
+                "--prost_out" | "--rs_out" => {
+                    out_dir = Some(PathBuf::from(value));
+                }
+                "--crate_name" => {
+                    crate_name = Some(os_str_to_string(flag, value)?);
+                }
+                "--package_info_output" => {
+                    package_info_file = Some(PathBuf::from(value));
+                }
+                "--deps_info" => {
+                    // Each line is the path of a package info file, optionally followed
+                    // by a tab and `<crate name>=<alias>` if the crate is renamed.
+                    for line in read_file(Path::new(value))?.lines() {
+                        if line.is_empty() {
+                            continue;
+                        }
+                        let (path, rename) = match line.split_once('\t') {
+                            Some((path, rename)) => (path, Some(rename)),
+                            None => (line, None),
+                        };
+                        let mut package_info = PackageInfo::parse(&read_file(Path::new(path))?);
+                        if let Some(rename) = rename {
+                            let (original, alias) = rename.split_once('=').ok_or_else(|| {
+                                format!(
+                                    "Expected a `<crate name>=<alias>` rename for `{}` but got `{}`.",
+                                    path, rename
+                                )
+                            })?;
+                            package_info.rename_crate(original, alias);
+                        }
+                        // Only the extern paths of types and the modules of files
+                        // generated by rust-protobuf are needed to generate code.
+                        dep_extern_paths.extend(package_info.extern_paths);
+                        dep_file_modules.extend(package_info.file_modules);
+                    }
+                }
+                "--additional_src" => {
+                    additional_srcs.push(PathBuf::from(value));
+                }
+                "--direct_dep_crate_names" => {
+                    let value = os_str_to_string(flag, value)?;
+                    if !value.trim().is_empty() {
+                        direct_dep_crate_names = value.split(',').map(|s| s.to_string()).collect();
+                    }
+                }
+                "--descriptor_set" => {
+                    descriptor_set = Some(PathBuf::from(value));
+                }
+                "--out_librs" => {
+                    out_librs = Some(PathBuf::from(value));
+                }
+                "--out_module_dir" => {
+                    out_module_dir = Some(PathBuf::from(value));
+                }
+                "--incremental_state_dir" => {
+                    incremental_state_dir = Some(PathBuf::from(value));
+                }
+                "--source_map_output" => {
+                    source_map_output = Some(PathBuf::from(value));
+                }
+                "--diagnostics_out" => {
+                    diagnostics_out = Some(PathBuf::from(value));
+                }
+                "--scaffold_output" => {
+                    scaffold_output = Some(PathBuf::from(value));
+                }
+                "--mocks_output" => {
+                    mocks_output = Some(PathBuf::from(value));
+                }
+                "--skip_format_threshold" => {
+                    skip_format_threshold = Some(parse_count(flag, value)?);
+                }
+                "--size_budget_bytes" => {
+                    size_budget.max_bytes = Some(parse_count(flag, value)?);
+                }
+                "--size_budget_types" => {
+                    size_budget.max_types = Some(parse_count(flag, value)?);
+                }
+                "--rustfmt" => {
+                    rustfmt = Some(PathBuf::from(value));
+                }
+                "--proto_path" => {
+                    proto_paths.push(value.to_os_string());
+                }
+                "--label" => {
+                    label = Some(os_str_to_string(flag, value)?);
+                }
+                "--prost_version" => {
+                    prost_version = ProstVersion::parse(&os_str_to_string(flag, value)?)?;
+                }
+                "--tonic_compat" => {
+                    tonic_compat = Some(TonicCompat::parse(&os_str_to_string(flag, value)?)?);
+                }
+                "--tonic_runtime_version" => {
+                    tonic_runtime_version = Some(os_str_to_string(flag, value)?);
+                }
+                "--wkt_mapping" => {
+                    let mapping = WktMapping::parse(&os_str_to_string(flag, value)?)?;
+                    wkt_mappings.insert(mapping.proto_name, mapping);
+                }
+                "--builder_package" => {
+                    builder_packages.insert(os_str_to_string(flag, value)?);
+                }
+                "--protoc_args_file" => {
+                    extra_args.extend(
+                        parse_protoc_args_file(&read_file(Path::new(value))?)
+                            .into_iter()
+                            .map(OsString::from),
+                    );
+                }
+                _ => {
+                    extra_args.push(arg);
+                }
+            }
+
+            Ok(())
+        };
+
+        // Iterate over the given command line arguments parsing out arguments
+        // for the process runner and arguments for protoc and potentially spawn
+        // additional arguments needed by prost.
+        for arg in args {
+            let arg: OsString = arg.into();
+            if let Some(path) = strip_os_str_prefix(&arg, "@") {
+                // handle argfile
+                let file = std::fs::File::open(path)
+                    .map_err(|_| format!("could not open argfile: {}", arg.to_string_lossy()))?;
+                for line in std::io::BufReader::new(file).lines() {
+                    let line = line.map_err(|_| {
+                        format!("could not read argfile: {}", arg.to_string_lossy())
+                    })?;
+                    handle_arg(OsString::from(parse_argfile_line(&line)?))?;
+                }
+            } else {
+                handle_arg(arg)?;
+            }
+        }
+
+        // Well-known types mapped to native Rust types replace the extern paths
+        // provided by the crates compiling them.
+        for mapping in wkt_mappings.values() {
+            dep_extern_paths.insert(mapping.proto_name.to_string(), mapping.rust_path());
+        }
+        for (proto_path, rust_path) in dep_extern_paths.iter() {
+            tonic_or_prost_opts.push(format!("extern_path={}={}", proto_path, rust_path));
+        }
+
+        for tonic_or_prost_opt in tonic_or_prost_opts {
+            extra_args.push(format!("--prost_opt={}", tonic_or_prost_opt).into());
+            if is_tonic {
+                extra_args.push(format!("--tonic_opt={}", tonic_or_prost_opt).into());
+            }
+            if is_connect {
+                extra_args.push(format!("--connect_opt={}", tonic_or_prost_opt).into());
+            }
+        }
+
+        prost_version.check_args(&extra_args)?;
+        if is_grpcio && is_tonic {
+            return Err("`--is_grpcio` and `--is_tonic` cannot be used together.".to_string());
+        }
+        if is_connect && (is_tonic || is_grpcio) {
+            return Err(
+                "`--is_connect` cannot be used together with `--is_tonic` or `--is_grpcio`."
+                    .to_string(),
+            );
+        }
+        if health_service && !is_tonic {
+            return Err(
+                "`--health_service` was set but tonic code is not being generated.".to_string(),
+            );
+        }
+        if mocks_output.is_some() && !is_tonic {
+            return Err(
+                "`--mocks_output` was set but tonic code is not being generated.".to_string(),
+            );
+        }
+        if scaffold_output.is_some() && !is_tonic {
+            return Err(
+                "`--scaffold_output` was set but tonic code is not being generated.".to_string(),
+            );
+        }
+        if scaffold_only && scaffold_output.is_none() {
+            return Err("`--scaffold_only` requires `--scaffold_output`.".to_string());
+        }
+        if mocks_output.is_some() && extra_args.iter().any(|arg| arg == "--tonic_opt=no_server") {
+            return Err(
+                "`--mocks_output` requires tonic server code which is disabled by `no_server`."
+                    .to_string(),
+            );
+        }
+        if let Some(tonic_compat) = tonic_compat {
+            if !is_tonic {
+                return Err(
+                    "`--tonic_compat` was set but tonic code is not being generated.".to_string(),
+                );
+            }
+            if let Some(runtime_version) = &tonic_runtime_version {
+                tonic_compat.check_runtime(runtime_version)?;
+            }
+            tonic_compat.check_args(&extra_args)?;
+        }
+
+        if protoc.is_none() && !scaffold_only {
+            return Err(
+                "No `--protoc` value was found. Unable to parse path to proto compiler."
+                    .to_string(),
+            );
+        }
+        if out_dir.is_none() && !scaffold_only {
+            return Err(
+                "No `--prost_out` value was found. Unable to parse output directory.".to_string(),
+            );
+        }
+        if crate_name.is_none() {
+            return Err(
+                "No `--crate_name` value was found. Unable to parse target crate name.".to_string(),
+            );
+        }
+        if package_info_file.is_none() && !scaffold_only {
+            return Err("No `--package_info_output` value was found. Unable to parse package info output file.".to_string());
+        }
+        if out_librs.is_none() && !scaffold_only {
+            return Err("No `--out_librs` value was found. Unable to parse the output location for all combined prost outputs.".to_string());
+        }
+        if descriptor_set.is_none() {
+            return Err(
+                "No `--descriptor_set` value was found. Unable to parse descriptor set path."
+                    .to_string(),
+            );
+        }
+        if label.is_none() {
+            return Err(
+                "No `--label` value was found. Unable to parse the label of the target crate."
+                    .to_string(),
+            );
+        }
+
+        Ok(Args {
+            protoc: protoc.unwrap_or_default(),
+            out_dir: out_dir.unwrap_or_default(),
+            crate_name: crate_name.unwrap(),
+            package_info_file: package_info_file.unwrap_or_default(),
+            proto_files,
+            additional_srcs,
+            includes,
+            descriptor_set: descriptor_set.unwrap(),
+            out_librs: out_librs.unwrap_or_default(),
+            out_module_dir,
+            incremental_state_dir,
+            source_map_output,
+            diagnostics_out,
+            skip_format_threshold,
+            size_budget,
+            rustfmt,
+            proto_paths,
+            direct_dep_crate_names,
+            is_tonic,
+            is_grpcio,
+            is_connect,
+            enum_helpers,
+            any_helpers,
+            name_constants,
+            health_service,
+            mocks_output,
+            scaffold_output,
+            scaffold_only,
+            prost_version,
+            builder_packages,
+            dep_extern_paths,
+            dep_file_modules,
+            wkt_mappings: wkt_mappings.into_values().collect(),
+            label: label.unwrap(),
+            extra_args,
+        })
+    }
+}
+
+/// A `major.minor` version of prost.
+///
+/// prost is still pre-1.0 so breaking changes are made in minor versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProstVersion {
+    major: u64,
+    minor: u64,
+}
+
+impl ProstVersion {
+    /// The most recent version of prost the wrapper is tested against.
+    const LATEST: ProstVersion = ProstVersion::new(0, 13);
+
+    /// The first version of prost which derives `TryFrom<i32>` for enumerations and
+    /// supports generating `prost::Name` implementations.
+    const V0_12: ProstVersion = ProstVersion::new(0, 12);
+
+    /// The first version of prost which takes buffers as `impl Buf` arguments in
+    /// the `Message` trait.
+    const V0_13: ProstVersion = ProstVersion::new(0, 13);
+
+    /// The oldest version of prost the wrapper supports.
+    const OLDEST: ProstVersion = ProstVersion::new(0, 11);
+
+    /// Create a new version.
+    pub const fn new(major: u64, minor: u64) -> Self {
+        Self { major, minor }
+    }
+
+    /// Parse a version such as `0.11` or `0.13.1`. Patch versions are ignored.
+    fn parse(value: &str) -> Result<Self, String> {
+        let (major, minor) = parse_major_minor(value).ok_or_else(|| {
+            format!(
+                "Invalid `--prost_version` value `{}`. Expected a version such as `0.13`.",
+                value
+            )
+        })?;
+
+        let version = Self::new(major, minor);
+        if version < Self::OLDEST {
+            return Err(format!(
+                "prost {} is not supported. The oldest supported version is {}.",
+                version,
+                Self::OLDEST
+            ));
+        }
+
+        Ok(version)
+    }
+
+    /// Whether prost derives `TryFrom<i32>` for enumerations. Older versions only
+    /// provide the `from_i32` associated function.
+    pub fn derives_enum_try_from(&self) -> bool {
+        *self >= Self::V0_12
+    }
+
+    /// Whether the `Message` trait takes buffers as `impl Buf` arguments rather than
+    /// through generic type parameters.
+    pub fn uses_impl_trait_buffers(&self) -> bool {
+        *self >= Self::V0_13
+    }
+
+    /// Ensure the plugin options passed to protoc are understood by this version of prost.
+    fn check_args(&self, args: &[OsString]) -> Result<(), String> {
+        if *self >= Self::V0_12 {
+            return Ok(());
+        }
+
+        for arg in args {
+            let opt = match arg
+                .to_str()
+                .and_then(|arg| arg.strip_prefix("--prost_opt="))
+            {
+                Some(opt) => opt,
+                None => continue,
+            };
+            let name = opt.split_once('=').map_or(opt, |(name, _)| name);
+            if name == "enable_type_names" || name == "type_name_domain" {
+                return Err(format!(
+                    "The prost option `{}` requires prost {} or newer but prost {} is in use.",
+                    name,
+                    Self::V0_12,
+                    self
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ProstVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// A `major.minor` version of tonic the generated code must be compatible with.
+///
+/// tonic 0.12 moved to hyper 1.0 which changed the transport glue the plugin emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct TonicCompat {
+    major: u64,
+    minor: u64,
+}
+
+impl TonicCompat {
+    /// The first version of tonic built on hyper 1.0.
+    const V0_12: TonicCompat = TonicCompat::new(0, 12);
+
+    /// The oldest version of tonic the wrapper supports.
+    const OLDEST: TonicCompat = TonicCompat::new(0, 10);
+
+    /// Create a new version.
+    const fn new(major: u64, minor: u64) -> Self {
+        Self { major, minor }
+    }
+
+    /// Parse a version such as `0.10` or `0.12.3`. Patch versions are ignored.
+    fn parse(value: &str) -> Result<Self, String> {
+        let (major, minor) = parse_major_minor(value).ok_or_else(|| {
+            format!(
+                "Invalid `--tonic_compat` value `{}`. Expected a version such as `0.12`.",
+                value
+            )
+        })?;
+
+        let version = Self::new(major, minor);
+        if version < Self::OLDEST {
+            return Err(format!(
+                "tonic {} is not supported. The oldest supported version is {}.",
+                version,
+                Self::OLDEST
+            ));
+        }
+
+        Ok(version)
+    }
+
+    /// Whether the matching tonic release is built on hyper 1.0.
+    fn uses_hyper_1(&self) -> bool {
+        *self >= Self::V0_12
+    }
+
+    /// Ensure the version of the tonic runtime crate provided by the toolchain
+    /// matches the compatibility mode.
+    fn check_runtime(&self, runtime_version: &str) -> Result<(), String> {
+        let matches = parse_major_minor(runtime_version)
+            .is_some_and(|(major, minor)| Self::new(major, minor) == *self);
+        if !matches {
+            return Err(format!(
+                "The tonic runtime provided by the prost toolchain is version `{}` but `tonic_compat` is set to `{}`. \
+                Update `tonic_compat` on the `rust_prost_toolchain` to match the `tonic_runtime` crate.",
+                runtime_version, self
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Ensure the tonic plugin options passed to protoc are understood by this version of tonic.
+    fn check_args(&self, args: &[OsString]) -> Result<(), String> {
+        if self.uses_hyper_1() {
+            return Ok(());
+        }
+
+        for arg in args {
+            let opt = match arg
+                .to_str()
+                .and_then(|arg| arg.strip_prefix("--tonic_opt="))
+            {
+                Some(opt) => opt,
+                None => continue,
+            };
+            let name = opt.split_once('=').map_or(opt, |(name, _)| name);
+            if name == "generate_default_stubs" {
+                return Err(format!(
+                    "The tonic option `{}` requires tonic {} or newer but `tonic_compat` is set to `{}`.",
+                    name,
+                    Self::V0_12,
+                    self
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for TonicCompat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Parse the major and minor components of a version such as `0.13` or `0.13.1-rc.1`.
+fn parse_major_minor(value: &str) -> Option<(u64, u64)> {
+    let mut parts = value.trim().splitn(3, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    if let Some(patch) = parts.next() {
+        let patch = patch.split(['-', '+']).next().unwrap_or_default();
+        patch.parse::<u64>().ok()?;
+    }
+
+    Some((major, minor))
+}
+
+/// Read a file passed as an argument to the wrapper.
+fn read_file(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read file `{}`: {}", path.display(), e))
+}
+
+/// Parse the value of a flag holding a non-negative number.
+fn parse_count(flag: &str, value: &OsStr) -> Result<u64, String> {
+    let value = os_str_to_string(flag, value)?;
+    value
+        .parse()
+        .map_err(|_| format!("`{}` must be a non-negative number, got `{}`.", flag, value))
+}
+
+/// Split a `--flag=value` argument on its first `=`. The flag must be valid UTF-8
+/// but the value may be any platform string.
+pub fn split_flag(arg: &OsStr) -> Option<(&str, &OsStr)> {
+    let bytes = arg.as_encoded_bytes();
+    let index = bytes.iter().position(|byte| *byte == b'=')?;
+    let flag = std::str::from_utf8(&bytes[..index]).ok()?;
+    // SAFETY: The bytes come from an `OsStr` and are split directly after an
+    // ASCII character, which is a valid boundary.
+    let value = unsafe { OsStr::from_encoded_bytes_unchecked(&bytes[index + 1..]) };
+
+    Some((flag, value))
+}
+
+/// Strip an ASCII prefix from a platform string.
+fn strip_os_str_prefix<'a>(arg: &'a OsStr, prefix: &str) -> Option<&'a OsStr> {
+    debug_assert!(prefix.is_ascii());
+    let rest = arg.as_encoded_bytes().strip_prefix(prefix.as_bytes())?;
+    // SAFETY: The bytes come from an `OsStr` and are split directly after an
+    // ASCII character, which is a valid boundary.
+    Some(unsafe { OsStr::from_encoded_bytes_unchecked(rest) })
+}
+
+/// Convert the value of a flag which is required to be valid UTF-8.
+fn os_str_to_string(flag: &str, value: &OsStr) -> Result<String, String> {
+    value
+        .to_str()
+        .map(|value| value.to_string())
+        .ok_or_else(|| format!("The value of `{}` is not valid UTF-8: {:?}", flag, value))
+}
+
+/// Create a `--flag=value` argument from a flag and a platform string.
+pub fn os_flag(flag: &str, value: impl AsRef<OsStr>) -> OsString {
+    let mut arg = OsString::from(flag);
+    arg.push("=");
+    arg.push(value);
+    arg
+}
+
+/// Render the contents of a protoc response file with one argument per line.
+pub fn render_args_file(args: &[OsString]) -> Vec<u8> {
+    args.iter()
+        .map(|arg| arg.as_encoded_bytes())
+        .collect::<Vec<_>>()
+        .join(&b'\n')
+}
+
+/// Parse a single line of an argfile into an argument.
+///
+/// Lines are used verbatim unless they start with a quote in which case the line
+/// is unquoted following shell quoting rules (as written by Bazel's `shell` param
+/// file format). Single quoted sections are literal while double quoted sections
+/// and unquoted text support backslash escapes.
+fn parse_argfile_line(line: &str) -> Result<String, String> {
+    if !line.starts_with(['\'', '"']) {
+        return Ok(line.to_string());
+    }
+
+    let mut arg = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(c) => arg.push(c),
+                    None => return Err(format!("Unterminated single quote in argument: {}", line)),
+                }
+            },
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '\\' | '$' | '`')) => arg.push(c),
+                        Some(c) => {
+                            arg.push('\\');
+                            arg.push(c);
+                        }
+                        None => {
+                            return Err(format!("Unterminated double quote in argument: {}", line))
+                        }
+                    },
+                    Some(c) => arg.push(c),
+                    None => return Err(format!("Unterminated double quote in argument: {}", line)),
+                }
+            },
+            '\\' => match chars.next() {
+                Some(c) => arg.push(c),
+                None => return Err(format!("Trailing escape in argument: {}", line)),
+            },
+            c => arg.push(c),
+        }
+    }
+
+    Ok(arg)
+}
+
+/// Parse the contents of a protoc response file. Each non-empty line is a single
+/// argument which is passed to protoc verbatim, allowing options to contain spaces
+/// or any number of `=` characters.
+fn parse_protoc_args_file(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Returns true if the given service option (e.g. `no_client`) was passed to the
+/// service generator. The client and server options are shared by tonic and
+/// Connect plugins.
+pub fn has_service_opt(extra_args: &[OsString], opt: &str) -> bool {
+    ["--tonic_opt=", "--connect_opt="].iter().any(|prefix| {
+        extra_args
+            .iter()
+            .any(|arg| *arg == *format!("{}{}", prefix, opt))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use super::super::replace_file_name_suffix;
+
+    use std::ffi::{OsStr, OsString};
+
+    use std::path::Path;
+    use std::path::PathBuf;
+
+    #[test]
+    fn parse_args_exotic_paths_test() {
+        let output_base = "/home/user name/.cache/bazel/_bazel_user/a=b c";
+        let bin_dir = format!("{}/execroot/_main/bazel-out/k8-fastbuild/bin", output_base);
+        let args = Args::parse_from([
+            format!("--protoc={}/external/protobuf/protoc", output_base),
+            format!("--prost_out={}", bin_dir),
+            format!("--out_librs={}/pkg/my proto.lib.rs", bin_dir),
+            "--crate_name=my_proto".to_string(),
+            format!(
+                "--package_info_output={}/pkg/my=proto.prost_package_info",
+                bin_dir
+            ),
+            format!(
+                "--descriptor_set={}/pkg/my proto-descriptor-set.proto.bin",
+                bin_dir
+            ),
+            format!("--additional_src={}/pkg/a,b.rs", bin_dir),
+            format!("--additional_src={}/pkg/c=d.rs", bin_dir),
+            "--label=@@repo~//pkg with space:my=proto".to_string(),
+            format!("-Ipkg/my proto.proto={}/pkg/my proto.proto", output_base),
+            "--prost_opt=type_attribute=.=#[derive(Eq)]".to_string(),
+            "--experimental_allow_proto3_optional".to_string(),
+            "pkg/my proto.proto".to_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            args.protoc,
+            PathBuf::from(format!("{}/external/protobuf/protoc", output_base))
+        );
+        assert_eq!(args.out_dir, PathBuf::from(&bin_dir));
+        assert_eq!(
+            args.out_librs,
+            PathBuf::from(format!("{}/pkg/my proto.lib.rs", bin_dir))
+        );
+        assert_eq!(args.crate_name, "my_proto");
+        assert_eq!(
+            args.package_info_file,
+            PathBuf::from(format!("{}/pkg/my=proto.prost_package_info", bin_dir))
+        );
+        assert_eq!(
+            args.descriptor_set,
+            PathBuf::from(format!("{}/pkg/my proto-descriptor-set.proto.bin", bin_dir))
+        );
+        assert_eq!(
+            args.additional_srcs,
+            vec![
+                PathBuf::from(format!("{}/pkg/a,b.rs", bin_dir)),
+                PathBuf::from(format!("{}/pkg/c=d.rs", bin_dir)),
+            ]
+        );
+        assert_eq!(args.label, "@@repo~//pkg with space:my=proto");
+        assert_eq!(
+            args.includes,
+            vec![OsString::from(format!(
+                "pkg/my proto.proto={}/pkg/my proto.proto",
+                output_base
+            ))]
+        );
+        assert_eq!(args.proto_files, vec![PathBuf::from("pkg/my proto.proto")]);
+        assert_eq!(
+            args.extra_args,
+            vec![
+                OsString::from("--prost_opt=type_attribute=.=#[derive(Eq)]"),
+                OsString::from("--experimental_allow_proto3_optional"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_args_unicode_paths_test() {
+        let args = Args::parse_from([
+            "--protoc=/cache/プロトコル/protoc",
+            "--prost_out=/out/données",
+            "--out_librs=/out/données/lib.rs",
+            "--crate_name=unicode_proto",
+            "--package_info_output=/out/données/🦀.prost_package_info",
+            "--descriptor_set=/out/données/descriptor.bin",
+            "--label=//données:🦀",
+            "-I/src/中文=/src/中文",
+            "/src/中文/消息.proto",
+        ])
+        .unwrap();
+
+        assert_eq!(args.protoc, PathBuf::from("/cache/プロトコル/protoc"));
+        assert_eq!(args.out_dir, PathBuf::from("/out/données"));
+        assert_eq!(
+            args.package_info_file,
+            PathBuf::from("/out/données/🦀.prost_package_info")
+        );
+        assert_eq!(args.label, "//données:🦀");
+        assert_eq!(args.includes, vec![OsString::from("/src/中文=/src/中文")]);
+        assert_eq!(
+            args.proto_files,
+            vec![PathBuf::from("/src/中文/消息.proto")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_args_non_utf8_paths_test() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = |bytes: &[u8]| OsStr::from_bytes(bytes).to_os_string();
+        let flag = |name: &str, value: &[u8]| os_flag(name, path(value));
+
+        let args = Args::parse_from([
+            flag("--protoc", b"/cache/\xff/protoc"),
+            flag("--prost_out", b"/out/\xfe\xff"),
+            flag("--out_librs", b"/out/\xfe\xff/lib.rs"),
+            OsString::from("--crate_name=non_utf8"),
+            flag("--package_info_output", b"/out/\xfe\xff/info"),
+            flag("--descriptor_set", b"/out/\xfe\xff/descriptor.bin"),
+            OsString::from("--label=//pkg:non_utf8"),
+            flag("--proto_path", b"/src/\x80"),
+            path(b"-I/src/\x80"),
+            path(b"/src/\x80/a.proto"),
+        ])
+        .unwrap();
+
+        assert_eq!(args.protoc, PathBuf::from(path(b"/cache/\xff/protoc")));
+        assert_eq!(args.out_dir, PathBuf::from(path(b"/out/\xfe\xff")));
+        assert_eq!(args.proto_paths, vec![path(b"/src/\x80")]);
+        assert_eq!(args.includes, vec![path(b"/src/\x80")]);
+        assert_eq!(
+            args.proto_files,
+            vec![PathBuf::from(path(b"/src/\x80/a.proto"))]
+        );
+
+        // Paths are passed through to protoc byte for byte.
+        assert_eq!(
+            render_args_file(&[flag("--prost_out", b"/out/\xfe\xff"), path(b"-I/src/\x80")]),
+            b"--prost_out=/out/\xfe\xff\n-I/src/\x80".to_vec()
+        );
+
+        // Generated file names are rewritten without touching the parent directory.
+        assert_eq!(
+            replace_file_name_suffix(
+                Path::new(&path(b"/out/\xfe\xff/foo.bar.tonic.rs")),
+                ".tonic.rs",
+                ".rs"
+            ),
+            Some(PathBuf::from(path(b"/out/\xfe\xff/foo.bar.rs")))
+        );
+
+        // Flags which must be strings report an error instead of panicking.
+        let err = Args::parse_from([flag("--label", b"//pkg:\xff")])
+            .err()
+            .unwrap();
+        assert!(err.contains("--label"), "{}", err);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn parse_args_non_utf16_paths_test() {
+        use std::os::windows::ffi::OsStringExt;
+
+        // An unpaired surrogate is valid in a Windows path but not in UTF-8.
+        let path = |prefix: &str| {
+            let mut wide: Vec<u16> = prefix.encode_utf16().collect();
+            wide.push(0xD800);
+            OsString::from_wide(&wide)
+        };
+
+        let args = Args::parse_from([
+            OsString::from("--protoc=C:\\protoc.exe"),
+            path("--prost_out=C:\\out\\"),
+            OsString::from("--out_librs=C:\\out\\lib.rs"),
+            OsString::from("--crate_name=non_utf16"),
+            OsString::from("--package_info_output=C:\\out\\info"),
+            OsString::from("--descriptor_set=C:\\out\\descriptor.bin"),
+            OsString::from("--label=//pkg:non_utf16"),
+            path("C:\\src\\"),
+        ])
+        .unwrap();
+
+        assert_eq!(args.out_dir, PathBuf::from(path("C:\\out\\")));
+        assert_eq!(args.proto_files, vec![PathBuf::from(path("C:\\src\\"))]);
+    }
+
+    #[test]
+    fn prost_version_test() {
+        assert_eq!(
+            ProstVersion::parse("0.11").unwrap(),
+            ProstVersion::new(0, 11)
+        );
+        assert_eq!(
+            ProstVersion::parse("0.13.1").unwrap(),
+            ProstVersion::new(0, 13)
+        );
+        assert!(ProstVersion::parse("0.10").is_err());
+        assert!(ProstVersion::parse("0").is_err());
+        assert!(ProstVersion::parse("0.x").is_err());
+        assert!(ProstVersion::parse("0.13.x").is_err());
+
+        assert!(!ProstVersion::new(0, 11).derives_enum_try_from());
+        assert!(ProstVersion::new(0, 12).derives_enum_try_from());
+        assert!(ProstVersion::LATEST.derives_enum_try_from());
+
+        let args = [OsString::from("--prost_opt=enable_type_names")];
+        assert!(ProstVersion::new(0, 12).check_args(&args).is_ok());
+        let err = ProstVersion::new(0, 11).check_args(&args).unwrap_err();
+        assert!(err.contains("enable_type_names"), "{}", err);
+        assert!(ProstVersion::new(0, 11)
+            .check_args(&[OsString::from("--prost_opt=type_attribute=.=#[derive(Eq)]")])
+            .is_ok());
+    }
+
+    #[test]
+    fn tonic_compat_test() {
+        assert_eq!(TonicCompat::parse("0.12").unwrap(), TonicCompat::new(0, 12));
+        assert!(TonicCompat::parse("0.9").is_err());
+        assert!(!TonicCompat::new(0, 10).uses_hyper_1());
+        assert!(TonicCompat::new(0, 12).uses_hyper_1());
+
+        assert!(TonicCompat::new(0, 12).check_runtime("0.12.3").is_ok());
+        let err = TonicCompat::new(0, 12).check_runtime("0.10.2").unwrap_err();
+        assert!(err.contains("0.10.2"), "{}", err);
+        assert!(err.contains("`0.12`"), "{}", err);
+
+        let args = [OsString::from("--tonic_opt=generate_default_stubs")];
+        assert!(TonicCompat::new(0, 12).check_args(&args).is_ok());
+        assert!(TonicCompat::new(0, 10).check_args(&args).is_err());
+
+        let base_args = [
+            "--protoc=protoc",
+            "--prost_out=out",
+            "--out_librs=out/lib.rs",
+            "--crate_name=my_proto",
+            "--package_info_output=out/info",
+            "--descriptor_set=out/descriptor.bin",
+            "--label=//pkg:my_proto",
+        ];
+
+        let args = Args::parse_from(base_args.iter().chain(&[
+            "--is_tonic",
+            "--tonic_compat=0.10",
+            "--tonic_runtime_version=0.10.2",
+        ]))
+        .unwrap();
+        assert!(args.is_tonic);
+
+        let err = Args::parse_from(base_args.iter().chain(&[
+            "--is_tonic",
+            "--tonic_compat=0.12",
+            "--tonic_runtime_version=0.10.2",
+        ]))
+        .err()
+        .unwrap();
+        assert!(err.contains("tonic runtime"), "{}", err);
+
+        let err = Args::parse_from(base_args.iter().chain(&["--tonic_compat=0.12"]))
+            .err()
+            .unwrap();
+        assert!(err.contains("--tonic_compat"), "{}", err);
+    }
+
+    #[test]
+    fn parse_args_connect_test() {
+        let base_args = [
+            "--protoc=protoc",
+            "--prost_out=out",
+            "--out_librs=out/lib.rs",
+            "--crate_name=my_proto",
+            "--package_info_output=out/info",
+            "--descriptor_set=out/descriptor.bin",
+            "--label=//pkg:my_proto",
+        ];
+
+        let args = Args::parse_from(base_args.iter().chain(&[
+            "--is_connect",
+            "--connect_opt=no_server",
+            "--prost_opt=compile_well_known_types",
+        ]))
+        .unwrap();
+        assert!(args.is_connect);
+        assert!(has_service_opt(&args.extra_args, "no_server"));
+        assert!(!has_service_opt(&args.extra_args, "no_client"));
+
+        let err = Args::parse_from(base_args.iter().chain(&["--is_connect", "--is_tonic"]))
+            .err()
+            .unwrap();
+        assert!(err.contains("--is_connect"), "{}", err);
+    }
+
+    #[test]
+    fn parse_args_scaffold_only_test() {
+        let args = Args::parse_from([
+            "--scaffold_only",
+            "--is_tonic",
+            "--crate_name=my_proto",
+            "--descriptor_set=out/descriptor.bin",
+            "--label=//pkg:my_proto",
+            "--scaffold_output=out/my_proto.tonic_scaffold.rs",
+        ])
+        .unwrap();
+        assert!(args.scaffold_only);
+        assert_eq!(
+            args.scaffold_output,
+            Some(PathBuf::from("out/my_proto.tonic_scaffold.rs"))
+        );
+
+        let err = Args::parse_from([
+            "--scaffold_only",
+            "--is_tonic",
+            "--crate_name=my_proto",
+            "--descriptor_set=out/descriptor.bin",
+            "--label=//pkg:my_proto",
+        ])
+        .err()
+        .unwrap();
+        assert!(err.contains("--scaffold_output"), "{}", err);
+    }
+
+    #[test]
+    fn parse_args_missing_required_test() {
+        let err = Args::parse_from(["--protoc=protoc".to_string()])
+            .err()
+            .unwrap();
+        assert!(err.contains("--prost_out"), "{}", err);
+    }
+
+    #[test]
+    fn parse_argfile_line_test() {
+        // Unquoted lines are used verbatim.
+        assert_eq!(
+            parse_argfile_line("--prost_out=/a b/c=d").unwrap(),
+            "--prost_out=/a b/c=d"
+        );
+        assert_eq!(
+            parse_argfile_line("--label=//foo:it's").unwrap(),
+            "--label=//foo:it's"
+        );
+
+        // Quoted lines are unquoted using shell rules.
+        assert_eq!(
+            parse_argfile_line("'--prost_out=/a b/c=d'").unwrap(),
+            "--prost_out=/a b/c=d"
+        );
+        assert_eq!(
+            parse_argfile_line("'--label=//foo:it'\\''s'").unwrap(),
+            "--label=//foo:it's"
+        );
+        assert_eq!(
+            parse_argfile_line("\"--opt=\\\"quoted\\\" \\n\"").unwrap(),
+            "--opt=\"quoted\" \\n"
+        );
+
+        assert!(parse_argfile_line("'--prost_out=/a b").is_err());
+        assert!(parse_argfile_line("\"--prost_out=/a b").is_err());
+    }
+
+    #[test]
+    fn parse_protoc_args_file_test() {
+        let content = [
+            "--prost_opt=type_attribute=.foo.Bar=#[derive(Hash, Eq)]",
+            "",
+            "--tonic_opt=server_mod_attribute=.=#[cfg(feature = \"server\")]",
+            "--experimental_allow_proto3_optional",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(
+            parse_protoc_args_file(&content),
+            vec![
+                "--prost_opt=type_attribute=.foo.Bar=#[derive(Hash, Eq)]".to_string(),
+                "--tonic_opt=server_mod_attribute=.=#[cfg(feature = \"server\")]".to_string(),
+                "--experimental_allow_proto3_optional".to_string(),
+            ]
+        );
+    }
+}
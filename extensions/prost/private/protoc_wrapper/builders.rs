@@ -0,0 +1,330 @@
+//! Generation of builders for the messages of the requested packages.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, FileDescriptorSet};
+
+use super::{escape_keyword, get_local_types, nested_rust_type_path, FieldTypeResolver};
+
+/// Generate an expression converting `value` into the type of a field.
+///
+/// The value is first converted into the type the setter was declared with and
+/// then into the field type, allowing for prost options such as `bytes` or boxed
+/// recursive messages which change the type of the field.
+fn convert_into_field(value: &str, rust_type: &str) -> String {
+    format!(
+        "::core::convert::Into::into(::core::convert::Into::<{}>::into({}))",
+        rust_type, value
+    )
+}
+
+/// Generate builders for all messages in the given proto packages.
+///
+/// Each message `Foo` gains a `Foo::builder()` constructor returning a `FooBuilder`
+/// with a setter per field. The generated code is returned per proto package and is
+/// expected to be placed in that package's module.
+pub(crate) fn generate_message_builders(
+    descriptor_set: &FileDescriptorSet,
+    packages: &BTreeSet<String>,
+    dep_extern_paths: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let local_types = get_local_types(descriptor_set);
+    let resolver = FieldTypeResolver {
+        local_types: &local_types,
+        dep_extern_paths,
+    };
+
+    let mut builders: BTreeMap<String, String> = BTreeMap::new();
+    for file in descriptor_set.file.iter() {
+        if !packages.contains(file.package()) {
+            continue;
+        }
+
+        let is_proto3 = file.syntax() == "proto3";
+        for message_type in file.message_type.iter() {
+            generate_message_builder(
+                builders.entry(file.package().to_string()).or_default(),
+                &resolver,
+                file.package(),
+                is_proto3,
+                &mut Vec::new(),
+                message_type,
+            );
+        }
+    }
+
+    builders
+}
+
+/// Generate a builder for a message and all of its nested messages.
+fn generate_message_builder(
+    content: &mut String,
+    resolver: &FieldTypeResolver,
+    package: &str,
+    is_proto3: bool,
+    parents: &mut Vec<String>,
+    message_type: &DescriptorProto,
+) {
+    // Map entries are generated as `HashMap`s rather than messages.
+    if message_type
+        .options
+        .as_ref()
+        .is_some_and(|options| options.map_entry())
+    {
+        return;
+    }
+
+    let parent_names = parents.iter().map(String::as_str).collect::<Vec<_>>();
+    let message_path = nested_rust_type_path(&parent_names, message_type.name());
+    let message_module = parent_names
+        .iter()
+        .chain([&message_type.name()])
+        .map(|name| escape_keyword(name.to_snake_case()))
+        .collect::<Vec<_>>()
+        .join("::");
+    let builder_name = format!(
+        "{}{}Builder",
+        parent_names
+            .iter()
+            .map(|parent| parent.to_upper_camel_case())
+            .collect::<String>(),
+        message_type.name().to_upper_camel_case()
+    );
+
+    let mut setters = Vec::new();
+    for field in message_type.field.iter() {
+        let setter_name = field.name().to_snake_case();
+        if setter_name == "build" {
+            continue;
+        }
+        let setter_name = escape_keyword(setter_name);
+        let field_name = setter_name.clone();
+        let doc = format!("    /// Sets the `{}` field.\n", field.name());
+
+        let map_entry = message_type.nested_type.iter().find(|nested_type| {
+            field.r#type() == Type::Message
+                && field.type_name().rsplit('.').next() == Some(nested_type.name())
+                && nested_type
+                    .options
+                    .as_ref()
+                    .is_some_and(|options| options.map_entry())
+        });
+
+        if let Some(map_entry) = map_entry {
+            let key_value = map_entry
+                .field
+                .iter()
+                .map(|field| resolver.resolve(package, field))
+                .collect::<Option<Vec<_>>>();
+            if let Some([key_type, value_type]) = key_value.as_deref() {
+                setters.push(format!(
+                    "{doc}    pub fn {setter_name}<K, V>(mut self, entries: impl ::core::iter::IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: ::core::convert::Into<{key_type}>,
+        V: ::core::convert::Into<{value_type}>,
+    {{
+        self.inner.{field_name} = entries
+            .into_iter()
+            .map(|(key, value)| ({}, {}))
+            .collect();
+        self
+    }}
+",
+                    convert_into_field("key", key_type),
+                    convert_into_field("value", value_type),
+                ));
+            }
+            continue;
+        }
+
+        let rust_type = match resolver.resolve(package, field) {
+            Some(rust_type) => rust_type,
+            None => continue,
+        };
+
+        let is_oneof = field.oneof_index.is_some() && !field.proto3_optional();
+        let assignment = if field.label() == Label::Repeated {
+            setters.push(format!(
+                "{doc}    pub fn {setter_name}(mut self, values: impl ::core::iter::IntoIterator<Item = impl ::core::convert::Into<{rust_type}>>) -> Self {{
+        self.inner.{field_name} = values
+            .into_iter()
+            .map(|value| {})
+            .collect();
+        self
+    }}
+",
+                convert_into_field("value", &rust_type),
+            ));
+            continue;
+        } else if is_oneof {
+            let oneof = &message_type.oneof_decl[field.oneof_index() as usize];
+            format!(
+                "self.inner.{} = ::core::option::Option::Some({}::{}::{}({}));",
+                escape_keyword(oneof.name().to_snake_case()),
+                message_module,
+                oneof.name().to_upper_camel_case(),
+                field.name().to_upper_camel_case(),
+                convert_into_field("value", &rust_type),
+            )
+        } else if field.r#type() == Type::Message
+            || field.proto3_optional()
+            || (!is_proto3 && field.label() != Label::Required)
+        {
+            format!(
+                "self.inner.{field_name} = ::core::option::Option::Some({});",
+                convert_into_field("value", &rust_type),
+            )
+        } else {
+            format!(
+                "self.inner.{field_name} = {};",
+                convert_into_field("value", &rust_type),
+            )
+        };
+
+        setters.push(format!(
+            "{doc}    pub fn {setter_name}(mut self, value: impl ::core::convert::Into<{rust_type}>) -> Self {{
+        {assignment}
+        self
+    }}
+"
+        ));
+    }
+
+    content.push_str(&format!(
+        "impl {message_path} {{
+    /// Returns a builder for `{message_path}`.
+    pub fn builder() -> {builder_name} {{
+        {builder_name}::default()
+    }}
+}}
+/// A builder for `{message_path}`.
+#[derive(Clone, Debug, Default)]
+pub struct {builder_name} {{
+    inner: {message_path},
+}}
+impl {builder_name} {{
+{}    /// Returns the built message.
+    pub fn build(self) -> {message_path} {{
+        self.inner
+    }}
+}}
+",
+        setters.join("")
+    ));
+
+    parents.push(message_type.name().to_string());
+    for nested_type in message_type.nested_type.iter() {
+        generate_message_builder(content, resolver, package, is_proto3, parents, nested_type);
+    }
+    parents.pop();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+    use std::collections::BTreeSet;
+
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        OneofDescriptorProto,
+    };
+
+    #[test]
+    fn generate_message_builders_test() {
+        let field = |name: &str, field_type: Type, type_name: Option<&str>| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            r#type: Some(field_type as i32),
+            type_name: type_name.map(|type_name| type_name.to_string()),
+            label: Some(Label::Optional as i32),
+            ..FieldDescriptorProto::default()
+        };
+
+        let descriptor_set = FileDescriptorSet {
+            file: vec![
+                FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    package: Some("foo".to_string()),
+                    syntax: Some("proto3".to_string()),
+                    message_type: vec![DescriptorProto {
+                        name: Some("Outer".to_string()),
+                        field: vec![
+                            field("name", Type::String, None),
+                            field("created", Type::Message, Some(".dep.Timestamp")),
+                            field("unknown", Type::Message, Some(".missing.Type")),
+                            FieldDescriptorProto {
+                                label: Some(Label::Repeated as i32),
+                                ..field("inner", Type::Message, Some(".foo.Outer.Inner"))
+                            },
+                            FieldDescriptorProto {
+                                oneof_index: Some(0),
+                                ..field("text", Type::String, None)
+                            },
+                        ],
+                        nested_type: vec![DescriptorProto {
+                            name: Some("Inner".to_string()),
+                            field: vec![field("type", Type::Int32, None)],
+                            ..DescriptorProto::default()
+                        }],
+                        oneof_decl: vec![OneofDescriptorProto {
+                            name: Some("value".to_string()),
+                            ..OneofDescriptorProto::default()
+                        }],
+                        ..DescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                },
+                FileDescriptorProto {
+                    name: Some("bar.proto".to_string()),
+                    package: Some("bar".to_string()),
+                    message_type: vec![DescriptorProto {
+                        name: Some("Skipped".to_string()),
+                        ..DescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                },
+            ],
+        };
+
+        let builders = generate_message_builders(
+            &descriptor_set,
+            &BTreeSet::from(["foo".to_string()]),
+            &BTreeMap::from([(".dep.Timestamp".to_string(), "::dep::Timestamp".to_string())]),
+        );
+        assert_eq!(builders.keys().collect::<Vec<_>>(), vec!["foo"]);
+
+        let content = &builders["foo"];
+        assert!(
+            content.contains("pub fn builder() -> OuterBuilder {"),
+            "{}",
+            content
+        );
+        assert!(
+            content.contains("pub fn builder() -> OuterInnerBuilder {"),
+            "{}",
+            content
+        );
+        assert!(content.contains("impl outer::Inner {"), "{}", content);
+        assert!(content.contains(
+            "pub fn name(mut self, value: impl ::core::convert::Into<::prost::alloc::string::String>) -> Self {"
+        ), "{}", content);
+        assert!(content.contains(
+            "pub fn created(mut self, value: impl ::core::convert::Into<::dep::Timestamp>) -> Self {"
+        ), "{}", content);
+        assert!(content.contains(
+            "values: impl ::core::iter::IntoIterator<Item = impl ::core::convert::Into<outer::Inner>>"
+        ), "{}", content);
+        assert!(
+            content.contains("self.inner.value = ::core::option::Option::Some(outer::Value::Text("),
+            "{}",
+            content
+        );
+        assert!(content.contains("pub fn r#type(mut self"), "{}", content);
+        assert!(!content.contains("pub fn unknown("), "{}", content);
+    }
+}
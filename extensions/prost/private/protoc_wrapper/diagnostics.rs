@@ -0,0 +1,663 @@
+//! Checks of the descriptor set and the size of the generated crate, and the diagnostics
+//! reporting their results.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{cmp, fmt, panic};
+
+use prost_types::{DescriptorProto, FileDescriptorSet};
+
+use super::{
+    for_each_enum, for_each_message, generated_file_package, json_string, snake_cased_package_name,
+    ProtoPath,
+};
+
+/// The severity of a diagnostic reported by the wrapper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A warning or error reported by the wrapper.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// How severe the problem is.
+    pub severity: Severity,
+
+    /// The proto file the problem was found in, if it is specific to one.
+    pub proto_file: Option<String>,
+
+    /// A description of the problem.
+    pub message: String,
+
+    /// How the problem can be resolved, if known.
+    suggested_fix: Option<String>,
+}
+
+impl Diagnostic {
+    /// Create a warning about the given proto file.
+    pub fn warning(proto_file: &str, message: String, suggested_fix: &str) -> Self {
+        Self {
+            severity: Severity::Warning,
+            proto_file: Some(proto_file.to_string()),
+            message,
+            suggested_fix: Some(suggested_fix.to_string()),
+        }
+    }
+
+    /// Create an error about the given proto file.
+    pub fn error(proto_file: &str, message: String, suggested_fix: &str) -> Self {
+        Self {
+            severity: Severity::Error,
+            proto_file: Some(proto_file.to_string()),
+            message,
+            suggested_fix: Some(suggested_fix.to_string()),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(suggested_fix) = &self.suggested_fix {
+            write!(f, " {}", suggested_fix)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the diagnostics reported by the wrapper and writes them as JSON to
+/// the `--diagnostics_out` file, if one was requested.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    /// The file to write diagnostics to.
+    output: Option<PathBuf>,
+
+    /// The diagnostics reported so far.
+    entries: Arc<Mutex<Vec<Diagnostic>>>,
+}
+
+impl Diagnostics {
+    /// Create a collection which writes to the given output.
+    pub fn new(output: Option<PathBuf>) -> Self {
+        Self {
+            output,
+            entries: Arc::default(),
+        }
+    }
+
+    /// Lock the collected diagnostics, even if a panic occurred while they were held.
+    fn lock(&self) -> MutexGuard<'_, Vec<Diagnostic>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Report a warning and print it to stderr.
+    pub fn warn(&self, diagnostic: Diagnostic) {
+        eprintln!("Warning: {}", diagnostic);
+        self.lock().push(diagnostic);
+    }
+
+    /// Report an error and abort.
+    pub fn fail(&self, diagnostic: Diagnostic) -> ! {
+        let message = diagnostic.to_string();
+        self.lock().push(diagnostic);
+        panic!("{}", message);
+    }
+
+    /// Record panics as errors and write the diagnostics before the process exits
+    /// so failures are reported even when they are not raised through `fail`.
+    pub fn install_panic_hook(&self) {
+        if self.output.is_none() {
+            return;
+        }
+
+        let diagnostics = self.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let payload = info.payload();
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "The protoc wrapper panicked.".to_string());
+            {
+                let mut entries = diagnostics.lock();
+                let reported = entries.last().is_some_and(|diagnostic| {
+                    diagnostic.severity == Severity::Error && diagnostic.to_string() == message
+                });
+                if !reported {
+                    entries.push(Diagnostic {
+                        severity: Severity::Error,
+                        proto_file: None,
+                        message,
+                        suggested_fix: None,
+                    });
+                }
+            }
+            diagnostics.write();
+            default_hook(info);
+        }));
+    }
+
+    /// Write the collected diagnostics to the output, if any.
+    pub fn write(&self) {
+        if let Some(output) = &self.output {
+            fs::write(output, render_diagnostics(&self.lock())).unwrap_or_else(|e| {
+                eprintln!("Failed to write diagnostics: {}\n{:?}", output.display(), e)
+            });
+        }
+    }
+}
+
+/// Render diagnostics as a JSON array of objects.
+fn render_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    if diagnostics.is_empty() {
+        return "[]\n".to_string();
+    }
+
+    let optional = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(json_string)
+            .unwrap_or_else(|| "null".to_string())
+    };
+    let entries = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            format!(
+                "  {{\n    \"severity\": {},\n    \"proto_file\": {},\n    \"message\": {},\n    \"suggested_fix\": {}\n  }}",
+                json_string(&diagnostic.severity.to_string()),
+                optional(&diagnostic.proto_file),
+                json_string(&diagnostic.message),
+                optional(&diagnostic.suggested_fix),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+    format!("[\n{}\n]\n", entries)
+}
+
+/// Ensure no two proto packages in the descriptor set map to the same Rust module.
+///
+/// Module names are the snake cased package names, so distinct packages normalizing
+/// to the same name (e.g. `Foo.Bar` and `foo.bar`, or `fooBar` and `foo_bar`) would
+/// otherwise be silently merged into one module.
+pub fn check_package_collisions(descriptor_set: &FileDescriptorSet) -> Result<(), Diagnostic> {
+    let mut modules: BTreeMap<String, (&str, &str)> = BTreeMap::new();
+
+    for file in descriptor_set.file.iter() {
+        let package = file.package();
+        let module_name = snake_cased_package_name(package);
+
+        match modules.get(&module_name) {
+            Some((other_package, other_file)) if *other_package != package => {
+                return Err(Diagnostic::error(
+                    file.name(),
+                    format!(
+                        "Proto packages `{}` (from `{}`) and `{}` (from `{}`) both normalize to the Rust module `{}`.",
+                        other_package,
+                        other_file,
+                        package,
+                        file.name(),
+                        module_name.replace('.', "::"),
+                    ),
+                    "Rename one of the packages so they map to different Rust modules.",
+                ));
+            }
+            Some(_) => {}
+            None => {
+                modules.insert(module_name, (package, file.name()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the types used by the proto files which are defined neither by the files
+/// themselves nor by a direct dependency. Prost resolves such types relative to
+/// the generated crate, which fails to compile.
+pub fn check_strict_deps(
+    descriptor_set: &FileDescriptorSet,
+    dep_extern_paths: &BTreeMap<String, String>,
+) -> Vec<Diagnostic> {
+    /// Collect the names of a message and its nested types along with the types
+    /// its fields refer to.
+    fn visit<'a>(
+        defined: &mut BTreeSet<String>,
+        used: &mut Vec<&'a str>,
+        parent_path: &ProtoPath,
+        message_type: &'a DescriptorProto,
+    ) {
+        let proto_path = parent_path.join(message_type.name());
+        defined.insert(format!(".{}", proto_path));
+        for enum_type in message_type.enum_type.iter() {
+            defined.insert(format!(".{}", proto_path.join(enum_type.name())));
+        }
+        used.extend(
+            message_type
+                .field
+                .iter()
+                .filter(|field| field.type_name.is_some())
+                .map(|field| field.type_name()),
+        );
+        for nested_type in message_type.nested_type.iter() {
+            visit(defined, used, &proto_path, nested_type);
+        }
+    }
+
+    let mut defined = BTreeSet::new();
+    let mut used_by_file = Vec::new();
+    for file in descriptor_set.file.iter() {
+        let package_path = ProtoPath(file.package().to_string());
+        let mut used = Vec::new();
+        for message_type in file.message_type.iter() {
+            visit(&mut defined, &mut used, &package_path, message_type);
+        }
+        for enum_type in file.enum_type.iter() {
+            defined.insert(format!(".{}", package_path.join(enum_type.name())));
+        }
+        for method in file
+            .service
+            .iter()
+            .flat_map(|service| service.method.iter())
+        {
+            used.extend([method.input_type(), method.output_type()]);
+        }
+        used_by_file.push((file.name(), used));
+    }
+
+    let mut diagnostics = Vec::new();
+    for (file_name, used) in used_by_file {
+        let missing = used
+            .into_iter()
+            .filter(|type_name| {
+                !defined.contains(*type_name) && !dep_extern_paths.contains_key(*type_name)
+            })
+            .collect::<BTreeSet<_>>();
+        for type_name in missing {
+            diagnostics.push(Diagnostic::warning(
+                file_name,
+                format!(
+                    "`{}` uses `{}` which is not defined by this target or any of its direct dependencies.",
+                    file_name, type_name
+                ),
+                &format!(
+                    "Add the `proto_library` defining `{}` to the `deps` of this target.",
+                    type_name.trim_start_matches('.')
+                ),
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+/// Find the imports of proto files which were generated by rust-protobuf. Their
+/// types do not implement `prost::Message` so prost types cannot contain them.
+pub fn check_protobuf_deps(
+    descriptor_set: &FileDescriptorSet,
+    dep_file_modules: &BTreeMap<String, String>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for file in descriptor_set.file.iter() {
+        for dependency in file
+            .dependency
+            .iter()
+            .filter(|dependency| dep_file_modules.contains_key(*dependency))
+        {
+            diagnostics.push(Diagnostic::warning(
+                file.name(),
+                format!(
+                    "`{}` imports `{}` which was generated with rust-protobuf and cannot be used by prost types.",
+                    file.name(),
+                    dependency
+                ),
+                "Generate both targets with the same backend.",
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+/// The number of packages listed when a size budget is exceeded.
+const SIZE_BUDGET_LARGEST_PACKAGES: usize = 5;
+
+/// The size of the code generated for a proto package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSize {
+    /// The proto package.
+    package: String,
+
+    /// The size in bytes of the protoc outputs for the package.
+    bytes: u64,
+
+    /// The number of messages and enums defined in the package.
+    types: u64,
+}
+
+/// Measure the protoc outputs and the types of each package.
+pub fn package_sizes(
+    rust_files: &BTreeSet<PathBuf>,
+    descriptor_set: &FileDescriptorSet,
+) -> Vec<PackageSize> {
+    let mut sizes: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for rust_file in rust_files {
+        if let Some(package) = generated_file_package(rust_file) {
+            let bytes = fs::metadata(rust_file)
+                .expect("Failed to read file metadata.")
+                .len();
+            sizes.entry(package).or_default().0 += bytes;
+        }
+    }
+    for_each_message(descriptor_set, |package, _, _, _| {
+        sizes.entry(package.to_string()).or_default().1 += 1;
+    });
+    for_each_enum(descriptor_set, |package, _, _, _| {
+        sizes.entry(package.to_string()).or_default().1 += 1;
+    });
+
+    sizes
+        .into_iter()
+        .map(|(package, (bytes, types))| PackageSize {
+            package,
+            bytes,
+            types,
+        })
+        .collect()
+}
+
+/// Limits on the size of a generated crate, past which compile times suffer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeBudget {
+    /// The maximum size in bytes of the generated lib.rs.
+    pub max_bytes: Option<u64>,
+
+    /// The maximum number of messages and enums in the crate.
+    pub max_types: Option<u64>,
+
+    /// Whether exceeding the budget is an error rather than a warning.
+    pub strict: bool,
+}
+
+impl SizeBudget {
+    /// Check the size of the generated crate against the budget, listing the
+    /// largest packages if it is exceeded.
+    pub fn check(&self, bytes: u64, package_sizes: &[PackageSize]) -> Option<Diagnostic> {
+        let types = package_sizes.iter().map(|size| size.types).sum::<u64>();
+        let mut exceeded = Vec::new();
+        if let Some(max_bytes) = self.max_bytes.filter(|max_bytes| bytes > *max_bytes) {
+            exceeded.push(format!("{} bytes (budget: {})", bytes, max_bytes));
+        }
+        if let Some(max_types) = self.max_types.filter(|max_types| types > *max_types) {
+            exceeded.push(format!("{} types (budget: {})", types, max_types));
+        }
+        if exceeded.is_empty() {
+            return None;
+        }
+
+        let mut largest = package_sizes.iter().collect::<Vec<_>>();
+        largest.sort_by_key(|size| cmp::Reverse((size.bytes, size.types)));
+        let largest = largest
+            .into_iter()
+            .take(SIZE_BUDGET_LARGEST_PACKAGES)
+            .map(|size| {
+                format!(
+                    "`{}` ({} bytes, {} types)",
+                    if size.package.is_empty() {
+                        "<no package>"
+                    } else {
+                        &size.package
+                    },
+                    size.bytes,
+                    size.types
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Some(Diagnostic {
+            severity: if self.strict {
+                Severity::Error
+            } else {
+                Severity::Warning
+            },
+            proto_file: None,
+            message: format!(
+                "The generated crate exceeds its size budget with {}. The largest packages are {}.",
+                exceeded.join(" and "),
+                largest.join(", ")
+            ),
+            suggested_fix: Some(
+                "Split the `proto_library` into smaller targets so each `rust_prost_library` compiles fewer packages."
+                    .to_string(),
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use prost_types::{
+        DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+        FileDescriptorSet,
+    };
+
+    #[test]
+    fn check_package_collisions_test() {
+        let file = |name: &str, package: &str| FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some(package.to_string()),
+            ..FileDescriptorProto::default()
+        };
+
+        {
+            // Files sharing a package are not collisions.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![file("a.proto", "foo.bar"), file("b.proto", "foo.bar")],
+            };
+            assert!(check_package_collisions(&descriptor_set).is_ok());
+        }
+        {
+            // Distinct packages are not collisions.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![file("a.proto", "foo.bar"), file("b.proto", "foo.baz")],
+            };
+            assert!(check_package_collisions(&descriptor_set).is_ok());
+        }
+        {
+            // Packages differing only by case collide.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![file("a.proto", "Foo.Bar"), file("b.proto", "foo.bar")],
+            };
+            let err = check_package_collisions(&descriptor_set)
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("`Foo.Bar` (from `a.proto`)"), "{}", err);
+            assert!(err.contains("`foo.bar` (from `b.proto`)"), "{}", err);
+            assert!(err.contains("`foo::bar`"), "{}", err);
+        }
+        {
+            // Packages normalizing to the same snake cased name collide.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![
+                    file("a.proto", "pkg.fooBar"),
+                    file("b.proto", "pkg.foo_bar"),
+                ],
+            };
+            let err = check_package_collisions(&descriptor_set)
+                .unwrap_err()
+                .to_string();
+            assert_eq!(
+                err,
+                "Proto packages `pkg.fooBar` (from `a.proto`) and `pkg.foo_bar` (from `b.proto`) both normalize to the Rust module `pkg::foo_bar`. Rename one of the packages so they map to different Rust modules."
+            );
+        }
+    }
+
+    #[test]
+    fn check_strict_deps_test() {
+        let field = |name: &str, type_name: &str| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            type_name: Some(type_name.to_string()),
+            ..FieldDescriptorProto::default()
+        };
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("a.proto".to_string()),
+                package: Some("pkg".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Outer".to_string()),
+                    field: vec![
+                        field("kind", ".pkg.Outer.Kind"),
+                        field("values", ".pkg.Outer.ValuesEntry"),
+                        field("dep", ".dep.Direct"),
+                        field("transitive", ".dep.Transitive"),
+                    ],
+                    enum_type: vec![EnumDescriptorProto {
+                        name: Some("Kind".to_string()),
+                        ..EnumDescriptorProto::default()
+                    }],
+                    nested_type: vec![DescriptorProto {
+                        name: Some("ValuesEntry".to_string()),
+                        field: vec![field("value", ".other.Value")],
+                        ..DescriptorProto::default()
+                    }],
+                    ..DescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        };
+        let dep_extern_paths =
+            BTreeMap::from([(".dep.Direct".to_string(), "::dep::dep::Direct".to_string())]);
+
+        let diagnostics = check_strict_deps(&descriptor_set, &dep_extern_paths);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "`a.proto` uses `.dep.Transitive` which is not defined by this target or any of its direct dependencies.",
+                "`a.proto` uses `.other.Value` which is not defined by this target or any of its direct dependencies.",
+            ]
+        );
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].proto_file.as_deref(), Some("a.proto"));
+        assert_eq!(
+            diagnostics[0].suggested_fix.as_deref(),
+            Some("Add the `proto_library` defining `dep.Transitive` to the `deps` of this target.")
+        );
+    }
+
+    #[test]
+    fn check_protobuf_deps_test() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("a.proto".to_string()),
+                dependency: vec!["prost.proto".to_string(), "protobuf.proto".to_string()],
+                ..FileDescriptorProto::default()
+            }],
+        };
+        let dep_file_modules = BTreeMap::from([(
+            "protobuf.proto".to_string(),
+            "::protobuf_proto::protobuf".to_string(),
+        )]);
+
+        let diagnostics = check_protobuf_deps(&descriptor_set, &dep_file_modules);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "`a.proto` imports `protobuf.proto` which was generated with rust-protobuf and cannot be used by prost types."
+        );
+    }
+
+    #[test]
+    fn render_diagnostics_test() {
+        assert_eq!(render_diagnostics(&[]), "[]\n");
+        assert_eq!(
+            render_diagnostics(&[
+                Diagnostic::warning("a.proto", "Uses `b`.".to_string(), "Add \"b\"."),
+                Diagnostic {
+                    severity: Severity::Error,
+                    proto_file: None,
+                    message: "Failed.".to_string(),
+                    suggested_fix: None,
+                },
+            ]),
+            r#"[
+  {
+    "severity": "warning",
+    "proto_file": "a.proto",
+    "message": "Uses `b`.",
+    "suggested_fix": "Add \"b\"."
+  },
+  {
+    "severity": "error",
+    "proto_file": null,
+    "message": "Failed.",
+    "suggested_fix": null
+  }
+]
+"#
+        );
+    }
+
+    #[test]
+    fn size_budget_test() {
+        let size = |package: &str, bytes: u64, types: u64| PackageSize {
+            package: package.to_string(),
+            bytes,
+            types,
+        };
+        let package_sizes = vec![size("", 10, 1), size("a", 300, 2), size("b", 200, 4)];
+
+        assert_eq!(SizeBudget::default().check(510, &package_sizes), None);
+        let budget = SizeBudget {
+            max_bytes: Some(1000),
+            max_types: Some(7),
+            strict: false,
+        };
+        assert_eq!(budget.check(510, &package_sizes), None);
+
+        let diagnostic = budget.check(1500, &package_sizes).unwrap();
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(
+            diagnostic.message,
+            "The generated crate exceeds its size budget with 1500 bytes (budget: 1000). The largest packages are `a` (300 bytes, 2 types), `b` (200 bytes, 4 types), `<no package>` (10 bytes, 1 types)."
+        );
+
+        let budget = SizeBudget {
+            max_types: Some(6),
+            strict: true,
+            ..budget
+        };
+        let diagnostic = budget.check(1500, &package_sizes).unwrap();
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert!(
+            diagnostic
+                .message
+                .contains("1500 bytes (budget: 1000) and 7 types (budget: 6)"),
+            "{}",
+            diagnostic.message
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Generation of helpers converting enums from and to their proto names.
+
+use std::collections::BTreeMap;
+
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, FileDescriptorSet};
+
+use super::{
+    escape_keyword, get_local_types, nested_rust_type_path, relative_rust_path, LocalType,
+};
+
+/// Generate conversion helpers for all enums in the descriptor set and typed
+/// accessor extension traits for every message field referencing one of them.
+///
+/// The generated code is returned per proto package and is expected to be placed
+/// in that package's module. `TryFrom<i32>` is only generated when
+/// `include_try_from` is set as newer versions of prost derive it for enumerations.
+pub(crate) fn generate_enum_helpers(
+    descriptor_set: &FileDescriptorSet,
+    include_try_from: bool,
+) -> BTreeMap<String, String> {
+    let local_enums = get_local_types(descriptor_set)
+        .into_iter()
+        .filter(|(_, local_type)| local_type.is_enum)
+        .collect::<BTreeMap<_, _>>();
+    let mut helpers: BTreeMap<String, String> = BTreeMap::new();
+
+    for enum_type in local_enums.values() {
+        let content = helpers.entry(enum_type.package.clone()).or_default();
+        let rust_path = &enum_type.rust_path;
+
+        if include_try_from {
+            content.push_str(&format!(
+                "impl ::core::convert::TryFrom<i32> for {rust_path} {{
+    type Error = i32;
+
+    fn try_from(value: i32) -> ::core::result::Result<Self, Self::Error> {{
+        Self::from_i32(value).ok_or(value)
+    }}
+}}
+"
+            ));
+        }
+
+        content.push_str(&format!(
+            "impl ::core::str::FromStr for {rust_path} {{
+    type Err = ::prost::alloc::string::String;
+
+    fn from_str(s: &str) -> ::core::result::Result<Self, Self::Err> {{
+        Self::from_str_name(s).ok_or_else(|| ::prost::alloc::string::ToString::to_string(s))
+    }}
+}}
+"
+        ));
+    }
+
+    for file in descriptor_set.file.iter() {
+        let is_proto3 = file.syntax() == "proto3";
+        for message_type in file.message_type.iter() {
+            generate_enum_field_accessors(
+                &mut helpers,
+                &local_enums,
+                file.package(),
+                is_proto3,
+                &mut Vec::new(),
+                message_type,
+            );
+        }
+    }
+
+    helpers
+}
+
+/// Generate an extension trait with typed accessors for the enum fields of a
+/// message and all of its nested messages.
+fn generate_enum_field_accessors(
+    helpers: &mut BTreeMap<String, String>,
+    local_enums: &BTreeMap<String, LocalType>,
+    package: &str,
+    is_proto3: bool,
+    parents: &mut Vec<String>,
+    message_type: &DescriptorProto,
+) {
+    // Map entries are generated as `HashMap`s rather than messages.
+    if message_type
+        .options
+        .as_ref()
+        .is_some_and(|options| options.map_entry())
+    {
+        return;
+    }
+
+    let mut signatures = Vec::new();
+    let mut bodies = Vec::new();
+    for field in message_type.field.iter() {
+        if field.r#type() != Type::Enum || (field.oneof_index.is_some() && !field.proto3_optional())
+        {
+            continue;
+        }
+        let enum_type = match local_enums.get(field.type_name()) {
+            Some(enum_type) => enum_type,
+            None => continue,
+        };
+
+        let enum_path = relative_rust_path(package, enum_type);
+        let field_name = escape_keyword(field.name().to_snake_case());
+        let method_name = format!("try_{}", field.name().to_snake_case());
+        let convert = format!("<{enum_path} as ::core::convert::TryFrom<i32>>::try_from");
+
+        let (return_type, body) = if field.label() == Label::Repeated {
+            (
+                format!("::prost::alloc::vec::Vec<{enum_path}>"),
+                format!("self.{field_name}.iter().map(|value| {convert}(*value).map_err(|_| *value)).collect()"),
+            )
+        } else if field.proto3_optional() || (!is_proto3 && field.label() == Label::Optional) {
+            (
+                format!("::core::option::Option<{enum_path}>"),
+                format!("self.{field_name}.map(|value| {convert}(value).map_err(|_| value)).transpose()"),
+            )
+        } else {
+            (
+                enum_path.clone(),
+                format!("{convert}(self.{field_name}).map_err(|_| self.{field_name})"),
+            )
+        };
+
+        let signature =
+            format!("fn {method_name}(&self) -> ::core::result::Result<{return_type}, i32>");
+        signatures.push(format!(
+            "    /// Returns the value of `{}`, or the raw value if it is not a known variant.\n    {signature};\n",
+            field.name()
+        ));
+        bodies.push(format!("    {signature} {{\n        {body}\n    }}\n"));
+    }
+
+    parents.push(message_type.name().to_string());
+
+    if !signatures.is_empty() {
+        let parent_names = parents.iter().map(String::as_str).collect::<Vec<_>>();
+        let message_path =
+            nested_rust_type_path(&parent_names[..parent_names.len() - 1], message_type.name());
+        let trait_name = format!(
+            "{}EnumFields",
+            parents
+                .iter()
+                .map(|parent| parent.to_upper_camel_case())
+                .collect::<String>()
+        );
+
+        helpers.entry(package.to_string()).or_default().push_str(&format!(
+            "/// Typed accessors for the enum fields of `{message_path}`.\npub trait {trait_name} {{\n{}}}\nimpl {trait_name} for {message_path} {{\n{}}}\n",
+            signatures.join(""),
+            bodies.join(""),
+        ));
+    }
+
+    for nested_type in message_type.nested_type.iter() {
+        generate_enum_field_accessors(
+            helpers,
+            local_enums,
+            package,
+            is_proto3,
+            parents,
+            nested_type,
+        );
+    }
+
+    parents.pop();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{
+        DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+        FileDescriptorSet,
+    };
+
+    #[test]
+    fn generate_enum_helpers_test() {
+        let enum_field = |name: &str, type_name: &str, label: Label| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            r#type: Some(Type::Enum as i32),
+            type_name: Some(type_name.to_string()),
+            label: Some(label as i32),
+            ..FieldDescriptorProto::default()
+        };
+
+        let descriptor_set = FileDescriptorSet {
+            file: vec![
+                FileDescriptorProto {
+                    name: Some("bar.proto".to_string()),
+                    package: Some("foo.bar".to_string()),
+                    syntax: Some("proto3".to_string()),
+                    enum_type: vec![EnumDescriptorProto {
+                        name: Some("Kind".to_string()),
+                        ..EnumDescriptorProto::default()
+                    }],
+                    message_type: vec![DescriptorProto {
+                        name: Some("Outer".to_string()),
+                        field: vec![
+                            enum_field("kind", ".foo.bar.Kind", Label::Optional),
+                            enum_field("all_kinds", ".foo.bar.Kind", Label::Repeated),
+                            FieldDescriptorProto {
+                                proto3_optional: Some(true),
+                                oneof_index: Some(0),
+                                ..enum_field("mode", ".foo.bar.Outer.Mode", Label::Optional)
+                            },
+                            enum_field("external", ".other.Kind", Label::Optional),
+                        ],
+                        enum_type: vec![EnumDescriptorProto {
+                            name: Some("Mode".to_string()),
+                            ..EnumDescriptorProto::default()
+                        }],
+                        ..DescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                },
+                FileDescriptorProto {
+                    name: Some("baz.proto".to_string()),
+                    package: Some("foo.baz".to_string()),
+                    syntax: Some("proto3".to_string()),
+                    message_type: vec![DescriptorProto {
+                        name: Some("Holder".to_string()),
+                        field: vec![enum_field("type", ".foo.bar.Kind", Label::Optional)],
+                        ..DescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                },
+            ],
+        };
+
+        let helpers = generate_enum_helpers(&descriptor_set, true);
+        assert_eq!(
+            helpers.keys().collect::<Vec<_>>(),
+            vec!["foo.bar", "foo.baz"]
+        );
+
+        let bar = &helpers["foo.bar"];
+        assert!(
+            bar.contains("impl ::core::str::FromStr for Kind {"),
+            "{}",
+            bar
+        );
+        assert!(
+            bar.contains("impl ::core::str::FromStr for outer::Mode {"),
+            "{}",
+            bar
+        );
+        assert!(
+            bar.contains("impl ::core::convert::TryFrom<i32> for Kind {"),
+            "{}",
+            bar
+        );
+        assert!(bar.contains("pub trait OuterEnumFields {"), "{}", bar);
+        assert!(
+            bar.contains("fn try_kind(&self) -> ::core::result::Result<Kind, i32>"),
+            "{}",
+            bar
+        );
+        assert!(bar.contains(
+            "fn try_all_kinds(&self) -> ::core::result::Result<::prost::alloc::vec::Vec<Kind>, i32>"
+        ), "{}", bar);
+        assert!(bar.contains(
+            "fn try_mode(&self) -> ::core::result::Result<::core::option::Option<outer::Mode>, i32>"
+        ), "{}", bar);
+        assert!(!bar.contains("try_external"), "{}", bar);
+
+        let baz = &helpers["foo.baz"];
+        assert!(
+            baz.contains("impl HolderEnumFields for Holder {"),
+            "{}",
+            baz
+        );
+        assert!(
+            baz.contains("fn try_type(&self) -> ::core::result::Result<super::bar::Kind, i32>"),
+            "{}",
+            baz
+        );
+        assert!(
+            baz.contains("(self.r#type).map_err(|_| self.r#type)"),
+            "{}",
+            baz
+        );
+
+        let helpers = generate_enum_helpers(&descriptor_set, false);
+        assert!(!helpers["foo.bar"].contains("TryFrom<i32> for"));
+    }
+}
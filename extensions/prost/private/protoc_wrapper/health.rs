@@ -0,0 +1,93 @@
+//! Generation of the module implementing the `grpc.health.v1` health service.
+
+use prost_types::FileDescriptorSet;
+
+use super::ProtoPath;
+
+/// The name of the module wiring the generated services into `tonic_health`.
+pub(crate) const HEALTH_MODULE_NAME: &str = "health";
+
+/// Generate the contents of a module registering every service in the
+/// descriptor set with a `tonic_health` reporter.
+pub(crate) fn generate_health_module(descriptor_set: &FileDescriptorSet) -> String {
+    let service_names = descriptor_set
+        .file
+        .iter()
+        .flat_map(|file| {
+            let package_path = ProtoPath(file.package().to_string());
+            file.service
+                .iter()
+                .map(move |service| package_path.join(service.name()))
+        })
+        .map(|service_name| format!("    \"{}\",\n", service_name))
+        .collect::<String>();
+
+    format!(
+        r#"//! Wiring of the services in this crate into the standard gRPC health service.
+
+/// The fully qualified names of all services in this crate.
+pub const SERVICE_NAMES: &[&str] = &[
+{service_names}];
+
+/// Create a health reporter and service with every service in this crate marked
+/// as serving. The service can be added to a `tonic` server directly.
+pub async fn health_service() -> (
+    ::tonic_health::server::HealthReporter,
+    ::tonic_health::pb::health_server::HealthServer<impl ::tonic_health::pb::health_server::Health>,
+) {{
+    let (mut reporter, service) = ::tonic_health::server::health_reporter();
+    set_serving_status(&mut reporter, ::tonic_health::ServingStatus::Serving).await;
+    (reporter, service)
+}}
+
+/// Set the status of every service in this crate.
+pub async fn set_serving_status(
+    reporter: &mut ::tonic_health::server::HealthReporter,
+    status: ::tonic_health::ServingStatus,
+) {{
+    for service_name in SERVICE_NAMES {{
+        reporter.set_service_status(service_name, status).await;
+    }}
+}}
+"#
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use prost_types::ServiceDescriptorProto;
+
+    use prost_types::{FileDescriptorProto, FileDescriptorSet};
+
+    #[test]
+    fn generate_health_module_test() {
+        let service = |name: &str| ServiceDescriptorProto {
+            name: Some(name.to_string()),
+            ..ServiceDescriptorProto::default()
+        };
+        let descriptor_set = FileDescriptorSet {
+            file: vec![
+                FileDescriptorProto {
+                    name: Some("a.proto".to_string()),
+                    package: Some("pkg.a".to_string()),
+                    service: vec![service("Greeter"), service("Farewell")],
+                    ..FileDescriptorProto::default()
+                },
+                FileDescriptorProto {
+                    name: Some("b.proto".to_string()),
+                    service: vec![service("Packageless")],
+                    ..FileDescriptorProto::default()
+                },
+            ],
+        };
+
+        let health_module = generate_health_module(&descriptor_set);
+        assert!(
+            health_module.contains("pub const SERVICE_NAMES: &[&str] = &[\n    \"pkg.a.Greeter\",\n    \"pkg.a.Farewell\",\n    \"Packageless\",\n];"),
+            "{}",
+            health_module
+        );
+    }
+}
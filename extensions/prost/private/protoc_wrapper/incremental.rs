@@ -0,0 +1,388 @@
+//! State of incremental generation, which restores the outputs of packages whose sources
+//! didn't change since the last run.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use prost::Message;
+use prost_types::FileDescriptorSet;
+
+use super::args::{render_args_file, split_flag};
+use protoc_wrapper_lib::{find_generated_rust_files, generated_file_package};
+
+/// The initial value of an FNV-1a hash.
+pub(crate) const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Feed bytes into an FNV-1a hash. Unlike `DefaultHasher` the result is stable
+/// across releases of Rust so it can be persisted.
+pub(crate) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// The paths of the plugins protoc is passed in `protoc_args`, as
+/// `--plugin=<name>=<path>` or `--plugin=<path>`.
+pub(crate) fn plugin_paths(protoc_args: &[OsString]) -> Vec<PathBuf> {
+    protoc_args
+        .iter()
+        .filter_map(|arg| match split_flag(arg)? {
+            ("--plugin", value) => Some(match split_flag(value) {
+                Some((_, path)) => PathBuf::from(path),
+                None => PathBuf::from(value),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Compute the key of the incremental state from the `options` of a generation,
+/// which cover everything besides the proto sources that affects its outputs,
+/// and the `tools` it runs. Tools are rebuilt in place, so they are identified
+/// by their size and modification time along with their path rather than by
+/// hashing their contents on every run.
+pub(crate) fn state_key<'a>(
+    options: &[OsString],
+    tools: impl IntoIterator<Item = &'a Path>,
+) -> u64 {
+    let mut key = fnv1a(FNV_OFFSET_BASIS, &render_args_file(options));
+    for tool in tools {
+        key = fnv1a(key, tool.as_os_str().as_encoded_bytes());
+        if let Ok(metadata) = fs::metadata(tool) {
+            key = fnv1a(key, &metadata.len().to_le_bytes());
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            key = fnv1a(key, &modified.as_nanos().to_le_bytes());
+        }
+    }
+    key
+}
+
+/// Find the source of a proto file given the `-I` and `--proto_path` values
+/// passed to protoc. Values may be directories or `<virtual path>=<real path>`
+/// mappings.
+fn resolve_proto_source(name: &str, search_paths: &[OsString]) -> Option<PathBuf> {
+    search_paths.iter().find_map(|search_path| {
+        let candidate = match split_flag(search_path) {
+            Some((virtual_path, real_path)) if virtual_path == name => PathBuf::from(real_path),
+            Some((virtual_path, real_path)) => {
+                let rest = name.strip_prefix(virtual_path)?.strip_prefix('/')?;
+                Path::new(real_path).join(rest)
+            }
+            None => Path::new(search_path).join(name),
+        };
+        candidate.is_file().then_some(candidate)
+    })
+}
+
+/// Parse the files imported by a proto source.
+fn parse_proto_imports(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim_start().strip_prefix("import")?;
+            let (_, rest) = rest.split_once('"')?;
+            let (import, _) = rest.split_once('"')?;
+            Some(import.to_string())
+        })
+        .collect()
+}
+
+/// Compute a fingerprint for every package in the descriptor set covering the
+/// sources of its proto files and their transitive imports along with `key`.
+///
+/// Files whose sources cannot be found are fingerprinted by their name and
+/// descriptor, if available.
+pub(crate) fn package_fingerprints(
+    descriptor_set: &FileDescriptorSet,
+    search_paths: &[OsString],
+    key: u64,
+) -> BTreeMap<String, u64> {
+    let descriptors = descriptor_set
+        .file
+        .iter()
+        .map(|file| (file.name(), file))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut fingerprints = BTreeMap::new();
+    for file in descriptor_set.file.iter() {
+        let fingerprint = fingerprints
+            .entry(file.package().to_string())
+            .or_insert(key);
+
+        let mut pending = vec![file.name().to_string()];
+        let mut visited = BTreeSet::new();
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            *fingerprint = fnv1a(*fingerprint, name.as_bytes());
+            match resolve_proto_source(&name, search_paths)
+                .and_then(|path| fs::read_to_string(path).ok())
+            {
+                Some(source) => {
+                    *fingerprint = fnv1a(*fingerprint, source.as_bytes());
+                    pending.extend(parse_proto_imports(&source));
+                }
+                None => {
+                    if let Some(descriptor) = descriptors.get(name.as_str()) {
+                        *fingerprint = fnv1a(*fingerprint, &descriptor.encode_to_vec());
+                        pending.extend(descriptor.dependency.iter().cloned());
+                    }
+                }
+            }
+        }
+    }
+
+    fingerprints
+}
+
+/// Protoc outputs persisted between runs to avoid regenerating unchanged packages.
+#[derive(Debug)]
+pub(crate) struct IncrementalState {
+    /// The directory holding the manifest and the outputs of each package.
+    dir: PathBuf,
+
+    /// Package fingerprints recorded by the previous run.
+    previous: BTreeMap<String, u64>,
+
+    /// Package fingerprints of the current run.
+    current: BTreeMap<String, u64>,
+}
+
+impl IncrementalState {
+    /// Load the state recorded by the previous run. A missing or unreadable
+    /// manifest causes all packages to be regenerated.
+    pub(crate) fn load(dir: PathBuf, current: BTreeMap<String, u64>) -> Self {
+        let previous = fs::read_to_string(dir.join("manifest"))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (fingerprint, package) = line.split_once(' ')?;
+                Some((
+                    package.to_string(),
+                    u64::from_str_radix(fingerprint, 16).ok()?,
+                ))
+            })
+            .collect();
+
+        Self {
+            dir,
+            previous,
+            current,
+        }
+    }
+
+    /// The packages which need to be regenerated.
+    pub(crate) fn changed_packages(&self) -> BTreeSet<String> {
+        self.current
+            .iter()
+            .filter(|(package, fingerprint)| self.previous.get(*package) != Some(fingerprint))
+            .map(|(package, _)| package.clone())
+            .collect()
+    }
+
+    /// Persist the outputs of regenerated packages found in `out_dir` and restore
+    /// the outputs of all other packages into it.
+    pub(crate) fn update(
+        &self,
+        out_dir: &Path,
+        changed_packages: &BTreeSet<String>,
+    ) -> io::Result<()> {
+        let fragments_dir = self.dir.join("fragments");
+        fs::create_dir_all(&fragments_dir)?;
+
+        // Invalidate the manifest first so an interrupted update forces a full
+        // regeneration on the next run.
+        let manifest = self.dir.join("manifest");
+        if manifest.exists() {
+            fs::remove_file(&manifest)?;
+        }
+
+        for entry in fs::read_dir(&fragments_dir)? {
+            let path = entry?.path();
+            let package = generated_file_package(&path);
+            match package {
+                Some(package) if !changed_packages.contains(&package) => {
+                    fs::copy(&path, out_dir.join(path.file_name().unwrap()))?;
+                }
+                _ => fs::remove_file(&path)?,
+            }
+        }
+
+        for path in find_generated_rust_files(out_dir) {
+            let is_changed = generated_file_package(&path)
+                .is_some_and(|package| changed_packages.contains(&package));
+            if is_changed {
+                fs::copy(&path, fragments_dir.join(path.file_name().unwrap()))?;
+            }
+        }
+
+        fs::write(
+            manifest,
+            self.current
+                .iter()
+                .map(|(package, fingerprint)| format!("{:016x} {}\n", fingerprint, package))
+                .collect::<String>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use super::super::args::os_flag;
+
+    use std::collections::BTreeSet;
+
+    use std::fs;
+
+    use prost_types::{FileDescriptorProto, FileDescriptorSet};
+    use std::{env, process};
+
+    #[test]
+    fn parse_proto_imports_test() {
+        let source = r#"
+syntax = "proto3";
+
+import "google/protobuf/timestamp.proto";
+import public "pkg/public.proto";
+  import weak "pkg/weak.proto";
+
+// import "commented.proto" is not an import statement.
+message Imported {}
+"#;
+        assert_eq!(
+            parse_proto_imports(source),
+            vec![
+                "google/protobuf/timestamp.proto".to_string(),
+                "pkg/public.proto".to_string(),
+                "pkg/weak.proto".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn plugin_paths_test() {
+        let protoc_args = [
+            OsString::from("--plugin=protoc-gen-prost=bazel-out/k8-opt-exec/bin/prost_plugin"),
+            OsString::from("--plugin=bazel-out/k8-opt-exec/bin/protoc-gen-tonic"),
+            OsString::from("--prost_opt=compile_well_known_types"),
+        ];
+        assert_eq!(
+            plugin_paths(&protoc_args),
+            vec![
+                PathBuf::from("bazel-out/k8-opt-exec/bin/prost_plugin"),
+                PathBuf::from("bazel-out/k8-opt-exec/bin/protoc-gen-tonic"),
+            ]
+        );
+    }
+
+    #[test]
+    fn state_key_test() {
+        let dir = env::temp_dir().join(format!("state_key_test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let plugin = dir.join("protoc-gen-prost");
+        fs::write(&plugin, "plugin").unwrap();
+        let options = [OsString::from("--prost_version=0.13")];
+
+        let key = state_key(&options, [plugin.as_path()]);
+        assert_eq!(key, state_key(&options, [plugin.as_path()]));
+        assert_ne!(
+            key,
+            state_key(
+                &[OsString::from("--prost_version=0.12")],
+                [plugin.as_path()]
+            )
+        );
+
+        // Rebuilding a tool in place invalidates the state.
+        fs::write(&plugin, "rebuilt plugin").unwrap();
+        assert_ne!(key, state_key(&options, [plugin.as_path()]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn incremental_state_test() {
+        let dir = env::temp_dir().join(format!("incremental_state_test-{}", process::id()));
+        let src_dir = dir.join("src");
+        let out_dir = dir.join("out");
+        let state_dir = dir.join("state");
+        fs::create_dir_all(src_dir.join("pkg")).unwrap();
+        fs::create_dir_all(&out_dir).unwrap();
+
+        fs::write(src_dir.join("pkg/a.proto"), "import \"pkg/common.proto\";").unwrap();
+        fs::write(src_dir.join("pkg/common.proto"), "message Common {}").unwrap();
+        fs::write(src_dir.join("b.proto"), "message B {}").unwrap();
+
+        let descriptor_set = FileDescriptorSet {
+            file: vec![
+                FileDescriptorProto {
+                    name: Some("pkg/a.proto".to_string()),
+                    package: Some("pkg.a".to_string()),
+                    ..FileDescriptorProto::default()
+                },
+                FileDescriptorProto {
+                    name: Some("b.proto".to_string()),
+                    package: Some("pkg.b".to_string()),
+                    ..FileDescriptorProto::default()
+                },
+            ],
+        };
+        let mut search_paths = vec![os_flag("b.proto", src_dir.join("b.proto"))];
+        search_paths.push(src_dir.clone().into_os_string());
+        let fingerprints = || package_fingerprints(&descriptor_set, &search_paths, 0);
+
+        // Everything is generated on the first run.
+        let state = IncrementalState::load(state_dir.clone(), fingerprints());
+        let changed = state.changed_packages();
+        assert_eq!(
+            changed,
+            BTreeSet::from(["pkg.a".to_string(), "pkg.b".to_string()])
+        );
+        fs::write(out_dir.join("pkg.a.rs"), "// a").unwrap();
+        fs::write(out_dir.join("pkg.b.rs"), "// b").unwrap();
+        state.update(&out_dir, &changed).unwrap();
+
+        // Nothing changed so outputs are restored from the state.
+        let state = IncrementalState::load(state_dir.clone(), fingerprints());
+        assert!(state.changed_packages().is_empty());
+
+        // Changing a transitive import only regenerates the importing package.
+        fs::write(src_dir.join("pkg/common.proto"), "message Common { }").unwrap();
+        let state = IncrementalState::load(state_dir.clone(), fingerprints());
+        let changed = state.changed_packages();
+        assert_eq!(changed, BTreeSet::from(["pkg.a".to_string()]));
+
+        fs::remove_dir_all(&out_dir).unwrap();
+        fs::create_dir_all(&out_dir).unwrap();
+        fs::write(out_dir.join("pkg.a.rs"), "// a2").unwrap();
+        state.update(&out_dir, &changed).unwrap();
+        assert_eq!(
+            fs::read_to_string(out_dir.join("pkg.a.rs")).unwrap(),
+            "// a2"
+        );
+        assert_eq!(
+            fs::read_to_string(out_dir.join("pkg.b.rs")).unwrap(),
+            "// b"
+        );
+
+        let state = IncrementalState::load(state_dir.clone(), fingerprints());
+        assert!(state.changed_packages().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
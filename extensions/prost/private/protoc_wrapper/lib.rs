@@ -1,14 +1,19 @@
-//! A process wrapper for running a Protobuf compiler configured for Prost or Tonic output in a Bazel rule.
+//! Argument parsing and assembly of the generated crate shared by the process wrappers running
+//! protoc for prost and rust-protobuf, so crates generated by either can depend on each other.
+
+pub mod args;
+pub mod diagnostics;
+pub mod wkt;
 
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fmt;
 use std::fmt::{Display, Formatter, Write};
 use std::fs;
-use std::io::BufRead;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process;
-use std::{env, fmt};
 
 use heck::{ToSnakeCase, ToUpperCamelCase};
 use prost::Message;
@@ -17,8 +22,10 @@ use prost_types::{
     OneofDescriptorProto,
 };
 
+use self::args::render_args_file;
+
 /// Locate prost outputs in the protoc output directory.
-fn find_generated_rust_files(out_dir: &Path) -> BTreeSet<PathBuf> {
+pub fn find_generated_rust_files(out_dir: &Path) -> BTreeSet<PathBuf> {
     let mut all_rs_files: BTreeSet<PathBuf> = BTreeSet::new();
     for entry in fs::read_dir(out_dir).expect("Failed to read directory") {
         let entry = entry.expect("Failed to read entry");
@@ -46,6 +53,14 @@ fn find_generated_rust_files(out_dir: &Path) -> BTreeSet<PathBuf> {
     all_rs_files
 }
 
+/// The package whose protoc outputs are written to the given file.
+pub fn generated_file_package(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    let stem = file_name.strip_suffix(".rs")?;
+    let stem = stem.strip_suffix(".tonic").unwrap_or(stem);
+    Some(if stem == "_" { "" } else { stem }.to_string())
+}
+
 fn snake_cased_package_name(package: &str) -> String {
     if package == "_" {
         return package.to_owned();
@@ -60,19 +75,19 @@ fn snake_cased_package_name(package: &str) -> String {
 
 /// Rust module definition.
 #[derive(Debug, Default)]
-struct Module {
+pub struct Module {
     /// The name of the module.
-    name: String,
+    pub name: String,
 
     /// The contents of the module.
-    contents: String,
+    pub contents: String,
 
     /// The names of any other modules which are submodules of this module.
-    submodules: BTreeMap<String, Module>,
+    pub submodules: BTreeMap<String, Module>,
 }
 
 impl Module {
-    fn insert(&mut self, module_name: String, contents: String) {
+    pub fn insert(&mut self, module_name: String, contents: String) {
         let module_parts = module_name.split('.').collect::<Vec<_>>();
 
         self.insert_module(module_parts.as_slice(), contents);
@@ -105,6 +120,10 @@ impl Module {
 const ADDITIONAL_CONTENT_HEADER: &str =
     "// A D D I T I O N A L   S O U R C E S ========================================";
 
+/// The attribute excluding generated modules from formatting. Inner `#![rustfmt::skip]`
+/// attributes are unstable so each top level module is marked instead.
+const RUSTFMT_SKIP_ATTRIBUTE: &str = "#[rustfmt::skip]\n";
+
 /// Generate a lib.rs file with all prost/tonic outputs embeeded in modules which
 /// mirror the proto packages. For the example proto file we would expect to see
 /// the Rust output that follows it.
@@ -151,12 +170,30 @@ const ADDITIONAL_CONTENT_HEADER: &str =
 ///     }
 /// }
 /// ```
-fn generate_lib_rs(
+pub fn generate_lib_rs(
+    module_info: &Module,
+    additional_content: &str,
+    skip_format: bool,
+) -> String {
+    let mut content = String::new();
+    write_module(&mut content, module_info, 0, skip_format);
+
+    if !additional_content.is_empty() {
+        return format!(
+            "{}\n\n{}\n\n{}",
+            content, ADDITIONAL_CONTENT_HEADER, additional_content
+        );
+    }
+
+    content
+}
+
+/// Collect all prost/tonic outputs into a tree of modules mirroring the proto packages.
+pub fn build_module_tree(
     prost_outputs: &BTreeSet<PathBuf>,
     is_tonic: bool,
     direct_dep_crate_names: Vec<String>,
-    additional_content: String,
-) -> String {
+) -> Module {
     let mut contents = vec!["// @generated".to_string(), "".to_string()];
     for crate_name in direct_dep_crate_names {
         contents.push(format!("pub use {crate_name};"));
@@ -170,11 +207,12 @@ fn generate_lib_rs(
     };
 
     for path in prost_outputs.iter() {
+        // Generated file names are derived from proto package names and are
+        // always valid UTF-8, even when the output directory is not.
         let mut package = path
             .file_stem()
             .expect("Failed to get file stem")
-            .to_str()
-            .expect("Failed to convert to str")
+            .to_string_lossy()
             .to_string();
 
         if is_tonic {
@@ -195,27 +233,75 @@ fn generate_lib_rs(
         module_info.insert(module_name, contents);
     }
 
+    module_info
+}
+
+/// Write all prost/tonic outputs as a `mod.rs` style directory hierarchy rooted at
+/// `dir`, with one directory per proto package.
+///
+/// The tree can be embedded in an existing crate with
+/// `#[path = "<dir>/mod.rs"] mod generated;`.
+pub fn write_module_tree(
+    dir: &Path,
+    module_info: &Module,
+    additional_content: &str,
+    skip_format: bool,
+) {
     let mut content = String::new();
-    write_module(&mut content, &module_info, 0);
+    write_module_dir(dir, module_info, &mut content, skip_format);
 
     if !additional_content.is_empty() {
-        return format!(
+        content = format!(
             "{}\n\n{}\n\n{}",
             content, ADDITIONAL_CONTENT_HEADER, additional_content
         );
     }
 
-    content
+    fs::write(dir.join("mod.rs"), content).expect("Failed to write file.");
 }
 
-/// Write out a rust module and all of its submodules.
-fn write_module(content: &mut String, module: &Module, depth: usize) {
+/// Write the contents of a module to `content` and each of its submodules to a
+/// `mod.rs` file in a directory named after the submodule. Submodule declarations
+/// are marked with `#[rustfmt::skip]` when `skip_format` is set.
+fn write_module_dir(dir: &Path, module: &Module, content: &mut String, skip_format: bool) {
+    content.push_str(&module.contents);
+
+    for submodule in module.submodules.values() {
+        // `_` modules hold packageless content which belongs to the parent module.
+        if submodule.name == "_" {
+            write_module_dir(dir, submodule, content, skip_format);
+            continue;
+        }
+
+        content.push_str(&format!(
+            "\n{}pub mod {};\n",
+            if skip_format {
+                RUSTFMT_SKIP_ATTRIBUTE
+            } else {
+                ""
+            },
+            escape_keyword(submodule.name.clone())
+        ));
+
+        // Raw identifiers are resolved to files without the `r#` prefix. Skipping
+        // a module declaration also skips the files of its submodules.
+        let submodule_dir = dir.join(&submodule.name);
+        fs::create_dir_all(&submodule_dir).expect("Failed to create directory.");
+        let mut submodule_content = String::new();
+        write_module_dir(&submodule_dir, submodule, &mut submodule_content, false);
+        fs::write(submodule_dir.join("mod.rs"), submodule_content).expect("Failed to write file.");
+    }
+}
+
+/// Write out a rust module and all of its submodules. The module is marked with
+/// `#[rustfmt::skip]` when `skip_format` is set, which also covers its submodules.
+fn write_module(content: &mut String, module: &Module, depth: usize, skip_format: bool) {
     if module.name.is_empty() {
         content
             .write_str(&module.contents)
             .expect("Failed to write string");
         for submodule in module.submodules.values() {
-            write_module(content, submodule, depth);
+            write_module(content, submodule, depth, skip_format);
         }
         return;
     }
@@ -224,6 +310,11 @@ fn write_module(content: &mut String, module: &Module, depth: usize) {
 
     if is_rust_module {
         let rust_module_name = escape_keyword(module.name.clone());
+        if skip_format {
+            content
+                .write_str(&format!("{}{}", indent, RUSTFMT_SKIP_ATTRIBUTE))
+                .expect("Failed to write string");
+        }
         content
             .write_str(&format!("{}pub mod {} {{\n", indent, rust_module_name))
             .expect("Failed to write string");
@@ -234,7 +325,12 @@ fn write_module(content: &mut String, module: &Module, depth: usize) {
         .expect("Failed to write string");
 
     for submodule in module.submodules.values() {
-        write_module(content, submodule, depth + 1);
+        write_module(
+            content,
+            submodule,
+            depth + 1,
+            skip_format && !is_rust_module,
+        );
     }
 
     if is_rust_module {
@@ -248,11 +344,11 @@ fn write_module(content: &mut String, module: &Module, depth: usize) {
 ///
 /// Example: `helloworld.Greeter.HelloRequest`
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
-struct ProtoPath(String);
+pub struct ProtoPath(pub String);
 
 impl ProtoPath {
     /// Join a component to the end of the path.
-    fn join(&self, component: &str) -> ProtoPath {
+    pub fn join(&self, component: &str) -> ProtoPath {
         if self.0.is_empty() {
             return ProtoPath(component.to_string());
         }
@@ -280,11 +376,11 @@ impl From<&str> for ProtoPath {
 ///
 /// Example: `helloworld::greeter::HelloRequest`
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
-struct RustModulePath(String);
+pub struct RustModulePath(String);
 
 impl RustModulePath {
     /// Join a path to the end of the module path.
-    fn join(&self, path: &str) -> RustModulePath {
+    pub fn join(&self, path: &str) -> RustModulePath {
         if self.0.is_empty() {
             return RustModulePath(escape_keyword(path.to_string()));
         }
@@ -311,7 +407,7 @@ impl From<&str> for RustModulePath {
 /// Compute the `--extern_path` flags for a list of proto files. This is
 /// expected to convert proto files into a BTreeMap of
 /// `example.prost.helloworld`: `crate_name::example::prost::helloworld`.
-fn get_extern_paths(
+pub fn get_extern_paths(
     descriptor_set: &FileDescriptorSet,
     crate_name: &str,
 ) -> Result<BTreeMap<ProtoPath, RustModulePath>, String> {
@@ -363,9 +459,10 @@ fn message_type_to_extern_paths(
         rust_path.join(&message_type_name.to_upper_camel_case()),
     );
 
-    let name_lower = message_type_name.to_lowercase();
-    let proto_path = proto_path.join(&name_lower);
-    let rust_path = rust_path.join(&name_lower);
+    // Nested types keep their proto names but live in a snake cased module
+    // named after the message.
+    let proto_path = proto_path.join(message_type_name);
+    let rust_path = rust_path.join(&message_type_name.to_snake_case());
 
     for nested_type in message_type.nested_type.iter() {
         message_type_to_extern_paths(extern_paths, &proto_path, &rust_path, nested_type)
@@ -393,10 +490,11 @@ fn enum_type_to_extern_paths(
         .expect("Failed to get enum type name");
     extern_paths.insert(
         proto_path.join(enum_type_name),
-        rust_path.join(enum_type_name),
+        rust_path.join(&enum_type_name.to_upper_camel_case()),
     );
 }
 
+/// Add the extern_path pair for the enum generated for a oneof.
 fn oneof_type_to_extern_paths(
     extern_paths: &mut BTreeMap<ProtoPath, RustModulePath>,
     proto_path: &ProtoPath,
@@ -409,264 +507,180 @@ fn oneof_type_to_extern_paths(
         .expect("Failed to get oneof type name");
     extern_paths.insert(
         proto_path.join(oneof_type_name),
-        rust_path.join(oneof_type_name),
+        rust_path.join(&oneof_type_name.to_upper_camel_case()),
     );
 }
 
-/// The parsed command-line arguments.
-struct Args {
-    /// The path to the protoc binary.
-    protoc: PathBuf,
-
-    /// The path to the output directory.
-    out_dir: PathBuf,
-
-    /// The name of the crate.
-    crate_name: String,
-
-    /// The bazel label.
-    label: String,
-
-    /// The path to the package info file.
-    package_info_file: PathBuf,
-
-    /// The proto files to compile.
-    proto_files: Vec<PathBuf>,
-
-    /// Additional source files to append to the generated rust source.
-    additional_srcs: Vec<PathBuf>,
-
-    /// The include directories.
-    includes: Vec<String>,
+/// The Rust modules generated by tonic for a service.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ServiceModules {
+    /// The module containing the client, unless clients are not generated.
+    client: Option<RustModulePath>,
 
-    /// Dependency descriptor sets.
-    descriptor_set: PathBuf,
-
-    /// The path to the generated lib.rs file.
-    out_librs: PathBuf,
-
-    /// The proto include paths.
-    proto_paths: Vec<String>,
-
-    /// Direct dependency crate names.
-    direct_dep_crate_names: Vec<String>,
+    /// The module containing the server, unless servers are not generated.
+    server: Option<RustModulePath>,
+}
 
-    /// The path to the rustfmt binary.
-    rustfmt: Option<PathBuf>,
+/// Compute the modules of the tonic clients and servers of all services in the
+/// descriptor set, e.g. `example.Greeter` to `crate_name::example::greeter_client`.
+pub fn get_service_modules(
+    descriptor_set: &FileDescriptorSet,
+    crate_name: &str,
+    with_client: bool,
+    with_server: bool,
+) -> BTreeMap<ProtoPath, ServiceModules> {
+    let mut service_modules = BTreeMap::new();
+    let crate_path = RustModulePath(escape_keyword(crate_name.to_string()));
 
-    /// Whether to generate tonic code.
-    is_tonic: bool,
+    for file in descriptor_set.file.iter() {
+        let package_path = package_module_parts(file.package())
+            .iter()
+            .fold(crate_path.clone(), |acc, part| acc.join(part));
+        for service in file.service.iter() {
+            let service_module = service.name().to_snake_case();
+            service_modules.insert(
+                ProtoPath(file.package().to_string()).join(service.name()),
+                ServiceModules {
+                    client: with_client
+                        .then(|| package_path.join(&format!("{}_client", service_module))),
+                    server: with_server
+                        .then(|| package_path.join(&format!("{}_server", service_module))),
+                },
+            );
+        }
+    }
 
-    /// Extra arguments to pass to protoc.
-    extra_args: Vec<String>,
+    service_modules
 }
 
-impl Args {
-    /// Parse the command-line arguments.
-    fn parse() -> Result<Args, String> {
-        let mut protoc: Option<PathBuf> = None;
-        let mut out_dir: Option<PathBuf> = None;
-        let mut crate_name: Option<String> = None;
-        let mut package_info_file: Option<PathBuf> = None;
-        let mut proto_files: Vec<PathBuf> = Vec::new();
-        let mut additional_srcs: Vec<PathBuf> = Vec::new();
-        let mut includes = Vec::new();
-        let mut descriptor_set = None;
-        let mut out_librs: Option<PathBuf> = None;
-        let mut rustfmt: Option<PathBuf> = None;
-        let mut proto_paths = Vec::new();
-        let mut label: Option<String> = None;
-        let mut tonic_or_prost_opts = Vec::new();
-        let mut direct_dep_crate_names = Vec::new();
-        let mut is_tonic = false;
-
-        let mut extra_args = Vec::new();
-
-        let mut handle_arg = |arg: String| {
-            if !arg.starts_with('-') {
-                proto_files.push(PathBuf::from(arg));
-                return;
+/// Render the package info file consumed by dependent targets through `--deps_info`.
+///
+/// Each line is either a `.<proto path>=::<rust path>` extern path of a type, or a
+/// `client .<service>=::<module>` or `server .<service>=::<module>` entry locating
+/// the tonic modules of a service. Crates generated by rust-protobuf additionally
+/// record a `file <proto file>=::<module>` entry per proto file.
+pub fn render_package_info(
+    extern_paths: &BTreeMap<ProtoPath, RustModulePath>,
+    service_modules: &BTreeMap<ProtoPath, ServiceModules>,
+) -> String {
+    let mut lines = extern_paths
+        .iter()
+        .map(|(proto_path, rust_path)| format!(".{}=::{}", proto_path, rust_path))
+        .collect::<Vec<_>>();
+    for (service, modules) in service_modules {
+        for (kind, module) in [("client", &modules.client), ("server", &modules.server)] {
+            if let Some(module) = module {
+                lines.push(format!("{} .{}=::{}", kind, service, module));
             }
+        }
+    }
 
-            if arg.starts_with("-I") {
-                includes.push(
-                    arg.strip_prefix("-I")
-                        .expect("Failed to strip -I")
-                        .to_string(),
-                );
-                return;
-            }
+    lines.join("\n")
+}
 
-            if arg == "--is_tonic" {
-                is_tonic = true;
-                return;
-            }
+/// The contents of a package info file.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct PackageInfo {
+    /// Fully qualified proto names of types mapped to their Rust paths.
+    extern_paths: BTreeMap<String, String>,
 
-            if !arg.contains('=') {
-                extra_args.push(arg);
-                return;
-            }
+    /// Fully qualified proto names of services mapped to their client and server modules.
+    service_modules: BTreeMap<String, (Option<String>, Option<String>)>,
 
-            let parts = arg.split_once('=').expect("Failed to split argument on =");
-            match parts {
-                ("--protoc", value) => {
-                    protoc = Some(PathBuf::from(value));
-                }
-                ("--prost_out", value) => {
-                    out_dir = Some(PathBuf::from(value));
-                }
-                ("--package_info_output", value) => {
-                    let (key, value) = value
-                        .split_once('=')
-                        .map(|(a, b)| (a.to_string(), PathBuf::from(b)))
-                        .expect("Failed to parse package info output");
-                    crate_name = Some(key);
-                    package_info_file = Some(value);
-                }
-                ("--deps_info", value) => {
-                    for line in fs::read_to_string(value)
-                        .expect("Failed to read file")
-                        .lines()
-                    {
-                        let path = PathBuf::from(line.trim());
-                        for flag in fs::read_to_string(path)
-                            .expect("Failed to read file")
-                            .lines()
-                        {
-                            tonic_or_prost_opts.push(format!("extern_path={}", flag.trim()));
-                        }
-                    }
-                }
-                ("--additional_srcs", value) => {
-                    if !value.is_empty() {
-                        additional_srcs
-                            .extend(value.split(',').map(PathBuf::from).collect::<Vec<_>>());
-                    }
-                }
-                ("--direct_dep_crate_names", value) => {
-                    if value.trim().is_empty() {
-                        return;
-                    }
+    /// Proto files generated by rust-protobuf mapped to the modules holding their types.
+    file_modules: BTreeMap<String, String>,
+}
 
-                    direct_dep_crate_names = value.split(',').map(|s| s.to_string()).collect();
-                }
-                ("--descriptor_set", value) => {
-                    descriptor_set = Some(PathBuf::from(value));
-                }
-                ("--out_librs", value) => {
-                    out_librs = Some(PathBuf::from(value));
-                }
-                ("--rustfmt", value) => {
-                    rustfmt = Some(PathBuf::from(value));
-                }
-                ("--proto_path", value) => {
-                    proto_paths.push(value.to_string());
-                }
-                ("--label", value) => {
-                    label = Some(value.to_string());
-                }
-                (arg, value) => {
-                    extra_args.push(format!("{}={}", arg, value));
+impl PackageInfo {
+    /// Parse a package info file. Unrecognized lines are ignored.
+    fn parse(content: &str) -> Self {
+        let mut package_info = Self::default();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.starts_with('.') {
+                if let Some((proto_path, rust_path)) = line.split_once('=') {
+                    package_info
+                        .extern_paths
+                        .insert(proto_path.to_string(), rust_path.to_string());
                 }
+                continue;
             }
-        };
 
-        // Iterate over the given command line arguments parsing out arguments
-        // for the process runner and arguments for protoc and potentially spawn
-        // additional arguments needed by prost.
-        for arg in env::args().skip(1) {
-            if let Some(path) = arg.strip_prefix('@') {
-                // handle argfile
-                let file = std::fs::File::open(path)
-                    .map_err(|_| format!("could not open argfile: {}", arg))?;
-                for line in std::io::BufReader::new(file).lines() {
-                    handle_arg(line.map_err(|_| format!("could not read argfile: {}", arg))?);
+            let (kind, entry) = match line.split_once(' ') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            let (name, module) = match entry.split_once('=') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            match kind {
+                "client" => {
+                    package_info
+                        .service_modules
+                        .entry(name.to_string())
+                        .or_default()
+                        .0 = Some(module.to_string())
                 }
-            } else {
-                handle_arg(arg);
+                "server" => {
+                    package_info
+                        .service_modules
+                        .entry(name.to_string())
+                        .or_default()
+                        .1 = Some(module.to_string())
+                }
+                "file" => {
+                    package_info
+                        .file_modules
+                        .insert(name.to_string(), module.to_string());
+                }
+                _ => {}
             }
         }
 
-        for tonic_or_prost_opt in tonic_or_prost_opts {
-            extra_args.push(format!("--prost_opt={}", tonic_or_prost_opt));
-            if is_tonic {
-                extra_args.push(format!("--tonic_opt={}", tonic_or_prost_opt));
+        package_info
+    }
+
+    /// Rewrite the paths of the crate the package info was generated for to the
+    /// name the crate is consumed under.
+    fn rename_crate(&mut self, original: &str, alias: &str) {
+        let original = format!("::{}", escape_keyword(original.to_string()));
+        let alias = format!("::{}", escape_keyword(alias.to_string()));
+        let rename = |path: &mut String| {
+            if let Some(rest) = path.strip_prefix(&original) {
+                if rest.is_empty() || rest.starts_with("::") {
+                    *path = format!("{}{}", alias, rest);
+                }
             }
-        }
+        };
 
-        if protoc.is_none() {
-            return Err(
-                "No `--protoc` value was found. Unable to parse path to proto compiler."
-                    .to_string(),
-            );
-        }
-        if out_dir.is_none() {
-            return Err(
-                "No `--prost_out` value was found. Unable to parse output directory.".to_string(),
-            );
-        }
-        if crate_name.is_none() {
-            return Err(
-                "No `--package_info_output` value was found. Unable to parse target crate name."
-                    .to_string(),
-            );
-        }
-        if package_info_file.is_none() {
-            return Err("No `--package_info_output` value was found. Unable to parse package info output file.".to_string());
+        self.extern_paths.values_mut().for_each(rename);
+        self.file_modules.values_mut().for_each(rename);
+        for (client, server) in self.service_modules.values_mut() {
+            client.iter_mut().chain(server.iter_mut()).for_each(rename);
         }
-        if out_librs.is_none() {
-            return Err("No `--out_librs` value was found. Unable to parse the output location for all combined prost outputs.".to_string());
-        }
-        if descriptor_set.is_none() {
-            return Err(
-                "No `--descriptor_set` value was found. Unable to parse descriptor set path."
-                    .to_string(),
-            );
-        }
-        if label.is_none() {
-            return Err(
-                "No `--label` value was found. Unable to parse the label of the target crate."
-                    .to_string(),
-            );
-        }
-
-        Ok(Args {
-            protoc: protoc.unwrap(),
-            out_dir: out_dir.unwrap(),
-            crate_name: crate_name.unwrap(),
-            package_info_file: package_info_file.unwrap(),
-            proto_files,
-            additional_srcs,
-            includes,
-            descriptor_set: descriptor_set.unwrap(),
-            out_librs: out_librs.unwrap(),
-            rustfmt,
-            proto_paths,
-            direct_dep_crate_names,
-            is_tonic,
-            label: label.unwrap(),
-            extra_args,
-        })
     }
 }
 
+/// Replace a suffix of the file name of a path, leaving the parent directory untouched.
+pub fn replace_file_name_suffix(path: &Path, suffix: &str, replacement: &str) -> Option<PathBuf> {
+    let stem = path.file_name()?.to_str()?.strip_suffix(suffix)?;
+    Some(path.with_file_name(format!("{}{}", stem, replacement)))
+}
+
 /// Get the output directory with the label suffixed.
-fn get_output_dir(out_dir: &Path, label: &str) -> PathBuf {
+pub fn get_output_dir(out_dir: &Path, label: &str) -> PathBuf {
     let label_as_path = label
         .replace('@', "")
         .replace("//", "_")
         .replace(['/', ':'], "_");
-    PathBuf::from(format!(
-        "{}/prost-build-{}",
-        out_dir.display(),
-        label_as_path
-    ))
+    out_dir.join(format!("prost-build-{}", label_as_path))
 }
 
 /// Get the output directory with the label suffixed, and create it if it doesn't exist.
 ///
 /// This will remove the directory first if it already exists.
-fn get_and_create_output_dir(out_dir: &Path, label: &str) -> PathBuf {
+pub fn get_and_create_output_dir(out_dir: &Path, label: &str) -> PathBuf {
     let out_dir = get_output_dir(out_dir, label);
     if out_dir.exists() {
         fs::remove_dir_all(&out_dir).expect("Failed to remove old output directory");
@@ -675,8 +689,40 @@ fn get_and_create_output_dir(out_dir: &Path, label: &str) -> PathBuf {
     out_dir
 }
 
+/// Run protoc with the given arguments, passed through an args file.
+pub fn invoke_protoc(protoc: &Path, args_file: &Path, args: &[OsString]) {
+    fs::write(args_file, render_args_file(args)).unwrap_or_else(|e| {
+        panic!(
+            "Failed to write args file: {}\n{:?}",
+            args_file.display(),
+            e
+        )
+    });
+
+    let mut cmd = process::Command::new(protoc);
+    let mut args_file_arg = OsString::from("@");
+    args_file_arg.push(args_file);
+    cmd.arg(args_file_arg);
+
+    let status = cmd.status().unwrap_or_else(|e| {
+        panic!(
+            "Failed to spawn protoc process\n{:#?}\n{} -- {:#?}\n{:?}",
+            cmd,
+            args_file.display(),
+            args,
+            e
+        )
+    });
+    if !status.success() {
+        panic!(
+            "protoc failed with status: {}",
+            status.code().expect("failed to get exit code")
+        );
+    }
+}
+
 /// Parse the descriptor set file into a `FileDescriptorSet`.
-fn parse_descriptor_set_file(descriptor_set_path: &PathBuf) -> FileDescriptorSet {
+pub fn parse_descriptor_set_file(descriptor_set_path: &PathBuf) -> FileDescriptorSet {
     let descriptor_set_bytes =
         fs::read(descriptor_set_path).expect("Failed to read descriptor set");
     let descriptor_set = FileDescriptorSet::decode(descriptor_set_bytes.as_slice())
@@ -685,79 +731,127 @@ fn parse_descriptor_set_file(descriptor_set_path: &PathBuf) -> FileDescriptorSet
     descriptor_set
 }
 
-/// Get the package name from the descriptor set.
-fn get_package_name(descriptor_set: &FileDescriptorSet) -> Option<String> {
-    let mut package_name = None;
-
-    for file in &descriptor_set.file {
-        if let Some(package) = &file.package {
-            package_name = Some(package.clone());
-            break;
+/// Quote and escape a string for use in JSON.
+pub fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
         }
     }
-
-    package_name
+    quoted.push('"');
+    quoted
 }
 
-/// Whether the proto file should expect to generate a .rs file.
-///
-/// If the proto file contains any messages, enums, or services, then it should generate a rust file.
-/// If the proto file only contains extensions, then it will not generate any rust files.
-fn expect_fs_file_to_be_generated(descriptor_set: &FileDescriptorSet) -> bool {
-    let mut expect_rs = false;
+/// Get the Rust module names for a proto package.
+pub fn package_module_parts(package: &str) -> Vec<String> {
+    if package.is_empty() {
+        return Vec::new();
+    }
 
-    for file in descriptor_set.file.iter() {
-        let has_messages = !file.message_type.is_empty();
-        let has_enums = !file.enum_type.is_empty();
-        let has_services = !file.service.is_empty();
-        let has_extensions = !file.extension.is_empty();
+    package
+        .split('.')
+        .map(|part| escape_keyword(part.to_snake_case()))
+        .collect()
+}
 
-        let has_definition = has_messages || has_enums || has_services;
+/// Call `f` with the package, parent message names, proto path and descriptor of
+/// every message prost generates a struct for. The proto path is built the same
+/// way as for extern paths. Map entries are skipped as they are generated as
+/// `HashMap`s rather than messages.
+pub fn for_each_message(
+    descriptor_set: &FileDescriptorSet,
+    mut f: impl FnMut(&str, &[&str], &ProtoPath, &DescriptorProto),
+) {
+    fn visit<F: FnMut(&str, &[&str], &ProtoPath, &DescriptorProto)>(
+        f: &mut F,
+        package: &str,
+        parents: &mut Vec<String>,
+        parent_path: &ProtoPath,
+        message_type: &DescriptorProto,
+    ) {
+        if message_type
+            .options
+            .as_ref()
+            .is_some_and(|options| options.map_entry())
+        {
+            return;
+        }
+
+        let proto_path = parent_path.join(message_type.name());
+        f(
+            package,
+            &parents.iter().map(String::as_str).collect::<Vec<_>>(),
+            &proto_path,
+            message_type,
+        );
 
-        if has_definition {
-            return true;
-        } else if !has_definition && !has_extensions {
-            expect_rs = true;
+        parents.push(message_type.name().to_string());
+        for nested_type in message_type.nested_type.iter() {
+            visit(f, package, parents, &proto_path, nested_type);
         }
+        parents.pop();
     }
 
-    expect_rs
+    for file in descriptor_set.file.iter() {
+        let package_path = ProtoPath(file.package().to_string());
+        for message_type in file.message_type.iter() {
+            visit(
+                &mut f,
+                file.package(),
+                &mut Vec::new(),
+                &package_path,
+                message_type,
+            );
+        }
+    }
 }
 
-/// Whether the proto file should expect to generate service definitions.
-fn has_services(descriptor_set: &FileDescriptorSet) -> bool {
-    descriptor_set
-        .file
-        .iter()
-        .any(|file| !file.service.is_empty())
-}
+/// Call `f` with the package, parent message names, proto path and descriptor of
+/// every enum in the descriptor set, including those nested in messages.
+pub fn for_each_enum(
+    descriptor_set: &FileDescriptorSet,
+    mut f: impl FnMut(&str, &[&str], &ProtoPath, &EnumDescriptorProto),
+) {
+    for file in descriptor_set.file.iter() {
+        let package_path = ProtoPath(file.package().to_string());
+        for enum_type in file.enum_type.iter() {
+            f(
+                file.package(),
+                &[],
+                &package_path.join(enum_type.name()),
+                enum_type,
+            );
+        }
+    }
 
-fn main() {
-    let Args {
-        protoc,
-        out_dir,
-        crate_name,
-        label,
-        package_info_file,
-        proto_files,
-        additional_srcs,
-        includes,
+    for_each_message(
         descriptor_set,
-        out_librs,
-        rustfmt,
-        proto_paths,
-        direct_dep_crate_names,
-        is_tonic,
-        extra_args,
-    } = Args::parse().expect("Failed to parse args");
-
-    let out_dir = get_and_create_output_dir(&out_dir, &label);
-
-    let descriptor_set = parse_descriptor_set_file(&descriptor_set);
-    let package_name = get_package_name(&descriptor_set).unwrap_or_default();
-    let expect_rs = expect_fs_file_to_be_generated(&descriptor_set);
-    let has_services = has_services(&descriptor_set);
-    let additional_content = additional_srcs
+        |package, parents, proto_path, message_type| {
+            let mut parents = parents.to_vec();
+            parents.push(message_type.name());
+            for enum_type in message_type.enum_type.iter() {
+                f(
+                    package,
+                    &parents,
+                    &proto_path.join(enum_type.name()),
+                    enum_type,
+                );
+            }
+        },
+    );
+}
+
+/// Read the additional source files appended to the generated crate.
+pub fn read_additional_srcs(additional_srcs: Vec<PathBuf>) -> String {
+    additional_srcs
         .into_iter()
         .map(|f| {
             fs::read_to_string(&f).unwrap_or_else(|e| {
@@ -769,183 +863,26 @@ fn main() {
             })
         })
         .collect::<Vec<_>>()
-        .join("\n");
-
-    if has_services && !is_tonic {
-        eprintln!("Warning: Service definitions will not be generated because the prost toolchain did not define a tonic plugin.");
-    }
-
-    let tmp_dir = out_dir.parent().unwrap().join(format!(
-        "{}.tmp",
-        out_dir.file_name().unwrap().to_string_lossy()
-    ));
-    if tmp_dir.exists() {
-        fs::remove_dir_all(&tmp_dir).unwrap_or_else(|e| {
-            panic!("Failed to delete directory: {}\n{:?}", tmp_dir.display(), e)
-        });
-    }
-    fs::create_dir_all(&tmp_dir)
-        .unwrap_or_else(|e| panic!("Failed to create directory: {}\n{:?}", tmp_dir.display(), e));
-
-    let args_file = out_dir.join("args.txt");
-    let mut args = Vec::new();
-
-    args.push(format!("--prost_out={}", out_dir.display()));
-    if is_tonic {
-        args.push(format!("--tonic_out={}", out_dir.display()));
-    }
-    args.extend(extra_args);
-    args.extend(
-        proto_paths
-            .iter()
-            .map(|proto_path| format!("--proto_path={}", proto_path)),
-    );
-    args.extend(includes.iter().map(|include| format!("-I{}", include)));
-    args.extend(proto_files.iter().map(|f| f.to_string_lossy().to_string()));
-
-    fs::write(&args_file, args.join("\n")).unwrap_or_else(|e| {
-        panic!(
-            "Failed to write args file: {}\n{:?}",
-            args_file.display(),
-            e
-        )
-    });
-    let mut cmd = process::Command::new(protoc);
-    cmd.arg(format!("@{}", args_file.display()));
-
-    let status_result = cmd.status();
-
-    fs::remove_dir_all(&tmp_dir)
-        .unwrap_or_else(|e| panic!("Failed to delete directory: {}\n{:?}", tmp_dir.display(), e));
+        .join("\n")
+}
 
-    let status = status_result.unwrap_or_else(|e| {
+/// Format the generated lib.rs file. Submodules of the module tree are formatted
+/// by following the `mod` declarations of its root.
+pub fn run_rustfmt(rustfmt: &Path, out_librs: &Path, out_module_dir: Option<&Path>) {
+    let fmt_status = process::Command::new(rustfmt)
+        .arg("--edition")
+        .arg("2021")
+        .arg("--quiet")
+        .arg(out_librs)
+        .args(out_module_dir.map(|dir| dir.join("mod.rs")))
+        .status()
+        .expect("Failed to spawn rustfmt process");
+    if !fmt_status.success() {
         panic!(
-            "Failed to spawn protoc process\n{:#?}\n{} -- {:#?}\n{:?}",
-            cmd,
-            args_file.display(),
-            args,
-            e
-        )
-    });
-    if !status.success() {
-        panic!(
-            "protoc failed with status: {}",
-            status.code().expect("failed to get exit code")
+            "rustfmt failed with exit code: {}",
+            fmt_status.code().expect("Failed to get exit code")
         );
     }
-
-    // Not all proto files will consistently produce `.rs` or `.tonic.rs` files. This is
-    // caused by the proto file being transpiled not having an RPC service or other protos
-    // defined (a natural and expected situation). To guarantee consistent outputs, all
-    // `.rs` files are either renamed to `.tonic.rs` if there is no `.tonic.rs` or prepended
-    // to the existing `.tonic.rs`.
-    if is_tonic {
-        let tonic_files: BTreeSet<PathBuf> = find_generated_rust_files(&out_dir);
-
-        for tonic_file in tonic_files.iter() {
-            let tonic_path_str = tonic_file.to_str().expect("Failed to convert to str");
-            let filename = tonic_file
-                .file_name()
-                .expect("Failed to get file name")
-                .to_str()
-                .expect("Failed to convert to str");
-
-            let is_tonic_file = filename.ends_with(".tonic.rs");
-
-            if is_tonic_file {
-                let rs_file_str = format!(
-                    "{}.rs",
-                    tonic_path_str
-                        .strip_suffix(".tonic.rs")
-                        .expect("Failed to strip suffix.")
-                );
-                let rs_file = PathBuf::from(&rs_file_str);
-
-                if rs_file.exists() {
-                    let rs_content = fs::read_to_string(&rs_file).expect("Failed to read file.");
-                    let tonic_content =
-                        fs::read_to_string(tonic_file).expect("Failed to read file.");
-                    fs::write(tonic_file, format!("{}\n{}", rs_content, tonic_content))
-                        .expect("Failed to write file.");
-                    fs::remove_file(&rs_file).unwrap_or_else(|err| {
-                        panic!("Failed to remove file: {err:?}: {rs_file:?}")
-                    });
-                }
-            } else {
-                let real_tonic_file = PathBuf::from(format!(
-                    "{}.tonic.rs",
-                    tonic_path_str
-                        .strip_suffix(".rs")
-                        .expect("Failed to strip suffix.")
-                ));
-                if real_tonic_file.exists() {
-                    continue;
-                }
-                fs::rename(tonic_file, &real_tonic_file).unwrap_or_else(|err| {
-                    panic!("Failed to rename file: {err:?}: {tonic_file:?} -> {real_tonic_file:?}");
-                });
-            }
-        }
-    }
-
-    // Locate all prost-generated outputs.
-    let mut rust_files = find_generated_rust_files(&out_dir);
-    if rust_files.is_empty() {
-        if expect_rs {
-            panic!("No .rs files were generated by prost.");
-        } else {
-            let file_stem = if package_name.is_empty() {
-                "_"
-            } else {
-                &package_name
-            };
-            let file_stem = format!("{}{}", file_stem, if is_tonic { ".tonic" } else { "" });
-            let empty_rs_file = out_dir.join(format!("{}.rs", file_stem));
-            fs::write(&empty_rs_file, "").expect("Failed to write file.");
-            rust_files.insert(empty_rs_file);
-        }
-    }
-
-    let extern_paths = get_extern_paths(&descriptor_set, &crate_name)
-        .expect("Failed to compute proto package info");
-
-    // Write outputs
-    fs::write(
-        &out_librs,
-        generate_lib_rs(
-            &rust_files,
-            is_tonic,
-            direct_dep_crate_names,
-            additional_content,
-        ),
-    )
-    .expect("Failed to write file.");
-    fs::write(
-        package_info_file,
-        extern_paths
-            .into_iter()
-            .map(|(proto_path, rust_path)| format!(".{}=::{}", proto_path, rust_path))
-            .collect::<Vec<_>>()
-            .join("\n"),
-    )
-    .expect("Failed to write file.");
-
-    // Finally run rustfmt on the output lib.rs file
-    if let Some(rustfmt) = rustfmt {
-        let fmt_status = process::Command::new(rustfmt)
-            .arg("--edition")
-            .arg("2021")
-            .arg("--quiet")
-            .arg(&out_librs)
-            .status()
-            .expect("Failed to spawn rustfmt process");
-        if !fmt_status.success() {
-            panic!(
-                "rustfmt failed with exit code: {}",
-                fmt_status.code().expect("Failed to get exit code")
-            );
-        }
-    }
 }
 
 /// Rust built-in keywords and reserved keywords.
@@ -958,12 +895,12 @@ const RUST_KEYWORDS: [&str; 51] = [
 ];
 
 /// Returns true if the given string is a Rust keyword.
-fn is_keyword(s: &str) -> bool {
+pub fn is_keyword(s: &str) -> bool {
     RUST_KEYWORDS.contains(&s)
 }
 
 /// Escapes a Rust keyword by prefixing it with `r#`.
-fn escape_keyword(s: String) -> String {
+pub fn escape_keyword(s: String) -> String {
     if is_keyword(&s) {
         return format!("r#{s}");
     }
@@ -972,10 +909,10 @@ fn escape_keyword(s: String) -> String {
 
 #[cfg(test)]
 mod test {
-
     use super::*;
 
-    use prost_types::{FieldDescriptorProto, ServiceDescriptorProto};
+    use prost_types::ServiceDescriptorProto;
+    use std::{env, process};
 
     #[test]
     fn oneof_type_to_extern_paths_test() {
@@ -1055,6 +992,24 @@ mod test {
                 Some(&RustModulePath::from("bar::baz::Foo"))
             );
         }
+
+        {
+            let mut extern_paths = BTreeMap::new();
+            enum_type_to_extern_paths(
+                &mut extern_paths,
+                &ProtoPath::from("bar"),
+                &RustModulePath::from("bar"),
+                &EnumDescriptorProto {
+                    name: Some("FOO_STATE".to_string()),
+                    ..EnumDescriptorProto::default()
+                },
+            );
+
+            assert_eq!(
+                extern_paths.get(&ProtoPath::from("bar.FOO_STATE")),
+                Some(&RustModulePath::from("bar::FooState"))
+            );
+        }
     }
 
     #[test]
@@ -1100,17 +1055,25 @@ mod test {
                 Some(&RustModulePath::from("bar::Foo"))
             );
             assert_eq!(
-                extern_paths.get(&ProtoPath::from("bar.foo.Bar")),
+                extern_paths.get(&ProtoPath::from("bar.Foo.Bar")),
                 Some(&RustModulePath::from("bar::foo::Bar"))
             );
             assert_eq!(
-                extern_paths.get(&ProtoPath::from("bar.foo.Nested")),
+                extern_paths.get(&ProtoPath::from("bar.Foo.Nested")),
                 Some(&RustModulePath::from("bar::foo::Nested"))
             );
             assert_eq!(
-                extern_paths.get(&ProtoPath::from("bar.foo.nested.Baz")),
+                extern_paths.get(&ProtoPath::from("bar.Foo.Nested.Baz")),
                 Some(&RustModulePath::from("bar::foo::nested::Baz"))
             );
+            assert_eq!(
+                extern_paths.get(&ProtoPath::from("bar.Foo.Nested.Baz.Chuck")),
+                Some(&RustModulePath::from("bar::foo::nested::baz::Chuck"))
+            );
+            assert_eq!(
+                extern_paths.get(&ProtoPath::from("bar.Foo.Qux")),
+                Some(&RustModulePath::from("bar::foo::Qux"))
+            );
         }
 
         {
@@ -1127,18 +1090,133 @@ mod test {
                 Some(&RustModulePath::from("bar::bob::Foo"))
             );
             assert_eq!(
-                extern_paths.get(&ProtoPath::from("bar.bob.foo.Bar")),
+                extern_paths.get(&ProtoPath::from("bar.bob.Foo.Bar")),
                 Some(&RustModulePath::from("bar::bob::foo::Bar"))
             );
             assert_eq!(
-                extern_paths.get(&ProtoPath::from("bar.bob.foo.Nested")),
+                extern_paths.get(&ProtoPath::from("bar.bob.Foo.Nested")),
                 Some(&RustModulePath::from("bar::bob::foo::Nested"))
             );
             assert_eq!(
-                extern_paths.get(&ProtoPath::from("bar.bob.foo.nested.Baz")),
+                extern_paths.get(&ProtoPath::from("bar.bob.Foo.Nested.Baz")),
                 Some(&RustModulePath::from("bar::bob::foo::nested::Baz"))
             );
         }
+
+        {
+            // Message modules are snake cased while enums and oneofs are camel cased.
+            let message_descriptor = DescriptorProto {
+                name: Some("OuterMessage".to_string()),
+                enum_type: vec![EnumDescriptorProto {
+                    name: Some("inner_kind".to_string()),
+                    ..EnumDescriptorProto::default()
+                }],
+                oneof_decl: vec![OneofDescriptorProto {
+                    name: Some("payload_value".to_string()),
+                    ..OneofDescriptorProto::default()
+                }],
+                ..DescriptorProto::default()
+            };
+            let mut extern_paths = BTreeMap::new();
+            message_type_to_extern_paths(
+                &mut extern_paths,
+                &ProtoPath::from("bar"),
+                &RustModulePath::from("bar"),
+                &message_descriptor,
+            );
+            assert_eq!(
+                extern_paths,
+                BTreeMap::from([
+                    (
+                        ProtoPath::from("bar.OuterMessage"),
+                        RustModulePath::from("bar::OuterMessage")
+                    ),
+                    (
+                        ProtoPath::from("bar.OuterMessage.inner_kind"),
+                        RustModulePath::from("bar::outer_message::InnerKind")
+                    ),
+                    (
+                        ProtoPath::from("bar.OuterMessage.payload_value"),
+                        RustModulePath::from("bar::outer_message::PayloadValue")
+                    ),
+                ])
+            );
+        }
+    }
+
+    #[test]
+    fn package_info_test() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("a.proto".to_string()),
+                package: Some("pkg.type".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Request".to_string()),
+                    ..DescriptorProto::default()
+                }],
+                service: vec![ServiceDescriptorProto {
+                    name: Some("HelloWorld".to_string()),
+                    ..ServiceDescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        };
+
+        let extern_paths = get_extern_paths(&descriptor_set, "my_crate").unwrap();
+        let service_modules = get_service_modules(&descriptor_set, "my_crate", true, false);
+        let content = render_package_info(&extern_paths, &service_modules);
+        assert_eq!(
+            content,
+            ".pkg.type.Request=::my_crate::pkg::r#type::Request\nclient .pkg.type.HelloWorld=::my_crate::pkg::r#type::hello_world_client"
+        );
+
+        let package_info = PackageInfo::parse(&format!(
+            "{}\nunknown entry\nfile a.proto=::other_crate::a\n",
+            content
+        ));
+        assert_eq!(
+            package_info.extern_paths,
+            BTreeMap::from([(
+                ".pkg.type.Request".to_string(),
+                "::my_crate::pkg::r#type::Request".to_string()
+            )])
+        );
+        assert_eq!(
+            package_info.service_modules,
+            BTreeMap::from([(
+                ".pkg.type.HelloWorld".to_string(),
+                (
+                    Some("::my_crate::pkg::r#type::hello_world_client".to_string()),
+                    None
+                )
+            )])
+        );
+        assert_eq!(
+            package_info.file_modules,
+            BTreeMap::from([("a.proto".to_string(), "::other_crate::a".to_string())])
+        );
+    }
+
+    #[test]
+    fn package_info_rename_crate_test() {
+        let mut package_info = PackageInfo::parse(
+            ".a.Foo=::a_proto::a::Foo\n.b.Bar=::a_proto_extra::b::Bar\nserver .a.Svc=::a_proto::a::svc_server\n.type.Baz=::r#type::Baz",
+        );
+        package_info.rename_crate("a_proto", "renamed");
+        package_info.rename_crate("type", "not_a_keyword");
+
+        assert_eq!(
+            package_info.extern_paths,
+            BTreeMap::from([
+                (".a.Foo".to_string(), "::renamed::a::Foo".to_string()),
+                (".b.Bar".to_string(), "::a_proto_extra::b::Bar".to_string()),
+                (".type.Baz".to_string(), "::not_a_keyword::Baz".to_string()),
+            ])
+        );
+        assert_eq!(
+            package_info.service_modules[".a.Svc"],
+            (None, Some("::renamed::a::svc_server".to_string()))
+        );
     }
 
     #[test]
@@ -1200,131 +1278,6 @@ mod test {
         }
     }
 
-    #[test]
-    fn expect_fs_file_to_be_generated_test() {
-        {
-            // Empty descriptor set should create a file.
-            let descriptor_set = FileDescriptorSet {
-                file: vec![FileDescriptorProto {
-                    name: Some("foo.proto".to_string()),
-                    ..FileDescriptorProto::default()
-                }],
-            };
-            assert!(expect_fs_file_to_be_generated(&descriptor_set));
-        }
-        {
-            // Descriptor set with only message should create a file.
-            let descriptor_set = FileDescriptorSet {
-                file: vec![FileDescriptorProto {
-                    name: Some("foo.proto".to_string()),
-                    message_type: vec![DescriptorProto {
-                        name: Some("Foo".to_string()),
-                        ..DescriptorProto::default()
-                    }],
-                    ..FileDescriptorProto::default()
-                }],
-            };
-            assert!(expect_fs_file_to_be_generated(&descriptor_set));
-        }
-        {
-            // Descriptor set with only enum should create a file.
-            let descriptor_set = FileDescriptorSet {
-                file: vec![FileDescriptorProto {
-                    name: Some("foo.proto".to_string()),
-                    enum_type: vec![EnumDescriptorProto {
-                        name: Some("Foo".to_string()),
-                        ..EnumDescriptorProto::default()
-                    }],
-                    ..FileDescriptorProto::default()
-                }],
-            };
-            assert!(expect_fs_file_to_be_generated(&descriptor_set));
-        }
-        {
-            // Descriptor set with only service should create a file.
-            let descriptor_set = FileDescriptorSet {
-                file: vec![FileDescriptorProto {
-                    name: Some("foo.proto".to_string()),
-                    service: vec![ServiceDescriptorProto {
-                        name: Some("Foo".to_string()),
-                        ..ServiceDescriptorProto::default()
-                    }],
-                    ..FileDescriptorProto::default()
-                }],
-            };
-            assert!(expect_fs_file_to_be_generated(&descriptor_set));
-        }
-        {
-            // Descriptor set with only extensions should not create a file.
-            let descriptor_set = FileDescriptorSet {
-                file: vec![FileDescriptorProto {
-                    name: Some("foo.proto".to_string()),
-                    extension: vec![FieldDescriptorProto {
-                        name: Some("Foo".to_string()),
-                        ..FieldDescriptorProto::default()
-                    }],
-                    ..FileDescriptorProto::default()
-                }],
-            };
-            assert!(!expect_fs_file_to_be_generated(&descriptor_set));
-        }
-    }
-
-    #[test]
-    fn has_services_test() {
-        {
-            // Empty file should not have services.
-            let descriptor_set = FileDescriptorSet {
-                file: vec![FileDescriptorProto {
-                    name: Some("foo.proto".to_string()),
-                    ..FileDescriptorProto::default()
-                }],
-            };
-            assert!(!has_services(&descriptor_set));
-        }
-        {
-            // File with only message should not have services.
-            let descriptor_set = FileDescriptorSet {
-                file: vec![FileDescriptorProto {
-                    name: Some("foo.proto".to_string()),
-                    message_type: vec![DescriptorProto {
-                        name: Some("Foo".to_string()),
-                        ..DescriptorProto::default()
-                    }],
-                    ..FileDescriptorProto::default()
-                }],
-            };
-            assert!(!has_services(&descriptor_set));
-        }
-        {
-            // File with services should have services.
-            let descriptor_set = FileDescriptorSet {
-                file: vec![FileDescriptorProto {
-                    name: Some("foo.proto".to_string()),
-                    service: vec![ServiceDescriptorProto {
-                        name: Some("Foo".to_string()),
-                        ..ServiceDescriptorProto::default()
-                    }],
-                    ..FileDescriptorProto::default()
-                }],
-            };
-            assert!(has_services(&descriptor_set));
-        }
-    }
-
-    #[test]
-    fn get_package_name_test() {
-        let descriptor_set = FileDescriptorSet {
-            file: vec![FileDescriptorProto {
-                name: Some("foo.proto".to_string()),
-                package: Some("foo".to_string()),
-                ..FileDescriptorProto::default()
-            }],
-        };
-
-        assert_eq!(get_package_name(&descriptor_set), Some("foo".to_string()));
-    }
-
     #[test]
     fn is_keyword_test() {
         let non_keywords = [
@@ -1340,6 +1293,70 @@ mod test {
         }
     }
 
+    #[test]
+    fn write_module_tree_test() {
+        let mut module_info = Module {
+            name: "".to_string(),
+            contents: "// @generated\n".to_string(),
+            submodules: BTreeMap::new(),
+        };
+        module_info.insert("_".to_string(), "pub struct Packageless;\n".to_string());
+        module_info.insert("foo".to_string(), "pub struct Foo;\n".to_string());
+        module_info.insert("foo.type".to_string(), "pub struct Type;\n".to_string());
+        module_info.insert("foo.bar".to_string(), "pub struct Bar;\n".to_string());
+
+        let dir = env::temp_dir().join(format!("write_module_tree_test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_module_tree(&dir, &module_info, "pub struct Additional;", false);
+
+        let read = |path: &str| fs::read_to_string(dir.join(path)).unwrap();
+        assert_eq!(
+            read("mod.rs"),
+            format!(
+                "// @generated\npub struct Packageless;\n\npub mod foo;\n\n\n{}\n\npub struct Additional;",
+                ADDITIONAL_CONTENT_HEADER
+            )
+        );
+        assert_eq!(
+            read("foo/mod.rs"),
+            "pub struct Foo;\n\npub mod bar;\n\npub mod r#type;\n"
+        );
+        assert_eq!(read("foo/bar/mod.rs"), "pub struct Bar;\n");
+        assert_eq!(read("foo/type/mod.rs"), "pub struct Type;\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skip_format_test() {
+        let mut module_info = Module {
+            name: "".to_string(),
+            contents: "// @generated\n".to_string(),
+            submodules: BTreeMap::new(),
+        };
+        module_info.insert("_".to_string(), "pub struct Packageless;\n".to_string());
+        module_info.insert("foo".to_string(), "pub struct Foo;\n".to_string());
+        module_info.insert("foo.bar".to_string(), "pub struct Bar;\n".to_string());
+
+        assert_eq!(
+            generate_lib_rs(&module_info, "", true),
+            "// @generated\npub struct Packageless;\n#[rustfmt::skip]\npub mod foo {\npub struct Foo;\n  pub mod bar {\npub struct Bar;\n  }\n}\n"
+        );
+
+        let dir = env::temp_dir().join(format!("skip_format_test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_module_tree(&dir, &module_info, "", true);
+
+        let read = |path: &str| fs::read_to_string(dir.join(path)).unwrap();
+        assert_eq!(
+            read("mod.rs"),
+            "// @generated\npub struct Packageless;\n\n#[rustfmt::skip]\npub mod foo;\n"
+        );
+        assert_eq!(read("foo/mod.rs"), "pub struct Foo;\n\npub mod bar;\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn escape_keyword_test() {
         let non_keywords = [
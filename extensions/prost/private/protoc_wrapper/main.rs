@@ -0,0 +1,1000 @@
+//! A process wrapper for running a Protobuf compiler configured for Prost or Tonic output in a Bazel rule.
+
+mod any_helpers;
+mod builders;
+mod enum_helpers;
+mod health;
+mod incremental;
+mod mocks;
+mod name_constants;
+mod scaffold;
+mod service_comments;
+mod source_map;
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs;
+use std::panic;
+use std::path::Path;
+use std::path::PathBuf;
+
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost_types::field_descriptor_proto::Type;
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use protoc_wrapper_lib::args::{self, has_service_opt, os_flag, Args};
+use protoc_wrapper_lib::diagnostics::{
+    check_package_collisions, check_protobuf_deps, check_strict_deps, package_sizes, Diagnostic,
+    Diagnostics, Severity,
+};
+use protoc_wrapper_lib::wkt::{generate_wkt_wrappers, WKT_MODULE_NAME};
+use protoc_wrapper_lib::{
+    build_module_tree, escape_keyword, find_generated_rust_files, for_each_enum, for_each_message,
+    generate_lib_rs, get_and_create_output_dir, get_extern_paths, get_output_dir,
+    get_service_modules, invoke_protoc, json_string, package_module_parts,
+    parse_descriptor_set_file, read_additional_srcs, render_package_info, replace_file_name_suffix,
+    run_rustfmt, write_module_tree, Module, ProtoPath,
+};
+
+use self::any_helpers::{generate_any_helpers, generate_any_type_url_registry};
+use self::builders::generate_message_builders;
+use self::enum_helpers::generate_enum_helpers;
+use self::health::{generate_health_module, HEALTH_MODULE_NAME};
+use self::incremental::{
+    fnv1a, package_fingerprints, plugin_paths, state_key, IncrementalState, FNV_OFFSET_BASIS,
+};
+use self::mocks::generate_service_mocks;
+use self::name_constants::generate_name_constants;
+use self::scaffold::generate_server_scaffold;
+use self::service_comments::{get_service_method_comments, inject_method_comments};
+use self::source_map::{generate_source_map, render_json_object};
+
+/// Merge the outputs of the Connect plugin into the prost outputs of the same
+/// proto package. Connect outputs may be named after the package either as
+/// `<package>.rs` or `<package>.connect.rs`.
+fn merge_connect_outputs(connect_dir: &Path, out_dir: &Path) {
+    for connect_file in find_generated_rust_files(connect_dir) {
+        let relative = connect_file
+            .strip_prefix(connect_dir)
+            .expect("Failed to strip prefix.");
+        let rs_file = replace_file_name_suffix(&out_dir.join(relative), ".connect.rs", ".rs")
+            .unwrap_or_else(|| out_dir.join(relative));
+        let connect_content = fs::read_to_string(&connect_file).expect("Failed to read file.");
+        let content = match fs::read_to_string(&rs_file) {
+            Ok(rs_content) => format!("{}\n{}", rs_content, connect_content),
+            Err(_) => connect_content,
+        };
+        if let Some(parent) = rs_file.parent() {
+            fs::create_dir_all(parent).expect("Failed to create directory.");
+        }
+        fs::write(&rs_file, content).expect("Failed to write file.");
+    }
+}
+
+/// Get the package name from the descriptor set.
+fn get_package_name(descriptor_set: &FileDescriptorSet) -> Option<String> {
+    let mut package_name = None;
+
+    for file in &descriptor_set.file {
+        if let Some(package) = &file.package {
+            package_name = Some(package.clone());
+            break;
+        }
+    }
+
+    package_name
+}
+
+/// Whether the proto file should expect to generate a .rs file.
+///
+/// If the proto file contains any messages, enums, or services, then it should generate a rust file.
+/// If the proto file only contains extensions, then it will not generate any rust files.
+fn expect_fs_file_to_be_generated(descriptor_set: &FileDescriptorSet) -> bool {
+    let mut expect_rs = false;
+
+    for file in descriptor_set.file.iter() {
+        let has_messages = !file.message_type.is_empty();
+        let has_enums = !file.enum_type.is_empty();
+        let has_services = !file.service.is_empty();
+        let has_extensions = !file.extension.is_empty();
+
+        let has_definition = has_messages || has_enums || has_services;
+
+        if has_definition {
+            return true;
+        } else if !has_definition && !has_extensions {
+            expect_rs = true;
+        }
+    }
+
+    expect_rs
+}
+
+/// The field number of `service` in `google.protobuf.FileDescriptorProto`.
+const FILE_SERVICE_FIELD_NUMBER: i32 = 6;
+
+/// The field number of `method` in `google.protobuf.ServiceDescriptorProto`.
+const SERVICE_METHOD_FIELD_NUMBER: i32 = 2;
+
+/// The field number of `message_type` in `google.protobuf.FileDescriptorProto`.
+const FILE_MESSAGE_TYPE_FIELD_NUMBER: i32 = 4;
+
+/// The field number of `enum_type` in `google.protobuf.FileDescriptorProto`.
+const FILE_ENUM_TYPE_FIELD_NUMBER: i32 = 5;
+
+/// The field number of `nested_type` in `google.protobuf.DescriptorProto`.
+const MESSAGE_NESTED_TYPE_FIELD_NUMBER: i32 = 3;
+
+/// The field number of `enum_type` in `google.protobuf.DescriptorProto`.
+const MESSAGE_ENUM_TYPE_FIELD_NUMBER: i32 = 4;
+
+/// Whether the proto file should expect to generate service definitions.
+fn has_services(descriptor_set: &FileDescriptorSet) -> bool {
+    descriptor_set
+        .file
+        .iter()
+        .any(|file| !file.service.is_empty())
+}
+
+/// A proto message or enum defined in the descriptor set being compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LocalType {
+    /// The proto package the type belongs to.
+    package: String,
+
+    /// The path of the Rust type relative to its package module.
+    ///
+    /// Example: `outer::Inner`
+    rust_path: String,
+
+    /// Whether the type is an enum rather than a message.
+    is_enum: bool,
+}
+
+/// Get the path of a Rust type relative to its package module given the names of
+/// the messages it is nested in.
+fn nested_rust_type_path(parents: &[&str], name: &str) -> String {
+    parents
+        .iter()
+        .map(|parent| escape_keyword(parent.to_snake_case()))
+        .chain([escape_keyword(name.to_upper_camel_case())])
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Get the path to a type from within the module of another package.
+fn relative_rust_path(from_package: &str, to: &LocalType) -> String {
+    let from = package_module_parts(from_package);
+    let to_parts = package_module_parts(&to.package);
+    let common = from
+        .iter()
+        .zip(to_parts.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts = vec!["super".to_string(); from.len() - common];
+    parts.extend(to_parts[common..].iter().cloned());
+    parts.push(to.rust_path.clone());
+    parts.join("::")
+}
+
+/// Collect all messages and enums defined in the descriptor set keyed by their
+/// fully qualified proto name (e.g. `.pkg.Outer.Kind`).
+fn get_local_types(descriptor_set: &FileDescriptorSet) -> BTreeMap<String, LocalType> {
+    fn insert(
+        types: &mut BTreeMap<String, LocalType>,
+        package: &str,
+        parents: &[String],
+        name: &str,
+        is_enum: bool,
+    ) {
+        let proto_name = ProtoPath(package.to_string())
+            .join(&parents.join("."))
+            .join(name);
+        let parents = parents.iter().map(String::as_str).collect::<Vec<_>>();
+        types.insert(
+            format!(".{}", proto_name),
+            LocalType {
+                package: package.to_string(),
+                rust_path: nested_rust_type_path(&parents, name),
+                is_enum,
+            },
+        );
+    }
+
+    fn collect_message(
+        types: &mut BTreeMap<String, LocalType>,
+        package: &str,
+        parents: &mut Vec<String>,
+        message_type: &DescriptorProto,
+    ) {
+        insert(types, package, parents, message_type.name(), false);
+        parents.push(message_type.name().to_string());
+        for enum_type in message_type.enum_type.iter() {
+            insert(types, package, parents, enum_type.name(), true);
+        }
+        for nested_type in message_type.nested_type.iter() {
+            collect_message(types, package, parents, nested_type);
+        }
+        parents.pop();
+    }
+
+    let mut types = BTreeMap::new();
+    for file in descriptor_set.file.iter() {
+        for enum_type in file.enum_type.iter() {
+            insert(&mut types, file.package(), &[], enum_type.name(), true);
+        }
+        for message_type in file.message_type.iter() {
+            collect_message(&mut types, file.package(), &mut Vec::new(), message_type);
+        }
+    }
+
+    types
+}
+
+/// Get the Rust type of a scalar proto field.
+fn scalar_rust_type(field_type: Type) -> Option<&'static str> {
+    let rust_type = match field_type {
+        Type::Double => "f64",
+        Type::Float => "f32",
+        Type::Int64 | Type::Sint64 | Type::Sfixed64 => "i64",
+        Type::Uint64 | Type::Fixed64 => "u64",
+        Type::Int32 | Type::Sint32 | Type::Sfixed32 | Type::Enum => "i32",
+        Type::Uint32 | Type::Fixed32 => "u32",
+        Type::Bool => "bool",
+        Type::String => "::prost::alloc::string::String",
+        Type::Bytes => "::prost::alloc::vec::Vec<u8>",
+        Type::Message | Type::Group => return None,
+    };
+
+    Some(rust_type)
+}
+
+/// Resolves Rust types for proto fields from within a package module.
+struct FieldTypeResolver<'a> {
+    /// Messages and enums defined in the descriptor set being compiled.
+    local_types: &'a BTreeMap<String, LocalType>,
+
+    /// Fully qualified proto names of types from dependencies mapped to their Rust paths.
+    dep_extern_paths: &'a BTreeMap<String, String>,
+}
+
+impl FieldTypeResolver<'_> {
+    /// Get the Rust type used when setting a single value of the field.
+    ///
+    /// Returns `None` when the type of the field cannot be resolved.
+    fn resolve(&self, package: &str, field: &FieldDescriptorProto) -> Option<String> {
+        if let Some(rust_type) = scalar_rust_type(field.r#type()) {
+            return Some(rust_type.to_string());
+        }
+
+        self.resolve_type_name(package, field.type_name())
+    }
+
+    /// Get the Rust type of a message or enum from its fully qualified proto name.
+    fn resolve_type_name(&self, package: &str, type_name: &str) -> Option<String> {
+        if let Some(local_type) = self.local_types.get(type_name) {
+            return Some(relative_rust_path(package, local_type));
+        }
+
+        self.dep_extern_paths.get(type_name).cloned()
+    }
+}
+
+/// Append generated helper code to the prost output for each proto package.
+fn append_package_helpers(rust_files: &BTreeSet<PathBuf>, helpers: &BTreeMap<String, String>) {
+    for (package, content) in helpers.iter() {
+        let file_stem = if package.is_empty() { "_" } else { package };
+        let rust_file = rust_files
+            .iter()
+            .find(|path| {
+                let stem = path
+                    .file_stem()
+                    .expect("Failed to get file stem")
+                    .to_string_lossy();
+                stem.strip_suffix(".tonic").unwrap_or(&stem) == file_stem
+            })
+            .unwrap_or_else(|| panic!("Failed to find generated file for package `{}`", package));
+
+        let mut existing = fs::read_to_string(rust_file).expect("Failed to read file.");
+        existing.push('\n');
+        existing.push_str(content);
+        fs::write(rust_file, existing).expect("Failed to write file.");
+    }
+}
+
+fn main() {
+    let args = Args::parse().expect("Failed to parse args");
+
+    let diagnostics = Diagnostics::new(args.diagnostics_out.clone());
+    diagnostics.install_panic_hook();
+
+    if args.is_grpcio {
+        panic!("grpcio service stubs can only be generated along with rust-protobuf messages.");
+    }
+
+    // The scaffold is generated by an action of its own, which only runs when it's requested.
+    if args.scaffold_only {
+        write_scaffold(&args, &parse_descriptor_set_file(&args.descriptor_set));
+        return;
+    }
+
+    let out_dir = get_and_create_output_dir(&args.out_dir, &args.label);
+    let descriptor_set = parse_descriptor_set_file(&args.descriptor_set);
+    check_descriptor_set(&args, &descriptor_set, &diagnostics);
+
+    let rust_files = generate_rust_files(&args, &out_dir, &descriptor_set);
+    let module_info = build_crate_modules(&args, &descriptor_set, &rust_files);
+    write_outputs(
+        &args,
+        &descriptor_set,
+        &rust_files,
+        &module_info,
+        &diagnostics,
+    );
+
+    diagnostics.write();
+}
+
+/// Write the `main.rs` of a tonic server implementing the services of the target.
+fn write_scaffold(args: &Args, descriptor_set: &FileDescriptorSet) {
+    let Some(scaffold_output) = &args.scaffold_output else {
+        return;
+    };
+    fs::write(
+        scaffold_output,
+        generate_server_scaffold(
+            descriptor_set,
+            &args.crate_name,
+            &args.label,
+            &args.dep_extern_paths,
+            !has_service_opt(&args.extra_args, "no_server"),
+        ),
+    )
+    .expect("Failed to write file.");
+}
+
+/// Report problems with the proto files which would otherwise only surface when
+/// compiling the generated crate.
+fn check_descriptor_set(
+    args: &Args,
+    descriptor_set: &FileDescriptorSet,
+    diagnostics: &Diagnostics,
+) {
+    check_package_collisions(descriptor_set).unwrap_or_else(|err| diagnostics.fail(err));
+    for diagnostic in check_strict_deps(descriptor_set, &args.dep_extern_paths)
+        .into_iter()
+        .chain(check_protobuf_deps(descriptor_set, &args.dep_file_modules))
+    {
+        diagnostics.warn(diagnostic);
+    }
+
+    if has_services(descriptor_set) && !args.is_tonic && !args.is_connect {
+        for file in descriptor_set
+            .file
+            .iter()
+            .filter(|file| !file.service.is_empty())
+        {
+            diagnostics.warn(Diagnostic::warning(
+                file.name(),
+                "Service definitions will not be generated because the prost toolchain did not define a tonic or Connect plugin.".to_string(),
+                "Set `tonic_plugin` on the `rust_prost_toolchain` to generate services.",
+            ));
+        }
+    }
+}
+
+/// Load the incremental state of the target and compute the fingerprints of its
+/// packages to determine which of them changed since the last run.
+fn load_incremental_state(
+    args: &Args,
+    dir: &Path,
+    descriptor_set: &FileDescriptorSet,
+) -> IncrementalState {
+    let mut options = vec![
+        OsString::from(&args.crate_name),
+        OsString::from(args.is_tonic.to_string()),
+        OsString::from(args.is_connect.to_string()),
+        OsString::from(format!("{:?}", args.prost_version)),
+        args.out_dir.clone().into_os_string(),
+    ];
+    options.extend(args.extra_args.iter().cloned());
+    let plugins = plugin_paths(&args.extra_args);
+    let key = state_key(
+        &options,
+        std::iter::once(args.protoc.as_path()).chain(plugins.iter().map(PathBuf::as_path)),
+    );
+    let mut search_paths = args.includes.clone();
+    search_paths.extend(args.proto_paths.iter().cloned());
+    // The state directory may be shared by all targets and configurations, whose
+    // output directories differ.
+    let config_dir = dir.join(format!(
+        "{:016x}",
+        fnv1a(
+            FNV_OFFSET_BASIS,
+            args.out_dir.as_os_str().as_encoded_bytes()
+        )
+    ));
+    IncrementalState::load(
+        get_output_dir(&config_dir, &args.label),
+        package_fingerprints(descriptor_set, &search_paths, key),
+    )
+}
+
+/// Run protoc with the prost and service plugins and collect their outputs in
+/// `out_dir`, one file per proto package.
+fn generate_rust_files(
+    args: &Args,
+    out_dir: &Path,
+    descriptor_set: &FileDescriptorSet,
+) -> BTreeSet<PathBuf> {
+    let mut tmp_dir_name = out_dir.file_name().unwrap().to_os_string();
+    tmp_dir_name.push(".tmp");
+    let tmp_dir = out_dir.with_file_name(tmp_dir_name);
+    if tmp_dir.exists() {
+        fs::remove_dir_all(&tmp_dir).unwrap_or_else(|e| {
+            panic!("Failed to delete directory: {}\n{:?}", tmp_dir.display(), e)
+        });
+    }
+    fs::create_dir_all(&tmp_dir)
+        .unwrap_or_else(|e| panic!("Failed to create directory: {}\n{:?}", tmp_dir.display(), e));
+
+    // In incremental mode protoc is only run for the proto files of packages whose
+    // sources changed since the last run. Outputs of other packages are restored
+    // from the state directory after generation.
+    let incremental_state = args
+        .incremental_state_dir
+        .as_deref()
+        .map(|dir| load_incremental_state(args, dir, descriptor_set));
+    let changed_packages = incremental_state
+        .as_ref()
+        .map(IncrementalState::changed_packages);
+    let generated_proto_files = match &changed_packages {
+        Some(changed_packages) => {
+            let file_packages = descriptor_set
+                .file
+                .iter()
+                .map(|file| (file.name(), file.package()))
+                .collect::<BTreeMap<_, _>>();
+            args.proto_files
+                .iter()
+                .filter(|proto_file| {
+                    proto_file
+                        .to_str()
+                        .and_then(|name| file_packages.get(name))
+                        .is_none_or(|package| changed_packages.contains(*package))
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        }
+        None => args.proto_files.clone(),
+    };
+    let run_protoc = changed_packages.is_none() || !generated_proto_files.is_empty();
+
+    let args_file = out_dir.join("args.txt");
+    let mut protoc_args = Vec::new();
+
+    // Services are documented using comments from the proto sources. Protoc is
+    // asked to emit a descriptor set with source info so these comments are
+    // available to the wrapper.
+    let source_info_file = out_dir.join("source_info.pb");
+    let inject_comments = args.is_tonic && has_services(descriptor_set) && run_protoc;
+
+    protoc_args.push(os_flag("--prost_out", out_dir));
+    if args.is_tonic {
+        protoc_args.push(os_flag("--tonic_out", out_dir));
+    }
+
+    // Connect outputs are written to a separate directory as they may share the
+    // names of the prost outputs they are merged into.
+    let mut connect_dir_name = out_dir.file_name().unwrap().to_os_string();
+    connect_dir_name.push(".connect");
+    let connect_dir = out_dir.with_file_name(connect_dir_name);
+    if args.is_connect {
+        if connect_dir.exists() {
+            fs::remove_dir_all(&connect_dir).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to delete directory: {}\n{:?}",
+                    connect_dir.display(),
+                    e
+                )
+            });
+        }
+        fs::create_dir_all(&connect_dir).unwrap_or_else(|e| {
+            panic!(
+                "Failed to create directory: {}\n{:?}",
+                connect_dir.display(),
+                e
+            )
+        });
+        protoc_args.push(os_flag("--connect_out", &connect_dir));
+    }
+    if inject_comments {
+        protoc_args.push("--include_source_info".into());
+        protoc_args.push(os_flag("--descriptor_set_out", &source_info_file));
+    }
+    protoc_args.extend(args.extra_args.iter().cloned());
+    let search_path_args = args
+        .proto_paths
+        .iter()
+        .map(|proto_path| os_flag("--proto_path", proto_path))
+        .chain(args.includes.iter().map(|include| {
+            let mut arg = OsString::from("-I");
+            arg.push(include);
+            arg
+        }))
+        .collect::<Vec<_>>();
+    protoc_args.extend(search_path_args.iter().cloned());
+    protoc_args.extend(
+        generated_proto_files
+            .iter()
+            .map(|f| f.as_os_str().to_os_string()),
+    );
+
+    if run_protoc {
+        invoke_protoc(&args.protoc, &args_file, &protoc_args);
+    }
+
+    if let Some(source_map_output) = &args.source_map_output {
+        write_source_map(args, out_dir, &search_path_args, source_map_output);
+    }
+
+    fs::remove_dir_all(&tmp_dir)
+        .unwrap_or_else(|e| panic!("Failed to delete directory: {}\n{:?}", tmp_dir.display(), e));
+
+    if args.is_connect {
+        merge_connect_outputs(&connect_dir, out_dir);
+        fs::remove_dir_all(&connect_dir).unwrap_or_else(|e| {
+            panic!(
+                "Failed to delete directory: {}\n{:?}",
+                connect_dir.display(),
+                e
+            )
+        });
+    }
+
+    if args.is_tonic {
+        merge_tonic_outputs(out_dir);
+    }
+
+    // Tonic does not consistently carry RPC method comments into the generated
+    // service traits and clients so any missing docs are restored here.
+    if inject_comments {
+        let comments = get_service_method_comments(&parse_descriptor_set_file(&source_info_file));
+        if !comments.is_empty() {
+            for rust_file in find_generated_rust_files(out_dir) {
+                let content = fs::read_to_string(&rust_file).expect("Failed to read file.");
+                fs::write(&rust_file, inject_method_comments(&content, &comments))
+                    .expect("Failed to write file.");
+            }
+        }
+    }
+
+    if let (Some(state), Some(changed_packages)) = (&incremental_state, &changed_packages) {
+        state
+            .update(out_dir, changed_packages)
+            .unwrap_or_else(|e| panic!("Failed to update incremental state: {:?}", e));
+    }
+
+    // Locate all prost-generated outputs.
+    let mut rust_files = find_generated_rust_files(out_dir);
+    if rust_files.is_empty() {
+        if expect_fs_file_to_be_generated(descriptor_set) {
+            panic!("No .rs files were generated by prost.");
+        } else {
+            let package_name = get_package_name(descriptor_set).unwrap_or_default();
+            let file_stem = if package_name.is_empty() {
+                "_"
+            } else {
+                &package_name
+            };
+            let file_stem = format!("{}{}", file_stem, if args.is_tonic { ".tonic" } else { "" });
+            let empty_rs_file = out_dir.join(format!("{}.rs", file_stem));
+            fs::write(&empty_rs_file, "").expect("Failed to write file.");
+            rust_files.insert(empty_rs_file);
+        }
+    }
+
+    rust_files
+}
+
+/// Write the source map of the generated crate to `source_map_output`.
+///
+/// The source map covers all proto files, including those skipped in incremental
+/// mode, so their source info is collected by a separate protoc invocation which
+/// does not run any plugins.
+fn write_source_map(
+    args: &Args,
+    out_dir: &Path,
+    search_path_args: &[OsString],
+    source_map_output: &Path,
+) {
+    let source_map_info_file = out_dir.join("source_map.pb");
+    let mut protoc_args = vec![
+        OsString::from("--include_source_info"),
+        os_flag("--descriptor_set_out", &source_map_info_file),
+    ];
+    protoc_args.extend(search_path_args.iter().cloned());
+    protoc_args.extend(
+        args.proto_files
+            .iter()
+            .map(|f| f.as_os_str().to_os_string()),
+    );
+    invoke_protoc(
+        &args.protoc,
+        &out_dir.join("source_map_args.txt"),
+        &protoc_args,
+    );
+
+    let source_map = generate_source_map(
+        &parse_descriptor_set_file(&source_map_info_file),
+        args.is_tonic,
+    );
+    fs::write(source_map_output, render_json_object(&source_map)).expect("Failed to write file.");
+}
+
+/// Not all proto files will consistently produce `.rs` or `.tonic.rs` files. This is
+/// caused by the proto file being transpiled not having an RPC service or other protos
+/// defined (a natural and expected situation). To guarantee consistent outputs, all
+/// `.rs` files are either renamed to `.tonic.rs` if there is no `.tonic.rs` or prepended
+/// to the existing `.tonic.rs`.
+fn merge_tonic_outputs(out_dir: &Path) {
+    let tonic_files: BTreeSet<PathBuf> = find_generated_rust_files(out_dir);
+
+    for tonic_file in tonic_files.iter() {
+        if let Some(rs_file) = replace_file_name_suffix(tonic_file, ".tonic.rs", ".rs") {
+            if rs_file.exists() {
+                let rs_content = fs::read_to_string(&rs_file).expect("Failed to read file.");
+                let tonic_content = fs::read_to_string(tonic_file).expect("Failed to read file.");
+                fs::write(tonic_file, format!("{}\n{}", rs_content, tonic_content))
+                    .expect("Failed to write file.");
+                fs::remove_file(&rs_file)
+                    .unwrap_or_else(|err| panic!("Failed to remove file: {err:?}: {rs_file:?}"));
+            }
+        } else {
+            let real_tonic_file = replace_file_name_suffix(tonic_file, ".rs", ".tonic.rs")
+                .expect("Failed to strip suffix.");
+            if real_tonic_file.exists() {
+                continue;
+            }
+            fs::rename(tonic_file, &real_tonic_file).unwrap_or_else(|err| {
+                panic!("Failed to rename file: {err:?}: {tonic_file:?} -> {real_tonic_file:?}");
+            });
+        }
+    }
+}
+
+/// Append the requested helpers to the generated code of each package and assemble
+/// the module tree of the crate, including its crate level modules.
+fn build_crate_modules(
+    args: &Args,
+    descriptor_set: &FileDescriptorSet,
+    rust_files: &BTreeSet<PathBuf>,
+) -> Module {
+    let with_client = !has_service_opt(&args.extra_args, "no_client");
+
+    if args.enum_helpers {
+        append_package_helpers(
+            rust_files,
+            &generate_enum_helpers(descriptor_set, !args.prost_version.derives_enum_try_from()),
+        );
+    }
+
+    if !args.builder_packages.is_empty() {
+        append_package_helpers(
+            rust_files,
+            &generate_message_builders(
+                descriptor_set,
+                &args.builder_packages,
+                &args.dep_extern_paths,
+            ),
+        );
+    }
+
+    if args.name_constants {
+        append_package_helpers(rust_files, &generate_name_constants(descriptor_set));
+    }
+
+    if let Some(mocks_output) = &args.mocks_output {
+        fs::write(
+            mocks_output,
+            generate_service_mocks(
+                descriptor_set,
+                &args.crate_name,
+                &args.dep_extern_paths,
+                with_client,
+            )
+            .unwrap_or_else(|err| panic!("{}", err)),
+        )
+        .expect("Failed to write file.");
+    }
+
+    let mut any_type_urls = None;
+    if args.any_helpers {
+        let (helpers, type_urls) = generate_any_helpers(descriptor_set);
+        append_package_helpers(rust_files, &helpers);
+        any_type_urls = Some(type_urls);
+    }
+
+    let mut module_info = build_module_tree(
+        rust_files,
+        args.is_tonic,
+        args.direct_dep_crate_names.clone(),
+    );
+    if let Some(type_urls) = any_type_urls {
+        module_info
+            .contents
+            .push_str(&generate_any_type_url_registry(&type_urls));
+    }
+    if args.health_service {
+        if module_info.submodules.contains_key(HEALTH_MODULE_NAME) {
+            panic!(
+                "The proto package `{}` collides with the module generated for the health service.",
+                HEALTH_MODULE_NAME
+            );
+        }
+        module_info.insert(
+            HEALTH_MODULE_NAME.to_string(),
+            generate_health_module(descriptor_set),
+        );
+    }
+    if !args.wkt_mappings.is_empty() {
+        if module_info.submodules.contains_key(WKT_MODULE_NAME) {
+            panic!(
+                "The proto package `{}` collides with the module generated for well-known type mappings.",
+                WKT_MODULE_NAME
+            );
+        }
+        module_info.insert(
+            WKT_MODULE_NAME.to_string(),
+            generate_wkt_wrappers(&args.wkt_mappings, args.prost_version),
+        );
+    }
+
+    module_info
+}
+
+/// Write the `lib.rs` of the crate, its module tree and package info, and format them.
+fn write_outputs(
+    args: &Args,
+    descriptor_set: &FileDescriptorSet,
+    rust_files: &BTreeSet<PathBuf>,
+    module_info: &Module,
+    diagnostics: &Diagnostics,
+) {
+    let additional_content = read_additional_srcs(args.additional_srcs.clone());
+
+    // Outputs above the threshold are not formatted. The decision only depends on
+    // the size of the unformatted output so it is the same on every run.
+    let mut lib_rs = generate_lib_rs(module_info, &additional_content, false);
+    let skip_format = args
+        .skip_format_threshold
+        .is_some_and(|threshold| lib_rs.len() as u64 > threshold);
+    if skip_format {
+        lib_rs = generate_lib_rs(module_info, &additional_content, true);
+    }
+    if let Some(diagnostic) = args.size_budget.check(
+        lib_rs.len() as u64,
+        &package_sizes(rust_files, descriptor_set),
+    ) {
+        match diagnostic.severity {
+            Severity::Error => diagnostics.fail(diagnostic),
+            Severity::Warning => diagnostics.warn(diagnostic),
+        }
+    }
+    fs::write(&args.out_librs, lib_rs).expect("Failed to write file.");
+    if let Some(out_module_dir) = &args.out_module_dir {
+        fs::create_dir_all(out_module_dir).expect("Failed to create directory.");
+        write_module_tree(
+            out_module_dir,
+            module_info,
+            &additional_content,
+            skip_format,
+        );
+    }
+
+    let extern_paths = get_extern_paths(descriptor_set, &args.crate_name)
+        .expect("Failed to compute proto package info");
+    let service_modules = if args.is_tonic {
+        get_service_modules(
+            descriptor_set,
+            &args.crate_name,
+            !has_service_opt(&args.extra_args, "no_client"),
+            !has_service_opt(&args.extra_args, "no_server"),
+        )
+    } else {
+        BTreeMap::new()
+    };
+    fs::write(
+        &args.package_info_file,
+        render_package_info(&extern_paths, &service_modules),
+    )
+    .expect("Failed to write file.");
+
+    // Finally run rustfmt on the output lib.rs file.
+    if let Some(rustfmt) = args.rustfmt.as_deref().filter(|_| !skip_format) {
+        run_rustfmt(rustfmt, &args.out_librs, args.out_module_dir.as_deref());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use prost_types::{EnumDescriptorProto, FileDescriptorProto, ServiceDescriptorProto};
+    use std::{env, process};
+
+    #[test]
+    fn expect_fs_file_to_be_generated_test() {
+        {
+            // Empty descriptor set should create a file.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    ..FileDescriptorProto::default()
+                }],
+            };
+            assert!(expect_fs_file_to_be_generated(&descriptor_set));
+        }
+        {
+            // Descriptor set with only message should create a file.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    message_type: vec![DescriptorProto {
+                        name: Some("Foo".to_string()),
+                        ..DescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                }],
+            };
+            assert!(expect_fs_file_to_be_generated(&descriptor_set));
+        }
+        {
+            // Descriptor set with only enum should create a file.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    enum_type: vec![EnumDescriptorProto {
+                        name: Some("Foo".to_string()),
+                        ..EnumDescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                }],
+            };
+            assert!(expect_fs_file_to_be_generated(&descriptor_set));
+        }
+        {
+            // Descriptor set with only service should create a file.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    service: vec![ServiceDescriptorProto {
+                        name: Some("Foo".to_string()),
+                        ..ServiceDescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                }],
+            };
+            assert!(expect_fs_file_to_be_generated(&descriptor_set));
+        }
+        {
+            // Descriptor set with only extensions should not create a file.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    extension: vec![FieldDescriptorProto {
+                        name: Some("Foo".to_string()),
+                        ..FieldDescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                }],
+            };
+            assert!(!expect_fs_file_to_be_generated(&descriptor_set));
+        }
+    }
+
+    #[test]
+    fn has_services_test() {
+        {
+            // Empty file should not have services.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    ..FileDescriptorProto::default()
+                }],
+            };
+            assert!(!has_services(&descriptor_set));
+        }
+        {
+            // File with only message should not have services.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    message_type: vec![DescriptorProto {
+                        name: Some("Foo".to_string()),
+                        ..DescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                }],
+            };
+            assert!(!has_services(&descriptor_set));
+        }
+        {
+            // File with services should have services.
+            let descriptor_set = FileDescriptorSet {
+                file: vec![FileDescriptorProto {
+                    name: Some("foo.proto".to_string()),
+                    service: vec![ServiceDescriptorProto {
+                        name: Some("Foo".to_string()),
+                        ..ServiceDescriptorProto::default()
+                    }],
+                    ..FileDescriptorProto::default()
+                }],
+            };
+            assert!(has_services(&descriptor_set));
+        }
+    }
+
+    #[test]
+    fn relative_rust_path_test() {
+        let local_type = LocalType {
+            package: "foo.bar".to_string(),
+            rust_path: "outer::Kind".to_string(),
+            is_enum: true,
+        };
+
+        assert_eq!(relative_rust_path("foo.bar", &local_type), "outer::Kind");
+        assert_eq!(
+            relative_rust_path("foo.baz", &local_type),
+            "super::bar::outer::Kind"
+        );
+        assert_eq!(relative_rust_path("", &local_type), "foo::bar::outer::Kind");
+        assert_eq!(
+            relative_rust_path("qux.type", &local_type),
+            "super::super::foo::bar::outer::Kind"
+        );
+    }
+
+    #[test]
+    fn get_package_name_test() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("foo.proto".to_string()),
+                package: Some("foo".to_string()),
+                ..FileDescriptorProto::default()
+            }],
+        };
+
+        assert_eq!(get_package_name(&descriptor_set), Some("foo".to_string()));
+    }
+
+    #[test]
+    fn merge_connect_outputs_test() {
+        let dir = env::temp_dir().join(format!("merge_connect_outputs_test-{}", process::id()));
+        let connect_dir = dir.join("connect");
+        let out_dir = dir.join("out");
+        fs::create_dir_all(&connect_dir).unwrap();
+        fs::create_dir_all(&out_dir).unwrap();
+        fs::write(out_dir.join("greeter.rs"), "pub struct Hello {}\n").unwrap();
+        fs::write(
+            connect_dir.join("greeter.connect.rs"),
+            "pub mod greeter_client {}\n",
+        )
+        .unwrap();
+        fs::write(connect_dir.join("echo.rs"), "pub mod echo_server {}\n").unwrap();
+
+        merge_connect_outputs(&connect_dir, &out_dir);
+
+        assert_eq!(
+            fs::read_to_string(out_dir.join("greeter.rs")).unwrap(),
+            "pub struct Hello {}\n\npub mod greeter_client {}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out_dir.join("echo.rs")).unwrap(),
+            "pub mod echo_server {}\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+//! Generation of mock clients and servers for services.
+
+use std::collections::BTreeMap;
+
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost_types::FileDescriptorSet;
+
+use super::{escape_keyword, get_local_types, package_module_parts, FieldTypeResolver, ProtoPath};
+
+/// A module of the mocks crate, mirroring a module of the generated crate.
+#[derive(Default)]
+struct MockModule {
+    content: String,
+    children: BTreeMap<String, MockModule>,
+}
+
+impl MockModule {
+    /// Write the content and child modules of this module to `out`.
+    fn render(&self, out: &mut String) {
+        out.push_str(&self.content);
+        for (name, child) in self.children.iter() {
+            out.push_str(&format!("pub mod {} {{\n", name));
+            child.render(out);
+            out.push_str("}\n");
+        }
+    }
+}
+
+/// Generate the root of a crate with programmable mocks of the tonic services in
+/// the descriptor set, which depends on the generated crate `crate_name`.
+///
+/// Each service `Foo` gains a `MockFooServer` implementing the server trait with
+/// a handler per method, and unless clients are not generated, a `MockFooClient`
+/// alias of the client calling the mock in process. The mocks are placed in the
+/// modules of the mocks crate matching those of their services, e.g.
+/// `foo_mocks::pkg::MockFooServer` for `foo::pkg::foo_server::Foo`. Being a crate of
+/// its own, the generated crate is used as is by the mocks and its other dependents.
+pub(crate) fn generate_service_mocks(
+    descriptor_set: &FileDescriptorSet,
+    crate_name: &str,
+    dep_extern_paths: &BTreeMap<String, String>,
+    with_client: bool,
+) -> Result<String, String> {
+    let local_types = get_local_types(descriptor_set);
+    let resolver = FieldTypeResolver {
+        local_types: &local_types,
+        dep_extern_paths,
+    };
+    let crate_path = format!("::{}", escape_keyword(crate_name.to_string()));
+
+    let mut root = MockModule::default();
+    for file in descriptor_set.file.iter() {
+        if file.service.is_empty() {
+            continue;
+        }
+        let package = file.package();
+        let module_parts = package_module_parts(package);
+        let package_path = module_parts
+            .iter()
+            .fold(crate_path.clone(), |acc, part| format!("{}::{}", acc, part));
+
+        // Local types are resolved from the root of the generated crate.
+        let resolve = |type_name: &str| -> Result<String, String> {
+            let rust_type = resolver.resolve_type_name("", type_name).ok_or_else(|| {
+                format!(
+                    "Failed to resolve the Rust type of `{}` used by the services of `{}`.",
+                    type_name,
+                    file.name()
+                )
+            })?;
+            Ok(if rust_type.starts_with("::") {
+                rust_type
+            } else {
+                format!("{}::{}", crate_path, rust_type)
+            })
+        };
+
+        let module = module_parts.into_iter().fold(&mut root, |module, part| {
+            module.children.entry(part).or_default()
+        });
+        for service in file.service.iter() {
+            let service_name = service.name().to_upper_camel_case();
+            let service_module = service.name().to_snake_case();
+            let mock_name = format!("Mock{}Server", service_name);
+            let server_module = format!("{}::{}_server", package_path, service_module);
+            let proto_name = ProtoPath(package.to_string()).join(service.name());
+
+            let mut fields = Vec::new();
+            let mut setters = Vec::new();
+            let mut stream_types = Vec::new();
+            let mut methods = Vec::new();
+            for method in service.method.iter() {
+                let method_name = method.name().to_snake_case();
+                let field_name = escape_keyword(method_name.clone());
+                let mut request = resolve(method.input_type())?;
+                if method.client_streaming() {
+                    request = format!("tonic::Streaming<{}>", request);
+                }
+                let mut response = resolve(method.output_type())?;
+                let mut returned = response.clone();
+                if method.server_streaming() {
+                    let stream_type = format!("{}Stream", method.name());
+                    stream_types.push(format!(
+                        "    type {} = tonic::codegen::BoxStream<{}>;\n",
+                        stream_type, response
+                    ));
+                    response = format!("tonic::codegen::BoxStream<{}>", response);
+                    returned = format!("Self::{}", stream_type);
+                }
+
+                fields.push(format!(
+                    "    {}: ::core::option::Option<crate::MockHandler<{}, {}>>,\n",
+                    field_name, request, response
+                ));
+                setters.push(format!(
+                    r#"    /// Handle `{proto_name}` requests with the given function.
+    pub fn on_{method_name}<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(tonic::Request<{request}>) -> Fut + Send + Sync + 'static,
+        Fut: ::core::future::Future<Output = ::core::result::Result<tonic::Response<{response}>, tonic::Status>> + Send + 'static,
+    {{
+        self.{field_name} = ::core::option::Option::Some(::std::boxed::Box::new(move |request| ::std::boxed::Box::pin(handler(request))));
+        self
+    }}
+"#,
+                    proto_name = method.name(),
+                ));
+                methods.push(format!(
+                    r#"    async fn {field_name}(&self, request: tonic::Request<{request}>) -> ::core::result::Result<tonic::Response<{returned}>, tonic::Status> {{
+        match &self.{field_name} {{
+            ::core::option::Option::Some(handler) => handler(request).await,
+            ::core::option::Option::None => ::core::result::Result::Err(tonic::Status::unimplemented("`{service_name}.{proto_name}` is not mocked.")),
+        }}
+    }}
+"#,
+                    proto_name = method.name(),
+                ));
+            }
+
+            let client = if with_client {
+                let client_type = format!(
+                    "{}::{}_client::{}Client<{}::{}Server<{}>>",
+                    package_path,
+                    service_module,
+                    service_name,
+                    server_module,
+                    service_name,
+                    mock_name
+                );
+                setters.push(format!(
+                    "    /// Create a client calling this mock in process.\n    pub fn into_client(self) -> Mock{service_name}Client {{\n        {package_path}::{service_module}_client::{service_name}Client::new({server_module}::{service_name}Server::new(self))\n    }}\n",
+                ));
+                format!(
+                    "/// A `{service_name}Client` calling a `{mock_name}` in process.\npub type Mock{service_name}Client = {client_type};\n",
+                )
+            } else {
+                String::new()
+            };
+
+            module.content.push_str(&format!(
+                r#"/// A programmable implementation of `{proto_name}`. Methods without a handler
+/// return `Status::unimplemented`.
+#[derive(Default)]
+pub struct {mock_name} {{
+{fields}}}
+impl {mock_name} {{
+    /// Create a mock without any handlers.
+    pub fn new() -> Self {{
+        Self::default()
+    }}
+{setters}}}
+#[tonic::async_trait]
+impl {server_module}::{service_name} for {mock_name} {{
+{stream_types}{methods}}}
+{client}"#,
+                fields = fields.join(""),
+                setters = setters.join(""),
+                stream_types = stream_types.join(""),
+                methods = methods.join(""),
+            ));
+        }
+    }
+
+    let mut content = format!(
+        r#"//! Programmable mocks of the services of `{crate_name}`.
+/// The future returned by the handler of a mocked method.
+type MockFuture<T> = ::core::pin::Pin<::std::boxed::Box<dyn ::core::future::Future<Output = ::core::result::Result<tonic::Response<T>, tonic::Status>> + Send>>;
+/// The handler of a mocked method.
+type MockHandler<Req, Res> = ::std::boxed::Box<dyn Fn(tonic::Request<Req>) -> MockFuture<Res> + Send + Sync>;
+"#
+    );
+    root.render(&mut content);
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use prost_types::{MethodDescriptorProto, ServiceDescriptorProto};
+    use std::collections::BTreeMap;
+
+    use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+    #[test]
+    fn generate_service_mocks_test() {
+        let method =
+            |name: &str, client_streaming: bool, server_streaming: bool| MethodDescriptorProto {
+                name: Some(name.to_string()),
+                input_type: Some(".pkg.HelloRequest".to_string()),
+                output_type: Some(".dep.Reply".to_string()),
+                client_streaming: Some(client_streaming),
+                server_streaming: Some(server_streaming),
+                ..MethodDescriptorProto::default()
+            };
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("pkg.proto".to_string()),
+                package: Some("pkg".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("HelloRequest".to_string()),
+                    ..DescriptorProto::default()
+                }],
+                service: vec![ServiceDescriptorProto {
+                    name: Some("Greeter".to_string()),
+                    method: vec![
+                        method("SayHello", false, false),
+                        method("SayHellos", true, true),
+                    ],
+                    ..ServiceDescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        };
+        let dep_extern_paths =
+            BTreeMap::from([(".dep.Reply".to_string(), "::dep::dep::Reply".to_string())]);
+
+        let mocks =
+            generate_service_mocks(&descriptor_set, "greeter", &dep_extern_paths, true).unwrap();
+        assert!(
+            mocks.starts_with("//! Programmable mocks of the services of `greeter`.\n"),
+            "{}",
+            mocks
+        );
+        assert!(
+            mocks.contains("pub mod pkg {\n/// A programmable implementation of `pkg.Greeter`."),
+            "{}",
+            mocks
+        );
+        assert!(
+            mocks.contains("    say_hello: ::core::option::Option<crate::MockHandler<::greeter::pkg::HelloRequest, ::dep::dep::Reply>>,\n"),
+            "{}",
+            mocks
+        );
+        assert!(
+            mocks.contains("    say_hellos: ::core::option::Option<crate::MockHandler<tonic::Streaming<::greeter::pkg::HelloRequest>, tonic::codegen::BoxStream<::dep::dep::Reply>>>,\n"),
+            "{}",
+            mocks
+        );
+        assert!(
+            mocks.contains("impl ::greeter::pkg::greeter_server::Greeter for MockGreeterServer {\n    type SayHellosStream = tonic::codegen::BoxStream<::dep::dep::Reply>;\n"),
+            "{}",
+            mocks
+        );
+        assert!(
+            mocks.contains("pub type MockGreeterClient = ::greeter::pkg::greeter_client::GreeterClient<::greeter::pkg::greeter_server::GreeterServer<MockGreeterServer>>;"),
+            "{}",
+            mocks
+        );
+        assert!(mocks.ends_with("<MockGreeterServer>>;\n}\n"), "{}", mocks);
+
+        let mocks =
+            generate_service_mocks(&descriptor_set, "greeter", &dep_extern_paths, false).unwrap();
+        assert!(!mocks.contains("MockGreeterClient"));
+
+        // Types which cannot be resolved are reported.
+        assert!(
+            generate_service_mocks(&descriptor_set, "greeter", &BTreeMap::new(), true).is_err()
+        );
+    }
+}
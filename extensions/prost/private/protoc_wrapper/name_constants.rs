@@ -0,0 +1,134 @@
+//! Generation of constants holding the fully qualified proto names of messages and enums.
+
+use std::collections::BTreeMap;
+
+use prost_types::FileDescriptorSet;
+
+use super::{for_each_enum, for_each_message, nested_rust_type_path};
+
+/// Generate constants holding the fully qualified proto names of all messages
+/// and enums in the descriptor set, along with value name lookup tables for enums.
+///
+/// The generated code is returned per proto package.
+pub(crate) fn generate_name_constants(
+    descriptor_set: &FileDescriptorSet,
+) -> BTreeMap<String, String> {
+    let mut helpers: BTreeMap<String, String> = BTreeMap::new();
+
+    for_each_message(
+        descriptor_set,
+        |package, parents, proto_path, message_type| {
+            let rust_path = nested_rust_type_path(parents, message_type.name());
+            helpers
+                .entry(package.to_string())
+                .or_default()
+                .push_str(&format!(
+                    "impl {rust_path} {{
+    /// The fully qualified proto name of the message.
+    pub const FULL_NAME: &'static str = \"{proto_path}\";
+}}
+"
+                ));
+        },
+    );
+
+    for_each_enum(descriptor_set, |package, parents, proto_path, enum_type| {
+        let rust_path = nested_rust_type_path(parents, enum_type.name());
+        let value_names = enum_type
+            .value
+            .iter()
+            .map(|value| format!("        (\"{}\", {}),\n", value.name(), value.number()))
+            .collect::<String>();
+        helpers
+            .entry(package.to_string())
+            .or_default()
+            .push_str(&format!(
+                "impl {rust_path} {{
+    /// The fully qualified proto name of the enum.
+    pub const FULL_NAME: &'static str = \"{proto_path}\";
+
+    /// The proto names of all values of the enum paired with their numbers.
+    pub const VALUE_NAMES: &'static [(&'static str, i32)] = &[
+{value_names}    ];
+
+    /// Get the proto name of a value of the enum, if it is known.
+    pub fn value_name(value: i32) -> ::core::option::Option<&'static str> {{
+        Self::VALUE_NAMES
+            .iter()
+            .find(|(_, number)| *number == value)
+            .map(|(name, _)| *name)
+    }}
+}}
+"
+            ));
+    });
+
+    helpers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use prost_types::{
+        DescriptorProto, EnumDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    };
+
+    #[test]
+    fn generate_name_constants_test() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("a.proto".to_string()),
+                package: Some("pkg.a".to_string()),
+                enum_type: vec![EnumDescriptorProto {
+                    name: Some("Color".to_string()),
+                    value: vec![
+                        prost_types::EnumValueDescriptorProto {
+                            name: Some("COLOR_UNSPECIFIED".to_string()),
+                            number: Some(0),
+                            ..Default::default()
+                        },
+                        prost_types::EnumValueDescriptorProto {
+                            name: Some("COLOR_RED".to_string()),
+                            number: Some(3),
+                            ..Default::default()
+                        },
+                    ],
+                    ..EnumDescriptorProto::default()
+                }],
+                message_type: vec![DescriptorProto {
+                    name: Some("Outer".to_string()),
+                    enum_type: vec![EnumDescriptorProto {
+                        name: Some("Kind".to_string()),
+                        ..EnumDescriptorProto::default()
+                    }],
+                    nested_type: vec![DescriptorProto {
+                        name: Some("ValuesEntry".to_string()),
+                        options: Some(prost_types::MessageOptions {
+                            map_entry: Some(true),
+                            ..prost_types::MessageOptions::default()
+                        }),
+                        ..DescriptorProto::default()
+                    }],
+                    ..DescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        };
+
+        let helpers = generate_name_constants(&descriptor_set);
+        let pkg = &helpers["pkg.a"];
+        assert!(
+            pkg.contains("impl Outer {\n    /// The fully qualified proto name of the message.\n    pub const FULL_NAME: &'static str = \"pkg.a.Outer\";"),
+            "{}",
+            pkg
+        );
+        assert!(
+            pkg.contains("impl outer::Kind {\n    /// The fully qualified proto name of the enum.\n    pub const FULL_NAME: &'static str = \"pkg.a.Outer.Kind\";"),
+            "{}",
+            pkg
+        );
+        assert!(pkg.contains("        (\"COLOR_UNSPECIFIED\", 0),\n        (\"COLOR_RED\", 3),\n"));
+        assert!(!pkg.contains("ValuesEntry"), "{}", pkg);
+    }
+}
@@ -0,0 +1,305 @@
+//! Generation of a server scaffold implementing the services of the target.
+
+use std::collections::BTreeMap;
+
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost_types::FileDescriptorSet;
+
+use super::{escape_keyword, get_local_types, package_module_parts, FieldTypeResolver, ProtoPath};
+
+/// Generate the `main.rs` of a tonic server implementing every service in the
+/// descriptor set with `unimplemented!()` bodies, as a starting point for new
+/// servers. Types are referred to by their absolute paths in `crate_name` and
+/// its dependencies. If the services cannot be implemented, the scaffold fails to
+/// compile with an explanation rather than failing the generation of the crate.
+pub(crate) fn generate_server_scaffold(
+    descriptor_set: &FileDescriptorSet,
+    crate_name: &str,
+    label: &str,
+    dep_extern_paths: &BTreeMap<String, String>,
+    with_server: bool,
+) -> String {
+    let header = format!(
+        "// @generated by the prost wrapper for `{}`. Copy this file into your sources to edit it.\n",
+        label
+    );
+    let scaffold = render_server_scaffold(
+        descriptor_set,
+        crate_name,
+        label,
+        dep_extern_paths,
+        with_server,
+    );
+    match scaffold {
+        Ok(content) => format!("{}{}", header, content),
+        Err(err) => format!("{}compile_error!({:?});\n\nfn main() {{}}\n", header, err),
+    }
+}
+
+/// Render the body of the server scaffold of `generate_server_scaffold`.
+fn render_server_scaffold(
+    descriptor_set: &FileDescriptorSet,
+    crate_name: &str,
+    label: &str,
+    dep_extern_paths: &BTreeMap<String, String>,
+    with_server: bool,
+) -> Result<String, String> {
+    if !with_server {
+        return Err(format!(
+            "`{}` does not generate tonic servers as `no_server` is set.",
+            label
+        ));
+    }
+
+    let services = descriptor_set
+        .file
+        .iter()
+        .flat_map(|file| file.service.iter().map(move |service| (file, service)))
+        .collect::<Vec<_>>();
+    if services.is_empty() {
+        return Err(format!("`{}` defines no services.", label));
+    }
+
+    let local_types = get_local_types(descriptor_set);
+    let resolver = FieldTypeResolver {
+        local_types: &local_types,
+        dep_extern_paths,
+    };
+    let crate_path = format!("::{}", escape_keyword(crate_name.to_string()));
+
+    // Services are implemented by structs named after them unless several
+    // packages define a service of the same name.
+    let mut name_counts = BTreeMap::new();
+    for (_, service) in services.iter() {
+        *name_counts.entry(service.name()).or_insert(0) += 1;
+    }
+
+    let mut impls = String::new();
+    let mut add_services = String::new();
+    for (file, service) in services {
+        let package = file.package();
+        let service_name = service.name().to_upper_camel_case();
+        let server_module = package_module_parts(package)
+            .into_iter()
+            .fold(crate_path.clone(), |acc, part| format!("{}::{}", acc, part))
+            + &format!("::{}_server", service.name().to_snake_case());
+        let struct_name = if name_counts[service.name()] > 1 {
+            format!("{}{}Impl", package.to_upper_camel_case(), service_name)
+        } else {
+            format!("{}Impl", service_name)
+        };
+        let proto_name = ProtoPath(package.to_string()).join(service.name());
+
+        // Local types are resolved from the root of the crate.
+        let resolve = |type_name: &str| -> Result<String, String> {
+            let rust_type = resolver.resolve_type_name("", type_name).ok_or_else(|| {
+                format!(
+                    "Failed to resolve the Rust type of `{}` used by `{}`.",
+                    type_name, proto_name
+                )
+            })?;
+            Ok(if rust_type.starts_with("::") {
+                rust_type
+            } else {
+                format!("{}::{}", crate_path, rust_type)
+            })
+        };
+
+        let mut stream_types = Vec::new();
+        let mut methods = Vec::new();
+        for method in service.method.iter() {
+            let mut request = resolve(method.input_type())?;
+            if method.client_streaming() {
+                request = format!("tonic::Streaming<{}>", request);
+            }
+            let mut response = resolve(method.output_type())?;
+            if method.server_streaming() {
+                let stream_type = format!("{}Stream", method.name());
+                stream_types.push(format!(
+                    "    type {} = tonic::codegen::BoxStream<{}>;\n\n",
+                    stream_type, response
+                ));
+                response = format!("Self::{}", stream_type);
+            }
+
+            methods.push(format!(
+                r#"    async fn {method_name}(
+        &self,
+        _request: tonic::Request<{request}>,
+    ) -> ::core::result::Result<tonic::Response<{response}>, tonic::Status> {{
+        unimplemented!("`{proto_name}.{proto_method}`")
+    }}
+"#,
+                method_name = escape_keyword(method.name().to_snake_case()),
+                proto_method = method.name(),
+            ));
+        }
+
+        impls.push_str(&format!(
+            r#"
+/// An implementation of `{proto_name}`.
+#[derive(Debug, Default)]
+pub struct {struct_name};
+
+#[tonic::async_trait]
+impl {server_module}::{service_name} for {struct_name} {{
+{stream_types}{methods}}}
+"#,
+            stream_types = stream_types.join(""),
+            methods = methods.join("\n"),
+        ));
+        add_services.push_str(&format!(
+            "        .add_service({}::{}Server::new({}))\n",
+            server_module, service_name, struct_name
+        ));
+    }
+
+    Ok(format!(
+        r#"//! A tonic server implementing the services of `{label}`.
+{impls}
+/// Serve all services on the address given as the first argument, `[::1]:50051`
+/// by default.
+#[tokio::main]
+async fn main() -> ::core::result::Result<(), ::std::boxed::Box<dyn ::std::error::Error>> {{
+    let addr = ::std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "[::1]:50051".to_string())
+        .parse()?;
+    println!("Serving `{label}` on {{}}", addr);
+
+    tonic::transport::Server::builder()
+{add_services}        .serve(addr)
+        .await?;
+
+    Ok(())
+}}
+"#
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use prost_types::{MethodDescriptorProto, ServiceDescriptorProto};
+    use std::collections::BTreeMap;
+
+    use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+    #[test]
+    fn generate_server_scaffold_test() {
+        let method =
+            |name: &str, client_streaming: bool, server_streaming: bool| MethodDescriptorProto {
+                name: Some(name.to_string()),
+                input_type: Some(".pkg.HelloRequest".to_string()),
+                output_type: Some(".dep.Reply".to_string()),
+                client_streaming: Some(client_streaming),
+                server_streaming: Some(server_streaming),
+                ..MethodDescriptorProto::default()
+            };
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("pkg.proto".to_string()),
+                package: Some("pkg".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("HelloRequest".to_string()),
+                    ..DescriptorProto::default()
+                }],
+                service: vec![ServiceDescriptorProto {
+                    name: Some("Greeter".to_string()),
+                    method: vec![
+                        method("SayHello", false, false),
+                        method("SayHellos", true, true),
+                    ],
+                    ..ServiceDescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        };
+        let dep_extern_paths =
+            BTreeMap::from([(".dep.Reply".to_string(), "::dep::dep::Reply".to_string())]);
+
+        let scaffold = generate_server_scaffold(
+            &descriptor_set,
+            "pkg_proto",
+            "//pkg:pkg_proto",
+            &dep_extern_paths,
+            true,
+        );
+        assert_eq!(
+            scaffold,
+            r#"// @generated by the prost wrapper for `//pkg:pkg_proto`. Copy this file into your sources to edit it.
+//! A tonic server implementing the services of `//pkg:pkg_proto`.
+
+/// An implementation of `pkg.Greeter`.
+#[derive(Debug, Default)]
+pub struct GreeterImpl;
+
+#[tonic::async_trait]
+impl ::pkg_proto::pkg::greeter_server::Greeter for GreeterImpl {
+    type SayHellosStream = tonic::codegen::BoxStream<::dep::dep::Reply>;
+
+    async fn say_hello(
+        &self,
+        _request: tonic::Request<::pkg_proto::pkg::HelloRequest>,
+    ) -> ::core::result::Result<tonic::Response<::dep::dep::Reply>, tonic::Status> {
+        unimplemented!("`pkg.Greeter.SayHello`")
+    }
+
+    async fn say_hellos(
+        &self,
+        _request: tonic::Request<tonic::Streaming<::pkg_proto::pkg::HelloRequest>>,
+    ) -> ::core::result::Result<tonic::Response<Self::SayHellosStream>, tonic::Status> {
+        unimplemented!("`pkg.Greeter.SayHellos`")
+    }
+}
+
+/// Serve all services on the address given as the first argument, `[::1]:50051`
+/// by default.
+#[tokio::main]
+async fn main() -> ::core::result::Result<(), ::std::boxed::Box<dyn ::std::error::Error>> {
+    let addr = ::std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "[::1]:50051".to_string())
+        .parse()?;
+    println!("Serving `//pkg:pkg_proto` on {}", addr);
+
+    tonic::transport::Server::builder()
+        .add_service(::pkg_proto::pkg::greeter_server::GreeterServer::new(GreeterImpl))
+        .serve(addr)
+        .await?;
+
+    Ok(())
+}
+"#
+        );
+
+        // Services which cannot be implemented produce a scaffold failing to compile.
+        assert_eq!(
+            generate_server_scaffold(
+                &descriptor_set,
+                "pkg_proto",
+                "//pkg:pkg_proto",
+                &dep_extern_paths,
+                false,
+            ),
+            "// @generated by the prost wrapper for `//pkg:pkg_proto`. Copy this file into your sources to edit it.\ncompile_error!(\"`//pkg:pkg_proto` does not generate tonic servers as `no_server` is set.\");\n\nfn main() {}\n"
+        );
+        assert!(generate_server_scaffold(
+            &descriptor_set,
+            "pkg_proto",
+            "//pkg:pkg_proto",
+            &BTreeMap::new(),
+            true,
+        )
+        .contains("compile_error!(\"Failed to resolve the Rust type of `.dep.Reply` used by `pkg.Greeter`.\");"));
+        assert!(generate_server_scaffold(
+            &FileDescriptorSet::default(),
+            "pkg_proto",
+            "//pkg:pkg_proto",
+            &dep_extern_paths,
+            true,
+        )
+        .contains("compile_error!(\"`//pkg:pkg_proto` defines no services.\");"));
+    }
+}
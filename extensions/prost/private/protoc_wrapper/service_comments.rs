@@ -0,0 +1,379 @@
+//! Restoring the comments of RPC methods which tonic drops from the generated services.
+
+use std::collections::BTreeMap;
+
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost_types::FileDescriptorSet;
+
+use super::{FILE_SERVICE_FIELD_NUMBER, SERVICE_METHOD_FIELD_NUMBER};
+
+/// Collect the leading comments of all services and RPC methods from the source
+/// info of a descriptor set. The comments are keyed by the service trait name and
+/// method function name tonic generates for them. Service comments use an empty
+/// method name.
+pub(crate) fn get_service_method_comments(
+    descriptor_set: &FileDescriptorSet,
+) -> BTreeMap<(String, String), String> {
+    let mut comments = BTreeMap::new();
+
+    for file in descriptor_set.file.iter() {
+        let source_code_info = match &file.source_code_info {
+            Some(info) => info,
+            None => continue,
+        };
+
+        for location in source_code_info.location.iter() {
+            let comment = match &location.leading_comments {
+                Some(comment) if !comment.trim().is_empty() => comment,
+                _ => continue,
+            };
+
+            match location.path.as_slice() {
+                [FILE_SERVICE_FIELD_NUMBER, service_index] => {
+                    let Some(service) = file.service.get(*service_index as usize) else {
+                        continue;
+                    };
+                    comments.insert(
+                        (service.name().to_upper_camel_case(), String::new()),
+                        comment.clone(),
+                    );
+                }
+                [FILE_SERVICE_FIELD_NUMBER, service_index, SERVICE_METHOD_FIELD_NUMBER, method_index] =>
+                {
+                    let Some((service, method)) = file
+                        .service
+                        .get(*service_index as usize)
+                        .and_then(|service| {
+                            Some((service, service.method.get(*method_index as usize)?))
+                        })
+                    else {
+                        continue;
+                    };
+                    comments.insert(
+                        (
+                            service.name().to_upper_camel_case(),
+                            method.name().to_snake_case(),
+                        ),
+                        comment.clone(),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    comments
+}
+
+/// Parse the name of the module a line of tonic output declares, e.g. `pub mod greeter_client {`.
+fn parse_module_name(line: &str) -> Option<&str> {
+    line.strip_prefix("pub mod ").and_then(parse_identifier)
+}
+
+/// Parse the name of the service a line of tonic output in `module` belongs to. This only
+/// matches the items tonic generates for a service in its `greeter_server` and `greeter_client`
+/// modules: the server trait (`pub trait Greeter`) and the generic server and client structs
+/// (`pub struct GreeterServer<T>`, `impl<T> GreeterClient<T>`). Messages which happen to be
+/// named like them aren't matched, as prost doesn't generate them in these modules.
+fn parse_tonic_service_name(line: &str, module: Option<&str>) -> Option<String> {
+    let module = module?;
+    if let Some(rest) = line.strip_prefix("pub trait ") {
+        let name = parse_identifier(rest)?;
+        return (module == format!("{}_server", name.to_snake_case())).then(|| name.to_string());
+    }
+
+    let rest = line.strip_prefix("pub struct ").or_else(|| {
+        line.strip_prefix("impl<")
+            .and_then(|rest| rest.split_once("> ").map(|(_, rest)| rest))
+    })?;
+    let name = parse_identifier(rest)?;
+    if !rest.trim_start_matches("r#")[name.len()..].starts_with('<') {
+        return None;
+    }
+    let (service, suffix) = if let Some(service) = name.strip_suffix("Client") {
+        (service, "_client")
+    } else {
+        (name.strip_suffix("Server")?, "_server")
+    };
+    (module == format!("{}{suffix}", service.to_snake_case())).then(|| service.to_string())
+}
+
+/// Parse the name of an `async fn` declared on a line of tonic output.
+fn parse_tonic_method_name(line: &str) -> Option<String> {
+    let rest = line.strip_prefix("pub ").unwrap_or(line);
+    let rest = rest.strip_prefix("async fn ")?;
+
+    parse_identifier(rest).map(|name| name.to_string())
+}
+
+/// Parse the leading Rust identifier from a string.
+fn parse_identifier(s: &str) -> Option<&str> {
+    let s = s.strip_prefix("r#").unwrap_or(s);
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    if end == 0 {
+        return None;
+    }
+
+    Some(&s[..end])
+}
+
+/// Whether the item following the given lines already has documentation.
+fn has_doc_comment(lines: &[String]) -> bool {
+    for line in lines.iter().rev() {
+        let line = line.trim_start();
+        if line.starts_with("///") || line.starts_with("#[doc") {
+            return true;
+        }
+        if !line.starts_with("#[") {
+            return false;
+        }
+    }
+
+    false
+}
+
+/// Add rustdoc comments from the proto sources to tonic services and methods
+/// which were generated without any documentation.
+pub(crate) fn inject_method_comments(
+    content: &str,
+    comments: &BTreeMap<(String, String), String>,
+) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut module: Option<String> = None;
+    let mut service: Option<String> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        let mut key = None;
+        if let Some(name) = parse_module_name(trimmed) {
+            module = Some(name.to_string());
+            service = None;
+        } else if let Some(name) = parse_tonic_service_name(trimmed, module.as_deref()) {
+            if trimmed.starts_with("pub ") {
+                key = Some((name.clone(), String::new()));
+            }
+            service = Some(name);
+        } else if let (Some(service), Some(method)) = (&service, parse_tonic_method_name(trimmed)) {
+            key = Some((service.clone(), method));
+        }
+
+        if let Some(comment) = key.and_then(|key| comments.get(&key)) {
+            if !has_doc_comment(&lines) {
+                let indent = &line[..line.len() - trimmed.len()];
+                lines.extend(
+                    comment
+                        .trim_end()
+                        .lines()
+                        .map(|comment_line| format!("{}///{}", indent, comment_line)),
+                );
+            }
+        }
+
+        lines.push(line.to_string());
+    }
+
+    let mut output = lines.join("\n");
+    if content.ends_with('\n') {
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use prost_types::source_code_info::Location;
+    use prost_types::{MethodDescriptorProto, ServiceDescriptorProto, SourceCodeInfo};
+    use std::collections::BTreeMap;
+
+    use prost_types::{FileDescriptorProto, FileDescriptorSet};
+
+    #[test]
+    fn get_service_method_comments_test() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("foo.proto".to_string()),
+                service: vec![ServiceDescriptorProto {
+                    name: Some("greeter".to_string()),
+                    method: vec![
+                        MethodDescriptorProto {
+                            name: Some("SayHello".to_string()),
+                            ..MethodDescriptorProto::default()
+                        },
+                        MethodDescriptorProto {
+                            name: Some("SayGoodbye".to_string()),
+                            ..MethodDescriptorProto::default()
+                        },
+                    ],
+                    ..ServiceDescriptorProto::default()
+                }],
+                source_code_info: Some(SourceCodeInfo {
+                    location: vec![
+                        Location {
+                            path: vec![6, 0],
+                            leading_comments: Some(" The greeting service.\n".to_string()),
+                            ..Location::default()
+                        },
+                        Location {
+                            path: vec![6, 0, 2, 1],
+                            leading_comments: Some(" Sends a farewell.\n".to_string()),
+                            ..Location::default()
+                        },
+                        Location {
+                            path: vec![6, 0, 2, 0],
+                            leading_comments: Some("\n".to_string()),
+                            ..Location::default()
+                        },
+                        // Locations of unknown services and methods are ignored.
+                        Location {
+                            path: vec![6, 1],
+                            leading_comments: Some(" Unknown.\n".to_string()),
+                            ..Location::default()
+                        },
+                        Location {
+                            path: vec![6, 0, 2, 2],
+                            leading_comments: Some(" Unknown.\n".to_string()),
+                            ..Location::default()
+                        },
+                    ],
+                }),
+                ..FileDescriptorProto::default()
+            }],
+        };
+
+        let comments = get_service_method_comments(&descriptor_set);
+        assert_eq!(comments.len(), 2);
+        assert_eq!(
+            comments.get(&("Greeter".to_string(), String::new())),
+            Some(&" The greeting service.\n".to_string())
+        );
+        assert_eq!(
+            comments.get(&("Greeter".to_string(), "say_goodbye".to_string())),
+            Some(&" Sends a farewell.\n".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_tonic_service_name_test() {
+        let client = Some("greeter_client");
+        let server = Some("greeter_server");
+
+        assert_eq!(
+            parse_tonic_service_name("pub struct GreeterClient<T> {", client),
+            Some("Greeter".to_string())
+        );
+        assert_eq!(
+            parse_tonic_service_name("impl<T> GreeterClient<T>", client),
+            Some("Greeter".to_string())
+        );
+        assert_eq!(
+            parse_tonic_service_name("pub struct GreeterServer<T> {", server),
+            Some("Greeter".to_string())
+        );
+        assert_eq!(
+            parse_tonic_service_name("pub trait Greeter: Send + Sync + 'static {", server),
+            Some("Greeter".to_string())
+        );
+
+        // Items outside of the modules of the service, and messages, aren't services.
+        assert_eq!(
+            parse_tonic_service_name("pub trait Greeter: Send {", client),
+            None
+        );
+        assert_eq!(
+            parse_tonic_service_name("pub struct GreeterClient<T> {", None),
+            None
+        );
+        assert_eq!(
+            parse_tonic_service_name("pub struct GreeterClient<T> {", server),
+            None
+        );
+        assert_eq!(
+            parse_tonic_service_name("pub struct GreeterClient {", client),
+            None
+        );
+        assert_eq!(
+            parse_tonic_service_name("pub struct HelloRequest {", client),
+            None
+        );
+    }
+
+    #[test]
+    fn inject_method_comments_test() {
+        let comments = BTreeMap::from([
+            (
+                ("Greeter".to_string(), "say_hello".to_string()),
+                " Sends a greeting.\n Second line.\n".to_string(),
+            ),
+            (
+                ("Greeter".to_string(), "say_goodbye".to_string()),
+                " Sends a farewell.\n".to_string(),
+            ),
+            (
+                ("Greeter".to_string(), String::new()),
+                " The greeting service.\n".to_string(),
+            ),
+        ]);
+
+        let content = [
+            "pub mod messages {",
+            "    pub struct Greeter {",
+            "    }",
+            "}",
+            "pub mod greeter_client {",
+            "    impl<T> GreeterClient<T> {",
+            "        pub async fn say_hello(",
+            "        ) {}",
+            "        /// Already documented.",
+            "        pub async fn say_goodbye(",
+            "        ) {}",
+            "    }",
+            "}",
+            "pub mod greeter_server {",
+            "    #[async_trait]",
+            "    pub trait Greeter: std::marker::Send + std::marker::Sync + 'static {",
+            "        async fn say_hello(",
+            "        );",
+            "    }",
+            "}",
+            "",
+        ]
+        .join("\n");
+
+        let expected = [
+            "pub mod messages {",
+            "    pub struct Greeter {",
+            "    }",
+            "}",
+            "pub mod greeter_client {",
+            "    impl<T> GreeterClient<T> {",
+            "        /// Sends a greeting.",
+            "        /// Second line.",
+            "        pub async fn say_hello(",
+            "        ) {}",
+            "        /// Already documented.",
+            "        pub async fn say_goodbye(",
+            "        ) {}",
+            "    }",
+            "}",
+            "pub mod greeter_server {",
+            "    #[async_trait]",
+            "    /// The greeting service.",
+            "    pub trait Greeter: std::marker::Send + std::marker::Sync + 'static {",
+            "        /// Sends a greeting.",
+            "        /// Second line.",
+            "        async fn say_hello(",
+            "        );",
+            "    }",
+            "}",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(inject_method_comments(&content, &comments), expected);
+    }
+}
@@ -0,0 +1,250 @@
+//! Generation of the source map from generated Rust paths to the proto definitions.
+
+use std::collections::BTreeMap;
+
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use prost_types::{DescriptorProto, FileDescriptorSet};
+
+use super::{
+    json_string, nested_rust_type_path, package_module_parts, FILE_ENUM_TYPE_FIELD_NUMBER,
+    FILE_MESSAGE_TYPE_FIELD_NUMBER, FILE_SERVICE_FIELD_NUMBER, MESSAGE_ENUM_TYPE_FIELD_NUMBER,
+    MESSAGE_NESTED_TYPE_FIELD_NUMBER,
+};
+
+/// Map the Rust paths of generated types to the `file:line` of their definition in
+/// the proto sources using the source info of the descriptor set.
+///
+/// Messages, enums and, when generating tonic code, service traits and clients are
+/// included. Paths are relative to the root of the generated crate.
+pub(crate) fn generate_source_map(
+    descriptor_set: &FileDescriptorSet,
+    is_tonic: bool,
+) -> BTreeMap<String, String> {
+    /// Add a message and all of its nested types to the source map.
+    #[allow(clippy::too_many_arguments)]
+    fn add_message(
+        source_map: &mut BTreeMap<String, String>,
+        lines: &BTreeMap<&[i32], i32>,
+        file_name: &str,
+        module: &[String],
+        parents: &mut Vec<String>,
+        path: &mut Vec<i32>,
+        message_type: &DescriptorProto,
+    ) {
+        if message_type
+            .options
+            .as_ref()
+            .is_some_and(|options| options.map_entry())
+        {
+            return;
+        }
+
+        let parent_names = parents.iter().map(String::as_str).collect::<Vec<_>>();
+        if let Some(line) = lines.get(path.as_slice()) {
+            let rust_path = nested_rust_type_path(&parent_names, message_type.name());
+            source_map.insert(
+                join_rust_path(module, &rust_path),
+                format!("{}:{}", file_name, line + 1),
+            );
+        }
+
+        parents.push(message_type.name().to_string());
+        let parent_names = parents.iter().map(String::as_str).collect::<Vec<_>>();
+        for (index, enum_type) in message_type.enum_type.iter().enumerate() {
+            path.extend([MESSAGE_ENUM_TYPE_FIELD_NUMBER, index as i32]);
+            if let Some(line) = lines.get(path.as_slice()) {
+                let rust_path = nested_rust_type_path(&parent_names, enum_type.name());
+                source_map.insert(
+                    join_rust_path(module, &rust_path),
+                    format!("{}:{}", file_name, line + 1),
+                );
+            }
+            path.truncate(path.len() - 2);
+        }
+        for (index, nested_type) in message_type.nested_type.iter().enumerate() {
+            path.extend([MESSAGE_NESTED_TYPE_FIELD_NUMBER, index as i32]);
+            add_message(
+                source_map,
+                lines,
+                file_name,
+                module,
+                parents,
+                path,
+                nested_type,
+            );
+            path.truncate(path.len() - 2);
+        }
+        parents.pop();
+    }
+
+    let mut source_map = BTreeMap::new();
+    for file in descriptor_set.file.iter() {
+        let source_code_info = match &file.source_code_info {
+            Some(info) => info,
+            None => continue,
+        };
+        let lines = source_code_info
+            .location
+            .iter()
+            .filter_map(|location| Some((location.path.as_slice(), *location.span.first()?)))
+            .collect::<BTreeMap<_, _>>();
+        let module = package_module_parts(file.package());
+
+        for (index, message_type) in file.message_type.iter().enumerate() {
+            add_message(
+                &mut source_map,
+                &lines,
+                file.name(),
+                &module,
+                &mut Vec::new(),
+                &mut vec![FILE_MESSAGE_TYPE_FIELD_NUMBER, index as i32],
+                message_type,
+            );
+        }
+
+        for (index, enum_type) in file.enum_type.iter().enumerate() {
+            if let Some(line) = lines.get([FILE_ENUM_TYPE_FIELD_NUMBER, index as i32].as_slice()) {
+                source_map.insert(
+                    join_rust_path(&module, &nested_rust_type_path(&[], enum_type.name())),
+                    format!("{}:{}", file.name(), line + 1),
+                );
+            }
+        }
+
+        if !is_tonic {
+            continue;
+        }
+        for (index, service) in file.service.iter().enumerate() {
+            if let Some(line) = lines.get([FILE_SERVICE_FIELD_NUMBER, index as i32].as_slice()) {
+                let location = format!("{}:{}", file.name(), line + 1);
+                let service_name = service.name().to_upper_camel_case();
+                let service_module = service.name().to_snake_case();
+                source_map.insert(
+                    join_rust_path(&module, &format!("{service_module}_server::{service_name}")),
+                    location.clone(),
+                );
+                source_map.insert(
+                    join_rust_path(
+                        &module,
+                        &format!("{service_module}_client::{service_name}Client"),
+                    ),
+                    location,
+                );
+            }
+        }
+    }
+
+    source_map
+}
+
+/// Join the path of a type within a package module to the path of the module.
+fn join_rust_path(module: &[String], rust_path: &str) -> String {
+    module
+        .iter()
+        .map(String::as_str)
+        .chain([rust_path])
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Render a JSON object with string values, with keys in sorted order.
+pub(crate) fn render_json_object(entries: &BTreeMap<String, String>) -> String {
+    if entries.is_empty() {
+        return "{}\n".to_string();
+    }
+
+    let entries = entries
+        .iter()
+        .map(|(key, value)| format!("  {}: {}", json_string(key), json_string(value)))
+        .collect::<Vec<_>>()
+        .join(",\n");
+    format!("{{\n{}\n}}\n", entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use prost_types::{
+        DescriptorProto, EnumDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    };
+
+    #[test]
+    fn generate_source_map_test() {
+        let location = |path: &[i32], line: i32| prost_types::source_code_info::Location {
+            path: path.to_vec(),
+            span: vec![line, 0, 10],
+            ..Default::default()
+        };
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("pkg/a.proto".to_string()),
+                package: Some("pkg.a".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Outer".to_string()),
+                    enum_type: vec![EnumDescriptorProto {
+                        name: Some("Kind".to_string()),
+                        ..EnumDescriptorProto::default()
+                    }],
+                    nested_type: vec![DescriptorProto {
+                        name: Some("ValuesEntry".to_string()),
+                        options: Some(prost_types::MessageOptions {
+                            map_entry: Some(true),
+                            ..prost_types::MessageOptions::default()
+                        }),
+                        ..DescriptorProto::default()
+                    }],
+                    ..DescriptorProto::default()
+                }],
+                service: vec![prost_types::ServiceDescriptorProto {
+                    name: Some("Greeter".to_string()),
+                    ..Default::default()
+                }],
+                source_code_info: Some(prost_types::SourceCodeInfo {
+                    location: vec![
+                        location(&[4, 0], 4),
+                        location(&[4, 0, 4, 0], 7),
+                        location(&[4, 0, 3, 0], 12),
+                        location(&[6, 0], 20),
+                    ],
+                }),
+                ..FileDescriptorProto::default()
+            }],
+        };
+
+        assert_eq!(
+            generate_source_map(&descriptor_set, false),
+            BTreeMap::from([
+                ("pkg::a::Outer".to_string(), "pkg/a.proto:5".to_string()),
+                (
+                    "pkg::a::outer::Kind".to_string(),
+                    "pkg/a.proto:8".to_string()
+                ),
+            ])
+        );
+
+        let source_map = generate_source_map(&descriptor_set, true);
+        assert_eq!(
+            source_map.get("pkg::a::greeter_server::Greeter"),
+            Some(&"pkg/a.proto:21".to_string())
+        );
+        assert_eq!(
+            source_map.get("pkg::a::greeter_client::GreeterClient"),
+            Some(&"pkg/a.proto:21".to_string())
+        );
+    }
+
+    #[test]
+    fn render_json_object_test() {
+        assert_eq!(render_json_object(&BTreeMap::new()), "{}\n");
+        assert_eq!(
+            render_json_object(&BTreeMap::from([
+                ("b".to_string(), "tab\there".to_string()),
+                ("a".to_string(), "say \"hi\"\\\u{1}".to_string()),
+            ])),
+            "{\n  \"a\": \"say \\\"hi\\\"\\\\\\u0001\",\n  \"b\": \"tab\\there\"\n}\n"
+        );
+    }
+}
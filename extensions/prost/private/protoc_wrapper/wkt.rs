@@ -0,0 +1,254 @@
+//! Mappings of well-known types to user provided Rust types and the wrappers generated for them.
+
+use super::args::ProstVersion;
+
+/// The name of the module containing the wrappers of mapped well-known types.
+pub const WKT_MODULE_NAME: &str = "prost_wkt";
+
+/// A native Rust representation of a well-known protobuf type made up of
+/// `seconds` and `nanos` fields.
+#[derive(Debug, PartialEq, Eq)]
+pub struct WktMapping {
+    /// The fully qualified proto name of the well-known type.
+    pub proto_name: &'static str,
+
+    /// The name of the representation, e.g. `chrono`.
+    flavor: &'static str,
+
+    /// The Rust type representing the well-known type.
+    rust_type: &'static str,
+
+    /// An expression converting `value: &rust_type` into `(seconds, nanos)`.
+    to_parts: &'static str,
+
+    /// An expression converting `seconds: i64` and `nanos: i32` into an
+    /// `Option<rust_type>`, returning `None` if the value is out of range.
+    from_parts: &'static str,
+}
+
+/// All supported mappings of well-known types to native Rust types.
+const WKT_MAPPINGS: [WktMapping; 6] = [
+    WktMapping {
+        proto_name: ".google.protobuf.Timestamp",
+        flavor: "chrono",
+        rust_type: "::chrono::DateTime<::chrono::Utc>",
+        to_parts: "(value.timestamp(), value.timestamp_subsec_nanos() as i32)",
+        from_parts: "::chrono::DateTime::from_timestamp(seconds, u32::try_from(nanos).ok()?)",
+    },
+    WktMapping {
+        proto_name: ".google.protobuf.Timestamp",
+        flavor: "time",
+        rust_type: "::time::OffsetDateTime",
+        to_parts: "(value.unix_timestamp(), value.nanosecond() as i32)",
+        from_parts: "::time::OffsetDateTime::from_unix_timestamp(seconds).ok()?.replace_nanosecond(u32::try_from(nanos).ok()?).ok()",
+    },
+    WktMapping {
+        proto_name: ".google.protobuf.Timestamp",
+        flavor: "std",
+        rust_type: "::std::time::SystemTime",
+        to_parts: "{ let proto = ::prost_types::Timestamp::from(*value); (proto.seconds, proto.nanos) }",
+        from_parts: "::std::time::SystemTime::try_from(::prost_types::Timestamp { seconds, nanos }).ok()",
+    },
+    WktMapping {
+        proto_name: ".google.protobuf.Duration",
+        flavor: "chrono",
+        rust_type: "::chrono::Duration",
+        to_parts: "(value.num_seconds(), value.subsec_nanos())",
+        from_parts: "::chrono::Duration::try_seconds(seconds)?.checked_add(&::chrono::Duration::nanoseconds(i64::from(nanos)))",
+    },
+    WktMapping {
+        proto_name: ".google.protobuf.Duration",
+        flavor: "time",
+        rust_type: "::time::Duration",
+        to_parts: "(value.whole_seconds(), value.subsec_nanoseconds())",
+        from_parts: "::time::Duration::seconds(seconds).checked_add(::time::Duration::nanoseconds(i64::from(nanos)))",
+    },
+    WktMapping {
+        proto_name: ".google.protobuf.Duration",
+        flavor: "std",
+        rust_type: "::std::time::Duration",
+        to_parts: "(i64::try_from(value.as_secs()).unwrap_or(i64::MAX), value.subsec_nanos() as i32)",
+        from_parts: "(seconds >= 0 && nanos >= 0).then(|| ::std::time::Duration::new(seconds as u64, nanos as u32))",
+    },
+];
+
+impl WktMapping {
+    /// Parse a `--wkt_mapping` value such as `google.protobuf.Timestamp=chrono`.
+    pub fn parse(value: &str) -> Result<&'static WktMapping, String> {
+        let (proto_name, flavor) = value.split_once('=').ok_or_else(|| {
+            format!(
+                "Invalid `--wkt_mapping` value `{}`. Expected `<type>=<flavor>`.",
+                value
+            )
+        })?;
+        let proto_name = format!(".{}", proto_name.trim_start_matches('.'));
+
+        WKT_MAPPINGS
+            .iter()
+            .find(|mapping| mapping.proto_name == proto_name && mapping.flavor == flavor)
+            .ok_or_else(|| {
+                let supported = WKT_MAPPINGS
+                    .iter()
+                    .map(|mapping| format!("{}={}", &mapping.proto_name[1..], mapping.flavor))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "Unsupported well-known type mapping `{}`. Supported mappings are: {}",
+                    value, supported
+                )
+            })
+    }
+
+    /// The name of the generated wrapper type.
+    fn wrapper_name(&self) -> &'static str {
+        self.proto_name.rsplit('.').next().unwrap()
+    }
+
+    /// The Rust path of the generated wrapper type.
+    pub fn rust_path(&self) -> String {
+        format!("crate::{}::{}", WKT_MODULE_NAME, self.wrapper_name())
+    }
+}
+
+/// Generate wrapper types for mapped well-known types.
+///
+/// Each wrapper is a newtype around the native Rust type which implements
+/// `prost::Message` by converting to and from the `prost_types` representation,
+/// along with conversions and `Deref` to the native type.
+pub fn generate_wkt_wrappers(mappings: &[&WktMapping], prost_version: ProstVersion) -> String {
+    let (encode_raw, merge_field) = if prost_version.uses_impl_trait_buffers() {
+        (
+            "fn encode_raw(&self, buf: &mut impl ::prost::bytes::BufMut)",
+            "fn merge_field(&mut self, tag: u32, wire_type: ::prost::encoding::WireType, buf: &mut impl ::prost::bytes::Buf, ctx: ::prost::encoding::DecodeContext)",
+        )
+    } else {
+        (
+            "fn encode_raw<B: ::prost::bytes::BufMut>(&self, buf: &mut B)",
+            "fn merge_field<B: ::prost::bytes::Buf>(&mut self, tag: u32, wire_type: ::prost::encoding::WireType, buf: &mut B, ctx: ::prost::encoding::DecodeContext)",
+        )
+    };
+
+    let mut content = String::new();
+    for mapping in mappings {
+        let WktMapping {
+            proto_name,
+            rust_type,
+            to_parts,
+            from_parts,
+            ..
+        } = mapping;
+        let proto_name = &proto_name[1..];
+        let name = mapping.wrapper_name();
+        let proto_type = format!("::prost_types::{name}");
+
+        content.push_str(&format!(
+            "/// `{proto_name}` represented as a `{rust_type}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct {name}(pub {rust_type});
+
+impl {name} {{
+    fn to_parts(value: &{rust_type}) -> (i64, i32) {{
+        {to_parts}
+    }}
+
+    fn from_parts(seconds: i64, nanos: i32) -> ::core::option::Option<{rust_type}> {{
+        {from_parts}
+    }}
+
+    fn to_proto(&self) -> {proto_type} {{
+        let (seconds, nanos) = Self::to_parts(&self.0);
+        {proto_type} {{ seconds, nanos }}
+    }}
+
+    fn from_proto(proto: {proto_type}) -> ::core::option::Option<Self> {{
+        Self::from_parts(proto.seconds, proto.nanos).map(Self)
+    }}
+}}
+
+impl ::core::default::Default for {name} {{
+    fn default() -> Self {{
+        Self::from_proto(::core::default::Default::default()).expect(\"The default value is representable\")
+    }}
+}}
+
+impl ::core::convert::From<{rust_type}> for {name} {{
+    fn from(value: {rust_type}) -> Self {{
+        Self(value)
+    }}
+}}
+
+impl ::core::convert::From<{name}> for {rust_type} {{
+    fn from(value: {name}) -> Self {{
+        value.0
+    }}
+}}
+
+impl ::core::ops::Deref for {name} {{
+    type Target = {rust_type};
+
+    fn deref(&self) -> &Self::Target {{
+        &self.0
+    }}
+}}
+
+impl ::core::ops::DerefMut for {name} {{
+    fn deref_mut(&mut self) -> &mut Self::Target {{
+        &mut self.0
+    }}
+}}
+
+impl ::prost::Message for {name} {{
+    {encode_raw} {{
+        ::prost::Message::encode_raw(&self.to_proto(), buf)
+    }}
+
+    {merge_field} -> ::core::result::Result<(), ::prost::DecodeError> {{
+        let mut proto = self.to_proto();
+        ::prost::Message::merge_field(&mut proto, tag, wire_type, buf, ctx)?;
+        *self = Self::from_proto(proto).ok_or_else(|| {{
+            ::prost::DecodeError::new(\"{proto_name} is out of range for {rust_type}\")
+        }})?;
+        ::core::result::Result::Ok(())
+    }}
+
+    fn encoded_len(&self) -> usize {{
+        ::prost::Message::encoded_len(&self.to_proto())
+    }}
+
+    fn clear(&mut self) {{
+        *self = ::core::default::Default::default();
+    }}
+}}
+"
+        ));
+    }
+
+    content
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wkt_mapping_test() {
+        let mapping = WktMapping::parse("google.protobuf.Timestamp=chrono").unwrap();
+        assert_eq!(mapping.rust_type, "::chrono::DateTime<::chrono::Utc>");
+        assert_eq!(mapping.wrapper_name(), "Timestamp");
+        assert_eq!(mapping.rust_path(), "crate::prost_wkt::Timestamp");
+        assert_eq!(
+            WktMapping::parse(".google.protobuf.Duration=std").unwrap(),
+            &WKT_MAPPINGS[5]
+        );
+
+        let err = WktMapping::parse("google.protobuf.Duration=jiff").unwrap_err();
+        assert!(err.contains("google.protobuf.Duration=std"), "{}", err);
+        assert!(WktMapping::parse("google.protobuf.Duration").is_err());
+
+        let wrappers = generate_wkt_wrappers(&[mapping], ProstVersion::new(0, 13));
+        assert!(wrappers.contains("pub struct Timestamp(pub ::chrono::DateTime<::chrono::Utc>);"));
+        assert!(wrappers.contains("buf: &mut impl ::prost::bytes::BufMut"));
+        let wrappers = generate_wkt_wrappers(&[mapping], ProstVersion::new(0, 12));
+        assert!(wrappers.contains("fn encode_raw<B: ::prost::bytes::BufMut>"));
+    }
+}
@@ -0,0 +1,61 @@
+"""Macros for smoke testing the gRPC server reflection of tonic servers."""
+
+load("@rules_rust//rust:defs.bzl", "rust_test")
+
+def rust_prost_reflection_test(name, protos, deps = [], **kwargs):
+    """A test checking that a tonic server registers every service of some protos with its reflection service.
+
+    The test sources create the server with a factory passed to `reflection_smoke_test::run`, which
+    serves it on a local port, queries its reflection service and fails if a service or method
+    defined in `protos` is not registered. E.g.
+    ```python
+    load("@rules_rust_prost//:defs.bzl", "rust_prost_reflection_test")
+
+    rust_prost_reflection_test(
+        name = "greeter_reflection_test",
+        srcs = ["greeter_reflection_test.rs"],
+        protos = [":greeter_proto"],
+        deps = [
+            ":greeter_server",
+            "@crates//:tonic",
+            "@crates//:tonic-reflection",
+        ],
+    )
+    ```
+
+    With `greeter_reflection_test.rs`:
+    ```rust
+    #[test]
+    fn reflection_test() {
+        reflection_smoke_test::run(|| {
+            let reflection = tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(greeter_server::FILE_DESCRIPTOR_SET)
+                .build_v1()
+                .unwrap();
+            tonic::transport::Server::builder()
+                .add_service(reflection)
+                .add_service(GreeterServer::new(MyGreeter::default()))
+        });
+    }
+    ```
+
+    Args:
+        name (str): The name of the `rust_test`.
+        protos (list): The `proto_library` targets defining the expected services.
+        deps (list, optional): Additional dependencies of the test.
+        **kwargs: Additional keyword arguments for the `rust_test`.
+    """
+    env = dict(kwargs.pop("env", {}))
+    env["REFLECTION_SMOKE_TEST_DESCRIPTOR_SETS"] = " ".join([
+        "$(rootpaths {})".format(proto)
+        for proto in protos
+    ])
+
+    rust_test(
+        name = name,
+        data = kwargs.pop("data", []) + protos,
+        edition = kwargs.pop("edition", "2021"),
+        env = env,
+        deps = [Label("//private:reflection_smoke_test")] + deps,
+        **kwargs
+    )
@@ -0,0 +1,449 @@
+//! A smoke test of tonic servers checking that every service and method defined
+//! by their protos is registered with the gRPC server reflection service.
+//!
+//! The server is created by a factory, served on a local port and queried with
+//! both the `v1` and `v1alpha` versions of the reflection protocol. The expected
+//! services are read from the descriptor sets listed in the environment variable
+//! `REFLECTION_SMOKE_TEST_DESCRIPTOR_SETS`, which `rust_prost_reflection_test`
+//! sets. E.g.
+//!
+//! ```ignore
+//! #[test]
+//! fn reflection_test() {
+//!     reflection_smoke_test::run(|| {
+//!         let reflection = tonic_reflection::server::Builder::configure()
+//!             .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+//!             .build_v1()
+//!             .unwrap();
+//!         tonic::transport::Server::builder()
+//!             .add_service(reflection)
+//!             .add_service(GreeterServer::new(MyGreeter::default()))
+//!     });
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+
+use prost::Message;
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::server::Router;
+use tonic::transport::{Channel, Endpoint};
+
+/// The environment variable listing the paths of the descriptor sets defining
+/// the expected services, separated by whitespace.
+pub const DESCRIPTOR_SETS_ENV: &str = "REFLECTION_SMOKE_TEST_DESCRIPTOR_SETS";
+
+/// The versions of the reflection service which are queried, in order.
+const REFLECTION_VERSIONS: [&str; 2] = ["v1", "v1alpha"];
+
+/// The messages of the reflection protocol used by the smoke test. Both versions
+/// of the protocol share the same messages.
+mod proto {
+    /// A request to the reflection service.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(crate) struct ServerReflectionRequest {
+        /// The host the request is made for.
+        #[prost(string, tag = "1")]
+        pub(crate) host: String,
+
+        /// The query.
+        #[prost(oneof = "MessageRequest", tags = "4, 7")]
+        pub(crate) message_request: Option<MessageRequest>,
+    }
+
+    /// The queries supported by the smoke test.
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub(crate) enum MessageRequest {
+        /// Find the file defining a fully qualified symbol.
+        #[prost(string, tag = "4")]
+        FileContainingSymbol(String),
+
+        /// List the registered services. The content is not checked.
+        #[prost(string, tag = "7")]
+        ListServices(String),
+    }
+
+    /// A response of the reflection service.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(crate) struct ServerReflectionResponse {
+        /// The answer to the query.
+        #[prost(oneof = "MessageResponse", tags = "4, 6, 7")]
+        pub(crate) message_response: Option<MessageResponse>,
+    }
+
+    /// The answers to the queries supported by the smoke test.
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub(crate) enum MessageResponse {
+        /// The answer to `FileContainingSymbol`.
+        #[prost(message, tag = "4")]
+        FileDescriptorResponse(FileDescriptorResponse),
+
+        /// The answer to `ListServices`.
+        #[prost(message, tag = "6")]
+        ListServicesResponse(ListServiceResponse),
+
+        /// The error of any query.
+        #[prost(message, tag = "7")]
+        ErrorResponse(ErrorResponse),
+    }
+
+    /// Serialized `FileDescriptorProto`s.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(crate) struct FileDescriptorResponse {
+        /// The file defining the symbol and possibly its dependencies.
+        #[prost(bytes = "vec", repeated, tag = "1")]
+        pub(crate) file_descriptor_proto: Vec<Vec<u8>>,
+    }
+
+    /// The registered services.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(crate) struct ListServiceResponse {
+        /// The services.
+        #[prost(message, repeated, tag = "1")]
+        pub(crate) service: Vec<ServiceResponse>,
+    }
+
+    /// A registered service.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(crate) struct ServiceResponse {
+        /// The fully qualified name of the service.
+        #[prost(string, tag = "1")]
+        pub(crate) name: String,
+    }
+
+    /// The error of a query.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(crate) struct ErrorResponse {
+        /// The gRPC status code of the error.
+        #[prost(int32, tag = "1")]
+        pub(crate) error_code: i32,
+
+        /// The description of the error.
+        #[prost(string, tag = "2")]
+        pub(crate) error_message: String,
+    }
+}
+
+/// Fully qualified service names mapped to the names of their methods.
+pub type Services = BTreeMap<String, BTreeSet<String>>;
+
+/// Collect the services defined in the given files.
+pub fn file_services<'a>(files: impl IntoIterator<Item = &'a FileDescriptorProto>) -> Services {
+    let mut services = Services::new();
+    for file in files {
+        for service in file.service.iter() {
+            let name = if file.package().is_empty() {
+                service.name().to_string()
+            } else {
+                format!("{}.{}", file.package(), service.name())
+            };
+            services.entry(name).or_default().extend(
+                service
+                    .method
+                    .iter()
+                    .map(|method| method.name().to_string()),
+            );
+        }
+    }
+
+    services
+}
+
+/// Read the services defined in the descriptor sets listed in
+/// `REFLECTION_SMOKE_TEST_DESCRIPTOR_SETS`.
+pub fn expected_services_from_env() -> Result<Services, String> {
+    let paths = env::var(DESCRIPTOR_SETS_ENV)
+        .map_err(|_| format!("`{}` is not set.", DESCRIPTOR_SETS_ENV))?;
+
+    let mut files = Vec::new();
+    for path in paths.split_whitespace() {
+        let bytes = fs::read(path)
+            .map_err(|e| format!("Failed to read descriptor set `{}`: {:?}", path, e))?;
+        let descriptor_set = FileDescriptorSet::decode(bytes.as_slice())
+            .map_err(|e| format!("Failed to decode descriptor set `{}`: {:?}", path, e))?;
+        files.extend(descriptor_set.file);
+    }
+
+    Ok(file_services(files.iter()))
+}
+
+/// Send a single query to the reflection service at the given path.
+async fn query(
+    channel: Channel,
+    path: &str,
+    request: proto::MessageRequest,
+) -> Result<proto::MessageResponse, tonic::Status> {
+    let mut grpc = tonic::client::Grpc::new(channel);
+    grpc.ready()
+        .await
+        .map_err(|e| tonic::Status::unavailable(format!("The server is not ready: {}", e)))?;
+
+    let request = proto::ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(request),
+    };
+    let path = PathAndQuery::try_from(path.to_string())
+        .map_err(|e| tonic::Status::internal(format!("Invalid path `{}`: {}", path, e)))?;
+    let mut responses = grpc
+        .streaming(
+            tonic::Request::new(tokio_stream::once(request)),
+            path,
+            tonic::codec::ProstCodec::<
+                proto::ServerReflectionRequest,
+                proto::ServerReflectionResponse,
+            >::default(),
+        )
+        .await?
+        .into_inner();
+
+    match responses
+        .message()
+        .await?
+        .and_then(|response| response.message_response)
+    {
+        Some(proto::MessageResponse::ErrorResponse(error)) => Err(tonic::Status::new(
+            tonic::Code::from_i32(error.error_code),
+            error.error_message,
+        )),
+        Some(response) => Ok(response),
+        None => Err(tonic::Status::internal(
+            "The reflection service returned no response.",
+        )),
+    }
+}
+
+/// Query which of the expected services are registered with the reflection service
+/// of a server, using the first version of the reflection protocol the server
+/// implements. Other services are ignored, as their files may not be available.
+pub async fn registered_services(
+    channel: Channel,
+    expected: &Services,
+) -> Result<Services, String> {
+    for version in REFLECTION_VERSIONS {
+        let path = format!(
+            "/grpc.reflection.{}.ServerReflection/ServerReflectionInfo",
+            version
+        );
+        let names = match query(
+            channel.clone(),
+            &path,
+            proto::MessageRequest::ListServices(String::new()),
+        )
+        .await
+        {
+            Ok(proto::MessageResponse::ListServicesResponse(response)) => response
+                .service
+                .into_iter()
+                .map(|service| service.name)
+                .collect::<BTreeSet<_>>(),
+            Ok(_) => {
+                return Err(format!(
+                    "Unexpected response to `ListServices` from `{}`.",
+                    path
+                ))
+            }
+            Err(status) if status.code() == tonic::Code::Unimplemented => continue,
+            Err(status) => return Err(format!("`ListServices` failed: {}", status)),
+        };
+
+        // The methods of each service are read from the file defining it.
+        let mut services = Services::new();
+        for name in names.into_iter().filter(|name| expected.contains_key(name)) {
+            let response = query(
+                channel.clone(),
+                &path,
+                proto::MessageRequest::FileContainingSymbol(name.clone()),
+            )
+            .await
+            .map_err(|status| {
+                format!("`FileContainingSymbol` failed for `{}`: {}", name, status)
+            })?;
+            let proto::MessageResponse::FileDescriptorResponse(response) = response else {
+                return Err(format!(
+                    "Unexpected response to `FileContainingSymbol` for `{}`.",
+                    name
+                ));
+            };
+            let files = response
+                .file_descriptor_proto
+                .iter()
+                .map(|bytes| FileDescriptorProto::decode(bytes.as_slice()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Failed to decode the file defining `{}`: {:?}", name, e))?;
+            let methods = file_services(files.iter())
+                .remove(&name)
+                .unwrap_or_default();
+            services.insert(name, methods);
+        }
+
+        return Ok(services);
+    }
+
+    Err("The server does not implement the gRPC server reflection service.".to_string())
+}
+
+/// Describe the expected services and methods which are not registered.
+pub fn missing_services(expected: &Services, registered: &Services) -> Vec<String> {
+    let mut missing = Vec::new();
+    for (service, methods) in expected {
+        let Some(registered_methods) = registered.get(service) else {
+            missing.push(format!("The service `{}` is not registered.", service));
+            continue;
+        };
+        for method in methods.difference(registered_methods) {
+            missing.push(format!(
+                "The method `{}.{}` is not registered.",
+                service, method
+            ));
+        }
+    }
+
+    missing
+}
+
+/// Serve a server on a local port and check that the expected services are
+/// registered with its reflection service.
+pub async fn check(router: Router, expected: &Services) -> Result<(), String> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| format!("Failed to bind a local port: {:?}", e))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to get the local address: {:?}", e))?;
+    let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(router.serve_with_incoming_shutdown(
+        TcpListenerStream::new(listener),
+        async {
+            shutdown_signal.await.ok();
+        },
+    ));
+
+    let registered = async {
+        let channel = Endpoint::from_shared(format!("http://{}", addr))
+            .map_err(|e| format!("Invalid endpoint: {}", e))?
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to the server: {}", e))?;
+        registered_services(channel, expected).await
+    }
+    .await;
+
+    shutdown.send(()).ok();
+    server
+        .await
+        .map_err(|e| format!("The server panicked: {:?}", e))?
+        .map_err(|e| format!("The server failed: {}", e))?;
+
+    let missing = missing_services(expected, &registered?);
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let mut report = String::new();
+    for line in missing {
+        writeln!(report, "{}", line).unwrap();
+    }
+    Err(report)
+}
+
+/// Check the server created by `factory` against the services defined in the
+/// descriptor sets listed in `REFLECTION_SMOKE_TEST_DESCRIPTOR_SETS`, panicking if
+/// any of them is not registered.
+pub fn run(factory: impl FnOnce() -> Router) {
+    let expected = expected_services_from_env().unwrap_or_else(|e| panic!("{}", e));
+    run_with(factory, &expected);
+}
+
+/// Check the server created by `factory` against the given services, panicking if
+/// any of them is not registered.
+pub fn run_with(factory: impl FnOnce() -> Router, expected: &Services) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create a tokio runtime.");
+    if let Err(report) = runtime.block_on(check(factory(), expected)) {
+        panic!("The reflection smoke test failed:\n{}", report);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use prost_types::{MethodDescriptorProto, ServiceDescriptorProto};
+
+    fn service(name: &str, methods: &[&str]) -> ServiceDescriptorProto {
+        ServiceDescriptorProto {
+            name: Some(name.to_string()),
+            method: methods
+                .iter()
+                .map(|name| MethodDescriptorProto {
+                    name: Some(name.to_string()),
+                    ..MethodDescriptorProto::default()
+                })
+                .collect(),
+            ..ServiceDescriptorProto::default()
+        }
+    }
+
+    fn services(entries: &[(&str, &[&str])]) -> Services {
+        entries
+            .iter()
+            .map(|(name, methods)| {
+                (
+                    name.to_string(),
+                    methods.iter().map(|method| method.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn file_services_test() {
+        let files = [
+            FileDescriptorProto {
+                package: Some("pkg".to_string()),
+                service: vec![service("Greeter", &["SayHello", "SayHellos"])],
+                ..FileDescriptorProto::default()
+            },
+            FileDescriptorProto {
+                service: vec![service("Root", &[])],
+                ..FileDescriptorProto::default()
+            },
+        ];
+
+        assert_eq!(
+            file_services(files.iter()),
+            services(&[("Root", &[]), ("pkg.Greeter", &["SayHello", "SayHellos"])])
+        );
+    }
+
+    #[test]
+    fn missing_services_test() {
+        let expected = services(&[
+            ("pkg.Echo", &["Echo"]),
+            ("pkg.Greeter", &["SayHello", "SayHellos"]),
+        ]);
+        let registered = services(&[
+            (
+                "grpc.reflection.v1.ServerReflection",
+                &["ServerReflectionInfo"],
+            ),
+            ("pkg.Greeter", &["SayHello"]),
+        ]);
+
+        assert_eq!(
+            missing_services(&expected, &registered),
+            vec![
+                "The service `pkg.Echo` is not registered.".to_string(),
+                "The method `pkg.Greeter.SayHellos` is not registered.".to_string(),
+            ]
+        );
+        assert!(missing_services(&registered, &registered).is_empty());
+    }
+}
@@ -0,0 +1,55 @@
+"""Macros for generating runnable skeletons of tonic servers."""
+
+load("@rules_rust//rust:defs.bzl", "rust_binary")
+
+def rust_prost_tonic_scaffold(name, lib, deps = [], **kwargs):
+    """A tonic server implementing every service of a `rust_prost_library` with `unimplemented!()` bodies.
+
+    The `main.rs` of the server is generated from the descriptors of the library, by an action which
+    only runs when the scaffold is built, and serves all services on the address given as its first
+    argument, `[::1]:50051` by default. It is meant as a starting point
+    for new servers: copy it from the `rust_generated_tonic_scaffold` output group of the library
+    into your sources and fill in the methods. E.g.
+    ```python
+    load("@rules_rust_prost//:defs.bzl", "rust_prost_library", "rust_prost_tonic_scaffold")
+
+    rust_prost_library(
+        name = "greeter_rs_proto",
+        proto = ":greeter_proto",
+    )
+
+    rust_prost_tonic_scaffold(
+        name = "greeter_scaffold",
+        lib = ":greeter_rs_proto",
+        deps = [
+            "@crates//:tokio",
+            "@crates//:tonic",
+        ],
+    )
+    ```
+
+    `bazel run //:greeter_scaffold` then starts the skeleton server. The scaffold fails to compile
+    with an explanation if the library defines no services or does not generate tonic servers.
+
+    Args:
+        name (str): The name of the `rust_binary` compiling the scaffold.
+        lib (Label): The `rust_prost_library` whose services are implemented.
+        deps (list, optional): Additional dependencies of the scaffold, which must provide the
+            `tokio` crate with the `macros` and `rt-multi-thread` features and the `tonic` crate.
+        **kwargs: Additional keyword arguments for the `rust_binary`.
+    """
+    native.filegroup(
+        name = name + "_main",
+        srcs = [lib],
+        output_group = "rust_generated_tonic_scaffold",
+        tags = kwargs.get("tags"),
+        visibility = ["//visibility:private"],
+    )
+
+    rust_binary(
+        name = name,
+        srcs = [name + "_main"],
+        edition = kwargs.pop("edition", "2021"),
+        deps = [lib] + deps,
+        **kwargs
+    )
@@ -0,0 +1,41 @@
+//! Tests the `google.protobuf.Any` helpers generated for messages.
+
+use any_helpers_proto::any_helpers::greeting::Signature;
+use any_helpers_proto::any_helpers::{Farewell, Greeting};
+use any_helpers_proto::ANY_TYPE_URLS;
+
+#[test]
+fn test_type_urls() {
+    assert_eq!(Greeting::TYPE_URL, "type.googleapis.com/any_helpers.Greeting");
+    assert_eq!(
+        Signature::TYPE_URL,
+        "type.googleapis.com/any_helpers.Greeting.Signature"
+    );
+    assert_eq!(
+        ANY_TYPE_URLS,
+        &[
+            "type.googleapis.com/any_helpers.Farewell",
+            "type.googleapis.com/any_helpers.Greeting",
+            "type.googleapis.com/any_helpers.Greeting.Signature",
+        ]
+    );
+}
+
+#[test]
+fn test_pack_unpack() {
+    let greeting = Greeting {
+        text: "hello".to_string(),
+    };
+
+    let any = greeting.pack_into_any();
+    assert_eq!(any.type_url, Greeting::TYPE_URL);
+    assert_eq!(Greeting::unpack_from_any(&any).unwrap(), greeting);
+    assert!(Farewell::unpack_from_any(&any).is_err());
+
+    // Type URLs from other domains are accepted.
+    let any = prost_types::Any {
+        type_url: "example.com/any_helpers.Greeting".to_string(),
+        ..any
+    };
+    assert_eq!(Greeting::unpack_from_any(&any).unwrap(), greeting);
+}
@@ -1,8 +1,20 @@
 //! Tests that strange casings of message names are handled correctly.
 
 use another_proto::another::Another;
+use another_proto::camel_case_proto::camel_case::name_with_caps::{InnerState, NestedValue};
 
 #[test]
 fn test_nested_messages() {
     let _a = Another::default();
 }
+
+#[test]
+fn test_nested_types_from_dependency() {
+    let a = Another {
+        state: InnerState::Ready as i32,
+        nested: Some(NestedValue { value: 1 }),
+        ..Another::default()
+    };
+
+    assert_eq!(a.state(), InnerState::Ready);
+}
@@ -0,0 +1,15 @@
+//! Tests depending on a proto crate under a different name.
+
+use aliasing_proto::crate_aliases::aliasing::Outer;
+use aliasing_proto::renamed_proto::crate_aliases::aliased::Inner;
+
+#[test]
+fn test_crate_aliases() {
+    let outer = Outer {
+        inner: Some(Inner {
+            name: "inner".to_string(),
+        }),
+    };
+
+    assert_eq!(outer.inner.as_ref().unwrap().name, "inner");
+}
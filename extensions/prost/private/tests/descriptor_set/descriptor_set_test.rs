@@ -0,0 +1,38 @@
+//! Tests the descriptor sets embedded by `rust_prost_descriptor_set`.
+
+mod compressed_descriptors;
+mod descriptors;
+
+use compressed_descriptors::COMPRESSED_FILE_DESCRIPTOR_SET;
+use descriptors::FILE_DESCRIPTOR_SET;
+use prost::Message;
+use prost_types::FileDescriptorSet;
+
+#[test]
+fn test_descriptor_set() {
+    let descriptor_set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET).unwrap();
+
+    let mut names = descriptor_set
+        .file
+        .iter()
+        .map(|file| file.name())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        ["descriptor_set.proto", "google/protobuf/timestamp.proto"]
+    );
+}
+
+#[test]
+fn test_compressed_descriptor_set() {
+    // The gzip magic number.
+    assert_eq!(&COMPRESSED_FILE_DESCRIPTOR_SET[..2], &[0x1f, 0x8b]);
+
+    // The trailer ends with the size of the uncompressed descriptor set.
+    let size = &COMPRESSED_FILE_DESCRIPTOR_SET[COMPRESSED_FILE_DESCRIPTOR_SET.len() - 4..];
+    assert_eq!(
+        u32::from_le_bytes(size.try_into().unwrap()) as usize,
+        FILE_DESCRIPTOR_SET.len()
+    );
+}
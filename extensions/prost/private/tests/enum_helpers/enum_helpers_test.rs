@@ -0,0 +1,39 @@
+//! Tests the conversion helpers generated for enums.
+
+use std::str::FromStr;
+
+use enum_helpers_proto::enum_helpers::palette::Finish;
+use enum_helpers_proto::enum_helpers::{Color, Palette, PaletteEnumFields};
+
+#[test]
+fn test_from_str() {
+    assert_eq!(Color::from_str("COLOR_RED"), Ok(Color::Red));
+    assert_eq!(Color::from_str(Color::Blue.as_str_name()), Ok(Color::Blue));
+    assert_eq!(
+        Color::from_str("COLOR_GREEN"),
+        Err("COLOR_GREEN".to_string())
+    );
+}
+
+#[test]
+fn test_typed_accessors() {
+    let palette = Palette {
+        primary: Color::Red as i32,
+        colors: vec![Color::Red as i32, Color::Blue as i32],
+        finish: Some(Finish::Matte as i32),
+    };
+
+    assert_eq!(palette.try_primary(), Ok(Color::Red));
+    assert_eq!(palette.try_colors(), Ok(vec![Color::Red, Color::Blue]));
+    assert_eq!(palette.try_finish(), Ok(Some(Finish::Matte)));
+
+    let unknown = Palette {
+        primary: 42,
+        colors: vec![Color::Red as i32, 7],
+        finish: None,
+    };
+
+    assert_eq!(unknown.try_primary(), Err(42));
+    assert_eq!(unknown.try_colors(), Err(7));
+    assert_eq!(unknown.try_finish(), Ok(None));
+}
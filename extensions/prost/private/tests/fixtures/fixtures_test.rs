@@ -0,0 +1,37 @@
+//! Tests decoding fixtures compiled by `rust_prost_fixture`.
+
+use std::collections::HashMap;
+
+use fixtures_proto::fixtures::{Mood, Person};
+use prost::Message;
+
+fn expected() -> Person {
+    Person {
+        name: "Ferris".to_string(),
+        age: 9,
+        emails: vec![
+            "ferris@example.com".to_string(),
+            "crab@example.com".to_string(),
+        ],
+        mood: Mood::Happy.into(),
+        scores: HashMap::from([("bazel".to_string(), 100)]),
+        best_friend: Some(Box::new(Person {
+            name: "Corro".to_string(),
+            ..Person::default()
+        })),
+    }
+}
+
+#[test]
+fn test_textproto_fixture() {
+    let bytes = std::fs::read(env!("TEXTPROTO_FIXTURE")).unwrap();
+
+    assert_eq!(Person::decode(bytes.as_slice()).unwrap(), expected());
+}
+
+#[test]
+fn test_json_fixture() {
+    let bytes = std::fs::read(env!("JSON_FIXTURE")).unwrap();
+
+    assert_eq!(Person::decode(bytes.as_slice()).unwrap(), expected());
+}
@@ -0,0 +1,30 @@
+//! Tests the builders generated for messages.
+
+use message_builders_proto::message_builders::{node, Node};
+
+#[test]
+fn test_builder() {
+    let node = Node::builder()
+        .name("root")
+        .payload(vec![1, 2, 3])
+        .parent(Node::builder().name("parent").build())
+        .children([Node::default(), Node::default()])
+        .counters([("visits", 3)])
+        .weight(7u32)
+        .number(42)
+        .build();
+
+    assert_eq!(node.name, "root");
+    assert_eq!(node.payload, vec![1, 2, 3]);
+    assert_eq!(node.parent.unwrap().name, "parent");
+    assert_eq!(node.children.len(), 2);
+    assert_eq!(node.counters.get("visits"), Some(&3));
+    assert_eq!(node.created, None);
+    assert_eq!(node.weight, Some(7));
+    assert_eq!(node.label, Some(node::Label::Number(42)));
+}
+
+#[test]
+fn test_builder_default() {
+    assert_eq!(Node::builder().build(), Node::default());
+}
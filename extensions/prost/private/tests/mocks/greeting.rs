@@ -0,0 +1,10 @@
+//! A library built on the generated crate without mocks.
+
+use mocks_proto::greeter::HelloRequest;
+
+/// Create the request greeting `name`.
+pub fn hello_request(name: &str) -> tonic::Request<HelloRequest> {
+    tonic::Request::new(HelloRequest {
+        name: name.to_owned(),
+    })
+}
@@ -0,0 +1,27 @@
+//! Tests the service mocks, which share the generated crate with its other dependents.
+
+use greeting::hello_request;
+use mocks_proto::greeter::HelloReply;
+use mocks_proto_mocks::greeter::MockGreeterServer;
+
+#[tokio::test]
+async fn test_mocked_method() {
+    let mut client = MockGreeterServer::new()
+        .on_say_hello(|request| async move {
+            Ok(tonic::Response::new(HelloReply {
+                message: format!("Hello {}!", request.into_inner().name),
+            }))
+        })
+        .into_client();
+
+    let reply = client.say_hello(hello_request("mocks")).await.unwrap();
+    assert_eq!(reply.into_inner().message, "Hello mocks!");
+}
+
+#[tokio::test]
+async fn test_unmocked_method() {
+    let mut client = MockGreeterServer::new().into_client();
+
+    let status = client.say_goodbye(hello_request("mocks")).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unimplemented);
+}
@@ -0,0 +1,27 @@
+//! Tests the proto name constants generated for messages and enums.
+
+use name_constants_proto::name_constants::v1::account::{Owner, Tier};
+use name_constants_proto::name_constants::v1::{Account, Status};
+
+#[test]
+fn test_message_full_names() {
+    assert_eq!(Account::FULL_NAME, "name_constants.v1.Account");
+    assert_eq!(Owner::FULL_NAME, "name_constants.v1.Account.Owner");
+}
+
+#[test]
+fn test_enum_names() {
+    assert_eq!(Status::FULL_NAME, "name_constants.v1.Status");
+    assert_eq!(Tier::FULL_NAME, "name_constants.v1.Account.Tier");
+    assert_eq!(
+        Status::VALUE_NAMES,
+        &[
+            ("STATUS_UNSPECIFIED", 0),
+            ("STATUS_ACTIVE", 1),
+            ("STATUS_DISABLED", 5),
+        ]
+    );
+    assert_eq!(Status::value_name(5), Some("STATUS_DISABLED"));
+    assert_eq!(Status::value_name(Status::Active as i32), Some("STATUS_ACTIVE"));
+    assert_eq!(Status::value_name(2), None);
+}
@@ -0,0 +1,156 @@
+use std::env;
+use std::fs;
+use std::pin::Pin;
+
+use prost::Message;
+use prost_types::FileDescriptorSet;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use greeter_proto::reflection::greeter::greeter_server::{Greeter, GreeterServer};
+use greeter_proto::reflection::greeter::{HelloReply, HelloRequest};
+use reflection_proto::grpc::reflection::v1::server_reflection_request::MessageRequest;
+use reflection_proto::grpc::reflection::v1::server_reflection_response::MessageResponse;
+use reflection_proto::grpc::reflection::v1::server_reflection_server::{
+    ServerReflection, ServerReflectionServer,
+};
+use reflection_proto::grpc::reflection::v1::{
+    ErrorResponse, FileDescriptorResponse, ListServiceResponse, ServerReflectionRequest,
+    ServerReflectionResponse, ServiceResponse,
+};
+
+const REFLECTION_SERVICE: &str = "grpc.reflection.v1.ServerReflection";
+
+#[derive(Default)]
+struct MyGreeter {}
+
+#[tonic::async_trait]
+impl Greeter for MyGreeter {
+    async fn say_hello(
+        &self,
+        request: Request<HelloRequest>,
+    ) -> Result<Response<HelloReply>, Status> {
+        Ok(Response::new(HelloReply {
+            message: format!("Hello {}!", request.into_inner().name),
+        }))
+    }
+
+    type SayHellosStream = Pin<Box<dyn Stream<Item = Result<HelloReply, Status>> + Send>>;
+
+    async fn say_hellos(
+        &self,
+        request: Request<HelloRequest>,
+    ) -> Result<Response<Self::SayHellosStream>, Status> {
+        let reply = HelloReply {
+            message: format!("Hello {}!", request.into_inner().name),
+        };
+        Ok(Response::new(Box::pin(tokio_stream::once(Ok(reply)))))
+    }
+}
+
+/// A minimal reflection service answering from the descriptor sets of the test.
+struct MyReflection {
+    descriptor_set: FileDescriptorSet,
+}
+
+impl MyReflection {
+    fn from_env() -> Self {
+        let mut descriptor_set = FileDescriptorSet::default();
+        let paths = env::var(reflection_smoke_test::DESCRIPTOR_SETS_ENV).unwrap();
+        for path in paths.split_whitespace() {
+            let bytes = fs::read(path).unwrap();
+            descriptor_set
+                .file
+                .extend(FileDescriptorSet::decode(bytes.as_slice()).unwrap().file);
+        }
+
+        Self { descriptor_set }
+    }
+
+    fn respond(&self, request: ServerReflectionRequest) -> ServerReflectionResponse {
+        let files = reflection_smoke_test::file_services(self.descriptor_set.file.iter());
+        let message_response = match request.message_request.clone() {
+            Some(MessageRequest::ListServices(_)) => {
+                MessageResponse::ListServicesResponse(ListServiceResponse {
+                    service: files
+                        .keys()
+                        .map(String::as_str)
+                        .chain([REFLECTION_SERVICE])
+                        .map(|name| ServiceResponse {
+                            name: name.to_string(),
+                        })
+                        .collect(),
+                })
+            }
+            Some(MessageRequest::FileContainingSymbol(symbol)) => {
+                match self.descriptor_set.file.iter().find(|file| {
+                    reflection_smoke_test::file_services([*file]).contains_key(&symbol)
+                }) {
+                    Some(file) => MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+                        file_descriptor_proto: vec![file.encode_to_vec()],
+                    }),
+                    None => MessageResponse::ErrorResponse(ErrorResponse {
+                        error_code: tonic::Code::NotFound as i32,
+                        error_message: format!("`{}` not found.", symbol),
+                    }),
+                }
+            }
+            _ => MessageResponse::ErrorResponse(ErrorResponse {
+                error_code: tonic::Code::Unimplemented as i32,
+                error_message: "Unsupported request.".to_string(),
+            }),
+        };
+
+        ServerReflectionResponse {
+            valid_host: request.host.clone(),
+            original_request: Some(request),
+            message_response: Some(message_response),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ServerReflection for MyReflection {
+    type ServerReflectionInfoStream = ReceiverStream<Result<ServerReflectionResponse, Status>>;
+
+    async fn server_reflection_info(
+        &self,
+        request: Request<Streaming<ServerReflectionRequest>>,
+    ) -> Result<Response<Self::ServerReflectionInfoStream>, Status> {
+        let mut requests = request.into_inner();
+        let mut responses = Vec::new();
+        while let Some(request) = requests.message().await? {
+            responses.push(Ok(self.respond(request)));
+        }
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(responses.len().max(1));
+        for response in responses {
+            sender.send(response).await.unwrap();
+        }
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+#[test]
+fn reflection_test() {
+    reflection_smoke_test::run(|| {
+        Server::builder()
+            .add_service(ServerReflectionServer::new(MyReflection::from_env()))
+            .add_service(GreeterServer::new(MyGreeter::default()))
+    });
+}
+
+#[tokio::test]
+async fn missing_service_test() {
+    let expected = reflection_smoke_test::expected_services_from_env().unwrap();
+    let router = Server::builder().add_service(ServerReflectionServer::new(MyReflection {
+        descriptor_set: FileDescriptorSet::default(),
+    }));
+
+    assert_eq!(
+        reflection_smoke_test::check(router, &expected).await,
+        Err("The service `reflection.greeter.Greeter` is not registered.\n".to_string())
+    );
+}
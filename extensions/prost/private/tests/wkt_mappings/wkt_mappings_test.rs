@@ -0,0 +1,36 @@
+//! Tests mapping well-known types to native Rust types.
+
+use std::time::{Duration, SystemTime};
+
+use prost::Message;
+use wkt_mappings_proto::prost_wkt;
+use wkt_mappings_proto::wkt_mappings::Event;
+
+#[test]
+fn test_native_types() {
+    let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let event = Event {
+        at: Some(at.into()),
+        took: Some(Duration::from_millis(1500).into()),
+        retries: vec![prost_wkt::Timestamp(SystemTime::UNIX_EPOCH)],
+    };
+
+    let at: SystemTime = event.at.unwrap().into();
+    assert_eq!(
+        at.duration_since(SystemTime::UNIX_EPOCH).unwrap(),
+        Duration::from_secs(1_700_000_000)
+    );
+    assert_eq!(event.took.unwrap().as_millis(), 1500);
+}
+
+#[test]
+fn test_round_trip() {
+    let event = Event {
+        at: Some(SystemTime::UNIX_EPOCH.into()),
+        took: Some(Duration::new(3, 5).into()),
+        retries: vec![(SystemTime::UNIX_EPOCH + Duration::from_nanos(7)).into()],
+    };
+
+    let decoded = Event::decode(event.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded, event);
+}
@@ -5,6 +5,8 @@ ProstProtoInfo = provider(
     fields = {
         "dep_variant_info": "DepVariantInfo: For the compiled Rust gencode (also covers its " +
                             "transitive dependencies)",
+        "mocks_dep_variant_info": "DepVariantInfo: For the compiled service mocks of " +
+                                  "`rust_prost_transform.mocks`, or None.",
         "package_info": "File: A newline delimited file of `--extern_path` values for protoc.",
         "transitive_dep_infos": "depset[DepVariantInfo]: Transitive dependencies of the compiled crate.",
     },
@@ -17,6 +17,7 @@ def rust_prost_dependencies(bzlmod = False):
 
     direct_deps = [
         struct(repo = "rrprd__heck", is_dev_dep = False),
+        struct(repo = "rrprd__miniz_oxide", is_dev_dep = False),
     ]
     if bzlmod:
         # Without bzlmod, this function is normally called by the
@@ -61,4 +62,15 @@ def rust_prost_dependencies(bzlmod = False):
         strip_prefix = "heck-0.5.0",
         build_file = Label("@rules_rust_prost//private/3rdparty/crates:BUILD.heck-0.5.0.bazel"),
     )
+
+    # Compresses descriptor sets embedded by `rust_prost_descriptor_set`.
+    maybe(
+        http_archive,
+        name = "rrprd__miniz_oxide",
+        integrity = "sha256-uKJA3bdP6vNKeaet1lp0HzFnhS+6AHBm3KwcpUjYnAg=",
+        type = "tar.gz",
+        urls = ["https://static.crates.io/crates/miniz_oxide/miniz_oxide-0.7.4.crate"],
+        strip_prefix = "miniz_oxide-0.7.4",
+        build_file = Label("@rules_rust_prost//private/3rdparty/crates:BUILD.miniz_oxide-0.7.4.bazel"),
+    )
     return direct_deps
@@ -662,6 +662,9 @@ _common_attrs = {
             expand locations should use cargo_build_script()'s build_script_env argument instead,
             as build scripts are run in a different environment - see cargo_build_script()'s
             documentation for more.
+
+            Values are subject to [workspace status](https://docs.bazel.build/versions/main/user-manual.html#workspace_status)
+            stamping should the `stamp` attribute be enabled, e.g. `NAME={STABLE_GIT_COMMIT}`.
         """),
     ),
     "rustc_env_files": attr.label_list(
@@ -691,6 +694,10 @@ _common_attrs = {
             source/output path variables like `$location`, `$execpath`, and
             `$rootpath`. This expansion is useful if you wish to pass a generated
             file of arguments to rustc: `@$(location //package:target)`.
+
+            Flags are subject to [workspace status](https://docs.bazel.build/versions/main/user-manual.html#workspace_status)
+            stamping should the `stamp` attribute be enabled, e.g.
+            `--cfg=commit="{STABLE_GIT_COMMIT}"`.
         """),
     ),
     # TODO(stardoc): How do we provide additional documentation to an inherited attribute?
@@ -798,6 +798,24 @@ def collect_inputs(
     compile_inputs = depset(build_env_files + lint_files, transitive = [build_script_compile_inputs, compile_inputs])
     return compile_inputs, out_dir, build_env_files, build_flags_files, linkstamp_outs, ambiguous_libs
 
+def _format_env_file(env):
+    """Formats environment variables for the `--env-file` flag of the process wrapper.
+
+    Args:
+        env (dict): The environment variables.
+
+    Returns:
+        str: The content of the file, with newlines and trailing backslashes of values escaped.
+    """
+    lines = []
+    for key, value in env.items():
+        segments = []
+        for segment in "{}={}".format(key, value).split("\n"):
+            stripped = segment.rstrip("\\")
+            segments.append(stripped + "\\" * (2 * (len(segment) - len(stripped))))
+        lines.append("\\\n".join(segments))
+    return "\n".join(lines) + "\n"
+
 def construct_arguments(
         *,
         ctx,
@@ -825,7 +843,8 @@ def construct_arguments(
         use_json_output = False,
         build_metadata = False,
         force_depend_on_objects = False,
-        skip_expanding_rustc_env = False):
+        skip_expanding_rustc_env = False,
+        worker_env_file = None):
     """Builds an Args object containing common rustc flags
 
     Args:
@@ -857,6 +876,8 @@ def construct_arguments(
         build_metadata (bool): Generate CLI arguments for building *only* .rmeta files. This requires use_json_output.
         force_depend_on_objects (bool): Force using `.rlib` object files instead of metadata (`.rmeta`) files even if they are available.
         skip_expanding_rustc_env (bool): Whether to skip expanding CrateInfo.rustc_env_attr
+        worker_env_file (File, optional): A file for the process wrapper to read the environment of rustc from. If set,
+            all arguments are passed in param files so the action can run in a persistent worker.
 
     Returns:
         tuple: A tuple of the following items
@@ -879,6 +900,14 @@ def construct_arguments(
     # Wrapper args first
     process_wrapper_flags = ctx.actions.args()
 
+    # Persistent workers require all arguments in param files. The environment specific to the crate
+    # is read from a file as workers are keyed by the environment of their actions. Build script
+    # environment files are read after it to take precedence.
+    if worker_env_file:
+        process_wrapper_flags.set_param_file_format("multiline")
+        process_wrapper_flags.use_param_file("@%s", use_always = True)
+        process_wrapper_flags.add("--env-file", worker_env_file)
+
     for build_env_file in build_env_files:
         process_wrapper_flags.add("--env-file", build_env_file)
 
@@ -898,6 +927,10 @@ def construct_arguments(
     # use `${pwd}` which resolves the `exec_root` at action execution time.
     process_wrapper_flags.add("--subst", "pwd=${pwd}")
 
+    # When Bazel cancels the action, rustc gets to exit along with the linker and the other
+    # processes it spawned instead of leaving them running.
+    process_wrapper_flags.add("--forward-signals", "true")
+
     # If stamping is enabled, enable the functionality in the process wrapper
     if stamp:
         process_wrapper_flags.add("--volatile-status-file", ctx.version_file)
@@ -913,13 +946,16 @@ def construct_arguments(
 
     # Arguments for launching rustc from the process wrapper
     rustc_path = ctx.actions.args()
+    if worker_env_file:
+        rustc_path.set_param_file_format("multiline")
+        rustc_path.use_param_file("@%s", use_always = True)
     rustc_path.add("--")
     rustc_path.add(tool_path)
 
     # Rustc arguments
     rustc_flags = ctx.actions.args()
     rustc_flags.set_param_file_format("multiline")
-    rustc_flags.use_param_file("@%s", use_always = False)
+    rustc_flags.use_param_file("@%s", use_always = bool(worker_env_file))
     rustc_flags.add(crate_info.root)
     rustc_flags.add(crate_info.name, format = "--crate-name=%s")
     rustc_flags.add(crate_info.type, format = "--crate-type=%s")
@@ -943,6 +979,9 @@ def construct_arguments(
             # If the os is not windows, we can get colorized output.
             json.append("diagnostic-rendered-ansi")
 
+        # The process wrapper removes the colors from the diagnostics it writes to files.
+        process_wrapper_flags.add("--color", "always" if "diagnostic-rendered-ansi" in json else "never")
+
         rustc_flags.add_joined(json, format_joined = "--json=%s", join_with = ",")
 
         error_format = "json"
@@ -951,9 +990,9 @@ def construct_arguments(
         # Configure process_wrapper to terminate rustc when metadata are emitted
         process_wrapper_flags.add("--rustc-quit-on-rmeta", "true")
         if crate_info.rustc_rmeta_output:
-            process_wrapper_flags.add("--output-file", crate_info.rustc_rmeta_output.path)
+            process_wrapper_flags.add("--rustc-json-output", crate_info.rustc_rmeta_output.path)
     elif crate_info.rustc_output:
-        process_wrapper_flags.add("--output-file", crate_info.rustc_output.path)
+        process_wrapper_flags.add("--rustc-json-output", crate_info.rustc_output.path)
 
     rustc_flags.add(error_format, format = "--error-format=%s")
 
@@ -980,6 +1019,11 @@ def construct_arguments(
     if remap_path_prefix != None:
         rustc_flags.add("--remap-path-prefix=${{pwd}}={}".format(remap_path_prefix))
 
+        # Paths outside of the exec root, which differ across machines, can only be remapped
+        # when rustc runs.
+        if toolchain._experimental_remap_path_prefixes:
+            process_wrapper_flags.add("--rustc-remap-path-prefixes", "true")
+
     emit_without_paths = []
     for kind in emit:
         if kind == "link" and crate_info.type == "bin" and crate_info.output != None:
@@ -990,8 +1034,12 @@ def construct_arguments(
     if emit_without_paths:
         rustc_flags.add_joined(emit_without_paths, format_joined = "--emit=%s", join_with = ",")
     if error_format != "json":
-        # Color is not compatible with json output.
-        rustc_flags.add("--color=always")
+        # Color is not compatible with json output. The process wrapper passes it on to rustc and
+        # removes the colors from the diagnostics it writes to files.
+        if getattr(ctx.executable, "_process_wrapper", None):
+            process_wrapper_flags.add("--color", "always")
+        else:
+            rustc_flags.add("--color=always")
     rustc_flags.add(toolchain.target_flag_value, format = "--target=%s")
     if hasattr(attr, "crate_features"):
         rustc_flags.add_all(getattr(attr, "crate_features"), before_each = "--cfg", format_each = 'feature="%s"')
@@ -1226,6 +1274,12 @@ def rustc_compile_action(
         experimental_use_cc_common_link = experimental_use_cc_common_link,
     )
 
+    # Run the compile actions in persistent workers of the process wrapper if enabled.
+    worker_env_file = None
+    if toolchain._experimental_use_persistent_worker and ctx.executable._process_wrapper:
+        worker_env_file = ctx.actions.declare_file(crate_info.output.basename + ".worker_env", sibling = crate_info.output)
+        compile_inputs = depset([worker_env_file], transitive = [compile_inputs])
+
     # The types of rustc outputs to emit.
     # If we build metadata, we need to keep the command line of the two invocations
     # (rlib and rmeta) as similar as possible, otherwise rustc rejects the rmeta as
@@ -1260,8 +1314,11 @@ def rustc_compile_action(
         build_flags_files = build_flags_files,
         force_all_deps_direct = force_all_deps_direct,
         stamp = stamp,
-        use_json_output = bool(build_metadata) or bool(rustc_output) or bool(rustc_rmeta_output),
+        # The process wrapper rewrites the paths of json diagnostics to be relative to the
+        # exec root if requested. The bootstrap process wrapper forwards the output of rustc as is.
+        use_json_output = (bool(ctx.executable._process_wrapper) and toolchain._experimental_relative_diagnostic_paths) or bool(build_metadata) or bool(rustc_output) or bool(rustc_rmeta_output),
         skip_expanding_rustc_env = skip_expanding_rustc_env,
+        worker_env_file = worker_env_file,
     )
 
     args_metadata = None
@@ -1288,12 +1345,43 @@ def rustc_compile_action(
             stamp = stamp,
             use_json_output = True,
             build_metadata = True,
+            worker_env_file = worker_env_file,
         )
 
+    # With pipelined compilation, the full action runs rustc identically to the metadata action
+    # until metadata is emitted, so it leaves reporting the diagnostics up to then to the
+    # metadata action. The metadata action is validated below so that it always runs.
+    if args_metadata and ctx.executable._process_wrapper:
+        args_metadata.process_wrapper_flags.add("--rustc-action-flavor", "metadata")
+        args.process_wrapper_flags.add("--rustc-action-flavor", "full")
+
     env = dict(ctx.configuration.default_shell_env)
 
     # this is the final list of env vars
-    env.update(env_from_args)
+    execution_requirements = {}
+    if worker_env_file:
+        # The process wrapper reads the environment specific to the crate from a file instead.
+        ctx.actions.write(worker_env_file, _format_env_file(env_from_args))
+        execution_requirements = {
+            "requires-worker-protocol": "json",
+            "supports-multiplex-workers": "1",
+            "supports-workers": "1",
+        }
+    else:
+        env.update(env_from_args)
+
+    # The incremental compilation directories managed by the process wrapper are outside of the
+    # sandbox and only exist on the local machine.
+    incremental_compilation_dir = toolchain._experimental_incremental_compilation_dir
+    if incremental_compilation_dir and ctx.executable._process_wrapper:
+        for action_args in [args, args_metadata]:
+            if action_args:
+                action_args.process_wrapper_flags.add("--rustc-incremental-dir", incremental_compilation_dir)
+        execution_requirements = dict(execution_requirements)
+        execution_requirements.update({
+            "no-remote": "1",
+            "no-sandbox": "1",
+        })
 
     if hasattr(attr, "version") and attr.version != "0.0.0":
         formatted_version = " v{}".format(attr.version)
@@ -1326,6 +1414,14 @@ def rustc_compile_action(
     if rustc_output:
         action_outputs.append(rustc_output)
 
+    # The diagnostics of rustc internal compiler errors, which are otherwise lost with the sandbox.
+    # A crash of the metadata action reoccurs in the full one, which is why only the latter captures them.
+    rustc_ice_report = None
+    if toolchain._experimental_capture_rustc_ice and ctx.executable._process_wrapper:
+        rustc_ice_report = ctx.actions.declare_file(crate_info.output.basename + ".ice.txt", sibling = crate_info.output)
+        args.process_wrapper_flags.add("--rustc-ice-report", rustc_ice_report)
+        action_outputs.append(rustc_ice_report)
+
     # Get the compilation mode for the current target.
     compilation_mode = get_compilation_mode_opts(ctx, toolchain)
 
@@ -1350,6 +1446,7 @@ def rustc_compile_action(
             env = env,
             arguments = args.all,
             mnemonic = "Rustc",
+            execution_requirements = execution_requirements,
             progress_message = "Compiling Rust {} {}{} ({} files)".format(
                 crate_info.type,
                 ctx.label.name,
@@ -1367,6 +1464,7 @@ def rustc_compile_action(
                 env = env,
                 arguments = args_metadata.all,
                 mnemonic = "RustcMetadata",
+                execution_requirements = execution_requirements,
                 progress_message = "Compiling Rust metadata {} {}{} ({} files)".format(
                     crate_info.type,
                     ctx.label.name,
@@ -1561,10 +1659,16 @@ def rustc_compile_action(
         output_group_info["dsym_folder"] = depset([dsym_folder])
     if build_metadata:
         output_group_info["build_metadata"] = depset([build_metadata])
+        if args_metadata and ctx.executable._process_wrapper:
+            # Validating the metadata runs its action even if nothing depends on it, so the
+            # diagnostics the full action leaves to it are reported.
+            output_group_info["_validation"] = depset([build_metadata])
         if rustc_rmeta_output:
             output_group_info["rustc_rmeta_output"] = depset([rustc_rmeta_output])
     if rustc_output:
         output_group_info["rustc_output"] = depset([rustc_output])
+    if rustc_ice_report:
+        output_group_info["rustc_ice_report"] = depset([rustc_ice_report])
 
     if output_group_info:
         providers.append(OutputGroupInfo(**output_group_info))
@@ -2237,7 +2341,8 @@ def _rustc_output_diagnostics_impl(ctx):
 rustc_output_diagnostics = rule(
     doc = (
         "Setting this flag from the command line with `--@rules_rust//rust/settings:rustc_output_diagnostics` " +
-        "makes rules_rust save rustc json output(suitable for consumption by rust-analyzer) in a file. " +
+        "makes rules_rust save rustc json output(suitable for consumption by rust-analyzer) in a file, " +
+        "with sandbox paths rewritten like those of the printed diagnostics. " +
         "These are accessible via the " +
         "`rustc_rmeta_output`(for pipelined compilation) and `rustc_output` output groups. " +
         "You can find these using `bazel cquery`"
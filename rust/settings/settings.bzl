@@ -153,6 +153,66 @@ def experimental_link_std_dylib():
         build_setting_default = False,
     )
 
+def experimental_use_persistent_worker():
+    """A flag to run rustc compile actions in persistent, multiplexed workers of the process wrapper.
+
+    Workers amortize the startup of the process wrapper across actions. Like other workers, they
+    are only used with the `worker` execution strategy (e.g. `--strategy=Rustc=worker`) and any
+    other strategy runs the actions standalone. Disabling this flag runs them standalone as well.
+    """
+    bool_flag(
+        name = "experimental_use_persistent_worker",
+        build_setting_default = False,
+    )
+
+def experimental_remap_path_prefixes():
+    """A flag to have the process wrapper remap the exec root, output base and home directory in rustc outputs.
+
+    The paths rustc embeds in debug info and panic messages are then independent of the machine
+    building them, which improves remote cache hits.
+    """
+    bool_flag(
+        name = "experimental_remap_path_prefixes",
+        build_setting_default = False,
+    )
+
+def experimental_incremental_compilation_dir():
+    """A flag specifying an absolute directory in which the process wrapper manages persistent rustc \
+    incremental compilation directories, speeding up local edit-compile loops.
+
+    Each crate gets a directory keyed by its name and configuration, and directories unused for a
+    week are removed. As the directory is outside of the sandbox, compile actions then run
+    unsandboxed and neither remotely nor with remote caching. Empty disables incremental compilation.
+    """
+    string_flag(
+        name = "experimental_incremental_compilation_dir",
+        build_setting_default = "",
+    )
+
+def experimental_capture_rustc_ice():
+    """A flag to have the process wrapper capture the diagnostics of rustc internal compiler errors.
+
+    The command line, environment and `rustc-ice-*.txt` reports of a crashing rustc are written to
+    a `<crate output>.ice.txt` file in the `rustc_ice_report` output group, which is empty unless
+    rustc crashed. Otherwise the reports are lost along with the sandbox.
+    """
+    bool_flag(
+        name = "experimental_capture_rustc_ice",
+        build_setting_default = False,
+    )
+
+def experimental_relative_diagnostic_paths():
+    """A flag to have the process wrapper rewrite the paths of rustc diagnostics to be relative to the exec root.
+
+    rustc emits json diagnostics which the process wrapper renders after replacing absolute paths
+    into the exec root or sandbox, so diagnostics are identical across machines. Otherwise the
+    output of rustc is forwarded as is.
+    """
+    bool_flag(
+        name = "experimental_relative_diagnostic_paths",
+        build_setting_default = False,
+    )
+
 def experimental_use_sh_toolchain_for_bootstrap_process_wrapper():
     """A flag to control whether the shell path from a shell toolchain (`@bazel_tools//tools/sh:toolchain_type`) \
     is embedded into the bootstrap process wrapper for the `.sh` file.
@@ -702,6 +702,11 @@ def _rust_toolchain_impl(ctx):
         _experimental_use_cc_common_link = _experimental_use_cc_common_link(ctx),
         _experimental_use_global_allocator = experimental_use_global_allocator,
         _experimental_use_coverage_metadata_files = ctx.attr._experimental_use_coverage_metadata_files[BuildSettingInfo].value,
+        _experimental_use_persistent_worker = ctx.attr._experimental_use_persistent_worker[BuildSettingInfo].value,
+        _experimental_remap_path_prefixes = ctx.attr._experimental_remap_path_prefixes[BuildSettingInfo].value,
+        _experimental_incremental_compilation_dir = ctx.attr._experimental_incremental_compilation_dir[BuildSettingInfo].value,
+        _experimental_capture_rustc_ice = ctx.attr._experimental_capture_rustc_ice[BuildSettingInfo].value,
+        _experimental_relative_diagnostic_paths = ctx.attr._experimental_relative_diagnostic_paths[BuildSettingInfo].value,
         _incompatible_change_rust_test_compilation_output_directory = ctx.attr._incompatible_change_rust_test_compilation_output_directory[IncompatibleFlagInfo].enabled,
         _toolchain_generated_sysroot = ctx.attr._toolchain_generated_sysroot[BuildSettingInfo].value,
         _incompatible_do_not_include_data_in_compile_data = ctx.attr._incompatible_do_not_include_data_in_compile_data[IncompatibleFlagInfo].enabled,
@@ -895,6 +900,21 @@ rust_toolchain = rule(
                 "This flag is only relevant when used together with --@rules_rust//rust/settings:experimental_use_global_allocator."
             ),
         ),
+        "_experimental_capture_rustc_ice": attr.label(
+            default = Label("//rust/settings:experimental_capture_rustc_ice"),
+        ),
+        "_experimental_incremental_compilation_dir": attr.label(
+            default = Label("//rust/settings:experimental_incremental_compilation_dir"),
+        ),
+        "_experimental_relative_diagnostic_paths": attr.label(
+            default = Label("//rust/settings:experimental_relative_diagnostic_paths"),
+        ),
+        "_experimental_remap_path_prefixes": attr.label(
+            default = Label("//rust/settings:experimental_remap_path_prefixes"),
+        ),
+        "_experimental_use_persistent_worker": attr.label(
+            default = Label("//rust/settings:experimental_use_persistent_worker"),
+        ),
         "_incompatible_change_rust_test_compilation_output_directory": attr.label(
             default = Label("//rust/settings:incompatible_change_rust_test_compilation_output_directory"),
         ),
@@ -9,7 +9,7 @@ mod test {
     /// process wrapper arguments. No arguments are passed to fake_rustc itself.
     ///
     fn fake_rustc(
-        process_wrapper_args: &[&'static str],
+        process_wrapper_args: &[&str],
         fake_rustc_args: &[&'static str],
         should_succeed: bool,
     ) -> String {
@@ -90,6 +90,68 @@ mod test {
         assert_eq!(rendered_content, "should be\nin output");
     }
 
+    #[test]
+    fn test_rustc_action_flavor() {
+        let metadata_content = fake_rustc(
+            &[
+                "--rustc-quit-on-rmeta",
+                "true",
+                "--rustc-action-flavor",
+                "metadata",
+                "--rustc-output-format",
+                "rendered",
+            ],
+            &[],
+            true,
+        );
+        assert_eq!(metadata_content, "should be\nin output");
+
+        // The full action skips the diagnostics the metadata action reports.
+        let full_content = fake_rustc(
+            &[
+                "--rustc-action-flavor",
+                "full",
+                "--rustc-output-format",
+                "rendered",
+            ],
+            &[],
+            true,
+        );
+        assert_eq!(full_content, "should not be in output");
+    }
+
+    #[test]
+    fn test_rustc_json_output() {
+        let json_output = std::path::Path::new(&std::env::var("TEST_TMPDIR").unwrap())
+            .join("rustc_json_output.json")
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let rendered_content = fake_rustc(
+            &[
+                "--rustc-json-output",
+                json_output.as_str(),
+                "--rustc-output-format",
+                "rendered",
+            ],
+            &[],
+            true,
+        );
+        assert_eq!(
+            rendered_content,
+            "should be\nin outputshould not be in output"
+        );
+        assert_eq!(
+            std::fs::read_to_string(json_output).unwrap(),
+            concat!(
+                r#"{"rendered": "should be\nin output"}"#,
+                "\n",
+                r#"{"rendered": "should not be in output"}"#,
+                "\n"
+            )
+        );
+    }
+
     #[test]
     fn test_rustc_panic() {
         let rendered_content = fake_rustc(&["--rustc-output-format", "json"], &["error"], false);
@@ -0,0 +1,97 @@
+"""Unit tests for crates_vendor.bzl."""
+
+load("@bazel_skylib//lib:unittest.bzl", "asserts", "unittest")
+
+# buildifier: disable=bzl-visibility
+load(
+    "//crate_universe/private:crates_vendor.bzl",
+    "generate_groups_file",
+    "relative_path",
+)
+
+def _relative_path_test_impl(ctx):
+    env = unittest.begin(ctx)
+    asserts.equals(
+        env,
+        "config.json",
+        relative_path("_main/3rdparty", "_main/3rdparty/config.json"),
+    )
+    asserts.equals(
+        env,
+        "../tools/config.json",
+        relative_path("_main/3rdparty/prod", "_main/3rdparty/tools/config.json"),
+    )
+    asserts.equals(
+        env,
+        "../../other_repo/config.json",
+        relative_path("_main/3rdparty", "other_repo/config.json"),
+    )
+    asserts.equals(
+        env,
+        "../3rdparty.json",
+        relative_path("_main/3rdparty", "_main/3rdparty.json"),
+    )
+    return unittest.end(env)
+
+def _generate_groups_file_test_impl(ctx):
+    env = unittest.begin(ctx)
+    groups = json.decode(generate_groups_file(
+        groups = [
+            struct(
+                cargo_lockfile = "${build_workspace_directory}/3rdparty/prod/Cargo.lock",
+                config = "_main/3rdparty/prod/crates_vendor.cargo-bazel-config.json",
+                name = "//3rdparty/prod:crates_vendor",
+                splicing_manifest = "_main/3rdparty/prod/crates_vendor.cargo-bazel-splicing-manifest.json",
+            ),
+            struct(
+                cargo_lockfile = None,
+                config = "_main/3rdparty/tools/crates_vendor.cargo-bazel-config.json",
+                name = "//3rdparty/tools:crates_vendor",
+                splicing_manifest = "_main/3rdparty/tools/crates_vendor.cargo-bazel-splicing-manifest.json",
+            ),
+        ],
+        groups_dir = "_main/3rdparty",
+    ))
+    asserts.equals(
+        env,
+        [
+            {
+                "cargo_lockfile": "${build_workspace_directory}/3rdparty/prod/Cargo.lock",
+                "config": "prod/crates_vendor.cargo-bazel-config.json",
+                "name": "//3rdparty/prod:crates_vendor",
+                "splicing_manifest": "prod/crates_vendor.cargo-bazel-splicing-manifest.json",
+            },
+            {
+                "cargo_lockfile": None,
+                "config": "tools/crates_vendor.cargo-bazel-config.json",
+                "name": "//3rdparty/tools:crates_vendor",
+                "splicing_manifest": "tools/crates_vendor.cargo-bazel-splicing-manifest.json",
+            },
+        ],
+        groups,
+    )
+    return unittest.end(env)
+
+relative_path_test = unittest.make(_relative_path_test_impl)
+generate_groups_file_test = unittest.make(_generate_groups_file_test_impl)
+
+def crates_vendor_test_suite(name):
+    """Entry-point macro called from the BUILD file.
+
+    Args:
+        name (str): Name of the macro.
+    """
+    relative_path_test(
+        name = "relative_path_test",
+    )
+    generate_groups_file_test(
+        name = "generate_groups_file_test",
+    )
+
+    native.test_suite(
+        name = name,
+        tests = [
+            "relative_path_test",
+            "generate_groups_file_test",
+        ],
+    )
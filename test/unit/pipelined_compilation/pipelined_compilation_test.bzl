@@ -2,7 +2,7 @@
 
 load("@bazel_skylib//lib:unittest.bzl", "analysistest", "asserts")
 load("//rust:defs.bzl", "rust_binary", "rust_library", "rust_proc_macro")
-load("//test/unit:common.bzl", "assert_argv_contains", "assert_list_contains_adjacent_elements", "assert_list_contains_adjacent_elements_not")
+load("//test/unit:common.bzl", "assert_argv_contains", "assert_argv_contains_prefix_not", "assert_list_contains_adjacent_elements", "assert_list_contains_adjacent_elements_not")
 load(":wrap.bzl", "wrap")
 
 NOT_WINDOWS = select({
@@ -43,6 +43,27 @@ def _second_lib_test_impl(ctx):
     assert_list_contains_adjacent_elements_not(env, rlib_action.argv, ["--rustc-quit-on-rmeta", "true"])
     assert_list_contains_adjacent_elements(env, metadata_action.argv, ["--rustc-quit-on-rmeta", "true"])
 
+    # Both actions forward termination requests to rustc.
+    assert_list_contains_adjacent_elements(env, rlib_action.argv, ["--forward-signals", "true"])
+    assert_list_contains_adjacent_elements(env, metadata_action.argv, ["--forward-signals", "true"])
+
+    # Rendered json diagnostics are colored through --json, so the process wrapper only strips the
+    # colors from the diagnostics it writes to files instead of passing --color on to rustc.
+    assert_list_contains_adjacent_elements(env, metadata_action.argv, ["--color", "always"])
+    assert_argv_contains_prefix_not(env, metadata_action, "--color=")
+
+    # Each action should be tagged with its flavor, so the action building the rlib skips the
+    # diagnostics the metadata action reports, without depending on it
+    assert_list_contains_adjacent_elements(env, metadata_action.argv, ["--rustc-action-flavor", "metadata"])
+    assert_list_contains_adjacent_elements(env, rlib_action.argv, ["--rustc-action-flavor", "full"])
+    metadata = metadata_action.outputs.to_list()[0]
+    asserts.false(env, metadata in rlib_action.inputs.to_list(), "The rlib action should not depend on its own metadata")
+    asserts.true(
+        env,
+        metadata in tut[OutputGroupInfo]._validation.to_list(),
+        "The metadata should be validated so that its diagnostics are reported",
+    )
+
     # Check that both actions refer to the metadata of :first, not the rlib
     extern_metadata = [arg for arg in metadata_action.argv if arg.startswith("--extern=first=") and "libfirst" in arg and arg.endswith(".rmeta")]
     asserts.true(
@@ -0,0 +1,165 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copying of outputs, which may be large, e.g. rlibs, with hardlinks or reflinks where the file
+//! system supports them.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How files are copied. Hardlinks and reflinks fall back to copying the bytes of the file
+/// if the file system doesn't support them, e.g. across devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CopyMode {
+    Copy,
+    // The copy shares the file of the source, so neither may be modified afterwards.
+    Hardlink,
+    // The copy shares the data of the source until either is modified.
+    Reflink,
+}
+
+impl CopyMode {
+    pub(crate) fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "copy" => Some(Self::Copy),
+            "hardlink" => Some(Self::Hardlink),
+            "reflink" => Some(Self::Reflink),
+            _ => None,
+        }
+    }
+}
+
+/// Clones the data of `source` into a new file `dest` with the `FICLONE` ioctl.
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, dest: &Path) -> io::Result<()> {
+    use std::fs::File;
+    use std::os::raw::{c_int, c_ulong};
+    use std::os::unix::io::AsRawFd;
+
+    extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+    const FICLONE: c_ulong = 0x4004_9409;
+
+    let source = File::open(source)?;
+    let permissions = source.metadata()?.permissions();
+    let dest_file = File::create(dest)?;
+    // Safe as both file descriptors stay open for the duration of the call.
+    if unsafe { ioctl(dest_file.as_raw_fd(), FICLONE, source.as_raw_fd()) } != 0 {
+        let error = io::Error::last_os_error();
+        drop(dest_file);
+        let _ = fs::remove_file(dest);
+        return Err(error);
+    }
+    dest_file.set_permissions(permissions)
+}
+
+/// Clones `source` into a new file `dest` with `clonefile`.
+#[cfg(target_os = "macos")]
+fn reflink(source: &Path, dest: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int};
+    use std::os::unix::ffi::OsStrExt;
+
+    extern "C" {
+        fn clonefile(src: *const c_char, dst: *const c_char, flags: c_int) -> c_int;
+    }
+
+    let c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    };
+    let (source, dest) = (c_path(source)?, c_path(dest)?);
+    // Safe as both pointers are valid for the duration of the call.
+    if unsafe { clonefile(source.as_ptr(), dest.as_ptr(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink(_source: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are not supported on this platform",
+    ))
+}
+
+/// Copies `source` to `dest`, replacing it if it exists, with `mode`.
+pub(crate) fn copy_file(source: &Path, dest: &Path, mode: CopyMode) -> io::Result<()> {
+    if mode != CopyMode::Copy {
+        // Neither links nor clones replace existing files.
+        match fs::remove_file(dest) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let linked = match mode {
+            CopyMode::Hardlink => fs::hard_link(source, dest),
+            _ => reflink(source, dest),
+        };
+        if linked.is_ok() {
+            return Ok(());
+        }
+    }
+    fs::copy(source, dest).map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_copy_file() {
+        let dir = env::var_os("TEST_TMPDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(format!("process_wrapper_copy_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source");
+        fs::write(&source, "contents").unwrap();
+
+        for mode in ["copy", "hardlink", "reflink"] {
+            let dest = dir.join(mode);
+            fs::write(&dest, "stale").unwrap();
+            copy_file(&source, &dest, CopyMode::parse(mode).unwrap()).unwrap();
+            assert_eq!(fs::read_to_string(&dest).unwrap(), "contents", "{}", mode);
+        }
+        assert!(CopyMode::parse("symlink").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlink() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = env::var_os("TEST_TMPDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(format!("process_wrapper_hardlink_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, dest) = (dir.join("source"), dir.join("dest"));
+        fs::write(&source, "contents").unwrap();
+        copy_file(&source, &dest, CopyMode::Hardlink).unwrap();
+        assert_eq!(
+            fs::metadata(&source).unwrap().ino(),
+            fs::metadata(&dest).unwrap().ino()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Handling of the raw profiles written by programs instrumented for coverage, e.g. proc macros
+//! loaded by rustc. Their location is otherwise determined by `LLVM_PROFILE_FILE` in the
+//! environment of the action, or defaults to `default_%m.profraw` in the working directory.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the raw profiles written to the profile directory. `%p` and `%m` keep the
+/// profiles of concurrent processes and of different binaries apart.
+const PROFILE_FILE_PATTERN: &str = "%p-%m.profraw";
+
+/// Returns the `LLVM_PROFILE_FILE` pattern writing raw profiles to `dir`.
+pub(crate) fn llvm_profile_file(dir: &str) -> String {
+    Path::new(dir)
+        .join(PROFILE_FILE_PATTERN)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns the raw profiles in `dir`, sorted by name.
+fn raw_profiles(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut profiles = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "profraw") {
+            profiles.push(path);
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
+/// Creates the profile directory `dir`, removing the raw profiles of previous runs.
+pub(crate) fn prepare_profile_dir(dir: &str) -> io::Result<()> {
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir)?;
+    for profile in raw_profiles(dir)? {
+        fs::remove_file(profile)?;
+    }
+    Ok(())
+}
+
+/// Concatenates the raw profiles in `dir` into `output`, which LLVM tools read like the
+/// individual profiles. The profiles are sorted by name, so `output` doesn't depend on the order
+/// they were written in, and `output` is created even if there are no profiles.
+pub(crate) fn collect_profiles(dir: &str, output: &str) -> io::Result<()> {
+    let mut output = File::create(output)?;
+    for profile in raw_profiles(Path::new(dir))? {
+        io::copy(&mut File::open(profile)?, &mut output)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn test_collect_profiles() {
+        let dir = env::var_os("TEST_TMPDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(format!("process_wrapper_coverage_{}", std::process::id()));
+        let profile_dir = dir.join("profiles");
+        let profile_dir = profile_dir.to_str().unwrap();
+        fs::create_dir_all(profile_dir).unwrap();
+        fs::write(dir.join("profiles/stale.profraw"), "stale").unwrap();
+
+        prepare_profile_dir(profile_dir).unwrap();
+        assert!(llvm_profile_file(profile_dir).starts_with(profile_dir));
+        fs::write(dir.join("profiles/2-abc.profraw"), "second").unwrap();
+        fs::write(dir.join("profiles/1-abc.profraw"), "first ").unwrap();
+        fs::write(dir.join("profiles/other.txt"), "other").unwrap();
+
+        let output = dir.join("coverage.profraw");
+        let output = output.to_str().unwrap();
+        collect_profiles(profile_dir, output).unwrap();
+        assert_eq!(fs::read_to_string(output).unwrap(), "first second");
+
+        prepare_profile_dir(profile_dir).unwrap();
+        collect_profiles(profile_dir, output).unwrap();
+        assert_eq!(fs::read_to_string(output).unwrap(), "");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
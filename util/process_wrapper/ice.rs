@@ -0,0 +1,150 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Capture of rustc internal compiler errors. rustc writes `rustc-ice-*.txt` reports to the
+//! directory named by `RUSTC_ICE`, or its working directory, which is lost along with the
+//! sandbox. They're copied into a declared output instead.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The exit code of rustc when it panics.
+const ICE_EXIT_CODE: i32 = 101;
+
+/// Returns the directory rustc writes ICE reports to when run with `env`.
+pub(crate) fn reports_dir(env: &HashMap<String, String>) -> PathBuf {
+    match env.get("RUSTC_ICE") {
+        Some(dir) if !dir.is_empty() && dir != "0" => PathBuf::from(dir),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Returns the ICE reports in `dir` written since `start`, sorted by name.
+fn reports_since(dir: &Path, start: SystemTime) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut reports: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("rustc-ice-") && name.ends_with(".txt")
+        })
+        .filter(|entry| {
+            entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified >= start)
+        })
+        .map(|entry| entry.path())
+        .collect();
+    reports.sort();
+    reports
+}
+
+/// Renders the diagnostics of an ICE: the command line and environment of rustc, followed by
+/// the `reports`.
+fn render(
+    executable: &str,
+    args: &[String],
+    env: &HashMap<String, String>,
+    reports: &[PathBuf],
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "command line:\n{} {}\n", executable, args.join(" "));
+    let mut vars: Vec<_> = env.iter().collect();
+    vars.sort();
+    let _ = writeln!(out, "environment:");
+    for (key, value) in vars {
+        let _ = writeln!(out, "{key}={value}");
+    }
+    for report in reports {
+        let contents = fs::read_to_string(report)
+            .unwrap_or_else(|e| format!("failed to read the report: {e}\n"));
+        let _ = writeln!(out, "\n{}:\n{}", report.display(), contents);
+    }
+    out
+}
+
+/// Writes the diagnostics of an ICE of rustc, which ran with `args` and `env` and exited with
+/// `code`, to `output` and returns whether it crashed. `output` is created empty otherwise, as
+/// Bazel expects declared outputs to exist.
+pub(crate) fn capture(
+    output: &str,
+    code: i32,
+    start: SystemTime,
+    executable: &str,
+    args: &[String],
+    env: &HashMap<String, String>,
+) -> io::Result<bool> {
+    let reports = reports_since(&reports_dir(env), start);
+    if code == 0 || (code != ICE_EXIT_CODE && reports.is_empty()) {
+        fs::write(output, "")?;
+        return Ok(false);
+    }
+    fs::write(output, render(executable, args, env, &reports))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn test_reports_dir() {
+        let mut env = HashMap::new();
+        assert_eq!(reports_dir(&env), PathBuf::from("."));
+        env.insert("RUSTC_ICE".to_owned(), "0".to_owned());
+        assert_eq!(reports_dir(&env), PathBuf::from("."));
+        env.insert("RUSTC_ICE".to_owned(), "/tmp/ice".to_owned());
+        assert_eq!(reports_dir(&env), PathBuf::from("/tmp/ice"));
+    }
+
+    #[test]
+    fn test_capture() {
+        let dir = env::var_os("TEST_TMPDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(format!("process_wrapper_ice_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let start = SystemTime::now() - std::time::Duration::from_secs(60);
+        let env = HashMap::from([("RUSTC_ICE".to_owned(), dir.display().to_string())]);
+        let args = vec!["--crate-name=foo".to_owned(), "lib.rs".to_owned()];
+        let output = dir.join("ice.txt");
+        let output = output.to_str().unwrap();
+
+        assert!(!capture(output, 1, start, "rustc", &args, &env).unwrap());
+        assert_eq!(fs::read_to_string(output).unwrap(), "");
+
+        fs::write(
+            dir.join("rustc-ice-2024-01-01-42.txt"),
+            "thread 'rustc' panicked",
+        )
+        .unwrap();
+        assert!(!capture(output, 0, start, "rustc", &args, &env).unwrap());
+        assert!(capture(output, 1, start, "rustc", &args, &env).unwrap());
+        let report = fs::read_to_string(output).unwrap();
+        assert!(report.starts_with("command line:\nrustc --crate-name=foo lib.rs\n"));
+        assert!(report.contains(&format!("RUSTC_ICE={}\n", dir.display())));
+        assert!(report.contains("thread 'rustc' panicked"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Management of persistent rustc incremental compilation directories. Each crate gets its own
+//! directory below a base directory outside of the sandbox, keyed by the crate, the
+//! configuration it's built in and the action of pipelined compilation. Directories which weren't used for a while are removed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::rustc::ActionFlavor;
+use crate::util::fnv1a;
+
+/// The file whose modification time records when a directory was last used.
+const LAST_USED_FILE: &str = ".last_used";
+
+/// Directories which weren't used for this long are removed.
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The rustc arguments identifying a crate and its configuration, which output directories
+/// contain, e.g. `bazel-out/k8-fastbuild/bin/pkg`.
+const KEY_ARGS: [&str; 3] = ["--crate-name=", "--crate-type=", "--out-dir="];
+
+/// Returns the incremental directory below `base` of the crate compiled with `rustc_args` by an
+/// action of `flavor`. The metadata and full actions of pipelined compilation emit different
+/// outputs and run concurrently, so each gets its own directory.
+fn crate_dir(base: &Path, rustc_args: &[String], flavor: Option<ActionFlavor>) -> PathBuf {
+    let mut key_args: Vec<&str> = rustc_args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| *arg == "--test" || KEY_ARGS.iter().any(|key| arg.starts_with(key)))
+        .collect();
+    match flavor {
+        Some(ActionFlavor::Metadata) => key_args.push("--rustc-action-flavor=metadata"),
+        Some(ActionFlavor::Full) => key_args.push("--rustc-action-flavor=full"),
+        None => {}
+    }
+    let crate_name = key_args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--crate-name="))
+        .unwrap_or("unknown");
+    let hash = fnv1a(key_args.join("\n").into_bytes());
+    base.join(format!("{crate_name}-{hash:016x}"))
+}
+
+/// Removes the directories below `base` which weren't used for `max_age`. Errors are ignored,
+/// as other actions may be using or removing the directories concurrently.
+fn collect_garbage(base: &Path, max_age: Duration) {
+    let Ok(entries) = fs::read_dir(base) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        let last_used = fs::metadata(dir.join(LAST_USED_FILE)).and_then(|m| m.modified());
+        let stale = match last_used {
+            Ok(last_used) => now.duration_since(last_used).unwrap_or_default() > max_age,
+            // Directories being created don't have the file yet.
+            Err(_) => false,
+        };
+        if stale {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Prepares the incremental directory below `base` of the crate compiled with `rustc_args` by an
+/// action of `flavor` and returns the rustc flag to use it.
+pub(crate) fn incremental_flag(
+    base: &str,
+    rustc_args: &[String],
+    flavor: Option<ActionFlavor>,
+) -> Result<String, String> {
+    let base = Path::new(base);
+    if !base.is_absolute() {
+        return Err(format!(
+            "the incremental directory {} must be absolute, as it's outside of the sandbox",
+            base.display()
+        ));
+    }
+    collect_garbage(base, MAX_AGE);
+
+    let dir = crate_dir(base, rustc_args, flavor);
+    fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(dir.join(LAST_USED_FILE), ""))
+        .map_err(|e| format!("failed to prepare {}: {e}", dir.display()))?;
+    Ok(format!("-Cincremental={}", dir.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| (*arg).to_owned()).collect()
+    }
+
+    #[test]
+    fn test_crate_dir() {
+        let base = Path::new("/incremental");
+        let lib = args(&[
+            "lib.rs",
+            "--crate-name=foo",
+            "--crate-type=rlib",
+            "--out-dir=bazel-out/k8-fastbuild/bin/pkg",
+            "--emit=dep-info,link",
+            "--codegen=opt-level=0",
+        ]);
+        let dir = crate_dir(base, &lib, None);
+        assert!(dir.starts_with(base));
+        assert!(dir
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("foo-"));
+        // Other flags don't affect the directory.
+        assert_eq!(crate_dir(base, &lib[..5], None), dir);
+
+        let mut opt_lib = lib.clone();
+        opt_lib[3] = "--out-dir=bazel-out/k8-opt/bin/pkg".to_owned();
+        assert_ne!(crate_dir(base, &opt_lib, None), dir);
+        let mut test = lib.clone();
+        test.push("--test".to_owned());
+        assert_ne!(crate_dir(base, &test, None), dir);
+
+        // The actions of pipelined compilation don't share a directory.
+        let metadata = crate_dir(base, &lib, Some(ActionFlavor::Metadata));
+        let full = crate_dir(base, &lib, Some(ActionFlavor::Full));
+        assert_ne!(metadata, full);
+        assert_ne!(metadata, dir);
+        assert_ne!(full, dir);
+        assert_eq!(crate_dir(base, &lib, Some(ActionFlavor::Full)), full);
+    }
+
+    #[test]
+    fn test_incremental_flag() {
+        let base = env::var_os("TEST_TMPDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(format!(
+                "process_wrapper_incremental_{}",
+                std::process::id()
+            ));
+        let stale = base.join("stale");
+        fs::create_dir_all(&stale).unwrap();
+        fs::File::create(stale.join(LAST_USED_FILE))
+            .unwrap()
+            .set_modified(SystemTime::now() - MAX_AGE - Duration::from_secs(60))
+            .unwrap();
+
+        let rustc_args = args(&["--crate-name=foo"]);
+        let flag = incremental_flag(base.to_str().unwrap(), &rustc_args, None).unwrap();
+        let dir = crate_dir(&base, &rustc_args, None);
+        assert_eq!(flag, format!("-Cincremental={}", dir.display()));
+        assert!(dir.join(LAST_USED_FILE).exists());
+        assert!(!stale.exists());
+        assert!(incremental_flag("relative", &rustc_args, None).is_err());
+        fs::remove_dir_all(base).unwrap();
+    }
+}
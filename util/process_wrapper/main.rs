@@ -12,19 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod copy;
+mod coverage;
 mod flags;
+mod ice;
+mod incremental;
+mod normalize;
 mod options;
 mod output;
+mod pattern;
+mod resources;
 mod rustc;
+mod signals;
+mod timeout;
 mod util;
+mod worker;
 
+use std::env;
 use std::fmt;
-use std::fs::{copy, OpenOptions};
-use std::io;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
 use std::process::{exit, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Instant, SystemTime};
 
-use crate::options::options;
-use crate::output::{process_output, LineOutput};
+use crate::options::{options, Options};
+use crate::output::{process_output, LineOutput, StripAnsi, Tee};
 
 #[cfg(windows)]
 fn status_code(status: ExitStatus, was_killed: bool) -> i32 {
@@ -69,42 +83,195 @@ macro_rules! log {
     };
 }
 
+/// The stream the stdout of the child process is written to, unless it's redirected to a file.
+#[derive(Debug, Clone, Copy)]
+enum StdoutTarget {
+    Stdout,
+    // Used by persistent workers, whose stdout is reserved for work responses.
+    Stderr,
+}
+
+impl StdoutTarget {
+    fn stdio(self) -> Stdio {
+        match self {
+            Self::Stdout => Stdio::inherit(),
+            Self::Stderr => Stdio::from(io::stderr()),
+        }
+    }
+
+    fn writer(self) -> Box<dyn io::Write + Send> {
+        match self {
+            Self::Stdout => Box::new(io::stdout()),
+            Self::Stderr => Box::new(io::stderr()),
+        }
+    }
+}
+
 fn main() -> Result<(), ProcessWrapperError> {
+    // Bazel starts persistent workers with this flag and sends the arguments of each action
+    // as work requests.
+    if env::args().any(|arg| arg == "--persistent_worker") {
+        return worker::run_worker();
+    }
+
     let opts = options().map_err(|e| ProcessWrapperError(e.to_string()))?;
+    if opts.forward_signals {
+        signals::install(opts.shutdown_grace_period);
+    }
+    let code = run(opts, StdoutTarget::Stdout, &mut io::stderr())?;
+    exit(code)
+}
 
-    let mut command = Command::new(opts.executable);
-    command
-        .args(opts.child_arguments)
-        .env_clear()
-        .envs(opts.child_environment)
-        .stdout(if let Some(stdout_file) = opts.stdout_file {
+/// Runs the child process described by `opts` and returns its exit code. Unless redirected to
+/// files by `opts`, the stdout of the child process is written to `stdout` and its processed
+/// stderr to `stderr`. Failures matching the retry pattern of `opts` are retried.
+fn run(
+    opts: Options,
+    stdout: StdoutTarget,
+    stderr: &mut dyn io::Write,
+) -> Result<i32, ProcessWrapperError> {
+    let Some(retry_pattern) = opts.retry_pattern.clone() else {
+        return run_once(opts, stdout, stderr, None);
+    };
+
+    let mut attempt = 0;
+    loop {
+        let mut captured_stderr = Vec::new();
+        let code = run_once(opts.clone(), stdout, stderr, Some(&mut captured_stderr))?;
+        let retry = code != 0
+            && attempt < opts.retry_count
+            && String::from_utf8_lossy(&captured_stderr)
+                .lines()
+                .any(|line| retry_pattern.is_match(line));
+        if !retry || signals::terminating() {
+            return Ok(code);
+        }
+        attempt += 1;
+        writeln!(
+            stderr,
+            "process wrapper: {} failed with a retryable error, retrying (attempt {} of {})",
+            opts.executable, attempt, opts.retry_count
+        )
+        .map_err(|e| ProcessWrapperError(format!("failed to write to stderr: {}", e)))?;
+    }
+}
+
+/// Runs the child process described by `opts` once, see `run`. The processed stderr is also
+/// written to `captured_stderr` if set.
+fn run_once(
+    opts: Options,
+    stdout: StdoutTarget,
+    stderr: &mut dyn io::Write,
+    captured_stderr: Option<&mut Vec<u8>>,
+) -> Result<i32, ProcessWrapperError> {
+    let stdout_file = opts
+        .stdout_file
+        .map(|stdout_file| {
             OpenOptions::new()
                 .create(true)
                 .truncate(true)
                 .write(true)
                 .open(stdout_file)
+                .map_err(|e| ProcessWrapperError(format!("unable to open stdout file: {}", e)))
+        })
+        .transpose()?;
+    let tee_output = opts.tee_output;
+    let executable = opts.executable.clone();
+
+    // The command line and environment are included in the diagnostics of internal compiler
+    // errors.
+    let ice_report = match opts.rustc_ice_report {
+        Some(output) => Some((
+            output,
+            SystemTime::now(),
+            opts.child_arguments.clone(),
+            opts.child_environment.clone(),
+        )),
+        None => None,
+    };
+
+    let mut command = Command::new(opts.executable);
+    command
+        .args(opts.child_arguments)
+        .env_clear()
+        .envs(opts.child_environment)
+        .stdout(match &stdout_file {
+            Some(_) if tee_output => Stdio::piped(),
+            Some(stdout_file) => stdout_file
+                .try_clone()
                 .map_err(|e| ProcessWrapperError(format!("unable to open stdout file: {}", e)))?
-                .into()
-        } else {
-            Stdio::inherit()
+                .into(),
+            None => stdout.stdio(),
         })
         .stderr(Stdio::piped());
+    if opts.timeout.is_some() || signals::installed() {
+        timeout::isolate_process_tree(&mut command);
+    }
+    if let Some(llvm_profile_dir) = &opts.llvm_profile_dir {
+        coverage::prepare_profile_dir(llvm_profile_dir).map_err(|e| {
+            ProcessWrapperError(format!("failed to prepare {}: {}", llvm_profile_dir, e))
+        })?;
+    }
     log!("{:#?}", command);
+    let start_time = Instant::now();
     let mut child = command
         .spawn()
         .map_err(|e| ProcessWrapperError(format!("failed to spawn child process: {}", e)))?;
+    signals::set_child(Some(child.id()));
+    let watchdog = opts
+        .timeout
+        .map(|timeout| timeout::Watchdog::start(child.id(), timeout));
+
+    // When teeing, stdout is copied to both its file and `stdout` while stderr is processed.
+    let stdout_tee = match (stdout_file, child.stdout.take()) {
+        (Some(stdout_file), Some(mut child_stdout)) => Some(thread::spawn(move || {
+            io::copy(&mut child_stdout, &mut Tee(stdout_file, stdout.writer()))
+        })),
+        _ => None,
+    };
 
-    let mut stderr: Box<dyn io::Write> = if let Some(stderr_file) = opts.stderr_file {
-        Box::new(
+    let color = opts.color;
+    let mut stderr_file = opts
+        .stderr_file
+        .map(|stderr_file| {
             OpenOptions::new()
                 .create(true)
                 .truncate(true)
                 .write(true)
                 .open(stderr_file)
-                .map_err(|e| ProcessWrapperError(format!("unable to open stderr file: {}", e)))?,
-        )
+                .map_err(|e| ProcessWrapperError(format!("unable to open stderr file: {}", e)))
+        })
+        .transpose()?
+        .map(|stderr_file| -> Box<dyn io::Write> {
+            if color.is_some() {
+                Box::new(StripAnsi::new(stderr_file))
+            } else {
+                Box::new(stderr_file)
+            }
+        });
+    let mut stderr_strip_ansi;
+    let stderr: &mut dyn io::Write = if color == Some(false) {
+        stderr_strip_ansi = StripAnsi::new(stderr);
+        &mut stderr_strip_ansi
     } else {
-        Box::new(io::stderr())
+        stderr
+    };
+    let mut stderr_tee;
+    let stderr: &mut dyn io::Write = match stderr_file.as_mut() {
+        Some(stderr_file) if tee_output => {
+            stderr_tee = Tee(stderr_file, stderr);
+            &mut stderr_tee
+        }
+        Some(stderr_file) => stderr_file,
+        None => stderr,
+    };
+    let mut stderr_capture;
+    let stderr: &mut dyn io::Write = match captured_stderr {
+        Some(captured_stderr) => {
+            stderr_capture = Tee(stderr, captured_stderr);
+            &mut stderr_capture
+        }
+        None => stderr,
     };
 
     let mut child_stderr = child.stderr.take().ok_or(ProcessWrapperError(
@@ -125,24 +292,89 @@ fn main() -> Result<(), ProcessWrapperError> {
     };
 
     let mut was_killed = false;
+    let mut denied_warnings = 0;
     let result = if let Some(format) = opts.rustc_output_format {
         let quit_on_rmeta = opts.rustc_quit_on_rmeta;
+        let skip_reported_by_metadata_action =
+            opts.rustc_action_flavor == Some(rustc::ActionFlavor::Full);
+        let mut rmeta_sentinel = opts.rustc_rmeta_sentinel;
+        let path_rewriter = rustc::PathRewriter::new(&opts.exec_root);
+        let mut json_output = opts
+            .rustc_json_output
+            .map(|json_output| {
+                File::create(&json_output).map_err(|e| {
+                    ProcessWrapperError(format!("unable to open {}: {}", json_output, e))
+                })
+            })
+            .transpose()?;
+        // Messages are processed as json and rendered afterwards if their json is needed.
+        let json_messages = json_output.is_some() || skip_reported_by_metadata_action;
+        let line_format = if json_messages {
+            rustc::ErrorFormat::Json
+        } else {
+            format
+        };
         // Process json rustc output and kill the subprocess when we get a signal
         // that we emitted a metadata file.
         let mut me = false;
         let metadata_emitted = &mut me;
+        // Unlike metadata_emitted, this doesn't terminate the child process.
+        let mut metadata_reported = false;
+        let mut wf = rustc::WarningFilter::new(opts.deny_warnings, opts.allowed_warnings);
+        let warning_filter = &mut wf;
         let result = process_output(
             &mut child_stderr,
-            stderr.as_mut(),
+            stderr,
             output_file.as_mut(),
             move |line| {
-                if quit_on_rmeta {
-                    rustc::stop_on_rmeta_completion(line, format, metadata_emitted)
+                let mut result = if quit_on_rmeta {
+                    rustc::stop_on_rmeta_completion(
+                        line,
+                        line_format,
+                        &path_rewriter,
+                        warning_filter,
+                        metadata_emitted,
+                    )
                 } else {
-                    rustc::process_json(line, format)
+                    rustc::process_json(
+                        line,
+                        line_format,
+                        &path_rewriter,
+                        warning_filter,
+                        &mut metadata_reported,
+                    )
+                };
+                if let (true, Ok(LineOutput::Message(json))) = (json_messages, &result) {
+                    // The json output contains all diagnostics, including skipped ones.
+                    if let Some(json_output) = json_output.as_mut() {
+                        json_output
+                            .write_all(json.as_bytes())
+                            .map_err(|e| format!("failed to write json output: {}", e))?;
+                    }
+                    if skip_reported_by_metadata_action
+                        && rustc::reported_by_metadata_action(json, metadata_reported)
+                    {
+                        result = Ok(LineOutput::Skip);
+                    } else if let rustc::ErrorFormat::Rendered = format {
+                        result = Ok(rustc::rendered_message(json)
+                            .map_or(LineOutput::Skip, LineOutput::Message));
+                    }
+                }
+                if *metadata_emitted || metadata_reported {
+                    if let Some(rmeta_sentinel) = rmeta_sentinel.take() {
+                        File::create(&rmeta_sentinel).map_err(|e| {
+                            format!("failed to create rmeta sentinel {}: {}", rmeta_sentinel, e)
+                        })?;
+                    }
                 }
+                result
             },
         );
+        if let Some(summary) = wf.summary() {
+            write!(stderr, "{}", summary)
+                .map_err(|e| ProcessWrapperError(format!("failed to write to stderr: {}", e)))?;
+        }
+        denied_warnings = wf.denied();
         if me {
             // If recv returns Ok(), a signal was sent in this channel so we should terminate the child process.
             // We can safely ignore the Result from kill() as we don't care if the process already terminated.
@@ -154,18 +386,73 @@ fn main() -> Result<(), ProcessWrapperError> {
         // Process output normally by forwarding stderr
         process_output(
             &mut child_stderr,
-            stderr.as_mut(),
+            stderr,
             output_file.as_mut(),
             move |line| Ok(LineOutput::Message(line)),
         )
     };
     result.map_err(|e| ProcessWrapperError(format!("failed to process stderr: {}", e)))?;
 
-    let status = child
-        .wait()
-        .map_err(|e| ProcessWrapperError(format!("failed to wait for child process: {}", e)))?;
+    let wait_error = |e| ProcessWrapperError(format!("failed to wait for child process: {}", e));
+    let status = match &opts.resource_report {
+        Some(resource_report) => {
+            let (status, usage) = resources::wait(&mut child).map_err(wait_error)?;
+            fs::write(resource_report, usage.to_json(start_time.elapsed())).map_err(|e| {
+                ProcessWrapperError(format!(
+                    "failed to write resource report {}: {}",
+                    resource_report, e
+                ))
+            })?;
+            status
+        }
+        None => child.wait().map_err(wait_error)?,
+    };
+    signals::set_child(None);
+    if let (Some(watchdog), Some(timeout)) = (watchdog, opts.timeout) {
+        if watchdog.stop() {
+            return Err(ProcessWrapperError(format!(
+                "{} timed out after {} seconds and was terminated along with its subprocesses",
+                executable,
+                timeout.as_secs()
+            )));
+        }
+    }
+    if let Some(stdout_tee) = stdout_tee {
+        stdout_tee
+            .join()
+            .map_err(|_| ProcessWrapperError("failed to copy stdout".to_owned()))?
+            .map_err(|e| ProcessWrapperError(format!("failed to copy stdout: {}", e)))?;
+    }
     // If the child process is rustc and is killed after metadata generation, that's also a success.
-    let code = status_code(status, was_killed);
+    let mut code = status_code(status, was_killed);
+    if code == 0 && denied_warnings > 0 {
+        code = 1;
+    }
+    if let Some((output, start, args, env)) = ice_report {
+        let crashed = ice::capture(&output, code, start, &executable, &args, &env)
+            .map_err(|e| ProcessWrapperError(format!("failed to write {}: {}", output, e)))?;
+        if crashed {
+            writeln!(
+                stderr,
+                "process wrapper: {} crashed with an internal compiler error, its diagnostics \
+                were written to {}",
+                executable, output
+            )
+            .map_err(|e| ProcessWrapperError(format!("failed to write to stderr: {}", e)))?;
+        }
+    }
+    if code == 0 {
+        if let Some(missing) = opts
+            .expected_outputs
+            .iter()
+            .find(|output| !Path::new(output).exists())
+        {
+            return Err(ProcessWrapperError(format!(
+                "{} succeeded but didn't create the expected output {}",
+                executable, missing
+            )));
+        }
+    }
     let success = code == 0;
     if success {
         if let Some(tf) = opts.touch_file {
@@ -176,15 +463,35 @@ fn main() -> Result<(), ProcessWrapperError> {
                 .open(tf)
                 .map_err(|e| ProcessWrapperError(format!("failed to create touch file: {}", e)))?;
         }
+        if let (Some(llvm_profile_dir), Some(llvm_profile_output)) =
+            (&opts.llvm_profile_dir, &opts.llvm_profile_output)
+        {
+            coverage::collect_profiles(llvm_profile_dir, llvm_profile_output).map_err(|e| {
+                ProcessWrapperError(format!(
+                    "failed to collect coverage profiles into {}: {}",
+                    llvm_profile_output, e
+                ))
+            })?;
+        }
         if let Some((copy_source, copy_dest)) = opts.copy_output {
-            copy(&copy_source, &copy_dest).map_err(|e| {
+            copy::copy_file(
+                Path::new(&copy_source),
+                Path::new(&copy_dest),
+                opts.copy_mode,
+            )
+            .map_err(|e| {
                 ProcessWrapperError(format!(
                     "failed to copy {} into {}: {}",
                     copy_source, copy_dest, e
                 ))
             })?;
         }
+        for output in &opts.normalized_outputs {
+            normalize::normalize(Path::new(output), normalize::normalized_time()).map_err(|e| {
+                ProcessWrapperError(format!("failed to normalize {}: {}", output, e))
+            })?;
+        }
     }
 
-    exit(code)
+    Ok(code)
 }
@@ -0,0 +1,219 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Normalization of the metadata of outputs, so archiving or packaging them downstream doesn't
+//! depend on when and where they were built.
+
+use std::fs::{self, File, FileTimes};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// The timestamp outputs get, 1980-01-01 00:00:00 UTC, as zip archives can't represent
+/// earlier ones.
+const NORMALIZED_TIME_SECS: u64 = 315_532_800;
+
+/// Sets the access and modification times of `path` to `time`.
+#[cfg(unix)]
+fn set_times(path: &Path, time: SystemTime, _is_dir: bool) -> io::Result<()> {
+    // Setting the times of a file only requires owning it, not write access.
+    File::open(path)?.set_times(FileTimes::new().set_accessed(time).set_modified(time))
+}
+
+/// Sets the access and modification times of `path` to `time`. Directories, which can't be
+/// opened as files, are skipped.
+#[cfg(windows)]
+fn set_times(path: &Path, time: SystemTime, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        return Ok(());
+    }
+    File::options()
+        .write(true)
+        .open(path)?
+        .set_times(FileTimes::new().set_accessed(time).set_modified(time))
+}
+
+/// Removes the extended attributes of `path`, e.g. `com.apple.quarantine` or
+/// `security.selinux`, without following symlinks. Attributes which can't be removed, such as
+/// those managed by the system, are kept.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn strip_xattrs(path: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int};
+    use std::os::unix::ffi::OsStrExt;
+
+    #[cfg(target_os = "linux")]
+    extern "C" {
+        fn llistxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize;
+        fn lremovexattr(path: *const c_char, name: *const c_char) -> c_int;
+    }
+    #[cfg(target_os = "macos")]
+    extern "C" {
+        fn listxattr(path: *const c_char, list: *mut c_char, size: usize, options: c_int) -> isize;
+        fn removexattr(path: *const c_char, name: *const c_char, options: c_int) -> c_int;
+    }
+    #[cfg(target_os = "macos")]
+    const XATTR_NOFOLLOW: c_int = 1;
+    // The error of file systems not supporting extended attributes, `ENOTSUP`.
+    #[cfg(target_os = "linux")]
+    const ENOTSUP: i32 = 95;
+    #[cfg(target_os = "macos")]
+    const ENOTSUP: i32 = 45;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // Safe as the pointers are valid for the duration of the calls.
+    #[cfg(target_os = "linux")]
+    let list = |buf: *mut c_char, size| unsafe { llistxattr(path.as_ptr(), buf, size) };
+    #[cfg(target_os = "macos")]
+    let list =
+        |buf: *mut c_char, size| unsafe { listxattr(path.as_ptr(), buf, size, XATTR_NOFOLLOW) };
+    #[cfg(target_os = "linux")]
+    let remove = |name: *const c_char| unsafe { lremovexattr(path.as_ptr(), name) };
+    #[cfg(target_os = "macos")]
+    let remove = |name: *const c_char| unsafe { removexattr(path.as_ptr(), name, XATTR_NOFOLLOW) };
+
+    // The list is a sequence of nul terminated names. Its size is queried first.
+    let size = list(std::ptr::null_mut(), 0);
+    if size < 0 {
+        let error = io::Error::last_os_error();
+        // The file system doesn't support extended attributes.
+        return match error.raw_os_error() {
+            Some(ENOTSUP) => Ok(()),
+            _ => Err(error),
+        };
+    }
+    let mut names = vec![0u8; size as usize];
+    let size = list(names.as_mut_ptr() as *mut c_char, names.len());
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    names.truncate(size as usize);
+    for name in names.split(|b| *b == 0).filter(|name| !name.is_empty()) {
+        // The name was split at nuls, so it doesn't contain any.
+        let name = CString::new(name).unwrap();
+        remove(name.as_ptr());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn strip_xattrs(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Normalizes the timestamps and strips the extended attributes of `path` and, if it's a
+/// directory, everything below it. Symlinks are left alone.
+pub(crate) fn normalize(path: &Path, time: SystemTime) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            normalize(&entry?.path(), time)?;
+        }
+    }
+    strip_xattrs(path)?;
+    // The times of directories are set last, as changing their entries updates them.
+    set_times(path, time, metadata.is_dir())
+}
+
+/// Returns the timestamp outputs are normalized to.
+pub(crate) fn normalized_time() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(NORMALIZED_TIME_SECS)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_normalize() {
+        let dir = env::var_os("TEST_TMPDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(format!("process_wrapper_normalize_{}", std::process::id()));
+        fs::create_dir_all(dir.join("tree/nested")).unwrap();
+        fs::write(dir.join("tree/a.txt"), "a").unwrap();
+        fs::write(dir.join("tree/nested/b.txt"), "b").unwrap();
+        fs::write(dir.join("c.txt"), "c").unwrap();
+
+        normalize(&dir.join("tree"), normalized_time()).unwrap();
+        let modified = |path: &str| fs::metadata(dir.join(path)).unwrap().modified().unwrap();
+        assert_eq!(modified("tree/a.txt"), normalized_time());
+        assert_eq!(modified("tree/nested/b.txt"), normalized_time());
+        if cfg!(unix) {
+            assert_eq!(modified("tree"), normalized_time());
+            assert_eq!(modified("tree/nested"), normalized_time());
+        }
+        assert_ne!(modified("c.txt"), normalized_time());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_strip_xattrs() {
+        use std::ffi::CString;
+        use std::os::raw::{c_char, c_int, c_void};
+        use std::os::unix::ffi::OsStrExt;
+
+        extern "C" {
+            fn setxattr(
+                path: *const c_char,
+                name: *const c_char,
+                value: *const c_void,
+                size: usize,
+                flags: c_int,
+            ) -> c_int;
+            fn getxattr(
+                path: *const c_char,
+                name: *const c_char,
+                value: *mut c_void,
+                size: usize,
+            ) -> isize;
+        }
+
+        let path = env::var_os("TEST_TMPDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(format!("process_wrapper_xattrs_{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let name = CString::new("user.process_wrapper").unwrap();
+        let value = b"value";
+        if unsafe {
+            setxattr(
+                c_path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                0,
+            )
+        } != 0
+        {
+            // The file system doesn't support user extended attributes.
+            fs::remove_file(path).unwrap();
+            return;
+        }
+
+        strip_xattrs(&path).unwrap();
+        let size = unsafe { getxattr(c_path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        assert_eq!(size, -1);
+        fs::remove_file(path).unwrap();
+    }
+}
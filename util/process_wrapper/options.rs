@@ -1,14 +1,22 @@
 use std::collections::HashMap;
 use std::env;
 use std::fmt;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Write};
+use std::path::Path;
 use std::process::exit;
+use std::time::Duration;
 
+use crate::copy::CopyMode;
+use crate::coverage;
 use crate::flags::{FlagParseError, Flags, ParseOutcome};
+use crate::incremental;
+use crate::pattern::Pattern;
 use crate::rustc;
 use crate::util::*;
 
+use tinyjson::JsonValue;
+
 #[derive(Debug)]
 pub(crate) enum OptionError {
     FlagError(FlagParseError),
@@ -24,7 +32,7 @@ impl fmt::Display for OptionError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Options {
     // Contains the path to the child executable
     pub(crate) executable: String,
@@ -32,42 +40,135 @@ pub(crate) struct Options {
     pub(crate) child_arguments: Vec<String>,
     // Contains environment variables for the child process fetched from files.
     pub(crate) child_environment: HashMap<String, String>,
+    // Files the child process must create, or it's considered to have failed.
+    pub(crate) expected_outputs: Vec<String>,
     // If set, create the specified file after the child process successfully
     // terminated its execution.
     pub(crate) touch_file: Option<String>,
     // If set to (source, dest) copies the source file to dest.
     pub(crate) copy_output: Option<(String, String)>,
+    // How copy_output copies the file.
+    pub(crate) copy_mode: CopyMode,
     // If set, redirects the child process stdout to this file.
     pub(crate) stdout_file: Option<String>,
     // If set, redirects the child process stderr to this file.
     pub(crate) stderr_file: Option<String>,
+    // If set, the output of the child process is written to stdout and stderr as well as to
+    // stdout_file and stderr_file.
+    pub(crate) tee_output: bool,
+    // If set, whether the child process renders colors. Unless rustc_output_format is set, it's
+    // passed on to the child process as `--color`. ANSI escape sequences are then always removed
+    // from stderr_file, and from stderr without colors.
+    pub(crate) color: Option<bool>,
     // If set, also logs all unprocessed output from the rustc output to this file.
     // Meant to be used to get json output out of rustc for tooling usage.
     pub(crate) output_file: Option<String>,
+    // If set, the child process and the processes it spawned are terminated if it runs for
+    // longer than this.
+    pub(crate) timeout: Option<Duration>,
+    // If enabled, termination requests of the process wrapper are forwarded to the child process.
+    pub(crate) forward_signals: bool,
+    // How long the child process has to exit after the process wrapper is asked to terminate,
+    // before the processes it spawned are forcefully terminated.
+    pub(crate) shutdown_grace_period: Duration,
+    // If set, the child process is run again, up to retry_count times, when it fails and a
+    // line of its stderr matches this pattern.
+    pub(crate) retry_pattern: Option<Pattern>,
+    pub(crate) retry_count: usize,
+    // Outputs, files or directories, whose timestamps are normalized and whose extended
+    // attributes are removed after the child process succeeded.
+    pub(crate) normalized_outputs: Vec<String>,
+    // If set, a json report of the resources used by the child process is written to this file.
+    pub(crate) resource_report: Option<String>,
+    // If set, the raw coverage profiles of instrumented programs run by the child process are
+    // written to this directory instead of wherever the environment tells them to.
+    pub(crate) llvm_profile_dir: Option<String>,
+    // If set, the raw coverage profiles are concatenated into this file.
+    pub(crate) llvm_profile_output: Option<String>,
     // If set, it configures rustc to emit an rmeta file and then
     // quit.
     pub(crate) rustc_quit_on_rmeta: bool,
+    // The flavor of the rustc action with pipelined compilation. The full action
+    // skips the diagnostics the metadata action reports.
+    pub(crate) rustc_action_flavor: Option<rustc::ActionFlavor>,
+    // If set, this file is created as soon as rustc emitted metadata.
+    pub(crate) rustc_rmeta_sentinel: Option<String>,
+    // If set, the json diagnostics of rustc, with rewritten paths, are written to this file
+    // regardless of rustc_output_format.
+    pub(crate) rustc_json_output: Option<String>,
+    // If set, the diagnostics of internal compiler errors of rustc are written to this file,
+    // which is created empty otherwise.
+    pub(crate) rustc_ice_report: Option<String>,
+    // If set, rustc fails if it reports warnings other than those allowed by allowed_warnings.
+    pub(crate) deny_warnings: bool,
+    // Lint names and path prefixes of the warnings allowed by deny_warnings.
+    pub(crate) allowed_warnings: Vec<String>,
     // This controls the output format of rustc messages.
     pub(crate) rustc_output_format: Option<rustc::ErrorFormat>,
+    // The directory the child process runs in, i.e. the exec root.
+    pub(crate) exec_root: String,
 }
 
 pub(crate) fn options() -> Result<Options, OptionError> {
+    options_from_args(env::args().collect())
+}
+
+/// Parses the options of the process wrapper from `args`, where the first argument is the
+/// program name.
+pub(crate) fn options_from_args(args: Vec<String>) -> Result<Options, OptionError> {
     // Process argument list until -- is encountered.
     // Everything after is sent to the child process.
     let mut subst_mapping_raw = None;
+    let mut out_dir_raw = None;
     let mut stable_status_file_raw = None;
     let mut volatile_status_file_raw = None;
     let mut env_file_raw = None;
     let mut arg_file_raw = None;
+    let mut inherit_env_raw = None;
+    let mut expected_outputs_raw = None;
+    // Expanded by `expand_wrapper_param_files`, it's only defined for its help.
+    let mut config_file_raw = None;
+    let mut param_file_format_raw = None;
     let mut touch_file = None;
     let mut copy_output_raw = None;
+    let mut copy_mode_raw = None;
     let mut stdout_file = None;
     let mut stderr_file = None;
+    let mut tee_output_raw = None;
+    let mut color_raw = None;
+    let mut timeout_raw = None;
+    let mut forward_signals_raw = None;
+    let mut shutdown_grace_period_raw = None;
+    let mut retry_pattern_raw = None;
+    let mut retry_count_raw = None;
+    let mut resource_report_raw = None;
+    let mut normalized_outputs_raw = None;
+    let mut rustc_ice_report_raw = None;
+    let mut llvm_profile_dir_raw = None;
+    let mut llvm_profile_output_raw = None;
     let mut output_file = None;
     let mut rustc_quit_on_rmeta_raw = None;
+    let mut rustc_action_flavor_raw = None;
+    let mut rustc_rmeta_sentinel_raw = None;
+    let mut rustc_json_output_raw = None;
+    let mut rustc_remap_path_prefixes_raw = None;
+    let mut deny_warnings_raw = None;
+    let mut allowed_warnings_raw = None;
+    let mut rustc_incremental_dir_raw = None;
     let mut rustc_output_format_raw = None;
     let mut flags = Flags::new();
-    flags.define_repeated_flag("--subst", "", &mut subst_mapping_raw);
+    flags.define_repeated_flag(
+        "--subst",
+        "A `key=value` pair replacing `${key}` in arguments and environment variables of the \
+        child process. Values may use the builtin substitutions `${pwd}`, `${exec_root}`, \
+        `${runfiles}` and `${out_dir}`, which are also available directly. `$${` is a literal `${`.",
+        &mut subst_mapping_raw,
+    );
+    flags.define_flag(
+        "--out-dir",
+        "The output directory of the action, substituted for `${out_dir}`.",
+        &mut out_dir_raw,
+    );
     flags.define_flag("--stable-status-file", "", &mut stable_status_file_raw);
     flags.define_flag("--volatile-status-file", "", &mut volatile_status_file_raw);
     flags.define_repeated_flag(
@@ -80,12 +181,45 @@ pub(crate) fn options() -> Result<Options, OptionError> {
         "File(s) containing command line arguments to pass to the child process.",
         &mut arg_file_raw,
     );
+    flags.define_repeated_flag(
+        "--inherit-env",
+        "If given, only these environment variables of the process wrapper are passed to the \
+        child process, in addition to those of --env-file. Otherwise all of them are.",
+        &mut inherit_env_raw,
+    );
+    flags.define_repeated_flag(
+        "--expect-output",
+        "A file the child process must create, or the process wrapper fails.",
+        &mut expected_outputs_raw,
+    );
+    flags.define_flag(
+        "--config-file",
+        "A json file containing flags of the process wrapper, e.g. \
+        `{\"subst\": {\"key\": \"value\"}, \"inherit-env\": [\"PATH\"], \"tee-output\": true}`, \
+        for wrapping tools other than rustc.",
+        &mut config_file_raw,
+    );
+    flags.define_flag(
+        "--param-file-format",
+        "The format of the param files of the process wrapper and of --arg-file: multiline, \
+        with one argument per line, or shell, with arguments quoted like in POSIX shells. It \
+        applies to the param files of the process wrapper which follow it.\n\
+        Default: multiline",
+        &mut param_file_format_raw,
+    );
     flags.define_flag(
         "--touch-file",
         "Create this file after the child process runs successfully.",
         &mut touch_file,
     );
     flags.define_repeated_flag("--copy-output", "", &mut copy_output_raw);
+    flags.define_flag(
+        "--copy-mode",
+        "How --copy-output copies the file: copy, hardlink or reflink. Hardlinks and reflinks \
+        fall back to copying if the file system doesn't support them.\n\
+        Default: copy",
+        &mut copy_mode_raw,
+    );
     flags.define_flag(
         "--stdout-file",
         "Redirect subprocess stdout in this file.",
@@ -96,16 +230,137 @@ pub(crate) fn options() -> Result<Options, OptionError> {
         "Redirect subprocess stderr in this file.",
         &mut stderr_file,
     );
+    flags.define_flag(
+        "--tee-output",
+        "If enabled, --stdout-file and --stderr-file capture the subprocess output while it's \
+        still written to stdout and stderr.",
+        &mut tee_output_raw,
+    );
+    flags.define_flag(
+        "--color",
+        "Whether the subprocess renders colors, one of 'always' or 'never'. Unless \
+        --rustc-output-format is set, it's passed on to the subprocess as --color, otherwise \
+        the colors of rendered diagnostics are requested with rustc's --json flag. If set, \
+        colors are removed from --stderr-file, and from stderr with 'never'.",
+        &mut color_raw,
+    );
     flags.define_flag(
         "--output-file",
         "Log all unprocessed subprocess stderr in this file.",
         &mut output_file,
     );
+    flags.define_flag(
+        "--timeout",
+        "Terminate the subprocess and the processes it spawned after this many seconds.",
+        &mut timeout_raw,
+    );
+    flags.define_flag(
+        "--forward-signals",
+        "If enabled, SIGTERM and SIGINT are forwarded to the subprocess and the processes it \
+        spawned, which run in a process group of their own.",
+        &mut forward_signals_raw,
+    );
+    flags.define_flag(
+        "--shutdown-grace-period",
+        "The number of seconds the subprocess has to exit after SIGTERM or SIGINT are forwarded \
+        to it, before it's terminated along with the processes it spawned. Requires \
+        --forward-signals.\n\
+        Default: 5",
+        &mut shutdown_grace_period_raw,
+    );
+    flags.define_flag(
+        "--retry-pattern",
+        "Run the subprocess again if it fails and a line of its stderr matches this regular \
+        expression, e.g. for transient linker errors.",
+        &mut retry_pattern_raw,
+    );
+    flags.define_flag(
+        "--retry-count",
+        "The number of times to retry the subprocess with --retry-pattern.\n\
+        Default: 1",
+        &mut retry_count_raw,
+    );
+    flags.define_repeated_flag(
+        "--normalize-output",
+        "An output, file or directory, whose modification and access times are set to \
+        1980-01-01 and whose extended attributes are removed if the subprocess succeeds, \
+        after --copy-output.",
+        &mut normalized_outputs_raw,
+    );
+    flags.define_flag(
+        "--resource-report",
+        "Writes the maximum resident set size, the user and system CPU time and the wall time \
+        of the subprocess to this file as json.",
+        &mut resource_report_raw,
+    );
+    flags.define_flag(
+        "--llvm-profile-dir",
+        "Sets LLVM_PROFILE_FILE so instrumented programs run by the subprocess write their raw \
+        coverage profiles to this directory, which is created and emptied first.",
+        &mut llvm_profile_dir_raw,
+    );
+    flags.define_flag(
+        "--llvm-profile-output",
+        "Concatenates the raw coverage profiles of --llvm-profile-dir into this file \
+        if the subprocess succeeds.",
+        &mut llvm_profile_output_raw,
+    );
     flags.define_flag(
         "--rustc-quit-on-rmeta",
         "If enabled, this wrapper will terminate rustc after rmeta has been emitted.",
         &mut rustc_quit_on_rmeta_raw,
     );
+    flags.define_flag(
+        "--rustc-action-flavor",
+        "The flavor of the rustc action with pipelined compilation, either 'metadata' or \
+        'full'. The full action doesn't report the diagnostics other than errors which rustc \
+        reports before emitting metadata, as the metadata action reports them. They are still \
+        written to --rustc-json-output. Requires --rustc-output-format.",
+        &mut rustc_action_flavor_raw,
+    );
+    flags.define_flag(
+        "--rustc-rmeta-sentinel",
+        "Create this file as soon as rustc emitted rmeta, rather than once it exits, so \
+        dependents may start compiling. Requires --rustc-output-format.",
+        &mut rustc_rmeta_sentinel_raw,
+    );
+    flags.define_flag(
+        "--rustc-json-output",
+        "Write the json diagnostics of rustc, with paths rewritten like those printed, to this \
+        file, e.g. for IDEs, while printing them in --rustc-output-format. \
+        Requires --rustc-output-format.",
+        &mut rustc_json_output_raw,
+    );
+    flags.define_flag(
+        "--rustc-ice-report",
+        "If rustc crashes with an internal compiler error, write its command line, environment \
+        and rustc-ice-*.txt reports to this file, which is created empty otherwise.",
+        &mut rustc_ice_report_raw,
+    );
+    flags.define_flag(
+        "--deny-warnings",
+        "If enabled, rustc fails if it reports any warnings not allowed by --allow-warning. \
+        Requires --rustc-output-format.",
+        &mut deny_warnings_raw,
+    );
+    flags.define_repeated_flag(
+        "--allow-warning",
+        "A lint name, e.g. `dead_code`, or a path prefix containing a `/`, e.g. `src/legacy/`, \
+        of warnings which --deny-warnings allows.",
+        &mut allowed_warnings_raw,
+    );
+    flags.define_flag(
+        "--rustc-remap-path-prefixes",
+        "If enabled, rustc is passed --remap-path-prefix flags for the exec root, the output \
+        base and the home directory, so its outputs don't depend on the machine.",
+        &mut rustc_remap_path_prefixes_raw,
+    );
+    flags.define_flag(
+        "--rustc-incremental-dir",
+        "An absolute directory in which a persistent incremental compilation directory is \
+        managed for each crate and configuration. Directories unused for a week are removed.",
+        &mut rustc_incremental_dir_raw,
+    );
     flags.define_flag(
         "--rustc-output-format",
         "Controls the rustc output format if --rustc-quit-on-rmeta is set.\n\
@@ -116,7 +371,7 @@ pub(crate) fn options() -> Result<Options, OptionError> {
     );
 
     let mut child_args = match flags
-        .parse(env::args().collect())
+        .parse(expand_wrapper_param_files(args)?)
         .map_err(OptionError::FlagError)?
     {
         ParseOutcome::Help(help) => {
@@ -130,6 +385,24 @@ pub(crate) fn options() -> Result<Options, OptionError> {
         .to_str()
         .ok_or_else(|| OptionError::Generic("current directory not utf-8".to_owned()))?
         .to_owned();
+    // The builtin substitutions, which the values of `--subst` may use.
+    let mut builtin_mappings = vec![
+        ("pwd".to_owned(), current_dir.clone()),
+        ("exec_root".to_owned(), current_dir.clone()),
+    ];
+    if let Some(out_dir) = out_dir_raw {
+        builtin_mappings.push(("out_dir".to_owned(), absolute_path(&current_dir, &out_dir)));
+    }
+    let runfiles_dir = env::var("RUNFILES_DIR").ok().or_else(|| {
+        // Otherwise the runfiles of the child process.
+        child_args.first().map(|executable| {
+            let executable = substitute(executable, &builtin_mappings);
+            absolute_path(&current_dir, &format!("{executable}.runfiles"))
+        })
+    });
+    if let Some(runfiles_dir) = runfiles_dir {
+        builtin_mappings.push(("runfiles".to_owned(), runfiles_dir));
+    }
     let subst_mappings = subst_mapping_raw
         .unwrap_or_default()
         .into_iter()
@@ -137,21 +410,29 @@ pub(crate) fn options() -> Result<Options, OptionError> {
             let (key, val) = arg.split_once('=').ok_or_else(|| {
                 OptionError::Generic(format!("empty key for substitution '{arg}'"))
             })?;
-            let v = if val == "${pwd}" {
-                current_dir.as_str()
-            } else {
-                val
-            }
-            .to_owned();
-            Ok((key.to_owned(), v))
+            Ok((key.to_owned(), substitute(val, &builtin_mappings)))
         })
         .collect::<Result<Vec<(String, String)>, OptionError>>()?;
-    let stable_stamp_mappings =
-        stable_status_file_raw.map_or_else(Vec::new, |s| read_stamp_status_to_array(s).unwrap());
-    let volatile_stamp_mappings =
-        volatile_status_file_raw.map_or_else(Vec::new, |s| read_stamp_status_to_array(s).unwrap());
+    // Mappings given with `--subst` take precedence over the builtin ones.
+    let subst_mappings: Vec<(String, String)> =
+        builtin_mappings.into_iter().chain(subst_mappings).collect();
+    // Workspace status keys are substituted as `{KEY}`, volatile values after stable ones.
+    let stamp_mappings = [stable_status_file_raw, volatile_status_file_raw]
+        .iter()
+        .flatten()
+        .map(|path| {
+            read_stamp_status_to_array(path.clone()).map_err(|e| {
+                OptionError::Generic(format!("failed to read workspace status file {path}: {e}"))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .concat();
     let environment_file_block = env_from_files(env_file_raw.unwrap_or_default())?;
-    let mut file_arguments = args_from_file(arg_file_raw.unwrap_or_default())?;
+    let param_file_format = param_file_format_raw
+        .map(|v| parse_param_file_format(&v))
+        .transpose()?
+        .unwrap_or(ParamFileFormat::Multiline);
+    let mut file_arguments = args_from_file(arg_file_raw.unwrap_or_default(), param_file_format)?;
     // Process --copy-output
     let copy_output = copy_output_raw
         .map(|co| {
@@ -171,8 +452,88 @@ pub(crate) fn options() -> Result<Options, OptionError> {
             Ok((copy_source.to_owned(), copy_dest.to_owned()))
         })
         .transpose()?;
+    let copy_mode = copy_mode_raw
+        .map(|v| {
+            CopyMode::parse(&v)
+                .ok_or_else(|| OptionError::Generic(format!("invalid --copy-mode '{v}'")))
+        })
+        .transpose()?
+        .unwrap_or(CopyMode::Copy);
 
+    let tee_output = tee_output_raw.is_some_and(|s| s == "true");
+    let color = color_raw
+        .map(|v| match v.as_str() {
+            "always" => Ok(true),
+            "never" => Ok(false),
+            _ => Err(OptionError::Generic(format!("invalid --color '{v}'"))),
+        })
+        .transpose()?;
+    let timeout = timeout_raw
+        .map(|v| {
+            v.parse::<u64>()
+                .map(Duration::from_secs)
+                .map_err(|_| OptionError::Generic(format!("invalid --timeout '{v}'")))
+        })
+        .transpose()?;
+    let forward_signals = forward_signals_raw.is_some_and(|s| s == "true");
+    if shutdown_grace_period_raw.is_some() && !forward_signals {
+        return Err(OptionError::Generic(
+            "--shutdown-grace-period requires --forward-signals".to_owned(),
+        ));
+    }
+    let shutdown_grace_period = shutdown_grace_period_raw
+        .map(|v| {
+            v.parse::<u64>()
+                .map(Duration::from_secs)
+                .map_err(|_| OptionError::Generic(format!("invalid --shutdown-grace-period '{v}'")))
+        })
+        .transpose()?
+        .unwrap_or(Duration::from_secs(5));
+    let retry_pattern = retry_pattern_raw
+        .map(|v| {
+            Pattern::new(&v)
+                .map_err(|e| OptionError::Generic(format!("invalid --retry-pattern '{v}': {e}")))
+        })
+        .transpose()?;
+    let retry_count = retry_count_raw
+        .map(|v| {
+            v.parse::<usize>()
+                .map_err(|_| OptionError::Generic(format!("invalid --retry-count '{v}'")))
+        })
+        .transpose()?
+        .unwrap_or(1);
     let rustc_quit_on_rmeta = rustc_quit_on_rmeta_raw.is_some_and(|s| s == "true");
+    if rustc_action_flavor_raw.is_some() && rustc_output_format_raw.is_none() {
+        return Err(OptionError::Generic(
+            "--rustc-action-flavor requires --rustc-output-format".to_owned(),
+        ));
+    }
+    let rustc_action_flavor = rustc_action_flavor_raw
+        .map(|flavor| match flavor.as_str() {
+            "metadata" => Ok(rustc::ActionFlavor::Metadata),
+            "full" => Ok(rustc::ActionFlavor::Full),
+            _ => Err(OptionError::Generic(format!(
+                "invalid --rustc-action-flavor '{flavor}'. Must be 'metadata' or 'full'."
+            ))),
+        })
+        .transpose()?;
+    let deny_warnings = deny_warnings_raw.is_some_and(|s| s == "true");
+    if deny_warnings && rustc_output_format_raw.is_none() {
+        return Err(OptionError::Generic(
+            "--deny-warnings requires --rustc-output-format".to_owned(),
+        ));
+    }
+    if rustc_rmeta_sentinel_raw.is_some() && rustc_output_format_raw.is_none() {
+        return Err(OptionError::Generic(
+            "--rustc-rmeta-sentinel requires --rustc-output-format".to_owned(),
+        ));
+    }
+    if rustc_json_output_raw.is_some() && rustc_output_format_raw.is_none() {
+        return Err(OptionError::Generic(
+            "--rustc-json-output requires --rustc-output-format".to_owned(),
+        ));
+    }
+    let rustc_remap_path_prefixes = rustc_remap_path_prefixes_raw.is_some_and(|s| s == "true");
     let rustc_output_format = rustc_output_format_raw
         .map(|v| match v.as_str() {
             "json" => Ok(rustc::ErrorFormat::Json),
@@ -185,40 +546,209 @@ pub(crate) fn options() -> Result<Options, OptionError> {
 
     // Prepare the environment variables, unifying those read from files with the ones
     // of the current process.
-    let vars = environment_block(
+    let mut vars = environment_block(
         environment_file_block,
-        &stable_stamp_mappings,
-        &volatile_stamp_mappings,
+        inherit_env_raw.as_deref(),
+        &stamp_mappings,
         &subst_mappings,
     );
+    // The child process may change its working directory, so the profile directory is absolute.
+    let llvm_profile_dir = llvm_profile_dir_raw.map(|dir| absolute_path(&current_dir, &dir));
+    if let Some(llvm_profile_dir) = &llvm_profile_dir {
+        vars.insert(
+            "LLVM_PROFILE_FILE".to_owned(),
+            coverage::llvm_profile_file(llvm_profile_dir),
+        );
+    } else if llvm_profile_output_raw.is_some() {
+        return Err(OptionError::Generic(
+            "--llvm-profile-output requires --llvm-profile-dir".to_owned(),
+        ));
+    }
     // Append all the arguments fetched from files to those provided via command line.
     child_args.append(&mut file_arguments);
-    let child_args = prepare_args(child_args, &subst_mappings)?;
+    let child_args = prepare_args(child_args, &stamp_mappings, &subst_mappings)?;
     // Split the executable path from the rest of the arguments.
     let (exec_path, args) = child_args.split_first().ok_or_else(|| {
         OptionError::Generic(
             "at least one argument after -- is required (the child process path)".to_owned(),
         )
     })?;
+    // The flags are passed first, so mappings passed explicitly take precedence.
+    let mut child_arguments = Vec::new();
+    if rustc_remap_path_prefixes {
+        let home = env::var(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).ok();
+        child_arguments = rustc::remap_path_prefix_flags(&current_dir, home.as_deref());
+    }
+    child_arguments.extend_from_slice(args);
+    // Rendered json diagnostics are colored through the `--json` flag instead.
+    if let (Some(color), None) = (color, rustc_output_format) {
+        child_arguments.push(format!(
+            "--color={}",
+            if color { "always" } else { "never" }
+        ));
+    }
+    if let Some(base) = rustc_incremental_dir_raw {
+        // The crate is identified by arguments which may be in (already expanded) param files.
+        let rustc_args = args
+            .iter()
+            .map(|arg| match arg.strip_prefix('@') {
+                Some(param_file) => read_file_to_array(param_file),
+                None => Ok(vec![arg.clone()]),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(OptionError::Generic)?
+            .concat();
+        let flag = incremental::incremental_flag(&base, &rustc_args, rustc_action_flavor)
+            .map_err(OptionError::Generic)?;
+        child_arguments.push(flag);
+    }
 
     Ok(Options {
         executable: exec_path.to_owned(),
-        child_arguments: args.to_vec(),
+        child_arguments,
         child_environment: vars,
+        expected_outputs: expected_outputs_raw.unwrap_or_default(),
         touch_file,
         copy_output,
+        copy_mode,
         stdout_file,
         stderr_file,
+        tee_output,
+        color,
         output_file,
+        timeout,
+        forward_signals,
+        shutdown_grace_period,
+        retry_pattern,
+        retry_count,
+        normalized_outputs: normalized_outputs_raw.unwrap_or_default(),
+        resource_report: resource_report_raw,
+        llvm_profile_dir,
+        llvm_profile_output: llvm_profile_output_raw,
         rustc_quit_on_rmeta,
+        rustc_action_flavor,
+        rustc_rmeta_sentinel: rustc_rmeta_sentinel_raw,
+        rustc_json_output: rustc_json_output_raw,
+        rustc_ice_report: rustc_ice_report_raw,
+        deny_warnings,
+        allowed_warnings: allowed_warnings_raw.unwrap_or_default(),
         rustc_output_format,
+        exec_root: current_dir,
     })
 }
 
-fn args_from_file(paths: Vec<String>) -> Result<Vec<String>, OptionError> {
+/// Expands the param files among the arguments of the process wrapper itself, i.e. those before
+/// `--`, as Bazel requires all arguments of actions run by persistent workers to be passed in
+/// param files. Param files of the child process are left as is.
+fn expand_wrapper_param_files(args: Vec<String>) -> Result<Vec<String>, OptionError> {
+    fn expand(
+        args: Vec<String>,
+        expanded: &mut Vec<String>,
+        in_child_args: &mut bool,
+        format: &mut ParamFileFormat,
+    ) -> Result<(), OptionError> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if *in_child_args || expanded.is_empty() {
+                expanded.push(arg);
+            } else if arg == "--" {
+                *in_child_args = true;
+                expanded.push(arg);
+            } else if let Some(param_file) = arg.strip_prefix('@') {
+                let lines =
+                    read_param_file_to_array(param_file, *format).map_err(OptionError::Generic)?;
+                expand(lines, expanded, in_child_args, format)?;
+            } else if arg == "--param-file-format" {
+                // Kept for the flag parser, which applies it to --arg-file.
+                let value = args.next().ok_or_else(|| {
+                    OptionError::FlagError(FlagParseError::ValueMissing(arg.clone()))
+                })?;
+                *format = parse_param_file_format(&value)?;
+                expanded.push(arg);
+                expanded.push(value);
+            } else if arg == "--config-file" {
+                let config_file = args.next().ok_or_else(|| {
+                    OptionError::FlagError(FlagParseError::ValueMissing(arg.clone()))
+                })?;
+                expanded.extend(args_from_config_file(&config_file)?);
+            } else {
+                expanded.push(arg);
+            }
+        }
+        Ok(())
+    }
+
+    let mut expanded = Vec::with_capacity(args.len());
+    expand(
+        args,
+        &mut expanded,
+        &mut false,
+        &mut ParamFileFormat::Multiline,
+    )?;
+    Ok(expanded)
+}
+
+/// Reads the flags of the process wrapper from the json object in `path`, whose keys are flag
+/// names without the leading `--`. Strings are the values of flags, arrays those of repeated
+/// flags and objects those of repeated `key=value` flags such as `subst`, while `true` enables
+/// boolean flags. For example `{"subst": {"pwd": "${pwd}"}, "expect-output": ["out"]}`.
+fn args_from_config_file(path: &str) -> Result<Vec<String>, OptionError> {
+    let error = |e: String| OptionError::Generic(format!("invalid config file {path}: {e}"));
+    let config: JsonValue = fs::read_to_string(path)
+        .map_err(|e| error(e.to_string()))?
+        .parse()
+        .map_err(|e: tinyjson::JsonParseError| error(e.to_string()))?;
+    let JsonValue::Object(config) = config else {
+        return Err(error("expected an object".to_owned()));
+    };
+    // The order of the keys doesn't matter, but sorting them keeps the arguments stable.
+    let mut config: Vec<(String, JsonValue)> = config.into_iter().collect();
+    config.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut args = vec![];
+    for (key, value) in config {
+        let flag = format!("--{key}");
+        let values = match value {
+            JsonValue::String(value) => vec![value],
+            JsonValue::Boolean(true) => vec!["true".to_owned()],
+            JsonValue::Boolean(false) => vec![],
+            JsonValue::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    JsonValue::String(value) => Ok(value),
+                    _ => Err(error(format!("expected strings in {key}"))),
+                })
+                .collect::<Result<_, _>>()?,
+            JsonValue::Object(values) => {
+                let mut values = values
+                    .into_iter()
+                    .map(|(k, value)| match value {
+                        JsonValue::String(value) => Ok(format!("{k}={value}")),
+                        _ => Err(error(format!("expected strings in {key}"))),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                values.sort();
+                values
+            }
+            _ => return Err(error(format!("unsupported value of {key}"))),
+        };
+        for value in values {
+            args.push(flag.clone());
+            args.push(value);
+        }
+    }
+    Ok(args)
+}
+
+fn parse_param_file_format(format: &str) -> Result<ParamFileFormat, OptionError> {
+    ParamFileFormat::parse(format)
+        .ok_or_else(|| OptionError::Generic(format!("invalid --param-file-format '{format}'")))
+}
+
+fn args_from_file(paths: Vec<String>, format: ParamFileFormat) -> Result<Vec<String>, OptionError> {
     let mut args = vec![];
     for path in paths.iter() {
-        let mut lines = read_file_to_array(path).map_err(|err| {
+        let mut lines = read_param_file_to_array(path, format).map_err(|err| {
             OptionError::Generic(format!(
                 "{} while processing args from file paths: {:?}",
                 err, &paths
@@ -243,17 +773,77 @@ fn env_from_files(paths: Vec<String>) -> Result<HashMap<String, String>, OptionE
     Ok(env_vars)
 }
 
-fn prepare_arg(mut arg: String, subst_mappings: &[(String, String)]) -> String {
-    for (f, replace_with) in subst_mappings {
-        let from = format!("${{{f}}}");
-        arg = arg.replace(&from, replace_with);
+fn prepare_arg(
+    arg: String,
+    stamp_mappings: &[(String, String)],
+    subst_mappings: &[(String, String)],
+) -> String {
+    substitute(&stamp(&arg, stamp_mappings), subst_mappings)
+}
+
+/// Replaces the `{KEY}` placeholders in `s` with the values of the workspace status keys in
+/// `stamp_mappings`.
+fn stamp(s: &str, stamp_mappings: &[(String, String)]) -> String {
+    let mut s = s.to_owned();
+    for (key, value) in stamp_mappings {
+        let placeholder = format!("{{{key}}}");
+        if s.contains(&placeholder) {
+            s = s.replace(&placeholder, value);
+        }
+    }
+    s
+}
+
+/// Replaces the `${key}` placeholders in `s` with the values of `subst_mappings`, the last
+/// mapping of a key taking precedence. Unknown placeholders are kept and `$${` is replaced by
+/// a literal `${`. Substituted values aren't substituted again.
+fn substitute(s: &str, subst_mappings: &[(String, String)]) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            result.push_str(&rest[..start - 1]);
+            result.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        result.push_str(&rest[..start]);
+        let placeholder = &rest[start + 2..];
+        let value = placeholder.find('}').and_then(|end| {
+            let key = &placeholder[..end];
+            subst_mappings
+                .iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| (value, end + 1))
+        });
+        match value {
+            Some((value, len)) => {
+                result.push_str(value);
+                rest = &placeholder[len..];
+            }
+            None => {
+                result.push_str("${");
+                rest = placeholder;
+            }
+        }
     }
-    arg
+    result.push_str(rest);
+    result
+}
+
+/// Resolves `path` relative to `current_dir` unless it's absolute.
+fn absolute_path(current_dir: &str, path: &str) -> String {
+    Path::new(current_dir)
+        .join(path)
+        .to_string_lossy()
+        .into_owned()
 }
 
 /// Apply substitutions to the given param file. Returns the new filename.
 fn prepare_param_file(
     filename: &str,
+    stamp_mappings: &[(String, String)],
     subst_mappings: &[(String, String)],
 ) -> Result<String, OptionError> {
     let expanded_file = format!("{filename}.expanded");
@@ -269,34 +859,42 @@ fn prepare_param_file(
     fn process_file(
         filename: &str,
         out: &mut io::BufWriter<File>,
+        stamp_mappings: &[(String, String)],
         subst_mappings: &[(String, String)],
         format_err: &impl Fn(io::Error) -> OptionError,
     ) -> Result<(), OptionError> {
         for arg in read_file_to_array(filename).map_err(OptionError::Generic)? {
-            let arg = prepare_arg(arg, subst_mappings);
+            let arg = prepare_arg(arg, stamp_mappings, subst_mappings);
             if let Some(arg_file) = arg.strip_prefix('@') {
-                process_file(arg_file, out, subst_mappings, format_err)?;
+                process_file(arg_file, out, stamp_mappings, subst_mappings, format_err)?;
             } else {
                 writeln!(out, "{arg}").map_err(format_err)?;
             }
         }
         Ok(())
     }
-    process_file(filename, &mut out, subst_mappings, &format_err)?;
+    process_file(
+        filename,
+        &mut out,
+        stamp_mappings,
+        subst_mappings,
+        &format_err,
+    )?;
     Ok(expanded_file)
 }
 
 /// Apply substitutions to the provided arguments, recursing into param files.
 fn prepare_args(
     args: Vec<String>,
+    stamp_mappings: &[(String, String)],
     subst_mappings: &[(String, String)],
 ) -> Result<Vec<String>, OptionError> {
     args.into_iter()
         .map(|arg| {
-            let arg = prepare_arg(arg, subst_mappings);
+            let arg = prepare_arg(arg, stamp_mappings, subst_mappings);
             if let Some(param_file) = arg.strip_prefix('@') {
                 // Note that substitutions may also apply to the param file path!
-                prepare_param_file(param_file, subst_mappings)
+                prepare_param_file(param_file, stamp_mappings, subst_mappings)
                     .map(|filename| format!("@{filename}"))
             } else {
                 Ok(arg)
@@ -307,30 +905,230 @@ fn prepare_args(
 
 fn environment_block(
     environment_file_block: HashMap<String, String>,
-    stable_stamp_mappings: &[(String, String)],
-    volatile_stamp_mappings: &[(String, String)],
+    inherited_env: Option<&[String]>,
+    stamp_mappings: &[(String, String)],
     subst_mappings: &[(String, String)],
 ) -> HashMap<String, String> {
-    // Taking all environment variables from the current process
-    // and sending them down to the child process
-    let mut environment_variables: HashMap<String, String> = std::env::vars().collect();
+    // Taking the environment variables from the current process, all of them unless
+    // restricted, and sending them down to the child process
+    let mut environment_variables: HashMap<String, String> = std::env::vars()
+        .filter(|(key, _)| inherited_env.is_none_or(|inherited| inherited.contains(key)))
+        .collect();
     // Have the last values added take precedence over the first.
     // This is simpler than needing to track duplicates and explicitly override
     // them.
     environment_variables.extend(environment_file_block);
-    for (f, replace_with) in &[stable_stamp_mappings, volatile_stamp_mappings].concat() {
-        for value in environment_variables.values_mut() {
-            let from = format!("{{{f}}}");
-            let new = value.replace(from.as_str(), replace_with);
-            *value = new;
-        }
-    }
-    for (f, replace_with) in subst_mappings {
-        for value in environment_variables.values_mut() {
-            let from = format!("${{{f}}}");
-            let new = value.replace(from.as_str(), replace_with);
-            *value = new;
-        }
+    for value in environment_variables.values_mut() {
+        *value = substitute(&stamp(value, stamp_mappings), subst_mappings);
     }
     environment_variables
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str, content: &str) -> String {
+        let dir = env::var_os("TEST_TMPDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir);
+        let path = dir.join(format!("process_wrapper_{}_{name}", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_substitute() {
+        let subst_mappings = vec![
+            ("pwd".to_owned(), "/exec_root".to_owned()),
+            ("out_dir".to_owned(), "/exec_root/bazel-out/bin".to_owned()),
+            ("out_dir".to_owned(), "/overridden".to_owned()),
+            ("value".to_owned(), "${pwd}".to_owned()),
+        ];
+        assert_eq!(
+            substitute("-L${pwd}/lib:${out_dir}", &subst_mappings),
+            "-L/exec_root/lib:/overridden"
+        );
+        assert_eq!(substitute("${value}", &subst_mappings), "${pwd}");
+        assert_eq!(
+            substitute("$${pwd} ${unknown} ${pwd", &subst_mappings),
+            "${pwd} ${unknown} ${pwd"
+        );
+        assert_eq!(substitute("$$ $ {pwd}", &subst_mappings), "$$ $ {pwd}");
+    }
+
+    #[test]
+    fn test_stamp() {
+        let stamp_mappings = vec![
+            ("STABLE_GIT_COMMIT".to_owned(), "abc123".to_owned()),
+            ("BUILD_TIMESTAMP".to_owned(), "1700000000".to_owned()),
+        ];
+        assert_eq!(
+            stamp(
+                "--cfg=commit=\"{STABLE_GIT_COMMIT}\" {BUILD_TIMESTAMP}",
+                &stamp_mappings
+            ),
+            "--cfg=commit=\"abc123\" 1700000000"
+        );
+        assert_eq!(stamp("{UNKNOWN} {}", &stamp_mappings), "{UNKNOWN} {}");
+
+        let param_file = temp_file("stamped_params", "--cfg=commit={STABLE_GIT_COMMIT}\n");
+        let args = prepare_args(
+            vec![
+                "--version={STABLE_GIT_COMMIT}".to_owned(),
+                format!("@{param_file}"),
+            ],
+            &stamp_mappings,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "--version=abc123".to_owned(),
+                format!("@{param_file}.expanded")
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{param_file}.expanded")).unwrap(),
+            "--cfg=commit=abc123\n"
+        );
+    }
+
+    #[test]
+    fn test_args_from_config_file() {
+        let config = temp_file(
+            "config.json",
+            r#"{
+                "subst": {"pwd": "${pwd}", "out": "bazel-out"},
+                "inherit-env": ["PATH", "HOME"],
+                "stdout-file": "stdout.txt",
+                "tee-output": true,
+                "deny-warnings": false
+            }"#,
+        );
+        let args = vec![
+            "process_wrapper".to_owned(),
+            "--config-file".to_owned(),
+            config,
+            "--".to_owned(),
+            "tool".to_owned(),
+        ];
+        assert_eq!(
+            expand_wrapper_param_files(args).unwrap(),
+            [
+                "process_wrapper",
+                "--inherit-env",
+                "PATH",
+                "--inherit-env",
+                "HOME",
+                "--stdout-file",
+                "stdout.txt",
+                "--subst",
+                "out=bazel-out",
+                "--subst",
+                "pwd=${pwd}",
+                "--tee-output",
+                "true",
+                "--",
+                "tool",
+            ]
+        );
+
+        let invalid = temp_file("invalid_config.json", r#"{"timeout": 10}"#);
+        assert!(args_from_config_file(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_expand_wrapper_param_files() {
+        let wrapper_params = temp_file("wrapper_params", "--subst\npwd=${pwd}\n--\nrustc\n");
+        let rustc_params = temp_file("rustc_params", "lib.rs\n");
+
+        let args = vec![
+            "process_wrapper".to_owned(),
+            "--touch-file".to_owned(),
+            "out".to_owned(),
+            format!("@{wrapper_params}"),
+            format!("@{rustc_params}"),
+        ];
+        assert_eq!(
+            expand_wrapper_param_files(args).unwrap(),
+            vec![
+                "process_wrapper".to_owned(),
+                "--touch-file".to_owned(),
+                "out".to_owned(),
+                "--subst".to_owned(),
+                "pwd=${pwd}".to_owned(),
+                "--".to_owned(),
+                "rustc".to_owned(),
+                format!("@{rustc_params}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_shell_param_files() {
+        let multiline_params = temp_file("multiline_params", "'--touch-file'\n");
+        let shell_params = temp_file("shell_params", "--subst 'a=b c'\n--\nrustc\n");
+
+        let args = vec![
+            "process_wrapper".to_owned(),
+            format!("@{multiline_params}"),
+            "--param-file-format".to_owned(),
+            "shell".to_owned(),
+            format!("@{shell_params}"),
+        ];
+        assert_eq!(
+            expand_wrapper_param_files(args).unwrap(),
+            [
+                "process_wrapper",
+                "'--touch-file'",
+                "--param-file-format",
+                "shell",
+                "--subst",
+                "a=b c",
+                "--",
+                "rustc",
+            ]
+        );
+
+        let args = vec![
+            "process_wrapper".to_owned(),
+            "--param-file-format".to_owned(),
+            "json".to_owned(),
+        ];
+        assert!(expand_wrapper_param_files(args).is_err());
+    }
+
+    #[test]
+    fn test_color() {
+        let options = |flags: &[&str]| {
+            let mut args = vec!["process_wrapper"];
+            args.extend_from_slice(flags);
+            args.extend_from_slice(&["--", "rustc", "lib.rs"]);
+            options_from_args(args.into_iter().map(str::to_owned).collect())
+        };
+
+        let opts = options(&["--color", "always"]).unwrap();
+        assert_eq!(opts.color, Some(true));
+        assert_eq!(opts.child_arguments, vec!["lib.rs", "--color=always"]);
+
+        let opts = options(&["--color", "never"]).unwrap();
+        assert_eq!(opts.color, Some(false));
+        assert_eq!(opts.child_arguments, vec!["lib.rs", "--color=never"]);
+
+        // The colors of json diagnostics are requested with `--json` instead.
+        let opts = options(&["--color", "never", "--rustc-output-format", "rendered"]).unwrap();
+        assert_eq!(opts.color, Some(false));
+        assert_eq!(opts.child_arguments, vec!["lib.rs"]);
+
+        let opts = options(&[]).unwrap();
+        assert_eq!(opts.color, None);
+        assert_eq!(opts.child_arguments, vec!["lib.rs"]);
+
+        assert!(options(&["--color", "auto"]).is_err());
+    }
+}
@@ -29,6 +29,76 @@ pub(crate) enum LineOutput {
     Terminate,
 }
 
+/// Tee writes everything to two writers, e.g. to capture output in a file while still
+/// streaming it to the console.
+#[derive(Debug)]
+pub(crate) struct Tee<A, B>(pub(crate) A, pub(crate) B);
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+/// The state of `StripAnsi` between writes, as escape sequences may be split across them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiState {
+    Text,
+    // After an ESC byte.
+    Escape,
+    // In a control sequence, i.e. after `ESC [`, until its final byte.
+    ControlSequence,
+}
+
+/// StripAnsi removes ANSI escape sequences, e.g. colors, from everything written to a writer.
+#[derive(Debug)]
+pub(crate) struct StripAnsi<W> {
+    inner: W,
+    state: AnsiState,
+}
+
+impl<W> StripAnsi<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            state: AnsiState::Text,
+        }
+    }
+}
+
+impl<W: Write> Write for StripAnsi<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut text = Vec::with_capacity(buf.len());
+        for &byte in buf {
+            self.state = match (self.state, byte) {
+                (AnsiState::Text, 0x1b) => AnsiState::Escape,
+                (AnsiState::Text, _) => {
+                    text.push(byte);
+                    AnsiState::Text
+                }
+                (AnsiState::Escape, b'[') => AnsiState::ControlSequence,
+                // Other escape sequences consist of a single byte.
+                (AnsiState::Escape, _) => AnsiState::Text,
+                (AnsiState::ControlSequence, 0x40..=0x7e) => AnsiState::Text,
+                (AnsiState::ControlSequence, _) => AnsiState::ControlSequence,
+            };
+        }
+        self.inner.write_all(&text)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum ProcessError {
     IO(io::Error),
@@ -117,6 +187,52 @@ where
 mod test {
     use super::*;
 
+    #[test]
+    fn test_tee() {
+        let mut input = io::Cursor::new(b"some\noutput\n");
+        let mut first: Vec<u8> = vec![];
+        let mut second: Vec<u8> = vec![];
+        let mut tee = Tee(&mut first, &mut second);
+        let result = process_output(&mut input, &mut tee, None, move |line| {
+            Ok(LineOutput::Message(line))
+        });
+        assert!(result.is_ok());
+        assert_eq!(&first, b"some\noutput\n");
+        assert_eq!(&second, b"some\noutput\n");
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        let mut output: Vec<u8> = vec![];
+        let mut strip_ansi = StripAnsi::new(&mut output);
+        strip_ansi
+            .write_all(b"\x1b[0m\x1b[1m\x1b[38;5;9merror\x1b[0m: oops\n\x1b")
+            .unwrap();
+        // Escape sequences may be split across writes.
+        strip_ansi.write_all(b"[1;3").unwrap();
+        strip_ansi.write_all(b"4m-->\x1b[0m lib.rs\n").unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "error: oops\n--> lib.rs\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_ansi_keeps_text() {
+        let mut output: Vec<u8> = vec![];
+        let mut strip_ansi = StripAnsi::new(&mut output);
+        // Escape sequences other than control sequences consist of a single byte after ESC, and
+        // text other than escape sequences is kept byte for byte.
+        strip_ansi
+            .write_all("\x1b7warning\x1b8: `ß` [E0308] \x1b[Kdone\n".as_bytes())
+            .unwrap();
+        strip_ansi.flush().unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "warning: `ß` [E0308] done\n"
+        );
+    }
+
     #[test]
     fn test_json_parsing_error() {
         let mut input = io::Cursor::new(b"ok text\nsome more\nerror text");
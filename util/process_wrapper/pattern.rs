@@ -0,0 +1,317 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small regular expression engine, as the process wrapper can't depend on the `regex` crate.
+//! It supports literals, `.`, character classes such as `[a-z]` and `[^0-9]`, the `\d`, `\w`
+//! and `\s` classes and their negations, the `*`, `+` and `?` quantifiers, the `^` and `$`
+//! anchors and alternation with `|`. Groups and counted repetitions are not supported.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Digit,
+    Word,
+    Space,
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Range(start, end) => (*start..=*end).contains(&c),
+            Self::Digit => c.is_ascii_digit(),
+            Self::Word => c.is_alphanumeric() || c == '_',
+            Self::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Atom {
+    Any,
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+    Start,
+    End,
+}
+
+impl Atom {
+    fn literal(c: char) -> Self {
+        Self::Class {
+            negated: false,
+            items: vec![ClassItem::Range(c, c)],
+        }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Class { negated, items } => items.iter().any(|item| item.matches(c)) != *negated,
+            Self::Start | Self::End => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Piece {
+    atom: Atom,
+    min: usize,
+    max: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PatternError(String);
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A compiled regular expression.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Pattern {
+    alternatives: Vec<Vec<Piece>>,
+}
+
+/// Parses the escape sequence following a `\`.
+fn parse_escape(c: Option<char>) -> Result<(bool, ClassItem), PatternError> {
+    Ok(match c {
+        Some('d') => (false, ClassItem::Digit),
+        Some('D') => (true, ClassItem::Digit),
+        Some('w') => (false, ClassItem::Word),
+        Some('W') => (true, ClassItem::Word),
+        Some('s') => (false, ClassItem::Space),
+        Some('S') => (true, ClassItem::Space),
+        Some('n') => (false, ClassItem::Range('\n', '\n')),
+        Some('t') => (false, ClassItem::Range('\t', '\t')),
+        Some(c) if !c.is_alphanumeric() => (false, ClassItem::Range(c, c)),
+        Some(c) => return Err(PatternError(format!("unsupported escape sequence \\{c}"))),
+        None => return Err(PatternError("trailing backslash".to_owned())),
+    })
+}
+
+impl Pattern {
+    pub(crate) fn new(pattern: &str) -> Result<Self, PatternError> {
+        let mut alternatives = vec![vec![]];
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            let pieces: &mut Vec<Piece> = alternatives.last_mut().unwrap();
+            let atom = match c {
+                '|' => {
+                    alternatives.push(vec![]);
+                    continue;
+                }
+                '*' | '+' | '?' => {
+                    let piece = match pieces.last_mut() {
+                        Some(piece)
+                            if piece.min == 1
+                                && piece.max == Some(1)
+                                && !matches!(piece.atom, Atom::Start | Atom::End) =>
+                        {
+                            piece
+                        }
+                        _ => return Err(PatternError(format!("nothing to repeat with {c}"))),
+                    };
+                    match c {
+                        '*' => (piece.min, piece.max) = (0, None),
+                        '+' => piece.max = None,
+                        _ => piece.min = 0,
+                    }
+                    continue;
+                }
+                '(' | ')' | '{' | '}' => {
+                    return Err(PatternError(format!(
+                        "groups and counted repetitions are not supported, escape {c} to match it"
+                    )))
+                }
+                '.' => Atom::Any,
+                '^' => Atom::Start,
+                '$' => Atom::End,
+                '\\' => {
+                    let (negated, item) = parse_escape(chars.next())?;
+                    Atom::Class {
+                        negated,
+                        items: vec![item],
+                    }
+                }
+                '[' => {
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    let mut items = vec![];
+                    // A `]` at the start of a class is a literal.
+                    let mut first = true;
+                    loop {
+                        let start = match chars.next() {
+                            Some(']') if !first => break,
+                            Some('\\') => match parse_escape(chars.next())? {
+                                (false, ClassItem::Range(c, _)) => c,
+                                (false, item) => {
+                                    items.push(item);
+                                    first = false;
+                                    continue;
+                                }
+                                (true, _) => {
+                                    return Err(PatternError(
+                                        "negated classes are not supported in brackets".to_owned(),
+                                    ))
+                                }
+                            },
+                            Some(c) => c,
+                            None => return Err(PatternError("unterminated [".to_owned())),
+                        };
+                        first = false;
+                        let mut lookahead = chars.clone();
+                        let end = match (lookahead.next(), lookahead.next()) {
+                            (Some('-'), Some(end)) if end != ']' => {
+                                chars.next();
+                                chars.next();
+                                end
+                            }
+                            _ => start,
+                        };
+                        if end < start {
+                            return Err(PatternError(format!("invalid range {start}-{end}")));
+                        }
+                        items.push(ClassItem::Range(start, end));
+                    }
+                    Atom::Class { negated, items }
+                }
+                c => Atom::literal(c),
+            };
+            pieces.push(Piece {
+                atom,
+                min: 1,
+                max: Some(1),
+            });
+        }
+        Ok(Self { alternatives })
+    }
+
+    /// Returns whether the pattern matches somewhere in `text`, where `^` and `$` match at
+    /// its start and end.
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.alternatives
+            .iter()
+            .any(|pieces| (0..=text.len()).any(|start| match_here(pieces, &text, start)))
+    }
+}
+
+fn match_here(pieces: &[Piece], text: &[char], pos: usize) -> bool {
+    let Some((piece, rest)) = pieces.split_first() else {
+        return true;
+    };
+    match piece.atom {
+        Atom::Start => pos == 0 && match_here(rest, text, pos),
+        Atom::End => pos == text.len() && match_here(rest, text, pos),
+        _ => {
+            // Match as many characters as possible, then backtrack.
+            let mut count = 0;
+            while piece.max.is_none_or(|max| count < max)
+                && pos + count < text.len()
+                && piece.atom.matches(text[pos + count])
+            {
+                count += 1;
+            }
+            loop {
+                if count < piece.min {
+                    return false;
+                }
+                if match_here(rest, text, pos + count) {
+                    return true;
+                }
+                if count == 0 {
+                    return false;
+                }
+                count -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_literals_and_wildcards() {
+        assert!(is_match("LNK1104", "error LNK1104: cannot open file"));
+        assert!(!is_match("LNK1104", "error LNK1105"));
+        assert!(is_match("cannot .* file", "cannot open file"));
+        assert!(is_match("a.c", "abc"));
+        assert!(!is_match("a.c", "ac"));
+        assert!(is_match("", "anything"));
+        assert!(is_match(r"\.rlib", "libfoo.rlib"));
+        assert!(!is_match(r"\.rlib", "libfoorlib"));
+    }
+
+    #[test]
+    fn test_quantifiers() {
+        assert!(is_match("ab*c", "ac"));
+        assert!(is_match("ab*c", "abbbc"));
+        assert!(!is_match("ab+c", "ac"));
+        assert!(is_match("ab+c", "abbc"));
+        assert!(is_match("ab?c", "ac"));
+        assert!(is_match("ab?c", "abc"));
+        assert!(!is_match("^ab?c$", "abbc"));
+        assert!(is_match("a.*b.*c", "a x b y c"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(is_match(
+            r"error\[E\d+\]",
+            "error[E0433]: failed to resolve"
+        ));
+        assert!(is_match("[a-c]+x", "abcx"));
+        assert!(!is_match("^[a-c]+x", "abdx"));
+        assert!(is_match("[^0-9]", "a"));
+        assert!(!is_match("^[^0-9]+$", "a1"));
+        assert!(is_match(r"[\d_]+", "_1"));
+        assert!(is_match("[]a]", "]"));
+        assert!(is_match("[a-]", "-"));
+        assert!(is_match(r"\w+\s\S", "ab c"));
+        assert!(!is_match(r"^\W$", "a"));
+    }
+
+    #[test]
+    fn test_anchors_and_alternation() {
+        assert!(is_match("^error", "error: linking failed"));
+        assert!(!is_match("^error", "an error"));
+        assert!(is_match("failed$", "linking failed"));
+        assert!(!is_match("failed$", "failed linking"));
+        assert!(is_match("LNK1104|being used by another process", "LNK1104"));
+        assert!(is_match(
+            "LNK1104|being used by another process",
+            "the file is being used by another process"
+        ));
+        assert!(!is_match("^a|^b", "cab"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for pattern in [
+            "*a", "a**", "^*", "(a)", "a{2}", "[a", r"a\", r"\q", "[z-a]",
+        ] {
+            assert!(Pattern::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
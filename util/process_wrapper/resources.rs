@@ -0,0 +1,239 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Measurement of the resources used by the child process, for `--resource-report`.
+
+use std::collections::HashMap;
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::time::Duration;
+
+use tinyjson::JsonValue;
+
+/// The resources used by a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ResourceUsage {
+    pub(crate) max_rss_bytes: u64,
+    pub(crate) user_time: Duration,
+    pub(crate) system_time: Duration,
+}
+
+impl ResourceUsage {
+    /// Renders the usage, along with the wall time of the process, as a json object.
+    pub(crate) fn to_json(self, wall_time: Duration) -> String {
+        let report: HashMap<String, JsonValue> = HashMap::from([
+            (
+                "max_rss_bytes".to_owned(),
+                JsonValue::Number(self.max_rss_bytes as f64),
+            ),
+            (
+                "user_time_secs".to_owned(),
+                JsonValue::Number(self.user_time.as_secs_f64()),
+            ),
+            (
+                "system_time_secs".to_owned(),
+                JsonValue::Number(self.system_time.as_secs_f64()),
+            ),
+            (
+                "wall_time_secs".to_owned(),
+                JsonValue::Number(wall_time.as_secs_f64()),
+            ),
+        ]);
+        // Rendering a json object of numbers can't fail.
+        JsonValue::Object(report).stringify().unwrap()
+    }
+}
+
+/// Waits for `child` to exit and returns its exit status and the resources it used, with
+/// `wait4`. The maximum resident set size is that of its largest descendant.
+#[cfg(unix)]
+pub(crate) fn wait(child: &mut Child) -> io::Result<(ExitStatus, ResourceUsage)> {
+    use std::os::raw::{c_int, c_long};
+    use std::os::unix::process::ExitStatusExt;
+
+    #[cfg(target_os = "macos")]
+    type Suseconds = i32;
+    #[cfg(not(target_os = "macos"))]
+    type Suseconds = c_long;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Timeval {
+        tv_sec: c_long,
+        tv_usec: Suseconds,
+    }
+
+    impl Timeval {
+        fn duration(&self) -> Duration {
+            Duration::from_secs(self.tv_sec as u64) + Duration::from_micros(self.tv_usec as u64)
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Rusage {
+        ru_utime: Timeval,
+        ru_stime: Timeval,
+        ru_maxrss: c_long,
+        // The remaining fields, which aren't used.
+        ru_other: [c_long; 13],
+    }
+
+    extern "C" {
+        fn wait4(pid: c_int, status: *mut c_int, options: c_int, rusage: *mut Rusage) -> c_int;
+    }
+
+    let mut status = 0;
+    let mut rusage = Rusage::default();
+    loop {
+        // Safe as both pointers are valid for the duration of the call.
+        if unsafe { wait4(child.id() as c_int, &mut status, 0, &mut rusage) } != -1 {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+
+    // Linux reports the maximum resident set size in kilobytes, macOS in bytes.
+    let max_rss_bytes = if cfg!(target_os = "macos") {
+        rusage.ru_maxrss as u64
+    } else {
+        rusage.ru_maxrss as u64 * 1024
+    };
+    Ok((
+        ExitStatus::from_raw(status),
+        ResourceUsage {
+            max_rss_bytes,
+            user_time: rusage.ru_utime.duration(),
+            system_time: rusage.ru_stime.duration(),
+        },
+    ))
+}
+
+/// Waits for `child` to exit and returns its exit status and the resources it used. Only the
+/// resources of the child itself are measured, not those of its descendants.
+#[cfg(windows)]
+pub(crate) fn wait(child: &mut Child) -> io::Result<(ExitStatus, ResourceUsage)> {
+    use std::ffi::c_void;
+    use std::os::windows::io::AsRawHandle;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct FileTime {
+        low: u32,
+        high: u32,
+    }
+
+    impl FileTime {
+        // File times count intervals of 100 nanoseconds.
+        fn duration(&self) -> Duration {
+            Duration::from_nanos(((u64::from(self.high) << 32) | u64::from(self.low)) * 100)
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct ProcessMemoryCounters {
+        cb: u32,
+        page_fault_count: u32,
+        peak_working_set_size: usize,
+        // The remaining fields, which aren't used.
+        other: [usize; 7],
+    }
+
+    extern "system" {
+        fn GetProcessTimes(
+            process: *mut c_void,
+            creation_time: *mut FileTime,
+            exit_time: *mut FileTime,
+            kernel_time: *mut FileTime,
+            user_time: *mut FileTime,
+        ) -> i32;
+        fn K32GetProcessMemoryInfo(
+            process: *mut c_void,
+            counters: *mut ProcessMemoryCounters,
+            cb: u32,
+        ) -> i32;
+    }
+
+    let status = child.wait()?;
+    let handle = child.as_raw_handle() as *mut c_void;
+    let (mut creation_time, mut exit_time) = (FileTime::default(), FileTime::default());
+    let (mut kernel_time, mut user_time) = (FileTime::default(), FileTime::default());
+    let mut counters = ProcessMemoryCounters {
+        cb: std::mem::size_of::<ProcessMemoryCounters>() as u32,
+        ..Default::default()
+    };
+    // Safe as the handle stays open until `child` is dropped and the pointers are valid for the
+    // duration of the calls.
+    unsafe {
+        if GetProcessTimes(
+            handle,
+            &mut creation_time,
+            &mut exit_time,
+            &mut kernel_time,
+            &mut user_time,
+        ) == 0
+            || K32GetProcessMemoryInfo(handle, &mut counters, counters.cb) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok((
+        status,
+        ResourceUsage {
+            max_rss_bytes: counters.peak_working_set_size as u64,
+            user_time: user_time.duration(),
+            system_time: kernel_time.duration(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let usage = ResourceUsage {
+            max_rss_bytes: 1024,
+            user_time: Duration::from_millis(1500),
+            system_time: Duration::from_millis(250),
+        };
+        let report: JsonValue = usage.to_json(Duration::from_secs(2)).parse().unwrap();
+        assert_eq!(
+            report,
+            JsonValue::Object(HashMap::from([
+                ("max_rss_bytes".to_owned(), JsonValue::Number(1024.0)),
+                ("user_time_secs".to_owned(), JsonValue::Number(1.5)),
+                ("system_time_secs".to_owned(), JsonValue::Number(0.25)),
+                ("wall_time_secs".to_owned(), JsonValue::Number(2.0)),
+            ]))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        let (status, usage) = wait(&mut child).unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert!(usage.max_rss_bytes > 0);
+    }
+}
@@ -12,7 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
 use std::convert::{TryFrom, TryInto};
+use std::path::Path;
 
 use tinyjson::JsonValue;
 
@@ -30,6 +32,85 @@ impl Default for ErrorFormat {
     }
 }
 
+/// Rewrites the absolute paths of the exec root, in which rustc runs, into paths relative to
+/// it in rustc diagnostics. For sources of the main repository these are workspace relative
+/// paths, so diagnostics are identical across machines and sandboxes and are clickable in IDEs.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PathRewriter {
+    // Pairs of absolute path prefixes and their replacements, as they appear in json strings.
+    prefixes: Vec<(String, String)>,
+}
+
+impl PathRewriter {
+    pub(crate) fn new(exec_root: &str) -> Self {
+        let exec_root = exec_root.trim_end_matches(['/', '\\']);
+        let mut prefixes = Vec::new();
+        for separator in ['/', '\\'] {
+            prefixes.push((format!("{exec_root}{separator}"), String::new()));
+            // External repositories are linked into the exec root from the output base,
+            // which some tools resolve the links to.
+            if let Some((output_base, _)) =
+                exec_root.rsplit_once(&format!("{separator}execroot{separator}"))
+            {
+                prefixes.push((
+                    format!("{output_base}{separator}external{separator}"),
+                    format!("external{separator}"),
+                ));
+            }
+        }
+        Self {
+            prefixes: prefixes
+                .into_iter()
+                .filter(|(prefix, _)| prefix.len() > 1)
+                .map(|(prefix, replacement)| (escape_json(&prefix), escape_json(&replacement)))
+                .collect(),
+        }
+    }
+
+    /// Rewrites the paths of a line of rustc json output, including those of the rendered
+    /// diagnostic.
+    pub(crate) fn rewrite_json(&self, line: &str) -> String {
+        self.prefixes
+            .iter()
+            .fold(line.to_owned(), |line, (prefix, replacement)| {
+                line.replace(prefix.as_str(), replacement)
+            })
+    }
+}
+
+/// Returns `--remap-path-prefix` flags making the paths rustc embeds in debug info and panic
+/// messages independent of the machine. The home directory is remapped to `~`, external
+/// repositories in the output base to `external` and the exec root to the empty path. As the
+/// last matching prefix takes precedence in rustc, more specific prefixes come last.
+pub(crate) fn remap_path_prefix_flags(exec_root: &str, home: Option<&str>) -> Vec<String> {
+    let exec_root = Path::new(exec_root);
+    let mut prefixes = Vec::new();
+    if let Some(home) = home.filter(|home| !home.is_empty()) {
+        prefixes.push((home.to_owned(), "~".to_owned()));
+    }
+    // The exec root is `<output_base>/execroot/<workspace name>`.
+    if let Some(output_base) = exec_root
+        .parent()
+        .filter(|parent| parent.file_name().is_some_and(|name| name == "execroot"))
+        .and_then(Path::parent)
+    {
+        prefixes.push((
+            output_base.join("external").display().to_string(),
+            "external".to_owned(),
+        ));
+    }
+    prefixes.push((exec_root.display().to_string(), String::new()));
+    prefixes
+        .into_iter()
+        .map(|(prefix, replacement)| format!("--remap-path-prefix={prefix}={replacement}"))
+        .collect()
+}
+
+/// Escapes `s` as it would be in a json string.
+fn escape_json(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
 fn get_key(value: &JsonValue, key: &str) -> Option<String> {
     if let JsonValue::Object(map) = value {
         if let JsonValue::String(s) = map.get(key)? {
@@ -42,6 +123,96 @@ fn get_key(value: &JsonValue, key: &str) -> Option<String> {
     }
 }
 
+/// Denies warnings like `-Dwarnings`, except for those allowed by lint name or by path, so the
+/// warnings of a codebase can be fixed incrementally. Warnings are still reported as such by
+/// rustc, and the compilation fails once it finished if any of them were denied.
+#[derive(Debug, Default)]
+pub(crate) struct WarningFilter {
+    deny: bool,
+    // Lint names, e.g. `dead_code`, and path prefixes, which contain a `/`.
+    allowed: Vec<String>,
+    denied: usize,
+    // The number of allowed warnings by the entry of `allowed` allowing them.
+    allowed_counts: BTreeMap<String, usize>,
+}
+
+impl WarningFilter {
+    pub(crate) fn new(deny: bool, allowed: Vec<String>) -> Self {
+        Self {
+            deny,
+            allowed,
+            ..Default::default()
+        }
+    }
+
+    fn check(&mut self, message: &JsonValue) {
+        if !self.deny || get_key(message, "level").as_deref() != Some("warning") {
+            return;
+        }
+        let JsonValue::Object(map) = message else {
+            return;
+        };
+        let code = map.get("code").and_then(|code| get_key(code, "code"));
+        let paths: Vec<String> = match map.get("spans") {
+            Some(JsonValue::Array(spans)) => spans
+                .iter()
+                .filter(|span| match span {
+                    JsonValue::Object(span) => {
+                        span.get("is_primary") == Some(&JsonValue::Boolean(true))
+                    }
+                    _ => false,
+                })
+                .filter_map(|span| get_key(span, "file_name"))
+                .collect(),
+            _ => Vec::new(),
+        };
+        // The summary of the number of warnings emitted has neither a code nor a span.
+        if code.is_none() && paths.is_empty() {
+            return;
+        }
+        let allowed_by = self.allowed.iter().find(|allowed| {
+            if allowed.contains('/') {
+                paths.iter().any(|path| path.starts_with(allowed.as_str()))
+            } else {
+                code.as_deref() == Some(allowed.as_str())
+            }
+        });
+        match allowed_by {
+            Some(allowed) => *self.allowed_counts.entry(allowed.clone()).or_default() += 1,
+            None => self.denied += 1,
+        }
+    }
+
+    /// The number of warnings which weren't allowed.
+    pub(crate) fn denied(&self) -> usize {
+        self.denied
+    }
+
+    /// Summarizes the allowed and denied warnings, if there were any.
+    pub(crate) fn summary(&self) -> Option<String> {
+        let mut summary = String::new();
+        if !self.allowed_counts.is_empty() {
+            let counts: Vec<String> = self
+                .allowed_counts
+                .iter()
+                .map(|(allowed, count)| format!("{allowed} ({count})"))
+                .collect();
+            summary.push_str(&format!(
+                "process wrapper: {} warning(s) allowed by --allow-warning: {}\n",
+                self.allowed_counts.values().sum::<usize>(),
+                counts.join(", ")
+            ));
+        }
+        if self.denied > 0 {
+            summary.push_str(&format!(
+                "error: {} warning(s) denied by --deny-warnings\n",
+                self.denied
+            ));
+        }
+        Some(summary).filter(|summary| !summary.is_empty())
+    }
+}
+
 #[derive(Debug)]
 enum RustcMessage {
     Emit(String),
@@ -64,13 +235,29 @@ impl TryFrom<JsonValue> for RustcMessage {
 /// process_rustc_json takes an output line from rustc configured with
 /// --error-format=json, parses the json and returns the appropriate output
 /// according to the original --error-format supplied.
-/// Only messages are returned, emits are ignored.
+/// Only messages are returned, emits are ignored except for setting
+/// `metadata_emitted` once metadata is emitted.
+/// Paths in the messages are rewritten with `path_rewriter`.
 /// Retuns an errors if parsing json fails.
-pub(crate) fn process_json(line: String, error_format: ErrorFormat) -> LineResult {
+pub(crate) fn process_json(
+    line: String,
+    error_format: ErrorFormat,
+    path_rewriter: &PathRewriter,
+    warning_filter: &mut WarningFilter,
+    metadata_emitted: &mut bool,
+) -> LineResult {
+    let line = path_rewriter.rewrite_json(&line);
     let parsed: JsonValue = line
         .parse()
         .map_err(|_| "error parsing rustc output as json".to_owned())?;
+    warning_filter.check(&parsed);
     Ok(match parsed.try_into() {
+        Ok(RustcMessage::Emit(emit)) => {
+            if emit == "metadata" {
+                *metadata_emitted = true;
+            }
+            LineOutput::Skip
+        }
         Ok(RustcMessage::Message(rendered)) => {
             output_based_on_error_format(line, rendered, error_format)
         }
@@ -88,11 +275,15 @@ pub(crate) fn process_json(line: String, error_format: ErrorFormat) -> LineResul
 pub(crate) fn stop_on_rmeta_completion(
     line: String,
     error_format: ErrorFormat,
+    path_rewriter: &PathRewriter,
+    warning_filter: &mut WarningFilter,
     kill: &mut bool,
 ) -> LineResult {
+    let line = path_rewriter.rewrite_json(&line);
     let parsed: JsonValue = line
         .parse()
         .map_err(|_| "error parsing rustc output as json".to_owned())?;
+    warning_filter.check(&parsed);
     Ok(match parsed.try_into() {
         Ok(RustcMessage::Emit(emit)) if emit == "metadata" => {
             *kill = true;
@@ -105,6 +296,40 @@ pub(crate) fn stop_on_rmeta_completion(
     })
 }
 
+/// The flavor of a rustc action of pipelined compilation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ActionFlavor {
+    /// The action emitting only metadata, which dependents compile against.
+    Metadata,
+    /// The action compiling the crate in full.
+    Full,
+}
+
+/// Returns whether the json diagnostic `line` is skipped by the full compile action of
+/// pipelined compilation, given whether rustc reported emitting metadata before it. Rustc runs
+/// identically in the metadata action until then, which reports the diagnostics other than
+/// errors already. Errors are always reported, as they fail both actions.
+pub(crate) fn reported_by_metadata_action(line: &str, metadata_reported: bool) -> bool {
+    if metadata_reported {
+        return false;
+    }
+    let Ok(parsed) = line.parse::<JsonValue>() else {
+        return false;
+    };
+    // Internal compiler errors have the level `error: internal compiler error`.
+    !get_key(&parsed, "level").is_some_and(|level| level.starts_with("error"))
+}
+
+/// Returns the rendered message of the json diagnostic `line`, e.g. one returned by
+/// process_json with ErrorFormat::Json.
+pub(crate) fn rendered_message(line: &str) -> Option<String> {
+    let parsed: JsonValue = line.parse().ok()?;
+    match parsed.try_into() {
+        Ok(RustcMessage::Message(rendered)) => Some(rendered),
+        _ => None,
+    }
+}
+
 fn output_based_on_error_format(
     line: String,
     rendered: String,
@@ -118,3 +343,152 @@ fn output_based_on_error_format(
         ErrorFormat::Rendered => LineOutput::Message(rendered),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rendered(result: LineResult) -> String {
+        match result {
+            Ok(LineOutput::Message(message)) => message,
+            other => panic!("unexpected output {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rewrite_exec_root_paths() {
+        let path_rewriter =
+            PathRewriter::new("/home/user/.cache/bazel/_bazel_user/abc/execroot/_main");
+        let line = r#"{"rendered": "error: oops\n --> /home/user/.cache/bazel/_bazel_user/abc/execroot/_main/src/lib.rs:1:1\n --> /home/user/.cache/bazel/_bazel_user/abc/external/foo/lib.rs:2:2\n --> /usr/lib/rustlib/lib.rs:3:3\n", "spans": [{"file_name": "/home/user/.cache/bazel/_bazel_user/abc/execroot/_main/src/lib.rs"}]}"#;
+
+        assert_eq!(
+            rendered(process_json(
+                line.to_owned(),
+                ErrorFormat::Rendered,
+                &path_rewriter,
+                &mut WarningFilter::default(),
+                &mut false
+            )),
+            "error: oops\n --> src/lib.rs:1:1\n --> external/foo/lib.rs:2:2\n --> /usr/lib/rustlib/lib.rs:3:3\n"
+        );
+        assert_eq!(
+            rendered(process_json(
+                line.to_owned(),
+                ErrorFormat::Json,
+                &path_rewriter,
+                &mut WarningFilter::default(),
+                &mut false
+            )),
+            r#"{"rendered": "error: oops\n --> src/lib.rs:1:1\n --> external/foo/lib.rs:2:2\n --> /usr/lib/rustlib/lib.rs:3:3\n", "spans": [{"file_name": "src/lib.rs"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_rewrite_windows_exec_root_paths() {
+        let path_rewriter = PathRewriter::new(r"C:\bazel\abc\execroot\_main");
+        let line = r#"{"rendered": "--> C:\\bazel\\abc\\execroot\\_main\\src\\lib.rs:1:1\n"}"#;
+
+        assert_eq!(
+            rendered(process_json(
+                line.to_owned(),
+                ErrorFormat::Rendered,
+                &path_rewriter,
+                &mut WarningFilter::default(),
+                &mut false
+            )),
+            "--> src\\lib.rs:1:1\n"
+        );
+    }
+
+    #[test]
+    fn test_remap_path_prefix_flags() {
+        assert_eq!(
+            remap_path_prefix_flags(
+                "/home/user/.cache/bazel/_bazel_user/abc/execroot/_main",
+                Some("/home/user")
+            ),
+            vec![
+                "--remap-path-prefix=/home/user=~",
+                "--remap-path-prefix=/home/user/.cache/bazel/_bazel_user/abc/external=external",
+                "--remap-path-prefix=/home/user/.cache/bazel/_bazel_user/abc/execroot/_main=",
+            ]
+        );
+        assert_eq!(
+            remap_path_prefix_flags("/tmp/sandbox", Some("")),
+            vec!["--remap-path-prefix=/tmp/sandbox="]
+        );
+    }
+
+    #[test]
+    fn test_warning_filter() {
+        let warning = |code: &str, file_name: &str| {
+            format!(
+                r#"{{"$message_type": "diagnostic", "level": "warning", "code": {{"code": "{code}", "explanation": null}}, "spans": [{{"file_name": "{file_name}", "is_primary": true}}], "rendered": "warning: {code}\n"}}"#
+            )
+        };
+        let lines = [
+            warning("dead_code", "src/lib.rs"),
+            warning("dead_code", "src/main.rs"),
+            warning("unused_imports", "src/legacy/mod.rs"),
+            warning("unused_variables", "src/lib.rs"),
+            r#"{"$message_type": "diagnostic", "level": "error", "code": {"code": "E0308"}, "spans": [], "rendered": "error\n"}"#.to_owned(),
+            r#"{"$message_type": "diagnostic", "level": "warning", "code": null, "spans": [], "rendered": "warning: 4 warnings emitted\n"}"#.to_owned(),
+        ];
+
+        let mut warning_filter =
+            WarningFilter::new(true, vec!["dead_code".to_owned(), "src/legacy/".to_owned()]);
+        for line in &lines {
+            process_json(
+                line.clone(),
+                ErrorFormat::Rendered,
+                &PathRewriter::default(),
+                &mut warning_filter,
+                &mut false,
+            )
+            .unwrap();
+        }
+        assert_eq!(warning_filter.denied(), 1);
+        assert_eq!(
+            warning_filter.summary().unwrap(),
+            "process wrapper: 3 warning(s) allowed by --allow-warning: dead_code (2), src/legacy/ (1)\n\
+            error: 1 warning(s) denied by --deny-warnings\n"
+        );
+
+        let mut warning_filter = WarningFilter::default();
+        for line in &lines {
+            process_json(
+                line.clone(),
+                ErrorFormat::Rendered,
+                &PathRewriter::default(),
+                &mut warning_filter,
+                &mut false,
+            )
+            .unwrap();
+        }
+        assert_eq!(warning_filter.denied(), 0);
+        assert_eq!(warning_filter.summary(), None);
+    }
+
+    #[test]
+    fn test_rendered_message() {
+        assert_eq!(
+            rendered_message(r#"{"rendered": "warning: unused\n"}"#),
+            Some("warning: unused\n".to_owned())
+        );
+        assert_eq!(rendered_message(r#"{"emit": "metadata"}"#), None);
+        assert_eq!(rendered_message("not json"), None);
+    }
+
+    #[test]
+    fn test_reported_by_metadata_action() {
+        let warning = r#"{"level": "warning", "rendered": "warning: unused\n"}"#;
+        let error = r#"{"level": "error", "rendered": "error: mismatched types\n"}"#;
+        let ice = r#"{"level": "error: internal compiler error", "rendered": "error: ice\n"}"#;
+        assert!(reported_by_metadata_action(warning, false));
+        assert!(!reported_by_metadata_action(error, false));
+        assert!(!reported_by_metadata_action(ice, false));
+        // Diagnostics following the emission of metadata are only reported by the full action.
+        assert!(!reported_by_metadata_action(warning, true));
+        assert!(!reported_by_metadata_action("not json", false));
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Forwarding of termination requests to the child process, so it and the processes it spawned
+//! don't keep running when Bazel cancels an action. The child process gets a grace period to
+//! exit before its process tree is forcefully terminated.
+
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::timeout::kill_process_tree;
+
+/// Whether the handlers are installed, in which case child processes run in their own process
+/// group.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a termination request was received.
+static TERMINATING: AtomicBool = AtomicBool::new(false);
+
+/// The signal of the last termination request, or 0.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// The id of the running child process, or 0.
+static CHILD: AtomicU32 = AtomicU32::new(0);
+
+/// How often the escalation thread checks for termination requests.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sets the running child process, whose process tree termination requests are forwarded to.
+pub(crate) fn set_child(pid: Option<u32>) {
+    CHILD.store(pid.unwrap_or(0), Ordering::SeqCst);
+
+    // The handlers set `TERMINATING` before loading `CHILD`, so a request which arrived before
+    // the child was set is forwarded here, possibly twice when they race.
+    if let Some(pid) = pid {
+        if terminating() {
+            forward(pid, SIGNAL.load(Ordering::SeqCst));
+        }
+    }
+}
+
+/// Returns whether termination requests are forwarded to child processes.
+pub(crate) fn installed() -> bool {
+    INSTALLED.load(Ordering::SeqCst)
+}
+
+/// Returns whether a termination request was received.
+pub(crate) fn terminating() -> bool {
+    TERMINATING.load(Ordering::SeqCst)
+}
+
+/// Sends `sig` to the process group of the child process `pid`. Only async-signal-safe
+/// functions may be called here, as it's called by the signal handlers.
+#[cfg(unix)]
+fn forward(pid: u32, sig: i32) {
+    extern "C" {
+        fn kill(pid: i32, sig: i32) -> i32;
+    }
+
+    // The process group of the child has the id of the child.
+    unsafe {
+        kill(-(pid as i32), sig);
+    }
+}
+
+/// The child process shares the console, so it receives console Ctrl events itself.
+#[cfg(windows)]
+fn forward(_pid: u32, _sig: i32) {}
+
+/// Forwards SIGTERM and SIGINT to the process group of the child process.
+#[cfg(unix)]
+fn install_handlers() {
+    use std::os::raw::c_int;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    // Only async-signal-safe functions may be called here.
+    extern "C" fn handle(sig: c_int) {
+        SIGNAL.store(sig, Ordering::SeqCst);
+        TERMINATING.store(true, Ordering::SeqCst);
+        let pid = CHILD.load(Ordering::SeqCst);
+        if pid != 0 {
+            forward(pid, sig);
+        }
+    }
+
+    for sig in [SIGINT, SIGTERM] {
+        unsafe {
+            signal(sig, handle);
+        }
+    }
+}
+
+/// Keeps the process wrapper running on console Ctrl events. The child process shares the
+/// console, so it receives the events itself.
+#[cfg(windows)]
+fn install_handlers() {
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    extern "system" fn handle(_ctrl_type: u32) -> i32 {
+        TERMINATING.store(true, Ordering::SeqCst);
+        // The event is handled, so the process wrapper isn't terminated before its child.
+        1
+    }
+
+    unsafe {
+        SetConsoleCtrlHandler(handle, 1);
+    }
+}
+
+/// Waits for a termination request, then forcefully terminates the process tree of the child
+/// process if it's still running after `grace_period`.
+fn escalate(grace_period: Duration) {
+    while !terminating() {
+        thread::sleep(POLL_INTERVAL);
+    }
+    thread::sleep(grace_period);
+    let pid = CHILD.load(Ordering::SeqCst);
+    if pid != 0 {
+        kill_process_tree(pid);
+    }
+}
+
+/// Forwards termination requests of the process wrapper to its child processes, see `set_child`.
+pub(crate) fn install(grace_period: Duration) {
+    install_handlers();
+    INSTALLED.store(true, Ordering::SeqCst);
+    thread::spawn(move || escalate(grace_period));
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, Stdio};
+
+    use crate::timeout::isolate_process_tree;
+
+    /// Spawns `sh -c script` and sets it as the child once it printed a line.
+    fn spawn(script: &str) -> std::process::Child {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]).stdout(Stdio::piped());
+        isolate_process_tree(&mut command);
+        let mut child = command.spawn().unwrap();
+        BufReader::new(child.stdout.as_mut().unwrap())
+            .read_line(&mut String::new())
+            .unwrap();
+        set_child(Some(child.id()));
+        child
+    }
+
+    #[test]
+    fn test_forward_and_escalate() {
+        install_handlers();
+        let mut child = spawn("echo ready; sleep 60 & wait");
+        extern "C" {
+            fn raise(sig: i32) -> i32;
+        }
+        // The test process itself isn't terminated, as the signal is forwarded.
+        unsafe {
+            raise(15);
+        }
+        assert_eq!(child.wait().unwrap().signal(), Some(15));
+        assert!(terminating());
+
+        // Children ignoring the request are terminated after the grace period.
+        let mut child = spawn("trap '' TERM; echo ready; sleep 60 & wait");
+        escalate(Duration::from_millis(100));
+        assert_eq!(child.wait().unwrap().signal(), Some(9));
+        set_child(None);
+
+        // Children set after the request get it too.
+        let mut child = spawn("echo ready; sleep 60 & wait");
+        assert_eq!(child.wait().unwrap().signal(), Some(15));
+        set_child(None);
+    }
+}
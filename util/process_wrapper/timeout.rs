@@ -0,0 +1,106 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::process::Command;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Configures `command` to run in its own process group, so it can be terminated along with
+/// the processes it spawns, e.g. linkers.
+#[cfg(unix)]
+pub(crate) fn isolate_process_tree(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+#[cfg(windows)]
+pub(crate) fn isolate_process_tree(_command: &mut Command) {}
+
+/// Forcefully terminates the process `pid` and all of its descendants.
+#[cfg(unix)]
+pub(crate) fn kill_process_tree(pid: u32) {
+    extern "C" {
+        fn kill(pid: i32, sig: i32) -> i32;
+    }
+    const SIGKILL: i32 = 9;
+    // The process group of the child, see `isolate_process_tree`, has the id of the child.
+    unsafe {
+        kill(-(pid as i32), SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn kill_process_tree(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .output();
+}
+
+/// A watchdog terminating the process tree of a child process if it runs for too long.
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+    stop: Sender<()>,
+    handle: JoinHandle<bool>,
+}
+
+impl Watchdog {
+    pub(crate) fn start(pid: u32, timeout: Duration) -> Self {
+        let (stop, stopped) = channel();
+        let handle = thread::spawn(move || match stopped.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => {
+                kill_process_tree(pid);
+                true
+            }
+            _ => false,
+        });
+        Self { stop, handle }
+    }
+
+    /// Stops the watchdog once the child process exited, returning whether it was terminated
+    /// for exceeding the timeout.
+    pub(crate) fn stop(self) -> bool {
+        let _ = self.stop.send(());
+        self.handle.join().unwrap_or(false)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::time::Instant;
+
+    #[test]
+    fn test_watchdog_kills_process_tree() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 60 & sleep 60"]);
+        isolate_process_tree(&mut command);
+        let mut child = command.spawn().unwrap();
+
+        let start = Instant::now();
+        let watchdog = Watchdog::start(child.id(), Duration::from_millis(100));
+        child.wait().unwrap();
+        assert!(watchdog.stop());
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_watchdog_stops() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let watchdog = Watchdog::start(child.id(), Duration::from_secs(60));
+        assert!(child.wait().unwrap().success());
+        assert!(!watchdog.stop());
+    }
+}
@@ -15,17 +15,59 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
 
-pub(crate) fn read_file_to_array(path: &str) -> Result<Vec<String>, String> {
-    let file = File::open(path).map_err(|e| e.to_string()).map_err(|err| {
+/// A stable 64 bit FNV-1a hash, as the hashers of std may change between Rust versions.
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The formats of param files, named after those of Bazel's `Args.use_param_file`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ParamFileFormat {
+    /// One argument per line, where a `\` at the end of a line escapes the line break.
+    Multiline,
+    /// Arguments quoted following the rules of POSIX shells, so they may contain any character.
+    Shell,
+}
+
+impl ParamFileFormat {
+    pub(crate) fn parse(format: &str) -> Option<Self> {
+        match format {
+            "multiline" => Some(Self::Multiline),
+            "shell" => Some(Self::Shell),
+            _ => None,
+        }
+    }
+}
+
+fn open(path: &str) -> Result<File, String> {
+    File::open(path).map_err(|e| e.to_string()).map_err(|err| {
         format!(
             "{} reading path: {:?}, current directory: {:?}",
             err,
             path,
             std::env::current_dir()
         )
-    })?;
+    })
+}
 
-    read_to_array(file)
+pub(crate) fn read_file_to_array(path: &str) -> Result<Vec<String>, String> {
+    read_to_array(open(path)?)
+}
+
+/// Reads the arguments in the param file at `path`, written in `format`.
+pub(crate) fn read_param_file_to_array(
+    path: &str,
+    format: ParamFileFormat,
+) -> Result<Vec<String>, String> {
+    let file = open(path)?;
+    match format {
+        ParamFileFormat::Multiline => read_to_array(file),
+        ParamFileFormat::Shell => {
+            read_shell_words(file).map_err(|err| format!("{err} in shell param file {path:?}"))
+        }
+    }
 }
 
 pub(crate) fn read_stamp_status_to_array(path: String) -> Result<Vec<(String, String)>, String> {
@@ -65,6 +107,77 @@ fn read_to_array(reader: impl Read) -> Result<Vec<String>, String> {
     Ok(ret)
 }
 
+/// Reads the words of `reader`, quoted following the rules of POSIX shells.
+fn read_shell_words(mut reader: impl Read) -> Result<Vec<String>, String> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map_err(|e| e.to_string())?;
+    split_shell_words(&content).ok_or_else(|| "unterminated quote or escape".to_owned())
+}
+
+/// Splits `s` into words following the quoting rules of POSIX shells. Returns `None` if a quote
+/// isn't terminated or `s` ends with an escaped line break, i.e. the words continue on the next
+/// line.
+fn split_shell_words(s: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    // Whether a word was started, as quotes start a word which may be empty.
+    let mut in_word = false;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            '\n' => {}
+                            c @ ('$' | '`' | '"' | '\\') => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => match chars.next()? {
+                '\n' => {}
+                c => {
+                    in_word = true;
+                    word.push(c);
+                }
+            },
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Some(words)
+}
+
 fn stamp_status_to_array(reader: impl Read) -> Result<Vec<(String, String)>, String> {
     let escaped_lines = read_to_array(reader)?;
     escaped_lines
@@ -97,6 +210,68 @@ string",
         assert_eq!(expected, got);
     }
 
+    #[test]
+    fn test_read_to_array_keeps_quotes() {
+        let input = "'quoted arg'\n\"double\" quoted\n";
+        let expected = vec!["'quoted arg'", "\"double\" quoted"];
+        let got = read_to_array(input.as_bytes()).unwrap();
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_read_shell_words() {
+        let input = r#"--cfg=feature=\"unquoted\"
+'-Clink-arg=-Wl,--version-script=a b.lds'
+'it'\''s'
+"double \"quoted\" \$HOME \n"
+''
+'spanning
+lines'
+'several' "words" on\ a line
+"#;
+        let expected = vec![
+            r#"--cfg=feature="unquoted""#,
+            "-Clink-arg=-Wl,--version-script=a b.lds",
+            "it's",
+            r#"double "quoted" $HOME \n"#,
+            "",
+            "spanning\nlines",
+            "several",
+            "words",
+            "on a",
+            "line",
+        ];
+        let got = read_shell_words(input.as_bytes()).unwrap();
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_read_shell_words_unterminated_quote() {
+        assert!(read_shell_words("'unterminated\nquote".as_bytes()).is_err());
+        assert!(read_shell_words("\"unterminated \\\"quote".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_split_shell_words() {
+        assert_eq!(split_shell_words(""), Some(vec![]));
+        assert_eq!(
+            split_shell_words("  a\tb  "),
+            Some(vec!["a".to_owned(), "b".to_owned()])
+        );
+        assert_eq!(
+            split_shell_words(r#"a'b'"c""#),
+            Some(vec!["abc".to_owned()])
+        );
+        assert_eq!(
+            split_shell_words(r#"'\' "\a""#),
+            Some(vec!["\\".to_owned(), "\\a".to_owned()])
+        );
+        assert_eq!(split_shell_words("a\\\nb"), Some(vec!["ab".to_owned()]));
+        assert_eq!(split_shell_words("a\\"), None);
+        assert_eq!(split_shell_words("'a"), None);
+        assert_eq!(split_shell_words(r#""a\""#), None);
+    }
+
     #[test]
     fn test_stamp_status_to_array() {
         let lines = "aaa bbb\\\nvvv\nccc ddd\neee fff";
@@ -0,0 +1,202 @@
+// Copyright 2024 The Bazel Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for running the process wrapper as a Bazel persistent worker using the json worker
+//! protocol, see https://bazel.build/remote/persistent. Multiplex workers handle requests
+//! concurrently, each in its own thread.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tinyjson::JsonValue;
+
+use crate::options::options_from_args;
+use crate::{run, ProcessWrapperError, StdoutTarget};
+
+/// A request to run an action.
+#[derive(Debug, PartialEq)]
+struct WorkRequest {
+    // The arguments of the action, i.e. the content of its param files.
+    arguments: Vec<String>,
+    // The id of the request, which is always 0 for singleplex workers.
+    request_id: i64,
+}
+
+impl TryFrom<JsonValue> for WorkRequest {
+    type Error = String;
+    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
+        let JsonValue::Object(mut map) = value else {
+            return Err("work request is not an object".to_owned());
+        };
+        let arguments = match map.remove("arguments") {
+            Some(JsonValue::Array(arguments)) => arguments
+                .into_iter()
+                .map(|argument| match argument {
+                    JsonValue::String(argument) => Ok(argument),
+                    _ => Err("work request argument is not a string".to_owned()),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+            Some(_) => return Err("work request arguments are not an array".to_owned()),
+        };
+        let request_id = match map.get("requestId") {
+            Some(JsonValue::Number(request_id)) => *request_id as i64,
+            None => 0,
+            Some(_) => return Err("work request id is not a number".to_owned()),
+        };
+        Ok(Self {
+            arguments,
+            request_id,
+        })
+    }
+}
+
+/// Renders the response to the request `request_id` as a line of json.
+fn work_response(request_id: i64, exit_code: i32, output: String) -> String {
+    let response: HashMap<String, JsonValue> = HashMap::from([
+        (
+            "exitCode".to_owned(),
+            JsonValue::Number(f64::from(exit_code)),
+        ),
+        ("output".to_owned(), JsonValue::String(output)),
+        ("requestId".to_owned(), JsonValue::Number(request_id as f64)),
+    ]);
+    // Rendering a json object of strings and numbers can't fail.
+    JsonValue::Object(response).stringify().unwrap()
+}
+
+/// Runs the action of a work request and returns its exit code and output.
+fn execute(arguments: Vec<String>) -> (i32, String) {
+    let program_name = env::args().next().unwrap_or_default();
+    let args = std::iter::once(program_name).chain(arguments).collect();
+
+    let mut output: Vec<u8> = Vec::new();
+    let result = options_from_args(args)
+        .map_err(|e| ProcessWrapperError(e.to_string()))
+        // The stdout of the worker is reserved for responses, so the stdout of the child
+        // process ends up in the worker log instead.
+        .and_then(|opts| run(opts, StdoutTarget::Stderr, &mut output));
+    let mut output = String::from_utf8_lossy(&output).into_owned();
+    match result {
+        Ok(code) => (code, output),
+        Err(e) => {
+            output.push_str(&e.to_string());
+            (1, output)
+        }
+    }
+}
+
+fn handle_request(request: WorkRequest, stdout: &Mutex<io::Stdout>) -> io::Result<()> {
+    let (exit_code, output) = execute(request.arguments);
+    let response = work_response(request.request_id, exit_code, output);
+
+    let mut stdout = stdout
+        .lock()
+        .map_err(|_| io::Error::other("stdout lock poisoned"))?;
+    writeln!(stdout, "{response}")?;
+    stdout.flush()
+}
+
+/// Handles the work requests sent to stdin until it's closed.
+pub(crate) fn run_worker() -> Result<(), ProcessWrapperError> {
+    let stdout = Arc::new(Mutex::new(io::stdout()));
+    let mut workers = Vec::new();
+    for line in io::stdin().lock().lines() {
+        let line =
+            line.map_err(|e| ProcessWrapperError(format!("failed to read work request: {e}")))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = line
+            .parse::<JsonValue>()
+            .map_err(|e| e.to_string())
+            .and_then(WorkRequest::try_from)
+            .map_err(|e| ProcessWrapperError(format!("invalid work request: {e}")))?;
+
+        if request.request_id == 0 {
+            handle_request(request, &stdout)
+                .map_err(|e| ProcessWrapperError(format!("failed to write work response: {e}")))?;
+        } else {
+            let stdout = Arc::clone(&stdout);
+            workers.push(thread::spawn(move || {
+                if let Err(e) = handle_request(request, &stdout) {
+                    eprintln!("failed to write work response: {e}");
+                }
+            }));
+        }
+    }
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(line: &str) -> Result<WorkRequest, String> {
+        WorkRequest::try_from(line.parse::<JsonValue>().unwrap())
+    }
+
+    #[test]
+    fn test_parse_work_request() {
+        assert_eq!(
+            parse(
+                r#"{"arguments": ["--subst", "pwd=${pwd}", "--", "rustc"], "inputs": [{"path": "lib.rs", "digest": "abc"}], "requestId": 12}"#
+            ),
+            Ok(WorkRequest {
+                arguments: vec![
+                    "--subst".to_owned(),
+                    "pwd=${pwd}".to_owned(),
+                    "--".to_owned(),
+                    "rustc".to_owned()
+                ],
+                request_id: 12,
+            })
+        );
+        assert_eq!(
+            parse(r#"{"arguments": ["--", "rustc"]}"#),
+            Ok(WorkRequest {
+                arguments: vec!["--".to_owned(), "rustc".to_owned()],
+                request_id: 0,
+            })
+        );
+        assert!(parse(r#"{"arguments": [1]}"#).is_err());
+        assert!(parse(r#"["--", "rustc"]"#).is_err());
+    }
+
+    #[test]
+    fn test_work_response() {
+        let response: JsonValue = work_response(3, 1, "error: oops\n".to_owned())
+            .parse()
+            .unwrap();
+        assert_eq!(
+            response,
+            JsonValue::Object(HashMap::from([
+                ("exitCode".to_owned(), JsonValue::Number(1.0)),
+                (
+                    "output".to_owned(),
+                    JsonValue::String("error: oops\n".to_owned())
+                ),
+                ("requestId".to_owned(), JsonValue::Number(3.0)),
+            ]))
+        );
+    }
+}